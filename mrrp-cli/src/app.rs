@@ -1,6 +1,7 @@
 use std::{
     fmt::Debug,
     io::stdout,
+    path::PathBuf,
     time::Duration,
 };
 
@@ -11,9 +12,15 @@ use chrono::{
 use color_eyre::eyre::{
     Error,
     bail,
+    eyre,
 };
 use crossterm::execute;
 use futures_util::TryStreamExt;
+use mrrp::{
+    analysis::sideband::SidebandDetector,
+    fft::FftPool,
+    io::ShutdownGuard,
+};
 use ratatui::{
     DefaultTerminal,
     Terminal,
@@ -21,32 +28,70 @@ use ratatui::{
 };
 use rodio::{
     DeviceSinkBuilder,
-    Source,
+    Source as _,
     source::AutomaticGainControlSettings,
 };
-use rtlsdr_async::Backend;
 use serde::{
     Deserialize,
     Serialize,
 };
 use tokio::{
-    sync::mpsc,
+    sync::{
+        mpsc,
+        oneshot,
+    },
     time::Interval,
 };
 
 use crate::{
-    args::MainArgs,
-    demodulator::Demodulator,
+    args::{
+        Gain,
+        MainArgs,
+    },
+    control::{
+        self,
+        AppStatus,
+    },
+    demodulator::{
+        Demodulator,
+        Mode,
+    },
     fft::Fft,
-    files::AppFiles,
+    files::{
+        AppFiles,
+        StationDatabase,
+    },
+    gain_control::{
+        GainControl,
+        GainControlSettings,
+    },
     reader::SampleReader,
+    recording::Recording,
+    source::{
+        Source,
+        SourceSpec,
+        Tuner,
+    },
+    state,
+    sweep::Sweep,
+    time_shift::{
+        CatchUp,
+        TimeShiftCommand,
+        TimeShiftHandle,
+    },
     ui::{
         Ui,
         UiEvent,
         UiState,
         UiWidget,
         bandplan::Bandplan,
-        keybinds::Keybinds,
+        keybinds::{
+            Action,
+            Keybinds,
+        },
+        markers::MarkerMeasurement,
+        scope::Scope,
+        theme::Themes,
         waterfall::ColorMap,
     },
     util::FrequencyBand,
@@ -59,31 +104,80 @@ const DEFAULT_SAMPLE_RATE: u32 = 2_400_000;
 pub struct AppState {
     ui_state: UiState,
     sampled_frequency_band: FrequencyBand,
+    /// Conjugate the samples, for sources that deliver a mirrored spectrum.
+    #[serde(default)]
+    invert_spectrum: bool,
+}
+
+impl AppState {
+    fn new(sampled_frequency_band: FrequencyBand) -> Self {
+        Self {
+            ui_state: UiState::new(sampled_frequency_band),
+            sampled_frequency_band,
+            invert_spectrum: false,
+        }
+    }
+
+    /// Restores the state from a migrated snapshot. Each section that is
+    /// missing or fails to load falls back to its default on its own.
+    fn restore(app_state: &ciborium::Value, default_sampled_frequency_band: FrequencyBand) -> Self {
+        let sampled_frequency_band = state::section(app_state, "sampled_frequency_band")
+            .unwrap_or(default_sampled_frequency_band);
+        Self {
+            ui_state: state::section(app_state, "ui_state")
+                .unwrap_or_else(|| UiState::new(sampled_frequency_band)),
+            sampled_frequency_band,
+            invert_spectrum: state::section(app_state, "invert_spectrum").unwrap_or_default(),
+        }
+    }
 }
 
 #[derive(Debug)]
-pub struct App<B> {
+pub struct App {
     state: AppState,
     files: AppFiles,
     app_events: mpsc::UnboundedReceiver<AppEvent>,
     proxy: AppProxy,
     scroll_interval: Interval,
-    rtl_sdr: B,
+    /// The source that samples are read from, or `None` while it's switched.
+    source: Option<SourceSpec>,
+    switching_source: bool,
+    tuner: Option<Box<dyn Tuner>>,
+    /// Sweeps the tuner over a wide band, if one was requested.
+    sweep: Option<Sweep>,
+    gain: Gain,
+    gain_control_settings: GainControlSettings,
+    gain_control: Option<GainControl>,
     sample_reader: SampleReader,
     fft: Fft,
+    fft_pool: FftPool,
+    recording: Option<Recording>,
+    /// Why the last recording stopped, if it failed.
+    recording_error: Option<String>,
+    marker_export_error: Option<String>,
+    time_shift_length: Duration,
+    time_shift_catch_up: CatchUp,
+    time_shift: Option<TimeShiftHandle>,
+    demodulation_mode: Mode,
+    sideband_detector: Option<SidebandDetector>,
+    /// Power spectrum for the sideband detector, reused between segments.
+    sideband_power: Vec<f32>,
     terminal: DefaultTerminal,
     terminal_events: crossterm::event::EventStream,
     ui: Ui,
     exit_requested: bool,
     redraw_interval: Interval,
+    /// Stops the app on Ctrl-C or SIGTERM.
+    shutdown: ShutdownGuard,
 }
 
-impl<B> App<B>
-where
-    B: Backend + Send + Clone + 'static,
-    <B as Backend>::Error: std::error::Error + Send + Sync + 'static,
-{
-    pub async fn new(args: MainArgs, app_files: AppFiles, rtl_sdr: B) -> Result<Self, Error> {
+impl App {
+    pub async fn new(
+        args: MainArgs,
+        app_files: AppFiles,
+        source_spec: SourceSpec,
+        shutdown: ShutdownGuard,
+    ) -> Result<Self, Error> {
         if args.fft_size == 0 {
             bail!("FFT size must be greater than 0");
         }
@@ -93,6 +187,14 @@ where
         if args.fft_overlap >= args.fft_size {
             bail!("FFT overlap must be less than FFT size");
         }
+        if args.sweep.is_some() {
+            if source_spec.is_file() {
+                bail!("Files can't be swept");
+            }
+            if matches!(args.gain, Gain::Software) {
+                bail!("The software gain control can't be used while sweeping");
+            }
+        }
 
         // todo: load config here
 
@@ -110,6 +212,13 @@ where
             app_files.bandplan()?
         };
 
+        let stations = if let Some(path) = &args.stations {
+            StationDatabase::from_path(path)?
+        }
+        else {
+            app_files.stations()?
+        };
+
         let colormap = if let Some(path) = &args.colormap {
             ColorMap::from_path(path)?
         }
@@ -117,8 +226,19 @@ where
             app_files.color_map()?
         };
 
+        let mut themes = if let Some(path) = &args.themes {
+            Themes::from_path(path)?
+        }
+        else {
+            app_files.themes()?
+        };
+
         let _bookmarks = app_files.bookmarks()?;
 
+        let default_sampled_frequency_band = FrequencyBand::from_center_and_bandwidth(
+            args.frequency.unwrap_or(DEFAULT_CENTER_FREQUENCY),
+            args.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE),
+        );
         let mut state = (!args.reset)
             .then(|| {
                 app_files
@@ -128,22 +248,11 @@ where
                     })
                     .ok()
                     .map(|snapshot| {
-                        // currently we only care about the actual state, but we intend to store
-                        // more metadata in the snapshot, like timestamp, version number, etc.
-                        snapshot.app_state
+                        AppState::restore(&snapshot.app_state, default_sampled_frequency_band)
                     })
             })
             .flatten()
-            .unwrap_or_else(|| {
-                let sampled_frequency_band = FrequencyBand::from_center_and_bandwidth(
-                    args.frequency.unwrap_or(DEFAULT_CENTER_FREQUENCY),
-                    args.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE),
-                );
-                AppState {
-                    ui_state: UiState::new(sampled_frequency_band),
-                    sampled_frequency_band,
-                }
-            });
+            .unwrap_or_else(|| AppState::new(default_sampled_frequency_band));
 
         if let Some(center_frequency) = args.frequency {
             if state.sampled_frequency_band.center() != center_frequency {
@@ -168,16 +277,44 @@ where
             }
         }
 
-        rtl_sdr
-            .set_center_frequency(state.sampled_frequency_band.center())
-            .await?;
-        rtl_sdr
-            .set_sample_rate(state.sampled_frequency_band.bandwidth())
-            .await?;
-        rtl_sdr.set_tuner_gain(args.gain.into()).await?;
+        if let Some(name) = &args.theme {
+            if !themes.select(name) {
+                bail!(
+                    "Unknown theme: {name}. Available themes: {}",
+                    themes.names().collect::<Vec<_>>().join(", ")
+                );
+            }
+        }
+        else if let Some(name) = state.ui_state.theme()
+            && !themes.select(name)
+        {
+            tracing::warn!(name, "The last selected theme doesn't exist anymore");
+        }
+
+        let source = Source::open(&source_spec, state.sampled_frequency_band, args.gain).await?;
+        // recordings can have their own sample rate and center frequency
+        state.sampled_frequency_band = source.sampled_frequency_band;
+
+        let gain_control_settings = GainControlSettings {
+            target: args.agc_target,
+            hysteresis: args.agc_hysteresis,
+            // measure over 250 ms
+            window: state.sampled_frequency_band.bandwidth() as usize / 4,
+        };
+        let gain_control =
+            matches!(args.gain, Gain::Software).then(|| GainControl::new(gain_control_settings));
 
-        let sample_reader =
-            SampleReader::new(rtl_sdr.samples().await?, args.fft_size, args.fft_overlap);
+        let mut sample_reader = SampleReader::new(source.samples, args.fft_size, args.fft_overlap);
+        sample_reader.set_conjugate(state.invert_spectrum);
+
+        let sweep = args.sweep.map(|frequency_band| {
+            state.ui_state.center_view(frequency_band);
+            Sweep::new(
+                frequency_band,
+                state.sampled_frequency_band.bandwidth(),
+                args.fft_size,
+            )
+        });
 
         // initialize the terminal. don't use `ratatui::init` as we don't want their
         // panic hook
@@ -192,32 +329,89 @@ where
 
         let (event_sender, event_receiver) = mpsc::unbounded_channel();
 
-        let ui = Ui::new(state.sampled_frequency_band, keybinds, bandplan, colormap);
+        let ui = Ui::new(
+            sweep
+                .as_ref()
+                .map_or(state.sampled_frequency_band, Sweep::frequency_band),
+            keybinds,
+            bandplan,
+            colormap,
+            themes,
+            stations,
+            args.station_tolerance,
+        );
+
+        let proxy = AppProxy { event_sender };
+
+        if let Some(address) = args.control.clone() {
+            let proxy = proxy.clone();
+            tokio::spawn(async move {
+                if let Err(error) = control::serve(&address, proxy).await {
+                    tracing::error!(?error, "Control socket failed");
+                }
+            });
+        }
+
+        #[cfg(feature = "scripting")]
+        if let Some(path) = args.script.clone() {
+            crate::script::spawn(path, proxy.clone());
+        }
+
+        let fft_pool = FftPool::with_threads(args.fft_threads);
 
         Ok(Self {
             state,
             files: app_files,
             app_events: event_receiver,
-            proxy: AppProxy { event_sender },
+            proxy,
             scroll_interval: tokio::time::interval(Duration::from_millis(args.scroll_interval)),
-            rtl_sdr,
+            source: Some(source_spec),
+            switching_source: false,
+            tuner: source.tuner,
+            sweep,
+            gain: args.gain,
+            gain_control_settings,
+            gain_control,
             sample_reader,
-            fft: Fft::new(args.fft_size, args.fft_window),
+            fft: Fft::new(args.fft_size, args.fft_window, args.fft_backend, &fft_pool).await,
+            fft_pool,
+            recording: None,
+            recording_error: None,
+            marker_export_error: None,
+            time_shift_length: Duration::from_secs(args.time_shift),
+            time_shift_catch_up: args.time_shift_catch_up,
+            time_shift: None,
+            demodulation_mode: args.mode,
+            sideband_detector: args.auto_sideband.then(SidebandDetector::default),
+            sideband_power: vec![],
             terminal,
             terminal_events,
             ui,
             exit_requested: false,
             redraw_interval: tokio::time::interval(Duration::from_millis(args.redraw_interval)),
+            shutdown,
         })
     }
 
     pub async fn run(&mut self) -> Result<(), Error> {
         self.exit_requested = false;
 
+        let demodulated_band = FrequencyBand::from_center_and_bandwidth(6_020_000, 10000);
         let mut am_demod = Demodulator::new(
-            FrequencyBand::from_center_and_bandwidth(6_020_000, 10000),
+            demodulated_band,
             self.state.sampled_frequency_band,
+            self.time_shift_length,
+            self.time_shift_catch_up,
+            self.demodulation_mode,
+            &self.fft_pool,
         );
+        self.time_shift = Some(am_demod.time_shift());
+        self.ui.set_scope(Scope {
+            handle: am_demod.scope(),
+            sample_rate: am_demod.audio_sample_rate(),
+        });
+        self.ui.set_audio_spectrum(am_demod.audio_spectrum());
+        self.ui.set_level_meter(am_demod.audio_stats());
         let audio_output = DeviceSinkBuilder::open_default_sink()?;
         audio_output
             .mixer()
@@ -232,8 +426,14 @@ where
                     }),
             );
 
+        let shutdown = self.shutdown.token();
+
         while !self.exit_requested {
             tokio::select! {
+                _ = shutdown.cancelled() => {
+                    tracing::info!("Shutdown requested");
+                    break;
+                }
                 option = self.app_events.recv() => {
                     let Some(event) = option
                     else {
@@ -241,6 +441,9 @@ where
                     };
 
                     self.handle_event(event)?;
+                    if am_demod.mode() != self.demodulation_mode {
+                        am_demod.set_mode(self.demodulation_mode);
+                    }
                 }
                 result = self.terminal_events.try_next() => {
                     let Some(event) = result?
@@ -256,16 +459,91 @@ where
                     self.ui.handle_event(UiEvent::ScrollWaterfall, &mut self.proxy, &mut self.state.ui_state);
                 }
                 result = self.sample_reader.read() => {
-                    let Some(samples) = result?
+                    let Some(segment) = result?
                     else {
+                        if self.source.as_ref().is_some_and(SourceSpec::is_file) {
+                            // keep the UI open, so another source can be selected
+                            tracing::info!("Playback finished");
+                            self.sample_reader.close();
+                            continue;
+                        }
                         tracing::warn!("sample stream stopped");
                         break;
                     };
 
-                    let spectrum = self.fft.forward(samples);
-                    self.ui.handle_event(UiEvent::Spectrum { spectrum, frequency_band: self.state.sampled_frequency_band }, &mut self.proxy, &mut self.state.ui_state);
+                    if let Some(recording) = &mut self.recording
+                        && let Err(error) = recording.write_segment(segment)
+                    {
+                        // `segment` borrows the sample reader, so this can't call
+                        // `recording_failed`
+                        tracing::error!(?error, "Recording failed");
+                        self.recording = None;
+                        self.recording_error = Some(error.to_string());
+                    }
+
+                    // consecutive segments can overlap, so everything but the FFT only
+                    // gets the new samples
+                    let samples = segment.new_samples();
+
+                    let timestamp = Local::now();
+                    let duration = Duration::from_secs_f64(
+                        samples.len() as f64
+                            / self.state.sampled_frequency_band.bandwidth() as f64,
+                    );
+                    let spectrum = self.fft.forward(segment.samples).await;
+
+                    if let Some(sweep) = &mut self.sweep
+                        && self.tuner.is_some()
+                    {
+                        // the samples hop around, so they're only used for the waterfall
+                        if let Some(line) = sweep.push(spectrum, self.state.sampled_frequency_band, timestamp, duration) {
+                            self.ui.handle_event(UiEvent::Spectrum { spectrum: line.spectrum, frequency_band: line.frequency_band, timestamp: line.timestamp, duration: line.duration }, &self.proxy, &mut self.state.ui_state);
+                        }
+                        if let Some(frequency) = sweep.next_retune()
+                            && frequency != self.state.sampled_frequency_band.center()
+                        {
+                            self.tune(frequency);
+                        }
+                        continue;
+                    }
+
+                    self.ui.handle_event(UiEvent::Spectrum { spectrum, frequency_band: self.state.sampled_frequency_band, timestamp, duration }, &self.proxy, &mut self.state.ui_state);
+
+                    let gain = self.gain_control.as_mut().and_then(|gain_control| {
+                        gain_control.push_samples(samples);
+                        gain_control.push_spectrum(spectrum);
+                        gain_control.update()
+                    });
+
+                    if let Some(detector) = &mut self.sideband_detector {
+                        let power = &mut self.sideband_power;
+                        power.clear();
+                        power.extend(spectrum.iter().map(|bin| bin.norm_sqr()));
+                        let sampled_frequency_band = self.state.sampled_frequency_band;
+                        let detected = detector.push(
+                            power,
+                            sampled_frequency_band.start.into(),
+                            f64::from(sampled_frequency_band.bandwidth()) / power.len() as f64,
+                            demodulated_band.center().into(),
+                        );
+                        // the detector can't tell AM from synchronous AM, so that is
+                        // kept
+                        if let Some(mode) = detected.map(Mode::from)
+                            && mode != am_demod.mode()
+                            && !(mode == Mode::Am && am_demod.mode() == Mode::SyncAm)
+                        {
+                            tracing::info!(?mode, "Detected demodulation mode");
+                            am_demod.set_mode(mode);
+                            self.demodulation_mode = mode;
+                        }
+                    }
 
+                    am_demod.set_sampled_frequency_band(self.state.sampled_frequency_band);
                     am_demod.push(samples);
+
+                    if let Some(gain) = gain {
+                        self.set_tuner_gain(Gain::Value(gain));
+                    }
                 }
             }
         }
@@ -273,8 +551,11 @@ where
         Ok(())
     }
 
-    pub fn persist(self) -> Result<(), Error> {
+    pub fn persist(mut self) -> Result<(), Error> {
+        self.stop_recording();
+
         self.files.save_app_state(AppSnapshot {
+            version: state::VERSION,
             app_state: &self.state,
             timestamp: Local::now(),
         })?;
@@ -291,39 +572,259 @@ where
                 self.scroll_interval = tokio::time::interval(interval);
             }
             AppEvent::SetCenterFrequency { frequency } => {
-                let rtl_sdr = self.rtl_sdr.clone();
-                let event_sender = self.proxy.event_sender.clone();
-
-                let sampled_frequency_band = FrequencyBand::from_center_and_bandwidth(
-                    frequency,
-                    self.state.sampled_frequency_band.bandwidth(),
-                );
-
-                tokio::spawn(async move {
-                    if let Err(error) = rtl_sdr.set_center_frequency(frequency).await {
-                        let _ = event_sender.send(AppEvent::Error {
-                            error: error.into(),
-                        });
-                    }
-                    else {
-                        let _ = event_sender.send(AppEvent::SampledFrequencyBandChanged {
-                            sampled_frequency_band,
-                        });
-                    }
-                });
+                if self.sweep.is_some() && self.tuner.is_some() {
+                    tracing::warn!(frequency, "Can't tune while sweeping");
+                    return Ok(());
+                }
+                self.tune(frequency);
             }
             AppEvent::SampledFrequencyBandChanged {
                 sampled_frequency_band,
             } => {
-                self.state.sampled_frequency_band = sampled_frequency_band;
+                self.set_sampled_frequency_band(sampled_frequency_band);
+            }
+            AppEvent::SetGain { gain } => {
+                self.gain = gain;
+                // setting a gain manually overrides the software gain control
+                self.gain_control = matches!(gain, Gain::Software)
+                    .then(|| GainControl::new(self.gain_control_settings));
+                self.set_tuner_gain(gain);
+            }
+            AppEvent::SetMode { mode } => {
+                tracing::info!(?mode, "Changed demodulation mode");
+                self.demodulation_mode = mode;
+            }
+            AppEvent::SwitchSource { spec, reply } => {
+                if self.switching_source {
+                    let _ = reply.send(Err(eyre!("Already switching the source")));
+                }
+                else {
+                    self.switch_source(spec, Some(reply))?;
+                }
+            }
+            AppEvent::SourceOpened {
+                spec,
+                result,
+                reply,
+                previous,
+            } => {
+                self.switching_source = false;
+                match result {
+                    Ok(source) => {
+                        tracing::info!(%spec, "Switched source");
+                        self.sample_reader.set_samples(source.samples);
+                        self.tuner = source.tuner;
+                        self.source = Some(spec);
+                        self.set_sampled_frequency_band(source.sampled_frequency_band);
+                        if let Some(sweep) = &mut self.sweep {
+                            // the new source isn't tuned to the current hop
+                            sweep.restart();
+                        }
+
+                        // the gain control measures over a time window, which depends on the
+                        // sample rate
+                        self.gain_control_settings.window =
+                            self.state.sampled_frequency_band.bandwidth() as usize / 4;
+                        self.gain_control = matches!(self.gain, Gain::Software)
+                            .then(|| GainControl::new(self.gain_control_settings));
+
+                        if let Some(reply) = reply {
+                            let _ = reply.send(Ok(()));
+                        }
+                    }
+                    Err(error) => {
+                        tracing::error!(%spec, ?error, "Failed to open source");
+                        if let Some(reply) = reply {
+                            let _ = reply.send(Err(error));
+                        }
+                        if let Some(previous) = previous {
+                            tracing::info!(%previous, "Reopening previous source");
+                            self.switch_source(previous, None)?;
+                        }
+                    }
+                }
+            }
+            AppEvent::StartRecording { path, reply } => {
+                let result = if self.recording.is_some() {
+                    Err(eyre!("Already recording"))
+                }
+                else {
+                    Recording::create(path).map(|recording| {
+                        self.recording = Some(recording);
+                        self.recording_error = None;
+                    })
+                };
+                let _ = reply.send(result);
+            }
+            AppEvent::StopRecording => self.stop_recording(),
+            AppEvent::QueryStatus { reply } => {
+                let _ = reply.send(AppStatus {
+                    center_frequency: self.state.sampled_frequency_band.center(),
+                    sample_rate: self.state.sampled_frequency_band.bandwidth(),
+                    gain: match self.gain {
+                        Gain::Value(gain) => Some(gain),
+                        Gain::Auto => None,
+                        Gain::Software => {
+                            self.gain_control
+                                .as_ref()
+                                .map(|gain_control| gain_control.gain())
+                        }
+                    },
+                    software_gain_control: self.gain_control.is_some(),
+                    source: self.source.as_ref().map(ToString::to_string),
+                    recording: self.recording.as_ref().map(|recording| recording.status()),
+                    recording_error: self.recording_error.clone(),
+                    marker_export_error: self.marker_export_error.clone(),
+                });
+            }
+            AppEvent::ExportMarkerMeasurement { measurement } => {
+                // e.g. a read-only data directory shouldn't end the session
+                match self.files.append_marker_measurement(&measurement) {
+                    Ok(()) => self.marker_export_error = None,
+                    Err(error) => {
+                        tracing::error!(?error, "Exporting marker measurement failed");
+                        self.marker_export_error = Some(error.to_string());
+                    }
+                }
+            }
+            AppEvent::ToggleSpectrumInversion => {
+                self.state.invert_spectrum = !self.state.invert_spectrum;
+                self.sample_reader.set_conjugate(self.state.invert_spectrum);
+            }
+            AppEvent::CycleFftOverlap => {
+                // none, 1/4, 1/2, 3/4 of the segment
+                let segment_size = self.sample_reader.segment_size();
+                let quarters = self.sample_reader.overlap() * 4 / segment_size;
+                let overlap = (quarters + 1) % 4 * segment_size / 4;
+                self.sample_reader.set_overlap(overlap);
+                tracing::info!(overlap, "Changed FFT overlap");
+            }
+            AppEvent::CycleFftWindow => {
+                let window = self.fft.window().next();
+                self.fft.set_window(window);
+                tracing::info!(?window, "Changed FFT window");
+            }
+            AppEvent::TimeShift { command } => {
+                if let Some(time_shift) = &self.time_shift {
+                    time_shift.apply(command);
+                }
+            }
+            AppEvent::Action { action } => {
+                self.ui.handle_event(
+                    UiEvent::Action(action),
+                    &self.proxy,
+                    &mut self.state.ui_state,
+                );
             }
         }
 
         Ok(())
     }
+
+    fn stop_recording(&mut self) {
+        if let Some(recording) = self.recording.take()
+            && let Err(error) = recording.finish()
+        {
+            self.recording_failed(error);
+        }
+    }
+
+    /// Drops the recording after an error, e.g. because the disk is full. The
+    /// TUI keeps running, and the error is reported by the `status` method of
+    /// the control socket.
+    fn recording_failed(&mut self, error: Error) {
+        tracing::error!(?error, "Recording failed");
+        self.recording = None;
+        self.recording_error = Some(error.to_string());
+    }
+
+    fn set_sampled_frequency_band(&mut self, sampled_frequency_band: FrequencyBand) {
+        self.state.sampled_frequency_band = sampled_frequency_band;
+        if let Some(detector) = &mut self.sideband_detector {
+            detector.reset();
+        }
+    }
+
+    /// Closes the current source, and opens `spec` in the background. The UI
+    /// and demodulator keep running, and [`AppEvent::SourceOpened`] is sent
+    /// when the source is open. If it fails to open, `previous` is reopened.
+    fn switch_source(
+        &mut self,
+        spec: SourceSpec,
+        reply: Option<oneshot::Sender<Result<(), Error>>>,
+    ) -> Result<(), Error> {
+        // close the current source first, since a device can only be opened once
+        self.sample_reader.close();
+        self.tuner = None;
+        let previous = self.source.take();
+
+        // the next source might have another sample rate, which the recording can't
+        // store
+        self.stop_recording();
+
+        tracing::info!(%spec, "Switching source");
+        self.switching_source = true;
+        let sampled_frequency_band = self.state.sampled_frequency_band;
+        let gain = self.gain;
+        let event_sender = self.proxy.event_sender.clone();
+
+        tokio::spawn(async move {
+            let result = Source::open(&spec, sampled_frequency_band, gain).await;
+            let _ = event_sender.send(AppEvent::SourceOpened {
+                spec,
+                result,
+                reply,
+                previous,
+            });
+        });
+
+        Ok(())
+    }
+
+    fn tune(&self, frequency: u32) {
+        let Some(tuner) = &self.tuner
+        else {
+            tracing::warn!(frequency, "Can't tune, the source has no tuner");
+            return;
+        };
+        let tune = tuner.tune(frequency);
+        let event_sender = self.proxy.event_sender.clone();
+
+        let sampled_frequency_band = FrequencyBand::from_center_and_bandwidth(
+            frequency,
+            self.state.sampled_frequency_band.bandwidth(),
+        );
+
+        tokio::spawn(async move {
+            if let Err(error) = tune.await {
+                let _ = event_sender.send(AppEvent::Error { error });
+            }
+            else {
+                let _ = event_sender.send(AppEvent::SampledFrequencyBandChanged {
+                    sampled_frequency_band,
+                });
+            }
+        });
+    }
+
+    fn set_tuner_gain(&self, gain: Gain) {
+        let Some(tuner) = &self.tuner
+        else {
+            tracing::debug!(?gain, "Can't set gain, the source has no tuner");
+            return;
+        };
+        let set_gain = tuner.set_gain(gain);
+        let event_sender = self.proxy.event_sender.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = set_gain.await {
+                let _ = event_sender.send(AppEvent::Error { error });
+            }
+        });
+    }
 }
 
-impl<B> Drop for App<B> {
+impl Drop for App {
     fn drop(&mut self) {
         let _ = execute!(std::io::stdout(), crossterm::event::DisableMouseCapture);
         ratatui::restore();
@@ -351,6 +852,74 @@ impl AppProxy {
             .event_sender
             .send(AppEvent::SetCenterFrequency { frequency });
     }
+
+    pub fn set_gain(&self, gain: Gain) {
+        let _ = self.event_sender.send(AppEvent::SetGain { gain });
+    }
+
+    pub fn set_mode(&self, mode: Mode) {
+        let _ = self.event_sender.send(AppEvent::SetMode { mode });
+    }
+
+    pub async fn start_recording(&self, path: PathBuf) -> Result<(), Error> {
+        let (reply, reply_receiver) = oneshot::channel();
+        let _ = self
+            .event_sender
+            .send(AppEvent::StartRecording { path, reply });
+        reply_receiver.await.map_err(|_| eyre!("App exited"))?
+    }
+
+    pub fn stop_recording(&self) {
+        let _ = self.event_sender.send(AppEvent::StopRecording);
+    }
+
+    /// Switches to another sample source, and returns when it's open. If it
+    /// can't be opened, the previous source is reopened.
+    pub async fn switch_source(&self, spec: SourceSpec) -> Result<(), Error> {
+        let (reply, reply_receiver) = oneshot::channel();
+        let _ = self
+            .event_sender
+            .send(AppEvent::SwitchSource { spec, reply });
+        reply_receiver.await.map_err(|_| eyre!("App exited"))?
+    }
+
+    pub async fn query_status(&self) -> Result<AppStatus, Error> {
+        let (reply, reply_receiver) = oneshot::channel();
+        let _ = self.event_sender.send(AppEvent::QueryStatus { reply });
+        reply_receiver.await.map_err(|_| eyre!("App exited"))
+    }
+
+    pub fn export_marker_measurement(&self, measurement: MarkerMeasurement) {
+        let _ = self
+            .event_sender
+            .send(AppEvent::ExportMarkerMeasurement { measurement });
+    }
+
+    pub fn toggle_spectrum_inversion(&self) {
+        let _ = self.event_sender.send(AppEvent::ToggleSpectrumInversion);
+    }
+
+    pub fn cycle_fft_overlap(&self) {
+        let _ = self.event_sender.send(AppEvent::CycleFftOverlap);
+    }
+
+    pub fn cycle_fft_window(&self) {
+        let _ = self.event_sender.send(AppEvent::CycleFftWindow);
+    }
+
+    pub fn time_shift(&self, command: TimeShiftCommand) {
+        let _ = self.event_sender.send(AppEvent::TimeShift { command });
+    }
+
+    /// Performs an action as if its keybind was pressed.
+    pub fn perform_action(&self, action: Action) {
+        let _ = self.event_sender.send(AppEvent::Action { action });
+    }
+
+    /// Whether the app has exited.
+    pub fn is_closed(&self) -> bool {
+        self.event_sender.is_closed()
+    }
 }
 
 #[derive(Debug)]
@@ -368,10 +937,50 @@ enum AppEvent {
     SampledFrequencyBandChanged {
         sampled_frequency_band: FrequencyBand,
     },
+    SetGain {
+        gain: Gain,
+    },
+    SetMode {
+        mode: Mode,
+    },
+    StartRecording {
+        path: PathBuf,
+        reply: oneshot::Sender<Result<(), Error>>,
+    },
+    StopRecording,
+    SwitchSource {
+        spec: SourceSpec,
+        reply: oneshot::Sender<Result<(), Error>>,
+    },
+    SourceOpened {
+        spec: SourceSpec,
+        result: Result<Source, Error>,
+        reply: Option<oneshot::Sender<Result<(), Error>>>,
+        previous: Option<SourceSpec>,
+    },
+    QueryStatus {
+        reply: oneshot::Sender<AppStatus>,
+    },
+    ExportMarkerMeasurement {
+        measurement: MarkerMeasurement,
+    },
+    ToggleSpectrumInversion,
+    CycleFftOverlap,
+    CycleFftWindow,
+    TimeShift {
+        command: TimeShiftCommand,
+    },
+    Action {
+        action: Action,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppSnapshot<A> {
+    /// Snapshots from before versioning don't have this field, so they're
+    /// version 0.
+    #[serde(default)]
+    pub version: u32,
     pub app_state: A,
     pub timestamp: DateTime<Local>,
 }
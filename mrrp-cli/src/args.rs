@@ -4,10 +4,20 @@ use std::{
 };
 
 use clap::FromArgMatches;
+use mrrp::{
+    fft::BackendKind,
+    modem::cw::KeyerMode,
+};
 
 use crate::{
     Error,
+    bench::SoakPipeline,
+    demodulator::Mode,
     fft::Window,
+    gain_control,
+    sweep,
+    time_shift::CatchUp,
+    util::FrequencyBand,
 };
 
 #[derive(Debug, clap::Parser)]
@@ -30,6 +40,29 @@ pub enum Command {
     DumpState {
         path: Option<PathBuf>,
     },
+    /// Migrate a saved app state to the current version.
+    ///
+    /// This also happens on start, but this command keeps a backup of the old
+    /// file. If no path is given, the default app state file is migrated.
+    MigrateState {
+        path: Option<PathBuf>,
+    },
+    /// Check the signal processing with generated signals.
+    ///
+    /// This needs no hardware. It prints a report and fails if any check is
+    /// out of tolerance.
+    Selftest(SelftestArgs),
+    /// Measure how fast the receive pipelines run on this machine.
+    ///
+    /// This runs a WBFM receiver and the waterfall FFT on generated signals
+    /// and prints the CPU load they need at each sample rate. Use it to pick
+    /// a sample rate and FFT size that your machine can sustain.
+    BenchPipeline(BenchPipelineArgs),
+    /// Key Morse from the keyboard.
+    ///
+    /// Plays a sidetone, and optionally writes the keyed signal as baseband
+    /// IQ to a WAV file.
+    Cw(CwArgs),
 }
 
 impl Default for Command {
@@ -43,14 +76,32 @@ impl Default for Command {
 
 #[derive(Debug, clap::Args)]
 pub struct MainArgs {
-    /// Device index to use. If neither this or --address is specified, the
-    /// first device is used.
+    /// Device index to use. If none of this, --address or --file is
+    /// specified, the first device is used.
     #[clap(short, long)]
     pub device: Option<u32>,
 
     #[clap(short, long)]
     pub address: Option<String>,
 
+    /// Play back a recorded file instead of reading from an SDR.
+    ///
+    /// WAV files are played with their own sample rate. Raw recordings
+    /// (interleaved little-endian `f32`, or `.bfp`/`.bfp8`) are played with
+    /// the current sample rate, so pass the one they were recorded with as
+    /// --samplerate. The source can be switched while running through the
+    /// control socket.
+    #[clap(long)]
+    pub file: Option<PathBuf>,
+
+    /// Sweep the tuner over a band that is wider than the sample rate, e.g.
+    /// `88M:108M`.
+    ///
+    /// The waterfall shows a line for every sweep, stitched together from the
+    /// spectra at each hop. Audio isn't demodulated while sweeping.
+    #[clap(long, value_parser = sweep::parse_frequency_band)]
+    pub sweep: Option<FrequencyBand>,
+
     /// Sample rate. This determines the bandwidth of the spectrum.
     #[clap(short, long = "samplerate")]
     pub sample_rate: Option<u32>,
@@ -59,10 +110,20 @@ pub struct MainArgs {
     #[clap(short, long)]
     pub frequency: Option<u32>,
 
-    /// Gain
+    /// Gain in dB, `auto` for the tuner's AGC, or `software` for a gain
+    /// control loop that keeps the ADC near --agc-target.
     #[clap(short, long, default_value = "auto")]
     pub gain: Gain,
 
+    /// Target peak level of the software gain control in dBFS.
+    #[clap(long, default_value = "-20", allow_negative_numbers = true)]
+    pub agc_target: f32,
+
+    /// How far the peak level may be from --agc-target before the software
+    /// gain control changes the gain, in dB.
+    #[clap(long, default_value = "3")]
+    pub agc_hysteresis: f32,
+
     /// Scroll down one line every X milliseconds.
     #[clap(long, default_value = "250")]
     pub scroll_interval: u64,
@@ -79,22 +140,84 @@ pub struct MainArgs {
     #[clap(long)]
     pub bandplan: Option<PathBuf>,
 
+    /// Use the specified CSV file instead of the default station database.
+    ///
+    /// See the `stations.csv` that is written to the config directory for
+    /// the format.
+    #[clap(long)]
+    pub stations: Option<PathBuf>,
+
+    /// How far a station in the database may be from a frequency to match
+    /// it, in Hz.
+    #[clap(long, default_value = "1000")]
+    pub station_tolerance: u32,
+
     /// Use the specified TOML file instead of the default keybinds
     pub keybinds: Option<PathBuf>,
 
     /// Use the specified JSON file as color map.
     pub colormap: Option<PathBuf>,
 
+    /// Use the themes from the specified JSON file instead of the default
+    /// themes file.
+    #[clap(long)]
+    pub themes: Option<PathBuf>,
+
+    /// Name of the theme to use, e.g. `dark`, `light` or `high-contrast`.
+    /// Defaults to the theme that was last selected.
+    #[clap(long)]
+    pub theme: Option<String>,
+
     /// Size of segments that are FFT'd
     #[clap(long, default_value = "16384")]
     pub fft_size: usize,
 
-    /// Overlap of segments that are FFT'd
+    /// Overlap of segments that are FFT'd. Can be cycled while running.
     #[clap(long, default_value = "0")]
     pub fft_overlap: usize,
 
     #[clap(long, default_value = "boxcar")]
     pub fft_window: Window,
+
+    /// FFT backend: `cpu` or `gpu`. The GPU backend requires the `gpu` feature
+    /// and falls back to the CPU if no GPU is available.
+    #[clap(long, default_value = "cpu")]
+    pub fft_backend: BackendKind,
+
+    /// Number of threads that run CPU FFTs, so they don't hold up the UI. With
+    /// 0 they run on the UI thread.
+    #[clap(long, default_value = "1")]
+    pub fft_threads: usize,
+
+    /// Demodulation mode: `am`, `sam` (synchronous AM), `usb` or `lsb`.
+    #[clap(long, default_value = "am")]
+    pub mode: Mode,
+
+    /// Detect from the spectrum whether the tuned signal is AM, USB or LSB,
+    /// and switch the demodulation mode accordingly.
+    #[clap(long)]
+    pub auto_sideband: bool,
+
+    /// Keep this many seconds of demodulated audio, for pausing and
+    /// rewinding.
+    #[clap(long, default_value = "60")]
+    pub time_shift: u64,
+
+    /// How audio playback catches up with live audio after pausing or
+    /// rewinding: `speedup` plays slightly faster, `skip` jumps to live on
+    /// resume.
+    #[clap(long, default_value = "speedup")]
+    pub time_shift_catch_up: CatchUp,
+
+    /// Listen for control commands on this address. Either a TCP address, or
+    /// `unix:<path>` for a UNIX domain socket.
+    #[clap(long)]
+    pub control: Option<String>,
+
+    /// Run this Rhai script, to automate tuning and recording.
+    #[cfg(feature = "scripting")]
+    #[clap(long)]
+    pub script: Option<PathBuf>,
 }
 
 #[derive(Debug, clap::Args)]
@@ -102,6 +225,91 @@ pub struct ImportSdrppBookmarksArgs {
     pub path: PathBuf,
 }
 
+#[derive(Debug, clap::Args)]
+pub struct SelftestArgs {
+    /// Skip slow checks, like decoding a full SSTV image.
+    #[clap(long)]
+    pub quick: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct BenchPipelineArgs {
+    /// Sample rates to run the WBFM receiver at, and to compare the FFT
+    /// throughput with.
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "1024000,2048000,2400000,3200000"
+    )]
+    pub sample_rates: Vec<u32>,
+
+    /// FFT sizes to run the waterfall with.
+    #[clap(long, value_delimiter = ',', default_value = "1024,4096,16384,65536")]
+    pub fft_sizes: Vec<usize>,
+
+    #[clap(long, default_value = "hann")]
+    pub fft_window: Window,
+
+    /// FFT backend: `cpu` or `gpu`.
+    #[clap(long, default_value = "cpu")]
+    pub fft_backend: BackendKind,
+
+    /// Seconds of signal each pipeline processes.
+    #[clap(long, default_value = "2")]
+    pub duration: f32,
+
+    /// Instead of measuring, run one pipeline in real time for this many
+    /// seconds. Memory, CPU load, buffer fill and dropped samples are logged
+    /// periodically, and the test fails if memory keeps growing or too many
+    /// samples are dropped.
+    #[clap(long)]
+    pub soak: Option<f32>,
+
+    /// Pipeline for --soak: `wbfm` or `waterfall`. It runs at the first of
+    /// the sample rates, and the waterfall with the first of the FFT sizes.
+    #[clap(long, default_value = "wbfm")]
+    pub soak_pipeline: SoakPipeline,
+
+    /// Seconds between the checks of --soak.
+    #[clap(long, default_value = "10")]
+    pub soak_interval: f32,
+
+    /// Largest share of samples --soak may drop.
+    #[clap(long, default_value = "0.001")]
+    pub max_drop_ratio: f64,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CwArgs {
+    /// Speed in words per minute.
+    #[clap(long, default_value = "20")]
+    pub wpm: f32,
+
+    /// How keys are turned into Morse: `straight`, `iambic-a` or `iambic-b`.
+    #[clap(long, default_value = "iambic-b")]
+    pub keyer: KeyerMode,
+
+    /// Frequency of the sidetone in Hz.
+    #[clap(long, default_value = "700")]
+    pub sidetone: f32,
+
+    /// Don't play a sidetone.
+    #[clap(long)]
+    pub no_sidetone: bool,
+
+    /// Rise and fall time of the keying envelope in seconds.
+    #[clap(long, default_value = "0.005")]
+    pub rise_time: f32,
+
+    /// Write the keyed signal as baseband IQ to this WAV file.
+    #[clap(long)]
+    pub tx_output: Option<PathBuf>,
+
+    /// Sample rate of the WAV file.
+    #[clap(long, default_value = "48000")]
+    pub tx_sample_rate: f32,
+}
+
 #[derive(Debug, clap::Args)]
 pub struct ProxyArgs {
     #[clap(short, long)]
@@ -115,6 +323,8 @@ pub struct ProxyArgs {
 pub enum Gain {
     Value(f32),
     Auto,
+    /// Gain is set by [`GainControl`][crate::gain_control::GainControl].
+    Software,
 }
 
 impl From<Gain> for rtlsdr_async::Gain {
@@ -122,6 +332,7 @@ impl From<Gain> for rtlsdr_async::Gain {
         match value {
             Gain::Value(gain) => Self::ManualValue((gain * 10.0) as i32),
             Gain::Auto => Self::Auto,
+            Gain::Software => Self::ManualValue((gain_control::INITIAL_GAIN * 10.0) as i32),
         }
     }
 }
@@ -132,6 +343,7 @@ impl FromStr for Gain {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "auto" => Ok(Self::Auto),
+            "software" => Ok(Self::Software),
             _ => Ok(Self::Value(s.parse()?)),
         }
     }
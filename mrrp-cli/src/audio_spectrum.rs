@@ -0,0 +1,180 @@
+//! Spectrogram of the demodulated audio.
+//!
+//! The demodulator feeds its audio output into an [`AudioSpectrumInspector`],
+//! which computes a spectrum every half FFT and keeps the latest few. The UI
+//! reads them through an [`AudioSpectrum`] handle.
+
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+};
+
+use mrrp::{
+    fft::{
+        FftBackend,
+        FftPool,
+        PooledFft,
+    },
+    io::combinators::Inspector,
+};
+use num_complex::Complex;
+use parking_lot::Mutex;
+
+use crate::fft::Window;
+
+/// At the usual audio rates this resolves CTCSS tones, which are a few Hz
+/// apart.
+const FFT_SIZE: usize = 1024;
+
+/// Consecutive FFTs overlap by half.
+const HOP_SIZE: usize = FFT_SIZE / 2;
+
+/// Number of spectra kept for display.
+const HISTORY: usize = 64;
+
+/// Spectra above this are not interesting for voice and data modes.
+const MAX_FREQUENCY: f32 = 5000.0;
+
+#[derive(Debug)]
+struct Lines {
+    /// Power in dB, newest first.
+    lines: VecDeque<Vec<f32>>,
+    num_bins: usize,
+}
+
+/// Cloneable handle to the spectra computed by an [`AudioSpectrumInspector`].
+#[derive(Clone, Debug)]
+pub struct AudioSpectrum {
+    lines: Arc<Mutex<Lines>>,
+    sample_rate: u32,
+}
+
+impl AudioSpectrum {
+    /// Frequency of the highest bin in each line.
+    pub fn max_frequency(&self) -> f32 {
+        let num_bins = self.lines.lock().num_bins;
+        num_bins as f32 * self.sample_rate as f32 / FFT_SIZE as f32
+    }
+
+    /// Returns up to `n` of the latest lines, newest first.
+    pub fn latest(&self, n: usize) -> Vec<Vec<f32>> {
+        self.lines.lock().lines.iter().take(n).cloned().collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct AudioSpectrumInspector {
+    fft: PooledFft,
+    window: Vec<f32>,
+    samples: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    spectrum: AudioSpectrum,
+}
+
+impl AudioSpectrumInspector {
+    pub fn new(sample_rate: u32, fft_pool: &FftPool) -> Self {
+        // only the bins for positive frequencies, up to the max frequency
+        let num_bins = ((MAX_FREQUENCY * FFT_SIZE as f32 / sample_rate as f32) as usize)
+            .clamp(1, FFT_SIZE / 2);
+
+        Self {
+            fft: fft_pool.plan(FFT_SIZE),
+            window: Window::Hann.to_vec(FFT_SIZE),
+            samples: Vec::with_capacity(2 * FFT_SIZE),
+            buffer: vec![Complex::default(); FFT_SIZE],
+            spectrum: AudioSpectrum {
+                lines: Arc::new(Mutex::new(Lines {
+                    lines: VecDeque::with_capacity(HISTORY),
+                    num_bins,
+                })),
+                sample_rate,
+            },
+        }
+    }
+
+    pub fn spectrum(&self) -> AudioSpectrum {
+        self.spectrum.clone()
+    }
+}
+
+impl Inspector<f32> for AudioSpectrumInspector {
+    fn inspect(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+
+        let mut offset = 0;
+        while self.samples.len() - offset >= FFT_SIZE {
+            // the demodulator outputs the envelope, so remove the DC offset,
+            // which would otherwise spill into the lowest bins
+            let frame = &self.samples[offset..][..FFT_SIZE];
+            let mean = frame.iter().sum::<f32>() / FFT_SIZE as f32;
+            for ((output, sample), window) in self.buffer.iter_mut().zip(frame).zip(&self.window) {
+                *output = Complex::from((sample - mean) * window);
+            }
+            self.fft.process(&mut self.buffer);
+
+            let mut lines = self.spectrum.lines.lock();
+            let mut line = if lines.lines.len() == HISTORY {
+                lines.lines.pop_back().unwrap()
+            }
+            else {
+                Vec::with_capacity(lines.num_bins)
+            };
+            line.clear();
+            line.extend(
+                self.buffer[..lines.num_bins]
+                    .iter()
+                    .map(|bin| 10.0 * bin.norm_sqr().max(1e-20).log10()),
+            );
+            lines.lines.push_front(line);
+
+            offset += HOP_SIZE;
+        }
+
+        self.samples.drain(..offset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use mrrp::{
+        fft::FftPool,
+        io::combinators::Inspector,
+    };
+
+    use super::{
+        AudioSpectrumInspector,
+        FFT_SIZE,
+    };
+
+    #[test]
+    fn it_shows_a_tone_in_the_right_bin() {
+        let sample_rate = 8000;
+        let mut inspector = AudioSpectrumInspector::new(sample_rate, &FftPool::new());
+        let spectrum = inspector.spectrum();
+
+        // a CTCSS tone of 100 Hz
+        let tone = (0..4 * FFT_SIZE)
+            .map(|i| 1.0 + (TAU * 100.0 * i as f32 / sample_rate as f32).sin())
+            .collect::<Vec<_>>();
+        for chunk in tone.chunks(100) {
+            inspector.inspect(chunk);
+        }
+
+        let lines = spectrum.latest(usize::MAX);
+        assert_eq!(lines.len(), 7);
+        // at 8 kHz, all bins up to Nyquist are below the max frequency
+        assert_eq!(lines[0].len(), FFT_SIZE / 2);
+        assert_eq!(spectrum.max_frequency(), 4000.0);
+
+        let peak = lines[0]
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+        let expected = 100.0 * FFT_SIZE as f32 / sample_rate as f32;
+        assert!((peak as f32 - expected).abs() <= 1.0, "peak at bin {peak}");
+    }
+}
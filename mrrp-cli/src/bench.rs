@@ -0,0 +1,603 @@
+//! Measures how fast the receive pipelines run on this machine.
+//!
+//! Every pipeline processes a generated signal as fast as it can, on a single
+//! thread. The time that takes, relative to how long the signal would take to
+//! receive, is the share of a CPU core that the pipeline needs at that sample
+//! rate. Anything close to 100% will drop samples.
+//!
+//! With `--soak` one pipeline runs in real time instead, for as long as
+//! requested, while a [`Watchdog`] checks that it keeps up and doesn't leak
+//! memory.
+
+use std::{
+    f32::consts::{
+        PI,
+        TAU,
+    },
+    hint::black_box,
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use color_eyre::eyre::{
+    bail,
+    eyre,
+};
+use mrrp::{
+    fft::{
+        BackendKind,
+        FftPool,
+    },
+    filter::fir::{
+        FirFilter,
+        hann_window,
+    },
+    io::{
+        ShutdownGuard,
+        combinators::Scanner,
+    },
+    modem::fm::{
+        FmDemodulator,
+        FmModulator,
+        FmPreset,
+    },
+    source::{
+        ComplexSinusoid,
+        SignalGenerator,
+        SineWave,
+    },
+    util::watchdog::{
+        PipelineCounters,
+        Watchdog,
+        WatchdogConfig,
+    },
+};
+use num_complex::Complex;
+use tokio::sync::mpsc;
+
+use crate::{
+    Error,
+    args::BenchPipelineArgs,
+    fft::{
+        Fft,
+        Window,
+    },
+};
+
+/// Sample rate the WBFM receiver decimates the channel to, approximately.
+const CHANNEL_SAMPLE_RATE: u32 = 240_000;
+
+/// Sample rate the WBFM receiver decimates the audio to, approximately.
+const AUDIO_SAMPLE_RATE: u32 = 48_000;
+
+/// Cutoff of the channel filter. This passes a broadcast FM signal with
+/// 75 kHz deviation.
+const CHANNEL_CUTOFF: f32 = 100_000.0;
+
+const CHANNEL_FILTER_TAPS: usize = 64;
+
+const AUDIO_CUTOFF: f32 = 15_000.0;
+
+const AUDIO_FILTER_TAPS: usize = 32;
+
+/// Length of the generated input, in seconds. Longer runs loop over it.
+const INPUT_DURATION: f32 = 0.25;
+
+/// How often the soak test's source delivers samples.
+const SOAK_CHUNK_DURATION: Duration = Duration::from_millis(10);
+
+/// Chunks the soak test's buffer holds, like the buffers of a USB source.
+const SOAK_BUFFER_CHUNKS: usize = 32;
+
+pub async fn run(args: BenchPipelineArgs, shutdown: ShutdownGuard) -> Result<(), Error> {
+    if args.duration.is_nan() || args.duration <= 0.0 {
+        bail!("Duration must be greater than 0");
+    }
+    if args
+        .sample_rates
+        .iter()
+        .any(|sample_rate| *sample_rate < 2 * CHANNEL_SAMPLE_RATE)
+    {
+        bail!(
+            "Sample rates must be at least {} for the WBFM receiver",
+            2 * CHANNEL_SAMPLE_RATE
+        );
+    }
+
+    if let Some(duration) = args.soak {
+        return soak(&args, duration, shutdown).await;
+    }
+
+    println!("WBFM receiver");
+    for &sample_rate in &args.sample_rates {
+        let measurement = wbfm_receiver(sample_rate, args.duration);
+        println!(
+            "  {:>6.3} MS/s  {}",
+            sample_rate as f64 * 1e-6,
+            measurement.format_load(sample_rate)
+        );
+    }
+
+    println!();
+    println!(
+        "FFT waterfall ({:?} window, {:?} backend)",
+        args.fft_window, args.fft_backend
+    );
+    let max_sample_rate = args.sample_rates.iter().copied().max().unwrap_or_default();
+    let num_samples = (args.duration * max_sample_rate as f32) as usize;
+    for &fft_size in &args.fft_sizes {
+        if fft_size == 0 || fft_size % 2 == 1 {
+            println!("  {fft_size:>6} points  SKIP: must be a non-zero multiple of 2");
+            continue;
+        }
+
+        let measurement =
+            fft_waterfall(fft_size, args.fft_window, args.fft_backend, num_samples).await;
+        println!(
+            "  {fft_size:>6} points  {:>7.1} MS/s max",
+            measurement.throughput() * 1e-6
+        );
+        for &sample_rate in &args.sample_rates {
+            println!(
+                "    at {:>6.3} MS/s  {}",
+                sample_rate as f64 * 1e-6,
+                measurement.format_load(sample_rate)
+            );
+        }
+    }
+
+    println!();
+    println!("The waterfall and the receiver run at the same time, so their CPU loads add up.");
+    println!("Overlapping FFT segments multiplies the FFT load by size / (size - overlap).");
+
+    Ok(())
+}
+
+/// Pipeline that `--soak` runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoakPipeline {
+    Wbfm,
+    Waterfall,
+}
+
+impl FromStr for SoakPipeline {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wbfm" => Ok(Self::Wbfm),
+            "waterfall" => Ok(Self::Waterfall),
+            _ => Err(eyre!("No such pipeline: {s}")),
+        }
+    }
+}
+
+/// Runs a pipeline in real time for `duration` seconds and fails if the
+/// watchdog does.
+///
+/// A task delivers samples at the sample rate into a buffer, like a receiver
+/// would, and drops them if the buffer is full because the pipeline can't keep
+/// up.
+async fn soak(
+    args: &BenchPipelineArgs,
+    duration: f32,
+    shutdown: ShutdownGuard,
+) -> Result<(), Error> {
+    if duration.is_nan() || duration <= 0.0 {
+        bail!("Soak duration must be greater than 0");
+    }
+    if args.soak_interval.is_nan() || args.soak_interval <= 0.0 {
+        bail!("Soak interval must be greater than 0");
+    }
+    let Some(&sample_rate) = args.sample_rates.first()
+    else {
+        bail!("No sample rate to run the soak test at");
+    };
+
+    let mut pipeline = match args.soak_pipeline {
+        SoakPipeline::Wbfm => {
+            let offset = sample_rate as f32 / 8.0;
+            Pipeline::Wbfm {
+                receiver: WbfmReceiver::new(sample_rate, offset),
+                input: wbfm_signal(sample_rate, offset),
+                position: 0,
+                audio: vec![],
+            }
+        }
+        SoakPipeline::Waterfall => {
+            let Some(&size) = args.fft_sizes.first()
+            else {
+                bail!("No FFT size to run the soak test with");
+            };
+            if size == 0 || size % 2 == 1 {
+                bail!("FFT size must be a non-zero multiple of 2");
+            }
+            let mut sinusoid = ComplexSinusoid::new(1000.0, 48_000.0);
+            Pipeline::Waterfall {
+                fft: Fft::new(size, args.fft_window, args.fft_backend, &FftPool::new()).await,
+                input: std::iter::repeat_with(|| sinusoid.next())
+                    .take(size)
+                    .collect(),
+                power: vec![0.0; size],
+                pending: 0,
+            }
+        }
+    };
+
+    println!(
+        "Soak test: {:?} at {:.3} MS/s for {duration} s",
+        args.soak_pipeline,
+        sample_rate as f64 * 1e-6
+    );
+
+    let chunk_size = (f64::from(sample_rate) * SOAK_CHUNK_DURATION.as_secs_f64()) as usize;
+    let (sender, mut receiver) = mpsc::channel(SOAK_BUFFER_CHUNKS);
+    let num_dropped = Arc::new(AtomicU64::new(0));
+    let num_processed = AtomicU64::new(0);
+    let token = shutdown.token().child_token();
+
+    tokio::spawn({
+        let sender = sender.clone();
+        let num_dropped = num_dropped.clone();
+        let token = token.clone();
+        async move {
+            let mut interval = tokio::time::interval(SOAK_CHUNK_DURATION);
+            loop {
+                tokio::select! {
+                    _ = token.cancelled() => break,
+                    _ = interval.tick() => {
+                        match sender.try_send(chunk_size) {
+                            Ok(()) => {}
+                            Err(mpsc::error::TrySendError::Full(_)) => {
+                                num_dropped.fetch_add(chunk_size as u64, Ordering::Relaxed);
+                            }
+                            Err(mpsc::error::TrySendError::Closed(_)) => break,
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    let process = async {
+        while let Some(num_samples) = receiver.recv().await {
+            pipeline.process(num_samples).await;
+            num_processed.fetch_add(num_samples as u64, Ordering::Relaxed);
+        }
+    };
+
+    let monitor = async {
+        let mut watchdog = Watchdog::new(WatchdogConfig {
+            max_drop_ratio: args.max_drop_ratio,
+            ..Default::default()
+        });
+        let mut checks = tokio::time::interval(Duration::from_secs_f32(args.soak_interval));
+        // the first tick is immediate
+        checks.tick().await;
+        let end = tokio::time::sleep(Duration::from_secs_f32(duration));
+        tokio::pin!(end);
+
+        loop {
+            tokio::select! {
+                _ = &mut end => break,
+                _ = token.cancelled() => {
+                    println!("Soak test stopped");
+                    break;
+                }
+                _ = checks.tick() => {
+                    let counters = PipelineCounters {
+                        buffer_fill: 1.0 - sender.capacity() as f32 / SOAK_BUFFER_CHUNKS as f32,
+                        num_processed: num_processed.load(Ordering::Relaxed),
+                        num_dropped: num_dropped.load(Ordering::Relaxed),
+                    };
+                    let (report, result) = watchdog.check(counters);
+                    println!("{report}");
+                    tracing::info!(%report, "Soak test");
+
+                    if let Err(error) = result {
+                        tracing::error!(%error, "Soak test failed");
+                        bail!("Soak test failed: {error}");
+                    }
+                }
+            }
+        }
+
+        Ok::<_, Error>(())
+    };
+
+    let result = tokio::select! {
+        result = monitor => result,
+        // the source only stops when cancelled
+        _ = process => Ok(()),
+    };
+    token.cancel();
+
+    if result.is_ok() {
+        println!("Soak test passed");
+    }
+    result
+}
+
+/// A pipeline the soak test runs, with its input.
+#[derive(Debug)]
+enum Pipeline {
+    Wbfm {
+        receiver: WbfmReceiver,
+        input: Vec<Complex<f32>>,
+        position: usize,
+        audio: Vec<f32>,
+    },
+    Waterfall {
+        fft: Fft,
+        input: Vec<Complex<f32>>,
+        power: Vec<f32>,
+        pending: usize,
+    },
+}
+
+impl Pipeline {
+    async fn process(&mut self, mut num_samples: usize) {
+        match self {
+            Self::Wbfm {
+                receiver,
+                input,
+                position,
+                audio,
+            } => {
+                while num_samples > 0 {
+                    let n = num_samples.min(input.len() - *position);
+                    audio.clear();
+                    receiver.process(&input[*position..][..n], audio);
+                    black_box(&audio);
+                    *position = (*position + n) % input.len();
+                    num_samples -= n;
+                }
+            }
+            Self::Waterfall {
+                fft,
+                input,
+                power,
+                pending,
+            } => {
+                *pending += num_samples;
+                while *pending >= input.len() {
+                    let spectrum = fft.forward(input).await;
+                    for (power, bin) in power.iter_mut().zip(spectrum) {
+                        *power = 10.0 * bin.norm_sqr().log10();
+                    }
+                    black_box(&power);
+                    *pending -= input.len();
+                }
+            }
+        }
+    }
+}
+
+/// How long a pipeline took to process a number of samples.
+#[derive(Clone, Copy, Debug)]
+struct Measurement {
+    num_samples: usize,
+    elapsed: Duration,
+}
+
+impl Measurement {
+    /// Samples per second
+    fn throughput(&self) -> f64 {
+        self.num_samples as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+
+    /// Share of a CPU core that's needed to keep up with `sample_rate`.
+    fn load(&self, sample_rate: u32) -> f64 {
+        f64::from(sample_rate) / self.throughput()
+    }
+
+    fn format_load(&self, sample_rate: u32) -> String {
+        let load = self.load(sample_rate);
+        format!(
+            "{:>6.1}x realtime  CPU {:>5.1}%{}",
+            1.0 / load,
+            load * 100.0,
+            if load >= 1.0 { "  too slow" } else { "" }
+        )
+    }
+}
+
+/// Runs the WBFM receiver for `duration` seconds of signal.
+fn wbfm_receiver(sample_rate: u32, duration: f32) -> Measurement {
+    // the station is off center, so that the receiver has to mix it down
+    let offset = sample_rate as f32 / 8.0;
+    let input = wbfm_signal(sample_rate, offset);
+    let mut receiver = WbfmReceiver::new(sample_rate, offset);
+    let mut audio = Vec::with_capacity(input.len() / receiver.decimation() + 1);
+
+    let num_samples = (duration * sample_rate as f32) as usize;
+    let start = Instant::now();
+    let mut remaining = num_samples;
+    while remaining > 0 {
+        let n = remaining.min(input.len());
+        audio.clear();
+        receiver.process(&input[..n], &mut audio);
+        black_box(&audio);
+        remaining -= n;
+    }
+
+    Measurement {
+        num_samples,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Runs the waterfall FFT, including the conversion to dB, on at least
+/// `num_samples` samples.
+async fn fft_waterfall(
+    size: usize,
+    window: Window,
+    backend: BackendKind,
+    num_samples: usize,
+) -> Measurement {
+    let mut fft = Fft::new(size, window, backend, &FftPool::new()).await;
+    let mut sinusoid = ComplexSinusoid::new(1000.0, 48_000.0);
+    let input = std::iter::repeat_with(|| sinusoid.next())
+        .take(size)
+        .collect::<Vec<_>>();
+    let mut power = vec![0.0f32; size];
+
+    let num_ffts = num_samples.div_ceil(size).max(1);
+    let start = Instant::now();
+    for _ in 0..num_ffts {
+        let spectrum = fft.forward(&input).await;
+        for (power, bin) in power.iter_mut().zip(spectrum) {
+            *power = 10.0 * bin.norm_sqr().log10();
+        }
+        black_box(&power);
+    }
+
+    Measurement {
+        num_samples: num_ffts * size,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// A broadcast FM station modulated with a 1 kHz tone, `offset` Hz from the
+/// center.
+fn wbfm_signal(sample_rate: u32, offset: f32) -> Vec<Complex<f32>> {
+    let sample_rate = sample_rate as f32;
+    let mut tone = SineWave::new(1000.0, sample_rate);
+    let mut modulator = FmModulator::from_preset(sample_rate, FmPreset::Broadcast);
+    let mut carrier = ComplexSinusoid::new(offset, sample_rate);
+
+    (0..(INPUT_DURATION * sample_rate) as usize)
+        .map(|_| modulator.scan(0.5 * tone.next()) * carrier.next())
+        .collect()
+}
+
+/// Broadcast FM receiver.
+///
+/// This mixes the station to baseband, filters and decimates the channel,
+/// demodulates it, and then filters and decimates the audio.
+#[derive(Debug)]
+struct WbfmReceiver {
+    mixer: ComplexSinusoid,
+    channel_filter: FirFilter<Complex<f32>, f32>,
+    channel_decimation: usize,
+    channel_phase: usize,
+    demodulator: FmDemodulator,
+    audio_filter: FirFilter<f32, f32>,
+    audio_decimation: usize,
+    audio_phase: usize,
+}
+
+impl WbfmReceiver {
+    fn new(sample_rate: u32, offset: f32) -> Self {
+        let channel_decimation = (sample_rate / CHANNEL_SAMPLE_RATE).max(1) as usize;
+        let channel_sample_rate = sample_rate as f32 / channel_decimation as f32;
+        let audio_decimation =
+            ((channel_sample_rate / AUDIO_SAMPLE_RATE as f32).round() as usize).max(1);
+
+        Self {
+            mixer: ComplexSinusoid::new(-offset, sample_rate as f32),
+            channel_filter: FirFilter::new(lowpass(
+                CHANNEL_CUTOFF,
+                sample_rate as f32,
+                CHANNEL_FILTER_TAPS,
+            )),
+            channel_decimation,
+            channel_phase: 0,
+            demodulator: FmDemodulator::new(
+                channel_sample_rate,
+                FmPreset::Broadcast.frequency_deviation(),
+            ),
+            audio_filter: FirFilter::new(lowpass(
+                AUDIO_CUTOFF,
+                channel_sample_rate,
+                AUDIO_FILTER_TAPS,
+            )),
+            audio_decimation,
+            audio_phase: 0,
+        }
+    }
+
+    /// Input samples per audio sample.
+    fn decimation(&self) -> usize {
+        self.channel_decimation * self.audio_decimation
+    }
+
+    fn process(&mut self, input: &[Complex<f32>], audio: &mut Vec<f32>) {
+        for sample in input {
+            let sample = self.channel_filter.scan(sample * self.mixer.next());
+            self.channel_phase += 1;
+            if self.channel_phase < self.channel_decimation {
+                continue;
+            }
+            self.channel_phase = 0;
+
+            let sample = self.audio_filter.scan(self.demodulator.scan(sample));
+            self.audio_phase += 1;
+            if self.audio_phase == self.audio_decimation {
+                self.audio_phase = 0;
+                audio.push(sample);
+            }
+        }
+    }
+}
+
+/// Windowed-sinc low-pass with unity gain at DC.
+fn lowpass(cutoff: f32, sample_rate: f32, num_taps: usize) -> Vec<f32> {
+    let cutoff = cutoff / sample_rate;
+    let center = 0.5 * (num_taps - 1) as f32;
+
+    let taps = hann_window::<f32>(num_taps - 1)
+        .enumerate()
+        .map(|(i, window)| {
+            let x = i as f32 - center;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            }
+            else {
+                (TAU * cutoff * x).sin() / (PI * x)
+            };
+            sinc * window
+        })
+        .collect::<Vec<_>>();
+
+    let gain = taps.iter().sum::<f32>();
+    taps.into_iter().map(|tap| tap / gain).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        WbfmReceiver,
+        wbfm_signal,
+    };
+
+    #[test]
+    fn wbfm_receiver_recovers_the_tone() {
+        let sample_rate = 1_024_000;
+        let offset = 128_000.0;
+        let input = wbfm_signal(sample_rate, offset);
+        let mut receiver = WbfmReceiver::new(sample_rate, offset);
+        assert_eq!(receiver.decimation(), 20);
+
+        let mut audio = vec![];
+        receiver.process(&input, &mut audio);
+        assert_eq!(audio.len(), input.len() / 20);
+
+        // skip the filters settling. the demodulator compresses a bit, since
+        // 75 kHz deviation is a large phase step at the channel sample rate.
+        let audio = &audio[audio.len() / 2..];
+        let rms = (audio.iter().map(|x| x * x).sum::<f32>() / audio.len() as f32).sqrt();
+        let expected = 0.5 / 2.0f32.sqrt();
+        assert!((rms - expected).abs() < 0.2 * expected, "rms = {rms}");
+    }
+}
@@ -0,0 +1,329 @@
+//! Control socket
+//!
+//! Lets external programs control a running TUI. The socket is enabled with
+//! `--control <address>`, where the address is either a TCP address (e.g.
+//! `127.0.0.1:4321`) or, on unix, `unix:<path>` for a UNIX domain socket.
+//!
+//! The protocol is JSON-RPC style with one JSON object per line. A request
+//! looks like this:
+//!
+//! ```json
+//! {"id": 1, "method": "set_frequency", "params": {"frequency": 7100000}}
+//! ```
+//!
+//! `id` is optional and can be any JSON value. It is copied into the response,
+//! which has either a `result` or an `error` field:
+//!
+//! ```json
+//! {"id": 1, "result": null}
+//! {"id": 1, "error": {"message": "Unknown method: foo"}}
+//! ```
+//!
+//! # Methods
+//!
+//! | method            | params                                 | result        |
+//! |-------------------|----------------------------------------|---------------|
+//! | `status`          |                                        | [`AppStatus`] |
+//! | `set_frequency`   | `{"frequency": <Hz>}`                  | `null`        |
+//! | `set_gain`        | `{"gain": <dB> \| null \| "software"}` | `null`        |
+//! | `set_mode`        | `{"mode": <mode>}`                     | `null`        |
+//! | `start_recording` | `{"path": <path>}`                     | `null`        |
+//! | `stop_recording`  |                                        | `null`        |
+//! | `set_source`      | `{"source": <source>}`                 | `null`        |
+//! | `quit`            |                                        | `null`        |
+//!
+//! A gain of `null` selects the tuner's automatic gain control, and
+//! `"software"` selects the software gain control. Recordings are written as
+//! interleaved 32-bit float IQ samples (little endian), or compressed if the
+//! path ends in `.bfp` or `.bfp8`, see
+//! [`Recording`][crate::recording::Recording]. If writing the recording fails,
+//! e.g. because the disk is full, the recording stops and `status` reports the
+//! error in `recording_error`. Likewise, `marker_export_error` reports why
+//! exporting a marker measurement from the UI failed.
+//!
+//! A source is `rtlsdr[:<index>]`, `rtl_tcp:<address>` or `file:<path>`, see
+//! [`SourceSpec`](crate::source::SourceSpec). `set_source` returns once the new
+//! source is open. If it can't be opened, the previous source is reopened and
+//! an error is returned. Switching the source stops a recording.
+//!
+//! A mode is `am`, `sam` (synchronous AM), `usb` or `lsb`, like with `--mode`.
+//! With `--auto-sideband` the detected mode can override it.
+
+use std::path::PathBuf;
+
+use color_eyre::eyre::eyre;
+use serde::{
+    Deserialize,
+    Serialize,
+    de::DeserializeOwned,
+};
+use serde_json::Value;
+use tokio::{
+    io::{
+        AsyncBufReadExt,
+        AsyncRead,
+        AsyncWrite,
+        AsyncWriteExt,
+        BufReader,
+    },
+    net::TcpListener,
+};
+
+use crate::{
+    Error,
+    app::AppProxy,
+    args::Gain,
+};
+
+/// Binds the control socket and serves connections until an error occurs.
+pub async fn serve(address: &str, app: AppProxy) -> Result<(), Error> {
+    if let Some(path) = address.strip_prefix("unix:") {
+        serve_unix(path, app).await
+    }
+    else {
+        let listener = TcpListener::bind(address).await?;
+        tracing::info!(address, "Control socket listening");
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            tracing::debug!(?peer, "Control connection");
+            let (reader, writer) = stream.into_split();
+            spawn_connection(reader, writer, app.clone());
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn serve_unix(path: &str, app: AppProxy) -> Result<(), Error> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // remove stale socket from a previous run, but nothing else that might be
+    // at that path
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => return Err(eyre!("{path} exists and is not a socket")),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error.into()),
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)?;
+    tracing::info!(path, "Control socket listening");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let (reader, writer) = stream.into_split();
+        spawn_connection(reader, writer, app.clone());
+    }
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_path: &str, _app: AppProxy) -> Result<(), Error> {
+    Err(eyre!(
+        "UNIX domain sockets are not supported on this platform"
+    ))
+}
+
+fn spawn_connection<R, W>(reader: R, writer: W, app: AppProxy)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        if let Err(error) = handle_connection(reader, writer, app).await {
+            tracing::debug!(?error, "Control connection closed");
+        }
+    });
+}
+
+async fn handle_connection<R, W>(reader: R, mut writer: W, app: AppProxy) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let result = dispatch(&request.method, request.params, &app).await;
+                Response::new(request.id, result)
+            }
+            Err(error) => Response::new(Value::Null, Err(error.into())),
+        };
+
+        let mut response = serde_json::to_vec(&response)?;
+        response.push(b'\n');
+        writer.write_all(&response).await?;
+        writer.flush().await?;
+    }
+
+    Ok(())
+}
+
+async fn dispatch(method: &str, params: Value, app: &AppProxy) -> Result<Value, Error> {
+    match method {
+        "status" => Ok(serde_json::to_value(app.query_status().await?)?),
+        "set_frequency" => {
+            let SetFrequency { frequency } = params_from_value(params)?;
+            app.set_center_frequency(frequency);
+            Ok(Value::Null)
+        }
+        "set_gain" => {
+            let SetGain { gain } = params_from_value(params)?;
+            app.set_gain(match gain {
+                None => Gain::Auto,
+                Some(GainParam::Value(gain)) => Gain::Value(gain),
+                Some(GainParam::Software(_)) => Gain::Software,
+            });
+            Ok(Value::Null)
+        }
+        "start_recording" => {
+            let StartRecording { path } = params_from_value(params)?;
+            app.start_recording(path).await?;
+            Ok(Value::Null)
+        }
+        "set_mode" => {
+            let SetMode { mode } = params_from_value(params)?;
+            app.set_mode(mode.parse()?);
+            Ok(Value::Null)
+        }
+        "stop_recording" => {
+            app.stop_recording();
+            Ok(Value::Null)
+        }
+        "set_source" => {
+            let SetSource { source } = params_from_value(params)?;
+            app.switch_source(source.parse()?).await?;
+            Ok(Value::Null)
+        }
+        "quit" => {
+            app.request_exit();
+            Ok(Value::Null)
+        }
+        _ => Err(eyre!("Unknown method: {method}")),
+    }
+}
+
+fn params_from_value<T: DeserializeOwned>(params: Value) -> Result<T, Error> {
+    serde_json::from_value(params).map_err(|error| eyre!("Invalid params: {error}"))
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ResponseError>,
+}
+
+impl Response {
+    fn new(id: Value, result: Result<Value, Error>) -> Self {
+        match result {
+            Ok(result) => {
+                Self {
+                    id,
+                    result: Some(result),
+                    error: None,
+                }
+            }
+            Err(error) => {
+                Self {
+                    id,
+                    result: None,
+                    error: Some(ResponseError {
+                        message: error.to_string(),
+                    }),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetFrequency {
+    frequency: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetGain {
+    gain: Option<GainParam>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum GainParam {
+    Value(f32),
+    Software(SoftwareGain),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SoftwareGain {
+    Software,
+}
+
+#[derive(Debug, Deserialize)]
+struct StartRecording {
+    path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetMode {
+    mode: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetSource {
+    source: String,
+}
+
+/// Result of the `status` method.
+#[derive(Clone, Debug, Serialize)]
+pub struct AppStatus {
+    pub center_frequency: u32,
+    pub sample_rate: u32,
+
+    /// Tuner gain in dB, or `None` for the tuner's automatic gain control.
+    pub gain: Option<f32>,
+
+    /// Whether the gain is set by the software gain control.
+    pub software_gain_control: bool,
+
+    /// The source samples are read from, or `None` while it's switched.
+    pub source: Option<String>,
+
+    pub recording: Option<RecordingStatus>,
+
+    /// Why the last recording stopped, if it failed, e.g. because the disk is
+    /// full. This is cleared when a recording is started.
+    pub recording_error: Option<String>,
+
+    /// Why the last export of a marker measurement failed, if it did. This is
+    /// cleared by the next successful export.
+    pub marker_export_error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RecordingStatus {
+    pub path: PathBuf,
+    pub num_samples: usize,
+}
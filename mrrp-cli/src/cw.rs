@@ -0,0 +1,256 @@
+//! Keys Morse from the keyboard.
+//!
+//! The keyboard works as a straight key or as paddles of an iambic keyer. The
+//! keying is played as a sidetone, and can be written as a baseband signal to
+//! a WAV file for transmission.
+//!
+//! Terminals only report key releases if they support the keyboard
+//! enhancement protocol. Otherwise every key press (or auto-repeat) holds the
+//! key for one dit.
+
+use std::{
+    collections::HashMap,
+    io::{
+        Write,
+        stdout,
+    },
+    time::Duration,
+};
+
+use color_eyre::eyre::bail;
+use crossterm::{
+    event::{
+        Event,
+        EventStream,
+        KeyCode,
+        KeyEventKind,
+        KeyModifiers,
+        KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags,
+    },
+    execute,
+};
+use futures_util::StreamExt;
+use mrrp::{
+    audio::RodioSource,
+    io::ShutdownGuard,
+    modem::cw::{
+        CwKeyer,
+        Key,
+        KeyEvent,
+        KeyInput,
+        KeyerDropped,
+    },
+    prelude::*,
+    sink::file::WavSink,
+};
+use rodio::DeviceSinkBuilder;
+use tokio::time::{
+    Instant,
+    sleep_until,
+};
+
+use crate::{
+    Error,
+    args::CwArgs,
+};
+
+/// Sample rate of the sidetone.
+const SIDETONE_SAMPLE_RATE: f32 = 48_000.0;
+
+pub async fn run(args: CwArgs, shutdown: ShutdownGuard) -> Result<(), Error> {
+    if args.wpm.is_nan() || args.wpm <= 0.0 {
+        bail!("WPM must be greater than 0");
+    }
+    if args.rise_time.is_nan() || args.rise_time < 0.0 {
+        bail!("Rise time must not be negative");
+    }
+
+    let keyer = |sample_rate| {
+        CwKeyer::new(args.keyer, args.wpm, sample_rate).with_rise_time(args.rise_time)
+    };
+    let mut key_inputs = vec![];
+
+    let _audio_output = if args.no_sidetone {
+        None
+    }
+    else {
+        let sidetone = keyer(SIDETONE_SAMPLE_RATE).sidetone(args.sidetone);
+        key_inputs.push(sidetone.keyer().key_input());
+        let audio_output = DeviceSinkBuilder::open_default_sink()?;
+        audio_output.mixer().add(RodioSource::new(
+            sidetone.into_read_samples().map(|sample| 0.5 * sample),
+        ));
+        Some(audio_output)
+    };
+
+    let mut tx_keyer = None;
+    let mut tx_sink = None;
+    if let Some(path) = &args.tx_output {
+        let keyer = keyer(args.tx_sample_rate);
+        key_inputs.push(keyer.key_input());
+        tx_keyer = Some(keyer);
+        tx_sink = Some(WavSink::<_, Complex<f32>>::from_path(
+            path,
+            args.tx_sample_rate,
+        )?);
+    }
+
+    let shutdown_token = shutdown.token();
+    let keys = async {
+        let terminal = RawTerminal::enter()?;
+        read_keys(
+            &key_inputs,
+            terminal.reports_releases,
+            Duration::from_secs_f32(1.2 / args.wpm),
+        )
+        .await
+    };
+    let transmit = async {
+        if let (Some(keyer), Some(sink)) = (tx_keyer, &mut tx_sink) {
+            // the keyer reads its key events as samples are pulled from it, so
+            // it has to run in real time
+            keyer
+                .into_read_samples()
+                .map(|envelope| Complex::new(envelope, 0.0))
+                .throttle_to_sample_rate()
+                .pump(sink)
+                .with_shutdown(shutdown)
+                .run()
+                .await?;
+        }
+        else {
+            std::future::pending::<()>().await;
+        }
+        Ok::<(), Error>(())
+    };
+
+    tokio::select! {
+        result = keys => result?,
+        result = transmit => result?,
+        _ = shutdown_token.cancelled() => {}
+    }
+
+    // this finalizes the WAV file
+    if let Some(mut sink) = tx_sink {
+        sink.close().await?;
+    }
+
+    Ok(())
+}
+
+async fn read_keys(
+    key_inputs: &[KeyInput],
+    reports_releases: bool,
+    dit_duration: Duration,
+) -> Result<(), Error> {
+    // raw mode doesn't translate newlines
+    print!("Dit: Z, Left. Dah: X, Right. Straight key: Space. Quit: Esc, Q.\r\n");
+    if !reports_releases {
+        print!(
+            "This terminal doesn't report key releases. Every key press holds the key for one dit.\r\n"
+        );
+    }
+    stdout().flush()?;
+
+    let mut terminal_events = EventStream::new();
+
+    // keys that are released when their deadline passes, if the terminal doesn't
+    // report releases
+    let mut held = HashMap::<Key, Instant>::new();
+
+    loop {
+        let next_release = held.values().min().copied();
+        let release = async {
+            match next_release {
+                Some(deadline) => sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            event = terminal_events.next() => {
+                let Some(event) = event.transpose()?
+                else {
+                    return Ok(());
+                };
+                let Event::Key(event) = event
+                else {
+                    continue;
+                };
+
+                let key = match event.code {
+                    KeyCode::Esc | KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(());
+                    }
+                    KeyCode::Char('z') | KeyCode::Left => Key::Dit,
+                    KeyCode::Char('x') | KeyCode::Right => Key::Dah,
+                    KeyCode::Char(' ') => Key::Straight,
+                    _ => continue,
+                };
+
+                match (event.kind, reports_releases) {
+                    (KeyEventKind::Press, true) => send(key_inputs, KeyEvent::Pressed(key))?,
+                    // repeats of a held key don't change anything
+                    (KeyEventKind::Repeat, true) => {}
+                    (KeyEventKind::Press | KeyEventKind::Repeat, false) => {
+                        if held.insert(key, Instant::now() + dit_duration).is_none() {
+                            send(key_inputs, KeyEvent::Pressed(key))?;
+                        }
+                    }
+                    (KeyEventKind::Release, _) => send(key_inputs, KeyEvent::Released(key))?,
+                }
+            }
+            _ = release => {
+                let now = Instant::now();
+                let released = held
+                    .iter()
+                    .filter(|(_, deadline)| **deadline <= now)
+                    .map(|(key, _)| *key)
+                    .collect::<Vec<_>>();
+                for key in released {
+                    held.remove(&key);
+                    send(key_inputs, KeyEvent::Released(key))?;
+                }
+            }
+        }
+    }
+}
+
+fn send(key_inputs: &[KeyInput], event: KeyEvent) -> Result<(), KeyerDropped> {
+    key_inputs.iter().try_for_each(|input| input.send(event))
+}
+
+/// Keeps the terminal in raw mode, so that key presses are read immediately,
+/// and asks it to report key releases, if it can.
+struct RawTerminal {
+    reports_releases: bool,
+}
+
+impl RawTerminal {
+    fn enter() -> Result<Self, Error> {
+        crossterm::terminal::enable_raw_mode()?;
+
+        let reports_releases =
+            crossterm::terminal::supports_keyboard_enhancement().unwrap_or_default();
+        if reports_releases {
+            execute!(
+                stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        Ok(Self { reports_releases })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        if self.reports_releases {
+            let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+}
@@ -2,9 +2,35 @@ use std::{
     collections::VecDeque,
     f32::consts::TAU,
     num::NonZero,
+    str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
+use color_eyre::eyre::eyre;
+use mrrp::{
+    analysis::sideband::SignalKind,
+    fft::FftPool,
+    io::combinators::{
+        Inspector,
+        Scanner,
+        ScopeHandle,
+        ScopeInspector,
+        StatsHandle,
+        StatsInspector,
+        Trigger,
+    },
+    modem::{
+        am::{
+            CoherentDemodulator,
+            EnvelopeDemodulator,
+        },
+        ssb::{
+            Sideband,
+            SsbDemodulator,
+        },
+    },
+};
 use num_complex::Complex;
 use parking_lot::Mutex;
 use serde::{
@@ -12,29 +38,90 @@ use serde::{
     Serialize,
 };
 
-use crate::util::FrequencyBand;
+use crate::{
+    Error,
+    audio_spectrum::{
+        AudioSpectrum,
+        AudioSpectrumInspector,
+    },
+    time_shift::{
+        CatchUp,
+        TimeShiftBuffer,
+        TimeShiftHandle,
+    },
+    util::FrequencyBand,
+};
+
+/// Length of the audio snapshots shown in the scope.
+const SCOPE_FRAME_LENGTH: usize = 1024;
+
+/// How the channel is turned into audio.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Am,
+    /// Synchronous AM, which locks onto the carrier.
+    SyncAm,
+    Usb,
+    Lsb,
+}
+
+impl From<SignalKind> for Mode {
+    fn from(kind: SignalKind) -> Self {
+        match kind {
+            SignalKind::Am => Self::Am,
+            SignalKind::Ssb(Sideband::Upper) => Self::Usb,
+            SignalKind::Ssb(Sideband::Lower) => Self::Lsb,
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "am" => Ok(Self::Am),
+            "sam" => Ok(Self::SyncAm),
+            "usb" => Ok(Self::Usb),
+            "lsb" => Ok(Self::Lsb),
+            _ => Err(eyre!("No such demodulation mode: {s}")),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Demodulator {
+    frequency_band: FrequencyBand,
+    sampled_frequency_band: FrequencyBand,
     shift: ComplexSine,
     lowpass: FirFilter,
     //lowpass: biquad::DirectForm1<f32>,
     decimation: usize,
     next_decimation: usize,
-    audio_buffer: Arc<Mutex<AudioBuffer>>,
+    mode: Mode,
+    am: EnvelopeDemodulator,
+    sync_am: CoherentDemodulator,
+    ssb: SsbDemodulator,
+    audio_buffer: Arc<Mutex<TimeShiftBuffer>>,
     audio_source: AudioSource,
+    audio_chunk: Vec<f32>,
+    scope: ScopeInspector<f32>,
+    audio_spectrum: AudioSpectrumInspector,
+    audio_stats: StatsInspector<f32>,
 }
 
 impl Demodulator {
-    pub fn new(frequency_band: FrequencyBand, sampled_frequency_band: FrequencyBand) -> Self {
-        let shift = frequency_band.center() as f32 - sampled_frequency_band.center() as f32;
-        let shift = ComplexSine::new(-shift, sampled_frequency_band.bandwidth() as f32);
-
-        let decimation = sampled_frequency_band
-            .bandwidth()
-            .div_ceil(frequency_band.bandwidth() / 2) as usize;
-
-        let lowpass = FirFilter::boxcar(decimation + 1);
+    /// `time_shift` is how much audio is kept for pausing and rewinding.
+    pub fn new(
+        frequency_band: FrequencyBand,
+        sampled_frequency_band: FrequencyBand,
+        time_shift: Duration,
+        catch_up: CatchUp,
+        mode: Mode,
+        fft_pool: &FftPool,
+    ) -> Self {
+        let (shift, lowpass, decimation) = mixer(frequency_band, sampled_frequency_band);
 
         /*let lowpass = biquad::DirectForm1::new(
             biquad::Coefficients::from_params(
@@ -46,21 +133,73 @@ impl Demodulator {
             .unwrap(),
         );*/
 
-        let audio_buffer = Arc::new(Mutex::new(AudioBuffer::new(
-            frequency_band.bandwidth() as usize
+        let sample_rate = frequency_band.bandwidth() / 2;
+        let audio_buffer = Arc::new(Mutex::new(TimeShiftBuffer::new(
+            sample_rate,
+            time_shift,
+            catch_up,
         )));
         let audio_source = AudioSource {
             audio_buffer: audio_buffer.clone(),
-            sample_rate: frequency_band.bandwidth() / 2,
+            sample_rate,
         };
 
         Self {
+            frequency_band,
+            sampled_frequency_band,
             shift,
             lowpass,
             decimation: decimation,
             next_decimation: 0,
+            mode,
+            am: EnvelopeDemodulator::new(sample_rate as f32),
+            sync_am: CoherentDemodulator::new(sample_rate as f32),
+            ssb: SsbDemodulator::new(
+                sample_rate as f32,
+                if mode == Mode::Lsb {
+                    Sideband::Lower
+                }
+                else {
+                    Sideband::Upper
+                },
+            ),
             audio_buffer,
             audio_source,
+            audio_chunk: vec![],
+            scope: ScopeInspector::new(
+                SCOPE_FRAME_LENGTH,
+                SCOPE_FRAME_LENGTH / 4,
+                Trigger::FreeRun,
+            ),
+            audio_spectrum: AudioSpectrumInspector::new(sample_rate, fft_pool),
+            audio_stats: StatsInspector::new(),
+        }
+    }
+
+    /// Changes the band that the input samples cover, e.g. when the SDR was
+    /// retuned, or the source switched. The audio sample rate stays the same,
+    /// so audio output and time shift keep running.
+    pub fn set_sampled_frequency_band(&mut self, sampled_frequency_band: FrequencyBand) {
+        if sampled_frequency_band == self.sampled_frequency_band {
+            return;
+        }
+        self.sampled_frequency_band = sampled_frequency_band;
+        (self.shift, self.lowpass, self.decimation) =
+            mixer(self.frequency_band, sampled_frequency_band);
+        self.next_decimation = 0;
+    }
+
+    #[inline]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        match mode {
+            Mode::Am | Mode::SyncAm => {}
+            Mode::Usb => self.ssb.set_sideband(Sideband::Upper),
+            Mode::Lsb => self.ssb.set_sideband(Sideband::Lower),
         }
     }
 
@@ -68,8 +207,35 @@ impl Demodulator {
         self.audio_source.clone()
     }
 
+    pub fn time_shift(&self) -> TimeShiftHandle {
+        TimeShiftHandle {
+            buffer: self.audio_buffer.clone(),
+        }
+    }
+
+    /// Handle to the scope that captures the demodulated audio.
+    pub fn scope(&self) -> ScopeHandle<f32> {
+        self.scope.handle()
+    }
+
+    /// Handle to the spectrogram of the demodulated audio.
+    pub fn audio_spectrum(&self) -> AudioSpectrum {
+        self.audio_spectrum.spectrum()
+    }
+
+    /// Handle to the level statistics of the demodulated audio.
+    pub fn audio_stats(&self) -> StatsHandle<f32> {
+        self.audio_stats.handle()
+    }
+
+    #[inline]
+    pub fn audio_sample_rate(&self) -> u32 {
+        self.audio_source.sample_rate
+    }
+
     pub fn push(&mut self, input: &[Complex<f32>]) {
         let mut audio_buffer = self.audio_buffer.lock();
+        self.audio_chunk.clear();
 
         for sample in input {
             let sample = *sample * self.shift.sample();
@@ -77,7 +243,13 @@ impl Demodulator {
             //let sample = self.lowpass.run(sample.norm());
 
             if self.next_decimation == 0 {
-                audio_buffer.push(self.lowpass.sample().norm());
+                let audio = match self.mode {
+                    Mode::Am => self.am.scan(self.lowpass.sample()),
+                    Mode::SyncAm => self.sync_am.scan(self.lowpass.sample()),
+                    Mode::Usb | Mode::Lsb => self.ssb.scan(self.lowpass.sample()),
+                };
+                audio_buffer.push(audio);
+                self.audio_chunk.push(audio);
                 //audio_buffer.push(sample);
 
                 self.next_decimation = self.decimation - 1;
@@ -86,9 +258,32 @@ impl Demodulator {
                 self.next_decimation -= 1;
             }
         }
+        drop(audio_buffer);
+
+        self.scope.inspect(&self.audio_chunk);
+        self.audio_spectrum.inspect(&self.audio_chunk);
+        self.audio_stats.inspect(&self.audio_chunk);
     }
 }
 
+/// Shifts `frequency_band` to baseband, and filters and decimates it to its
+/// bandwidth.
+fn mixer(
+    frequency_band: FrequencyBand,
+    sampled_frequency_band: FrequencyBand,
+) -> (ComplexSine, FirFilter, usize) {
+    let shift = frequency_band.center() as f32 - sampled_frequency_band.center() as f32;
+    let shift = ComplexSine::new(-shift, sampled_frequency_band.bandwidth() as f32);
+
+    let decimation = sampled_frequency_band
+        .bandwidth()
+        .div_ceil(frequency_band.bandwidth() / 2) as usize;
+
+    let lowpass = FirFilter::boxcar(decimation + 1);
+
+    (shift, lowpass, decimation)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FirFilter {
     buffer: VecDeque<Complex<f32>>,
@@ -142,36 +337,9 @@ impl ComplexSine {
     }
 }
 
-#[derive(Debug)]
-struct AudioBuffer {
-    buffer: VecDeque<f32>,
-    buffer_size: usize,
-}
-
-impl AudioBuffer {
-    pub fn new(buffer_size: usize) -> Self {
-        Self {
-            buffer: VecDeque::with_capacity(buffer_size),
-            buffer_size,
-        }
-    }
-
-    pub fn push(&mut self, sample: f32) {
-        while self.buffer.len() + 1 > self.buffer_size {
-            self.buffer.pop_front();
-        }
-
-        self.buffer.push_back(sample);
-    }
-
-    pub fn next(&mut self) -> Option<f32> {
-        self.buffer.pop_front()
-    }
-}
-
 #[derive(Clone, Debug)]
 pub struct AudioSource {
-    audio_buffer: Arc<Mutex<AudioBuffer>>,
+    audio_buffer: Arc<Mutex<TimeShiftBuffer>>,
     sample_rate: u32,
 }
 
@@ -197,7 +365,6 @@ impl Iterator for AudioSource {
     type Item = rodio::Sample;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut audio_buffer = self.audio_buffer.lock();
-        Some(audio_buffer.next().unwrap_or_default())
+        Some(self.audio_buffer.lock().next_sample())
     }
 }
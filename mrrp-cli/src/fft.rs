@@ -1,26 +1,42 @@
 use std::{
     f32::consts::PI,
-    fmt::Debug,
     str::FromStr,
-    sync::Arc,
 };
 
 use color_eyre::eyre::eyre;
+use mrrp::fft::{
+    BackendKind,
+    FftBackend,
+    FftPool,
+    PooledFft,
+};
 use num_complex::Complex;
-use rustfft::FftPlanner;
 
 use crate::Error;
 
+/// The FFT for the waterfall and everything else that looks at the spectrum.
+///
+/// The window is scaled, such that noise has the same power in the spectrum
+/// with any window. This way the levels don't change when the window is
+/// changed.
+#[derive(Debug)]
 pub struct Fft {
     buffer: Vec<Complex<f32>>,
-    scratch: Vec<Complex<f32>>,
     window: Vec<f32>,
-    fft: Arc<dyn rustfft::Fft<f32>>,
+    window_kind: Window,
+    transform: Transform,
     size: usize,
 }
 
+#[derive(Debug)]
+enum Transform {
+    /// CPU FFTs go through the pool, which may run them on a worker thread.
+    Pooled(PooledFft),
+    Other(Box<dyn FftBackend>),
+}
+
 impl Fft {
-    pub fn new(size: usize, window: Window) -> Self {
+    pub async fn new(size: usize, window: Window, backend: BackendKind, pool: &FftPool) -> Self {
         assert!(size > 0, "Number of samples must be greater than 0: {size}");
         // todo: should we support this? the bin at the center would contain an
         // amplitude for -samplerate/2 and +samplerate/2 frequencies.
@@ -29,14 +45,17 @@ impl Fft {
             "Number of samples must be divisble by 2: {size}"
         );
 
-        let mut planner = FftPlanner::new();
-        let fft = planner.plan_fft_forward(size);
+        let transform = match backend {
+            BackendKind::Cpu => Transform::Pooled(pool.plan(size)),
+            // `forward` transforms one window at a time
+            BackendKind::Gpu => Transform::Other(mrrp::fft::plan_forward(size, 1, backend).await),
+        };
 
         Self {
             buffer: vec![Default::default(); size],
-            scratch: vec![Default::default(); fft.get_inplace_scratch_len()],
-            window: window.to_vec(size),
-            fft,
+            window: window.to_power_corrected_vec(size),
+            window_kind: window,
+            transform,
             size,
         }
     }
@@ -45,16 +64,34 @@ impl Fft {
         self.size
     }
 
-    pub fn forward(&mut self, samples: &[Complex<f32>]) -> &[Complex<f32>] {
+    pub fn window(&self) -> Window {
+        self.window_kind
+    }
+
+    pub fn set_window(&mut self, window: Window) {
+        self.window = window.to_power_corrected_vec(self.size);
+        self.window_kind = window;
+    }
+
+    pub async fn forward(&mut self, samples: &[Complex<f32>]) -> &[Complex<f32>] {
         assert_eq!(samples.len(), self.size);
 
+        // the buffer is handed to the pool while it's transformed, and would be
+        // missing if that was cancelled
+        self.buffer.resize(self.size, Default::default());
+
         // apply window
         for i in 0..self.size {
             self.buffer[i] = self.window[i] * samples[i];
         }
 
-        self.fft
-            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+        match &mut self.transform {
+            Transform::Pooled(fft) => {
+                let buffer = std::mem::take(&mut self.buffer);
+                self.buffer = fft.process_in_pool(buffer).await;
+            }
+            Transform::Other(fft) => fft.process(&mut self.buffer),
+        }
 
         // we do no normalization here. it will be done later.
 
@@ -70,14 +107,6 @@ impl Fft {
     }
 }
 
-impl Debug for Fft {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Fft")
-            .field("size", &self.size)
-            .finish_non_exhaustive()
-    }
-}
-
 fn hann_window(n: usize) -> impl Iterator<Item = f32> {
     let n_f32 = n as f32;
     (0..=n).map(move |i| (PI * i as f32 / n_f32).sin().powi(2))
@@ -102,10 +131,50 @@ impl FromStr for Window {
 }
 
 impl Window {
-    fn to_vec(&self, size: usize) -> Vec<f32> {
+    pub fn to_vec(&self, size: usize) -> Vec<f32> {
         match self {
             Window::Boxcar => std::iter::repeat_n(1.0, size).collect(),
             Window::Hann => hann_window(size - 1).collect(),
         }
     }
+
+    /// The window scaled such that the sum of its squares is `size`, like the
+    /// boxcar window's.
+    ///
+    /// A window attenuates the signal, and so the power of noise in the
+    /// spectrum, by the mean of its squares. E.g. a Hann window shows noise
+    /// 4.3 dB lower than a boxcar window. Scaling the window corrects this.
+    pub fn to_power_corrected_vec(&self, size: usize) -> Vec<f32> {
+        let mut window = self.to_vec(size);
+        let power = window.iter().map(|w| w * w).sum::<f32>() / size as f32;
+        let correction = power.sqrt().recip();
+        for w in &mut window {
+            *w *= correction;
+        }
+        window
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Window::Boxcar => Window::Hann,
+            Window::Hann => Window::Boxcar,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Window;
+
+    #[test]
+    fn corrected_windows_have_the_same_power() {
+        for window in [Window::Boxcar, Window::Hann] {
+            let power = window
+                .to_power_corrected_vec(1024)
+                .iter()
+                .map(|w| w * w)
+                .sum::<f32>();
+            assert!((power - 1024.0).abs() < 1e-2, "{window:?}: {power}");
+        }
+    }
 }
@@ -1,8 +1,18 @@
 use std::{
-    fs::File,
+    ffi::OsString,
+    fs::{
+        File,
+        OpenOptions,
+    },
     io::{
         BufReader,
         BufWriter,
+        Read,
+        Write,
+    },
+    ops::{
+        Bound,
+        RangeBounds,
     },
     path::{
         Path,
@@ -12,6 +22,7 @@ use std::{
 
 use color_eyre::eyre::eyre;
 use directories::ProjectDirs;
+use serde::Deserialize;
 
 use crate::{
     Error,
@@ -19,6 +30,7 @@ use crate::{
         AppSnapshot,
         AppState,
     },
+    state,
     ui::{
         bandplan::{
             BANDPLAN_INTERNATIONAL_BYTES,
@@ -26,10 +38,16 @@ use crate::{
         },
         bookmarks::Bookmarks,
         keybinds::Keybinds,
+        markers::MarkerMeasurement,
+        theme::Themes,
         waterfall::ColorMap,
     },
 };
 
+/// Example station database, which is written to the config directory if
+/// there is none. This also documents the format.
+pub(crate) const STATIONS_EXAMPLE_BYTES: &[u8] = include_bytes!("stations.csv");
+
 #[derive(Debug)]
 pub struct AppFiles {
     project_dirs: ProjectDirs,
@@ -73,6 +91,23 @@ impl AppFiles {
         }
     }
 
+    /// Loads the station database, or writes the example database if there
+    /// is none.
+    pub fn stations(&self) -> Result<StationDatabase, Error> {
+        let path = self.config_dir().join("stations.csv");
+
+        if path.exists() {
+            StationDatabase::from_path(path)
+        }
+        else {
+            tracing::debug!(path = %path.display(), "Writing example station database to file");
+            let data = STATIONS_EXAMPLE_BYTES;
+            let stations = StationDatabase::from_reader(data)?;
+            std::fs::write(&path, data)?;
+            Ok(stations)
+        }
+    }
+
     pub fn keybinds(&self) -> Result<Keybinds, Error> {
         let path = self.config_dir().join("keybinds.toml");
 
@@ -99,26 +134,60 @@ impl AppFiles {
         }
     }
 
+    /// Loads the themes, or writes the built-in ones as an example if there
+    /// is no themes file.
+    pub fn themes(&self) -> Result<Themes, Error> {
+        let path = self.config_dir().join("themes.json");
+
+        if path.exists() {
+            Themes::from_path(path)
+        }
+        else {
+            let themes = Themes::default();
+            themes.to_path(path)?;
+            Ok(themes)
+        }
+    }
+
     pub fn bookmarks(&self) -> Result<Bookmarks, Error> {
         let path = self.config_dir().join("bookmarks");
         std::fs::create_dir_all(&path)?;
         Bookmarks::open(path)
     }
 
-    fn app_state_path(&self) -> PathBuf {
+    pub fn app_state_path(&self) -> PathBuf {
         self.state_dir().join("app_state.cbor")
     }
 
-    pub fn load_app_state(&self) -> Result<AppSnapshot<AppState>, Error> {
+    /// Loads the app state snapshot and migrates it to the current version.
+    pub fn load_app_state(&self) -> Result<AppSnapshot<ciborium::Value>, Error> {
         let path = self.app_state_path();
         tracing::debug!(path = %path.display(), "Loading app state");
-        Ok(ciborium::from_reader(BufReader::new(File::open(path)?))?)
+        Ok(state::read_snapshot(BufReader::new(File::open(path)?))?.snapshot)
     }
 
     pub fn save_app_state(&self, snapshot: AppSnapshot<&AppState>) -> Result<(), Error> {
         let path = self.app_state_path();
         tracing::debug!(path = %path.display(), "Saving app state");
-        ciborium::into_writer(&snapshot, BufWriter::new(File::create(path)?))?;
+        state::write_snapshot(BufWriter::new(File::create(path)?), &snapshot)
+    }
+
+    pub fn marker_measurements_file(&self) -> PathBuf {
+        self.project_dirs.data_local_dir().join("markers.csv")
+    }
+
+    pub fn append_marker_measurement(&self, measurement: &MarkerMeasurement) -> Result<(), Error> {
+        let path = self.marker_measurements_file();
+        tracing::debug!(path = %path.display(), "Exporting marker measurement");
+
+        // only write the header if we're creating the file
+        let has_headers = !path.exists();
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(has_headers)
+            .from_writer(OpenOptions::new().append(true).create(true).open(path)?);
+        writer.serialize(measurement)?;
+        writer.flush()?;
+
         Ok(())
     }
 
@@ -126,3 +195,226 @@ impl AppFiles {
         self.project_dirs.data_local_dir().join("mrrp-cli.log")
     }
 }
+
+/// Writes a file atomically.
+///
+/// The contents are written to a temporary file next to `path`, which is then
+/// renamed over it. Readers, e.g. other instances, see either the old or the
+/// new file, and a crash while writing leaves the old one intact.
+pub fn write_atomically(
+    path: impl AsRef<Path>,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| eyre!("Not a file path: {}", path.display()))?;
+
+    // hidden, and with the process id so that instances don't write to the same
+    // temporary file
+    let mut temp_file_name = OsString::from(".");
+    temp_file_name.push(file_name);
+    temp_file_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_file_name);
+
+    let result = (|| -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        write(&mut writer)?;
+        writer.flush()?;
+        let file = writer.into_inner().map_err(|error| error.into_error())?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// A known station, from the [`StationDatabase`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Station {
+    /// Frequency in Hz
+    pub frequency: u32,
+    pub name: String,
+    #[serde(default)]
+    pub mode: String,
+    #[serde(default)]
+    pub location: String,
+}
+
+/// Offline database of known stations.
+///
+/// This is loaded from a CSV file with the columns frequency (in Hz), name,
+/// mode and location. The last two can be left out. The example database in
+/// `stations.csv` documents the format.
+#[derive(Clone, Debug, Default)]
+pub struct StationDatabase {
+    /// Sorted by frequency
+    stations: Vec<Station>,
+}
+
+impl StationDatabase {
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .comment(Some(b'#'))
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(reader);
+
+        let mut stations = vec![];
+        for result in reader.deserialize::<Station>() {
+            match result {
+                Ok(station) if !station.name.is_empty() => stations.push(station),
+                Ok(_) => {}
+                Err(error) => {
+                    // one bad line shouldn't make the whole database unusable
+                    tracing::warn!(%error, "Skipping invalid station");
+                }
+            }
+        }
+        stations.sort_by_key(|station| station.frequency);
+
+        Ok(Self { stations })
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        tracing::debug!(path = %path.as_ref().display(), "Loading station database from file");
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.stations.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty()
+    }
+
+    /// Stations with frequencies in `range`, sorted by frequency.
+    pub fn range(&self, range: impl RangeBounds<u32>) -> &[Station] {
+        let start = match range.start_bound() {
+            Bound::Included(frequency) => {
+                self.stations
+                    .partition_point(|station| station.frequency < *frequency)
+            }
+            Bound::Excluded(frequency) => {
+                self.stations
+                    .partition_point(|station| station.frequency <= *frequency)
+            }
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(frequency) => {
+                self.stations
+                    .partition_point(|station| station.frequency <= *frequency)
+            }
+            Bound::Excluded(frequency) => {
+                self.stations
+                    .partition_point(|station| station.frequency < *frequency)
+            }
+            Bound::Unbounded => self.stations.len(),
+        };
+
+        self.stations.get(start..end).unwrap_or_default()
+    }
+
+    /// Stations at most `tolerance` Hz away from `frequency`, sorted by
+    /// frequency.
+    #[inline]
+    pub fn lookup(&self, frequency: u32, tolerance: u32) -> &[Station] {
+        self.range(frequency.saturating_sub(tolerance)..=frequency.saturating_add(tolerance))
+    }
+
+    /// The station closest to `frequency`, if it's at most `tolerance` Hz
+    /// away.
+    ///
+    /// If several stations share a frequency, the first one in the database
+    /// is returned.
+    pub fn nearest(&self, frequency: u32, tolerance: u32) -> Option<&Station> {
+        self.lookup(frequency, tolerance)
+            .iter()
+            .min_by_key(|station| station.frequency.abs_diff(frequency))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        STATIONS_EXAMPLE_BYTES,
+        Station,
+        StationDatabase,
+    };
+
+    #[test]
+    fn it_parses_the_example_database() {
+        let stations = StationDatabase::from_reader(STATIONS_EXAMPLE_BYTES).unwrap();
+        assert!(!stations.is_empty());
+
+        let wwv = stations.nearest(10_000_000, 0).unwrap();
+        assert_eq!(wwv.name, "WWV");
+        assert_eq!(wwv.mode, "AM");
+        assert_eq!(wwv.location, "Fort Collins, US");
+    }
+
+    #[test]
+    fn it_allows_optional_fields_and_skips_invalid_lines() {
+        let data = b"\
+# frequency, name, mode, location
+7850000, CHU
+not a frequency, Broken
+3330000, CHU, USB
+14670000, , USB
+";
+        let stations = StationDatabase::from_reader(&data[..]).unwrap();
+        assert_eq!(
+            stations.range(..),
+            [
+                Station {
+                    frequency: 3_330_000,
+                    name: "CHU".to_owned(),
+                    mode: "USB".to_owned(),
+                    location: "".to_owned(),
+                },
+                Station {
+                    frequency: 7_850_000,
+                    name: "CHU".to_owned(),
+                    mode: "".to_owned(),
+                    location: "".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_looks_up_stations_with_tolerance() {
+        let data = b"\
+1000, A
+2000, B
+2500, C
+4000, D
+";
+        let stations = StationDatabase::from_reader(&data[..]).unwrap();
+        fn names(stations: &[Station]) -> Vec<&str> {
+            stations
+                .iter()
+                .map(|station| station.name.as_str())
+                .collect()
+        }
+
+        assert_eq!(names(stations.lookup(2200, 300)), ["B", "C"]);
+        assert_eq!(names(stations.lookup(3000, 499)), [] as [&str; 0]);
+        assert_eq!(names(stations.lookup(0, 1000)), ["A"]);
+        assert_eq!(stations.nearest(2300, 500).unwrap().name, "C");
+        assert_eq!(
+            stations.nearest(3000, 499).map(|station| &*station.name),
+            None
+        );
+        assert_eq!(names(stations.range(2000..4000)), ["B", "C"]);
+    }
+}
@@ -0,0 +1,241 @@
+//! Software gain control for the tuner.
+//!
+//! The tuner's own AGC only looks at the RF level in the tuner, not at how
+//! the ADC is loaded. This loop measures the ADC loading from the samples and
+//! steps the tuner gain so that the peak level sits near a target. When a lot
+//! of the spectrum is occupied, the target is lowered, because many strong
+//! signals together are more likely to overload the ADC and produce
+//! intermodulation.
+
+use num_complex::Complex;
+
+/// Gains supported by the R820T/R828D tuners in dB. The driver rounds to the
+/// nearest supported value anyway, but stepping through the real values
+/// avoids steps that don't change anything.
+const TUNER_GAINS: &[f32] = &[
+    0.0, 0.9, 1.4, 2.7, 3.7, 7.7, 8.7, 12.5, 14.4, 15.7, 16.6, 19.7, 20.7, 22.9, 25.4, 28.0, 29.7,
+    32.8, 33.8, 36.4, 37.2, 38.6, 40.2, 42.1, 43.4, 43.9, 44.5, 48.0, 49.6,
+];
+
+/// Gain the loop starts with, in dB.
+pub const INITIAL_GAIN: f32 = 28.0;
+
+/// A sample component at or above this level counts as clipped.
+const CLIP_LEVEL: f32 = 0.99;
+
+/// Fraction of clipped samples above which the gain is reduced immediately.
+const MAX_CLIP_RATIO: f32 = 1e-4;
+
+/// How much the target is lowered when the whole spectrum is occupied.
+const OCCUPANCY_BACKOFF: f32 = 6.0;
+
+/// A bin counts as occupied if it's this many dB above the median bin.
+const OCCUPANCY_THRESHOLD: f32 = 10.0;
+
+/// Number of updates after a gain reduction in which the gain isn't raised.
+const HOLD_UPDATES: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+pub struct GainControlSettings {
+    /// Target peak level in dBFS.
+    pub target: f32,
+
+    /// The gain is only changed if the peak level is further than this from
+    /// the target, in dB.
+    pub hysteresis: f32,
+
+    /// Number of samples over which the peak level is measured.
+    pub window: usize,
+}
+
+#[derive(Debug)]
+pub struct GainControl {
+    settings: GainControlSettings,
+    gain_index: usize,
+    peak: f32,
+    num_clipped: usize,
+    num_samples: usize,
+    occupancy: f32,
+    hold: usize,
+    power: Vec<f32>,
+}
+
+impl GainControl {
+    pub fn new(settings: GainControlSettings) -> Self {
+        Self {
+            settings,
+            gain_index: nearest_gain_index(INITIAL_GAIN),
+            peak: 0.0,
+            num_clipped: 0,
+            num_samples: 0,
+            occupancy: 0.0,
+            hold: 0,
+            power: vec![],
+        }
+    }
+
+    /// Current tuner gain in dB.
+    pub fn gain(&self) -> f32 {
+        TUNER_GAINS[self.gain_index]
+    }
+
+    /// Measures the ADC loading from raw samples.
+    pub fn push_samples(&mut self, samples: &[Complex<f32>]) {
+        for sample in samples {
+            let level = sample.re.abs().max(sample.im.abs());
+            self.peak = self.peak.max(level);
+            if level >= CLIP_LEVEL {
+                self.num_clipped += 1;
+            }
+        }
+        self.num_samples += samples.len();
+    }
+
+    /// Measures how much of the spectrum is occupied.
+    ///
+    /// The spectrum doesn't need to be normalized, since occupancy is
+    /// measured relative to the median bin.
+    pub fn push_spectrum(&mut self, spectrum: &[Complex<f32>]) {
+        if spectrum.is_empty() {
+            return;
+        }
+
+        self.power.clear();
+        self.power.extend(spectrum.iter().map(|bin| bin.norm_sqr()));
+        let median_index = self.power.len() / 2;
+        let (_, median, _) = self
+            .power
+            .select_nth_unstable_by(median_index, f32::total_cmp);
+        let threshold = *median * 10.0f32.powf(OCCUPANCY_THRESHOLD / 10.0);
+
+        let num_occupied = self
+            .power
+            .iter()
+            .filter(|power| **power > threshold)
+            .count();
+        let occupancy = num_occupied as f32 / spectrum.len() as f32;
+
+        // smooth, since single spectra are noisy
+        self.occupancy += 0.1 * (occupancy - self.occupancy);
+    }
+
+    /// Adjusts the gain once a full window was measured.
+    ///
+    /// Returns the new gain in dB, if it changed.
+    pub fn update(&mut self) -> Option<f32> {
+        if self.num_samples < self.settings.window {
+            return None;
+        }
+
+        let clip_ratio = self.num_clipped as f32 / self.num_samples as f32;
+        let peak = 20.0 * self.peak.max(f32::MIN_POSITIVE).log10();
+        self.peak = 0.0;
+        self.num_clipped = 0;
+        self.num_samples = 0;
+
+        let target = self.settings.target - self.occupancy * OCCUPANCY_BACKOFF;
+        let current_gain = self.gain();
+
+        let gain_index = if clip_ratio > MAX_CLIP_RATIO {
+            // we can't tell how far above full scale the signal is, so step down by the
+            // hysteresis and a bit, and measure again
+            tracing::debug!(clip_ratio, "ADC clipping");
+            nearest_gain_index(current_gain - 2.0 * self.settings.hysteresis)
+                .min(self.gain_index.saturating_sub(1))
+        }
+        else if peak > target + self.settings.hysteresis {
+            // reduce gain right away
+            nearest_gain_index(current_gain - (peak - target))
+                .min(self.gain_index.saturating_sub(1))
+        }
+        else if peak < target - self.settings.hysteresis && self.hold == 0 {
+            // raise gain slowly, one step at a time
+            (self.gain_index + 1).min(TUNER_GAINS.len() - 1)
+        }
+        else {
+            self.gain_index
+        };
+
+        self.hold = if gain_index < self.gain_index {
+            HOLD_UPDATES
+        }
+        else {
+            self.hold.saturating_sub(1)
+        };
+
+        (gain_index != self.gain_index).then(|| {
+            self.gain_index = gain_index;
+            tracing::debug!(
+                peak,
+                target,
+                occupancy = self.occupancy,
+                gain = self.gain(),
+                "Changing tuner gain"
+            );
+            self.gain()
+        })
+    }
+}
+
+fn nearest_gain_index(gain: f32) -> usize {
+    TUNER_GAINS
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| (*a - gain).abs().total_cmp(&(*b - gain).abs()))
+        .map(|(index, _)| index)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex;
+
+    use super::{
+        GainControl,
+        GainControlSettings,
+        INITIAL_GAIN,
+    };
+
+    fn gain_control() -> GainControl {
+        GainControl::new(GainControlSettings {
+            target: -20.0,
+            hysteresis: 3.0,
+            window: 100,
+        })
+    }
+
+    fn tone(amplitude: f32) -> Vec<Complex<f32>> {
+        (0..100)
+            .map(|i| Complex::from_polar(amplitude, i as f32 * 0.1))
+            .collect()
+    }
+
+    #[test]
+    fn it_reduces_gain_when_clipping() {
+        let mut gain_control = gain_control();
+        gain_control.push_samples(&tone(1.0));
+        let gain = gain_control.update().unwrap();
+        assert!(gain < INITIAL_GAIN - 3.0);
+    }
+
+    #[test]
+    fn it_keeps_gain_within_hysteresis() {
+        let mut gain_control = gain_control();
+        // -21 dBFS
+        gain_control.push_samples(&tone(0.089));
+        assert_eq!(gain_control.update(), None);
+    }
+
+    #[test]
+    fn it_raises_gain_slowly_and_not_after_reducing() {
+        let mut gain_control = gain_control();
+        gain_control.push_samples(&tone(0.001));
+        assert_eq!(gain_control.update(), Some(29.7));
+
+        gain_control.push_samples(&tone(0.5));
+        assert!(gain_control.update().unwrap() < 29.7);
+
+        gain_control.push_samples(&tone(0.001));
+        assert_eq!(gain_control.update(), None);
+    }
+}
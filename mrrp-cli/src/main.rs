@@ -1,10 +1,23 @@
 pub mod app;
 pub mod args;
+pub mod audio_spectrum;
+pub mod bench;
+pub mod control;
+pub mod cw;
 pub mod demodulator;
 pub mod fft;
 pub mod files;
+pub mod gain_control;
 pub mod proxy;
 pub mod reader;
+pub mod recording;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod selftest;
+pub mod source;
+pub mod state;
+pub mod sweep;
+pub mod time_shift;
 pub mod ui;
 pub mod util;
 
@@ -13,7 +26,11 @@ use std::{
         File,
         OpenOptions,
     },
-    io::BufReader,
+    io::{
+        BufReader,
+        BufWriter,
+    },
+    time::Duration,
 };
 
 use clap::Parser;
@@ -21,11 +38,7 @@ use color_eyre::eyre::{
     Error,
     bail,
 };
-use rtlsdr_async::{
-    Backend,
-    RtlSdr,
-    rtl_tcp::client::RtlTcpClient,
-};
+use mrrp::io::Shutdown;
 use tracing_subscriber::EnvFilter;
 
 use crate::{
@@ -33,9 +46,9 @@ use crate::{
     args::{
         Args,
         Command,
-        MainArgs,
     },
     files::AppFiles,
+    source::SourceSpec,
     ui::bookmarks::import_sdrpp_bookmarks,
 };
 
@@ -60,40 +73,35 @@ async fn main() -> Result<(), Error> {
     let args = Args::parse();
     tracing::debug!(?args);
 
+    let shutdown = Shutdown::new();
+    spawn_signal_handler(&shutdown);
+
     let result = match args.command.unwrap_or_default() {
         Command::Main(args) => {
-            async fn run_app<B>(
-                args: MainArgs,
-                app_files: AppFiles,
-                rtl_sdr: B,
-            ) -> Result<(), Error>
-            where
-                B: Backend + Send + Clone + 'static,
-                <B as Backend>::Error: std::error::Error + Send + Sync + 'static,
-            {
-                let mut app = App::new(args, app_files, rtl_sdr).await?;
-                app.run().await?;
-                app.persist()?;
-                Ok(())
-            }
-
-            match (&args.device, &args.address) {
-                (device_opt, None) => {
-                    let rtl_sdr = RtlSdr::open(device_opt.unwrap_or_default())?;
-                    run_app(args, app_files, rtl_sdr).await
-                }
-                (None, Some(address)) => {
-                    let rtl_tcp = RtlTcpClient::connect(address).await?;
-                    run_app(args, app_files, rtl_tcp).await
+            let source = match (args.device, &args.address, &args.file) {
+                (device, None, None) => {
+                    SourceSpec::RtlSdr {
+                        device: device.unwrap_or_default(),
+                    }
                 }
-                (Some(_), Some(_)) => {
-                    bail!("Only either --device or --address can be used at once")
+                (None, Some(address), None) => {
+                    SourceSpec::RtlTcp {
+                        address: address.clone(),
+                    }
                 }
-            }
+                (None, None, Some(path)) => SourceSpec::File { path: path.clone() },
+                _ => bail!("Only one of --device, --address or --file can be used at once"),
+            };
+
+            let mut app = App::new(args, app_files, source, shutdown.guard()).await?;
+            let result = app.run().await;
+            // finish the recording and save the state, even if the app failed
+            app.persist()?;
+            result
         }
         Command::DumpState { path } => {
             let app_state = if let Some(path) = path {
-                ciborium::from_reader(BufReader::new(File::open(path)?))?
+                state::read_snapshot(BufReader::new(File::open(path)?))?.snapshot
             }
             else {
                 app_files.load_app_state()?
@@ -102,6 +110,26 @@ async fn main() -> Result<(), Error> {
             println!("{app_state:#?}");
             Ok(())
         }
+        Command::MigrateState { path } => {
+            let path = path.unwrap_or_else(|| app_files.app_state_path());
+            let migrated = state::read_snapshot(BufReader::new(File::open(&path)?))?;
+
+            if migrated.from_version == state::VERSION {
+                println!("App state is already at version {}", state::VERSION);
+            }
+            else {
+                let backup_path = path.with_extension(format!("v{}.bak", migrated.from_version));
+                std::fs::copy(&path, &backup_path)?;
+                state::write_snapshot(BufWriter::new(File::create(&path)?), &migrated.snapshot)?;
+                println!(
+                    "Migrated app state from version {} to {}. Backup: {}",
+                    migrated.from_version,
+                    state::VERSION,
+                    backup_path.display()
+                );
+            }
+            Ok(())
+        }
         Command::ImportSdrppBookmarks(args) => {
             let mut bookmarks = app_files.bookmarks()?;
             for bookmark in import_sdrpp_bookmarks(&args.path)? {
@@ -109,12 +137,18 @@ async fn main() -> Result<(), Error> {
             }
             Ok(())
         }
+        Command::Selftest(args) => selftest::run(args).await,
+        Command::BenchPipeline(args) => bench::run(args, shutdown.guard()).await,
+        Command::Cw(args) => cw::run(args, shutdown.guard()).await,
         Command::Proxy(args) => {
             proxy::serve(&args.input, &args.output).await?;
             Ok(())
         }
     };
 
+    let summary = shutdown.shutdown(SHUTDOWN_TIMEOUT).await;
+    tracing::debug!(?summary, "Pipelines stopped");
+
     if let Err(error) = &result {
         tracing::error!(?error);
     }
@@ -124,3 +158,52 @@ async fn main() -> Result<(), Error> {
 
     result
 }
+
+/// How long to wait for pipelines to flush their sinks on exit.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Triggers the shutdown on Ctrl-C, or when the process is asked to
+/// terminate.
+///
+/// While the UI is running the terminal is in raw mode, so Ctrl-C arrives as a
+/// key press instead. This catches signals from elsewhere, which would
+/// otherwise leave the terminal in raw mode and recordings unfinished.
+fn spawn_signal_handler(shutdown: &Shutdown) {
+    let token = shutdown.token();
+
+    tokio::spawn(async move {
+        let ctrl_c = async {
+            if let Err(error) = tokio::signal::ctrl_c().await {
+                tracing::warn!(?error, "Can't listen for Ctrl-C");
+                std::future::pending::<()>().await;
+            }
+        };
+
+        #[cfg(unix)]
+        let terminate = async {
+            use tokio::signal::unix::{
+                SignalKind,
+                signal,
+            };
+            match signal(SignalKind::terminate()) {
+                Ok(mut signal) => {
+                    signal.recv().await;
+                }
+                Err(error) => {
+                    tracing::warn!(?error, "Can't listen for SIGTERM");
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = ctrl_c => {}
+            _ = terminate => {}
+        }
+
+        tracing::info!("Shutting down");
+        token.cancel();
+    });
+}
@@ -1,71 +1,157 @@
-use futures_util::TryStreamExt;
-use num_complex::Complex;
-use rtlsdr_async::{
-    Chunk,
-    Iq,
-    Samples,
+use mrrp::io::{
+    AsyncReadSamplesExt,
+    combinators::{
+        Conjugate,
+        Scanner,
+    },
 };
+use num_complex::Complex;
 
-use crate::Error;
+use crate::{
+    Error,
+    source::SourceSamples,
+};
 
-#[derive(Debug)]
+/// Reads segments for the FFT from the sample source.
+///
+/// Consecutive segments can overlap, i.e. a segment starts with the last
+/// `overlap` samples of the previous one. The overlap can be changed at any
+/// time, and takes effect with the next segment.
 pub struct SampleReader {
-    samples: Samples<Iq>,
+    samples: Option<SourceSamples>,
     segment_size: usize,
     overlap: usize,
-    chunk: Option<Chunk<Iq>>,
-    read_pos: usize,
     buffer: Vec<Complex<f32>>,
     write_pos: usize,
     first_segment: bool,
+    num_reused: usize,
+    conjugate: bool,
+}
+
+impl std::fmt::Debug for SampleReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SampleReader")
+            .field("open", &self.samples.is_some())
+            .field("segment_size", &self.segment_size)
+            .field("overlap", &self.overlap)
+            .field("write_pos", &self.write_pos)
+            .field("first_segment", &self.first_segment)
+            .field("num_reused", &self.num_reused)
+            .field("conjugate", &self.conjugate)
+            .finish_non_exhaustive()
+    }
 }
 
 impl SampleReader {
-    pub fn new(samples: Samples<Iq>, segment_size: usize, overlap: usize) -> Self {
+    pub fn new(samples: SourceSamples, segment_size: usize, overlap: usize) -> Self {
         assert!(overlap < segment_size);
         Self {
-            samples,
+            samples: Some(samples),
             segment_size,
             overlap,
-            chunk: None,
-            read_pos: 0,
             buffer: vec![Default::default(); segment_size],
             write_pos: 0,
             first_segment: true,
+            num_reused: 0,
+            conjugate: false,
         }
     }
 
-    pub async fn read(&mut self) -> Result<Option<&'_ [Complex<f32>]>, Error> {
-        if !self.first_segment && self.write_pos == 0 && self.overlap != 0 {
-            self.buffer
-                .copy_within(self.segment_size - self.overlap.., 0);
-            self.write_pos = self.overlap
+    /// Conjugate the samples, which mirrors the spectrum. Some sources have I
+    /// and Q swapped, or use high-side mixing, and need this.
+    pub fn set_conjugate(&mut self, conjugate: bool) {
+        self.conjugate = conjugate;
+    }
+
+    pub fn segment_size(&self) -> usize {
+        self.segment_size
+    }
+
+    pub fn overlap(&self) -> usize {
+        self.overlap
+    }
+
+    pub fn set_overlap(&mut self, overlap: usize) {
+        assert!(overlap < self.segment_size);
+        self.overlap = overlap;
+    }
+
+    /// Switches to reading from another source. The partially read segment is
+    /// discarded, and the next segment doesn't overlap with the previous one,
+    /// since the signal isn't continuous.
+    pub fn set_samples(&mut self, samples: SourceSamples) {
+        self.samples = Some(samples);
+        self.write_pos = 0;
+        self.first_segment = true;
+    }
+
+    /// Drops the source, e.g. so that the device can be opened again. Until
+    /// [`set_samples`][Self::set_samples] is called, [`read`][Self::read]
+    /// never returns.
+    pub fn close(&mut self) {
+        self.samples = None;
+        self.write_pos = 0;
+        self.first_segment = true;
+    }
+
+    pub async fn read(&mut self) -> Result<Option<Segment<'_>>, Error> {
+        // a segment that was cancelled half-way keeps what it has read so far
+        if self.write_pos == 0 {
+            if !self.first_segment && self.overlap != 0 {
+                self.buffer
+                    .copy_within(self.segment_size - self.overlap.., 0);
+                self.write_pos = self.overlap;
+            }
+            self.num_reused = self.write_pos;
         }
 
         while self.write_pos < self.segment_size {
-            if let Some(chunk) = &self.chunk {
-                let samples = chunk.samples();
-                while self.write_pos < self.segment_size && self.read_pos < samples.len() {
-                    self.buffer[self.write_pos] = samples[self.read_pos].into();
-                    self.write_pos += 1;
-                    self.read_pos += 1;
-                }
+            let Some(samples) = &mut self.samples
+            else {
+                // closed until a new source is set
+                return std::future::pending().await;
+            };
 
-                if self.read_pos >= samples.len() {
-                    self.chunk = None;
-                    self.read_pos = 0;
-                }
+            let output = &mut self.buffer[self.write_pos..];
+            let num_read = samples.read_samples(output).await?;
+            if num_read == 0 {
+                return Ok(None);
             }
-            else {
-                self.chunk = self.samples.try_next().await?;
-                if self.chunk.is_none() {
-                    return Ok(None);
+
+            if self.conjugate {
+                for sample in &mut output[..num_read] {
+                    *sample = Conjugate.scan(*sample);
                 }
             }
+            self.write_pos += num_read;
         }
 
         self.write_pos = 0;
+        self.first_segment = false;
+
+        Ok(Some(Segment {
+            samples: &self.buffer,
+            num_reused: self.num_reused,
+        }))
+    }
+}
+
+/// A segment read by [`SampleReader`].
+#[derive(Clone, Copy, Debug)]
+pub struct Segment<'a> {
+    /// All samples of the segment, which is what's transformed.
+    pub samples: &'a [Complex<f32>],
+
+    /// Number of samples at the start that were already part of the previous
+    /// segment.
+    pub num_reused: usize,
+}
 
-        Ok(Some(&self.buffer))
+impl<'a> Segment<'a> {
+    /// The samples that weren't part of the previous segment. Everything that
+    /// processes the signal as a stream, e.g. the demodulator, needs only
+    /// these.
+    pub fn new_samples(&self) -> &'a [Complex<f32>] {
+        &self.samples[self.num_reused..]
     }
 }
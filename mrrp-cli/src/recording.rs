@@ -0,0 +1,101 @@
+use std::{
+    fs::File,
+    io::{
+        BufWriter,
+        Write,
+    },
+    path::PathBuf,
+};
+
+use mrrp::sample::bfp::{
+    BfpEncoder,
+    MantissaBits,
+};
+
+use crate::{
+    Error,
+    control::RecordingStatus,
+    reader::Segment,
+};
+
+/// Writes the sampled IQ signal to a file.
+///
+/// By default the samples are written as interleaved little-endian `f32`. If
+/// the file name ends in `.bfp` or `.bfp8`, they're compressed with the
+/// [block floating point codec][mrrp::sample::bfp] with 12 or 8-bit
+/// mantissas.
+#[derive(Debug)]
+pub struct Recording {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    encoder: Option<BfpEncoder>,
+    encoded: Vec<u8>,
+    num_samples: usize,
+
+    /// The segments we get from the sample reader can overlap. We skip the
+    /// overlap, except for the first segment.
+    first_segment: bool,
+}
+
+impl Recording {
+    pub fn create(path: PathBuf) -> Result<Self, Error> {
+        tracing::info!(path = %path.display(), "Starting recording");
+        let encoder = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("bfp") => Some(BfpEncoder::new(MantissaBits::Twelve)),
+            Some("bfp8") => Some(BfpEncoder::new(MantissaBits::Eight)),
+            _ => None,
+        };
+        let writer = BufWriter::new(File::create(&path)?);
+        Ok(Self {
+            path,
+            writer,
+            encoder,
+            encoded: vec![],
+            num_samples: 0,
+            first_segment: true,
+        })
+    }
+
+    pub fn write_segment(&mut self, segment: Segment) -> Result<(), Error> {
+        let samples = segment.samples;
+        let skip = if self.first_segment {
+            0
+        }
+        else {
+            segment.num_reused
+        };
+        self.first_segment = false;
+
+        if let Some(encoder) = &mut self.encoder {
+            encoder.encode(&samples[skip..], &mut self.encoded);
+            self.writer.write_all(&self.encoded)?;
+            self.encoded.clear();
+        }
+        else {
+            for sample in &samples[skip..] {
+                self.writer.write_all(&sample.re.to_le_bytes())?;
+                self.writer.write_all(&sample.im.to_le_bytes())?;
+            }
+        }
+        self.num_samples += samples.len() - skip;
+
+        Ok(())
+    }
+
+    pub fn status(&self) -> RecordingStatus {
+        RecordingStatus {
+            path: self.path.clone(),
+            num_samples: self.num_samples,
+        }
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        tracing::info!(path = %self.path.display(), num_samples = self.num_samples, "Stopping recording");
+        if let Some(encoder) = &mut self.encoder {
+            encoder.flush(&mut self.encoded);
+            self.writer.write_all(&self.encoded)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Scripting
+//!
+//! Runs a [Rhai](https://rhai.rs) script next to the TUI, to automate what
+//! would otherwise be done with keybinds or the control socket. The script is
+//! passed with `--script <path>` and needs the `scripting` feature.
+//!
+//! For example, this records 30 s of FT8 every 10 minutes:
+//!
+//! ```rhai
+//! loop {
+//!     tune(7_074_000);
+//!     start_recording(`ft8-${now()}.cf32`);
+//!     wait(30);
+//!     stop_recording();
+//!     wait(570);
+//! }
+//! ```
+//!
+//! # Functions
+//!
+//! | function                | description                                          |
+//! |-------------------------|------------------------------------------------------|
+//! | `tune(hz)`              | Sets the center frequency.                           |
+//! | `step_frequency(hz)`    | Moves the center frequency by `hz`.                  |
+//! | `frequency()`           | Returns the center frequency.                        |
+//! | `set_gain(db)`          | Sets the tuner gain.                                 |
+//! | `auto_gain()`           | Selects the tuner's automatic gain control.          |
+//! | `software_gain()`       | Selects the software gain control.                   |
+//! | `set_mode(name)`        | Sets the demodulation mode, like `--mode`.           |
+//! | `start_recording(path)` | Starts recording IQ samples, see [`control`].        |
+//! | `stop_recording()`      | Stops recording.                                     |
+//! | `set_source(source)`    | Switches the sample source, see [`control`].         |
+//! | `action(name)`          | Performs a keybind action, e.g. `"toggle-scope"`.    |
+//! | `wait(seconds)`         | Waits.                                               |
+//! | `now()`                 | Local time as `YYYYmmdd-HHMMSS`, e.g. for file names.|
+//! | `quit()`                | Exits the TUI.                                       |
+//!
+//! `print` and `debug` go to the log file, since the terminal belongs to the
+//! TUI. The script stops when the TUI exits.
+//!
+//! [`control`]: crate::control
+
+use std::{
+    path::PathBuf,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use chrono::Local;
+use rhai::{
+    Dynamic,
+    Engine,
+    EvalAltResult,
+    FLOAT,
+    INT,
+};
+use tokio::runtime::Handle;
+
+use crate::{
+    app::AppProxy,
+    args::Gain,
+    demodulator::Mode,
+    source::SourceSpec,
+    ui::keybinds::Action,
+};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// How often `wait` checks whether the TUI exited.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs the script at `path` on its own thread.
+///
+/// Rhai is synchronous, so the script blocks its thread, e.g. in `wait`. The
+/// thread isn't joined, so it can't keep the program from exiting.
+pub fn spawn(path: PathBuf, app: AppProxy) {
+    let runtime = Handle::current();
+
+    std::thread::spawn(move || {
+        tracing::info!(path = %path.display(), "Running script");
+        let engine = engine(app, runtime);
+        match engine.run_file(path.clone()) {
+            Ok(()) => tracing::info!(path = %path.display(), "Script finished"),
+            Err(error) => tracing::error!(path = %path.display(), %error, "Script failed"),
+        }
+    });
+}
+
+fn engine(app: AppProxy, runtime: Handle) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.on_print(|text| tracing::info!(target: "script", "{text}"));
+    engine.on_debug(|text, source, position| {
+        tracing::debug!(target: "script", ?source, %position, "{text}");
+    });
+
+    // stop the script when the TUI exits
+    let proxy = app.clone();
+    engine.on_progress(move |_| proxy.is_closed().then(|| Dynamic::from("TUI exited")));
+
+    let proxy = app.clone();
+    engine.register_fn("tune", move |frequency: INT| -> ScriptResult<()> {
+        proxy.set_center_frequency(to_frequency(frequency)?);
+        Ok(())
+    });
+
+    let proxy = app.clone();
+    let handle = runtime.clone();
+    engine.register_fn("step_frequency", move |step: INT| -> ScriptResult<()> {
+        let status = handle
+            .block_on(proxy.query_status())
+            .map_err(|error| error.to_string())?;
+        proxy.set_center_frequency(to_frequency(INT::from(status.center_frequency) + step)?);
+        Ok(())
+    });
+
+    let proxy = app.clone();
+    let handle = runtime.clone();
+    engine.register_fn("frequency", move || -> ScriptResult<INT> {
+        let status = handle
+            .block_on(proxy.query_status())
+            .map_err(|error| error.to_string())?;
+        Ok(status.center_frequency.into())
+    });
+
+    let proxy = app.clone();
+    engine.register_fn("set_gain", move |gain: FLOAT| {
+        proxy.set_gain(Gain::Value(gain as f32));
+    });
+    let proxy = app.clone();
+    engine.register_fn("set_gain", move |gain: INT| {
+        proxy.set_gain(Gain::Value(gain as f32));
+    });
+
+    let proxy = app.clone();
+    engine.register_fn("auto_gain", move || proxy.set_gain(Gain::Auto));
+
+    let proxy = app.clone();
+    engine.register_fn("software_gain", move || proxy.set_gain(Gain::Software));
+
+    let proxy = app.clone();
+    engine.register_fn("set_mode", move |name: &str| -> ScriptResult<()> {
+        let mode = name.parse::<Mode>().map_err(|error| error.to_string())?;
+        proxy.set_mode(mode);
+        Ok(())
+    });
+
+    let proxy = app.clone();
+    let handle = runtime.clone();
+    engine.register_fn("start_recording", move |path: &str| -> ScriptResult<()> {
+        handle
+            .block_on(proxy.start_recording(path.into()))
+            .map_err(|error| error.to_string().into())
+    });
+
+    let proxy = app.clone();
+    engine.register_fn("stop_recording", move || proxy.stop_recording());
+
+    let proxy = app.clone();
+    let handle = runtime.clone();
+    engine.register_fn("set_source", move |source: &str| -> ScriptResult<()> {
+        let spec = source
+            .parse::<SourceSpec>()
+            .map_err(|error| error.to_string())?;
+        handle
+            .block_on(proxy.switch_source(spec))
+            .map_err(|error| error.to_string().into())
+    });
+
+    let proxy = app.clone();
+    engine.register_fn("action", move |name: &str| -> ScriptResult<()> {
+        // actions are named like in the keybinds file
+        let action = serde_json::from_value::<Action>(name.into())
+            .map_err(|_| format!("Unknown action: {name}"))?;
+        proxy.perform_action(action);
+        Ok(())
+    });
+
+    let proxy = app.clone();
+    engine.register_fn("wait", move |seconds: FLOAT| -> ScriptResult<()> {
+        wait(&proxy, seconds)
+    });
+    let proxy = app.clone();
+    engine.register_fn("wait", move |seconds: INT| -> ScriptResult<()> {
+        wait(&proxy, seconds as FLOAT)
+    });
+
+    engine.register_fn("now", || Local::now().format("%Y%m%d-%H%M%S").to_string());
+
+    let proxy = app;
+    engine.register_fn("quit", move || proxy.request_exit());
+
+    engine
+}
+
+fn to_frequency(frequency: INT) -> ScriptResult<u32> {
+    u32::try_from(frequency).map_err(|_| format!("Invalid frequency: {frequency}").into())
+}
+
+/// Sleeps, but returns early with an error if the TUI exits.
+fn wait(app: &AppProxy, seconds: FLOAT) -> ScriptResult<()> {
+    let duration =
+        Duration::try_from_secs_f64(seconds).map_err(|_| format!("Invalid duration: {seconds}"))?;
+    let deadline = Instant::now() + duration;
+
+    loop {
+        if app.is_closed() {
+            return Err("TUI exited".into());
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(());
+        }
+        std::thread::sleep(WAIT_POLL_INTERVAL.min(deadline - now));
+    }
+}
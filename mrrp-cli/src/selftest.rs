@@ -0,0 +1,501 @@
+//! Self-check that runs without hardware.
+//!
+//! Every check generates a known signal, runs it through the receive path
+//! that would handle it on air, and compares the result against what was
+//! sent. This is meant as a smoke test for a build, so the tolerances are
+//! loose enough to not depend on float details of the platform.
+
+use std::{
+    f32::consts::TAU,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use color_eyre::eyre::bail;
+use mrrp::{
+    fft::{
+        BackendKind,
+        FftPool,
+    },
+    io::combinators::Scanner,
+    modem::{
+        afsk::{
+            AfskDemodulator,
+            AfskModulator,
+        },
+        am::AmModulator,
+        fm::{
+            FmDemodulator,
+            FmModulator,
+        },
+        pocsag::{
+            PocsagDecoder,
+            PocsagEncoder,
+            fsk_config,
+        },
+        sstv::{
+            DecodeError,
+            SstvDecoder,
+            SstvEncoder,
+            image::{
+                Channel,
+                FrameBuffer,
+                FrameBufferMut,
+            },
+            modes::ModeSpecification,
+        },
+    },
+    source::{
+        ComplexSinusoid,
+        SignalGenerator,
+        SineWave,
+    },
+};
+use num_complex::Complex;
+
+use crate::{
+    Error,
+    args::SelftestArgs,
+    demodulator::{
+        Demodulator,
+        Mode,
+    },
+    fft::{
+        Fft,
+        Window,
+    },
+    time_shift::CatchUp,
+    util::FrequencyBand,
+};
+
+/// Frequency of the test tone used for the audio checks.
+const TONE_FREQUENCY: f32 = 1000.0;
+
+/// Amplitude of the test tone used for the audio checks.
+const TONE_AMPLITUDE: f32 = 0.5;
+
+/// Maximum relative deviation of a demodulated tone's level.
+const MAX_LEVEL_ERROR: f32 = 0.05;
+
+/// Maximum noise and distortion relative to a demodulated tone's level.
+const MAX_DISTORTION: f32 = 0.05;
+
+pub async fn run(args: SelftestArgs) -> Result<(), Error> {
+    let mut report = Report::default();
+
+    report.run("FFT tone sweep", fft_tone_sweep()).await;
+    report.run("AM receiver", async { am_receiver() }).await;
+    report.run("FM modem", async { fm_modem() }).await;
+    report.run("POCSAG modem", async { pocsag_modem() }).await;
+    if args.quick {
+        report.skip("SSTV modem");
+    }
+    else {
+        report.run("SSTV modem", sstv_modem()).await;
+    }
+
+    println!();
+    if report.num_failed > 0 {
+        bail!(
+            "{} of {} checks failed",
+            report.num_failed,
+            report.num_checks
+        );
+    }
+    println!("All {} checks passed", report.num_checks);
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    num_checks: usize,
+    num_failed: usize,
+}
+
+impl Report {
+    async fn run(&mut self, name: &str, check: impl Future<Output = Result<Outcome, Error>>) {
+        let start = Instant::now();
+        let outcome = check.await.unwrap_or_else(|error| {
+            Outcome {
+                passed: false,
+                details: format!("error: {error}"),
+            }
+        });
+        let duration = start.elapsed();
+
+        self.num_checks += 1;
+        if !outcome.passed {
+            self.num_failed += 1;
+        }
+
+        println!(
+            "{name:<16} {:<4}  {:>8}  {}",
+            if outcome.passed { "PASS" } else { "FAIL" },
+            format_duration(duration),
+            outcome.details
+        );
+    }
+
+    fn skip(&self, name: &str) {
+        println!("{name:<16} SKIP");
+    }
+}
+
+#[derive(Debug)]
+struct Outcome {
+    passed: bool,
+    details: String,
+}
+
+impl Outcome {
+    /// Checks the level and distortion of a demodulated test tone.
+    fn tone(tone: Tone) -> Self {
+        let level_error = (tone.amplitude - TONE_AMPLITUDE).abs() / TONE_AMPLITUDE;
+        Self {
+            passed: level_error <= MAX_LEVEL_ERROR && tone.distortion <= MAX_DISTORTION,
+            details: format!(
+                "level {:.3} (expected {TONE_AMPLITUDE} ± {:.0}%), distortion {:.2}% (max {:.0}%)",
+                tone.amplitude,
+                MAX_LEVEL_ERROR * 100.0,
+                tone.distortion * 100.0,
+                MAX_DISTORTION * 100.0,
+            ),
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{} ms", duration.as_millis())
+    }
+    else {
+        format!("{:.1} s", duration.as_secs_f32())
+    }
+}
+
+/// Feeds complex tones across the spectrum into the FFT used for the
+/// waterfall, and checks that each shows up in the right bin.
+async fn fft_tone_sweep() -> Result<Outcome, Error> {
+    const SIZE: usize = 1024;
+    const SAMPLE_RATE: f32 = 1_024_000.0;
+    const MAX_BIN_ERROR: usize = 1;
+
+    let mut fft = Fft::new(SIZE, Window::Hann, BackendKind::Cpu, &FftPool::new()).await;
+    let bin_width = SAMPLE_RATE / SIZE as f32;
+
+    let mut max_bin_error = 0;
+    let mut samples = vec![Complex::default(); SIZE];
+    for i in -8..8 {
+        // odd multiples of half a bin would be ambiguous, so we offset by a quarter bin
+        let frequency = (i as f32 * 60.0 + 0.25) * bin_width;
+        let mut sinusoid = ComplexSinusoid::new(frequency, SAMPLE_RATE);
+        samples.fill_with(|| sinusoid.next());
+
+        let spectrum = fft.forward(&samples).await;
+        let peak = spectrum
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.norm_sqr().total_cmp(&b.norm_sqr()))
+            .map(|(bin, _)| bin)
+            .unwrap();
+
+        let expected = (SIZE as isize / 2 + (frequency / bin_width).round() as isize) as usize;
+        max_bin_error = max_bin_error.max(peak.abs_diff(expected));
+    }
+
+    Ok(Outcome {
+        passed: max_bin_error <= MAX_BIN_ERROR,
+        details: format!("peak off by {max_bin_error} bins (max {MAX_BIN_ERROR})"),
+    })
+}
+
+/// Modulates a tone onto an AM carrier off center and runs it through the
+/// demodulator that the app uses for audio.
+fn am_receiver() -> Result<Outcome, Error> {
+    const SAMPLE_RATE: u32 = 240_000;
+    const CENTER_FREQUENCY: u32 = 100_000_000;
+    const OFFSET: u32 = 20_000;
+    const BANDWIDTH: u32 = 24_000;
+
+    let sampled_band = FrequencyBand::from_center_and_bandwidth(CENTER_FREQUENCY, SAMPLE_RATE);
+    let band = FrequencyBand::from_center_and_bandwidth(CENTER_FREQUENCY + OFFSET, BANDWIDTH);
+    let mut demodulator = Demodulator::new(
+        band,
+        sampled_band,
+        Duration::from_secs(1),
+        CatchUp::Skip,
+        Mode::Am,
+        &FftPool::new(),
+    );
+    let mut audio_source = demodulator.audio_source();
+    let audio_sample_rate = BANDWIDTH / 2;
+
+    let mut tone = SineWave::new(TONE_FREQUENCY, SAMPLE_RATE as f32);
+    let mut carrier = ComplexSinusoid::new(OFFSET as f32, SAMPLE_RATE as f32);
+    let mut modulator = AmModulator::new(1.0);
+
+    // push and play 10 ms at a time, so that playback stays at live audio
+    let chunk_size = SAMPLE_RATE as usize / 100;
+    let audio_chunk_size = audio_sample_rate as usize / 100;
+    let mut chunk = vec![Complex::default(); chunk_size];
+    let mut audio = vec![];
+    for _ in 0..50 {
+        chunk.fill_with(|| modulator.scan(TONE_AMPLITUDE * tone.next()) * carrier.next());
+        demodulator.push(&chunk);
+        audio.extend(audio_source.by_ref().take(audio_chunk_size));
+    }
+
+    // skip the first chunk while the filter settles
+    let tone = Tone::measure(
+        &audio[audio_chunk_size..],
+        TONE_FREQUENCY,
+        audio_sample_rate as f32,
+    );
+    Ok(Outcome::tone(tone))
+}
+
+/// Sends a tone through the FM modulator and demodulator.
+fn fm_modem() -> Result<Outcome, Error> {
+    const SAMPLE_RATE: f32 = 48_000.0;
+    const DEVIATION: f32 = 5_000.0;
+
+    let mut tone = SineWave::new(TONE_FREQUENCY, SAMPLE_RATE);
+    let mut modulator = FmModulator::new(SAMPLE_RATE, DEVIATION);
+    let mut demodulator = FmDemodulator::new(SAMPLE_RATE, DEVIATION);
+
+    let audio = (0..SAMPLE_RATE as usize)
+        .map(|_| demodulator.scan(modulator.scan(TONE_AMPLITUDE * tone.next())))
+        .skip(48)
+        .collect::<Vec<_>>();
+
+    Ok(Outcome::tone(Tone::measure(
+        &audio,
+        TONE_FREQUENCY,
+        SAMPLE_RATE,
+    )))
+}
+
+/// Encodes pages, modulates them with FSK and decodes them again.
+fn pocsag_modem() -> Result<Outcome, Error> {
+    const SAMPLE_RATE: f32 = 48_000.0;
+    const BAUD_RATE: f32 = 1200.0;
+
+    let pages = [
+        (1234567, "mrrp selftest"),
+        (42, "The quick brown fox jumps over the lazy dog"),
+    ];
+
+    let mut encoder = PocsagEncoder::new();
+    for (address, text) in pages {
+        encoder.push_alphanumeric(address, text);
+    }
+
+    let mut signal = vec![];
+    AfskModulator::new(fsk_config(BAUD_RATE), SAMPLE_RATE)
+        .modulate_bits(encoder.finish(), &mut signal);
+
+    let mut demodulator = AfskDemodulator::new(fsk_config(BAUD_RATE), SAMPLE_RATE);
+    let mut decoder = PocsagDecoder::new();
+    let messages = signal
+        .into_iter()
+        .filter_map(|sample| demodulator.scan(sample))
+        .filter_map(|bit| decoder.scan(bit))
+        .collect::<Vec<_>>();
+
+    let num_correct = pages
+        .iter()
+        .zip(&messages)
+        .filter(|((address, text), message)| {
+            message.address == *address && message.alphanumeric() == *text && !message.has_errors
+        })
+        .count();
+
+    Ok(Outcome {
+        passed: num_correct == pages.len() && messages.len() == pages.len(),
+        details: format!(
+            "{num_correct} of {} pages decoded correctly, {} received",
+            pages.len(),
+            messages.len()
+        ),
+    })
+}
+
+/// Encodes a test pattern as Martin M2 and decodes it again.
+async fn sstv_modem() -> Result<Outcome, Error> {
+    const SAMPLE_RATE: f32 = 11_025.0;
+    const MAX_PIXEL_ERROR: f32 = 24.0;
+
+    let mode = ModeSpecification::M2;
+    let pattern = TestPattern {
+        width: mode.pixels_per_line,
+        height: mode.num_lines,
+    };
+
+    let encoder = SstvEncoder::new(pattern, mode, SAMPLE_RATE);
+    let mut image = Image::default();
+    match SstvDecoder::new_with_mode_select(encoder, &mut image, mode).await {
+        // the decoder might wait for more samples after the last line
+        Ok(()) | Err(DecodeError::Eof) => {}
+        Err(error) => bail!("{error:?}"),
+    }
+
+    if (image.width, image.height) != (pattern.width, pattern.height) {
+        return Ok(Outcome {
+            passed: false,
+            details: format!(
+                "decoded {}x{} image, expected {}x{}",
+                image.width, image.height, pattern.width, pattern.height
+            ),
+        });
+    }
+
+    let mut total_error = 0;
+    for y in 0..image.height {
+        for x in 0..image.width {
+            for channel in [Channel::Red, Channel::Green, Channel::Blue] {
+                total_error += pattern
+                    .channel(x, y, channel)
+                    .abs_diff(image.channel(x, y, channel)) as u64;
+            }
+        }
+    }
+    let pixel_error = total_error as f32 / (3 * image.pixels.len()) as f32;
+
+    Ok(Outcome {
+        passed: pixel_error <= MAX_PIXEL_ERROR,
+        details: format!("mean pixel error {pixel_error:.1} (max {MAX_PIXEL_ERROR})"),
+    })
+}
+
+/// Level and distortion of a tone in a signal.
+#[derive(Clone, Copy, Debug)]
+struct Tone {
+    amplitude: f32,
+
+    /// RMS of everything that isn't the tone or DC, relative to the RMS of
+    /// the tone.
+    distortion: f32,
+}
+
+impl Tone {
+    /// Fits a sinusoid at `frequency` to `samples`.
+    ///
+    /// The fit is only exact if `samples` spans whole periods, but it's close
+    /// enough for long signals.
+    fn measure(samples: &[f32], frequency: f32, sample_rate: f32) -> Self {
+        let n = samples.len() as f32;
+        let omega = TAU * frequency / sample_rate;
+
+        let dc = samples.iter().sum::<f32>() / n;
+        let (mut a, mut b) = (0.0, 0.0);
+        for (i, sample) in samples.iter().enumerate() {
+            let (sin, cos) = (omega * i as f32).sin_cos();
+            a += (sample - dc) * cos;
+            b += (sample - dc) * sin;
+        }
+        let (a, b) = (2.0 * a / n, 2.0 * b / n);
+
+        let residual = samples
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                let (sin, cos) = (omega * i as f32).sin_cos();
+                (sample - dc - a * cos - b * sin).powi(2)
+            })
+            .sum::<f32>();
+
+        let amplitude = a.hypot(b);
+        Self {
+            amplitude,
+            distortion: (residual / n).sqrt() / (amplitude / 2.0f32.sqrt()),
+        }
+    }
+}
+
+/// Gradients in all channels, so that every pixel value shows up somewhere
+/// and there are no hard edges for the decoder to smear.
+#[derive(Clone, Copy, Debug)]
+struct TestPattern {
+    width: usize,
+    height: usize,
+}
+
+impl FrameBuffer for TestPattern {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn channel(&self, x: usize, y: usize, channel: Channel) -> u8 {
+        let horizontal = (x * 255 / (self.width - 1)) as u8;
+        let vertical = (y * 255 / (self.height - 1)) as u8;
+        match channel {
+            Channel::Red => horizontal,
+            Channel::Green => vertical,
+            Channel::Blue => 255 - horizontal,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    fn channel(&self, x: usize, y: usize, channel: Channel) -> u8 {
+        self.pixels[y * self.width + x][channel_index(channel)]
+    }
+}
+
+impl FrameBufferMut for Image {
+    fn set_size(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![[0; 3]; width * height];
+    }
+
+    fn set_channel(&mut self, x: usize, y: usize, channel: Channel, value: u8) {
+        self.pixels[y * self.width + x][channel_index(channel)] = value;
+    }
+}
+
+fn channel_index(channel: Channel) -> usize {
+    match channel {
+        Channel::Red => 0,
+        Channel::Green => 1,
+        Channel::Blue => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Tone,
+        fm_modem,
+        pocsag_modem,
+    };
+
+    #[test]
+    fn it_measures_tones() {
+        let samples = (0..4800)
+            .map(|i| 0.1 + 0.25 * (std::f32::consts::TAU * 1000.0 * i as f32 / 48000.0).cos())
+            .collect::<Vec<_>>();
+        let tone = Tone::measure(&samples, 1000.0, 48000.0);
+        assert!((tone.amplitude - 0.25).abs() < 1e-3);
+        assert!(tone.distortion < 1e-3);
+    }
+
+    #[test]
+    fn quick_checks_pass() {
+        assert!(fm_modem().unwrap().passed);
+        assert!(pocsag_modem().unwrap().passed);
+    }
+}
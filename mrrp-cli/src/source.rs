@@ -0,0 +1,356 @@
+//! Sample sources
+//!
+//! The TUI reads samples either from an SDR (a local RTL-SDR, or one shared
+//! with `rtl_tcp`), or plays back a recording. The source can be switched while
+//! the TUI is running, see [`AppProxy::switch_source`].
+//!
+//! [`AppProxy::switch_source`]: crate::app::AppProxy::switch_source
+
+use std::{
+    fmt::Display,
+    path::{
+        Path,
+        PathBuf,
+    },
+    str::FromStr,
+};
+
+use color_eyre::eyre::{
+    bail,
+    eyre,
+};
+use futures_util::future::BoxFuture;
+use mrrp::{
+    chunk::ChunkStreamReadSamples,
+    io::{
+        AsyncReadSamplesExt,
+        DynReadSamples,
+        GetCenterFrequency,
+        GetSampleRate,
+    },
+    source::{
+        bfp::BfpAsyncReader,
+        file::{
+            RawIqFormat,
+            RawIqSource,
+            WavSource,
+        },
+    },
+};
+use num_complex::Complex;
+use rtlsdr_async::{
+    Backend,
+    Iq,
+    RtlSdr,
+    rtl_tcp::client::RtlTcpClient,
+};
+use tokio::fs::File;
+
+use crate::{
+    Error,
+    args::Gain,
+    util::FrequencyBand,
+};
+
+/// Samples read from a [`Source`].
+pub type SourceSamples = DynReadSamples<Complex<f32>, mrrp::Error>;
+
+/// Which source to read samples from.
+///
+/// Parsed from `rtlsdr[:<index>]`, `rtl_tcp:<address>` or `file:<path>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceSpec {
+    RtlSdr {
+        device: u32,
+    },
+    RtlTcp {
+        address: String,
+    },
+    /// A recording. WAV files are played back with their own sample rate, and
+    /// center frequency if they're tagged with one. Files ending in `.bfp` or
+    /// `.bfp8` are decoded with the block floating point codec, anything else
+    /// is read as interleaved little-endian `f32`, like
+    /// [`Recording`][crate::recording::Recording] writes them. These are
+    /// assumed to have the current sample rate and center frequency.
+    File {
+        path: PathBuf,
+    },
+}
+
+impl SourceSpec {
+    pub fn is_file(&self) -> bool {
+        matches!(self, Self::File { .. })
+    }
+}
+
+impl FromStr for SourceSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, argument) = s
+            .split_once(':')
+            .map_or((s, None), |(kind, argument)| (kind, Some(argument)));
+
+        match (kind, argument) {
+            ("rtlsdr", None) => Ok(Self::RtlSdr { device: 0 }),
+            ("rtlsdr", Some(device)) => {
+                Ok(Self::RtlSdr {
+                    device: device.parse()?,
+                })
+            }
+            ("rtl_tcp", Some(address)) => {
+                Ok(Self::RtlTcp {
+                    address: address.to_owned(),
+                })
+            }
+            ("file", Some(path)) => Ok(Self::File { path: path.into() }),
+            _ => {
+                Err(eyre!(
+                    "Invalid source: {s}. Expected `rtlsdr[:<index>]`, `rtl_tcp:<address>` or `file:<path>`"
+                ))
+            }
+        }
+    }
+}
+
+impl Display for SourceSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RtlSdr { device } => write!(f, "rtlsdr:{device}"),
+            Self::RtlTcp { address } => write!(f, "rtl_tcp:{address}"),
+            Self::File { path } => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+/// Controls the tuner of an SDR.
+///
+/// This is object-safe, so that the app doesn't need to know which backend
+/// it's talking to. The returned futures don't borrow the tuner, so they can be
+/// spawned.
+pub trait Tuner: Send {
+    fn tune(&self, frequency: u32) -> BoxFuture<'static, Result<(), Error>>;
+
+    fn set_gain(&self, gain: Gain) -> BoxFuture<'static, Result<(), Error>>;
+}
+
+impl std::fmt::Debug for dyn Tuner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tuner").finish_non_exhaustive()
+    }
+}
+
+impl<B> Tuner for B
+where
+    B: Backend + Send + Clone + 'static,
+    <B as Backend>::Error: std::error::Error + Send + Sync + 'static,
+{
+    fn tune(&self, frequency: u32) -> BoxFuture<'static, Result<(), Error>> {
+        let backend = self.clone();
+        Box::pin(async move { Ok(Backend::set_center_frequency(&backend, frequency).await?) })
+    }
+
+    fn set_gain(&self, gain: Gain) -> BoxFuture<'static, Result<(), Error>> {
+        let backend = self.clone();
+        Box::pin(async move { Ok(Backend::set_tuner_gain(&backend, gain.into()).await?) })
+    }
+}
+
+/// An opened sample source.
+pub struct Source {
+    /// The SDR's tuner. Recordings don't have one.
+    pub tuner: Option<Box<dyn Tuner>>,
+    pub samples: SourceSamples,
+    pub sampled_frequency_band: FrequencyBand,
+}
+
+impl Source {
+    /// Opens the source.
+    ///
+    /// SDRs are tuned to `sampled_frequency_band` and set to `gain`. Files
+    /// are played back in real time.
+    pub async fn open(
+        spec: &SourceSpec,
+        sampled_frequency_band: FrequencyBand,
+        gain: Gain,
+    ) -> Result<Self, Error> {
+        match spec {
+            SourceSpec::RtlSdr { device } => {
+                open_backend(RtlSdr::open(*device)?, sampled_frequency_band, gain).await
+            }
+            SourceSpec::RtlTcp { address } => {
+                open_backend(
+                    RtlTcpClient::connect(address).await?,
+                    sampled_frequency_band,
+                    gain,
+                )
+                .await
+            }
+            SourceSpec::File { path } => open_file(path, sampled_frequency_band).await,
+        }
+    }
+}
+
+impl std::fmt::Debug for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Source")
+            .field("tuner", &self.tuner)
+            .field("sampled_frequency_band", &self.sampled_frequency_band)
+            .finish_non_exhaustive()
+    }
+}
+
+async fn open_backend<B>(
+    backend: B,
+    sampled_frequency_band: FrequencyBand,
+    gain: Gain,
+) -> Result<Source, Error>
+where
+    B: Backend + Send + Clone + 'static,
+    <B as Backend>::Error: std::error::Error + Send + Sync + 'static,
+{
+    Backend::set_center_frequency(&backend, sampled_frequency_band.center()).await?;
+    Backend::set_sample_rate(&backend, sampled_frequency_band.bandwidth()).await?;
+    Backend::set_tuner_gain(&backend, gain.into()).await?;
+
+    let samples = ChunkStreamReadSamples::<_, _, Iq, _>::new(backend.samples().await?)
+        .map(Complex::<f32>::from)
+        .erase_err()
+        .boxed();
+
+    Ok(Source {
+        tuner: Some(Box::new(backend)),
+        samples,
+        sampled_frequency_band,
+    })
+}
+
+async fn open_file(path: &Path, sampled_frequency_band: FrequencyBand) -> Result<Source, Error> {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    let (samples, sampled_frequency_band) = match extension {
+        Some("wav") => {
+            let wav = WavSource::<_, Complex<f32>>::from_path(path)?;
+
+            let sample_rate = wav.sample_rate() as u32;
+            if sample_rate % 2 == 1 {
+                bail!("Sample rate must be divisible by 2, but the file has {sample_rate} Hz");
+            }
+            let center_frequency = match wav.center_frequency() as u32 {
+                0 => sampled_frequency_band.center(),
+                center_frequency => center_frequency,
+            };
+
+            (
+                wav.throttle_to_sample_rate().erase_err().boxed(),
+                FrequencyBand::from_center_and_bandwidth(center_frequency, sample_rate),
+            )
+        }
+        Some("bfp" | "bfp8") => {
+            let reader = BfpAsyncReader::new(File::open(path).await?);
+            (
+                reader
+                    .with_sample_rate(sampled_frequency_band.bandwidth() as f32)
+                    .throttle_to_sample_rate()
+                    .erase_err()
+                    .boxed(),
+                sampled_frequency_band,
+            )
+        }
+        _ => {
+            // anything else is a raw IQ file, of 32 bit floats unless the
+            // extension says otherwise
+            let format = RawIqFormat::from_path(path).unwrap_or(RawIqFormat::Cf32);
+            let reader = RawIqSource::from_path(
+                path,
+                format,
+                sampled_frequency_band.bandwidth() as f32,
+                sampled_frequency_band.center() as f32,
+            )?;
+            (
+                reader.throttle_to_sample_rate().erase_err().boxed(),
+                sampled_frequency_band,
+            )
+        }
+    };
+
+    Ok(Source {
+        tuner: None,
+        samples,
+        sampled_frequency_band,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use futures_util::FutureExt;
+    use mrrp::{
+        io::AsyncReadSamplesExt,
+        source::file::{
+            RawIqFormat,
+            RawIqSource,
+        },
+    };
+    use num_complex::Complex;
+
+    use super::SourceSpec;
+
+    #[test]
+    fn it_parses_source_specs() {
+        for (s, spec) in [
+            ("rtlsdr", SourceSpec::RtlSdr { device: 0 }),
+            ("rtlsdr:2", SourceSpec::RtlSdr { device: 2 }),
+            (
+                "rtl_tcp:127.0.0.1:1234",
+                SourceSpec::RtlTcp {
+                    address: "127.0.0.1:1234".to_owned(),
+                },
+            ),
+            (
+                "file:/tmp/recording.cf32",
+                SourceSpec::File {
+                    path: PathBuf::from("/tmp/recording.cf32"),
+                },
+            ),
+        ] {
+            assert_eq!(s.parse::<SourceSpec>().unwrap(), spec);
+        }
+
+        for s in ["", "rtlsdr:foo", "rtl_tcp", "file", "hackrf:0"] {
+            assert!(s.parse::<SourceSpec>().is_err(), "{s:?} should be invalid");
+        }
+    }
+
+    #[test]
+    fn it_reads_raw_iq() {
+        let input = (0..1000)
+            .map(|i| Complex::new(i as f32, -(i as f32)))
+            .collect::<Vec<_>>();
+        let mut bytes = input
+            .iter()
+            .flat_map(|sample| [sample.re.to_le_bytes(), sample.im.to_le_bytes()])
+            .flatten()
+            .collect::<Vec<u8>>();
+        // an incomplete sample is ignored
+        bytes.extend_from_slice(&[1, 2, 3]);
+
+        let mut reader = RawIqSource::new(&bytes[..], RawIqFormat::Cf32, 1000.0, 0.0);
+        let mut output = vec![Complex::default(); 1500];
+        let mut num_read = 0;
+        loop {
+            let n = reader
+                .read_samples(&mut output[num_read..])
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            if n == 0 {
+                break;
+            }
+            num_read += n;
+        }
+
+        assert_eq!(&output[..num_read], &input[..]);
+    }
+}
@@ -0,0 +1,178 @@
+//! Versioned app state snapshots.
+//!
+//! Snapshots are stored as CBOR. Loading first reads the snapshot as an
+//! untyped [`Value`] and runs it through the migration chain. The app state
+//! itself is then restored section by section with [`section`], so that a
+//! section that doesn't load (e.g. because its struct changed) falls back to
+//! its default instead of discarding everything.
+
+use std::io::{
+    Read,
+    Write,
+};
+
+use ciborium::Value;
+use color_eyre::eyre::{
+    Error,
+    bail,
+};
+use serde::{
+    Serialize,
+    de::DeserializeOwned,
+};
+
+use crate::app::AppSnapshot;
+
+/// Version of the snapshot format that this build writes.
+pub const VERSION: u32 = 1;
+
+type Migration = fn(&mut Vec<(Value, Value)>) -> Result<(), Error>;
+
+/// `MIGRATIONS[n]` migrates a snapshot from version `n` to `n + 1`.
+const MIGRATIONS: &[Migration] = &[
+    // v0 -> v1: snapshots from before versioning. the layout is the same, they just
+    // lack the version field.
+    |_snapshot| Ok(()),
+];
+
+const _: () = assert!(MIGRATIONS.len() == VERSION as usize);
+
+/// A snapshot that was migrated to the current version.
+#[derive(Debug)]
+pub struct Migrated {
+    /// Version the snapshot was stored with.
+    pub from_version: u32,
+
+    /// The migrated snapshot, with the app state left as a CBOR value.
+    pub snapshot: AppSnapshot<Value>,
+}
+
+pub fn read_snapshot<R: Read>(reader: R) -> Result<Migrated, Error> {
+    let Value::Map(mut snapshot) = ciborium::from_reader(reader)?
+    else {
+        bail!("App state snapshot is not a map");
+    };
+
+    let from_version = match get(&snapshot, "version") {
+        Some(version) => version.deserialized::<u32>()?,
+        None => 0,
+    };
+
+    if from_version > VERSION {
+        tracing::warn!(
+            from_version,
+            VERSION,
+            "App state is from a newer version. Loading what we can."
+        );
+    }
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from_version as usize) {
+        tracing::info!(from = version, to = version + 1, "Migrating app state");
+        migration(&mut snapshot)?;
+    }
+    set(&mut snapshot, "version", VERSION.into());
+
+    Ok(Migrated {
+        from_version,
+        snapshot: Value::Map(snapshot).deserialized()?,
+    })
+}
+
+pub fn write_snapshot<W: Write, A: Serialize>(
+    writer: W,
+    snapshot: &AppSnapshot<A>,
+) -> Result<(), Error> {
+    ciborium::into_writer(snapshot, writer)?;
+    Ok(())
+}
+
+/// Deserializes the field `key` of the map `value`.
+///
+/// Returns `None` if the field is missing or doesn't deserialize. The latter
+/// is logged.
+pub fn section<T: DeserializeOwned>(value: &Value, key: &str) -> Option<T> {
+    let Value::Map(map) = value
+    else {
+        return None;
+    };
+
+    let Some(field) = get(map, key)
+    else {
+        tracing::debug!(key, "Section missing from app state");
+        return None;
+    };
+
+    field
+        .deserialized()
+        .inspect_err(|error| {
+            tracing::warn!(
+                key,
+                ?error,
+                "Failed to load app state section. Using default."
+            )
+        })
+        .ok()
+}
+
+fn get<'a>(map: &'a [(Value, Value)], key: &str) -> Option<&'a Value> {
+    map.iter()
+        .find_map(|(k, v)| (k.as_text() == Some(key)).then_some(v))
+}
+
+fn set(map: &mut Vec<(Value, Value)>, key: &str, value: Value) {
+    if let Some((_, v)) = map.iter_mut().find(|(k, _)| k.as_text() == Some(key)) {
+        *v = value;
+    }
+    else {
+        map.push((key.into(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use ciborium::Value;
+
+    use super::{
+        VERSION,
+        read_snapshot,
+        section,
+    };
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut buffer = vec![];
+        ciborium::into_writer(value, &mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn it_migrates_unversioned_snapshots() {
+        let snapshot = Value::Map(vec![
+            (
+                "app_state".into(),
+                Value::Map(vec![("invert_spectrum".into(), true.into())]),
+            ),
+            ("timestamp".into(), Local::now().to_rfc3339().into()),
+        ]);
+
+        let migrated = read_snapshot(&encode(&snapshot)[..]).unwrap();
+        assert_eq!(migrated.from_version, 0);
+        assert_eq!(migrated.snapshot.version, VERSION);
+        assert_eq!(
+            section::<bool>(&migrated.snapshot.app_state, "invert_spectrum"),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn broken_sections_fall_back_to_none() {
+        let app_state = Value::Map(vec![
+            ("invert_spectrum".into(), "not a bool".into()),
+            ("zoom_level".into(), 3.into()),
+        ]);
+
+        assert_eq!(section::<bool>(&app_state, "invert_spectrum"), None);
+        assert_eq!(section::<bool>(&app_state, "missing"), None);
+        assert_eq!(section::<u32>(&app_state, "zoom_level"), Some(3));
+    }
+}
@@ -0,0 +1,432 @@
+//! Sweeps the tuner over a band that is wider than it can sample at once.
+//!
+//! The band is covered by hops, each tuned to its own center frequency. Only
+//! the middle of a hop's spectrum is used, since the filters of the SDR roll
+//! off towards the edges. Neighbouring hops overlap a little: The gain can
+//! differ from hop to hop, so every hop is scaled to the level of the previous
+//! one in the overlap, and then the two are cross-faded across it.
+
+use std::{
+    mem,
+    ops::Range,
+    time::Duration,
+};
+
+use chrono::{
+    DateTime,
+    Local,
+};
+use color_eyre::eyre::{
+    Error,
+    bail,
+    eyre,
+};
+use mrrp::frequency::Frequency;
+use num_complex::Complex;
+
+use crate::util::{
+    FrequencyBand,
+    lerp,
+};
+
+/// Fraction of the sampled bandwidth of a hop that is used.
+const USABLE_FRACTION: f64 = 0.8;
+
+/// Fraction of the sampled bandwidth by which neighbouring hops overlap.
+const OVERLAP_FRACTION: f64 = 0.1;
+
+/// Spectra that are discarded after a retune. They might still contain
+/// samples from the previous hop.
+const SETTLE_SPECTRA: usize = 2;
+
+/// Spectra that are averaged per hop.
+const HOP_SPECTRA: usize = 4;
+
+/// Bins on either side of the center of a hop that are replaced by their
+/// neighbours, to hide the DC spike.
+const DC_BINS: usize = 1;
+
+/// Parses a band to sweep, e.g. `88M:108M`.
+pub fn parse_frequency_band(s: &str) -> Result<FrequencyBand, Error> {
+    let (start, end) = s
+        .split_once(':')
+        .ok_or_else(|| eyre!("Invalid sweep: {s}. Expected `<start>:<end>`"))?;
+    let start = parse_frequency(start)?;
+    let end = parse_frequency(end)?;
+    if start >= end {
+        bail!("The start of the sweep must be below its end");
+    }
+    Ok(FrequencyBand { start, end })
+}
+
+fn parse_frequency(s: &str) -> Result<u32, Error> {
+    let frequency: Frequency = s.parse()?;
+    Ok(frequency.as_hz().try_into()?)
+}
+
+/// A sweep that was stitched together from all hops.
+#[derive(Debug)]
+pub struct SweepLine<'a> {
+    /// Scaled such that the waterfall shows the same levels as for a single
+    /// hop.
+    pub spectrum: &'a [Complex<f32>],
+    pub frequency_band: FrequencyBand,
+    /// When the sweep started.
+    pub timestamp: DateTime<Local>,
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+pub struct Sweep {
+    frequency_band: FrequencyBand,
+    sample_rate: u32,
+    fft_size: usize,
+
+    /// Bins of a hop's spectrum that are used.
+    usable: Range<usize>,
+
+    /// Distance between hops in bins.
+    step: usize,
+
+    num_hops: usize,
+    hop: usize,
+    retune_requested: bool,
+    settle: usize,
+
+    /// Sum of the power spectra of the current hop.
+    hop_power: Vec<f32>,
+    num_spectra: usize,
+
+    /// Stitched power spectrum of the whole band.
+    power: Vec<f32>,
+
+    /// Bins of `power` that were written by previous hops of this sweep.
+    filled: usize,
+
+    ratios: Vec<f32>,
+    line: Vec<Complex<f32>>,
+    timestamp: Option<DateTime<Local>>,
+    duration: Duration,
+}
+
+impl Sweep {
+    pub fn new(frequency_band: FrequencyBand, sample_rate: u32, fft_size: usize) -> Self {
+        let margin = ((1.0 - USABLE_FRACTION) / 2.0 * fft_size as f64).round() as usize;
+        let usable = margin..fft_size - margin;
+        let step = (((USABLE_FRACTION - OVERLAP_FRACTION) * fft_size as f64).round() as usize)
+            .clamp(1, usable.len().max(1));
+
+        let bin_width = f64::from(sample_rate) / fft_size as f64;
+        let num_bins =
+            ((f64::from(frequency_band.bandwidth()) / bin_width).round() as usize).max(1);
+        let num_hops = num_bins.saturating_sub(usable.len()).div_ceil(step) + 1;
+
+        Self {
+            frequency_band,
+            sample_rate,
+            fft_size,
+            usable,
+            step,
+            num_hops,
+            hop: 0,
+            retune_requested: false,
+            settle: SETTLE_SPECTRA,
+            hop_power: vec![0.0; fft_size],
+            num_spectra: 0,
+            power: vec![0.0; num_bins],
+            filled: 0,
+            ratios: vec![],
+            line: vec![],
+            timestamp: None,
+            duration: Duration::ZERO,
+        }
+    }
+
+    pub fn frequency_band(&self) -> FrequencyBand {
+        self.frequency_band
+    }
+
+    pub fn num_hops(&self) -> usize {
+        self.num_hops
+    }
+
+    /// Center frequency of the current hop.
+    pub fn hop_frequency(&self) -> u32 {
+        let bin_width = f64::from(self.sample_rate) / self.fft_size as f64;
+        let offset = (self.hop * self.step + self.fft_size / 2 - self.usable.start) as f64;
+        (f64::from(self.frequency_band.start) + offset * bin_width).round() as u32
+    }
+
+    /// Returns the frequency to tune to, once for every hop.
+    pub fn next_retune(&mut self) -> Option<u32> {
+        (!mem::replace(&mut self.retune_requested, true)).then(|| self.hop_frequency())
+    }
+
+    /// Discards the current sweep and starts over at the first hop.
+    pub fn restart(&mut self) {
+        *self = Self::new(self.frequency_band, self.sample_rate, self.fft_size);
+    }
+
+    /// Adds a spectrum that was sampled at `sampled_frequency_band`. Spectra
+    /// that weren't sampled at the current hop are ignored.
+    ///
+    /// Returns the stitched spectrum when the last hop of the sweep is done.
+    pub fn push(
+        &mut self,
+        spectrum: &[Complex<f32>],
+        sampled_frequency_band: FrequencyBand,
+        timestamp: DateTime<Local>,
+        duration: Duration,
+    ) -> Option<SweepLine<'_>> {
+        if sampled_frequency_band.bandwidth() != self.sample_rate || spectrum.len() != self.fft_size
+        {
+            // the source or the FFT changed, so the hops have to be laid out again
+            *self = Self::new(
+                self.frequency_band,
+                sampled_frequency_band.bandwidth(),
+                spectrum.len(),
+            );
+        }
+
+        self.timestamp.get_or_insert(timestamp);
+        self.duration += duration;
+
+        if sampled_frequency_band.center() != self.hop_frequency() {
+            // still tuning
+            return None;
+        }
+        if self.settle > 0 {
+            self.settle -= 1;
+            return None;
+        }
+
+        for (sum, bin) in self.hop_power.iter_mut().zip(spectrum) {
+            *sum += bin.norm_sqr();
+        }
+        self.num_spectra += 1;
+        if self.num_spectra < HOP_SPECTRA {
+            return None;
+        }
+
+        self.stitch_hop();
+
+        self.hop_power.fill(0.0);
+        self.num_spectra = 0;
+        self.settle = SETTLE_SPECTRA;
+        self.retune_requested = false;
+        self.hop += 1;
+        if self.hop < self.num_hops {
+            return None;
+        }
+        self.hop = 0;
+        self.filled = 0;
+
+        // the waterfall normalizes by the bandwidth of a line, which is that of
+        // all hops together
+        let scale = self.power.len() as f32 / self.fft_size as f32;
+        self.line.clear();
+        self.line.extend(
+            self.power
+                .iter()
+                .map(|power| Complex::new((scale * power).sqrt(), 0.0)),
+        );
+
+        let bin_width = f64::from(self.sample_rate) / self.fft_size as f64;
+        let start = self.frequency_band.start;
+        Some(SweepLine {
+            spectrum: &self.line,
+            frequency_band: FrequencyBand {
+                start,
+                end: start + (self.power.len() as f64 * bin_width).round() as u32,
+            },
+            timestamp: self.timestamp.take().unwrap_or(timestamp),
+            duration: mem::take(&mut self.duration),
+        })
+    }
+
+    fn stitch_hop(&mut self) {
+        let num_spectra = self.num_spectra as f32;
+        for power in &mut self.hop_power {
+            *power /= num_spectra;
+        }
+        remove_dc(&mut self.hop_power);
+
+        // index of the first usable bin in the stitched spectrum
+        let offset = self.hop * self.step;
+        let end = (offset + self.usable.len()).min(self.power.len());
+        if offset >= end {
+            return;
+        }
+        let hop = &self.hop_power[self.usable.start..][..end - offset];
+        let overlap = self.filled.saturating_sub(offset).min(hop.len());
+
+        // match the level of the previous hop. the median ignores signals that
+        // only showed up in one of them.
+        self.ratios.clear();
+        self.ratios.extend(
+            self.power[offset..][..overlap]
+                .iter()
+                .zip(hop)
+                .filter(|(_, new)| **new > 0.0)
+                .map(|(previous, new)| previous / new),
+        );
+        let gain = median(&mut self.ratios)
+            .filter(|gain| gain.is_finite() && *gain > 0.0)
+            .unwrap_or(1.0);
+
+        for (i, (stitched, new)) in self.power[offset..end].iter_mut().zip(hop).enumerate() {
+            let new = gain * new;
+            if i < overlap {
+                let t = (i as f32 + 0.5) / overlap as f32;
+                *stitched = lerp(t, *stitched, new);
+            }
+            else {
+                *stitched = new;
+            }
+        }
+
+        self.filled = end;
+    }
+}
+
+/// Replaces the bins around DC by the average of their neighbours.
+fn remove_dc(power: &mut [f32]) {
+    let center = power.len() / 2;
+    if center < DC_BINS + 1 || center + DC_BINS + 1 >= power.len() {
+        return;
+    }
+    let fill = 0.5 * (power[center - DC_BINS - 1] + power[center + DC_BINS + 1]);
+    power[center - DC_BINS..=center + DC_BINS].fill(fill);
+}
+
+fn median(values: &mut [f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    let middle = values.len() / 2;
+    let (_, median, _) = values.select_nth_unstable_by(middle, f32::total_cmp);
+    Some(*median)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Local;
+    use num_complex::Complex;
+
+    use super::{
+        Sweep,
+        parse_frequency_band,
+    };
+    use crate::util::FrequencyBand;
+
+    #[test]
+    fn it_parses_sweep_bands() {
+        assert_eq!(
+            parse_frequency_band("88M:108 MHz").unwrap(),
+            FrequencyBand {
+                start: 88_000_000,
+                end: 108_000_000
+            }
+        );
+        assert_eq!(
+            parse_frequency_band("7000000:7300k").unwrap(),
+            FrequencyBand {
+                start: 7_000_000,
+                end: 7_300_000
+            }
+        );
+        assert!(parse_frequency_band("108M:88M").is_err());
+        assert!(parse_frequency_band("88M").is_err());
+        assert!(parse_frequency_band("88M:5G").is_err());
+    }
+
+    #[test]
+    fn it_covers_the_band() {
+        let band = FrequencyBand {
+            start: 100_000_000,
+            end: 110_000_000,
+        };
+        let mut sweep = Sweep::new(band, 2_400_000, 1024);
+        let num_hops = sweep.num_hops();
+        assert_eq!(num_hops, 6);
+
+        let mut previous = None;
+        for _ in 0..num_hops {
+            let frequency = sweep.next_retune().unwrap();
+            assert!(sweep.next_retune().is_none());
+            if let Some(previous) = previous {
+                assert!(frequency > previous);
+            }
+            previous = Some(frequency);
+            push_hop(&mut sweep, frequency, 1.0);
+        }
+
+        // the usable part of a hop is 1.92 MHz wide. the first hop starts it at the
+        // start of the band, and the last one covers its end.
+        let bin_width = 2_400_000 / 1024;
+        let first = sweep.next_retune().unwrap();
+        assert!(first.abs_diff(band.start + 960_000) < bin_width);
+        assert!(previous.unwrap() + 960_000 + bin_width >= band.end);
+    }
+
+    #[test]
+    fn it_matches_the_levels_of_hops() {
+        let band = FrequencyBand {
+            start: 100_000_000,
+            end: 110_000_000,
+        };
+        let fft_size = 1024;
+        let mut sweep = Sweep::new(band, 2_400_000, fft_size);
+
+        // every hop has another gain
+        let mut first_gain = None;
+        let mut line = None;
+        for hop in 0.. {
+            let frequency = sweep.next_retune().unwrap();
+            let gain = 1.0 + (hop % 3) as f32;
+            first_gain.get_or_insert(gain);
+            if let Some(stitched) = push_hop(&mut sweep, frequency, gain) {
+                line = Some(stitched);
+                break;
+            }
+        }
+
+        let (line, frequency_band) = line.unwrap();
+        assert_eq!(line.len(), 4267);
+        assert_eq!(frequency_band.start, band.start);
+        assert!(frequency_band.end.abs_diff(band.end) < 2_400_000 / 1024);
+
+        // everything is at the level of the first hop
+        let scale = line.len() as f32 / fft_size as f32;
+        for bin in line {
+            let power = bin.norm_sqr() / scale;
+            assert!((power - first_gain.unwrap()).abs() < 1e-3, "{power}");
+        }
+    }
+
+    /// Pushes spectra at `frequency` until the sweep moves on to the next hop.
+    fn push_hop(
+        sweep: &mut Sweep,
+        frequency: u32,
+        gain: f32,
+    ) -> Option<(Vec<Complex<f32>>, FrequencyBand)> {
+        let sampled_frequency_band = FrequencyBand::from_center_and_bandwidth(frequency, 2_400_000);
+        let spectrum = vec![Complex::new(gain.sqrt(), 0.0); 1024];
+        for _ in 0..100 {
+            if let Some(line) = sweep.push(
+                &spectrum,
+                sampled_frequency_band,
+                Local::now(),
+                Duration::from_millis(1),
+            ) {
+                return Some((line.spectrum.to_vec(), line.frequency_band));
+            }
+            if sweep.hop_frequency() != frequency {
+                return None;
+            }
+        }
+        panic!("hop didn't finish");
+    }
+}
@@ -0,0 +1,259 @@
+//! Time-shift buffer for demodulated audio.
+//!
+//! The demodulator pushes audio into a ring that holds the last few seconds,
+//! and the audio output plays from a cursor into that ring. Pausing stops the
+//! cursor while the ring keeps filling, and rewinding moves it back. When the
+//! cursor is behind live, playback either speeds up slightly until it caught
+//! up, or jumps back to live on resume.
+
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use color_eyre::eyre::eyre;
+use parking_lot::Mutex;
+
+use crate::Error;
+
+/// Playback rate while catching up with live audio.
+const CATCH_UP_RATE: f64 = 1.05;
+
+/// How far playback may lag behind live before we consider it time-shifted.
+/// This absorbs the jitter between the demodulator pushing whole chunks and
+/// the audio output pulling samples.
+const MAX_LIVE_DELAY: Duration = Duration::from_millis(250);
+
+/// How playback gets back to live audio after pausing or rewinding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CatchUp {
+    /// Play slightly faster until live audio is reached.
+    #[default]
+    Speedup,
+    /// Jump to live audio on resume.
+    Skip,
+}
+
+impl FromStr for CatchUp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "speedup" => Ok(Self::Speedup),
+            "skip" => Ok(Self::Skip),
+            _ => Err(eyre!("No such catch-up mode: {s}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TimeShiftCommand {
+    TogglePause,
+    Rewind { duration: Duration },
+    SkipToLive,
+}
+
+#[derive(Debug)]
+pub struct TimeShiftBuffer {
+    samples: VecDeque<f32>,
+    capacity: usize,
+    sample_rate: u32,
+    /// Absolute index of `samples[0]`.
+    start: u64,
+    /// Absolute playback position. This is fractional, because catching up
+    /// plays at a rate other than 1.
+    cursor: f64,
+    paused: bool,
+    catch_up: CatchUp,
+    max_live_delay: f64,
+}
+
+impl TimeShiftBuffer {
+    pub fn new(sample_rate: u32, length: Duration, catch_up: CatchUp) -> Self {
+        let capacity = ((sample_rate as f64 * length.as_secs_f64()) as usize).max(1);
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            sample_rate,
+            start: 0,
+            cursor: 0.0,
+            paused: false,
+            catch_up,
+            max_live_delay: sample_rate as f64 * MAX_LIVE_DELAY.as_secs_f64(),
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+            self.start += 1;
+            // the sample under the cursor was dropped, so playback has to skip ahead
+            self.cursor = self.cursor.max(self.start as f64);
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Returns the next sample to play.
+    ///
+    /// Returns silence while paused, or if playback reached live audio.
+    pub fn next_sample(&mut self) -> f32 {
+        if self.paused {
+            return 0.0;
+        }
+
+        let delay = self.delay_samples();
+        if delay < 1.0 {
+            return 0.0;
+        }
+
+        let position = self.cursor - self.start as f64;
+        let index = position as usize;
+        let fraction = position.fract() as f32;
+        let sample = match self.samples.get(index + 1) {
+            Some(next) => self.samples[index] * (1.0 - fraction) + next * fraction,
+            None => self.samples[index],
+        };
+
+        let rate = if self.catch_up == CatchUp::Speedup && delay > self.max_live_delay {
+            CATCH_UP_RATE
+        }
+        else {
+            1.0
+        };
+        self.cursor += rate;
+
+        sample
+    }
+
+    pub fn apply(&mut self, command: TimeShiftCommand) {
+        match command {
+            TimeShiftCommand::TogglePause => {
+                self.paused = !self.paused;
+                if !self.paused && self.catch_up == CatchUp::Skip {
+                    self.skip_to_live();
+                }
+            }
+            TimeShiftCommand::Rewind { duration } => {
+                self.cursor = (self.cursor - self.sample_rate as f64 * duration.as_secs_f64())
+                    .max(self.start as f64);
+            }
+            TimeShiftCommand::SkipToLive => self.skip_to_live(),
+        }
+    }
+
+    pub fn skip_to_live(&mut self) {
+        self.cursor = self.live() as f64;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// How far playback is behind live audio.
+    pub fn delay(&self) -> Duration {
+        Duration::from_secs_f64(self.delay_samples() / self.sample_rate as f64)
+    }
+
+    fn live(&self) -> u64 {
+        self.start + self.samples.len() as u64
+    }
+
+    fn delay_samples(&self) -> f64 {
+        (self.live() as f64 - self.cursor).max(0.0)
+    }
+}
+
+/// Cloneable handle to control the time shift from the app.
+#[derive(Clone, Debug)]
+pub struct TimeShiftHandle {
+    pub(crate) buffer: Arc<Mutex<TimeShiftBuffer>>,
+}
+
+impl TimeShiftHandle {
+    pub fn apply(&self, command: TimeShiftCommand) {
+        self.buffer.lock().apply(command);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.buffer.lock().is_paused()
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.buffer.lock().delay()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        CatchUp,
+        TimeShiftBuffer,
+        TimeShiftCommand,
+    };
+
+    #[test]
+    fn it_pauses_and_rewinds() {
+        // 10 samples is well within the live delay at this sample rate
+        let mut buffer = TimeShiftBuffer::new(100, Duration::from_secs(10), CatchUp::Speedup);
+        for i in 0..10 {
+            buffer.push(i as f32);
+        }
+        for i in 0..5 {
+            assert_eq!(buffer.next_sample(), i as f32);
+        }
+
+        buffer.apply(TimeShiftCommand::TogglePause);
+        assert_eq!(buffer.next_sample(), 0.0);
+        assert_eq!(buffer.next_sample(), 0.0);
+        buffer.apply(TimeShiftCommand::TogglePause);
+        assert_eq!(buffer.next_sample(), 5.0);
+
+        buffer.apply(TimeShiftCommand::Rewind {
+            duration: Duration::from_millis(20),
+        });
+        assert_eq!(buffer.next_sample(), 4.0);
+    }
+
+    #[test]
+    fn it_catches_up() {
+        let mut buffer = TimeShiftBuffer::new(100, Duration::from_secs(10), CatchUp::Speedup);
+        for i in 0..100 {
+            buffer.push(i as f32);
+        }
+
+        // 1 second behind, so playback is faster than real time
+        for _ in 0..40 {
+            buffer.next_sample();
+        }
+        assert!(buffer.cursor > 41.0);
+
+        buffer.apply(TimeShiftCommand::SkipToLive);
+        assert_eq!(buffer.delay(), Duration::ZERO);
+        assert_eq!(buffer.next_sample(), 0.0);
+    }
+
+    #[test]
+    fn it_skips_to_live_on_resume() {
+        let mut buffer = TimeShiftBuffer::new(10, Duration::from_secs(10), CatchUp::Skip);
+        buffer.apply(TimeShiftCommand::TogglePause);
+        for i in 0..10 {
+            buffer.push(i as f32);
+        }
+        buffer.apply(TimeShiftCommand::TogglePause);
+        buffer.push(10.0);
+        assert_eq!(buffer.next_sample(), 10.0);
+    }
+
+    #[test]
+    fn it_drops_old_audio() {
+        let mut buffer = TimeShiftBuffer::new(10, Duration::from_secs(1), CatchUp::Speedup);
+        for i in 0..15 {
+            buffer.push(i as f32);
+        }
+        assert_eq!(buffer.next_sample(), 5.0);
+    }
+}
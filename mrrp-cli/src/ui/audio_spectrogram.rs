@@ -0,0 +1,71 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::Widget,
+};
+
+use crate::{
+    audio_spectrum::AudioSpectrum,
+    ui::{
+        theme::Theme,
+        waterfall::ColorMap,
+    },
+};
+
+/// Range of the color map below the strongest bin.
+const DYNAMIC_RANGE: f32 = 60.0;
+
+/// Small waterfall of the demodulated audio, newest line at the top.
+#[derive(Debug)]
+pub struct AudioSpectrogramWidget<'a> {
+    pub spectrum: &'a AudioSpectrum,
+    pub color_map: &'a ColorMap,
+    pub theme: &'a Theme,
+}
+
+impl<'a> Widget for AudioSpectrogramWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let block = self.theme.block().title(format!(
+            "Audio: 0 - {:.0} Hz",
+            self.spectrum.max_frequency()
+        ));
+        let inner = block.inner(area);
+        block.render(area, buf);
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+
+        let lines = self.spectrum.latest(inner.height.into());
+        let max = lines
+            .iter()
+            .flatten()
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max);
+        let min = max - DYNAMIC_RANGE;
+
+        for (y, line) in lines.iter().enumerate() {
+            let bins_per_cell = line.len() as f32 / inner.width as f32;
+
+            for x in 0..inner.width {
+                let start = (x as f32 * bins_per_cell) as usize;
+                let end = (((x + 1) as f32 * bins_per_cell) as usize)
+                    .max(start + 1)
+                    .min(line.len());
+                let Some(power) = line
+                    .get(start..end)
+                    .and_then(|bins| bins.iter().copied().reduce(f32::max))
+                else {
+                    continue;
+                };
+
+                let normalized = ((power - min) / DYNAMIC_RANGE).clamp(0.0, 1.0);
+                buf[(inner.x + x, inner.y + y as u16)]
+                    .set_char(' ')
+                    .set_bg(self.color_map.map(normalized));
+            }
+        }
+    }
+}
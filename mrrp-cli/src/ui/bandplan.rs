@@ -22,7 +22,6 @@ use palette::{
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
-    style::Color,
     widgets::Widget,
 };
 use serde::{
@@ -30,7 +29,10 @@ use serde::{
     Deserializer,
 };
 
-use crate::util::FrequencyBand;
+use crate::{
+    ui::theme::Theme,
+    util::FrequencyBand,
+};
 
 pub(crate) const BANDPLAN_INTERNATIONAL_BYTES: &'static [u8] = include_bytes!("bandplan.csv");
 
@@ -255,6 +257,7 @@ impl Band {
 pub struct BandplanWidget<'a> {
     pub bandplan: &'a Bandplan,
     pub view_frequency_band: FrequencyBand,
+    pub theme: &'a Theme,
 }
 
 impl<'a> Widget for BandplanWidget<'a> {
@@ -277,12 +280,9 @@ impl<'a> Widget for BandplanWidget<'a> {
                     buf[(area.x + x, area.y)].bg = band.color.color.into();
                 }
 
-                let text_color = if band.color.color.relative_luminance().luma > 0.5 {
-                    Color::Black
-                }
-                else {
-                    Color::White
-                };
+                let text_color = self
+                    .theme
+                    .bandplan_text(band.color.color.relative_luminance().luma);
 
                 buf.set_stringn(
                     area.x + cell_start,
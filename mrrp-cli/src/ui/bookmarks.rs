@@ -1,26 +1,59 @@
+//! Bookmarks
+//!
+//! Every bookmark is stored in its own JSON file in the bookmarks directory,
+//! named after its id. Several instances can share the directory: Files are
+//! written atomically, and [`Bookmarks::refresh`] picks up changes that other
+//! instances made, by comparing modification times.
+
 use std::{
+    collections::HashMap,
     fs::File,
-    io::{
-        BufReader,
-        BufWriter,
-    },
+    io::BufReader,
     path::{
         Path,
         PathBuf,
     },
+    time::SystemTime,
 };
 
+use chrono::Local;
+use mrrp::frequency::Frequency;
 use serde::{
     Deserialize,
     Serialize,
 };
+use uuid::Uuid;
 use walkdir::WalkDir;
 
 pub use self::import_sdrpp::import_sdrpp_bookmarks;
-use crate::Error;
+use crate::{
+    Error,
+    files::write_atomically,
+};
+
+/// Version of the bookmark file format.
+///
+/// - 0: No version and no id.
+/// - 1: Added version and id.
+pub const BOOKMARK_VERSION: u32 = 1;
+
+/// Namespace for the ids of bookmarks that were saved without one. The id is
+/// derived from the file name, so that instances migrating the same file
+/// concurrently agree on it.
+const LEGACY_ID_NAMESPACE: Uuid = Uuid::from_u128(0x6d72_7270_626f_6f6b_6d61_726b_7300_0000);
+
+/// Subdirectory of the bookmarks directory, that backups are written to.
+const BACKUP_DIRECTORY: &str = "backups";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Bookmark {
+    #[serde(default)]
+    version: u32,
+
+    /// Unique id, by which bookmarks are merged.
+    #[serde(default)]
+    pub id: Uuid,
+
     pub name: String,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -29,30 +62,63 @@ pub struct Bookmark {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tags: Vec<String>,
 
-    pub frequency: u32,
+    pub frequency: Frequency,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub bandwidth: Option<u32>,
+    pub bandwidth: Option<Frequency>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mode: Option<String>,
 }
 
 impl Bookmark {
+    /// Creates a bookmark with a new id.
+    pub fn new(name: impl Into<String>, frequency: Frequency) -> Self {
+        Self {
+            version: BOOKMARK_VERSION,
+            id: Uuid::new_v4(),
+            name: name.into(),
+            description: None,
+            tags: vec![],
+            frequency,
+            bandwidth: None,
+            mode: None,
+        }
+    }
+
     pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
         Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
     }
 
     pub fn to_path(&self, path: impl AsRef<Path>) -> Result<(), Error> {
-        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
-        Ok(())
+        write_atomically(path, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, self)?)
+        })
+    }
+}
+
+/// Modification time and size of a bookmark file when it was last read or
+/// written. If either changed, another instance wrote the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileStamp {
+    fn from_path(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
     }
 }
 
 #[derive(Debug)]
 struct Item {
-    #[allow(unused)]
     path: PathBuf,
+    stamp: Option<FileStamp>,
     bookmark: Bookmark,
 }
 
@@ -63,36 +129,129 @@ pub struct Bookmarks {
 }
 
 impl Bookmarks {
+    /// Loads all bookmarks in the directory at `path`.
+    ///
+    /// Bookmarks saved by older versions are migrated, after a backup of them
+    /// was written.
     pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
-        let path = path.as_ref();
+        let mut this = Self {
+            root: path.as_ref().to_owned(),
+            items: vec![],
+        };
+        this.refresh()?;
+
+        for item in &mut this.items {
+            if item.bookmark.version < BOOKMARK_VERSION {
+                migrate(&this.root, item)?;
+            }
+        }
+
+        Ok(this)
+    }
+
+    /// Merges changes that other instances made to the bookmarks directory.
+    ///
+    /// Files that are new, or were modified since they were last read, are
+    /// (re)loaded. Bookmarks whose files were removed are dropped. If several
+    /// files contain the same id, the most recently modified one is used.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        let mut known = std::mem::take(&mut self.items)
+            .into_iter()
+            .map(|item| (item.path.clone(), item))
+            .collect::<HashMap<_, _>>();
 
         let mut items = vec![];
-        for result in WalkDir::new(path) {
+        let walk = WalkDir::new(&self.root)
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || entry.file_name() != BACKUP_DIRECTORY);
+        for result in walk {
             let dir_entry = result?;
+            let path = dir_entry.path();
+            if !dir_entry.file_type().is_file() || !is_bookmark_file(path) {
+                continue;
+            }
 
-            match Bookmark::from_path(dir_entry.path()) {
+            let stamp = FileStamp::from_path(path);
+            if let Some(item) = known.remove(path)
+                && stamp.is_some()
+                && item.stamp == stamp
+            {
+                items.push(item);
+                continue;
+            }
+
+            match Bookmark::from_path(path) {
                 Ok(bookmark) => {
                     items.push(Item {
-                        path: dir_entry.path().to_owned(),
+                        path: path.to_owned(),
+                        stamp,
                         bookmark,
-                    })
+                    });
                 }
-                Err(_error) => {
-                    // just skip
+                Err(error) => {
+                    tracing::warn!(path = %path.display(), ?error, "Skipping invalid bookmark");
                 }
             }
         }
 
-        Ok(Self {
-            root: path.to_owned(),
-            items,
-        })
+        // merge by id. legacy bookmarks don't have one yet.
+        let mut by_id = HashMap::<Uuid, usize>::new();
+        let mut merged = Vec::<Item>::with_capacity(items.len());
+        for item in items {
+            let id = item.bookmark.id;
+            if id.is_nil() {
+                merged.push(item);
+            }
+            else if let Some(&index) = by_id.get(&id) {
+                let newer = item.stamp.map(|stamp| stamp.modified)
+                    > merged[index].stamp.map(|stamp| stamp.modified);
+                if newer {
+                    merged[index] = item;
+                }
+            }
+            else {
+                by_id.insert(id, merged.len());
+                merged.push(item);
+            }
+        }
+
+        self.items = merged;
+        Ok(())
     }
 
-    pub fn add_and_save_bookmark(&mut self, bookmark: Bookmark) -> Result<(), Error> {
-        let path = self.root.join(format!("{}.json", bookmark.name));
+    /// Saves a bookmark, replacing the one with the same id, if any.
+    ///
+    /// Changes by other instances are merged first, so that their bookmarks
+    /// aren't lost. If another instance changed the same bookmark, this
+    /// overwrites it.
+    pub fn add_and_save_bookmark(&mut self, mut bookmark: Bookmark) -> Result<(), Error> {
+        self.refresh()?;
+
+        if bookmark.id.is_nil() {
+            bookmark.id = Uuid::new_v4();
+        }
+        bookmark.version = BOOKMARK_VERSION;
+
+        let index = self
+            .items
+            .iter()
+            .position(|item| item.bookmark.id == bookmark.id);
+        let path = match index {
+            Some(index) => self.items[index].path.clone(),
+            None => self.root.join(format!("{}.json", bookmark.id)),
+        };
+
         bookmark.to_path(&path)?;
-        self.items.push(Item { path, bookmark });
+        let item = Item {
+            stamp: FileStamp::from_path(&path),
+            path,
+            bookmark,
+        };
+        match index {
+            Some(index) => self.items[index] = item,
+            None => self.items.push(item),
+        }
+
         Ok(())
     }
 
@@ -101,6 +260,50 @@ impl Bookmarks {
     }
 }
 
+fn is_bookmark_file(path: &Path) -> bool {
+    // this skips temporary files from atomic writes
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+        && !path
+            .file_name()
+            .is_some_and(|file_name| file_name.as_encoded_bytes().starts_with(b"."))
+}
+
+/// Migrates a bookmark to the current version, after writing a timestamped
+/// backup of its file.
+fn migrate(root: &Path, item: &mut Item) -> Result<(), Error> {
+    let file_name = item
+        .path
+        .file_name()
+        .expect("bug: bookmark path without file name")
+        .to_string_lossy()
+        .into_owned();
+
+    let backup_directory = root.join(BACKUP_DIRECTORY);
+    std::fs::create_dir_all(&backup_directory)?;
+    let backup_path = backup_directory.join(format!(
+        "{file_name}.v{}.{}.bak",
+        item.bookmark.version,
+        Local::now().format("%Y%m%dT%H%M%S")
+    ));
+    std::fs::copy(&item.path, &backup_path)?;
+    tracing::info!(
+        path = %item.path.display(),
+        backup = %backup_path.display(),
+        from_version = item.bookmark.version,
+        "Migrating bookmark"
+    );
+
+    if item.bookmark.id.is_nil() {
+        item.bookmark.id = Uuid::new_v5(&LEGACY_ID_NAMESPACE, file_name.as_bytes());
+    }
+    item.bookmark.version = BOOKMARK_VERSION;
+    item.bookmark.to_path(&item.path)?;
+    item.stamp = FileStamp::from_path(&item.path);
+
+    Ok(())
+}
+
 mod import_sdrpp {
     use std::{
         collections::HashMap,
@@ -109,6 +312,7 @@ mod import_sdrpp {
         path::Path,
     };
 
+    use mrrp::frequency::Frequency;
     use serde::Deserialize;
 
     use crate::Error;
@@ -141,12 +345,9 @@ mod import_sdrpp {
         for (_, list) in config.lists {
             for (name, bookmark) in list.bookmarks {
                 bookmarks.push(super::Bookmark {
-                    name,
-                    description: None,
-                    tags: vec![],
-                    frequency: bookmark.frequency as u32,
-                    bandwidth: Some(bookmark.bandwidth as u32),
+                    bandwidth: Some(Frequency::from_hz_f64(bookmark.bandwidth.into())),
                     mode: convert_mode(bookmark.mode),
+                    ..super::Bookmark::new(name, Frequency::from_hz_f64(bookmark.frequency.into()))
                 });
             }
         }
@@ -161,3 +362,117 @@ mod import_sdrpp {
         Some(mode_name.to_owned())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use mrrp::frequency::Frequency;
+
+    use super::{
+        BACKUP_DIRECTORY,
+        BOOKMARK_VERSION,
+        Bookmark,
+        Bookmarks,
+    };
+
+    fn test_directory(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("mrrp-bookmarks-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn names(bookmarks: &Bookmarks) -> Vec<&str> {
+        let mut names = bookmarks
+            .iter()
+            .map(|bookmark| bookmark.name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn it_loads_bookmarks_with_plain_hz() {
+        let bookmark: Bookmark =
+            serde_json::from_str(r#"{"name": "FT8 40m", "frequency": 7074000, "bandwidth": 3000}"#)
+                .unwrap();
+        assert_eq!(bookmark.frequency, Frequency::from_hz(7_074_000));
+        assert_eq!(bookmark.bandwidth, Some(Frequency::from_khz(3)));
+
+        // and writes them the same way
+        let json = serde_json::to_value(&bookmark).unwrap();
+        assert_eq!(json["frequency"], 7074000);
+    }
+
+    #[test]
+    fn it_merges_bookmarks_of_concurrent_instances() {
+        let path = test_directory("concurrent");
+        let mut a = Bookmarks::open(&path).unwrap();
+        let mut b = Bookmarks::open(&path).unwrap();
+
+        // same name, but they must not overwrite each other
+        a.add_and_save_bookmark(Bookmark::new("FT8", Frequency::from_hz(7_074_000)))
+            .unwrap();
+        b.add_and_save_bookmark(Bookmark::new("FT8", Frequency::from_hz(14_074_000)))
+            .unwrap();
+        assert_eq!(names(&b), ["FT8", "FT8"]);
+
+        a.refresh().unwrap();
+        assert_eq!(names(&a), ["FT8", "FT8"]);
+
+        // b renames a's bookmark
+        let mut renamed = a
+            .iter()
+            .find(|bookmark| bookmark.frequency == Frequency::from_hz(7_074_000))
+            .unwrap()
+            .clone();
+        renamed.name = "FT8 40m".to_owned();
+        b.add_and_save_bookmark(renamed).unwrap();
+        a.refresh().unwrap();
+        assert_eq!(names(&a), ["FT8", "FT8 40m"]);
+
+        // no temporary files are left
+        assert_eq!(std::fs::read_dir(&path).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn it_backs_up_and_migrates_old_bookmarks() {
+        let path = test_directory("migrate");
+        std::fs::write(
+            path.join("FT8 40m.json"),
+            r#"{"name": "FT8 40m", "frequency": 7074000}"#,
+        )
+        .unwrap();
+
+        let bookmarks = Bookmarks::open(&path).unwrap();
+        let bookmark = bookmarks.iter().next().unwrap().clone();
+        assert_eq!(bookmark.version, BOOKMARK_VERSION);
+        assert!(!bookmark.id.is_nil());
+
+        let backups = std::fs::read_dir(path.join(BACKUP_DIRECTORY))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(backups[0].path()).unwrap(),
+            r#"{"name": "FT8 40m", "frequency": 7074000}"#
+        );
+
+        // migrated in place, and not again
+        let bookmarks = Bookmarks::open(&path).unwrap();
+        assert_eq!(bookmarks.iter().next().unwrap().id, bookmark.id);
+        assert_eq!(
+            std::fs::read_dir(path.join(BACKUP_DIRECTORY))
+                .unwrap()
+                .count(),
+            1
+        );
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -1,17 +1,16 @@
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
-    style::Color,
-    widgets::{
-        Block,
-        Widget,
-    },
+    widgets::Widget,
 };
 
+use crate::ui::theme::Theme;
+
 #[derive(Debug)]
 pub struct FrequencyDial<'a> {
     pub frequency: u32,
     pub title: &'a str,
+    pub theme: &'a Theme,
 }
 
 impl<'a> Widget for FrequencyDial<'a> {
@@ -19,11 +18,11 @@ impl<'a> Widget for FrequencyDial<'a> {
     where
         Self: Sized,
     {
-        let block = Block::bordered().title(self.title);
+        let block = self.theme.block().title(self.title);
         let inner = block.inner(area);
         block.render(area, buf);
 
         let text = format!("{:>width$}", self.frequency, width = inner.width.into());
-        buf.set_stringn(inner.x, inner.y, &text, inner.width.into(), Color::White);
+        buf.set_stringn(inner.x, inner.y, &text, inner.width.into(), self.theme.text);
     }
 }
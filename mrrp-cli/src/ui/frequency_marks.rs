@@ -1,22 +1,75 @@
+use std::ops::RangeBounds;
+
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
-    style::Color,
     widgets::Widget,
 };
 
-use crate::util::{
-    FrequencyBand,
-    format_frequency,
+use crate::{
+    ui::{
+        markers::Markers,
+        theme::Theme,
+    },
+    util::{
+        FrequencyBand,
+        format_frequency,
+        nice_interval,
+    },
 };
 
+const MAJOR_TICK: char = '|';
+const MINOR_TICK: char = '\u{00b7}';
+
+/// Minimum number of cells between minor ticks.
+const MIN_MINOR_TICK_SPACING: f32 = 2.0;
+
 // todo: more precise name
 #[derive(Clone, Copy, Debug)]
-pub struct FrequencyMarks {
+pub struct FrequencyMarks<'a> {
     pub view_frequency_band: FrequencyBand,
+    pub markers: &'a Markers,
+    pub theme: &'a Theme,
+}
+
+impl<'a> FrequencyMarks<'a> {
+    fn label(&self, frequency: u32, interval: u32) -> String {
+        format_frequency(frequency)
+            .with_unit_of(self.view_frequency_band.end)
+            .with_step(interval)
+            .to_string()
+    }
+
+    /// Picks the major tick interval, such that the labels don't overlap.
+    fn major_interval(&self, width: u16) -> u32 {
+        let bandwidth = self.view_frequency_band.bandwidth();
+
+        // the label width depends on the interval (number of decimals), so we
+        // start with a guess and widen the interval until the labels fit.
+        let mut interval = nice_interval(bandwidth, u32::from(width) / 8);
+        loop {
+            let label_width = self.label(self.view_frequency_band.end, interval).len() + 2;
+            let max_labels = (usize::from(width) / label_width).max(1);
+            let fitting = nice_interval(bandwidth, max_labels as u32);
+            if fitting <= interval {
+                return interval;
+            }
+            interval = fitting;
+        }
+    }
+}
+
+/// Splits a major tick interval into minor intervals.
+fn minor_interval(major: u32) -> Option<u32> {
+    let mut leading = major;
+    while leading >= 10 && leading.is_multiple_of(10) {
+        leading /= 10;
+    }
+    let divisions = if leading == 2 { 4 } else { 5 };
+    major.is_multiple_of(divisions).then(|| major / divisions)
 }
 
-impl Widget for FrequencyMarks {
+impl<'a> Widget for FrequencyMarks<'a> {
     fn render(self, area: Rect, buf: &mut Buffer)
     where
         Self: Sized,
@@ -25,37 +78,71 @@ impl Widget for FrequencyMarks {
             buf[(x + area.x, area.y)].reset();
         }
 
-        let frequency_start = format_frequency(self.view_frequency_band.start)
-            .with_band(self.view_frequency_band)
-            .to_string();
-        let frequency_center = format_frequency(self.view_frequency_band.center())
-            .with_band(self.view_frequency_band)
-            .to_string();
-        let frequency_center_pos =
-            area.x + (area.width - u16::try_from(frequency_center.len()).unwrap()) / 2;
-        let frequency_end = format_frequency(self.view_frequency_band.end)
-            .with_band(self.view_frequency_band)
-            .to_string();
-        let frequency_end_pos = area.x + (area.width - u16::try_from(frequency_end.len()).unwrap());
-
-        if usize::from(area.width) > frequency_center.len() {
-            buf.set_string(
-                area.x + frequency_center_pos,
-                area.y,
-                &frequency_center,
-                Color::White,
-            );
+        let band = self.view_frequency_band;
+        if area.width == 0 || area.height == 0 || band.bandwidth() == 0 {
+            return;
         }
-        if usize::from(area.width)
-            > frequency_center.len() + frequency_start.len() + frequency_end.len() + 10
+
+        let cells_per_hz = area.width as f32 / band.bandwidth() as f32;
+        let to_cell = |frequency: u32| {
+            (((frequency - band.start) as f32 * cells_per_hz) as u16).min(area.width - 1)
+        };
+        let ticks = |interval: u32| {
+            let first = band.start.div_ceil(interval).saturating_mul(interval);
+            (first..band.end).step_by(interval as usize)
+        };
+
+        let major = self.major_interval(area.width);
+
+        if let Some(minor) = minor_interval(major)
+            .filter(|minor| *minor as f32 * cells_per_hz >= MIN_MINOR_TICK_SPACING)
         {
-            buf.set_string(area.x, area.y, &frequency_start, Color::White);
-            buf.set_string(
-                area.x + frequency_end_pos,
-                area.y,
-                &frequency_end,
-                Color::White,
-            );
+            for frequency in ticks(minor) {
+                let cell = &mut buf[(area.x + to_cell(frequency), area.y)];
+                cell.set_char(MINOR_TICK);
+                cell.fg = self.theme.dim;
+            }
+        }
+
+        // end of the last label, so that labels never overlap.
+        let mut free_from = 0;
+        for frequency in ticks(major) {
+            let x = to_cell(frequency);
+            let label = self.label(frequency, major);
+            let label_end = x + 1 + u16::try_from(label.len()).unwrap_or(u16::MAX);
+
+            let cell = &mut buf[(area.x + x, area.y)];
+            cell.set_char(MAJOR_TICK);
+            cell.fg = self.theme.tick;
+
+            if x >= free_from && label_end <= area.width {
+                buf.set_string(area.x + x + 1, area.y, &label, self.theme.text);
+                free_from = label_end + 1;
+            }
         }
+
+        // markers are drawn on top of the labels, since their position matters more.
+        for (id, marker) in self.markers.iter() {
+            if band.contains(&marker.frequency) {
+                let x = to_cell(marker.frequency);
+                let cell = &mut buf[(area.x + x, area.y)];
+                cell.set_char(id.as_char());
+                cell.fg = self.theme.marker;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::minor_interval;
+
+    #[test]
+    fn minor_intervals() {
+        assert_eq!(minor_interval(1_000), Some(200));
+        assert_eq!(minor_interval(200), Some(50));
+        assert_eq!(minor_interval(5_000), Some(1_000));
+        assert_eq!(minor_interval(2), None);
+        assert_eq!(minor_interval(1), None);
     }
 }
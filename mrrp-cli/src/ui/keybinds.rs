@@ -32,6 +32,26 @@ pub enum Action {
     MoveRightBig,
     CenterView,
     TuneToView,
+    ClearMarkers,
+    ExportMarkers,
+    ToggleTimeAxis,
+    ToggleBaselineSubtraction,
+    CycleAveraging,
+    ToggleSignalMarkers,
+    ToggleOccupancy,
+    ToggleScope,
+    CycleScopeTrigger,
+    ToggleAudioSpectrogram,
+    ToggleStations,
+    CycleTheme,
+    NextSignal,
+    PreviousSignal,
+    ToggleSpectrumInversion,
+    CycleFftOverlap,
+    CycleFftWindow,
+    ToggleAudioPause,
+    RewindAudio,
+    SkipAudioToLive,
     Test,
 }
 
@@ -77,6 +97,26 @@ impl Default for Keybinds {
                 (Keybind::from(KeyCode::Right).with_modifiers(KeyModifiers::SHIFT), Action::MoveRightBig),
                 ('c'.into(), Action::CenterView),
                 ('t'.into(), Action::TuneToView),
+                ('x'.into(), Action::ClearMarkers),
+                ('e'.into(), Action::ExportMarkers),
+                (Keybind::from('T').with_modifiers(KeyModifiers::SHIFT), Action::ToggleTimeAxis),
+                ('b'.into(), Action::ToggleBaselineSubtraction),
+                ('a'.into(), Action::CycleAveraging),
+                ('m'.into(), Action::ToggleSignalMarkers),
+                ('o'.into(), Action::ToggleOccupancy),
+                ('s'.into(), Action::ToggleScope),
+                (Keybind::from('S').with_modifiers(KeyModifiers::SHIFT), Action::CycleScopeTrigger),
+                ('w'.into(), Action::ToggleAudioSpectrogram),
+                ('l'.into(), Action::ToggleStations),
+                (Keybind::from('C').with_modifiers(KeyModifiers::SHIFT), Action::CycleTheme),
+                ('n'.into(), Action::NextSignal),
+                ('p'.into(), Action::PreviousSignal),
+                ('i'.into(), Action::ToggleSpectrumInversion),
+                (Keybind::from('O').with_modifiers(KeyModifiers::SHIFT), Action::CycleFftOverlap),
+                (Keybind::from('W').with_modifiers(KeyModifiers::SHIFT), Action::CycleFftWindow),
+                (' '.into(), Action::ToggleAudioPause),
+                (','.into(), Action::RewindAudio),
+                ('.'.into(), Action::SkipAudioToLive),
                 (KeyCode::F(5).into(), Action::Test),
             ]
                 .into_iter()
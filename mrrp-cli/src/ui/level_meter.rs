@@ -0,0 +1,219 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use mrrp::io::combinators::{
+    Stats,
+    StatsHandle,
+};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::Widget,
+};
+
+use crate::ui::theme::Theme;
+
+/// Lowest level shown by the meter.
+const FLOOR_DBFS: f32 = -60.0;
+
+/// How fast the peak falls back, in dB per second.
+const PEAK_DECAY: f32 = 20.0;
+
+/// How long the clipping indicator stays lit after the audio clipped.
+const CLIP_HOLD: Duration = Duration::from_secs(2);
+
+/// Width of the clipping indicator, including the space before it.
+const CLIP_WIDTH: u16 = 5;
+
+/// Level meter for the demodulated audio.
+///
+/// Shows the RMS and peak level since the last update. The peak falls back
+/// slowly, so short peaks can be read.
+#[derive(Debug)]
+pub struct LevelMeter {
+    handle: StatsHandle<f32>,
+    last_update: Instant,
+    rms_dbfs: f32,
+    peak_dbfs: f32,
+    clip_hold: Duration,
+}
+
+impl LevelMeter {
+    pub fn new(handle: StatsHandle<f32>) -> Self {
+        Self {
+            handle,
+            last_update: Instant::now(),
+            rms_dbfs: FLOOR_DBFS,
+            peak_dbfs: FLOOR_DBFS,
+            clip_hold: Duration::ZERO,
+        }
+    }
+
+    /// Takes the statistics of the audio since the last update.
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last_update;
+        self.last_update = now;
+        let stats = self.handle.take();
+        self.apply(stats, elapsed);
+    }
+
+    fn apply(&mut self, stats: Stats<f32>, elapsed: Duration) {
+        // without audio both levels are at -inf
+        self.rms_dbfs = stats.rms_dbfs().max(FLOOR_DBFS);
+        self.peak_dbfs = stats
+            .peak_dbfs()
+            .max(self.peak_dbfs - PEAK_DECAY * elapsed.as_secs_f32())
+            .max(FLOOR_DBFS);
+
+        self.clip_hold = if stats.peak >= 1.0 {
+            CLIP_HOLD
+        }
+        else {
+            self.clip_hold.saturating_sub(elapsed)
+        };
+    }
+
+    #[inline]
+    pub fn rms_dbfs(&self) -> f32 {
+        self.rms_dbfs
+    }
+
+    #[inline]
+    pub fn peak_dbfs(&self) -> f32 {
+        self.peak_dbfs
+    }
+
+    #[inline]
+    pub fn is_clipping(&self) -> bool {
+        !self.clip_hold.is_zero()
+    }
+}
+
+#[derive(Debug)]
+pub struct LevelMeterWidget<'a> {
+    pub meter: &'a LevelMeter,
+    pub theme: &'a Theme,
+}
+
+impl<'a> Widget for LevelMeterWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let block = self
+            .theme
+            .block()
+            .title(format!("Audio {:.0} dB", self.meter.peak_dbfs));
+        let inner = block.inner(area);
+        block.render(area, buf);
+        if inner.height == 0 || inner.width <= CLIP_WIDTH {
+            return;
+        }
+
+        let bar_width = inner.width - CLIP_WIDTH;
+        let cell = |dbfs: f32| {
+            let x = (dbfs - FLOOR_DBFS) / -FLOOR_DBFS * f32::from(bar_width);
+            (x.round() as u16).min(bar_width)
+        };
+        let rms = cell(self.meter.rms_dbfs);
+        let peak = cell(self.meter.peak_dbfs).saturating_sub(1);
+
+        for x in 0..bar_width {
+            let dbfs = FLOOR_DBFS * (1.0 - f32::from(x) / f32::from(bar_width));
+            let color = if dbfs >= -3.0 {
+                self.theme.alert
+            }
+            else if dbfs >= -12.0 {
+                self.theme.warning
+            }
+            else {
+                self.theme.good
+            };
+            let symbol = if x < rms {
+                "█"
+            }
+            else if x == peak && self.meter.peak_dbfs > FLOOR_DBFS {
+                "▌"
+            }
+            else {
+                "·"
+            };
+            buf[(inner.x + x, inner.y)]
+                .set_symbol(symbol)
+                .set_fg(if symbol == "·" {
+                    self.theme.dim
+                }
+                else {
+                    color
+                });
+        }
+
+        buf.set_stringn(
+            inner.x + bar_width + 1,
+            inner.y,
+            "CLIP",
+            4,
+            if self.meter.is_clipping() {
+                self.theme.alert
+            }
+            else {
+                self.theme.dim
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use mrrp::io::combinators::{
+        Stats,
+        StatsInspector,
+    };
+
+    use super::{
+        FLOOR_DBFS,
+        LevelMeter,
+    };
+
+    fn stats(rms: f32, peak: f32) -> Stats<f32> {
+        Stats {
+            num_samples: 100,
+            dc_offset: 0.0,
+            rms,
+            peak,
+        }
+    }
+
+    #[test]
+    fn it_decays_the_peak_and_holds_clipping() {
+        let mut meter = LevelMeter::new(StatsInspector::new().handle());
+        let step = Duration::from_millis(100);
+
+        meter.apply(stats(0.1, 1.0), step);
+        assert!((meter.rms_dbfs() + 20.0).abs() < 1e-4);
+        assert!(meter.peak_dbfs().abs() < 1e-4);
+        assert!(meter.is_clipping());
+
+        // the peak falls back by 2 dB per update, the rms follows immediately
+        meter.apply(stats(0.01, 0.01), step);
+        assert!((meter.rms_dbfs() + 40.0).abs() < 1e-4);
+        assert!((meter.peak_dbfs() + 2.0).abs() < 1e-4);
+        assert!(meter.is_clipping());
+
+        // a louder peak is taken over
+        meter.apply(stats(0.01, 0.9), step);
+        assert!((meter.peak_dbfs() + 0.915).abs() < 1e-3);
+
+        for _ in 0..20 {
+            meter.apply(stats(0.0, 0.0), step);
+        }
+        assert!(!meter.is_clipping());
+        assert_eq!(meter.rms_dbfs(), FLOOR_DBFS);
+        assert!(meter.peak_dbfs() < -40.0);
+    }
+}
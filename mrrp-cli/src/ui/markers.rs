@@ -0,0 +1,226 @@
+use std::fmt::Display;
+
+use chrono::{
+    DateTime,
+    Local,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::util::format_frequency;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub frequency: u32,
+
+    /// Power at the marker in dBFS, if there was any data where the marker was
+    /// placed.
+    pub power: Option<f32>,
+}
+
+impl Display for Marker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.3}", format_frequency(self.frequency))?;
+        if let Some(power) = self.power {
+            write!(f, " {power:.1} dBFS")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkerId {
+    A,
+    B,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Markers {
+    pub a: Option<Marker>,
+    pub b: Option<Marker>,
+}
+
+impl Markers {
+    pub fn set(&mut self, id: MarkerId, marker: Marker) {
+        match id {
+            MarkerId::A => self.a = Some(marker),
+            MarkerId::B => self.b = Some(marker),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.a = None;
+        self.b = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.a.is_none() && self.b.is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MarkerId, Marker)> {
+        [(MarkerId::A, self.a), (MarkerId::B, self.b)]
+            .into_iter()
+            .filter_map(|(id, marker)| marker.map(|marker| (id, marker)))
+    }
+
+    pub fn delta(&self) -> Option<MarkerDelta> {
+        let a = self.a?;
+        let b = self.b?;
+        Some(MarkerDelta {
+            frequency: i64::from(b.frequency) - i64::from(a.frequency),
+            power: a.power.zip(b.power).map(|(a, b)| b - a),
+        })
+    }
+
+    pub fn measurement(&self) -> Option<MarkerMeasurement> {
+        let a = self.a?;
+        let b = self.b?;
+        let delta = self.delta()?;
+        Some(MarkerMeasurement {
+            timestamp: Local::now(),
+            frequency_a: a.frequency,
+            power_a: a.power,
+            frequency_b: b.frequency,
+            power_b: b.power,
+            delta_frequency: delta.frequency,
+            delta_power: delta.power,
+        })
+    }
+}
+
+impl MarkerId {
+    pub fn as_char(&self) -> char {
+        match self {
+            MarkerId::A => 'A',
+            MarkerId::B => 'B',
+        }
+    }
+}
+
+/// Difference between marker B and marker A.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MarkerDelta {
+    pub frequency: i64,
+    pub power: Option<f32>,
+}
+
+impl Display for MarkerDelta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.frequency < 0 { "-" } else { "+" };
+        let frequency = u32::try_from(self.frequency.unsigned_abs()).unwrap_or(u32::MAX);
+        write!(f, "{sign}{:.3}", format_frequency(frequency))?;
+        if let Some(power) = self.power {
+            write!(f, " {power:+.1} dB")?;
+        }
+        Ok(())
+    }
+}
+
+/// A single exported marker measurement. This is written as one row to the
+/// marker CSV file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MarkerMeasurement {
+    pub timestamp: DateTime<Local>,
+    pub frequency_a: u32,
+    pub power_a: Option<f32>,
+    pub frequency_b: u32,
+    pub power_b: Option<f32>,
+    pub delta_frequency: i64,
+    pub delta_power: Option<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Marker,
+        MarkerDelta,
+        MarkerId,
+        Markers,
+    };
+
+    const A: Marker = Marker {
+        frequency: 100_000_000,
+        power: Some(-30.0),
+    };
+
+    const B: Marker = Marker {
+        frequency: 99_975_000,
+        power: Some(-42.5),
+    };
+
+    #[test]
+    fn it_has_no_delta_with_a_single_marker() {
+        let mut markers = Markers::default();
+        markers.set(MarkerId::A, A);
+        assert_eq!(markers.delta(), None);
+        assert!(markers.measurement().is_none());
+
+        let mut markers = Markers::default();
+        markers.set(MarkerId::B, B);
+        assert_eq!(markers.delta(), None);
+        assert!(markers.measurement().is_none());
+    }
+
+    #[test]
+    fn it_measures_from_a_to_b() {
+        let mut markers = Markers::default();
+        markers.set(MarkerId::A, A);
+        markers.set(MarkerId::B, B);
+        assert_eq!(
+            markers.delta(),
+            Some(MarkerDelta {
+                frequency: -25_000,
+                power: Some(-12.5),
+            })
+        );
+
+        let measurement = markers.measurement().unwrap();
+        assert_eq!(measurement.frequency_a, A.frequency);
+        assert_eq!(measurement.power_a, A.power);
+        assert_eq!(measurement.frequency_b, B.frequency);
+        assert_eq!(measurement.power_b, B.power);
+        assert_eq!(measurement.delta_frequency, -25_000);
+        assert_eq!(measurement.delta_power, Some(-12.5));
+    }
+
+    #[test]
+    fn it_has_no_delta_power_without_power_at_both_markers() {
+        let mut markers = Markers::default();
+        markers.set(MarkerId::A, Marker { power: None, ..A });
+        markers.set(MarkerId::B, B);
+        assert_eq!(
+            markers.delta(),
+            Some(MarkerDelta {
+                frequency: -25_000,
+                power: None,
+            })
+        );
+        assert_eq!(markers.measurement().unwrap().delta_power, None);
+    }
+
+    #[test]
+    fn it_exports_a_measurement_as_a_csv_row() {
+        let mut markers = Markers::default();
+        markers.set(MarkerId::A, A);
+        markers.set(MarkerId::B, Marker { power: None, ..B });
+        let measurement = markers.measurement().unwrap();
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(&measurement).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "timestamp,frequency_a,power_a,frequency_b,power_b,delta_frequency,delta_power"
+        );
+        let row = lines.next().unwrap();
+        assert!(
+            row.ends_with(",100000000,-30.0,99975000,,-25000,"),
+            "unexpected row: {row}"
+        );
+        assert_eq!(lines.next(), None);
+    }
+}
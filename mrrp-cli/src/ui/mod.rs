@@ -1,14 +1,30 @@
+pub mod audio_spectrogram;
 pub mod bandplan;
 pub mod bookmarks;
 pub mod frequency_dial;
 pub mod frequency_marks;
 pub mod keybinds;
+pub mod level_meter;
+pub mod markers;
+pub mod occupancy;
+pub mod scope;
+pub mod signal_markers;
+pub mod stations;
+pub mod theme;
 pub mod waterfall;
 
+use std::time::Duration;
+
+use chrono::{
+    DateTime,
+    Local,
+};
 use crossterm::event::{
     Event as TerminalEvent,
+    MouseButton,
     MouseEventKind,
 };
+use mrrp::io::combinators::StatsHandle;
 use num_complex::Complex;
 use ratatui::{
     buffer::Buffer,
@@ -27,7 +43,11 @@ use serde::{
 
 use crate::{
     app::AppProxy,
+    audio_spectrum::AudioSpectrum,
+    files::StationDatabase,
+    time_shift::TimeShiftCommand,
     ui::{
+        audio_spectrogram::AudioSpectrogramWidget,
         bandplan::{
             Bandplan,
             BandplanWidget,
@@ -38,6 +58,29 @@ use crate::{
             Action,
             Keybinds,
         },
+        level_meter::{
+            LevelMeter,
+            LevelMeterWidget,
+        },
+        markers::{
+            Marker,
+            MarkerId,
+            Markers,
+        },
+        occupancy::{
+            BandOccupancy,
+            OccupancyWidget,
+        },
+        scope::{
+            Scope,
+            ScopeWidget,
+        },
+        signal_markers::{
+            SignalMarkers,
+            SignalMarkersWidget,
+        },
+        stations::StationsWidget,
+        theme::Themes,
         waterfall::{
             ColorMap,
             WaterfallState,
@@ -47,11 +90,45 @@ use crate::{
     util::FrequencyBand,
 };
 
+/// How far [`Action::RewindAudio`] rewinds.
+const AUDIO_REWIND_STEP: Duration = Duration::from_secs(5);
+
+/// Width of the audio level meter next to the tuner dial, including its
+/// border.
+const LEVEL_METER_WIDTH: u16 = 40;
+
+/// Height of the scope below the waterfall, including its border.
+const SCOPE_HEIGHT: u16 = 12;
+
+/// Height of the audio spectrogram below the waterfall, including its
+/// border.
+const AUDIO_SPECTROGRAM_HEIGHT: u16 = 10;
+
+/// Height of the station lookup panel below the waterfall, including its
+/// border. The frequency axis is shared with the waterfall, so it's not put
+/// next to it.
+const STATIONS_HEIGHT: u16 = 8;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UiState {
     view_frequency_band: FrequencyBand,
     zoom_level: u32,
     waterfall_state: WaterfallState,
+    #[serde(default)]
+    show_signal_markers: bool,
+    #[serde(default)]
+    markers: Markers,
+    #[serde(default)]
+    show_occupancy: bool,
+    #[serde(default)]
+    show_scope: bool,
+    #[serde(default)]
+    show_audio_spectrogram: bool,
+    #[serde(default)]
+    show_stations: bool,
+    /// Name of the selected theme.
+    #[serde(default)]
+    theme: Option<String>,
 }
 
 impl UiState {
@@ -60,9 +137,20 @@ impl UiState {
             view_frequency_band,
             zoom_level: 0,
             waterfall_state: WaterfallState::default(),
+            show_signal_markers: false,
+            markers: Markers::default(),
+            show_occupancy: false,
+            show_scope: false,
+            show_audio_spectrogram: false,
+            show_stations: false,
+            theme: None,
         }
     }
 
+    pub fn theme(&self) -> Option<&str> {
+        self.theme.as_deref()
+    }
+
     fn zoom_view(&mut self, delta: i32, sampled_frequency_band: FrequencyBand) {
         self.zoom_level = self.zoom_level.saturating_add_signed(delta).min(30);
 
@@ -101,10 +189,17 @@ impl UiState {
         self.view_frequency_band.end = self.view_frequency_band.start + bandwidth;
     }
 
-    fn center_view(&mut self, sampled_frequency_band: FrequencyBand) {
+    pub fn center_view(&mut self, sampled_frequency_band: FrequencyBand) {
         self.zoom_level = 0;
         self.view_frequency_band = sampled_frequency_band;
     }
+
+    fn center_view_on(&mut self, frequency: u32) {
+        self.view_frequency_band = FrequencyBand::from_center_and_bandwidth(
+            frequency,
+            self.view_frequency_band.bandwidth(),
+        );
+    }
 }
 
 #[derive(Debug)]
@@ -114,9 +209,24 @@ pub struct Ui {
     mouse_position: Option<Position>,
     exit_requested: bool,
 
+    /// Area the waterfall was last rendered to. Used to map mouse clicks to
+    /// frequencies.
+    waterfall_area: Option<Rect>,
+
     keybinds: Keybinds,
     bandplan: Bandplan,
     color_map: ColorMap,
+    themes: Themes,
+    stations: StationDatabase,
+
+    /// How far stations may be from a frequency to match it, in Hz.
+    station_tolerance: u32,
+
+    signal_markers: SignalMarkers,
+    occupancy: BandOccupancy,
+    scope: Option<Scope>,
+    audio_spectrum: Option<AudioSpectrum>,
+    level_meter: Option<LevelMeter>,
 
     // todo: remove this - how?
     sampled_frequency_band: FrequencyBand,
@@ -128,6 +238,9 @@ impl Ui {
         keybinds: Keybinds,
         bandplan: Bandplan,
         color_map: ColorMap,
+        themes: Themes,
+        stations: StationDatabase,
+        station_tolerance: u32,
     ) -> Self {
         Self {
             layout: Layout::vertical([
@@ -138,22 +251,73 @@ impl Ui {
             ]),
             mouse_position: None,
             exit_requested: false,
+            waterfall_area: None,
             keybinds,
             sampled_frequency_band,
             bandplan,
             color_map,
+            themes,
+            stations,
+            station_tolerance,
+            signal_markers: SignalMarkers::default(),
+            occupancy: BandOccupancy::default(),
+            scope: None,
+            audio_spectrum: None,
+            level_meter: None,
         }
     }
 
+    /// Sets the scope shown below the waterfall.
+    pub fn set_scope(&mut self, scope: Scope) {
+        self.scope = Some(scope);
+    }
+
+    /// Sets the audio spectrum shown below the waterfall.
+    pub fn set_audio_spectrum(&mut self, audio_spectrum: AudioSpectrum) {
+        self.audio_spectrum = Some(audio_spectrum);
+    }
+
+    /// Sets the meter for the level of the demodulated audio, shown next to
+    /// the tuner dial.
+    pub fn set_level_meter(&mut self, stats: StatsHandle<f32>) {
+        self.level_meter = Some(LevelMeter::new(stats));
+    }
+
     fn mouse_position_inside_area(&self, area: Rect) -> Option<Position> {
-        self.mouse_position.and_then(|mouse_position| {
-            mouse_position
-                .x
-                .checked_sub(area.x)
-                .zip(mouse_position.y.checked_sub(area.y))
-                .filter(|(x, y)| *x < area.width && *y < area.height)
-                .map(|(x, y)| Position { x, y })
-        })
+        self.mouse_position
+            .and_then(|mouse_position| position_inside_area(mouse_position, area))
+    }
+
+    /// Places a marker at the waterfall cell at `position`, which is in
+    /// terminal coordinates.
+    fn place_marker(&self, id: MarkerId, position: Position, state: &mut UiState) {
+        let Some(waterfall_area) = self.waterfall_area
+        else {
+            return;
+        };
+        let Some(position) = position_inside_area(position, waterfall_area)
+        else {
+            return;
+        };
+
+        // the frequency band covered by the cell that was clicked
+        let cell_bandwidth =
+            state.view_frequency_band.bandwidth() as f32 / waterfall_area.width as f32;
+        let cell_frequency_band = FrequencyBand {
+            start: state.view_frequency_band.start + (position.x as f32 * cell_bandwidth) as u32,
+            end: state.view_frequency_band.start
+                + ((position.x + 1) as f32 * cell_bandwidth) as u32,
+        };
+
+        state.markers.set(
+            id,
+            Marker {
+                frequency: cell_frequency_band.center(),
+                power: state
+                    .waterfall_state
+                    .power(position.y.into(), cell_frequency_band),
+            },
+        );
     }
 
     pub fn exit_requested(&self) -> bool {
@@ -163,16 +327,110 @@ impl Ui {
     pub fn handle_event(&mut self, event: UiEvent, app: &AppProxy, state: &mut UiState) {
         match event {
             UiEvent::Terminal(event) => self.handle_terminal_event(event, app, state),
+            UiEvent::Action(action) => self.handle_action(action, app, state),
             UiEvent::ScrollWaterfall => {
                 state.waterfall_state.scroll();
+
+                if let Some((line, frequency_band)) = state.waterfall_state.latest_line() {
+                    self.signal_markers.update(line, frequency_band);
+                }
             }
             UiEvent::Spectrum {
                 spectrum,
                 frequency_band,
+                timestamp,
+                duration,
             } => {
                 self.sampled_frequency_band = frequency_band;
-                state.waterfall_state.push(spectrum, frequency_band);
+                state
+                    .waterfall_state
+                    .push(spectrum, frequency_band, timestamp, duration);
+
+                if state.show_occupancy {
+                    self.occupancy
+                        .push(spectrum, frequency_band, duration, &self.bandplan);
+                }
+            }
+        }
+    }
+
+    fn handle_action(&mut self, action: Action, app: &AppProxy, state: &mut UiState) {
+        match action {
+            Action::Quit => app.request_exit(),
+            Action::ZoomIn => state.zoom_view(1, self.sampled_frequency_band),
+            Action::ZoomOut => state.zoom_view(-1, self.sampled_frequency_band),
+            Action::MoveLeft => state.move_view(-1, false),
+            Action::MoveLeftBig => state.move_view(-1, true),
+            Action::MoveRight => state.move_view(1, false),
+            Action::MoveRightBig => state.move_view(1, true),
+            Action::CenterView => state.center_view(self.sampled_frequency_band),
+            Action::TuneToView => {
+                app.set_center_frequency(state.view_frequency_band.center());
+            }
+            Action::ClearMarkers => state.markers.clear(),
+            Action::ExportMarkers => {
+                if let Some(measurement) = state.markers.measurement() {
+                    app.export_marker_measurement(measurement);
+                }
+            }
+            Action::ToggleTimeAxis => state.waterfall_state.toggle_time_axis(),
+            Action::ToggleBaselineSubtraction => {
+                state.waterfall_state.toggle_baseline_subtraction();
             }
+            Action::CycleAveraging => state.waterfall_state.cycle_averaging(),
+            Action::ToggleSignalMarkers => {
+                state.show_signal_markers = !state.show_signal_markers;
+            }
+            Action::ToggleOccupancy => {
+                state.show_occupancy = !state.show_occupancy;
+                self.occupancy.clear();
+            }
+            Action::ToggleScope => state.show_scope = !state.show_scope,
+            Action::ToggleAudioSpectrogram => {
+                state.show_audio_spectrogram = !state.show_audio_spectrogram;
+            }
+            Action::ToggleStations => state.show_stations = !state.show_stations,
+            Action::CycleTheme => {
+                let theme = self.themes.cycle();
+                tracing::info!(theme = %theme.name, "Switched theme");
+                state.theme = Some(theme.name.clone());
+            }
+            Action::CycleScopeTrigger => {
+                if let Some(scope) = &self.scope {
+                    scope.cycle_trigger();
+                }
+            }
+            Action::NextSignal => {
+                if let Some(frequency) = self
+                    .signal_markers
+                    .next_signal(self.sampled_frequency_band.center())
+                {
+                    state.center_view_on(frequency);
+                    app.set_center_frequency(frequency);
+                }
+            }
+            Action::PreviousSignal => {
+                if let Some(frequency) = self
+                    .signal_markers
+                    .previous_signal(self.sampled_frequency_band.center())
+                {
+                    state.center_view_on(frequency);
+                    app.set_center_frequency(frequency);
+                }
+            }
+            Action::ToggleSpectrumInversion => app.toggle_spectrum_inversion(),
+            Action::CycleFftOverlap => app.cycle_fft_overlap(),
+            Action::CycleFftWindow => app.cycle_fft_window(),
+            Action::ToggleAudioPause => {
+                app.time_shift(TimeShiftCommand::TogglePause);
+            }
+            Action::RewindAudio => {
+                app.time_shift(TimeShiftCommand::Rewind {
+                    duration: AUDIO_REWIND_STEP,
+                });
+            }
+            Action::SkipAudioToLive => app.time_shift(TimeShiftCommand::SkipToLive),
+            Action::Test => {}
         }
     }
 
@@ -185,20 +443,7 @@ impl Ui {
         match event {
             TerminalEvent::Key(key_event) => {
                 if let Some(action) = self.keybinds.get(key_event) {
-                    match action {
-                        Action::Quit => app.request_exit(),
-                        Action::ZoomIn => state.zoom_view(1, self.sampled_frequency_band),
-                        Action::ZoomOut => state.zoom_view(-1, self.sampled_frequency_band),
-                        Action::MoveLeft => state.move_view(-1, false),
-                        Action::MoveLeftBig => state.move_view(-1, true),
-                        Action::MoveRight => state.move_view(1, false),
-                        Action::MoveRightBig => state.move_view(1, true),
-                        Action::CenterView => state.center_view(self.sampled_frequency_band),
-                        Action::TuneToView => {
-                            app.set_center_frequency(state.view_frequency_band.center());
-                        }
-                        Action::Test => {}
-                    }
+                    self.handle_action(action, app, state);
                 }
             }
             TerminalEvent::Mouse(mouse_event) => {
@@ -209,6 +454,22 @@ impl Ui {
                             y: mouse_event.row,
                         });
                     }
+                    // not every terminal reports motion, so the click's own
+                    // position is used
+                    MouseEventKind::Down(MouseButton::Left) => {
+                        let position = Position {
+                            x: mouse_event.column,
+                            y: mouse_event.row,
+                        };
+                        self.place_marker(MarkerId::A, position, state);
+                    }
+                    MouseEventKind::Down(MouseButton::Right) => {
+                        let position = Position {
+                            x: mouse_event.column,
+                            y: mouse_event.row,
+                        };
+                        self.place_marker(MarkerId::B, position, state);
+                    }
                     MouseEventKind::ScrollDown => {
                         state.zoom_view(-1, self.sampled_frequency_band);
                     }
@@ -229,6 +490,16 @@ impl Ui {
     }
 }
 
+/// `position` relative to `area`, or `None` if it's outside of it.
+fn position_inside_area(position: Position, area: Rect) -> Option<Position> {
+    position
+        .x
+        .checked_sub(area.x)
+        .zip(position.y.checked_sub(area.y))
+        .filter(|(x, y)| *x < area.width && *y < area.height)
+        .map(|(x, y)| Position { x, y })
+}
+
 #[derive(Debug)]
 pub struct UiWidget<'a> {
     pub ui: &'a mut Ui,
@@ -246,40 +517,143 @@ impl<'a> Widget for UiWidget<'a> {
             bandplan_area,
             waterfall_area,
         ] = self.ui.layout.areas(area);
+        let theme = self.ui.themes.current();
+
+        let [dial_area, level_meter_area] = Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Length(if self.ui.level_meter.is_some() {
+                LEVEL_METER_WIDTH
+            }
+            else {
+                0
+            }),
+        ])
+        .areas(controls_area);
 
         FrequencyDial {
             frequency: self.ui.sampled_frequency_band.center(),
             title: "Tuner",
+            theme,
+        }
+        .render(dial_area, buf);
+
+        if let Some(meter) = &mut self.ui.level_meter {
+            meter.update();
+            LevelMeterWidget { meter, theme }.render(level_meter_area, buf);
         }
-        .render(controls_area, buf);
 
         FrequencyMarks {
             view_frequency_band: self.state.view_frequency_band,
+            markers: &self.state.markers,
+            theme,
         }
         .render(frequencies_area, buf);
 
+        let scope = self.ui.scope.as_ref().filter(|_| self.state.show_scope);
+        let audio_spectrum = self
+            .ui
+            .audio_spectrum
+            .as_ref()
+            .filter(|_| self.state.show_audio_spectrogram);
+        let [
+            waterfall_area,
+            stations_area,
+            audio_spectrogram_area,
+            scope_area,
+        ] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(if self.state.show_stations {
+                STATIONS_HEIGHT
+            }
+            else {
+                0
+            }),
+            Constraint::Length(if audio_spectrum.is_some() {
+                AUDIO_SPECTROGRAM_HEIGHT
+            }
+            else {
+                0
+            }),
+            Constraint::Length(if scope.is_some() { SCOPE_HEIGHT } else { 0 }),
+        ])
+        .areas(waterfall_area);
+
         BandplanWidget {
             bandplan: &self.ui.bandplan,
             view_frequency_band: self.state.view_frequency_band,
+            theme,
         }
         .render(bandplan_area, buf);
 
+        if self.state.show_occupancy {
+            OccupancyWidget {
+                occupancy: &self.ui.occupancy,
+                view_frequency_band: self.state.view_frequency_band,
+                theme,
+            }
+            .render(bandplan_area, buf);
+        }
+
         WaterfallWidget {
             waterfall: &mut self.state.waterfall_state,
             view_frequency_band: self.state.view_frequency_band,
             mouse_position: self.ui.mouse_position_inside_area(waterfall_area),
             color_map: &self.ui.color_map,
+            markers: &self.state.markers,
+            stations: &self.ui.stations,
+            station_tolerance: self.ui.station_tolerance,
+            theme,
         }
         .render(waterfall_area, buf);
+        self.ui.waterfall_area = Some(waterfall_area);
+
+        if self.state.show_signal_markers {
+            SignalMarkersWidget {
+                signal_markers: &self.ui.signal_markers,
+                view_frequency_band: self.state.view_frequency_band,
+                theme,
+            }
+            .render(waterfall_area, buf);
+        }
+
+        if self.state.show_stations {
+            StationsWidget {
+                stations: &self.ui.stations,
+                view_frequency_band: self.state.view_frequency_band,
+                tuned_frequency: self.ui.sampled_frequency_band.center(),
+                tolerance: self.ui.station_tolerance,
+                theme,
+            }
+            .render(stations_area, buf);
+        }
+
+        if let Some(spectrum) = audio_spectrum {
+            AudioSpectrogramWidget {
+                spectrum,
+                color_map: &self.ui.color_map,
+                theme,
+            }
+            .render(audio_spectrogram_area, buf);
+        }
+
+        if let Some(scope) = scope {
+            ScopeWidget { scope, theme }.render(scope_area, buf);
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum UiEvent<'a> {
     Terminal(TerminalEvent),
+    /// An action that didn't come from a keybind, e.g. from a script.
+    Action(Action),
     ScrollWaterfall,
     Spectrum {
         spectrum: &'a [Complex<f32>],
         frequency_band: FrequencyBand,
+        /// Time at which the samples were captured.
+        timestamp: DateTime<Local>,
+        /// Time between this spectrum and the next one.
+        duration: Duration,
     },
 }
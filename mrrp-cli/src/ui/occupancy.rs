@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use mrrp::{
+    analysis::occupancy::{
+        OccupancyMeter,
+        OccupancyReport,
+    },
+    frequency::Frequency,
+};
+use num_complex::Complex;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{
+        Color,
+        Style,
+    },
+    widgets::Widget,
+};
+
+use crate::{
+    ui::{
+        bandplan::Bandplan,
+        theme::Theme,
+    },
+    util::FrequencyBand,
+};
+
+/// A band is busy if it's this many dB above the noise floor.
+const BUSY_THRESHOLD: f32 = 10.0;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Occupancy of the bandplan bands in the sampled frequency band.
+#[derive(Debug, Default)]
+pub struct BandOccupancy {
+    meter: Option<OccupancyMeter>,
+    frequency_band: Option<FrequencyBand>,
+    power: Vec<f32>,
+    report: Option<OccupancyReport>,
+}
+
+impl BandOccupancy {
+    pub fn push(
+        &mut self,
+        spectrum: &[Complex<f32>],
+        frequency_band: FrequencyBand,
+        duration: Duration,
+        bandplan: &Bandplan,
+    ) {
+        // the bands depend on the tuning, so we start over when it changes
+        if self.frequency_band != Some(frequency_band) {
+            let bands = bandplan
+                .range(frequency_band)
+                .map(|band| Frequency::from(band.start)..Frequency::from(band.end))
+                .collect();
+            self.meter = Some(OccupancyMeter::new(bands, BUSY_THRESHOLD, REPORT_INTERVAL));
+            self.frequency_band = Some(frequency_band);
+            self.report = None;
+        }
+
+        let Some(meter) = &mut self.meter
+        else {
+            return;
+        };
+
+        self.power.clear();
+        self.power.extend(spectrum.iter().map(|bin| bin.norm_sqr()));
+        let bin_width = frequency_band.bandwidth() as f64 / spectrum.len() as f64;
+
+        if let Some(report) = meter.push(
+            &self.power,
+            frequency_band.start.into(),
+            bin_width,
+            duration,
+        ) {
+            self.report = Some(report);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Draws the duty cycle of each band over the end of its bandplan entry.
+#[derive(Debug)]
+pub struct OccupancyWidget<'a> {
+    pub occupancy: &'a BandOccupancy,
+    pub view_frequency_band: FrequencyBand,
+    pub theme: &'a Theme,
+}
+
+impl<'a> Widget for OccupancyWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let Some(report) = &self.occupancy.report
+        else {
+            return;
+        };
+        if area.height == 0 || area.width == 0 {
+            return;
+        }
+
+        let cells_per_hz = area.width as f32 / self.view_frequency_band.bandwidth() as f32;
+        let to_cell = |frequency: u32| {
+            ((frequency.saturating_sub(self.view_frequency_band.start)) as f32 * cells_per_hz)
+                as u16
+        };
+
+        for band_report in &report.bands {
+            if band_report.observed.is_zero() {
+                continue;
+            }
+
+            let band = FrequencyBand {
+                start: band_report.band.start.as_hz() as u32,
+                end: band_report.band.end.as_hz() as u32,
+            };
+            let Some(visible) = band.intersection(&self.view_frequency_band)
+            else {
+                continue;
+            };
+
+            let cell_start = to_cell(visible.start).min(area.width);
+            let cell_end = to_cell(visible.end).min(area.width);
+            let text = format!("{:.0}%", band_report.duty_cycle * 100.0);
+            let width = text.len() as u16;
+            if cell_end - cell_start < width {
+                continue;
+            }
+
+            buf.set_string(
+                area.x + cell_end - width,
+                area.y,
+                &text,
+                Style::new()
+                    .fg(self.theme.highlight_text)
+                    .bg(duty_cycle_color(band_report.duty_cycle, self.theme)),
+            );
+        }
+    }
+}
+
+fn duty_cycle_color(duty_cycle: f32, theme: &Theme) -> Color {
+    if duty_cycle < 0.1 {
+        theme.good
+    }
+    else if duty_cycle < 0.5 {
+        theme.warning
+    }
+    else {
+        theme.alert
+    }
+}
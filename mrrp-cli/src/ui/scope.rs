@@ -0,0 +1,132 @@
+use mrrp::io::combinators::{
+    ScopeHandle,
+    Trigger,
+};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    symbols::Marker,
+    widgets::{
+        Widget,
+        canvas::{
+            Canvas,
+            Line,
+        },
+    },
+};
+
+use crate::ui::theme::Theme;
+
+/// Scope on the demodulated audio.
+#[derive(Clone, Debug)]
+pub struct Scope {
+    pub handle: ScopeHandle<f32>,
+    pub sample_rate: u32,
+}
+
+impl Scope {
+    /// Switches between free running and triggering on a rising edge.
+    ///
+    /// The trigger level is set halfway between the minimum and maximum of
+    /// the latest frame, like the "50%" button on a real scope.
+    pub fn cycle_trigger(&self) {
+        let trigger = match self.handle.trigger_mode() {
+            Trigger::FreeRun => {
+                let Some(frame) = self.handle.latest()
+                else {
+                    return;
+                };
+                let (min, max) = min_max(&frame.samples);
+                Trigger::RisingEdge {
+                    level: 0.5 * (min + max),
+                }
+            }
+            Trigger::RisingEdge { .. } | Trigger::External => Trigger::FreeRun,
+        };
+        self.handle.set_trigger_mode(trigger);
+    }
+}
+
+#[derive(Debug)]
+pub struct ScopeWidget<'a> {
+    pub scope: &'a Scope,
+    pub theme: &'a Theme,
+}
+
+impl<'a> Widget for ScopeWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let trigger = self.scope.handle.trigger_mode();
+        let frame = self.scope.handle.latest();
+
+        let mut title = match trigger {
+            Trigger::FreeRun => "Scope: free run".to_owned(),
+            Trigger::RisingEdge { level } => format!("Scope: rising edge at {level:.3}"),
+            Trigger::External => "Scope: external".to_owned(),
+        };
+        if let Some(frame) = &frame {
+            let span = frame.samples.len() as f32 / self.scope.sample_rate as f32;
+            title.push_str(&format!(", {:.0} ms", span * 1000.0));
+        }
+        let block = self.theme.block().title(title);
+
+        let Some(frame) = frame
+        else {
+            block.render(area, buf);
+            return;
+        };
+
+        let (min, max) = min_max(&frame.samples);
+        // leave some room above and below the trace, and don't blow up noise
+        // on a silent channel
+        let margin = (0.1 * (max - min)).max(1e-6);
+        let x_max = frame.samples.len().saturating_sub(1) as f64;
+        let trigger_x = frame.trigger_offset as f64;
+
+        Canvas::default()
+            .block(block)
+            .marker(Marker::Braille)
+            .x_bounds([0.0, x_max])
+            .y_bounds([(min - margin).into(), (max + margin).into()])
+            .paint(|context| {
+                context.draw(&Line::new(
+                    trigger_x,
+                    (min - margin).into(),
+                    trigger_x,
+                    (max + margin).into(),
+                    self.theme.grid,
+                ));
+                if let Trigger::RisingEdge { level } = trigger {
+                    context.draw(&Line::new(
+                        0.0,
+                        level.into(),
+                        x_max,
+                        level.into(),
+                        self.theme.grid,
+                    ));
+                }
+                context.layer();
+
+                for (x, pair) in frame.samples.windows(2).enumerate() {
+                    context.draw(&Line::new(
+                        x as f64,
+                        pair[0].into(),
+                        (x + 1) as f64,
+                        pair[1].into(),
+                        self.theme.trace,
+                    ));
+                }
+            })
+            .render(area, buf);
+    }
+}
+
+fn min_max(samples: &[f32]) -> (f32, f32) {
+    samples
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), sample| {
+            (min.min(*sample), max.max(*sample))
+        })
+}
@@ -0,0 +1,112 @@
+use std::ops::RangeBounds;
+
+use mrrp::analysis::peaks::{
+    Peak,
+    PeakDetector,
+};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::Widget,
+};
+
+use crate::{
+    ui::theme::Theme,
+    util::FrequencyBand,
+};
+
+const MARKER_CENTER: char = '\u{25bc}';
+const MARKER_SPAN: char = '\u{2500}';
+
+/// Signals detected in the most recent waterfall line.
+#[derive(Debug, Default)]
+pub struct SignalMarkers {
+    detector: PeakDetector,
+    peaks: Vec<Peak>,
+    sorted: Vec<f32>,
+    signals: Vec<FrequencyBand>,
+}
+
+impl SignalMarkers {
+    pub fn update(&mut self, spectrum: &[f32], frequency_band: FrequencyBand) {
+        self.detector
+            .find_peaks_into(spectrum, &mut self.peaks, &mut self.sorted);
+
+        let bin_width = frequency_band.bandwidth() as f32 / spectrum.len() as f32;
+
+        self.signals.clear();
+        self.signals.extend(self.peaks.iter().map(|peak| {
+            FrequencyBand::from_center_and_bandwidth(
+                peak.center_frequency(frequency_band.start as f32, bin_width) as u32,
+                (peak.bandwidth_hz(bin_width) as u32).max(1),
+            )
+        }));
+    }
+
+    /// Returns the signals sorted by frequency.
+    pub fn signals(&self) -> &[FrequencyBand] {
+        &self.signals
+    }
+
+    /// Center frequency of the first signal above `frequency`.
+    pub fn next_signal(&self, frequency: u32) -> Option<u32> {
+        self.signals
+            .iter()
+            .map(|signal| signal.center())
+            .find(|center| *center > frequency)
+    }
+
+    /// Center frequency of the first signal below `frequency`.
+    pub fn previous_signal(&self, frequency: u32) -> Option<u32> {
+        self.signals
+            .iter()
+            .rev()
+            .map(|signal| signal.center())
+            .find(|center| *center < frequency)
+    }
+}
+
+#[derive(Debug)]
+pub struct SignalMarkersWidget<'a> {
+    pub signal_markers: &'a SignalMarkers,
+    pub view_frequency_band: FrequencyBand,
+    pub theme: &'a Theme,
+}
+
+impl<'a> Widget for SignalMarkersWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        if area.height == 0 || area.width == 0 {
+            return;
+        }
+
+        let cells_per_hz = area.width as f32 / self.view_frequency_band.bandwidth() as f32;
+        let to_cell = |frequency: u32| {
+            ((frequency as f32 - self.view_frequency_band.start as f32) * cells_per_hz) as i64
+        };
+
+        for signal in self.signal_markers.signals() {
+            let Some(visible) = signal.intersection(&self.view_frequency_band)
+            else {
+                continue;
+            };
+
+            let cell_start = to_cell(visible.start).clamp(0, i64::from(area.width - 1)) as u16;
+            let cell_end = to_cell(visible.end).clamp(0, i64::from(area.width - 1)) as u16;
+            for x in cell_start..=cell_end {
+                let cell = &mut buf[(area.x + x, area.y)];
+                cell.set_char(MARKER_SPAN);
+                cell.fg = self.theme.signal;
+            }
+
+            if self.view_frequency_band.contains(&signal.center()) {
+                let x = to_cell(signal.center()).clamp(0, i64::from(area.width - 1)) as u16;
+                let cell = &mut buf[(area.x + x, area.y)];
+                cell.set_char(MARKER_CENTER);
+                cell.fg = self.theme.signal;
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    widgets::Widget,
+};
+
+use crate::{
+    files::{
+        Station,
+        StationDatabase,
+    },
+    ui::theme::Theme,
+    util::{
+        FrequencyBand,
+        format_frequency,
+    },
+};
+
+/// Lookup panel listing the stations in view.
+///
+/// The list is scrolled so that the station closest to the tuner is in the
+/// middle, and stations within the tolerance of the tuner are highlighted.
+#[derive(Debug)]
+pub struct StationsWidget<'a> {
+    pub stations: &'a StationDatabase,
+    pub view_frequency_band: FrequencyBand,
+    pub tuned_frequency: u32,
+    pub tolerance: u32,
+    pub theme: &'a Theme,
+}
+
+impl<'a> Widget for StationsWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let visible = self.stations.range(self.view_frequency_band);
+        let block = self
+            .theme
+            .block()
+            .title(format!("Stations: {} in view", visible.len()));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let height = usize::from(inner.height);
+        let tuned = visible.partition_point(|station| station.frequency < self.tuned_frequency);
+        let first = tuned
+            .saturating_sub(height / 2)
+            .min(visible.len().saturating_sub(height));
+
+        for (row, station) in visible[first..].iter().take(height).enumerate() {
+            let style = if station.frequency.abs_diff(self.tuned_frequency) <= self.tolerance {
+                Style::new()
+                    .fg(self.theme.highlight_text)
+                    .bg(self.theme.highlight_background)
+            }
+            else {
+                Style::new().fg(self.theme.text)
+            };
+
+            buf.set_stringn(
+                inner.x,
+                inner.y + row as u16,
+                station_label(station),
+                inner.width.into(),
+                style,
+            );
+        }
+    }
+}
+
+/// Frequency, name, mode and location of a station on one line.
+fn station_label(station: &Station) -> String {
+    let mut label = format!(
+        "{} {}",
+        format_frequency(station.frequency).with_step(station.frequency),
+        station.name
+    );
+    if !station.mode.is_empty() {
+        label.push_str(&format!(" {}", station.mode));
+    }
+    if !station.location.is_empty() {
+        label.push_str(&format!(" ({})", station.location));
+    }
+    label
+}
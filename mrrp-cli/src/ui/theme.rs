@@ -0,0 +1,289 @@
+use std::{
+    fs::File,
+    io::{
+        BufReader,
+        BufWriter,
+    },
+    path::Path,
+};
+
+use ratatui::{
+    style::Color,
+    widgets::Block,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::Error;
+
+/// Colors the widgets are drawn with.
+///
+/// Missing fields in a theme file are taken from the dark theme, so a theme
+/// only needs to list the colors it changes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub name: String,
+
+    /// Regular text, e.g. the tuner frequency and axis labels.
+    pub text: Color,
+
+    /// Less important details, e.g. minor ticks on the frequency axis.
+    pub dim: Color,
+
+    /// Major ticks on the frequency axis.
+    pub tick: Color,
+
+    pub border: Color,
+
+    /// Background of the waterfall where there is no spectrum.
+    pub background: Color,
+
+    /// Markers and their readout.
+    pub marker: Color,
+
+    /// Detected signals.
+    pub signal: Color,
+
+    /// Text of highlighted entries, e.g. the tuned station.
+    pub highlight_text: Color,
+    pub highlight_background: Color,
+
+    /// Levels that are fine, e.g. in the audio level meter.
+    pub good: Color,
+
+    /// Levels that are getting high.
+    pub warning: Color,
+
+    /// Levels that are too high, e.g. clipping.
+    pub alert: Color,
+
+    /// Trace of the scope.
+    pub trace: Color,
+
+    /// Grid and trigger lines of the scope.
+    pub grid: Color,
+
+    /// Band names on bands whose color has a relative luminance above
+    /// `bandplan_luminance_threshold`.
+    pub bandplan_dark_text: Color,
+
+    /// Band names on darker bands.
+    pub bandplan_light_text: Color,
+
+    pub bandplan_luminance_threshold: f32,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: "dark".to_owned(),
+            text: Color::White,
+            dim: Color::DarkGray,
+            tick: Color::Gray,
+            border: Color::Reset,
+            background: Color::Rgb(0, 0, 0),
+            marker: Color::Cyan,
+            signal: Color::Yellow,
+            highlight_text: Color::Black,
+            highlight_background: Color::Yellow,
+            good: Color::Green,
+            warning: Color::Yellow,
+            alert: Color::Red,
+            trace: Color::Green,
+            grid: Color::DarkGray,
+            bandplan_dark_text: Color::Black,
+            bandplan_light_text: Color::White,
+            bandplan_luminance_threshold: 0.5,
+        }
+    }
+
+    /// For terminals with a light background.
+    pub fn light() -> Self {
+        Self {
+            name: "light".to_owned(),
+            text: Color::Black,
+            dim: Color::Gray,
+            tick: Color::DarkGray,
+            border: Color::DarkGray,
+            background: Color::Rgb(255, 255, 255),
+            marker: Color::Blue,
+            signal: Color::Magenta,
+            highlight_text: Color::White,
+            highlight_background: Color::Blue,
+            good: Color::Green,
+            warning: Color::Rgb(200, 120, 0),
+            alert: Color::Red,
+            trace: Color::Blue,
+            grid: Color::Gray,
+            bandplan_dark_text: Color::Black,
+            bandplan_light_text: Color::White,
+            bandplan_luminance_threshold: 0.5,
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            name: "high-contrast".to_owned(),
+            text: Color::White,
+            dim: Color::White,
+            tick: Color::White,
+            border: Color::White,
+            background: Color::Rgb(0, 0, 0),
+            marker: Color::LightCyan,
+            signal: Color::LightYellow,
+            highlight_text: Color::Black,
+            highlight_background: Color::White,
+            good: Color::LightGreen,
+            warning: Color::LightYellow,
+            alert: Color::LightRed,
+            trace: Color::LightGreen,
+            grid: Color::Gray,
+            bandplan_dark_text: Color::Black,
+            bandplan_light_text: Color::White,
+            // bias towards black text, which reads better on most band colors
+            bandplan_luminance_threshold: 0.35,
+        }
+    }
+
+    /// A bordered block in the colors of this theme.
+    pub fn block(&self) -> Block<'static> {
+        Block::bordered()
+            .border_style(self.border)
+            .title_style(self.text)
+    }
+
+    pub fn builtin() -> Vec<Self> {
+        vec![Self::dark(), Self::light(), Self::high_contrast()]
+    }
+
+    /// Color for the name of a band with the given relative luminance.
+    pub fn bandplan_text(&self, luminance: f32) -> Color {
+        if luminance > self.bandplan_luminance_threshold {
+            self.bandplan_dark_text
+        }
+        else {
+            self.bandplan_light_text
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+/// The available themes, and which one is used.
+#[derive(Clone, Debug)]
+pub struct Themes {
+    themes: Vec<Theme>,
+    current: usize,
+}
+
+impl Themes {
+    /// The built-in themes, followed by `themes`. A theme with the name of a
+    /// built-in one replaces it.
+    pub fn new(themes: Vec<Theme>) -> Self {
+        let mut all = Theme::builtin();
+        for theme in themes {
+            if let Some(existing) = all.iter_mut().find(|existing| existing.name == theme.name) {
+                *existing = theme;
+            }
+            else {
+                all.push(theme);
+            }
+        }
+        Self {
+            themes: all,
+            current: 0,
+        }
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        tracing::debug!(path = %path.as_ref().display(), "Loading themes from file");
+        let themes: Vec<Theme> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        Ok(Self::new(themes))
+    }
+
+    pub fn to_path(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        tracing::debug!(path = %path.as_ref().display(), "Writing themes to file");
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &self.themes)?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn current(&self) -> &Theme {
+        &self.themes[self.current]
+    }
+
+    /// Switches to the theme with the given name. Returns `false` if there is
+    /// none.
+    pub fn select(&mut self, name: &str) -> bool {
+        if let Some(index) = self.themes.iter().position(|theme| theme.name == name) {
+            self.current = index;
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    /// Switches to the next theme.
+    pub fn cycle(&mut self) -> &Theme {
+        self.current = (self.current + 1) % self.themes.len();
+        self.current()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.themes.iter().map(|theme| theme.name.as_str())
+    }
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        Self::new(vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use super::{
+        Theme,
+        Themes,
+    };
+
+    #[test]
+    fn it_fills_missing_colors_from_the_dark_theme() {
+        let themes: Vec<Theme> =
+            serde_json::from_str(r##"[{"name": "solar", "text": "#ffcc00"}]"##).unwrap();
+        assert_eq!(themes[0].text, Color::Rgb(0xff, 0xcc, 0x00));
+        assert_eq!(themes[0].border, Theme::dark().border);
+
+        let mut themes = Themes::new(themes);
+        assert_eq!(
+            themes.names().collect::<Vec<_>>(),
+            ["dark", "light", "high-contrast", "solar"]
+        );
+        assert!(themes.select("solar"));
+        assert!(!themes.select("nope"));
+        assert_eq!(themes.current().name, "solar");
+        assert_eq!(themes.cycle().name, "dark");
+    }
+
+    #[test]
+    fn it_replaces_builtin_themes() {
+        let light = Theme {
+            marker: Color::Red,
+            ..Theme::light()
+        };
+        let mut themes = Themes::new(vec![light]);
+        assert_eq!(themes.names().count(), 3);
+        assert!(themes.select("light"));
+        assert_eq!(themes.current().marker, Color::Red);
+    }
+}
@@ -5,10 +5,19 @@ use std::{
         BufReader,
         BufWriter,
     },
-    ops::Index,
+    ops::{
+        Index,
+        Range,
+    },
     path::Path,
+    time::Duration,
 };
 
+use chrono::{
+    DateTime,
+    Local,
+    TimeDelta,
+};
 use num_complex::Complex;
 use palette::LinSrgb;
 use ratatui::{
@@ -29,6 +38,11 @@ use serde::{
 
 use crate::{
     Error,
+    files::StationDatabase,
+    ui::{
+        markers::Markers,
+        theme::Theme,
+    },
     util::{
         FrequencyBand,
         debug_limited,
@@ -50,6 +64,20 @@ pub struct WaterfallState {
     draw_mode: DrawMode,
     min_z: f32,
     max_z: f32,
+    #[serde(default)]
+    show_time_axis: bool,
+    #[serde(default)]
+    display_mode: DisplayMode,
+    #[serde(skip, default)]
+    baseline: Baseline,
+    #[serde(default)]
+    averaging: Averaging,
+    #[serde(skip, default)]
+    exponential_average: ExponentialAverage,
+    /// Buffer of the last line that fell out of the history, for the next
+    /// line.
+    #[serde(skip, default)]
+    spare_samples: Vec<f32>,
 }
 
 impl Default for WaterfallState {
@@ -67,6 +95,12 @@ impl Default for WaterfallState {
             draw_mode: DrawMode::HalfBlockHorizontal,
             min_z,
             max_z,
+            show_time_axis: false,
+            display_mode: DisplayMode::default(),
+            baseline: Baseline::default(),
+            averaging: Averaging::default(),
+            exponential_average: ExponentialAverage::default(),
+            spare_samples: vec![],
         }
     }
 }
@@ -74,24 +108,42 @@ impl Default for WaterfallState {
 impl WaterfallState {
     pub fn scroll(&mut self) {
         if let Some(line) = self.new_line.take() {
-            if let Some(line) = line.into_line() {
-                self.lines.push(line);
+            if let Some(line) = line.into_line(&mut self.exponential_average) {
+                self.baseline.update(&line);
+                if let Some(samples) = self.lines.push(line) {
+                    self.spare_samples = samples;
+                }
 
                 self.cache.scroll(self.lines.history);
             }
         }
     }
 
-    pub fn push(&mut self, spectrum: &[Complex<f32>], sampled_frequency_band: FrequencyBand) {
+    /// Adds a spectrum to the next line. `duration` is the time of signal
+    /// that's new in this spectrum, which is shorter than the spectrum's
+    /// segment if segments overlap.
+    pub fn push(
+        &mut self,
+        spectrum: &[Complex<f32>],
+        sampled_frequency_band: FrequencyBand,
+        timestamp: DateTime<Local>,
+        duration: Duration,
+    ) {
         if let Some(new_line) = &mut self.new_line {
             if new_line.frequency_band != sampled_frequency_band {
                 self.scroll();
             }
         }
 
-        let new_line = self
-            .new_line
-            .get_or_insert_with(|| NewLine::new(spectrum.len(), sampled_frequency_band));
+        let new_line = self.new_line.get_or_insert_with(|| {
+            NewLine::new(
+                std::mem::take(&mut self.spare_samples),
+                spectrum.len(),
+                sampled_frequency_band,
+                timestamp,
+                self.averaging,
+            )
+        });
 
         assert_eq!(new_line.samples.len(), spectrum.len(), "fft size changed");
         assert_eq!(
@@ -99,16 +151,53 @@ impl WaterfallState {
             "sampled frequency band mismatch"
         );
 
-        for i in 0..new_line.samples.len() {
-            new_line.samples[i] += spectrum[i].norm_sqr();
-        }
-        new_line.count += 1;
+        new_line.accumulate(spectrum, duration);
+    }
+
+    /// Switches to the next [`Averaging`] mode.
+    pub fn cycle_averaging(&mut self) {
+        self.averaging = self.averaging.next();
+        // the line that is being accumulated was started with the old mode
+        self.scroll();
+        tracing::debug!(averaging = ?self.averaging, "Waterfall averaging changed");
+    }
+
+    /// Switches between showing absolute power and power relative to the
+    /// per-frequency baseline.
+    pub fn toggle_baseline_subtraction(&mut self) {
+        self.display_mode = match self.display_mode {
+            DisplayMode::Absolute => DisplayMode::RelativeToBaseline,
+            DisplayMode::RelativeToBaseline => DisplayMode::Absolute,
+        };
+        self.cache.clear();
+    }
+
+    pub fn toggle_time_axis(&mut self) {
+        self.show_time_axis = !self.show_time_axis;
+    }
+
+    /// Returns the power (in dBFS) in line `y` (0 being the most recent line)
+    /// over the given frequency band.
+    pub fn power(&self, y: usize, frequency_band: FrequencyBand) -> Option<f32> {
+        self.lines.get_line(y).and_then(|line| {
+            line.bins(frequency_band.start as f32, frequency_band.end as f32)
+                .map(|samples| self.downsampling.apply(samples.iter().copied()))
+        })
+    }
+
+    /// Returns the most recent line (in dBFS) and the frequency band it covers.
+    pub fn latest_line(&self) -> Option<(&[f32], FrequencyBand)> {
+        self.lines
+            .get_line(0)
+            .map(|line| (&line.samples[..], line.frequency_band))
     }
 }
 
 const HALF_BLOCK_LEFT: char = '\u{258c}';
 const HALF_BLOCK_TOP: char = '\u{2580}';
-const COLOR_BLACK: Color = Color::Rgb(0, 0, 0);
+
+/// Number of rows between labels on the time axis.
+const TIME_AXIS_INTERVAL: usize = 10;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum DrawMode {
@@ -124,10 +213,13 @@ pub struct Canvas<'a> {
     pub buf: &'a mut Buffer,
     pub mode: DrawMode,
     pub size: Size,
+
+    /// Color of cleared cells.
+    pub background: Color,
 }
 
 impl<'a> Canvas<'a> {
-    pub fn new(area: Rect, buf: &'a mut Buffer, mode: DrawMode) -> Self {
+    pub fn new(area: Rect, buf: &'a mut Buffer, mode: DrawMode, background: Color) -> Self {
         let size = match mode {
             DrawMode::FullBlock => {
                 Size {
@@ -154,6 +246,7 @@ impl<'a> Canvas<'a> {
             buf,
             mode,
             size,
+            background,
         }
     }
 
@@ -193,7 +286,7 @@ impl<'a> Canvas<'a> {
     }
 
     pub fn clear(&mut self, position: impl Into<Position>) {
-        self.draw_impl(position.into(), COLOR_BLACK);
+        self.draw_impl(position.into(), self.background);
     }
 
     pub fn clear_line(&mut self, y: u16) {
@@ -206,7 +299,7 @@ impl<'a> Canvas<'a> {
         for x in 0..self.area.width {
             let cell = &mut self.buf[(self.area.x + x, self.area.y + y)];
             cell.reset();
-            cell.bg = COLOR_BLACK;
+            cell.bg = self.background;
         }
     }
 }
@@ -217,6 +310,13 @@ pub struct WaterfallWidget<'a> {
     pub view_frequency_band: FrequencyBand,
     pub mouse_position: Option<Position>,
     pub color_map: &'a ColorMap,
+    pub markers: &'a Markers,
+    pub stations: &'a StationDatabase,
+
+    /// How far a station may be from the hovered cell to be shown, in Hz.
+    pub station_tolerance: u32,
+
+    pub theme: &'a Theme,
 }
 
 impl<'a> Widget for WaterfallWidget<'a> {
@@ -224,40 +324,45 @@ impl<'a> Widget for WaterfallWidget<'a> {
     where
         Self: Sized,
     {
-        let mut canvas = Canvas::new(area, buf, self.waterfall.draw_mode);
+        let mut canvas = Canvas::new(area, buf, self.waterfall.draw_mode, self.theme.background);
         self.waterfall.lines.history = canvas.size.height.max(10).into();
 
         let mut total_min_max = None;
         let display_bin_width =
             self.view_frequency_band.bandwidth() as f32 / canvas.size.width as f32;
 
-        let sample_spectrum = |x: u16, line: &Line| {
-            let line_start = line.frequency_band.start as f32;
-
+        // samples the spectrum for column `x` from line `y`, see `Lines::sample_at`
+        let sample_spectrum = |x: u16, y: usize| {
             let start_frequency =
                 self.view_frequency_band.start as f32 + x as f32 * display_bin_width;
             let end_frequency =
                 self.view_frequency_band.start as f32 + (x + 1) as f32 * display_bin_width;
 
-            let start_line_index = (((start_frequency - line_start) / line.bin_width).max(0.0)
-                as usize)
-                .min(line.samples.len());
-
-            let end_line_index = (((end_frequency - line_start) / line.bin_width)
-                .ceil()
-                .max(0.0) as usize)
-                .min(line.samples.len());
-
-            (start_line_index < end_line_index).then(|| {
-                let samples = &line.samples[start_line_index..end_line_index];
-                (
-                    self.waterfall.downsampling.apply(samples),
-                    FrequencyBand {
-                        start: start_frequency as u32,
-                        end: end_frequency as u32,
-                    },
-                )
-            })
+            self.waterfall
+                .lines
+                .sample_at(y, start_frequency, end_frequency)
+                .map(|(line, range)| {
+                    let z = match self.waterfall.display_mode {
+                        DisplayMode::Absolute => {
+                            self.waterfall
+                                .downsampling
+                                .apply(line.samples[range].iter().copied())
+                        }
+                        DisplayMode::RelativeToBaseline => {
+                            self.waterfall
+                                .downsampling
+                                .apply(self.waterfall.baseline.subtract(line, range))
+                        }
+                    };
+                    (
+                        z,
+                        FrequencyBand {
+                            start: start_frequency as u32,
+                            end: end_frequency as u32,
+                        },
+                        line,
+                    )
+                })
         };
 
         let mut render_cell = |x, y, z, canvas: &mut Canvas| {
@@ -291,12 +396,12 @@ impl<'a> Widget for WaterfallWidget<'a> {
 
         // render spectral density history
         for y in 0..canvas.size.height {
-            if let Some(line) = self.waterfall.lines.get_line(y.into()) {
+            if self.waterfall.lines.get_line(y.into()).is_some() {
                 let cache_line = self.waterfall.cache.get_line_or_sample(
                     y,
                     canvas.size.width,
                     self.view_frequency_band,
-                    |x| sample_spectrum(x, line).map(|(z, _)| z),
+                    |x| sample_spectrum(x, y.into()).map(|(z, _, _)| z),
                 );
 
                 for x in 0..canvas.size.width {
@@ -315,17 +420,58 @@ impl<'a> Widget for WaterfallWidget<'a> {
             self.waterfall.max_z = max;
         }
 
+        // render time axis
+        if self.waterfall.show_time_axis {
+            // rows per line
+            let line_height = match self.waterfall.draw_mode {
+                DrawMode::FullBlock | DrawMode::HalfBlockHorizontal => 1,
+                DrawMode::HalfBlockVertical => 2,
+            };
+
+            for row in (0..area.height).step_by(TIME_AXIS_INTERVAL) {
+                if let Some(line) = self
+                    .waterfall
+                    .lines
+                    .get_line(usize::from(row) * line_height)
+                {
+                    buf.set_string(
+                        area.x,
+                        area.y + row,
+                        format!("\u{2500} {}", line.timestamp.format("%H:%M:%S")),
+                        self.theme.text,
+                    );
+                }
+            }
+        }
+
         // render mouse cursor
         if let Some(mouse_position) = self.mouse_position {
-            if let Some(line) = self.waterfall.lines.get_line(mouse_position.y.into()) {
+            if self
+                .waterfall
+                .lines
+                .get_line(mouse_position.y.into())
+                .is_some()
+            {
                 // fixme: this is still broken with half-width blocks
-                if let Some((z, mouse_frequency_band)) = sample_spectrum(mouse_position.x, line) {
+                if let Some((z, mouse_frequency_band, line)) =
+                    sample_spectrum(mouse_position.x, mouse_position.y.into())
+                {
+                    let station = self
+                        .stations
+                        .nearest(
+                            mouse_frequency_band.center(),
+                            mouse_frequency_band.bandwidth() / 2 + self.station_tolerance,
+                        )
+                        .map(|station| format!(" | {}", station.name))
+                        .unwrap_or_default();
                     let text = format!(
-                        "x-[{} ± {}: {:.1} dBFS]-x",
+                        "x-[{} ± {}: {:.1} dBFS @ {}{}]-x",
                         format_frequency(mouse_frequency_band.center())
                             .with_band(self.view_frequency_band),
                         format_frequency(mouse_frequency_band.bandwidth() / 2),
                         z,
+                        line.timestamp.format("%H:%M:%S%.3f"),
+                        station,
                     );
                     let text_width = text.len() - 4;
 
@@ -336,7 +482,7 @@ impl<'a> Widget for WaterfallWidget<'a> {
                             area.x + mouse_position.x - u16::try_from(text_width).unwrap(),
                             area.y + mouse_position.y,
                             &text[2..],
-                            Color::White,
+                            self.theme.text,
                         );
                     }
                     else {
@@ -344,12 +490,48 @@ impl<'a> Widget for WaterfallWidget<'a> {
                             area.x + mouse_position.x,
                             area.y + mouse_position.y,
                             &text[..text_width + 2],
-                            Color::White,
+                            self.theme.text,
                         );
                     }
                 }
             }
         }
+
+        // render marker readout
+        if !self.markers.is_empty() && area.height > 0 {
+            let mut text = String::new();
+            for (id, marker) in self.markers.iter() {
+                text.push_str(&format!("[{}: {marker}] ", id.as_char()));
+            }
+            if let Some(delta) = self.markers.delta() {
+                text.push_str(&format!("[\u{394}: {delta}]"));
+            }
+
+            let y = area.y + area.height - 1;
+            for x in 0..area.width {
+                buf[(area.x + x, y)].reset();
+            }
+            buf.set_stringn(
+                area.x,
+                y,
+                text.trim_end(),
+                area.width.into(),
+                self.theme.marker,
+            );
+        }
+
+        // render averaging mode, unless it's the default
+        if self.waterfall.averaging != Averaging::default() && area.height > 0 {
+            let text = format!("[{}]", self.waterfall.averaging.label());
+            let width = (text.len() as u16).min(area.width);
+            buf.set_stringn(
+                area.x + area.width - width,
+                area.y,
+                &text,
+                width.into(),
+                self.theme.text,
+            );
+        }
     }
 }
 
@@ -358,22 +540,76 @@ struct NewLine {
     #[debug("{:?}", debug_limited(samples))]
     samples: Vec<f32>,
     count: usize,
+
+    /// Sum of the durations (in seconds) by which the accumulated spectra are
+    /// weighted.
+    #[serde(default)]
+    weight: f32,
+
     frequency_band: FrequencyBand,
     bin_width: f32,
+    #[serde(default)]
+    timestamp: DateTime<Local>,
+    #[serde(default)]
+    averaging: Averaging,
 }
 
 impl NewLine {
-    fn new(width: usize, frequency_band: FrequencyBand) -> Self {
+    /// `samples` is reused for the line, if it has enough capacity.
+    fn new(
+        mut samples: Vec<f32>,
+        width: usize,
+        frequency_band: FrequencyBand,
+        timestamp: DateTime<Local>,
+        averaging: Averaging,
+    ) -> Self {
         let bin_width = frequency_band.bandwidth() as f32 / width as f32;
+        samples.clear();
+        samples.resize(width, 0.0);
         Self {
-            samples: vec![0.0; width],
+            samples,
             count: 0,
+            weight: 0.0,
             frequency_band,
             bin_width,
+            timestamp,
+            averaging,
         }
     }
 
-    fn into_line(mut self) -> Option<Line> {
+    fn accumulate(&mut self, spectrum: &[Complex<f32>], duration: Duration) {
+        let power = spectrum.iter().map(|bin| bin.norm_sqr());
+        match self.averaging {
+            Averaging::Rms | Averaging::Exponential => {
+                // overlapping spectra share some of their samples. weighting them by the
+                // time they add keeps the mean right when the overlap changes within a
+                // line.
+                let weight = duration.as_secs_f32();
+                for (z, power) in self.samples.iter_mut().zip(power) {
+                    *z += weight * power;
+                }
+                self.weight += weight;
+            }
+            Averaging::PeakHold => {
+                for (z, power) in self.samples.iter_mut().zip(power) {
+                    *z = z.max(power);
+                }
+            }
+            Averaging::MinHold if self.count == 0 => {
+                for (z, power) in self.samples.iter_mut().zip(power) {
+                    *z = power;
+                }
+            }
+            Averaging::MinHold => {
+                for (z, power) in self.samples.iter_mut().zip(power) {
+                    *z = z.min(power);
+                }
+            }
+        }
+        self.count += 1;
+    }
+
+    fn into_line(mut self, exponential_average: &mut ExponentialAverage) -> Option<Line> {
         if self.count > 0 {
             // z is the energy for that frequency over line.count * sample_rate / len(line).
             // convert to power in dBFS.
@@ -394,19 +630,36 @@ impl NewLine {
 
             // let normalize = 1.0 / (self.count as f32 * self.bin_width *
             // self.samples.len() as f32);
-            let normalize = 1.0 / (self.count as f32 * self.frequency_band.bandwidth() as f32);
+            // hold modes keep a single spectrum per bin, so they're not divided by the
+            // count.
+            let count = match self.averaging {
+                Averaging::Rms | Averaging::Exponential if self.weight > 0.0 => self.weight,
+                // lines from snapshots before the spectra were weighted
+                Averaging::Rms | Averaging::Exponential => self.count as f32,
+                Averaging::PeakHold | Averaging::MinHold => 1.0,
+            };
+            let normalize = 1.0 / (count * self.frequency_band.bandwidth() as f32);
 
             // dB power
             //let normalize = 1.0 / (self.count as f32 * self.samples.len() as f32);
 
             for z in &mut self.samples {
-                *z = 10.0 * (*z * normalize).log10();
+                *z *= normalize;
+            }
+
+            if self.averaging == Averaging::Exponential {
+                exponential_average.update(&mut self.samples, self.frequency_band);
+            }
+
+            for z in &mut self.samples {
+                *z = 10.0 * z.log10();
             }
 
             Some(Line {
                 samples: self.samples,
                 frequency_band: self.frequency_band,
                 bin_width: self.bin_width,
+                timestamp: self.timestamp,
             })
         }
         else {
@@ -421,6 +674,215 @@ struct Line {
     samples: Vec<f32>,
     frequency_band: FrequencyBand,
     bin_width: f32,
+
+    /// Capture time of the first spectrum in this line.
+    #[serde(default)]
+    timestamp: DateTime<Local>,
+}
+
+impl Line {
+    /// Returns the bins that overlap the given frequency range, if any.
+    fn bins(&self, start_frequency: f32, end_frequency: f32) -> Option<&[f32]> {
+        self.bin_range(start_frequency, end_frequency)
+            .map(|range| &self.samples[range])
+    }
+
+    /// Returns the indices of the bins that overlap the given frequency range,
+    /// if any.
+    fn bin_range(&self, start_frequency: f32, end_frequency: f32) -> Option<Range<usize>> {
+        let line_start = self.frequency_band.start as f32;
+
+        let start_index = (((start_frequency - line_start) / self.bin_width).max(0.0) as usize)
+            .min(self.samples.len());
+
+        let end_index = (((end_frequency - line_start) / self.bin_width)
+            .ceil()
+            .max(0.0) as usize)
+            .min(self.samples.len());
+
+        (start_index < end_index).then_some(start_index..end_index)
+    }
+}
+
+/// How the spectra within one scroll tick are combined into a line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Averaging {
+    /// Mean power per bin, i.e. the RMS average of the magnitude.
+    #[default]
+    Rms,
+
+    /// Mean power per bin, smoothed across lines with an exponential moving
+    /// average. This reduces the noise variance, so weak steady signals stand
+    /// out.
+    Exponential,
+
+    /// Maximum power per bin. Bursts shorter than a scroll tick show up at
+    /// full strength instead of being averaged away.
+    PeakHold,
+
+    /// Minimum power per bin. Shows the noise floor under intermittent
+    /// signals.
+    MinHold,
+}
+
+impl Averaging {
+    pub fn next(&self) -> Self {
+        match self {
+            Averaging::Rms => Averaging::Exponential,
+            Averaging::Exponential => Averaging::PeakHold,
+            Averaging::PeakHold => Averaging::MinHold,
+            Averaging::MinHold => Averaging::Rms,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Averaging::Rms => "rms",
+            Averaging::Exponential => "exponential",
+            Averaging::PeakHold => "peak hold",
+            Averaging::MinHold => "min hold",
+        }
+    }
+}
+
+/// Weight of a new line in the [`Averaging::Exponential`] average.
+const EXPONENTIAL_AVERAGE_WEIGHT: f32 = 0.25;
+
+/// State of the [`Averaging::Exponential`] average, in linear power.
+#[derive(derive_more::Debug, Default)]
+struct ExponentialAverage {
+    frequency_band: Option<FrequencyBand>,
+    #[debug("{:?}", debug_limited(average))]
+    average: Vec<f32>,
+}
+
+impl ExponentialAverage {
+    /// Blends `samples` into the average and replaces them with the new
+    /// average.
+    fn update(&mut self, samples: &mut [f32], frequency_band: FrequencyBand) {
+        // like the baseline, the average is only valid for one tuning.
+        if self.frequency_band != Some(frequency_band) || self.average.len() != samples.len() {
+            self.frequency_band = Some(frequency_band);
+            self.average.clear();
+            self.average.extend_from_slice(samples);
+            return;
+        }
+
+        for (average, z) in self.average.iter_mut().zip(samples) {
+            *average += EXPONENTIAL_AVERAGE_WEIGHT * (*z - *average);
+            *z = *average;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisplayMode {
+    /// Power in dBFS
+    #[default]
+    Absolute,
+
+    /// Power relative to the [`Baseline`] in dB.
+    RelativeToBaseline,
+}
+
+/// Slowly-updated per-bin noise floor estimate.
+///
+/// Every [`snapshot_interval`][Self::snapshot_interval] a line is stored as a
+/// snapshot. The baseline is the per-bin median over all snapshots, so signals
+/// that are only present some of the time don't end up in it.
+#[derive(derive_more::Debug)]
+struct Baseline {
+    frequency_band: Option<FrequencyBand>,
+    #[debug("{} snapshots", snapshots.len())]
+    snapshots: VecDeque<Vec<f32>>,
+    last_snapshot: Option<DateTime<Local>>,
+    #[debug("{:?}", debug_limited(median))]
+    median: Vec<f32>,
+    snapshot_interval: TimeDelta,
+    max_snapshots: usize,
+}
+
+impl Default for Baseline {
+    fn default() -> Self {
+        // median over the last 5 minutes
+        Self::new(TimeDelta::seconds(10), 30)
+    }
+}
+
+impl Baseline {
+    fn new(snapshot_interval: TimeDelta, max_snapshots: usize) -> Self {
+        Self {
+            frequency_band: None,
+            snapshots: VecDeque::with_capacity(max_snapshots),
+            last_snapshot: None,
+            median: vec![],
+            snapshot_interval,
+            max_snapshots,
+        }
+    }
+
+    fn update(&mut self, line: &Line) {
+        // the baseline is only valid for one tuning.
+        if self.frequency_band != Some(line.frequency_band)
+            || self
+                .snapshots
+                .front()
+                .is_some_and(|snapshot| snapshot.len() != line.samples.len())
+        {
+            self.snapshots.clear();
+            self.last_snapshot = None;
+            self.frequency_band = Some(line.frequency_band);
+        }
+
+        if self
+            .last_snapshot
+            .is_some_and(|last| line.timestamp - last < self.snapshot_interval)
+        {
+            return;
+        }
+
+        while self.snapshots.len() >= self.max_snapshots {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(line.samples.clone());
+        self.last_snapshot = Some(line.timestamp);
+
+        self.update_median();
+    }
+
+    fn update_median(&mut self) {
+        let num_bins = self.snapshots.front().map_or(0, |snapshot| snapshot.len());
+        self.median.resize(num_bins, 0.0);
+
+        let mut column = Vec::with_capacity(self.snapshots.len());
+        for i in 0..num_bins {
+            column.clear();
+            column.extend(self.snapshots.iter().map(|snapshot| snapshot[i]));
+            let middle = column.len() / 2;
+            let (_, median, _) = column.select_nth_unstable_by(middle, |a, b| a.total_cmp(b));
+            self.median[i] = *median;
+        }
+    }
+
+    /// Returns the given bins of the line relative to the baseline. If there
+    /// is no baseline for the line yet, the bins are returned as-is.
+    ///
+    /// This is called for every cell when rendering, so it doesn't allocate.
+    fn subtract<'a>(
+        &'a self,
+        line: &'a Line,
+        range: Range<usize>,
+    ) -> impl ExactSizeIterator<Item = f32> + 'a {
+        let baseline = (self.frequency_band == Some(line.frequency_band)
+            && self.median.len() == line.samples.len())
+        .then(|| &self.median[range.clone()]);
+
+        line.samples[range]
+            .iter()
+            .enumerate()
+            .map(move |(i, z)| z - baseline.map_or(0.0, |baseline| baseline[i]))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -518,14 +980,15 @@ pub enum Downsampling {
 }
 
 impl Downsampling {
-    pub fn apply(&self, samples: &[f32]) -> f32 {
-        assert!(samples.len() > 0);
+    pub fn apply(&self, mut samples: impl ExactSizeIterator<Item = f32>) -> f32 {
+        let num_samples = samples.len();
+        assert!(num_samples > 0);
         match self {
-            Downsampling::Sum => samples.iter().sum(),
-            Downsampling::Average => samples.iter().sum::<f32>() / samples.len() as f32,
-            Downsampling::Min => min_float(samples.iter().copied()).unwrap(),
-            Downsampling::Max => max_float(samples.iter().copied()).unwrap(),
-            Downsampling::First => samples[0],
+            Downsampling::Sum => samples.sum(),
+            Downsampling::Average => samples.sum::<f32>() / num_samples as f32,
+            Downsampling::Min => min_float(samples).unwrap(),
+            Downsampling::Max => max_float(samples).unwrap(),
+            Downsampling::First => samples.next().unwrap(),
         }
     }
 }
@@ -544,18 +1007,40 @@ impl Lines {
         }
     }
 
-    pub fn push(&mut self, line: Line) {
+    /// Returns the buffer of a line that fell out of the history, so it can be
+    /// reused.
+    pub fn push(&mut self, line: Line) -> Option<Vec<f32>> {
+        let mut evicted = None;
         while self.lines.len() >= self.history && !self.lines.is_empty() {
-            // todo: reuse those poor buffers :cryring:
-            self.lines.pop_front();
+            evicted = self.lines.pop_front().map(|line| line.samples);
         }
 
         self.lines.push_back(line);
+        evicted
     }
 
     pub fn get_line(&self, i: usize) -> Option<&Line> {
         self.lines.len().checked_sub(i + 1).map(|i| &self.lines[i])
     }
+
+    /// Returns the line to sample for the given frequency range in row `y`,
+    /// and the bins in it that overlap the range.
+    ///
+    /// If line `y` doesn't cover the frequencies (e.g. because we retuned
+    /// since), the most recent older line that does is used instead. This way
+    /// lines from different tunings are stitched together by their absolute
+    /// frequency.
+    pub fn sample_at(
+        &self,
+        y: usize,
+        start_frequency: f32,
+        end_frequency: f32,
+    ) -> Option<(&Line, Range<usize>)> {
+        (y..).map_while(|y| self.get_line(y)).find_map(|line| {
+            line.bin_range(start_frequency, end_frequency)
+                .map(|range| (line, range))
+        })
+    }
 }
 
 #[derive(Debug, Default)]
@@ -573,7 +1058,6 @@ impl Cache {
         }
     }
 
-    #[allow(unused)]
     pub fn clear(&mut self) {
         self.lines.clear();
         self.view_frequency_band = None;
@@ -652,3 +1136,181 @@ impl Index<u16> for CacheLine {
         &self[usize::from(index)]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{
+        Local,
+        TimeDelta,
+    };
+    use num_complex::Complex;
+
+    use super::{
+        Averaging,
+        Baseline,
+        ExponentialAverage,
+        Line,
+        Lines,
+        NewLine,
+    };
+    use crate::util::FrequencyBand;
+
+    fn accumulate(averaging: Averaging, magnitudes: &[[f32; 2]]) -> Vec<f32> {
+        // bandwidth of 1 Hz, so the normalization doesn't change the power
+        let frequency_band = FrequencyBand { start: 0, end: 1 };
+        let mut new_line = NewLine::new(vec![], 2, frequency_band, Local::now(), averaging);
+        for magnitudes in magnitudes {
+            new_line.accumulate(
+                &magnitudes.map(|magnitude| Complex::new(magnitude, 0.0)),
+                Duration::from_millis(10),
+            );
+        }
+        new_line
+            .into_line(&mut ExponentialAverage::default())
+            .unwrap()
+            .samples
+    }
+
+    #[test]
+    fn averaging_modes_combine_spectra() {
+        // 0 dB and 20 dB in the first bin, -20 dB and 0 dB in the second
+        let magnitudes = [[1.0, 0.1], [10.0, 1.0]];
+        let assert_line = |averaging, expected: [f32; 2]| {
+            let line = accumulate(averaging, &magnitudes);
+            for (z, expected) in line.iter().zip(expected) {
+                assert!((z - expected).abs() < 1e-3, "{averaging:?}: {line:?}");
+            }
+        };
+
+        assert_line(Averaging::Rms, [17.0329, -2.9671]);
+        assert_line(Averaging::PeakHold, [20.0, 0.0]);
+        assert_line(Averaging::MinHold, [0.0, -20.0]);
+    }
+
+    #[test]
+    fn mean_is_weighted_by_duration() {
+        let frequency_band = FrequencyBand { start: 0, end: 1 };
+        let mut new_line = NewLine::new(vec![], 1, frequency_band, Local::now(), Averaging::Rms);
+
+        // the second spectrum overlaps the first by 3/4
+        new_line.accumulate(&[Complex::new(1.0, 0.0)], Duration::from_millis(12));
+        new_line.accumulate(
+            &[Complex::new(5.0f32.sqrt(), 0.0)],
+            Duration::from_millis(4),
+        );
+
+        // (12 * 1 + 4 * 5) / 16 = 2
+        let line = new_line
+            .into_line(&mut ExponentialAverage::default())
+            .unwrap();
+        assert!((line.samples[0] - 2.0f32.log10() * 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn exponential_average_carries_over_lines() {
+        let frequency_band = FrequencyBand { start: 0, end: 1 };
+        let mut average = ExponentialAverage::default();
+
+        let mut samples = [1.0];
+        average.update(&mut samples, frequency_band);
+        assert_eq!(samples, [1.0]);
+
+        let mut samples = [5.0];
+        average.update(&mut samples, frequency_band);
+        assert_eq!(samples, [2.0]);
+
+        // retuning starts over
+        let mut samples = [5.0];
+        average.update(&mut samples, FrequencyBand { start: 1, end: 2 });
+        assert_eq!(samples, [5.0]);
+    }
+
+    #[test]
+    fn baseline_is_median_over_snapshots() {
+        let frequency_band = FrequencyBand {
+            start: 1000,
+            end: 1004,
+        };
+        let start = Local::now();
+
+        let mut baseline = Baseline::new(TimeDelta::seconds(1), 5);
+
+        for (i, z) in [-100.0, -90.0, -100.0, -100.0, -20.0]
+            .into_iter()
+            .enumerate()
+        {
+            baseline.update(&Line {
+                samples: vec![z, -80.0, -80.0, -80.0],
+                frequency_band,
+                bin_width: 1.0,
+                timestamp: start + TimeDelta::seconds(i as i64),
+            });
+        }
+
+        assert_eq!(baseline.median, [-100.0, -80.0, -80.0, -80.0]);
+
+        let line = Line {
+            samples: vec![-70.0, -70.0, -80.0, -90.0],
+            frequency_band,
+            bin_width: 1.0,
+            timestamp: start,
+        };
+        assert_eq!(
+            baseline.subtract(&line, 0..4).collect::<Vec<_>>(),
+            [30.0, 10.0, 0.0, -10.0]
+        );
+    }
+
+    #[test]
+    fn baseline_skips_lines_within_snapshot_interval() {
+        let frequency_band = FrequencyBand { start: 0, end: 1 };
+        let start = Local::now();
+
+        let mut baseline = Baseline::new(TimeDelta::seconds(10), 5);
+        for i in 0..5 {
+            baseline.update(&Line {
+                samples: vec![i as f32],
+                frequency_band,
+                bin_width: 1.0,
+                timestamp: start + TimeDelta::seconds(i),
+            });
+        }
+
+        assert_eq!(baseline.snapshots.len(), 1);
+        assert_eq!(baseline.median, [0.0]);
+    }
+
+    #[test]
+    fn lines_are_stitched_across_tunings() {
+        let line = |start, end| {
+            Line {
+                samples: (0..10).map(|i| i as f32).collect(),
+                frequency_band: FrequencyBand { start, end },
+                bin_width: 100.0,
+                timestamp: Local::now(),
+            }
+        };
+        let mut lines = Lines::new(10);
+        lines.push(line(0, 1000));
+        lines.push(line(500, 1500));
+
+        // covered by the newest line
+        let (sampled, range) = lines.sample_at(0, 600.0, 700.0).unwrap();
+        assert_eq!(sampled.frequency_band.start, 500);
+        assert_eq!(range, 1..2);
+
+        // outside of the newest line's band, so it comes from the older one
+        let (sampled, range) = lines.sample_at(0, 100.0, 200.0).unwrap();
+        assert_eq!(sampled.frequency_band.start, 0);
+        assert_eq!(range, 1..2);
+
+        // older rows never sample newer lines
+        let (sampled, _) = lines.sample_at(1, 600.0, 700.0).unwrap();
+        assert_eq!(sampled.frequency_band.start, 0);
+
+        // no line covers this
+        assert!(lines.sample_at(0, 2000.0, 2100.0).is_none());
+    }
+}
@@ -121,41 +121,88 @@ impl<T: 'static> From<&'static T> for StaticOrArc<T> {
 pub fn format_frequency(frequency: u32) -> FormatFrequency {
     FormatFrequency {
         frequency,
-        band: None,
+        precision: None,
+        unit_of: None,
     }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct FormatFrequency {
     frequency: u32,
-    band: Option<FrequencyBand>,
+    precision: Option<Precision>,
+    unit_of: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Precision {
+    Band(FrequencyBand),
+    Step(u32),
 }
 
 impl FormatFrequency {
+    /// Show enough digits to tell frequencies within `band` apart.
     pub fn with_band(mut self, band: FrequencyBand) -> Self {
-        self.band = Some(band);
+        self.precision = Some(Precision::Band(band));
+        self
+    }
+
+    /// Show exactly as many digits as are needed for multiples of `step`.
+    pub fn with_step(mut self, step: u32) -> Self {
+        self.precision = Some(Precision::Step(step));
+        self
+    }
+
+    /// Use the SI prefix that `reference` would be formatted with, so that a
+    /// row of labels has a consistent unit.
+    pub fn with_unit_of(mut self, reference: u32) -> Self {
+        self.unit_of = Some(reference);
         self
     }
 }
 
 impl Display for FormatFrequency {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let (divisor, prefix) = si_prefix(self.frequency);
-        let divisor = divisor as f32;
+        let (divisor, prefix) = si_prefix(self.unit_of.unwrap_or(self.frequency));
+
+        let precision = match self.precision {
+            Some(Precision::Band(band)) => {
+                (divisor as f32 / band.bandwidth().max(1) as f32)
+                    .log10()
+                    .ceil()
+                    .max(0.0) as usize
+                    + 1
+            }
+            Some(Precision::Step(step)) => decimals_for_step(step, divisor),
+            None => f.precision().unwrap_or(2),
+        };
+        let precision = precision.min(MAX_DECIMALS);
+
+        // fixed-point, since f32 can't even represent GHz frequencies to 1 Hz.
+        let scale = 10u64.pow(precision as u32);
+        let divisor = u64::from(divisor);
+        let scaled = (u64::from(self.frequency) * scale + divisor / 2) / divisor;
+        let integer = scaled / scale;
+        let fraction = scaled % scale;
+
+        if precision == 0 {
+            write!(f, "{integer} {prefix}Hz")
+        }
+        else {
+            write!(f, "{integer}.{fraction:0precision$} {prefix}Hz")
+        }
+    }
+}
 
-        let precision = self
-            .band
-            .map(|band| (divisor / band.bandwidth() as f32).log10().ceil() as usize + 1)
-            .or_else(|| f.precision())
-            .unwrap_or(2);
+const MAX_DECIMALS: usize = 9;
 
-        // todo: you could certainly do this without floats
-        write!(
-            f,
-            "{:.precision$} {prefix}Hz",
-            self.frequency as f32 / divisor
-        )
-    }
+/// Number of decimals needed to show multiples of `step` in units of
+/// `divisor`.
+fn decimals_for_step(step: u32, divisor: u32) -> usize {
+    let step = u64::from(step.max(1));
+    let divisor = u64::from(divisor);
+    (0..MAX_DECIMALS)
+        .find(|decimals| (step * 10u64.pow(*decimals as u32)).is_multiple_of(divisor))
+        .unwrap_or(MAX_DECIMALS)
 }
 
 const SI_PREFIXES: &'static [(u32, &'static str)] =
@@ -166,10 +213,27 @@ pub fn si_prefix(x: u32) -> (u32, &'static str) {
         .iter()
         .rev()
         .copied()
-        .find(|(n, _)| x > *n)
+        .find(|(n, _)| x >= *n)
         .unwrap_or((1, ""))
 }
 
+/// Returns the smallest "nice" interval (1, 2 or 5 times a power of 10 Hz)
+/// that divides `bandwidth` into at most `max_intervals` intervals.
+pub fn nice_interval(bandwidth: u32, max_intervals: u32) -> u32 {
+    let min_interval = u64::from(bandwidth.div_ceil(max_intervals.max(1)).max(1));
+
+    let mut power = 1u64;
+    loop {
+        for factor in [1, 2, 5] {
+            let interval = power * factor;
+            if interval >= min_interval {
+                return u32::try_from(interval).unwrap_or(u32::MAX);
+            }
+        }
+        power *= 10;
+    }
+}
+
 pub fn debug_limited<I>(iter: I) -> DebugLimited<I>
 where
     I: IntoIterator + Clone,
@@ -210,3 +274,40 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        format_frequency,
+        nice_interval,
+    };
+
+    #[test]
+    fn nice_intervals() {
+        assert_eq!(nice_interval(2_400_000, 10), 500_000);
+        assert_eq!(nice_interval(1_000, 10), 100);
+        assert_eq!(nice_interval(1_100, 10), 200);
+        assert_eq!(nice_interval(7, 10), 1);
+        assert_eq!(nice_interval(0, 10), 1);
+    }
+
+    #[test]
+    fn formats_with_step_precision() {
+        assert_eq!(
+            format_frequency(145_100_000).with_step(100_000).to_string(),
+            "145.1 MHz"
+        );
+        assert_eq!(
+            format_frequency(1_090_000_001).with_step(1).to_string(),
+            "1.090000001 GHz"
+        );
+        assert_eq!(
+            format_frequency(999_000)
+                .with_unit_of(1_001_000)
+                .with_step(1_000)
+                .to_string(),
+            "0.999 MHz"
+        );
+        assert_eq!(format_frequency(1_000).to_string(), "1.00 kHz");
+    }
+}
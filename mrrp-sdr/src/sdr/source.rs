@@ -16,6 +16,7 @@ use mrrp::{
     io::{
         AsyncReadSamples,
         AsyncReadSamplesExt,
+        GetCenterFrequency,
         GetSampleRate,
         ReadBuf,
         StreamLength,
@@ -131,15 +132,15 @@ pub struct LoopedFileSource {
 
 impl LoopedFileSource {
     pub fn new(path: impl AsRef<Path>) -> Result<Self, Error> {
-        let inner = WavSource::from_path(path)?
-            .convert::<Iq>()
-            .throttle_to_sample_rate();
+        let source = WavSource::from_path(path)?;
+        let center_frequency = source.center_frequency();
+        let inner = source.convert::<Iq>().throttle_to_sample_rate();
 
-        tracing::debug!(sample_rate = ?inner.sample_rate(), "opened wav file");
+        tracing::debug!(sample_rate = ?inner.sample_rate(), center_frequency, "opened wav file");
 
         Ok(Self {
             inner,
-            center_frequency: 0.0,
+            center_frequency,
         })
     }
 
@@ -0,0 +1,111 @@
+//! Compares the ADS-B preamble detectors.
+//!
+//! By default this runs on `benches/data/adsb.cu8`, 200 ms of unsigned 8-bit
+//! IQ samples at 2 MS/s, as written by `rtl_sdr -f 1090M -s 2M`. The capture
+//! is generated by `benches/data/adsb.py`. Another capture can be used by
+//! setting `MRRP_ADSB_CAPTURE` to its path.
+//!
+//! Besides the timing, this prints how many frames with a valid CRC each
+//! detector decodes.
+
+use std::{
+    hint::black_box,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::Duration,
+};
+
+use criterion::{
+    Criterion,
+    Throughput,
+    criterion_group,
+    criterion_main,
+};
+use futures_util::{
+    FutureExt,
+    StreamExt,
+};
+use mrrp::{
+    io::Cursor,
+    modem::adsb::{
+        DEFAULT_CORRELATION_THRESHOLD,
+        DemodulateStream,
+        Demodulator,
+        PreambleDetector,
+        message::Message,
+    },
+};
+use num_complex::Complex;
+
+fn capture_path() -> PathBuf {
+    std::env::var_os("MRRP_ADSB_CAPTURE").map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/data/adsb.cu8"),
+        PathBuf::from,
+    )
+}
+
+fn read_capture(path: &Path) -> Vec<Complex<f32>> {
+    let bytes = std::fs::read(path)
+        .unwrap_or_else(|error| panic!("could not read capture {}: {error}", path.display()));
+    bytes
+        .chunks_exact(2)
+        .map(|iq| {
+            Complex::new(
+                (f32::from(iq[0]) - 127.5) / 127.5,
+                (f32::from(iq[1]) - 127.5) / 127.5,
+            )
+        })
+        .collect()
+}
+
+fn count_frames(samples: &[Complex<f32>], preamble_detector: PreambleDetector) -> usize {
+    let demodulator = Demodulator::default().with_preamble_detector(preamble_detector);
+    DemodulateStream::new(Cursor::new(samples), demodulator, 0x4000)
+        .filter(|frame| {
+            let is_valid = frame
+                .as_ref()
+                .is_ok_and(|frame| Message::decode(&frame.frame).is_some());
+            async move { is_valid }
+        })
+        .count()
+        .now_or_never()
+        .expect("demodulator returned pending")
+}
+
+pub fn bench_adsb(c: &mut Criterion) {
+    let samples = read_capture(&capture_path());
+
+    let detectors = [
+        ("comparison", PreambleDetector::Comparison),
+        (
+            "correlation",
+            PreambleDetector::Correlation {
+                threshold: DEFAULT_CORRELATION_THRESHOLD,
+            },
+        ),
+    ];
+
+    for (name, detector) in detectors {
+        println!(
+            "adsb {name}: {} frames with valid CRC",
+            count_frames(&samples, detector)
+        );
+    }
+
+    let mut group = c.benchmark_group("adsb");
+    group.throughput(Throughput::Elements(samples.len() as u64));
+    group.measurement_time(Duration::from_secs(20));
+
+    for (name, detector) in detectors {
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(count_frames(&samples, detector)));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_adsb);
+criterion_main!(benches);
@@ -0,0 +1,125 @@
+#!/usr/bin/env python3
+"""Generates benches/data/adsb.cu8, the capture benches/adsb.rs runs on.
+
+Usage: adsb.py [output file]
+
+The capture imitates 200 ms of `rtl_sdr -f 1090M -s 2M` in moderately busy
+airspace: A dozen aircraft at different distances and frequency offsets send
+extended squitters (identification, airborne position and velocity) and
+all-call replies at random times, so some frames overlap. Frames start at
+fractional sample offsets with a random carrier phase. The samples are
+quantized to unsigned 8 bit with the DC offset of an RTL-SDR. The random
+generator is seeded, so the output is reproducible.
+"""
+
+import cmath
+import math
+import os
+import random
+import sys
+
+SAMPLE_RATE = 2_000_000
+DURATION = 0.2
+MESSAGE_RATE = 1000
+NUM_AIRCRAFT = 12
+NOISE = 3.5
+DC_OFFSET = complex(127.9, 127.1)
+
+CRC24_GENERATOR = 0x1FFF409
+CALLSIGN_CHARSET = "#ABCDEFGHIJKLMNOPQRSTUVWXYZ##### ###############0123456789######"
+
+
+def crc24(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte << 16
+        for _ in range(8):
+            crc <<= 1
+            if crc & 0x1000000:
+                crc ^= CRC24_GENERATOR
+    return crc & 0xFFFFFF
+
+
+def with_parity(data):
+    return data + crc24(data).to_bytes(3, "big")
+
+
+class Aircraft:
+    def __init__(self, rng):
+        self.icao = rng.getrandbits(24)
+        self.callsign = "".join(rng.choice("ABCDEFGHIJKLMNOPQRSTUVWXYZ") for _ in range(3))
+        self.callsign += str(rng.randrange(10, 9999)).ljust(5)
+        self.snr = rng.uniform(3, 25)
+        self.frequency_offset = rng.uniform(-60e3, 60e3)
+        self.altitude = rng.randrange(40, 1600)
+
+    def squitter(self, rng):
+        kind = rng.random()
+        if kind < 0.2:
+            me = 4 << 51 | rng.randrange(8) << 48
+            for i, c in enumerate(self.callsign):
+                me |= CALLSIGN_CHARSET.index(c) << (42 - 6 * i)
+        elif kind < 0.6:
+            altitude = (self.altitude & 0x7F0) << 1 | 0x10 | (self.altitude & 0xF)
+            me = 11 << 51 | altitude << 36 | rng.getrandbits(1) << 34 | rng.getrandbits(34)
+        else:
+            me = 19 << 51 | 1 << 48 | rng.getrandbits(48)
+        return with_parity(bytes([17 << 3 | 5]) + self.icao.to_bytes(3, "big") + me.to_bytes(7, "big"))
+
+    def all_call_reply(self):
+        return with_parity(bytes([11 << 3 | 5]) + self.icao.to_bytes(3, "big"))
+
+
+def pulses(frame):
+    # preamble pulses at 0, 2, 7 and 9, then one pulse per bit. Every position
+    # is half a microsecond, i.e. one sample.
+    yield from [0, 2, 7, 9]
+    for i, byte in enumerate(frame):
+        for j in range(8):
+            bit = (byte >> (7 - j)) & 1
+            yield 16 + 2 * (8 * i + j) + (1 - bit)
+
+
+def main():
+    output = sys.argv[1] if len(sys.argv) > 1 else os.path.join(os.path.dirname(__file__), "adsb.cu8")
+
+    rng = random.Random(1090)
+    num_samples = int(DURATION * SAMPLE_RATE)
+    samples = [complex(rng.gauss(0, NOISE), rng.gauss(0, NOISE)) for _ in range(num_samples)]
+
+    aircraft = [Aircraft(rng) for _ in range(NUM_AIRCRAFT)]
+    time = rng.expovariate(MESSAGE_RATE)
+    while time < DURATION - 130e-6:
+        sender = rng.choice(aircraft)
+        frame = sender.squitter(rng) if rng.random() < 0.7 else sender.all_call_reply()
+
+        amplitude = math.sqrt(2 * NOISE**2 * 10 ** (sender.snr / 10))
+        phase = rng.uniform(0, 2 * math.pi)
+        start = time * SAMPLE_RATE
+        for pulse in pulses(frame):
+            # a pulse is as long as a sample, so it spreads over two samples
+            # if it doesn't start on a sample boundary.
+            position = start + pulse
+            index = int(position)
+            fraction = position - index
+            carrier = amplitude * cmath.exp(1j * (phase + 2 * math.pi * sender.frequency_offset * position / SAMPLE_RATE))
+            samples[index] += (1 - fraction) * carrier
+            samples[index + 1] += fraction * carrier
+
+        time += rng.expovariate(MESSAGE_RATE)
+
+    def quantize(value):
+        return max(0, min(255, round(value)))
+
+    with open(output, "wb") as file:
+        file.write(
+            bytes(
+                byte
+                for sample in samples
+                for byte in (quantize(sample.real + DC_OFFSET.real), quantize(sample.imag + DC_OFFSET.imag))
+            )
+        )
+
+
+if __name__ == "__main__":
+    main()
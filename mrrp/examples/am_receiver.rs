@@ -15,10 +15,7 @@ use futures_util::pin_mut;
 use mrrp::{
     audio::play_audio,
     filter::resampling::AverageDecimate,
-    io::{
-        AsyncReadSamplesExt,
-        GetSampleRate,
-    },
+    prelude::*,
     source::rtlsdr::RtlSdrSource,
 };
 use tokio::signal::ctrl_c;
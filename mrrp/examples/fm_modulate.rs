@@ -1,4 +1,7 @@
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    time::Duration,
+};
 
 use clap::Parser;
 use color_eyre::eyre::Error;
@@ -6,24 +9,19 @@ use mrrp::{
     filter::{
         biquad,
         design::{
-            FilterDesign,
             Lowpass,
-            Normalize,
             pm_remez::pm_remez,
         },
     },
-    io::{
-        AsyncReadSamplesExt,
-        GetSampleRate,
-    },
     modem::fm::FmModulator,
+    prelude::*,
     sink::{
-        file::write_stream_to_wav,
+        file::WavSink,
         rtl_tcp,
     },
-    source::file::WavSource,
 };
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -37,7 +35,7 @@ async fn main() -> Result<(), Error> {
     // Convert from any format to the expected 32-bit float single-channel wav:
     // sox input_file -c 1 -b 32 -e float output.wav
 
-    let wav_source = WavSource::<_, f32>::from_path(&args.input)?;
+    let wav_source = from_wav::<f32>(&args.input)?;
     let sample_rate = wav_source.sample_rate();
 
     let interpolated = wav_source
@@ -59,7 +57,33 @@ async fn main() -> Result<(), Error> {
     println!("output sample rate: {}", filtered.sample_rate());
 
     if let Some(output) = &args.file_output {
-        write_stream_to_wav(output, filtered).await?;
+        // stop writing on Ctrl-C, but keep what's written so far
+        let cancel = CancellationToken::new();
+        tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                let _ = tokio::signal::ctrl_c().await;
+                cancel.cancel();
+            }
+        });
+
+        let mut sink = WavSink::<_, Complex<f32>>::from_path(output, filtered.sample_rate())?;
+        let stats = filtered
+            .pump(&mut sink)
+            .with_cancellation(cancel)
+            .with_progress(Duration::from_secs(1), |stats| {
+                println!(
+                    "{} samples written ({:.1} MS/s)",
+                    stats.num_samples,
+                    stats.rate() * 1e-6
+                );
+            })
+            .run()
+            .await?;
+        sink.close().await?;
+        if stats.cancelled {
+            println!("Cancelled");
+        }
     }
     else if let Some(output) = &args.tcp_output {
         //let tcp_stream = TcpStream::connect(&output).await?;
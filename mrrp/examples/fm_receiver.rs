@@ -5,17 +5,12 @@ use mrrp::{
     filter::{
         biquad,
         design::{
-            FilterDesign,
             Lowpass,
-            Normalize,
             pm_remez::pm_remez,
         },
     },
-    io::{
-        AsyncReadSamplesExt,
-        GetSampleRate,
-    },
     modem::fm::FmDemodulator,
+    prelude::*,
     source::rtlsdr::RtlSdrSource,
 };
 use tokio::signal::ctrl_c;
@@ -0,0 +1,234 @@
+//! POCSAG pager decoder
+//!
+//! Decodes pages from an RTL-SDR or from a complex IQ WAV file. The `encode`
+//! subcommand writes a short test capture, which can be decoded with the
+//! `file` subcommand:
+//!
+//! ```sh
+//! cargo run --example pocsag -- encode /tmp/pocsag.wav 1234567 "Hello, pager!"
+//! cargo run --example pocsag -- file /tmp/pocsag.wav
+//! ```
+//!
+//! `cargo test --example pocsag` decodes the capture in `tests/data/`, which is
+//! generated by `tests/data/pocsag.py`.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use color_eyre::eyre::Error;
+use mrrp::{
+    error::ClassifyError,
+    filter::{
+        biquad,
+        resampling::AverageDecimate,
+    },
+    io::{
+        AsyncReadSamples,
+        AsyncReadSamplesExt,
+        Cursor,
+        GetSampleRate,
+        combinators::Scanner,
+    },
+    modem::{
+        afsk::{
+            AfskDemodulator,
+            AfskModulator,
+        },
+        pocsag::{
+            self,
+            Message,
+            PocsagDecoder,
+            PocsagEncoder,
+        },
+    },
+    sink::file::write_stream_to_wav,
+    source::{
+        file::WavSource,
+        rtlsdr::RtlSdrSource,
+    },
+};
+use num_complex::Complex;
+use tokio::signal::ctrl_c;
+
+/// Sample rate at which we demodulate. This leaves enough room for the
+/// ±4.5 kHz deviation and some tuning error.
+const DEMODULATOR_SAMPLE_RATE: f32 = 48_000.0;
+
+/// Bandwidth of the channel filter.
+const CHANNEL_BANDWIDTH: f32 = 12_500.0;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let _ = dotenvy::dotenv();
+    color_eyre::install()?;
+    tracing_subscriber::fmt::init();
+    tracing::info!("POCSAG example");
+
+    let args = Args::parse();
+
+    match args {
+        Args::Rtlsdr {
+            frequency,
+            device,
+            baud_rate,
+        } => {
+            const RTLSDR_SAMPLE_RATE: f32 = 2_400_000.0;
+            let source = RtlSdrSource::open(device, frequency, RTLSDR_SAMPLE_RATE).await?;
+
+            tokio::select! {
+                result = decode(source, baud_rate, print_message) => result?,
+                _ = ctrl_c() => {
+                    println!("Ctrl-C pressed. Aborting.");
+                }
+            }
+        }
+        Args::File { input, baud_rate } => {
+            let source = WavSource::<_, Complex<f32>>::from_path(input)?;
+            decode(source, baud_rate, print_message).await?;
+        }
+        Args::Encode {
+            output,
+            address,
+            message,
+            baud_rate,
+            sample_rate,
+        } => {
+            let mut encoder = PocsagEncoder::new();
+            encoder.push_alphanumeric(address, &message);
+            let bits = encoder.finish();
+
+            let mut signal = vec![];
+            let mut modulator = AfskModulator::new(pocsag::fsk_config(baud_rate), sample_rate);
+            modulator.modulate_bits(bits, &mut signal);
+
+            write_stream_to_wav(output, Cursor::new(signal).with_sample_rate(sample_rate)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_message(message: Message) {
+    println!(
+        "address={} function={}{}: {}",
+        message.address,
+        message.function,
+        if message.has_errors { " (errors)" } else { "" },
+        message.text(),
+    );
+}
+
+/// Channel selection, FSK demodulation and decoding. Calls `on_message` for
+/// every decoded page.
+async fn decode<R>(
+    source: R,
+    baud_rate: f32,
+    mut on_message: impl FnMut(Message),
+) -> Result<(), Error>
+where
+    R: AsyncReadSamples<Complex<f32>> + GetSampleRate + Unpin,
+    R::Error: ClassifyError + std::error::Error + Send + Sync + 'static,
+{
+    let sample_rate = source.sample_rate();
+    println!("source sample rate: {sample_rate}");
+
+    // cheaply decimate by averaging first, if we have a high sample rate, like from
+    // an RTL-SDR.
+    let factor = (sample_rate / (4.0 * DEMODULATOR_SAMPLE_RATE)).max(1.0) as usize;
+    let baseband = AverageDecimate::<_, Complex<f32>>::new(source, factor);
+
+    // select the channel and decimate to the demodulator's sample rate.
+    let sample_rate = baseband.sample_rate();
+    let baseband = baseband
+        .scan_in_place_with(biquad::lowpass(sample_rate, 0.5 * CHANNEL_BANDWIDTH))
+        .decimate_to(DEMODULATOR_SAMPLE_RATE.min(sample_rate));
+
+    let sample_rate = baseband.sample_rate();
+    println!("demodulator sample rate: {sample_rate}");
+
+    let mut bits = baseband
+        .scan_with(AfskDemodulator::new(
+            pocsag::fsk_config(baud_rate),
+            sample_rate,
+        ))
+        .erase_err();
+
+    let mut decoder = PocsagDecoder::new();
+    let mut buffer = vec![None; 0x1000];
+    loop {
+        let num_samples = bits.read_samples(&mut buffer).await?;
+        if num_samples == 0 {
+            break;
+        }
+
+        for bit in buffer[..num_samples].iter().flatten() {
+            if let Some(message) = decoder.scan(*bit) {
+                on_message(message);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, clap::Parser)]
+enum Args {
+    /// Receive from an RTL-SDR.
+    Rtlsdr {
+        frequency: f32,
+
+        #[clap(short, long, default_value = "0")]
+        device: u32,
+
+        #[clap(short, long, default_value = "1200")]
+        baud_rate: f32,
+    },
+    /// Decode a complex IQ WAV file.
+    File {
+        input: PathBuf,
+
+        #[clap(short, long, default_value = "1200")]
+        baud_rate: f32,
+    },
+    /// Write an alphanumeric page to a complex IQ WAV file.
+    Encode {
+        output: PathBuf,
+
+        address: u32,
+
+        message: String,
+
+        #[clap(short, long, default_value = "1200")]
+        baud_rate: f32,
+
+        #[clap(short, long, default_value = "48000")]
+        sample_rate: f32,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use mrrp::source::file::WavSource;
+    use num_complex::Complex;
+
+    use super::decode;
+
+    #[tokio::test]
+    async fn it_decodes_the_test_capture() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/pocsag.wav");
+        let source = WavSource::<_, Complex<f32>>::from_path(path).unwrap();
+
+        let mut messages = vec![];
+        decode(source, 1200.0, |message| messages.push(message))
+            .await
+            .unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].address, 1234560);
+        assert_eq!(messages[0].function, 3);
+        assert!(!messages[0].has_errors);
+        assert_eq!(messages[0].text(), "Hello, pager!");
+    }
+}
@@ -14,21 +14,10 @@ use mrrp::{
         GoertzelFilter,
         MovingAverage,
         design::{
-            FilterDesign,
             Lowpass,
-            Normalize,
             pm_remez::pm_remez,
         },
     },
-    io::{
-        AsyncReadSamplesExt,
-        FiniteStream,
-        GetSampleRate,
-        combinators::{
-            Scanner,
-            ScannerExt,
-        },
-    },
     modem::{
         fm,
         sstv::{
@@ -45,10 +34,9 @@ use mrrp::{
             },
         },
     },
+    prelude::*,
     sink::file::write_stream_to_wav,
-    source::file::WavSource,
 };
-use num_complex::Complex;
 use plotters::{
     chart::{
         ChartBuilder,
@@ -159,8 +147,8 @@ async fn encode_image(
 }
 
 async fn decode_image(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<(), Error> {
-    //let source = WavSource::<_, f32>::from_path("tmp/Martin_1.wav")?;
-    let source = WavSource::<_, Complex<f32>>::from_path(input)?;
+    //let source = from_wav::<f32>("tmp/Martin_1.wav")?;
+    let source = from_wav::<Complex<f32>>(input)?;
     let sample_rate = source.sample_rate();
     let num_samples = source.len();
 
@@ -184,7 +172,7 @@ async fn decode_image(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Resu
 }
 
 async fn plot(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<(), Error> {
-    let source = WavSource::<_, Complex<f32>>::from_path(input)?;
+    let source = from_wav::<Complex<f32>>(input)?;
     let sample_rate = source.sample_rate();
     let num_samples = source.len();
 
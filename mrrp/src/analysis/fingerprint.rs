@@ -0,0 +1,726 @@
+//! Fingerprinting of recurring bursts
+//!
+//! **Experimental.** [`FingerprintDatabase`] watches power spectra for bursts,
+//! i.e. signals that rise above the noise floor and disappear again, and
+//! groups bursts that look alike: They're at the same frequency, have about
+//! the same duration and bandwidth, and the same [`SpectralShape`]. For each
+//! group it keeps a [`Fingerprint`], which tells how often the signal was seen
+//! and whether it repeats regularly, e.g. "433.92 MHz, 20 kHz wide, 120 ms
+//! bursts, every 60 s". This is meant for exploring bands like ISM, where lots
+//! of unknown devices transmit.
+//!
+//! Spectra are linear power like for the
+//! [`OccupancyMeter`][super::occupancy::OccupancyMeter], and a bin counts as
+//! busy if it is `threshold` dB above the median of its spectrum. Bursts are
+//! tracked from spectrum to spectrum, so the FFT hop has to be short enough to
+//! resolve them in time. Signals that stay on for longer than
+//! [`max_burst_duration`][FingerprintSettings::max_burst_duration] aren't
+//! bursts, and are ignored.
+//!
+//! With the `serde` feature the database can be serialized, to keep the
+//! fingerprints across sessions. Bursts that are in progress aren't stored.
+
+use std::{
+    fmt::Display,
+    ops::Range,
+    time::{
+        Duration,
+        SystemTime,
+    },
+};
+
+use crate::frequency::Frequency;
+
+/// Number of cells a [`SpectralShape`] has.
+pub const SHAPE_CELLS: usize = 8;
+
+/// Level step of a [`SpectralShape`] in dB.
+const SHAPE_STEP: f32 = 3.0;
+
+/// Lowest level of a [`SpectralShape`], i.e. 45 dB below the strongest cell.
+const MAX_SHAPE_LEVEL: u8 = 15;
+
+/// Busy bins that are separated by at most this many quiet bins belong to the
+/// same signal.
+const MERGE_GAP: usize = 2;
+
+/// A signal repeats regularly if the standard deviation of the intervals
+/// between its bursts is at most this fraction of their mean.
+const PERIOD_JITTER: f64 = 0.1;
+
+/// How many intervals between bursts are needed to tell whether a signal
+/// repeats regularly.
+const MIN_PERIOD_INTERVALS: usize = 2;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FingerprintSettings {
+    /// Level above the noise floor at which a bin is busy (dB).
+    pub threshold: f32,
+
+    /// How far apart the center frequencies of bursts with the same
+    /// fingerprint may be (Hz). For wide signals, half their bandwidth is
+    /// used if that's more.
+    pub frequency_tolerance: f64,
+
+    /// How much the durations of bursts with the same fingerprint may differ,
+    /// relative to the longer one.
+    pub duration_tolerance: f64,
+
+    /// How much the bandwidths of bursts with the same fingerprint may
+    /// differ, relative to the wider one.
+    pub bandwidth_tolerance: f64,
+
+    /// Largest [`SpectralShape::distance`] between bursts with the same
+    /// fingerprint.
+    pub max_shape_distance: u32,
+
+    /// Signals that are on for longer than this aren't bursts.
+    pub max_burst_duration: Duration,
+}
+
+impl Default for FingerprintSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 10.0,
+            frequency_tolerance: 10_000.0,
+            duration_tolerance: 0.25,
+            bandwidth_tolerance: 0.5,
+            max_shape_distance: 8,
+            max_burst_duration: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Coarse spectrum of a burst.
+///
+/// The burst's power spectrum, averaged over its duration, is resampled to
+/// [`SHAPE_CELLS`] cells across its bandwidth. Each cell stores how far below
+/// the strongest cell it is, in steps of 3 dB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpectralShape([u8; SHAPE_CELLS]);
+
+impl SpectralShape {
+    /// Computes the shape from linear power over the bandwidth of a burst.
+    pub fn from_power(power: &[f32]) -> Self {
+        let mut cells = [0.0f32; SHAPE_CELLS];
+        if !power.is_empty() {
+            for (i, cell) in cells.iter_mut().enumerate() {
+                // with less bins than cells, bins are repeated
+                let start = i * power.len() / SHAPE_CELLS;
+                let end = ((i + 1) * power.len() / SHAPE_CELLS).max(start + 1);
+                let bins = &power[start..end];
+                *cell = bins.iter().sum::<f32>() / bins.len() as f32;
+            }
+        }
+
+        let max = cells.iter().copied().fold(0.0, f32::max);
+        Self(cells.map(|cell| {
+            if max > 0.0 && cell > 0.0 {
+                let level = 10.0 * (max / cell).log10() / SHAPE_STEP;
+                level.round().min(MAX_SHAPE_LEVEL.into()) as u8
+            }
+            else {
+                MAX_SHAPE_LEVEL
+            }
+        }))
+    }
+
+    /// Level of each cell, in 3 dB steps below the strongest cell.
+    #[inline]
+    pub fn levels(&self) -> &[u8; SHAPE_CELLS] {
+        &self.0
+    }
+
+    /// Packs the levels into a hash with 4 bits per cell. Equal shapes have
+    /// equal digests.
+    pub fn digest(&self) -> u32 {
+        self.0
+            .iter()
+            .fold(0, |digest, level| digest << 4 | u32::from(*level))
+    }
+
+    /// Sum of the level differences of all cells.
+    pub fn distance(&self, other: &Self) -> u32 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| u32::from(a.abs_diff(*b)))
+            .sum()
+    }
+}
+
+/// A burst that has ended.
+#[derive(Clone, Copy, Debug)]
+pub struct Burst {
+    /// When the first spectrum the burst was in started.
+    pub start: SystemTime,
+
+    pub duration: Duration,
+
+    /// Power-weighted center of the burst.
+    pub center_frequency: Frequency,
+
+    /// Width of the busy bins (Hz).
+    pub bandwidth: f64,
+
+    pub shape: SpectralShape,
+}
+
+/// A group of bursts that look alike, i.e. probably the same transmitter.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fingerprint {
+    /// Mean center frequency of the bursts.
+    pub center_frequency: Frequency,
+
+    /// Mean bandwidth of the bursts (Hz).
+    pub bandwidth: f64,
+
+    /// Mean duration of the bursts.
+    pub duration: Duration,
+
+    /// Shape of the first burst.
+    pub shape: SpectralShape,
+
+    /// Number of bursts seen.
+    pub count: usize,
+
+    pub first_seen: SystemTime,
+
+    /// Start of the last burst.
+    pub last_seen: SystemTime,
+
+    intervals: IntervalStats,
+}
+
+impl Fingerprint {
+    fn new(burst: &Burst) -> Self {
+        Self {
+            center_frequency: burst.center_frequency,
+            bandwidth: burst.bandwidth,
+            duration: burst.duration,
+            shape: burst.shape,
+            count: 1,
+            first_seen: burst.start,
+            last_seen: burst.start,
+            intervals: IntervalStats::default(),
+        }
+    }
+
+    /// Mean time between the starts of consecutive bursts, or `None` if it
+    /// was only seen once.
+    pub fn mean_interval(&self) -> Option<Duration> {
+        (self.intervals.count > 0).then(|| Duration::from_secs_f64(self.intervals.mean))
+    }
+
+    /// The interval at which the signal repeats, if it does so regularly.
+    ///
+    /// A missed burst makes one interval twice as long, so this only works
+    /// for signals that are received reliably.
+    pub fn period(&self) -> Option<Duration> {
+        let intervals = &self.intervals;
+        (intervals.count >= MIN_PERIOD_INTERVALS
+            && intervals.standard_deviation() <= PERIOD_JITTER * intervals.mean)
+            .then(|| Duration::from_secs_f64(intervals.mean))
+    }
+
+    fn matches(&self, burst: &Burst, settings: &FingerprintSettings) -> bool {
+        let frequency_tolerance = settings.frequency_tolerance.max(0.5 * self.bandwidth);
+        let frequency_offset = burst
+            .center_frequency
+            .offset_from(self.center_frequency)
+            .unsigned_abs() as f64;
+
+        frequency_offset <= frequency_tolerance
+            && relative_difference(burst.duration.as_secs_f64(), self.duration.as_secs_f64())
+                <= settings.duration_tolerance
+            && relative_difference(burst.bandwidth, self.bandwidth) <= settings.bandwidth_tolerance
+            && burst.shape.distance(&self.shape) <= settings.max_shape_distance
+    }
+
+    fn update(&mut self, burst: &Burst) {
+        self.count += 1;
+        let weight = 1.0 / self.count as f64;
+        let mean = |mean: f64, value: f64| mean + weight * (value - mean);
+
+        self.center_frequency = Frequency::from_hz_f64(mean(
+            self.center_frequency.as_f64(),
+            burst.center_frequency.as_f64(),
+        ));
+        self.bandwidth = mean(self.bandwidth, burst.bandwidth);
+        self.duration = Duration::from_nanos(
+            mean(
+                self.duration.as_nanos() as f64,
+                burst.duration.as_nanos() as f64,
+            )
+            .round() as u64,
+        );
+
+        if let Ok(interval) = burst.start.duration_since(self.last_seen) {
+            self.intervals.push(interval.as_secs_f64());
+        }
+        self.last_seen = self.last_seen.max(burst.start);
+    }
+}
+
+/// Formats like `433.92 MHz, 20 kHz wide, 120 ms bursts, every 60 s, seen 5
+/// times`.
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, {} wide, {} ms bursts",
+            self.center_frequency,
+            Frequency::from_hz_f64(self.bandwidth),
+            self.duration.as_millis()
+        )?;
+        if let Some(period) = self.period() {
+            write!(f, ", every {:.0} s", period.as_secs_f64())?;
+        }
+        write!(f, ", seen {} times", self.count)
+    }
+}
+
+/// Running mean and variance of the intervals between bursts (s).
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct IntervalStats {
+    count: usize,
+    mean: f64,
+    /// Sum of squared differences from the mean.
+    m2: f64,
+}
+
+impl IntervalStats {
+    fn push(&mut self, interval: f64) {
+        self.count += 1;
+        let delta = interval - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (interval - self.mean);
+    }
+
+    fn standard_deviation(&self) -> f64 {
+        if self.count > 1 {
+            (self.m2 / (self.count - 1) as f64).sqrt()
+        }
+        else {
+            0.0
+        }
+    }
+}
+
+/// A burst that was matched to a fingerprint.
+#[derive(Clone, Copy, Debug)]
+pub struct Sighting {
+    pub burst: Burst,
+
+    /// Index of the fingerprint in [`FingerprintDatabase::fingerprints`].
+    pub fingerprint: usize,
+
+    /// Whether the burst didn't match any known fingerprint, and a new one
+    /// was created.
+    pub new: bool,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FingerprintDatabase {
+    settings: FingerprintSettings,
+    fingerprints: Vec<Fingerprint>,
+
+    /// Layout of the spectra the open bursts were found in.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    layout: Option<(f64, f64, usize)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    open_bursts: Vec<OpenBurst>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    sorted: Vec<f32>,
+}
+
+impl FingerprintDatabase {
+    pub fn new(settings: FingerprintSettings) -> Self {
+        Self::with_fingerprints(settings, vec![])
+    }
+
+    /// Creates a database that already knows some fingerprints, e.g. from a
+    /// previous session.
+    pub fn with_fingerprints(
+        settings: FingerprintSettings,
+        fingerprints: Vec<Fingerprint>,
+    ) -> Self {
+        Self {
+            settings,
+            fingerprints,
+            layout: None,
+            open_bursts: vec![],
+            sorted: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn settings(&self) -> &FingerprintSettings {
+        &self.settings
+    }
+
+    #[inline]
+    pub fn fingerprints(&self) -> &[Fingerprint] {
+        &self.fingerprints
+    }
+
+    #[inline]
+    pub fn into_fingerprints(self) -> Vec<Fingerprint> {
+        self.fingerprints
+    }
+
+    /// Adds a spectrum that started at `timestamp` and covers `duration`.
+    ///
+    /// Returns the bursts that ended with this spectrum. If the frequency
+    /// layout of the spectra changed, e.g. because the receiver was retuned,
+    /// all bursts that were in progress end.
+    pub fn push(
+        &mut self,
+        timestamp: SystemTime,
+        power: &[f32],
+        start_frequency: f64,
+        bin_width: f64,
+        duration: Duration,
+    ) -> Vec<Sighting> {
+        let mut sightings = vec![];
+        if power.is_empty() {
+            return sightings;
+        }
+
+        let layout = (start_frequency, bin_width, power.len());
+        if self.layout != Some(layout) {
+            sightings = self.flush();
+            self.layout = Some(layout);
+        }
+
+        // median of all bins, like in the occupancy meter
+        self.sorted.clear();
+        self.sorted.extend_from_slice(power);
+        let middle = self.sorted.len() / 2;
+        let (_, noise_floor, _) = self.sorted.select_nth_unstable_by(middle, f32::total_cmp);
+        let busy_level = *noise_floor * 10.0f32.powf(self.settings.threshold / 10.0);
+
+        for burst in &mut self.open_bursts {
+            burst.seen = false;
+        }
+
+        let mut bin = 0;
+        while bin < power.len() {
+            if power[bin] < busy_level {
+                bin += 1;
+                continue;
+            }
+
+            let start = bin;
+            let mut end = bin + 1;
+            for (i, value) in power.iter().enumerate().skip(end) {
+                if *value >= busy_level {
+                    end = i + 1;
+                }
+                else if i - end >= MERGE_GAP {
+                    break;
+                }
+            }
+            self.add_busy_bins(start..end, power, timestamp, duration);
+            bin = end;
+        }
+
+        let ended = self
+            .open_bursts
+            .extract_if(.., |burst| !burst.seen)
+            .collect::<Vec<_>>();
+        sightings.extend(
+            ended
+                .into_iter()
+                .filter_map(|burst| self.record(burst.finish(start_frequency, bin_width))),
+        );
+
+        sightings
+    }
+
+    /// Ends all bursts that are in progress, e.g. when there are no more
+    /// spectra.
+    pub fn flush(&mut self) -> Vec<Sighting> {
+        let Some((start_frequency, bin_width, _)) = self.layout
+        else {
+            return vec![];
+        };
+
+        std::mem::take(&mut self.open_bursts)
+            .into_iter()
+            .filter_map(|burst| self.record(burst.finish(start_frequency, bin_width)))
+            .collect()
+    }
+
+    fn add_busy_bins(
+        &mut self,
+        bins: Range<usize>,
+        power: &[f32],
+        timestamp: SystemTime,
+        duration: Duration,
+    ) {
+        if let Some(burst) = self
+            .open_bursts
+            .iter_mut()
+            .find(|burst| burst.bins.start < bins.end && bins.start < burst.bins.end)
+        {
+            burst.add(bins, power, duration);
+        }
+        else {
+            let mut burst = OpenBurst {
+                start: timestamp,
+                duration: Duration::ZERO,
+                bins: bins.clone(),
+                power: vec![0.0; bins.len()],
+                seen: false,
+            };
+            burst.add(bins, power, duration);
+            self.open_bursts.push(burst);
+        }
+    }
+
+    fn record(&mut self, burst: Burst) -> Option<Sighting> {
+        if burst.duration > self.settings.max_burst_duration {
+            return None;
+        }
+
+        let matching = self
+            .fingerprints
+            .iter()
+            .enumerate()
+            .filter(|(_, fingerprint)| fingerprint.matches(&burst, &self.settings))
+            .min_by_key(|(_, fingerprint)| {
+                burst
+                    .center_frequency
+                    .offset_from(fingerprint.center_frequency)
+                    .unsigned_abs()
+            })
+            .map(|(index, _)| index);
+
+        let sighting = if let Some(index) = matching {
+            self.fingerprints[index].update(&burst);
+            Sighting {
+                burst,
+                fingerprint: index,
+                new: false,
+            }
+        }
+        else {
+            self.fingerprints.push(Fingerprint::new(&burst));
+            Sighting {
+                burst,
+                fingerprint: self.fingerprints.len() - 1,
+                new: true,
+            }
+        };
+
+        Some(sighting)
+    }
+}
+
+/// A burst that is in progress.
+#[derive(Clone, Debug)]
+struct OpenBurst {
+    start: SystemTime,
+    duration: Duration,
+    bins: Range<usize>,
+    /// Power summed over the spectra, for each bin in `bins`.
+    power: Vec<f32>,
+    /// Whether the burst was in the current spectrum.
+    seen: bool,
+}
+
+impl OpenBurst {
+    fn add(&mut self, bins: Range<usize>, power: &[f32], duration: Duration) {
+        let start = self.bins.start.min(bins.start);
+        let end = self.bins.end.max(bins.end);
+        if (start..end) != self.bins {
+            let mut grown = vec![0.0; end - start];
+            grown[self.bins.start - start..self.bins.end - start].copy_from_slice(&self.power);
+            self.power = grown;
+            self.bins = start..end;
+        }
+
+        for (sum, power) in self.power[bins.start - start..bins.end - start]
+            .iter_mut()
+            .zip(&power[bins])
+        {
+            *sum += power;
+        }
+
+        // a burst can cover several groups of busy bins in a spectrum
+        if !self.seen {
+            self.seen = true;
+            self.duration += duration;
+        }
+    }
+
+    fn finish(self, start_frequency: f64, bin_width: f64) -> Burst {
+        let total = self.power.iter().sum::<f32>();
+        let centroid = if total > 0.0 {
+            self.power
+                .iter()
+                .enumerate()
+                .map(|(i, power)| (i as f64 + 0.5) * f64::from(*power))
+                .sum::<f64>()
+                / f64::from(total)
+        }
+        else {
+            0.5 * self.power.len() as f64
+        };
+
+        Burst {
+            start: self.start,
+            duration: self.duration,
+            center_frequency: Frequency::from_hz_f64(
+                start_frequency + (self.bins.start as f64 + centroid) * bin_width,
+            ),
+            bandwidth: self.bins.len() as f64 * bin_width,
+            shape: SpectralShape::from_power(&self.power),
+        }
+    }
+}
+
+fn relative_difference(a: f64, b: f64) -> f64 {
+    let max = a.abs().max(b.abs());
+    if max > 0.0 { (a - b).abs() / max } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{
+        Duration,
+        SystemTime,
+    };
+
+    use super::{
+        FingerprintDatabase,
+        FingerprintSettings,
+        Sighting,
+        SpectralShape,
+    };
+    use crate::frequency::Frequency;
+
+    const HOP: Duration = Duration::from_millis(100);
+
+    /// 200 bins of 1 kHz around 433.92 MHz, with noise at 1.
+    const START_FREQUENCY: f64 = 433_820_000.0;
+
+    /// Runs `seconds` of spectra through the database. `signals` returns the
+    /// busy bins at a time (ms).
+    fn run(
+        database: &mut FingerprintDatabase,
+        seconds: u64,
+        signals: impl Fn(u64) -> Vec<std::ops::Range<usize>>,
+    ) -> Vec<Sighting> {
+        let num_spectra = seconds * 1000 / HOP.as_millis() as u64;
+        let mut sightings = vec![];
+        for i in 0..num_spectra {
+            let mut power = vec![1.0; 200];
+            for bins in signals(i * HOP.as_millis() as u64) {
+                power[bins].fill(1000.0);
+            }
+            sightings.extend(database.push(
+                SystemTime::UNIX_EPOCH + HOP * i as u32,
+                &power,
+                START_FREQUENCY,
+                1000.0,
+                HOP,
+            ));
+        }
+        sightings.extend(database.flush());
+        sightings
+    }
+
+    #[test]
+    fn it_finds_the_period_of_a_repeating_burst() {
+        let mut database = FingerprintDatabase::new(FingerprintSettings::default());
+
+        // 300 ms, 20 kHz wide, every 60 s
+        let sightings = run(&mut database, 300, |time| {
+            if time % 60_000 < 300 {
+                vec![90..110]
+            }
+            else {
+                vec![]
+            }
+        });
+
+        assert_eq!(sightings.len(), 5);
+        assert!(sightings[0].new);
+        assert!(sightings[1..].iter().all(|sighting| !sighting.new));
+
+        let [fingerprint] = database.fingerprints()
+        else {
+            panic!("expected one fingerprint: {:#?}", database.fingerprints());
+        };
+        assert_eq!(fingerprint.count, 5);
+        assert_eq!(
+            fingerprint.center_frequency,
+            Frequency::from_hz(433_920_000)
+        );
+        assert_eq!(fingerprint.bandwidth, 20_000.0);
+        assert_eq!(fingerprint.duration, Duration::from_millis(300));
+        assert_eq!(fingerprint.period(), Some(Duration::from_secs(60)));
+        assert_eq!(
+            fingerprint.to_string(),
+            "433.92 MHz, 20 kHz wide, 300 ms bursts, every 60 s, seen 5 times"
+        );
+    }
+
+    #[test]
+    fn it_tells_signals_apart() {
+        let mut database = FingerprintDatabase::new(FingerprintSettings::default());
+
+        let sightings = run(&mut database, 100, |time| {
+            let mut signals = vec![
+                // a carrier that is always on
+                10..12,
+            ];
+            // a narrow burst every 10 s, and a wide one at irregular times
+            if time % 10_000 < 200 {
+                signals.push(95..100);
+            }
+            if [7, 23, 61].contains(&(time / 1000)) {
+                signals.push(60..140);
+            }
+            signals
+        });
+
+        assert_eq!(sightings.len(), 13);
+        let fingerprints = database.fingerprints();
+        assert_eq!(fingerprints.len(), 2, "{fingerprints:#?}");
+
+        let narrow = &fingerprints[sightings[0].fingerprint];
+        assert_eq!(narrow.count, 10);
+        assert_eq!(narrow.bandwidth, 5_000.0);
+        assert_eq!(narrow.period(), Some(Duration::from_secs(10)));
+
+        let wide = &fingerprints[1 - sightings[0].fingerprint];
+        assert_eq!(wide.count, 3);
+        assert_eq!(wide.bandwidth, 80_000.0);
+        assert_eq!(wide.duration, Duration::from_secs(1));
+        assert_eq!(wide.period(), None);
+    }
+
+    #[test]
+    fn shapes_are_relative_to_the_strongest_cell() {
+        let flat = SpectralShape::from_power(&[2.0; 16]);
+        assert_eq!(flat.levels(), &[0; 8]);
+        assert_eq!(flat.digest(), 0);
+
+        // one cell 6 dB down, one 30 dB down
+        let mut power = [4.0; 8];
+        power[2] = 1.0;
+        power[5] = 0.004;
+        let shape = SpectralShape::from_power(&power);
+        assert_eq!(shape.levels(), &[0, 0, 2, 0, 0, 10, 0, 0]);
+        assert_eq!(shape.distance(&flat), 12);
+        assert_eq!(shape.digest(), 0x0020_0a00);
+    }
+}
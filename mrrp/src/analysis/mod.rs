@@ -0,0 +1,6 @@
+//! Tools for analysing signals and spectra.
+
+pub mod fingerprint;
+pub mod occupancy;
+pub mod peaks;
+pub mod sideband;
@@ -0,0 +1,438 @@
+//! Channel power and occupancy per frequency band
+//!
+//! [`OccupancyMeter`] takes power spectra and measures, for each of a set of
+//! bands (e.g. from a bandplan), how much power is in it and how often it is
+//! in use. A band counts as busy in a spectrum if its mean power is at least
+//! `threshold` dB above the noise floor of that spectrum. The statistics are
+//! collected over a report interval and then returned as an
+//! [`OccupancyReport`].
+//!
+//! Spectra are linear power (e.g. `|X|²` of the FFT output), ordered by
+//! frequency, with bin 0 starting at `start_frequency`.
+
+use std::{
+    ops::Range,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+    time::Duration,
+};
+
+use futures_util::Stream;
+use pin_project_lite::pin_project;
+
+use crate::frequency::Frequency;
+
+/// A power spectrum and how much time it covers.
+#[derive(Clone, Debug)]
+pub struct PowerSpectrum {
+    /// Linear power per bin.
+    pub power: Vec<f32>,
+
+    /// Lower edge of bin 0 in Hz.
+    pub start_frequency: f64,
+
+    /// Width of a bin in Hz.
+    pub bin_width: f64,
+
+    /// Time between this spectrum and the next, i.e. the FFT hop.
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+pub struct OccupancyMeter {
+    bands: Vec<Range<Frequency>>,
+    threshold: f32,
+    report_interval: Duration,
+
+    /// Layout of the spectra the bin ranges were computed for.
+    layout: Option<(f64, f64, usize)>,
+    bin_ranges: Vec<Range<usize>>,
+
+    accumulators: Vec<BandAccumulator>,
+    elapsed: Duration,
+    sorted: Vec<f32>,
+}
+
+impl OccupancyMeter {
+    pub fn new(bands: Vec<Range<Frequency>>, threshold: f32, report_interval: Duration) -> Self {
+        let accumulators = vec![BandAccumulator::default(); bands.len()];
+        Self {
+            bands,
+            threshold,
+            report_interval,
+            layout: None,
+            bin_ranges: vec![],
+            accumulators,
+            elapsed: Duration::ZERO,
+            sorted: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn bands(&self) -> &[Range<Frequency>] {
+        &self.bands
+    }
+
+    /// Adds a spectrum to the statistics.
+    ///
+    /// Returns a report once the spectra since the last report cover the
+    /// report interval.
+    pub fn push(
+        &mut self,
+        power: &[f32],
+        start_frequency: f64,
+        bin_width: f64,
+        duration: Duration,
+    ) -> Option<OccupancyReport> {
+        if power.is_empty() {
+            return None;
+        }
+
+        if self.layout != Some((start_frequency, bin_width, power.len())) {
+            self.layout = Some((start_frequency, bin_width, power.len()));
+            self.bin_ranges = self
+                .bands
+                .iter()
+                .map(|band| bin_range(band, start_frequency, bin_width, power.len()))
+                .collect();
+        }
+
+        // median of all bins, like peaks::noise_floor, but without allocating every
+        // time
+        self.sorted.clear();
+        self.sorted.extend_from_slice(power);
+        let middle = self.sorted.len() / 2;
+        let (_, noise_floor, _) = self.sorted.select_nth_unstable_by(middle, f32::total_cmp);
+        let busy_level = *noise_floor * 10.0f32.powf(self.threshold / 10.0);
+
+        for (accumulator, bins) in self.accumulators.iter_mut().zip(&self.bin_ranges) {
+            if bins.is_empty() {
+                continue;
+            }
+
+            let channel_power = power[bins.clone()].iter().sum::<f32>();
+            let busy = channel_power / bins.len() as f32 >= busy_level;
+            accumulator.push(channel_power, busy, duration);
+        }
+
+        self.elapsed += duration;
+        (self.elapsed >= self.report_interval).then(|| self.report())
+    }
+
+    /// Returns the statistics since the last report and starts a new
+    /// interval.
+    pub fn report(&mut self) -> OccupancyReport {
+        let report = OccupancyReport {
+            interval: self.elapsed,
+            bands: self
+                .bands
+                .iter()
+                .zip(&mut self.accumulators)
+                .map(|(band, accumulator)| accumulator.report(band.clone()))
+                .collect(),
+        };
+        self.elapsed = Duration::ZERO;
+        report
+    }
+
+    /// Turns a stream of spectra into a stream of reports.
+    pub fn reports<St>(self, stream: St) -> OccupancyReports<St>
+    where
+        St: Stream<Item = PowerSpectrum>,
+    {
+        OccupancyReports {
+            stream,
+            meter: self,
+        }
+    }
+}
+
+/// Bins whose center lies in `band`. If the band is narrower than a bin,
+/// the bin containing its center.
+fn bin_range(
+    band: &Range<Frequency>,
+    start_frequency: f64,
+    bin_width: f64,
+    num_bins: usize,
+) -> Range<usize> {
+    let to_bin = |frequency: f64| (frequency - start_frequency) / bin_width;
+    let clamp = |bin: f64| (bin.max(0.0) as usize).min(num_bins);
+
+    let start = clamp((to_bin(band.start.as_f64()) - 0.5).ceil());
+    let end = clamp((to_bin(band.end.as_f64()) - 0.5).ceil());
+    if start < end {
+        return start..end;
+    }
+
+    let center = to_bin(0.5 * (band.start.as_f64() + band.end.as_f64()));
+    if center >= 0.0 && center < num_bins as f64 {
+        let bin = center as usize;
+        bin..bin + 1
+    }
+    else {
+        0..0
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct BandAccumulator {
+    num_spectra: usize,
+    observed: Duration,
+    busy: Duration,
+    power_sum: f64,
+    peak_power: f32,
+    num_bursts: usize,
+    longest_burst: Duration,
+    /// Length of the burst that is going on, if the band is busy. This
+    /// carries over into the next interval.
+    current_burst: Option<Duration>,
+}
+
+impl BandAccumulator {
+    fn push(&mut self, channel_power: f32, busy: bool, duration: Duration) {
+        self.num_spectra += 1;
+        self.observed += duration;
+        self.power_sum += f64::from(channel_power);
+        self.peak_power = self.peak_power.max(channel_power);
+
+        if busy {
+            self.busy += duration;
+            let burst = self.current_burst.get_or_insert_with(|| {
+                self.num_bursts += 1;
+                Duration::ZERO
+            });
+            *burst += duration;
+            self.longest_burst = self.longest_burst.max(*burst);
+        }
+        else {
+            self.current_burst = None;
+        }
+    }
+
+    fn report(&mut self, band: Range<Frequency>) -> BandReport {
+        let observed = self.num_spectra > 0;
+        let report = BandReport {
+            band,
+            observed: self.observed,
+            mean_power: observed
+                .then(|| 10.0 * (self.power_sum / self.num_spectra as f64).log10() as f32),
+            peak_power: observed.then(|| 10.0 * self.peak_power.log10()),
+            duty_cycle: if !self.observed.is_zero() {
+                self.busy.as_secs_f32() / self.observed.as_secs_f32()
+            }
+            else {
+                0.0
+            },
+            num_bursts: self.num_bursts,
+            longest_burst: self.longest_burst,
+            busy: self.current_burst.is_some(),
+        };
+
+        *self = Self {
+            longest_burst: self.current_burst.unwrap_or_default(),
+            current_burst: self.current_burst,
+            ..Default::default()
+        };
+
+        report
+    }
+}
+
+/// Occupancy statistics over one report interval.
+#[derive(Clone, Debug)]
+pub struct OccupancyReport {
+    /// Time covered by the spectra in this report.
+    pub interval: Duration,
+
+    /// Statistics per band, in the order the bands were passed to
+    /// [`OccupancyMeter::new`].
+    pub bands: Vec<BandReport>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BandReport {
+    pub band: Range<Frequency>,
+
+    /// Time the band was covered by the spectra. This is less than the
+    /// report interval if the band is (partially) outside of the spectra.
+    pub observed: Duration,
+
+    /// Mean channel power (dB), or `None` if the band wasn't observed.
+    pub mean_power: Option<f32>,
+
+    /// Highest channel power (dB), or `None` if the band wasn't observed.
+    pub peak_power: Option<f32>,
+
+    /// Fraction of the observed time the band was busy.
+    pub duty_cycle: f32,
+
+    /// Number of busy periods that started in this interval.
+    pub num_bursts: usize,
+
+    /// Longest busy period. A busy period that continues from the last
+    /// interval is counted with its full length.
+    pub longest_burst: Duration,
+
+    /// Whether the band was busy in the last spectrum.
+    pub busy: bool,
+}
+
+pin_project! {
+    /// Stream of [`OccupancyReport`]s, created by
+    /// [`OccupancyMeter::reports`].
+    ///
+    /// When the stream of spectra ends, a last report over the remaining
+    /// spectra is returned, if there are any.
+    #[derive(Debug)]
+    pub struct OccupancyReports<St> {
+        #[pin]
+        stream: St,
+        meter: OccupancyMeter,
+    }
+}
+
+impl<St> OccupancyReports<St> {
+    #[inline]
+    pub fn meter(&self) -> &OccupancyMeter {
+        &self.meter
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> St {
+        self.stream
+    }
+}
+
+impl<St> Stream for OccupancyReports<St>
+where
+    St: Stream<Item = PowerSpectrum>,
+{
+    type Item = OccupancyReport;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            let Some(spectrum) = ready!(this.stream.as_mut().poll_next(cx))
+            else {
+                let report = (this.meter.elapsed > Duration::ZERO).then(|| this.meter.report());
+                return Poll::Ready(report);
+            };
+
+            if let Some(report) = this.meter.push(
+                &spectrum.power,
+                spectrum.start_frequency,
+                spectrum.bin_width,
+                spectrum.duration,
+            ) {
+                return Poll::Ready(Some(report));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use approx::assert_abs_diff_eq;
+    use futures_util::{
+        FutureExt,
+        StreamExt,
+        stream,
+    };
+
+    use super::{
+        OccupancyMeter,
+        PowerSpectrum,
+    };
+    use crate::frequency::Frequency;
+
+    const HOP: Duration = Duration::from_millis(10);
+
+    /// 100 bins of 1 kHz from 0 Hz, with noise at 1 and a signal of power 100
+    /// in bins 10..15 if `busy`.
+    fn spectrum(busy: bool) -> PowerSpectrum {
+        let mut power = vec![1.0; 100];
+        if busy {
+            power[10..15].fill(100.0);
+        }
+        PowerSpectrum {
+            power,
+            start_frequency: 0.0,
+            bin_width: 1000.0,
+            duration: HOP,
+        }
+    }
+
+    fn bands() -> Vec<std::ops::Range<Frequency>> {
+        vec![
+            Frequency::from_khz(10)..Frequency::from_khz(15),
+            Frequency::from_khz(50)..Frequency::from_khz(60),
+            Frequency::from_khz(200)..Frequency::from_khz(300),
+        ]
+    }
+
+    #[test]
+    fn it_measures_duty_cycle_and_bursts() {
+        let mut meter = OccupancyMeter::new(bands(), 10.0, Duration::from_millis(100));
+
+        // two bursts of 30 and 20 ms
+        let pattern = [
+            true, true, true, false, false, true, true, false, false, false,
+        ];
+        let mut reports = pattern.into_iter().filter_map(|busy| {
+            let spectrum = spectrum(busy);
+            meter.push(
+                &spectrum.power,
+                spectrum.start_frequency,
+                spectrum.bin_width,
+                spectrum.duration,
+            )
+        });
+        let report = reports.next().unwrap();
+        assert!(reports.next().is_none());
+
+        let [signal, quiet, outside] = &report.bands[..]
+        else {
+            panic!("expected 3 bands");
+        };
+
+        assert_abs_diff_eq!(signal.duty_cycle, 0.5, epsilon = 1e-6);
+        assert_eq!(signal.num_bursts, 2);
+        assert_eq!(signal.longest_burst, Duration::from_millis(30));
+        assert!(!signal.busy);
+        // 5 bins of power 100
+        assert_abs_diff_eq!(signal.peak_power.unwrap(), 27.0, epsilon = 0.02);
+
+        assert_eq!(quiet.duty_cycle, 0.0);
+        assert_abs_diff_eq!(quiet.mean_power.unwrap(), 10.0, epsilon = 0.01);
+
+        assert_eq!(outside.observed, Duration::ZERO);
+        assert_eq!(outside.mean_power, None);
+    }
+
+    #[test]
+    fn it_reports_from_a_stream() {
+        let meter = OccupancyMeter::new(bands(), 10.0, Duration::from_millis(50));
+        let spectra = (0..12).map(|i| spectrum(i >= 6));
+        let reports = meter
+            .reports(stream::iter(spectra))
+            .collect::<Vec<_>>()
+            .now_or_never()
+            .expect("test stream pending");
+
+        // 2 full intervals and the remaining 20 ms
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[2].interval, Duration::from_millis(20));
+        assert_eq!(reports[0].bands[0].duty_cycle, 0.0);
+        assert_eq!(reports[2].bands[0].duty_cycle, 1.0);
+        // the burst started in the second interval and continues
+        assert_eq!(reports[2].bands[0].num_bursts, 0);
+        assert_eq!(reports[2].bands[0].longest_burst, Duration::from_millis(60));
+    }
+}
@@ -0,0 +1,299 @@
+//! Spectral peak detection
+//!
+//! Finds peaks in a power spectrum that stand out from the noise floor and
+//! estimates their center and bandwidth. All levels are expected to be in dB
+//! and all positions are in (fractional) bins. Use [`Peak::center_frequency`]
+//! and [`Peak::bandwidth_hz`] to convert to frequencies.
+
+#[derive(Clone, Copy, Debug)]
+pub struct PeakDetector {
+    /// Minimum height of a peak above the noise floor (dB).
+    pub threshold: f32,
+
+    /// Minimum prominence of a peak (dB).
+    ///
+    /// The prominence is how far a peak rises above the higher of the two
+    /// lowest points that separate it from any higher parts of the spectrum.
+    pub min_prominence: f32,
+
+    /// Minimum distance between two peaks (bins). If two peaks are closer,
+    /// only the higher one is kept.
+    pub min_distance: usize,
+
+    /// How far below the peak the bandwidth is measured (dB).
+    pub bandwidth_level: f32,
+}
+
+impl Default for PeakDetector {
+    fn default() -> Self {
+        Self {
+            threshold: 10.0,
+            min_prominence: 6.0,
+            min_distance: 4,
+            bandwidth_level: 3.0,
+        }
+    }
+}
+
+impl PeakDetector {
+    pub fn new(threshold: f32, min_prominence: f32, min_distance: usize) -> Self {
+        Self {
+            threshold,
+            min_prominence,
+            min_distance,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_bandwidth_level(mut self, bandwidth_level: f32) -> Self {
+        self.bandwidth_level = bandwidth_level;
+        self
+    }
+
+    /// Finds all peaks in the spectrum. The peaks are returned sorted by their
+    /// position.
+    pub fn find_peaks(&self, spectrum: &[f32]) -> Vec<Peak> {
+        let mut peaks = vec![];
+        self.find_peaks_into(spectrum, &mut peaks, &mut vec![]);
+        peaks
+    }
+
+    /// Same as [`find_peaks`][Self::find_peaks], but reuses the provided
+    /// vectors, so it doesn't allocate once they're large enough. `peaks` is
+    /// cleared first, `sorted` is scratch space for [`noise_floor_with`].
+    pub fn find_peaks_into(&self, spectrum: &[f32], peaks: &mut Vec<Peak>, sorted: &mut Vec<f32>) {
+        peaks.clear();
+
+        if spectrum.len() < 3 {
+            return;
+        }
+
+        let Some(noise_floor) = noise_floor_with(spectrum, sorted)
+        else {
+            return;
+        };
+
+        for index in 1..spectrum.len() - 1 {
+            let value = spectrum[index];
+
+            // local maximum. for plateaus only the left-most sample is a candidate.
+            if !value.is_finite() || value <= spectrum[index - 1] || value < spectrum[index + 1] {
+                continue;
+            }
+
+            let snr = value - noise_floor;
+            if snr < self.threshold {
+                continue;
+            }
+
+            let (prominence, left_base, right_base) = prominence(spectrum, index);
+            if prominence < self.min_prominence {
+                continue;
+            }
+
+            let level = value - self.bandwidth_level;
+            let left = crossing(spectrum, index, left_base, level);
+            let right = crossing(spectrum, index, right_base, level);
+
+            peaks.push(Peak {
+                index,
+                value,
+                snr,
+                prominence,
+                center: 0.5 * (left + right),
+                bandwidth: right - left,
+            });
+        }
+
+        if self.min_distance > 1 && peaks.len() > 1 {
+            // keep the highest peaks first and drop everything that is too close to a peak
+            // we already kept.
+            peaks.sort_by(|a, b| b.value.total_cmp(&a.value));
+
+            let mut num_kept = 0;
+            for i in 0..peaks.len() {
+                let index = peaks[i].index;
+                if peaks[..num_kept]
+                    .iter()
+                    .all(|kept| kept.index.abs_diff(index) >= self.min_distance)
+                {
+                    peaks.swap(num_kept, i);
+                    num_kept += 1;
+                }
+            }
+            peaks.truncate(num_kept);
+
+            peaks.sort_by_key(|peak| peak.index);
+        }
+    }
+}
+
+/// A peak found by [`PeakDetector`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    /// Index of the highest bin of the peak.
+    pub index: usize,
+
+    /// Level of the highest bin (dB).
+    pub value: f32,
+
+    /// Height above the estimated noise floor (dB).
+    pub snr: f32,
+
+    /// Prominence of the peak (dB).
+    pub prominence: f32,
+
+    /// Estimated center of the peak (fractional bins).
+    pub center: f32,
+
+    /// Estimated bandwidth of the peak (fractional bins).
+    pub bandwidth: f32,
+}
+
+impl Peak {
+    /// Center frequency of the peak, given the frequency of bin 0 and the
+    /// width of a bin.
+    #[inline]
+    pub fn center_frequency(&self, start_frequency: f32, bin_width: f32) -> f32 {
+        start_frequency + self.center * bin_width
+    }
+
+    #[inline]
+    pub fn bandwidth_hz(&self, bin_width: f32) -> f32 {
+        self.bandwidth * bin_width
+    }
+}
+
+/// Estimates the noise floor of the spectrum as the median of all bins.
+///
+/// Returns `None` if the spectrum is empty.
+pub fn noise_floor(spectrum: &[f32]) -> Option<f32> {
+    noise_floor_with(spectrum, &mut vec![])
+}
+
+/// Same as [`noise_floor`], but sorts in `sorted` instead of a copy of the
+/// spectrum.
+pub fn noise_floor_with(spectrum: &[f32], sorted: &mut Vec<f32>) -> Option<f32> {
+    if spectrum.is_empty() {
+        None
+    }
+    else {
+        sorted.clear();
+        sorted.extend_from_slice(spectrum);
+        let middle = sorted.len() / 2;
+        let (_, median, _) = sorted.select_nth_unstable_by(middle, |a, b| a.total_cmp(b));
+        Some(*median)
+    }
+}
+
+/// Returns the prominence of the peak at `index` and the indices of the
+/// lowest points to the left and right of it.
+fn prominence(spectrum: &[f32], index: usize) -> (f32, usize, usize) {
+    let value = spectrum[index];
+
+    let mut left_min = value;
+    let mut left_base = index;
+    for (i, x) in spectrum[..index].iter().copied().enumerate().rev() {
+        if x > value {
+            break;
+        }
+        if x < left_min {
+            left_min = x;
+            left_base = i;
+        }
+    }
+
+    let mut right_min = value;
+    let mut right_base = index;
+    for (i, x) in spectrum.iter().copied().enumerate().skip(index + 1) {
+        if x > value {
+            break;
+        }
+        if x < right_min {
+            right_min = x;
+            right_base = i;
+        }
+    }
+
+    (value - left_min.max(right_min), left_base, right_base)
+}
+
+/// Walks from `index` towards `base` and returns the (interpolated) position
+/// where the spectrum drops to `level`. If it never does, `base` is returned.
+fn crossing(spectrum: &[f32], index: usize, base: usize, level: f32) -> f32 {
+    let mut i = index;
+
+    while i != base {
+        let next = if base < i { i - 1 } else { i + 1 };
+
+        if spectrum[next] <= level {
+            let t = (spectrum[i] - level) / (spectrum[i] - spectrum[next]);
+            return if base < i { i as f32 - t } else { i as f32 + t };
+        }
+
+        i = next;
+    }
+
+    base as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::{
+        PeakDetector,
+        noise_floor,
+    };
+
+    fn spectrum(peaks: &[(usize, f32, f32)]) -> Vec<f32> {
+        (0..512)
+            .map(|i| {
+                // deterministic ripple as "noise"
+                let noise = -100.0 + ((i * 7919) % 13) as f32 * 0.05;
+                peaks
+                    .iter()
+                    .map(|(center, height, width)| {
+                        let x = (i as f32 - *center as f32) / width;
+                        noise + height * (-0.5 * x * x).exp()
+                    })
+                    .fold(noise, f32::max)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_estimates_the_noise_floor() {
+        let spectrum = spectrum(&[(100, 40.0, 2.0)]);
+        let noise_floor = noise_floor(&spectrum).unwrap();
+        assert!(noise_floor > -100.5 && noise_floor < -99.0);
+    }
+
+    #[test]
+    fn it_finds_peaks_above_the_noise_floor() {
+        let spectrum = spectrum(&[(100, 40.0, 4.0), (300, 20.0, 8.0)]);
+        let peaks = PeakDetector::default().find_peaks(&spectrum);
+
+        assert_eq!(peaks.len(), 2, "{peaks:#?}");
+
+        assert_eq!(peaks[0].index, 100);
+        assert_abs_diff_eq!(peaks[0].center, 100.0, epsilon = 0.5);
+        assert!(peaks[0].bandwidth > 1.0 && peaks[0].bandwidth < 8.0);
+
+        assert!(peaks[1].index.abs_diff(300) <= 1);
+        assert_abs_diff_eq!(peaks[1].center, 300.0, epsilon = 1.0);
+        assert!(peaks[1].bandwidth > peaks[0].bandwidth);
+    }
+
+    #[test]
+    fn it_keeps_only_the_highest_of_close_peaks() {
+        let spectrum = spectrum(&[(100, 40.0, 1.0), (106, 30.0, 1.0)]);
+
+        let peaks = PeakDetector::new(10.0, 6.0, 10).find_peaks(&spectrum);
+        assert_eq!(peaks.len(), 1, "{peaks:#?}");
+        assert_eq!(peaks[0].index, 100);
+
+        let peaks = PeakDetector::new(10.0, 6.0, 2).find_peaks(&spectrum);
+        assert_eq!(peaks.len(), 2, "{peaks:#?}");
+    }
+}
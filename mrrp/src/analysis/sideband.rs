@@ -0,0 +1,313 @@
+//! Carrier and sideband detection
+//!
+//! Tells from the power spectrum around a tuned frequency whether a signal
+//! there is AM, i.e. a carrier with both sidebands, or SSB, and if so which
+//! sideband it uses. This can be used to set up an
+//! [`SsbDemodulator`][crate::modem::ssb::SsbDemodulator] automatically.
+//!
+//! Like [`occupancy`][super::occupancy], spectra are linear power, ordered by
+//! frequency, with bin 0 starting at `start_frequency`.
+
+use std::ops::Range;
+
+use crate::{
+    analysis::peaks::noise_floor_with,
+    modem::ssb::{
+        DEFAULT_PASSBAND_HIGH,
+        DEFAULT_PASSBAND_LOW,
+        Sideband,
+    },
+};
+
+/// What kind of signal was detected at the tuned frequency.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SignalKind {
+    Am,
+    Ssb(Sideband),
+}
+
+/// Mean power in the parts of the spectrum around the tuned frequency.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SidebandLevels {
+    /// Power of the strongest bin within half the carrier width of the tuned
+    /// frequency.
+    pub carrier: f32,
+
+    /// Mean power per bin of the upper sideband.
+    pub upper: f32,
+
+    /// Mean power per bin of the lower sideband.
+    pub lower: f32,
+
+    /// Median power of all bins.
+    pub noise_floor: f32,
+}
+
+impl SidebandLevels {
+    /// Measures the levels around `tuned_frequency` in a spectrum.
+    ///
+    /// The sidebands span `passband` (in Hz) above and below the tuned
+    /// frequency. Returns `None` if the spectrum doesn't cover them.
+    pub fn measure(
+        power: &[f32],
+        start_frequency: f64,
+        bin_width: f64,
+        tuned_frequency: f64,
+        passband: &Range<f32>,
+        carrier_width: f32,
+    ) -> Option<Self> {
+        Self::measure_with(
+            power,
+            start_frequency,
+            bin_width,
+            tuned_frequency,
+            passband,
+            carrier_width,
+            &mut vec![],
+        )
+    }
+
+    /// Same as [`measure`][Self::measure], but uses `sorted` as scratch space
+    /// for the noise floor.
+    fn measure_with(
+        power: &[f32],
+        start_frequency: f64,
+        bin_width: f64,
+        tuned_frequency: f64,
+        passband: &Range<f32>,
+        carrier_width: f32,
+        sorted: &mut Vec<f32>,
+    ) -> Option<Self> {
+        let to_bin = |offset: f32| (tuned_frequency + offset as f64 - start_frequency) / bin_width;
+        let bins = |start: f32, end: f32| {
+            let start = to_bin(start).round();
+            let end = to_bin(end).round();
+            (start >= 0.0 && end <= power.len() as f64 && start < end)
+                .then_some(start as usize..end as usize)
+        };
+
+        let upper = bins(passband.start, passband.end)?;
+        let lower = bins(-passband.end, -passband.start)?;
+        // if the carrier width is narrower than a bin, the bin containing the
+        // tuned frequency
+        let carrier = bins(-0.5 * carrier_width, 0.5 * carrier_width).or_else(|| {
+            let bin = to_bin(0.0).floor();
+            (bin >= 0.0 && bin < power.len() as f64).then(|| bin as usize..bin as usize + 1)
+        })?;
+
+        let mean = |bins: Range<usize>| {
+            let len = bins.len() as f32;
+            power[bins].iter().sum::<f32>() / len
+        };
+
+        let noise_floor = noise_floor_with(power, sorted)?;
+
+        Some(Self {
+            carrier: power[carrier].iter().copied().fold(0.0, f32::max),
+            upper: mean(upper),
+            lower: mean(lower),
+            noise_floor,
+        })
+    }
+
+    /// Decides what kind of signal these levels belong to.
+    ///
+    /// A carrier is present if it's at least `carrier_threshold` dB above the
+    /// noise floor. A signal whose sidebands differ by at least
+    /// `sideband_threshold` dB is SSB, even if it has a carrier. A carrier with
+    /// balanced sidebands is AM. Anything else is undecided.
+    pub fn classify(&self, carrier_threshold: f32, sideband_threshold: f32) -> Option<SignalKind> {
+        let db = |power: f32| 10.0 * power.max(f32::MIN_POSITIVE).log10();
+        let carrier = db(self.carrier) - db(self.noise_floor);
+        let imbalance = db(self.upper) - db(self.lower);
+
+        if imbalance >= sideband_threshold {
+            Some(SignalKind::Ssb(Sideband::Upper))
+        }
+        else if -imbalance >= sideband_threshold {
+            Some(SignalKind::Ssb(Sideband::Lower))
+        }
+        else if carrier >= carrier_threshold {
+            Some(SignalKind::Am)
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// Detects the kind of signal at a tuned frequency over a series of spectra.
+///
+/// The levels are averaged over spectra, so that speech pauses and fading
+/// don't flip the decision back and forth.
+#[derive(Clone, Debug)]
+pub struct SidebandDetector {
+    passband: Range<f32>,
+    carrier_width: f32,
+    carrier_threshold: f32,
+    sideband_threshold: f32,
+    smoothing: f32,
+    levels: Option<SidebandLevels>,
+    sorted: Vec<f32>,
+}
+
+impl Default for SidebandDetector {
+    fn default() -> Self {
+        Self::new(10.0, 6.0)
+    }
+}
+
+impl SidebandDetector {
+    /// Creates a detector with the default SSB passband, see
+    /// [`SidebandLevels::classify`] for the thresholds.
+    pub fn new(carrier_threshold: f32, sideband_threshold: f32) -> Self {
+        Self {
+            passband: DEFAULT_PASSBAND_LOW..DEFAULT_PASSBAND_HIGH,
+            carrier_width: DEFAULT_PASSBAND_LOW,
+            carrier_threshold,
+            sideband_threshold,
+            smoothing: 0.1,
+            levels: None,
+            sorted: vec![],
+        }
+    }
+
+    /// Sets the audio frequencies (in Hz) that make up a sideband, and the
+    /// width around the tuned frequency in which the carrier is looked for.
+    pub fn with_passband(mut self, passband: Range<f32>, carrier_width: f32) -> Self {
+        assert!(
+            0.0 < passband.start && passband.start < passband.end,
+            "invalid passband"
+        );
+        self.passband = passband;
+        self.carrier_width = carrier_width;
+        self
+    }
+
+    /// How much of every new spectrum goes into the averaged levels, between
+    /// 0 and 1. With 1, every spectrum is decided on its own.
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        assert!(0.0 < smoothing && smoothing <= 1.0, "invalid smoothing");
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// The averaged levels, if a spectrum was pushed yet.
+    #[inline]
+    pub fn levels(&self) -> Option<&SidebandLevels> {
+        self.levels.as_ref()
+    }
+
+    /// Adds a spectrum and returns the current decision.
+    pub fn push(
+        &mut self,
+        power: &[f32],
+        start_frequency: f64,
+        bin_width: f64,
+        tuned_frequency: f64,
+    ) -> Option<SignalKind> {
+        let new = SidebandLevels::measure_with(
+            power,
+            start_frequency,
+            bin_width,
+            tuned_frequency,
+            &self.passband,
+            self.carrier_width,
+            &mut self.sorted,
+        )?;
+
+        if let Some(levels) = &mut self.levels {
+            let smooth = |old: &mut f32, new: f32| *old += self.smoothing * (new - *old);
+            smooth(&mut levels.carrier, new.carrier);
+            smooth(&mut levels.upper, new.upper);
+            smooth(&mut levels.lower, new.lower);
+            smooth(&mut levels.noise_floor, new.noise_floor);
+        }
+        else {
+            self.levels = Some(new);
+        }
+
+        self.levels
+            .and_then(|levels| levels.classify(self.carrier_threshold, self.sideband_threshold))
+    }
+
+    /// Forgets the averaged levels, e.g. after retuning.
+    pub fn reset(&mut self) {
+        self.levels = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        SidebandDetector,
+        SignalKind,
+    };
+    use crate::modem::ssb::Sideband;
+
+    /// 10 kHz wide spectrum with 100 Hz bins, tuned to its center, with a
+    /// noise floor of 1.
+    fn spectrum(carrier: f32, upper: f32, lower: f32) -> Vec<f32> {
+        (0..100)
+            .map(|bin| {
+                let offset = bin as f32 * 100.0 + 50.0 - 5000.0;
+                if offset.abs() < 100.0 {
+                    carrier.max(1.0)
+                }
+                else if (300.0..2700.0).contains(&offset) {
+                    upper.max(1.0)
+                }
+                else if (-2700.0..-300.0).contains(&offset) {
+                    lower.max(1.0)
+                }
+                else {
+                    1.0
+                }
+            })
+            .collect()
+    }
+
+    fn detect(power: &[f32]) -> Option<SignalKind> {
+        SidebandDetector::default().push(power, 0.0, 100.0, 5000.0)
+    }
+
+    #[test]
+    fn it_classifies_signals() {
+        assert_eq!(
+            detect(&spectrum(0.0, 100.0, 0.0)),
+            Some(SignalKind::Ssb(Sideband::Upper))
+        );
+        assert_eq!(
+            detect(&spectrum(0.0, 0.0, 100.0)),
+            Some(SignalKind::Ssb(Sideband::Lower))
+        );
+        assert_eq!(detect(&spectrum(1000.0, 50.0, 50.0)), Some(SignalKind::Am));
+        assert_eq!(detect(&spectrum(1000.0, 0.0, 0.0)), Some(SignalKind::Am));
+        assert_eq!(detect(&spectrum(0.0, 0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn it_smooths_the_decision() {
+        let mut detector = SidebandDetector::default();
+        let usb = spectrum(0.0, 100.0, 0.0);
+        let lsb = spectrum(0.0, 0.0, 100.0);
+
+        for _ in 0..20 {
+            detector.push(&usb, 0.0, 100.0, 5000.0);
+        }
+
+        // a single spectrum doesn't flip the decision
+        assert_eq!(
+            detector.push(&lsb, 0.0, 100.0, 5000.0),
+            Some(SignalKind::Ssb(Sideband::Upper))
+        );
+
+        for _ in 0..50 {
+            detector.push(&lsb, 0.0, 100.0, 5000.0);
+        }
+        assert_eq!(
+            detector.push(&lsb, 0.0, 100.0, 5000.0),
+            Some(SignalKind::Ssb(Sideband::Lower))
+        );
+    }
+}
@@ -1,14 +1,21 @@
-// should we make audio sinks? currently this just turns any stream into rodio
-// sources.
+//! Audio playback with rodio.
+//!
+//! [`RodioSource`] turns a stream into a rodio source, which rodio pulls
+//! samples from. [`AudioSink`] is pushed to instead, and resamples to the rate
+//! of the output device. [`AudioSource`] records from an input device.
 
 use std::{
+    collections::VecDeque,
     num::NonZero,
+    pin::Pin,
     sync::{
         Arc,
         atomic::{
             AtomicBool,
+            AtomicU64,
             Ordering,
         },
+        mpsc,
     },
     task::{
         Context,
@@ -19,10 +26,24 @@ use std::{
     time::Duration,
 };
 
-use futures_util::FutureExt;
+use futures_util::{
+    FutureExt,
+    task::AtomicWaker,
+};
 use parking_lot::Mutex;
 use rodio::{
     Source as _,
+    cpal::{
+        self,
+        FromSample,
+        SampleFormat,
+        SizedSample,
+        traits::{
+            DeviceTrait,
+            HostTrait,
+            StreamTrait,
+        },
+    },
     source::AutomaticGainControlSettings,
 };
 use tokio::{
@@ -30,14 +51,38 @@ use tokio::{
     sync::oneshot,
 };
 
-use crate::io::{
-    AsyncReadSamples,
-    AsyncReadSamplesExt,
-    EofError,
-    GetSampleRate,
-    Remaining,
+use crate::{
+    buf::SampleBufMut,
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    filter::resampling::{
+        PolyphaseResampler,
+        resampling_ratio,
+    },
+    io::{
+        AsyncReadSamples,
+        AsyncReadSamplesExt,
+        AsyncWriteSamples,
+        EofError,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        StreamLength,
+    },
 };
 
+/// How much audio the [`AudioSink`] buffers for the output device.
+const AUDIO_SINK_BUFFER_DURATION: Duration = Duration::from_millis(100);
+
+/// How many samples the output device takes from the buffer at once.
+const AUDIO_SINK_CHUNK_SIZE: usize = 256;
+
+/// How much audio the [`AudioSource`] buffers until it's read. If it isn't read
+/// in time, the oldest samples are dropped.
+const AUDIO_SOURCE_BUFFER_DURATION: Duration = Duration::from_secs(1);
+
 #[derive(Debug)]
 #[non_exhaustive]
 pub struct RodioSource<R>
@@ -206,6 +251,16 @@ pub enum Error<S> {
     Dropped,
 }
 
+impl<S: ClassifyError> ClassifyError for Error<S> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            Error::Playback(_) => ErrorKind::Hardware,
+            Error::Stream(error) => error.error_kind(),
+            Error::Dropped => ErrorKind::Other,
+        }
+    }
+}
+
 pub async fn play_audio<S>(signal: S, volume: f32) -> Result<(), Error<S::Error>>
 where
     S: AsyncReadSamples<f32> + GetSampleRate + Unpin + Send + 'static,
@@ -258,6 +313,552 @@ fn global_output_stream() -> Result<&'static rodio::MixerDeviceSink, rodio::Devi
     Ok(output_stream.unwrap())
 }
 
+#[derive(Debug, thiserror::Error)]
+#[error("audio sink error")]
+pub enum AudioSinkError {
+    Open(#[from] rodio::DeviceSinkError),
+    ListDevices(#[from] cpal::DevicesError),
+    NoSuchDevice(String),
+    Resampler(#[from] pm_remez::Error),
+    Closed,
+}
+
+impl ClassifyError for AudioSinkError {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            AudioSinkError::Open(_)
+            | AudioSinkError::ListDevices(_)
+            | AudioSinkError::NoSuchDevice(_) => ErrorKind::Hardware,
+            AudioSinkError::Resampler(_) | AudioSinkError::Closed => ErrorKind::Other,
+        }
+    }
+}
+
+/// Names of the audio output devices, which can be passed to
+/// [`AudioSink::open_device`].
+pub fn output_device_names() -> Result<Vec<String>, AudioSinkError> {
+    Ok(cpal::default_host()
+        .output_devices()?
+        .filter_map(|device| device.name().ok())
+        .collect())
+}
+
+/// Plays samples on an audio output device.
+///
+/// Samples can have any sample rate, they're resampled to the rate of the
+/// device. Writes are pending while the device's buffer is full, so a stream
+/// that is pumped into the sink is paced by the device. If the stream can't
+/// keep up, silence is played.
+///
+/// Closing the sink waits until all samples were played.
+#[derive(derive_more::Debug)]
+pub struct AudioSink {
+    #[debug(skip)]
+    device_sink: Option<rodio::MixerDeviceSink>,
+    shared: Arc<AudioSinkBuffer>,
+    resampler: Option<PolyphaseResampler<f32>>,
+    /// Resampled samples that didn't fit into the buffer yet.
+    pending: VecDeque<f32>,
+    input_sample_rate: f32,
+    output_sample_rate: u32,
+    is_closed: bool,
+}
+
+impl AudioSink {
+    /// Plays on the default output device, which is shared with
+    /// [`play_audio`].
+    pub fn open_default(sample_rate: f32) -> Result<Self, AudioSinkError> {
+        let device_sink = global_output_stream()?;
+        let (sink, source) = Self::new(sample_rate, device_sink.config().sample_rate().get())?;
+        device_sink.mixer().add(source);
+        Ok(sink)
+    }
+
+    /// Plays on the output device with the given name, see
+    /// [`output_device_names`].
+    pub fn open_device(name: &str, sample_rate: f32) -> Result<Self, AudioSinkError> {
+        let device = cpal::default_host()
+            .output_devices()?
+            .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+            .ok_or_else(|| AudioSinkError::NoSuchDevice(name.to_owned()))?;
+        let device_sink = rodio::DeviceSinkBuilder::from_device(device)?.open_sink()?;
+
+        let (mut sink, source) = Self::new(sample_rate, device_sink.config().sample_rate().get())?;
+        device_sink.mixer().add(source);
+        sink.device_sink = Some(device_sink);
+        Ok(sink)
+    }
+
+    fn new(
+        input_sample_rate: f32,
+        output_sample_rate: u32,
+    ) -> Result<(Self, AudioSinkSource), AudioSinkError> {
+        let (interpolation, decimation) =
+            resampling_ratio(input_sample_rate, output_sample_rate as f32);
+        let resampler = if interpolation == decimation {
+            None
+        }
+        else {
+            Some(PolyphaseResampler::new(interpolation, decimation)?)
+        };
+
+        let capacity = (AUDIO_SINK_BUFFER_DURATION.as_secs_f32() * output_sample_rate as f32)
+            as usize
+            + AUDIO_SINK_CHUNK_SIZE;
+        let shared = Arc::new(AudioSinkBuffer {
+            samples: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            writer_waker: AtomicWaker::new(),
+            is_closed: AtomicBool::new(false),
+        });
+        let source = AudioSinkSource {
+            shared: shared.clone(),
+            chunk: VecDeque::with_capacity(AUDIO_SINK_CHUNK_SIZE),
+            sample_rate: NonZero::new(output_sample_rate).unwrap(),
+        };
+
+        let sink = Self {
+            device_sink: None,
+            shared,
+            resampler,
+            pending: VecDeque::new(),
+            input_sample_rate,
+            output_sample_rate,
+            is_closed: false,
+        };
+        Ok((sink, source))
+    }
+
+    /// Sample rate of the output device.
+    #[inline]
+    pub fn output_sample_rate(&self) -> u32 {
+        self.output_sample_rate
+    }
+
+    /// Moves pending samples into the buffer, and returns whether all fit.
+    fn push_pending(&mut self) -> bool {
+        if !self.pending.is_empty() {
+            let mut samples = self.shared.samples.lock();
+            let num_samples = self
+                .pending
+                .len()
+                .min(self.shared.capacity.saturating_sub(samples.len()));
+            samples.extend(self.pending.drain(..num_samples));
+        }
+        self.pending.is_empty()
+    }
+
+    fn poll_push_pending(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.push_pending() {
+            return Poll::Ready(());
+        }
+        // the device might have taken samples since we checked
+        self.shared.writer_waker.register(cx.waker());
+        if self.push_pending() {
+            Poll::Ready(())
+        }
+        else {
+            Poll::Pending
+        }
+    }
+
+    fn poll_drained(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.poll_push_pending(cx).is_pending() {
+            return Poll::Pending;
+        }
+        self.shared.writer_waker.register(cx.waker());
+        if self.shared.samples.lock().is_empty() {
+            Poll::Ready(())
+        }
+        else {
+            Poll::Pending
+        }
+    }
+}
+
+impl GetSampleRate for AudioSink {
+    /// The sample rate of the input.
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.input_sample_rate
+    }
+}
+
+impl AsyncWriteSamples<f32> for AudioSink {
+    type Error = AudioSinkError;
+
+    fn poll_write_samples(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &[f32],
+    ) -> Poll<Result<usize, Self::Error>> {
+        let this = &mut *self;
+        if this.is_closed {
+            return Poll::Ready(Err(AudioSinkError::Closed));
+        }
+        if this.poll_push_pending(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        // only take as many samples as fit into the buffer after resampling,
+        // so the pending samples don't pile up
+        let free = this.shared.capacity - this.shared.samples.lock().len();
+        let free = (free as f32 * this.input_sample_rate / this.output_sample_rate as f32) as usize;
+        let num_samples = buffer.len().min(free.max(1));
+
+        match &mut this.resampler {
+            Some(resampler) => {
+                for sample in &buffer[..num_samples] {
+                    resampler.push(*sample, |sample| this.pending.push_back(sample));
+                }
+            }
+            None => this.pending.extend(&buffer[..num_samples]),
+        }
+        this.push_pending();
+
+        Poll::Ready(Ok(num_samples))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.is_closed {
+            return Poll::Ready(Err(AudioSinkError::Closed));
+        }
+        self.poll_drained(cx).map(Ok)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+        if !this.is_closed {
+            this.is_closed = true;
+            if let Some(resampler) = &mut this.resampler {
+                resampler.finish(|sample| this.pending.push_back(sample));
+            }
+        }
+
+        if this.poll_drained(cx).is_pending() {
+            return Poll::Pending;
+        }
+        this.shared.is_closed.store(true, Ordering::Release);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for AudioSink {
+    fn drop(&mut self) {
+        // the device plays what's left in the buffer, and then removes the
+        // source
+        self.shared.is_closed.store(true, Ordering::Release);
+    }
+}
+
+/// Buffer between an [`AudioSink`] and the output device.
+#[derive(Debug)]
+struct AudioSinkBuffer {
+    samples: Mutex<VecDeque<f32>>,
+    capacity: usize,
+    writer_waker: AtomicWaker,
+    is_closed: AtomicBool,
+}
+
+/// The rodio source that plays the samples written to an [`AudioSink`].
+#[derive(Debug)]
+struct AudioSinkSource {
+    shared: Arc<AudioSinkBuffer>,
+    /// Samples taken from the buffer, so that it isn't locked for every
+    /// sample.
+    chunk: VecDeque<f32>,
+    sample_rate: rodio::SampleRate,
+}
+
+impl Iterator for AudioSinkSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.chunk.is_empty() {
+            let mut samples = self.shared.samples.lock();
+            if samples.is_empty() && self.shared.is_closed.load(Ordering::Acquire) {
+                return None;
+            }
+
+            let num_samples = samples.len().min(AUDIO_SINK_CHUNK_SIZE);
+            self.chunk.extend(samples.drain(..num_samples));
+            let is_half_empty = samples.len() <= self.shared.capacity / 2;
+            drop(samples);
+
+            if is_half_empty {
+                self.shared.writer_waker.wake();
+            }
+        }
+
+        // play silence if the writer can't keep up
+        Some(self.chunk.pop_front().unwrap_or_default())
+    }
+}
+
+impl rodio::Source for AudioSinkSource {
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> rodio::ChannelCount {
+        const { NonZero::new(1).unwrap() }
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.sample_rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("audio source error")]
+pub enum AudioSourceError {
+    ListDevices(#[from] cpal::DevicesError),
+    NoSuchDevice(String),
+    NoDefaultDevice,
+    Config(#[from] cpal::DefaultStreamConfigError),
+    UnsupportedSampleFormat(SampleFormat),
+    Build(#[from] cpal::BuildStreamError),
+    Play(#[from] cpal::PlayStreamError),
+    Stream(#[from] cpal::StreamError),
+    Closed,
+}
+
+impl ClassifyError for AudioSourceError {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            AudioSourceError::Closed => ErrorKind::Other,
+            _ => ErrorKind::Hardware,
+        }
+    }
+}
+
+/// Names of the audio input devices, which can be passed to
+/// [`AudioSource::open_device`].
+pub fn input_device_names() -> Result<Vec<String>, AudioSourceError> {
+    Ok(cpal::default_host()
+        .input_devices()?
+        .filter_map(|device| device.name().ok())
+        .collect())
+}
+
+/// Records from an audio input device, e.g. a microphone or a virtual cable
+/// from another program.
+///
+/// The samples are in the device's sample rate. Devices with more than one
+/// channel are mixed down to mono.
+///
+/// The device keeps recording while the source isn't read, for up to a
+/// second. After that the oldest samples are dropped, see
+/// [`num_dropped`][Self::num_dropped].
+#[derive(Debug)]
+pub struct AudioSource {
+    shared: Arc<AudioSourceBuffer>,
+    sample_rate: u32,
+    /// The stream runs on its own thread, because it can't be sent between
+    /// threads on all platforms. The thread stops when this is dropped.
+    _stop_sender: mpsc::Sender<()>,
+}
+
+impl AudioSource {
+    /// Records from the default input device.
+    pub fn open_default() -> Result<Self, AudioSourceError> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or(AudioSourceError::NoDefaultDevice)?;
+        Self::from_device(device)
+    }
+
+    /// Records from the input device with the given name, see
+    /// [`input_device_names`].
+    pub fn open_device(name: &str) -> Result<Self, AudioSourceError> {
+        let device = cpal::default_host()
+            .input_devices()?
+            .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+            .ok_or_else(|| AudioSourceError::NoSuchDevice(name.to_owned()))?;
+        Self::from_device(device)
+    }
+
+    pub fn from_device(device: cpal::Device) -> Result<Self, AudioSourceError> {
+        let config = device.default_input_config()?;
+        let sample_rate = config.sample_rate().0;
+        let shared = Arc::new(AudioSourceBuffer::new(
+            (AUDIO_SOURCE_BUFFER_DURATION.as_secs_f32() * sample_rate as f32) as usize,
+        ));
+
+        let (started_sender, started_receiver) = mpsc::channel();
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+        let thread_shared = shared.clone();
+        std::thread::spawn(move || {
+            match build_input_stream(&device, config, thread_shared) {
+                Ok(stream) => {
+                    let _ = started_sender.send(Ok(()));
+                    // blocks until the source is dropped
+                    let _ = stop_receiver.recv();
+                    drop(stream);
+                }
+                Err(error) => {
+                    let _ = started_sender.send(Err(error));
+                }
+            }
+        });
+        started_receiver
+            .recv()
+            .map_err(|_| AudioSourceError::Closed)??;
+
+        Ok(Self {
+            shared,
+            sample_rate,
+            _stop_sender: stop_sender,
+        })
+    }
+
+    /// Number of samples that were dropped, because the source wasn't read in
+    /// time.
+    #[inline]
+    pub fn num_dropped(&self) -> u64 {
+        self.shared.num_dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl AsyncReadSamples<f32> for AudioSource {
+    type Error = AudioSourceError;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<f32>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let shared = &self.shared;
+        let mut samples = shared.samples.lock();
+        if samples.is_empty() {
+            if let Some(error) = shared.error.lock().take() {
+                return Poll::Ready(Err(error.into()));
+            }
+            // the waker is registered while the lock is held, so the device
+            // can't push samples in between
+            shared.reader_waker.register(cx.waker());
+            return Poll::Pending;
+        }
+
+        let num_samples = samples.len().min(buffer.remaining());
+        for sample in samples.drain(..num_samples) {
+            buffer.put_sample(sample);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl GetSampleRate for AudioSource {
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate as f32
+    }
+}
+
+impl StreamLength for AudioSource {
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        Remaining::Infinite
+    }
+}
+
+/// Buffer between the input device and an [`AudioSource`].
+#[derive(Debug)]
+struct AudioSourceBuffer {
+    samples: Mutex<VecDeque<f32>>,
+    capacity: usize,
+    reader_waker: AtomicWaker,
+    error: Mutex<Option<cpal::StreamError>>,
+    num_dropped: AtomicU64,
+}
+
+impl AudioSourceBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            samples: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            reader_waker: AtomicWaker::new(),
+            error: Mutex::new(None),
+            num_dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Mixes interleaved frames down to mono and appends them.
+    fn push_frames<T>(&self, data: &[T], channels: usize)
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let mut samples = self.samples.lock();
+        for frame in data.chunks_exact(channels) {
+            let sum = frame
+                .iter()
+                .map(|sample| f32::from_sample_(*sample))
+                .sum::<f32>();
+            samples.push_back(sum / channels as f32);
+        }
+
+        let overflow = samples.len().saturating_sub(self.capacity);
+        if overflow > 0 {
+            samples.drain(..overflow);
+            self.num_dropped
+                .fetch_add(overflow as u64, Ordering::Relaxed);
+        }
+        drop(samples);
+
+        self.reader_waker.wake();
+    }
+}
+
+fn build_input_stream(
+    device: &cpal::Device,
+    config: cpal::SupportedStreamConfig,
+    shared: Arc<AudioSourceBuffer>,
+) -> Result<cpal::Stream, AudioSourceError> {
+    fn build<T>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        shared: Arc<AudioSourceBuffer>,
+    ) -> Result<cpal::Stream, cpal::BuildStreamError>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let channels = usize::from(config.channels);
+        let error_shared = shared.clone();
+        device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| shared.push_frames(data, channels),
+            move |error| {
+                tracing::error!(?error, "audio input error");
+                *error_shared.error.lock() = Some(error);
+                error_shared.reader_waker.wake();
+            },
+            None,
+        )
+    }
+
+    let sample_format = config.sample_format();
+    let config = config.config();
+    let stream = match sample_format {
+        SampleFormat::F32 => build::<f32>(device, &config, shared)?,
+        SampleFormat::I16 => build::<i16>(device, &config, shared)?,
+        SampleFormat::U16 => build::<u16>(device, &config, shared)?,
+        SampleFormat::I32 => build::<i32>(device, &config, shared)?,
+        sample_format => return Err(AudioSourceError::UnsupportedSampleFormat(sample_format)),
+    };
+    stream.play()?;
+    Ok(stream)
+}
+
 /// Wrapper around an [`AtomicBool`] that can be turned into a waker (if put
 /// into an [`Arc`]).
 #[derive(Debug, Default)]
@@ -284,3 +885,68 @@ impl IsPending {
         self.flag.store(true, Ordering::Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::Ordering,
+        task::{
+            Context,
+            Poll,
+        },
+    };
+
+    use futures_util::{
+        FutureExt,
+        task::noop_waker_ref,
+    };
+
+    use super::{
+        AudioSink,
+        AudioSourceBuffer,
+    };
+    use crate::io::AsyncWriteSamplesExt;
+
+    #[test]
+    fn it_resamples_to_the_device_rate_with_backpressure() {
+        let (mut sink, mut source) = AudioSink::new(8_000.0, 48_000).unwrap();
+        let capacity = sink.shared.capacity;
+
+        // the buffer fills up, and then writes are pending
+        let input = vec![0.5f32; 8_000];
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut num_written = 0;
+        while let Poll::Ready(result) =
+            sink.poll_write_samples_unpin(&mut cx, &input[num_written..])
+        {
+            num_written += result.unwrap();
+        }
+        assert_eq!(sink.shared.samples.lock().len(), capacity);
+        assert!(num_written < 1_000, "{num_written}");
+
+        // the device takes samples, which makes room for more
+        let played = source.by_ref().take(capacity).collect::<Vec<_>>();
+        assert!((played[capacity / 2] - 0.5).abs() < 1e-2);
+        sink.write_all(&input[num_written..num_written + 10])
+            .now_or_never()
+            .expect("write pending")
+            .unwrap();
+
+        // closing waits until everything was played, and then ends the source
+        assert!(sink.close().now_or_never().is_none());
+        source.by_ref().take(1_000).for_each(drop);
+        sink.close().now_or_never().unwrap().unwrap();
+        assert_eq!(source.next(), None);
+    }
+
+    #[test]
+    fn it_mixes_input_down_and_drops_old_samples() {
+        let buffer = AudioSourceBuffer::new(4);
+        buffer.push_frames(&[0.5f32, 0.1, 1.0, 0.0], 2);
+        assert_eq!(*buffer.samples.lock(), [0.3, 0.5]);
+
+        buffer.push_frames(&[i16::MIN, 0, 0, 0, 0, 0], 2);
+        assert_eq!(*buffer.samples.lock(), [0.5, -0.5, 0.0, 0.0]);
+        assert_eq!(buffer.num_dropped.load(Ordering::Relaxed), 1);
+    }
+}
@@ -1,3 +1,26 @@
+//! Sample buffers
+//!
+//! # Error handling
+//!
+//! Operations whose preconditions depend on the contents of a buffer come in
+//! two flavours: a `try_` variant that returns one of the error types of this
+//! module, and a panicking variant without the prefix (e.g.
+//! [`try_advance`][SampleBuf::try_advance] and
+//! [`advance`][SampleBuf::advance]). The panicking variants are meant for
+//! cases where a failure is a bug in the caller, and panic with the message of
+//! the error the `try_` variant would have returned.
+//!
+//! There is no global switch that makes the panicking variants fail softly:
+//! they have nothing sensible to return on failure, so whether an error is a
+//! bug or expected is decided at each call site. Code that must not panic,
+//! e.g. when parsing untrusted input, uses the `try_` variants throughout.
+//!
+//! Safe functions always check their bounds, also in release builds. `unsafe`
+//! functions like [`SampleBufMut::advance_mut`] make their bounds part of the
+//! safety contract, but still check them where violating them would be
+//! undefined behaviour, e.g. when advancing a `Vec` past its capacity.
+
+mod policy;
 mod samples;
 mod samples_mut;
 mod uninit_slice;
@@ -8,6 +31,12 @@ use std::{
 };
 
 pub use crate::buf::{
+    policy::{
+        BufferLimitExceeded,
+        BufferPolicy,
+        DEFAULT_CHUNK_SIZE,
+        PolicyAllocator,
+    },
     samples::Samples,
     samples_mut::SamplesMut,
     uninit_slice::UninitSlice,
@@ -47,9 +76,13 @@ pub trait SampleBuf<S> {
     fn remaining(&self) -> usize;
     fn chunk(&self) -> &[S];
 
+    /// # Panics
+    ///
+    /// Panics if `amount` is larger than [`remaining`][Self::remaining].
     #[inline]
     fn advance(&mut self, amount: usize) {
-        self.try_advance(amount).unwrap()
+        self.try_advance(amount)
+            .unwrap_or_else(|error| panic!("{error}"))
     }
 
     #[inline]
@@ -81,7 +114,8 @@ pub trait SampleBuf<S> {
     where
         S: Clone,
     {
-        self.try_get_sample().unwrap()
+        self.try_get_sample()
+            .unwrap_or_else(|error| panic!("{error}"))
     }
 
     #[inline]
@@ -132,7 +166,8 @@ pub trait SampleBuf<S> {
     where
         S: Clone,
     {
-        self.try_copy_to_samples(length).unwrap()
+        self.try_copy_to_samples(length)
+            .unwrap_or_else(|error| panic!("{error}"))
     }
 
     fn try_copy_to_slice(&mut self, mut output: &mut [S]) -> Result<(), TryGetError>
@@ -156,7 +191,8 @@ pub trait SampleBuf<S> {
     where
         S: Clone,
     {
-        self.try_copy_to_slice(output).unwrap()
+        self.try_copy_to_slice(output)
+            .unwrap_or_else(|error| panic!("{error}"))
     }
 }
 
@@ -220,11 +256,18 @@ where
     fn try_advance(&mut self, amount: usize) -> Result<(), TryAdvanceError> {
         let head_remaining = self.head.remaining();
 
+        if amount > self.remaining() {
+            return Err(TryAdvanceError {
+                requested: amount,
+                available: self.remaining(),
+            });
+        }
+
         if head_remaining == 0 {
             self.tail.try_advance(amount)?;
         }
         else if head_remaining >= amount {
-            self.head.advance(head_remaining);
+            self.head.advance(amount);
         }
         else {
             self.head.advance(head_remaining);
@@ -270,6 +313,7 @@ where
         }
         else {
             self.inner.try_advance(amount)?;
+            self.limit -= amount;
             Ok(())
         }
     }
@@ -289,6 +333,13 @@ where
 }
 
 pub trait SampleBufMut<S> {
+    /// Marks `amount` samples of the buffer as written.
+    ///
+    /// # Safety
+    ///
+    /// The first `amount` samples of [`chunk_mut`][Self::chunk_mut] must have
+    /// been initialized. In particular `amount` must not be larger than the
+    /// length of that chunk.
     unsafe fn advance_mut(&mut self, amount: usize);
     fn remaining_mut(&self) -> usize;
     fn chunk_mut(&mut self) -> &mut UninitSlice<S>;
@@ -317,7 +368,8 @@ pub trait SampleBufMut<S> {
 
     #[inline]
     fn put_sample(&mut self, sample: S) {
-        self.try_put_sample(sample).unwrap()
+        self.try_put_sample(sample)
+            .unwrap_or_else(|error| panic!("{error}"))
     }
 
     fn try_put<B>(&mut self, mut source: B) -> Result<(), TryPutError>
@@ -332,7 +384,7 @@ pub trait SampleBufMut<S> {
             })
         }
         else {
-            loop {
+            while source.has_remaining() {
                 let source_chunk = source.chunk();
                 let destination_chunk = self.chunk_mut();
                 let n = source_chunk.len().min(destination_chunk.len());
@@ -342,6 +394,7 @@ pub trait SampleBufMut<S> {
                     self.advance_mut(n);
                 }
             }
+            Ok(())
         }
     }
 
@@ -351,7 +404,8 @@ pub trait SampleBufMut<S> {
         B: SampleBuf<S>,
         S: Clone,
     {
-        self.try_put(source).unwrap();
+        self.try_put(source)
+            .unwrap_or_else(|error| panic!("{error}"));
     }
 
     #[inline]
@@ -384,16 +438,17 @@ pub trait SampleBufMut<S> {
 
 impl<S> SampleBufMut<S> for &mut [S] {
     unsafe fn advance_mut(&mut self, amount: usize) {
-        if amount > self.len() {
-            panic!(
-                "{}",
-                TryAdvanceError {
-                    requested: amount,
-                    available: self.len(),
-                }
-            );
-        }
+        debug_assert!(
+            amount <= self.len(),
+            "{}",
+            TryAdvanceError {
+                requested: amount,
+                available: self.len(),
+            }
+        );
 
+        // this still panics if `amount` is out of bounds, but that's fine since
+        // it's a precondition anyway.
         let (_, right) = core::mem::take(self).split_at_mut(amount);
         *self = right;
     }
@@ -412,6 +467,7 @@ impl<S> SampleBufMut<S> for &mut [S] {
 impl<S> SampleBufMut<S> for Vec<S> {
     unsafe fn advance_mut(&mut self, amount: usize) {
         let new_length = self.len() + amount;
+        // `set_len` past the capacity would be undefined behaviour
         assert!(new_length <= self.capacity());
         unsafe {
             self.set_len(new_length);
@@ -440,10 +496,11 @@ where
     B: SampleBufMut<S>,
 {
     unsafe fn advance_mut(&mut self, amount: usize) {
-        assert!(amount < self.limit);
+        assert!(amount <= self.limit);
         unsafe {
             self.inner.advance_mut(amount);
         }
+        self.limit = self.limit.saturating_sub(amount);
     }
 
     fn remaining_mut(&self) -> usize {
@@ -506,3 +563,49 @@ fn slice_bounds(
         Ok((start, end))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::buf::{
+        SampleBuf,
+        SampleBufMut,
+        Samples,
+    };
+
+    #[test]
+    fn chain_and_take_advance_by_requested_amount() {
+        let mut buf = (&[1, 2, 3][..]).chain(&[4, 5][..]);
+        buf.advance(1);
+        assert_eq!(buf.chunk(), &[2, 3]);
+        buf.advance(3);
+        assert_eq!(buf.chunk(), &[5]);
+        assert!(buf.try_advance(2).is_err());
+
+        let mut take = (&[1, 2, 3, 4][..]).take(3);
+        take.advance(2);
+        assert_eq!(take.remaining(), 1);
+        assert_eq!(take.chunk(), &[3]);
+    }
+
+    #[test]
+    fn try_variants_report_errors() {
+        let mut buf = &[1, 2][..];
+        assert!(buf.try_copy_to_samples(3).is_err());
+        assert_eq!(buf.try_copy_to_samples(2).unwrap().chunk(), &[1, 2]);
+
+        let mut storage = [0; 2];
+        let mut output = &mut storage[..];
+        assert!(output.try_put(&[1, 2, 3][..]).is_err());
+        output.put(&[1, 2][..]);
+        assert!(output.try_put_sample(3).is_err());
+        assert_eq!(storage, [1, 2]);
+    }
+
+    #[test]
+    fn samples_slice_is_relative() {
+        let mut samples = Samples::clone_from_slice(&[1, 2, 3, 4, 5]);
+        samples.advance(1);
+        assert_eq!(samples.slice(1..3).chunk(), &[3, 4]);
+        assert!(samples.try_slice(..5).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Memory policy for combinator buffers.
+//!
+//! Combinators that buffer samples internally (e.g.
+//! [`Buffered`][crate::io::combinators::Buffered] or
+//! [`Repeated`][crate::io::combinators::Repeated]) size their buffers from
+//! size hints or from how much the caller asked for. That's fine for a
+//! desktop app, but a long-running daemon wants an upper bound. Combinators
+//! read the [global policy][BufferPolicy::global] when they are created, so
+//! changing it only affects combinators created afterwards.
+
+use std::{
+    alloc::{
+        AllocError,
+        Allocator,
+        Global,
+        Layout,
+    },
+    fmt::Debug,
+    ptr::NonNull,
+    sync::Arc,
+};
+
+use parking_lot::RwLock;
+
+use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    io::SizeHint,
+};
+
+/// Chunk size used when a stream gives no upper bound for its length.
+pub const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+static GLOBAL_POLICY: RwLock<BufferPolicy> = RwLock::new(BufferPolicy::new());
+
+#[derive(Clone, Debug)]
+pub struct BufferPolicy {
+    max_buffer_bytes: Option<usize>,
+    preferred_chunk_size: usize,
+    allocator: PolicyAllocator,
+}
+
+impl Default for BufferPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BufferPolicy {
+    /// Unbounded buffers, allocated from the global allocator.
+    pub const fn new() -> Self {
+        Self {
+            max_buffer_bytes: None,
+            preferred_chunk_size: DEFAULT_CHUNK_SIZE,
+            allocator: PolicyAllocator(None),
+        }
+    }
+
+    /// Returns the policy combinators currently use.
+    pub fn global() -> Self {
+        GLOBAL_POLICY.read().clone()
+    }
+
+    /// Sets the policy used by combinators that are created from now on.
+    pub fn set_global(policy: Self) {
+        *GLOBAL_POLICY.write() = policy;
+    }
+
+    /// Limits any single buffer of a combinator to `max_buffer_bytes`.
+    ///
+    /// Buffers that only need to be large for performance are capped
+    /// silently. Combinators that need the memory to work correctly (e.g.
+    /// [`Repeated`][crate::io::combinators::Repeated]) fail with
+    /// [`BufferLimitExceeded`] instead.
+    pub fn with_max_buffer_bytes(mut self, max_buffer_bytes: Option<usize>) -> Self {
+        self.max_buffer_bytes = max_buffer_bytes;
+        self
+    }
+
+    /// Number of samples to read at a time if a stream's length is unknown.
+    pub fn with_preferred_chunk_size(mut self, preferred_chunk_size: usize) -> Self {
+        self.preferred_chunk_size = preferred_chunk_size.max(1);
+        self
+    }
+
+    /// Allocates buffers from `allocator`, e.g. an arena or a counting
+    /// allocator.
+    pub fn with_allocator<A>(mut self, allocator: A) -> Self
+    where
+        A: Allocator + Debug + Send + Sync + 'static,
+    {
+        self.allocator = PolicyAllocator(Some(Arc::new(allocator)));
+        self
+    }
+
+    #[inline]
+    pub fn max_buffer_bytes(&self) -> Option<usize> {
+        self.max_buffer_bytes
+    }
+
+    #[inline]
+    pub fn preferred_chunk_size(&self) -> usize {
+        self.preferred_chunk_size
+    }
+
+    #[inline]
+    pub fn allocator(&self) -> &PolicyAllocator {
+        &self.allocator
+    }
+
+    /// Maximum number of samples of type `S` in a single buffer.
+    ///
+    /// This is at least 1, so that combinators can always make progress.
+    pub fn max_samples<S>(&self) -> usize {
+        self.max_buffer_bytes
+            .map_or(usize::MAX, |max_buffer_bytes| {
+                (max_buffer_bytes / size_of::<S>().max(1)).max(1)
+            })
+    }
+
+    /// Number of samples to reserve for reading from a stream with the given
+    /// size hint.
+    pub fn buffer_size<S>(&self, size_hint: SizeHint) -> usize {
+        size_hint
+            .buffer_size(self.preferred_chunk_size)
+            .min(self.max_samples::<S>())
+    }
+
+    /// Checks whether a buffer may hold `num_samples` samples of type `S`.
+    pub fn check<S>(&self, num_samples: usize) -> Result<(), BufferLimitExceeded> {
+        if num_samples > self.max_samples::<S>() {
+            Err(BufferLimitExceeded {
+                requested_bytes: num_samples.saturating_mul(size_of::<S>()),
+                max_bytes: self.max_buffer_bytes.unwrap_or(usize::MAX),
+            })
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+#[error("Buffer of {requested_bytes} bytes exceeds the limit of {max_bytes} bytes")]
+pub struct BufferLimitExceeded {
+    pub requested_bytes: usize,
+    pub max_bytes: usize,
+}
+
+impl ClassifyError for BufferLimitExceeded {
+    fn error_kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+/// Allocator chosen by a [`BufferPolicy`].
+///
+/// This is the global allocator, unless one was set with
+/// [`BufferPolicy::with_allocator`].
+#[derive(Clone, Debug, Default)]
+pub struct PolicyAllocator(Option<Arc<dyn DebugAllocator>>);
+
+trait DebugAllocator: Allocator + Debug + Send + Sync {}
+
+impl<A: Allocator + Debug + Send + Sync> DebugAllocator for A {}
+
+// SAFETY: clones share the same allocator, so memory allocated by one can be
+// freed by any other.
+unsafe impl Allocator for PolicyAllocator {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        match &self.0 {
+            Some(allocator) => allocator.allocate(layout),
+            None => Global.allocate(layout),
+        }
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, pointer: NonNull<u8>, layout: Layout) {
+        unsafe {
+            match &self.0 {
+                Some(allocator) => allocator.deallocate(pointer, layout),
+                None => Global.deallocate(pointer, layout),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex;
+
+    use super::BufferPolicy;
+
+    #[test]
+    fn it_limits_samples_by_size() {
+        let policy = BufferPolicy::new().with_max_buffer_bytes(Some(1024));
+        assert_eq!(policy.max_samples::<u8>(), 1024);
+        assert_eq!(policy.max_samples::<Complex<f32>>(), 128);
+        assert!(policy.check::<Complex<f32>>(128).is_ok());
+        assert!(policy.check::<Complex<f32>>(129).is_err());
+
+        // always at least one sample
+        let policy = BufferPolicy::new().with_max_buffer_bytes(Some(0));
+        assert_eq!(policy.max_samples::<f32>(), 1);
+    }
+}
@@ -7,6 +7,7 @@ use std::{
 };
 
 use crate::buf::{
+    IndexOutOfBounds,
     IntoIter,
     SampleBuf,
     TryAdvanceError,
@@ -133,15 +134,26 @@ impl<S> Samples<S> {
         unsafe { self.buffer[self.start..][..self.length].assume_init_ref() }
     }
 
+    /// Returns a sub-slice of these samples, sharing the same buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds. See [`try_slice`][Self::try_slice]
+    /// for a non-panicking version.
     #[inline]
     pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
-        let (start, end) = slice_bounds(range, self.length).unwrap();
-        Self {
+        self.try_slice(range)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    pub fn try_slice(&self, range: impl RangeBounds<usize>) -> Result<Self, IndexOutOfBounds> {
+        let (start, end) = slice_bounds(range, self.length)?;
+        Ok(Self {
             buffer: self.buffer.clone(),
             initialized: self.initialized,
-            start,
+            start: self.start + start,
             length: end - start,
-        }
+        })
     }
 }
 
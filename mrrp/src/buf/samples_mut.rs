@@ -157,7 +157,7 @@ impl<S> SampleBufMut<S> for SamplesMut<S> {
     #[inline]
     unsafe fn advance_mut(&mut self, amount: usize) {
         unsafe {
-            self.set_length(self.buffer.len() + amount);
+            self.set_length(self.len() + amount);
         }
     }
 
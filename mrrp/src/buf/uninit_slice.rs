@@ -1,10 +1,12 @@
 use core::fmt;
 use std::{
+    alloc::Allocator,
     mem::MaybeUninit,
     ops::{
         Bound,
         Index,
         IndexMut,
+        Range,
     },
     sync::Arc,
 };
@@ -54,7 +56,9 @@ impl<S> UninitSlice<S> {
     }
 
     #[inline]
-    pub fn box_from_uninit(value: Box<[MaybeUninit<S>]>) -> Box<UninitSlice<S>> {
+    pub fn box_from_uninit<A: Allocator>(
+        value: Box<[MaybeUninit<S>], A>,
+    ) -> Box<UninitSlice<S>, A> {
         let (pointer, alloc) = Box::into_raw_with_allocator(value);
         unsafe { Box::from_raw_in(UninitSlice::pointer_mut_from_uninit(pointer), alloc) }
     }
@@ -70,6 +74,11 @@ impl<S> UninitSlice<S> {
         Self::box_from_uninit(Box::new_uninit_slice(length))
     }
 
+    #[inline]
+    pub fn box_new_in<A: Allocator>(length: usize, allocator: A) -> Box<UninitSlice<S>, A> {
+        Self::box_from_uninit(Box::new_uninit_slice_in(length, allocator))
+    }
+
     #[inline]
     pub fn arc_from_init(value: Arc<[S]>) -> Arc<UninitSlice<S>> {
         let (pointer, alloc) = Arc::into_raw_with_allocator(value);
@@ -110,6 +119,22 @@ impl<S> UninitSlice<S> {
         }
     }
 
+    /// Moves the samples in `source` to `destination`, like
+    /// [`slice::copy_within`]. The samples in `source` that are not
+    /// overwritten must be considered uninitialized afterwards.
+    #[inline]
+    pub fn move_within(&mut self, source: Range<usize>, destination: usize) {
+        let _ = &self.0[source.clone()];
+        let _ = &self.0[destination..][..source.len()];
+        unsafe {
+            std::ptr::copy(
+                self.0.as_ptr().add(source.start),
+                self.0.as_mut_ptr().add(destination),
+                source.len(),
+            );
+        }
+    }
+
     #[inline]
     pub fn as_mut_ptr(&mut self) -> *mut S {
         self.0.as_mut_ptr() as *mut S
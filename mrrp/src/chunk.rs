@@ -23,9 +23,11 @@ use crate::{
         AsyncReadSamples,
         AsyncWriteSamples,
         AsyncWriteSamplesExt,
+        GetSampleIndexMap,
         GetSampleRate,
         ReadBuf,
         Remaining,
+        SampleIndexMap,
         StreamLength,
     },
 };
@@ -114,6 +116,16 @@ where
     }
 }
 
+impl<T, C, S, E> GetSampleIndexMap for ChunkStreamReadSamples<T, C, S, E>
+where
+    T: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.stream.sample_index_map()
+    }
+}
+
 impl<T, C, S, E> StreamLength for ChunkStreamReadSamples<T, C, S, E> {
     #[inline]
     fn remaining(&self) -> Remaining {
@@ -175,6 +187,16 @@ where
     }
 }
 
+impl<R, S> GetSampleIndexMap for ReadSamplesChunkStream<R, S>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.read_samples.sample_index_map()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct WriteSamplesChunkSink<W, C, S> {
     pub write_samples: W,
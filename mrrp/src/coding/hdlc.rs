@@ -0,0 +1,287 @@
+//! HDLC framing
+//!
+//! Frames are delimited by flags (`0x7e`) and protected by a 16 bit frame check
+//! sequence (FCS). Bytes are sent LSB first. Within a frame, a 0 is inserted
+//! after five consecutive 1s, so that the flag never appears in the data. Seven
+//! or more 1s abort a frame.
+//!
+//! This is the link layer of AX.25. The bits are usually NRZI coded on the
+//! line, see [`Transition::OnZero`][crate::coding::line::Transition::OnZero].
+
+use crate::io::combinators::Scanner;
+
+pub const FLAG: u8 = 0x7e;
+
+/// Frames need at least this many bytes, including the FCS.
+pub const MIN_FRAME_LENGTH: usize = 3;
+
+/// Default maximum length of a frame in bytes, including the FCS.
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 1024;
+
+/// CRC-16/X-25, as used for the FCS.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for byte in data {
+        crc ^= u16::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            }
+            else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Finds frames in a bit stream.
+///
+/// Outputs the contents of a frame without its FCS, after its closing flag.
+/// Frames with a wrong FCS are dropped.
+#[derive(Clone, Debug)]
+pub struct HdlcDeframer {
+    max_length: usize,
+    /// Whether a flag was seen since the last abort.
+    in_frame: bool,
+    /// Number of consecutive 1s.
+    num_ones: usize,
+    bytes: Vec<u8>,
+    byte: u8,
+    num_bits: usize,
+    num_bad_frames: usize,
+}
+
+impl Default for HdlcDeframer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HdlcDeframer {
+    pub fn new() -> Self {
+        Self {
+            max_length: DEFAULT_MAX_FRAME_LENGTH,
+            in_frame: false,
+            num_ones: 0,
+            bytes: vec![],
+            byte: 0,
+            num_bits: 0,
+            num_bad_frames: 0,
+        }
+    }
+
+    /// Drops frames that are longer than `max_length` bytes, including the
+    /// FCS.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Number of frames that were dropped because of a wrong FCS.
+    #[inline]
+    pub fn num_bad_frames(&self) -> usize {
+        self.num_bad_frames
+    }
+
+    fn push_bit(&mut self, bit: bool) {
+        if !self.in_frame {
+            return;
+        }
+
+        self.byte |= u8::from(bit) << self.num_bits;
+        self.num_bits += 1;
+        if self.num_bits == 8 {
+            if self.bytes.len() == self.max_length {
+                self.in_frame = false;
+                self.bytes.clear();
+            }
+            else {
+                self.bytes.push(self.byte);
+            }
+            self.byte = 0;
+            self.num_bits = 0;
+        }
+    }
+
+    fn end_frame(&mut self) -> Option<Vec<u8>> {
+        // the flag's 0 and five 1s were pushed as data bits
+        let is_aligned = self.num_bits == 6;
+        let was_in_frame = std::mem::replace(&mut self.in_frame, true);
+        self.byte = 0;
+        self.num_bits = 0;
+        let mut frame = std::mem::take(&mut self.bytes);
+
+        if !was_in_frame || !is_aligned || frame.len() < MIN_FRAME_LENGTH {
+            return None;
+        }
+
+        let fcs_start = frame.len() - 2;
+        let fcs = u16::from_le_bytes([frame[fcs_start], frame[fcs_start + 1]]);
+        if crc16(&frame[..fcs_start]) != fcs {
+            self.num_bad_frames += 1;
+            return None;
+        }
+
+        frame.truncate(fcs_start);
+        Some(frame)
+    }
+}
+
+impl Scanner<bool> for HdlcDeframer {
+    type Output = Option<Vec<u8>>;
+
+    fn scan(&mut self, bit: bool) -> Self::Output {
+        if bit {
+            self.num_ones += 1;
+            match self.num_ones {
+                ..=5 => self.push_bit(true),
+                // part of a flag, or an abort
+                6 => {}
+                _ => {
+                    self.in_frame = false;
+                    self.bytes.clear();
+                }
+            }
+            None
+        }
+        else {
+            let num_ones = std::mem::take(&mut self.num_ones);
+            match num_ones {
+                // stuffed bit
+                5 => None,
+                6 => self.end_frame(),
+                _ => {
+                    self.push_bit(false);
+                    None
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.in_frame = false;
+        self.num_ones = 0;
+        self.bytes.clear();
+        self.byte = 0;
+        self.num_bits = 0;
+        None
+    }
+}
+
+/// Encodes frames into a bit stream.
+#[derive(Clone, Debug)]
+pub struct HdlcEncoder {
+    bits: Vec<bool>,
+    num_preamble_flags: usize,
+}
+
+impl Default for HdlcEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HdlcEncoder {
+    pub fn new() -> Self {
+        Self {
+            bits: vec![],
+            num_preamble_flags: 32,
+        }
+    }
+
+    /// Number of flags before the first frame, which give the receiver time
+    /// to synchronize.
+    pub fn with_preamble(mut self, num_flags: usize) -> Self {
+        self.num_preamble_flags = num_flags;
+        self
+    }
+
+    fn push_flag(&mut self) {
+        self.bits.extend((0..8).map(|i| FLAG & (1 << i) != 0));
+    }
+
+    /// Adds a frame and its FCS, followed by a flag.
+    pub fn push(&mut self, frame: &[u8]) {
+        if self.bits.is_empty() {
+            for _ in 0..self.num_preamble_flags.max(1) {
+                self.push_flag();
+            }
+        }
+
+        let fcs = crc16(frame).to_le_bytes();
+        let mut num_ones = 0;
+        for byte in frame.iter().chain(&fcs) {
+            for i in 0..8 {
+                let bit = byte & (1 << i) != 0;
+                self.bits.push(bit);
+                if bit {
+                    num_ones += 1;
+                    if num_ones == 5 {
+                        self.bits.push(false);
+                        num_ones = 0;
+                    }
+                }
+                else {
+                    num_ones = 0;
+                }
+            }
+        }
+
+        self.push_flag();
+    }
+
+    /// Returns the bits of the transmission.
+    pub fn finish(mut self) -> Vec<bool> {
+        // some receivers need a few more flags to see the end of the frame
+        for _ in 0..2 {
+            self.push_flag();
+        }
+        self.bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        HdlcDeframer,
+        HdlcEncoder,
+        crc16,
+    };
+    use crate::io::combinators::Scanner;
+
+    #[test]
+    fn it_computes_the_fcs() {
+        assert_eq!(crc16(b"123456789"), 0x906e);
+    }
+
+    #[test]
+    fn it_round_trips_frames() {
+        // lots of 1s, which need bit stuffing
+        let frames = [
+            b"hello".to_vec(),
+            vec![0xff, 0x7e, 0xfe, 0x3f, 0x00],
+            b"world".to_vec(),
+        ];
+
+        let mut encoder = HdlcEncoder::new();
+        for frame in &frames {
+            encoder.push(frame);
+        }
+        let mut bits = encoder.finish();
+
+        // noise before the transmission, and the first frame is aborted
+        let mut noise = vec![true, false, true, true, false, false, true];
+        noise.extend(bits.drain(..8 * 32 + 20));
+        noise.extend([true; 7]);
+        noise.extend(bits);
+
+        let mut deframer = HdlcDeframer::new();
+        let received = noise
+            .into_iter()
+            .filter_map(|bit| deframer.scan(bit))
+            .collect::<Vec<_>>();
+        assert_eq!(received, &frames[1..]);
+        assert_eq!(deframer.num_bad_frames(), 0);
+    }
+}
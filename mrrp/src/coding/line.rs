@@ -0,0 +1,425 @@
+//! Line coding
+//!
+//! - Differential coding (NRZ-M, NRZ-S) encodes bits as transitions, which
+//!   makes the bit stream immune to inversion.
+//! - Manchester coding encodes each bit as a pair of opposite chips, which
+//!   guarantees a transition in every bit.
+
+use crate::{
+    coding::Polarity,
+    io::combinators::{
+        GroupDelay,
+        Scanner,
+    },
+};
+
+/// Which bit value is encoded as a transition by differential coding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transition {
+    /// NRZ-M: A `true` bit is sent as a transition.
+    #[default]
+    OnOne,
+
+    /// NRZ-S: A `false` bit is sent as a transition. This is also commonly
+    /// called NRZI, e.g. by AX.25 and USB.
+    OnZero,
+}
+
+impl Transition {
+    #[inline]
+    fn is_transition(self, bit: bool) -> bool {
+        match self {
+            Self::OnOne => bit,
+            Self::OnZero => !bit,
+        }
+    }
+}
+
+/// Differential encoder.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiffEncode {
+    transition: Transition,
+    level: bool,
+}
+
+impl DiffEncode {
+    #[inline]
+    pub fn new(transition: Transition) -> Self {
+        Self {
+            transition,
+            level: false,
+        }
+    }
+
+    /// Sets the level before the first bit.
+    #[inline]
+    pub fn with_initial_level(mut self, level: bool) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+impl Scanner<bool> for DiffEncode {
+    type Output = bool;
+
+    #[inline]
+    fn scan(&mut self, bit: bool) -> Self::Output {
+        self.level ^= self.transition.is_transition(bit);
+        self.level
+    }
+}
+
+impl GroupDelay for DiffEncode {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Differential decoder.
+///
+/// The output doesn't depend on the polarity of the input, except for the
+/// first bit, which depends on the
+/// [initial level][Self::with_initial_level].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiffDecode {
+    transition: Transition,
+    level: bool,
+}
+
+impl DiffDecode {
+    #[inline]
+    pub fn new(transition: Transition) -> Self {
+        Self {
+            transition,
+            level: false,
+        }
+    }
+
+    /// Sets the level before the first bit.
+    #[inline]
+    pub fn with_initial_level(mut self, level: bool) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+impl Scanner<bool> for DiffDecode {
+    type Output = bool;
+
+    #[inline]
+    fn scan(&mut self, level: bool) -> Self::Output {
+        let changed = level != self.level;
+        self.level = level;
+        self.transition.is_transition(true) == changed
+    }
+}
+
+impl GroupDelay for DiffDecode {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Chip order of Manchester coding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ManchesterConvention {
+    /// IEEE 802.3: A `true` bit is sent as a low chip followed by a high chip.
+    #[default]
+    Ieee,
+
+    /// G. E. Thomas: A `true` bit is sent as a high chip followed by a low
+    /// chip.
+    Thomas,
+}
+
+/// Manchester encoder.
+///
+/// Outputs two chips per bit.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ManchesterEncode {
+    convention: ManchesterConvention,
+}
+
+impl ManchesterEncode {
+    #[inline]
+    pub fn new(convention: ManchesterConvention) -> Self {
+        Self { convention }
+    }
+}
+
+impl Scanner<bool> for ManchesterEncode {
+    type Output = [bool; 2];
+
+    #[inline]
+    fn scan(&mut self, bit: bool) -> Self::Output {
+        match self.convention {
+            ManchesterConvention::Ieee => [!bit, bit],
+            ManchesterConvention::Thomas => [bit, !bit],
+        }
+    }
+}
+
+impl GroupDelay for ManchesterEncode {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Two chips of a Manchester coded bit were equal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Manchester code violation")]
+pub struct CodeViolation;
+
+/// Manchester decoder.
+///
+/// Scans chips and outputs a bit for every second chip. If the two chips of a
+/// bit are equal, it outputs a [`CodeViolation`] instead. With
+/// [auto alignment][Self::with_auto_align] it then slips by one chip, which
+/// finds the correct chip alignment within a few bits.
+///
+/// An inverted chip stream decodes with the opposite
+/// [convention][ManchesterConvention]. If the polarity is known, e.g. from a
+/// [`SyncWord`][crate::coding::sync::SyncWord], it can be corrected with
+/// [`set_polarity`][Self::set_polarity].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ManchesterDecode {
+    convention: ManchesterConvention,
+    polarity: Polarity,
+    auto_align: bool,
+    first_chip: Option<bool>,
+    num_violations: usize,
+}
+
+impl ManchesterDecode {
+    #[inline]
+    pub fn new(convention: ManchesterConvention) -> Self {
+        Self {
+            convention,
+            polarity: Polarity::Normal,
+            auto_align: false,
+            first_chip: None,
+            num_violations: 0,
+        }
+    }
+
+    #[inline]
+    pub fn with_auto_align(mut self, auto_align: bool) -> Self {
+        self.auto_align = auto_align;
+        self
+    }
+
+    #[inline]
+    pub fn with_polarity(mut self, polarity: Polarity) -> Self {
+        self.polarity = polarity;
+        self
+    }
+
+    #[inline]
+    pub fn polarity(&self) -> Polarity {
+        self.polarity
+    }
+
+    #[inline]
+    pub fn set_polarity(&mut self, polarity: Polarity) {
+        self.polarity = polarity;
+    }
+
+    /// Skips one chip, moving the bit boundary.
+    #[inline]
+    pub fn chip_slip(&mut self) {
+        self.first_chip = None;
+    }
+
+    /// Number of code violations seen so far.
+    #[inline]
+    pub fn num_violations(&self) -> usize {
+        self.num_violations
+    }
+}
+
+impl Scanner<bool> for ManchesterDecode {
+    type Output = Option<Result<bool, CodeViolation>>;
+
+    fn scan(&mut self, chip: bool) -> Self::Output {
+        let chip = self.polarity.apply(chip);
+
+        let Some(first_chip) = self.first_chip.take()
+        else {
+            self.first_chip = Some(chip);
+            return None;
+        };
+
+        if first_chip == chip {
+            self.num_violations += 1;
+            if self.auto_align {
+                // treat this chip as the first chip of the next bit
+                self.first_chip = Some(chip);
+            }
+            Some(Err(CodeViolation))
+        }
+        else {
+            Some(Ok(match self.convention {
+                ManchesterConvention::Ieee => chip,
+                ManchesterConvention::Thomas => first_chip,
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CodeViolation,
+        DiffDecode,
+        DiffEncode,
+        ManchesterConvention,
+        ManchesterDecode,
+        ManchesterEncode,
+        Transition,
+    };
+    use crate::{
+        coding::Polarity,
+        io::combinators::Scanner,
+    };
+
+    #[test]
+    fn diff_encode_truth_table() {
+        // (transition, level, bit) -> level
+        let table = [
+            (Transition::OnOne, false, false, false),
+            (Transition::OnOne, false, true, true),
+            (Transition::OnOne, true, false, true),
+            (Transition::OnOne, true, true, false),
+            (Transition::OnZero, false, false, true),
+            (Transition::OnZero, false, true, false),
+            (Transition::OnZero, true, false, false),
+            (Transition::OnZero, true, true, true),
+        ];
+
+        for (transition, level, bit, expected) in table {
+            let mut encoder = DiffEncode::new(transition).with_initial_level(level);
+            assert_eq!(
+                encoder.scan(bit),
+                expected,
+                "{transition:?}, level={level}, bit={bit}"
+            );
+        }
+    }
+
+    #[test]
+    fn diff_decode_truth_table() {
+        // (transition, previous level, level) -> bit
+        let table = [
+            (Transition::OnOne, false, false, false),
+            (Transition::OnOne, false, true, true),
+            (Transition::OnOne, true, false, true),
+            (Transition::OnOne, true, true, false),
+            (Transition::OnZero, false, false, true),
+            (Transition::OnZero, false, true, false),
+            (Transition::OnZero, true, false, false),
+            (Transition::OnZero, true, true, true),
+        ];
+
+        for (transition, previous, level, expected) in table {
+            let mut decoder = DiffDecode::new(transition).with_initial_level(previous);
+            assert_eq!(
+                decoder.scan(level),
+                expected,
+                "{transition:?}, previous={previous}, level={level}"
+            );
+        }
+    }
+
+    #[test]
+    fn diff_decode_ignores_inversion() {
+        let bits = (0..64).map(|i| (i * 7) % 5 < 2).collect::<Vec<_>>();
+
+        for transition in [Transition::OnOne, Transition::OnZero] {
+            let mut encoder = DiffEncode::new(transition);
+            let mut decoder = DiffDecode::new(transition);
+
+            // the first bit is lost, because the decoder starts with the wrong level
+            let decoded = bits
+                .iter()
+                .map(|bit| decoder.scan(!encoder.scan(*bit)))
+                .collect::<Vec<_>>();
+            assert_eq!(decoded[1..], bits[1..]);
+        }
+    }
+
+    #[test]
+    fn manchester_encode_truth_table() {
+        let table = [
+            (ManchesterConvention::Ieee, false, [true, false]),
+            (ManchesterConvention::Ieee, true, [false, true]),
+            (ManchesterConvention::Thomas, false, [false, true]),
+            (ManchesterConvention::Thomas, true, [true, false]),
+        ];
+
+        for (convention, bit, expected) in table {
+            assert_eq!(ManchesterEncode::new(convention).scan(bit), expected);
+        }
+    }
+
+    #[test]
+    fn manchester_decode_truth_table() {
+        // (convention, polarity, chips) -> bit
+        #[rustfmt::skip]
+        let table = [
+            (ManchesterConvention::Ieee, Polarity::Normal, [false, false], Err(CodeViolation)),
+            (ManchesterConvention::Ieee, Polarity::Normal, [false, true], Ok(true)),
+            (ManchesterConvention::Ieee, Polarity::Normal, [true, false], Ok(false)),
+            (ManchesterConvention::Ieee, Polarity::Normal, [true, true], Err(CodeViolation)),
+            (ManchesterConvention::Ieee, Polarity::Inverted, [false, false], Err(CodeViolation)),
+            (ManchesterConvention::Ieee, Polarity::Inverted, [false, true], Ok(false)),
+            (ManchesterConvention::Ieee, Polarity::Inverted, [true, false], Ok(true)),
+            (ManchesterConvention::Ieee, Polarity::Inverted, [true, true], Err(CodeViolation)),
+            (ManchesterConvention::Thomas, Polarity::Normal, [false, false], Err(CodeViolation)),
+            (ManchesterConvention::Thomas, Polarity::Normal, [false, true], Ok(false)),
+            (ManchesterConvention::Thomas, Polarity::Normal, [true, false], Ok(true)),
+            (ManchesterConvention::Thomas, Polarity::Normal, [true, true], Err(CodeViolation)),
+            (ManchesterConvention::Thomas, Polarity::Inverted, [false, false], Err(CodeViolation)),
+            (ManchesterConvention::Thomas, Polarity::Inverted, [false, true], Ok(true)),
+            (ManchesterConvention::Thomas, Polarity::Inverted, [true, false], Ok(false)),
+            (ManchesterConvention::Thomas, Polarity::Inverted, [true, true], Err(CodeViolation)),
+        ];
+
+        for (convention, polarity, [first, second], expected) in table {
+            let mut decoder = ManchesterDecode::new(convention).with_polarity(polarity);
+            assert_eq!(decoder.scan(first), None);
+            assert_eq!(
+                decoder.scan(second),
+                Some(expected),
+                "{convention:?}, {polarity:?}, chips={first},{second}"
+            );
+        }
+    }
+
+    #[test]
+    fn manchester_decode_aligns_itself() {
+        let bits = [
+            true, true, false, true, false, false, true, true, true, false,
+        ];
+        let mut encoder = ManchesterEncode::default();
+
+        // start in the middle of a bit, so the decoder is misaligned
+        let chips = bits
+            .iter()
+            .flat_map(|bit| encoder.scan(*bit))
+            .skip(1)
+            .collect::<Vec<_>>();
+
+        let mut decoder = ManchesterDecode::default().with_auto_align(true);
+        let decoded = chips
+            .iter()
+            .filter_map(|chip| decoder.scan(*chip))
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+
+        assert!(decoder.num_violations() > 0);
+        assert!(decoded.ends_with(&bits[4..]));
+    }
+}
@@ -0,0 +1,42 @@
+//! Bit-level coding
+//!
+//! Scanners operating on bit streams, i.e. streams of `bool`, as they come out
+//! of a symbol slicer. Use
+//! [`pack_bits`][crate::io::AsyncReadSamplesExt::pack_bits] to turn them into
+//! bytes for a framer.
+
+pub mod hdlc;
+pub mod line;
+pub mod sync;
+pub mod varicode;
+
+/// Polarity of a bit stream.
+///
+/// Many demodulators can't tell a bit from its inverse, e.g. FSK with an
+/// unknown sideband, or BPSK after carrier recovery.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Polarity {
+    #[default]
+    Normal,
+    Inverted,
+}
+
+impl Polarity {
+    /// Applies the polarity to a bit, i.e. inverts it if the polarity is
+    /// [`Inverted`][Self::Inverted].
+    #[inline]
+    pub fn apply(self, bit: bool) -> bool {
+        match self {
+            Self::Normal => bit,
+            Self::Inverted => !bit,
+        }
+    }
+
+    #[inline]
+    pub fn invert(self) -> Self {
+        match self {
+            Self::Normal => Self::Inverted,
+            Self::Inverted => Self::Normal,
+        }
+    }
+}
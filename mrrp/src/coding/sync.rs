@@ -0,0 +1,155 @@
+//! Sync word detection
+
+use crate::{
+    coding::Polarity,
+    io::combinators::{
+        GroupDelay,
+        Scanner,
+    },
+};
+
+/// Finds a sync word in a bit stream.
+///
+/// Outputs `true` after the last bit of the sync word has been scanned. The
+/// next bit is the first bit after the sync word, which makes this useful to
+/// find the byte alignment of a frame, e.g. by calling
+/// [`PackBits::align`][crate::io::combinators::PackBits::align] at that point.
+#[derive(Clone, Copy, Debug)]
+pub struct SyncWord {
+    word: u64,
+    length: u32,
+    mask: u64,
+    max_errors: u32,
+    inverted: bool,
+    register: u64,
+    num_bits: u32,
+    last_match: Option<Polarity>,
+}
+
+impl SyncWord {
+    /// Creates a detector for the `length` least significant bits of `word`,
+    /// which are expected MSB first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `length` is 0 or more than 64.
+    pub fn new(word: u64, length: u32) -> Self {
+        assert!(
+            (1..=64).contains(&length),
+            "sync word length must be between 1 and 64: {length}"
+        );
+        let mask = u64::MAX >> (64 - length);
+        Self {
+            word: word & mask,
+            length,
+            mask,
+            max_errors: 0,
+            inverted: false,
+            register: 0,
+            num_bits: 0,
+            last_match: None,
+        }
+    }
+
+    /// Accepts the sync word with up to `max_errors` wrong bits.
+    #[inline]
+    pub fn with_max_errors(mut self, max_errors: u32) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Also accepts the inverted sync word. This resolves the polarity
+    /// ambiguity of e.g. FSK demodulators; see
+    /// [`last_match`][Self::last_match].
+    #[inline]
+    pub fn with_inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    /// Polarity of the last match.
+    #[inline]
+    pub fn last_match(&self) -> Option<Polarity> {
+        self.last_match
+    }
+
+    pub fn reset(&mut self) {
+        self.register = 0;
+        self.num_bits = 0;
+        self.last_match = None;
+    }
+}
+
+impl Scanner<bool> for SyncWord {
+    type Output = bool;
+
+    fn scan(&mut self, bit: bool) -> Self::Output {
+        self.register = ((self.register << 1) | u64::from(bit)) & self.mask;
+
+        // don't match before the register was filled once
+        if self.num_bits < self.length {
+            self.num_bits += 1;
+            if self.num_bits < self.length {
+                return false;
+            }
+        }
+
+        let errors = (self.register ^ self.word).count_ones();
+        if errors <= self.max_errors {
+            self.last_match = Some(Polarity::Normal);
+            true
+        }
+        else if self.inverted && self.length - errors <= self.max_errors {
+            self.last_match = Some(Polarity::Inverted);
+            true
+        }
+        else {
+            false
+        }
+    }
+}
+
+impl GroupDelay for SyncWord {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SyncWord;
+    use crate::{
+        coding::Polarity,
+        io::combinators::Scanner,
+    };
+
+    fn bits(word: u64, length: u32) -> impl Iterator<Item = bool> {
+        (0..length).rev().map(move |i| word & (1 << i) != 0)
+    }
+
+    #[test]
+    fn it_finds_sync_word_with_errors_and_inversion() {
+        let mut sync = SyncWord::new(0x2dd4, 16)
+            .with_max_errors(1)
+            .with_inverted(true);
+
+        // junk, the sync word with one bit error, junk, and the inverted sync word
+        let stream = bits(0b101, 3)
+            .chain(bits(0x2dd4 ^ 0x0100, 16))
+            .chain(bits(0b0110, 4))
+            .chain(bits(!0x2dd4, 16))
+            .collect::<Vec<_>>();
+
+        let matches = stream
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, bit)| sync.scan(bit).then(|| (i, sync.last_match())))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            matches,
+            [(18, Some(Polarity::Normal)), (38, Some(Polarity::Inverted))]
+        );
+    }
+}
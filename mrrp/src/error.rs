@@ -0,0 +1,164 @@
+//! Type-erased errors
+//!
+//! Every combinator has its own error type, which is nice if you want to know
+//! exactly where an error came from, but quickly leads to types like
+//! `ChainedError<ForwardError<...>, ...>`. Streams can be converted to return
+//! [`Error`] instead with
+//! [`erase_err`][crate::io::AsyncReadSamplesExt::erase_err]. The erased error
+//! still carries an [`ErrorKind`] that can be matched on.
+
+use std::{
+    convert::Infallible,
+    error::Error as StdError,
+    fmt::Display,
+};
+
+pub type BoxError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// Class of failure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A stream ended unexpectedly.
+    Eof,
+
+    /// The radio or audio device failed.
+    Hardware,
+
+    /// Data could not be decoded or encoded.
+    Decode,
+
+    /// An I/O error, e.g. from a file or socket.
+    Io,
+
+    /// Anything else.
+    Other,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ErrorKind::Eof => "eof",
+            ErrorKind::Hardware => "hardware",
+            ErrorKind::Decode => "decode",
+            ErrorKind::Io => "io",
+            ErrorKind::Other => "other",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Errors that know which [`ErrorKind`] they are.
+///
+/// Error types that wrap other errors (e.g.
+/// [`ChainedError`][crate::io::combinators::ChainedError]) forward to the
+/// wrapped error.
+pub trait ClassifyError {
+    fn error_kind(&self) -> ErrorKind;
+}
+
+/// Type-erased error with an [`ErrorKind`].
+#[derive(Debug, thiserror::Error)]
+#[error("{kind} error")]
+pub struct Error {
+    kind: ErrorKind,
+    #[source]
+    source: BoxError,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, source: impl Into<BoxError>) -> Self {
+        Self {
+            kind,
+            source: source.into(),
+        }
+    }
+
+    /// Erases the error type, but keeps its [`ErrorKind`].
+    pub fn erase<E>(error: E) -> Self
+    where
+        E: ClassifyError + StdError + Send + Sync + 'static,
+    {
+        Self::new(error.error_kind(), error)
+    }
+
+    pub fn other(source: impl Into<BoxError>) -> Self {
+        Self::new(ErrorKind::Other, source)
+    }
+
+    #[inline]
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &(dyn StdError + Send + Sync + 'static) {
+        &*self.source
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> BoxError {
+        self.source
+    }
+
+    /// Returns a reference to the original error, if it is of type `E`.
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: StdError + 'static,
+    {
+        self.source.downcast_ref()
+    }
+}
+
+impl ClassifyError for Error {
+    #[inline]
+    fn error_kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl ClassifyError for Infallible {
+    fn error_kind(&self) -> ErrorKind {
+        match *self {}
+    }
+}
+
+impl ClassifyError for std::io::Error {
+    fn error_kind(&self) -> ErrorKind {
+        match self.kind() {
+            std::io::ErrorKind::UnexpectedEof => ErrorKind::Eof,
+            _ => ErrorKind::Io,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Error,
+        ErrorKind,
+    };
+    use crate::io::{
+        EofError,
+        combinators::ChainedError,
+    };
+
+    #[test]
+    fn erased_error_keeps_kind_of_nested_error() {
+        let error: ChainedError<EofError<std::io::Error>, std::io::Error> =
+            ChainedError::Head(EofError::Eof {
+                num_samples_read: 12,
+            });
+        let erased = Error::erase(error);
+        assert_eq!(erased.kind(), ErrorKind::Eof);
+        assert!(
+            erased
+                .downcast_ref::<ChainedError<EofError<std::io::Error>, std::io::Error>>()
+                .is_some()
+        );
+
+        let error: ChainedError<std::io::Error, std::io::Error> =
+            ChainedError::Tail(std::io::Error::other("oops"));
+        assert_eq!(Error::erase(error).kind(), ErrorKind::Io);
+    }
+}
@@ -0,0 +1,50 @@
+// Radix-2 Stockham FFT. Each dispatch does one stage.
+//
+// Invocation `x` computes one butterfly, and invocation `y` selects the FFT in
+// the batch.
+
+struct Params {
+    // FFT size
+    n: u32,
+    // size of the sub-transforms computed by the previous stages
+    p: u32,
+    // number of FFTs in the batch
+    batch: u32,
+    // 1 for the inverse transform
+    inverse: u32,
+}
+
+@group(0) @binding(0)
+var<storage, read> source: array<vec2f>;
+
+@group(0) @binding(1)
+var<storage, read_write> destination: array<vec2f>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+const PI: f32 = 3.14159265358979323846;
+
+fn complex_mul(a: vec2f, b: vec2f) -> vec2f {
+    return vec2f(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+@compute @workgroup_size(64)
+fn stage(@builtin(global_invocation_id) id: vec3u) {
+    let half = params.n / 2u;
+    let i = id.x;
+    if i >= half || id.y >= params.batch {
+        return;
+    }
+
+    let offset = id.y * params.n;
+    let k = i & (params.p - 1u);
+
+    let alpha = select(-PI, PI, params.inverse != 0u) * f32(k) / f32(params.p);
+    let u0 = source[offset + i];
+    let u1 = complex_mul(source[offset + i + half], vec2f(cos(alpha), sin(alpha)));
+
+    let j = offset + 2u * i - k;
+    destination[j] = u0 + u1;
+    destination[j + params.p] = u0 - u1;
+}
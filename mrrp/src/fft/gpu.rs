@@ -0,0 +1,441 @@
+//! FFT on the GPU using wgpu.
+//!
+//! This implements a radix-2 Stockham FFT, with one compute dispatch per
+//! stage. Only power-of-two sizes are supported.
+
+use std::sync::mpsc;
+
+use num_complex::Complex;
+
+use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    fft::FftBackend,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum GpuError {
+    #[error("GPU FFT size must be a power of two and at least 2: {0}")]
+    UnsupportedSize(usize),
+    #[error("GPU FFT buffer of {required} bytes exceeds device limit of {limit} bytes")]
+    BufferTooLarge { required: u64, limit: u64 },
+    #[error("No GPU adapter available")]
+    Adapter(#[from] wgpu::RequestAdapterError),
+    #[error("Could not create GPU device")]
+    Device(#[from] wgpu::RequestDeviceError),
+}
+
+impl ClassifyError for GpuError {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            GpuError::UnsupportedSize(_) => ErrorKind::Other,
+            GpuError::BufferTooLarge { .. } | GpuError::Adapter(_) | GpuError::Device(_) => {
+                ErrorKind::Hardware
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Params {
+    n: u32,
+    p: u32,
+    batch: u32,
+    inverse: u32,
+}
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[derive(Debug)]
+struct Stage {
+    params: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// FFT running on the GPU.
+///
+/// Up to `max_batch` FFTs are computed per submission. Larger batches are
+/// split.
+///
+/// # Panics
+///
+/// [`process_batch`][FftBackend::process_batch] panics if the device is lost.
+#[derive(Debug)]
+pub struct GpuFft {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    data: [wgpu::Buffer; 2],
+    readback: wgpu::Buffer,
+    stages: Vec<Stage>,
+    size: usize,
+    max_batch: usize,
+    /// Batch size and direction the stage parameters were written for.
+    current_params: Option<(usize, bool)>,
+}
+
+impl GpuFft {
+    /// Creates a GPU FFT on a new device.
+    pub async fn new(size: usize, max_batch: usize) -> Result<Self, GpuError> {
+        check_size(size)?;
+
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("fft"),
+                ..Default::default()
+            })
+            .await?;
+
+        Self::with_device(device, queue, size, max_batch)
+    }
+
+    /// Creates a GPU FFT on an existing device, e.g. the one used for
+    /// rendering.
+    pub fn with_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        size: usize,
+        max_batch: usize,
+    ) -> Result<Self, GpuError> {
+        check_size(size)?;
+        let max_batch = max_batch.max(1);
+
+        let buffer_size = (size * max_batch * size_of::<Complex<f32>>()) as u64;
+        let limit = u64::from(device.limits().max_storage_buffer_binding_size);
+        if buffer_size > limit {
+            return Err(GpuError::BufferTooLarge {
+                required: buffer_size,
+                limit,
+            });
+        }
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("fft"),
+            entries: &[
+                // source
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // destination
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // params
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("fft"),
+            bind_group_layouts: &[Some(&bind_group_layout)],
+            immediate_size: 0,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("fft.wgsl"));
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("fft"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("stage"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let data = [0, 1].map(|_| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("fft data"),
+                size: buffer_size,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        });
+
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fft readback"),
+            size: buffer_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // the stages ping-pong between the two data buffers
+        let num_stages = size.trailing_zeros() as usize;
+        let stages = (0..num_stages)
+            .map(|i| {
+                let params = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("fft params"),
+                    size: size_of::<Params>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("fft"),
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: data[i % 2].as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: data[(i + 1) % 2].as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: params.as_entire_binding(),
+                        },
+                    ],
+                });
+
+                Stage { params, bind_group }
+            })
+            .collect();
+
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            data,
+            readback,
+            stages,
+            size,
+            max_batch,
+            current_params: None,
+        })
+    }
+
+    fn process_chunk(&mut self, data: &mut [Complex<f32>], inverse: bool) {
+        let batch = data.len() / self.size;
+        debug_assert!(batch <= self.max_batch);
+
+        if self.current_params != Some((batch, inverse)) {
+            for (i, stage) in self.stages.iter().enumerate() {
+                let params = Params {
+                    n: self.size as u32,
+                    p: 1 << i,
+                    batch: batch as u32,
+                    inverse: u32::from(inverse),
+                };
+                self.queue
+                    .write_buffer(&stage.params, 0, bytemuck::bytes_of(&params));
+            }
+            self.current_params = Some((batch, inverse));
+        }
+
+        let num_bytes = size_of_val(data) as u64;
+        self.queue
+            .write_buffer(&self.data[0], 0, bytemuck::cast_slice(data));
+
+        let mut command_encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("fft") });
+
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("fft"),
+                    timestamp_writes: None,
+                });
+            compute_pass.set_pipeline(&self.pipeline);
+
+            let num_workgroups = (self.size as u32 / 2).div_ceil(WORKGROUP_SIZE);
+            for stage in &self.stages {
+                compute_pass.set_bind_group(0, &stage.bind_group, &[]);
+                compute_pass.dispatch_workgroups(num_workgroups, batch as u32, 1);
+            }
+        }
+
+        let result = &self.data[self.stages.len() % 2];
+        command_encoder.copy_buffer_to_buffer(result, 0, &self.readback, 0, num_bytes);
+        self.queue.submit([command_encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        self.readback
+            .map_async(wgpu::MapMode::Read, ..num_bytes, move |result| {
+                let _ = sender.send(result);
+            });
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .expect("GPU device lost");
+        receiver
+            .recv()
+            .expect("map callback dropped")
+            .expect("failed to map FFT readback buffer");
+
+        {
+            let view = self.readback.slice(..num_bytes).get_mapped_range();
+            data.copy_from_slice(bytemuck::cast_slice(&view));
+        }
+        self.readback.unmap();
+    }
+}
+
+impl FftBackend for GpuFft {
+    #[inline]
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn process_batch(&mut self, data: &mut [Complex<f32>]) {
+        assert_eq!(data.len() % self.size, 0);
+
+        for chunk in data.chunks_mut(self.size * self.max_batch) {
+            self.process_chunk(chunk, false);
+        }
+    }
+
+    fn process_inverse_batch(&mut self, data: &mut [Complex<f32>]) {
+        assert_eq!(data.len() % self.size, 0);
+
+        for chunk in data.chunks_mut(self.size * self.max_batch) {
+            self.process_chunk(chunk, true);
+        }
+    }
+}
+
+fn check_size(size: usize) -> Result<(), GpuError> {
+    if size < 2 || !size.is_power_of_two() {
+        Err(GpuError::UnsupportedSize(size))
+    }
+    else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use num_complex::Complex;
+
+    use super::{
+        GpuError,
+        GpuFft,
+    };
+    use crate::{
+        fft::{
+            CpuFft,
+            FftBackend,
+        },
+        filter::{
+            fft_fir::FftFirFilter,
+            fir::hann_window,
+        },
+        io::combinators::Scanner,
+    };
+
+    /// Returns `None` if there's no GPU in this environment.
+    async fn gpu_fft(size: usize, max_batch: usize) -> Option<GpuFft> {
+        match GpuFft::new(size, max_batch).await {
+            Ok(gpu) => Some(gpu),
+            Err(GpuError::Adapter(_) | GpuError::Device(_)) => None,
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    fn test_signal(len: usize) -> Vec<Complex<f32>> {
+        (0..len)
+            .map(|i| Complex::new((i as f32 * 0.37).sin(), (i as f32 * 0.11).cos()))
+            .collect()
+    }
+
+    fn assert_close(output: &[Complex<f32>], expected: &[Complex<f32>]) {
+        for (output, expected) in output.iter().zip(expected) {
+            assert_abs_diff_eq!(output.re, expected.re, epsilon = 1e-2);
+            assert_abs_diff_eq!(output.im, expected.im, epsilon = 1e-2);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_matches_cpu_fft() {
+        let Some(mut gpu) = gpu_fft(256, 4).await
+        else {
+            return;
+        };
+        let mut cpu = CpuFft::new(256);
+
+        let mut expected = test_signal(256 * 6);
+        cpu.process_batch(&mut expected);
+        let mut output = test_signal(256 * 6);
+        gpu.process_batch(&mut output);
+
+        assert_close(&output, &expected);
+    }
+
+    #[tokio::test]
+    async fn it_inverts_the_fft() {
+        let Some(mut gpu) = gpu_fft(256, 4).await
+        else {
+            return;
+        };
+
+        let mut output = test_signal(256 * 6);
+        gpu.process_batch(&mut output);
+        gpu.process_inverse_batch(&mut output);
+        for value in &mut output {
+            *value /= 256.0;
+        }
+
+        assert_close(&output, &test_signal(256 * 6));
+    }
+
+    #[tokio::test]
+    async fn it_runs_the_fft_fir_filter() {
+        let Some(gpu) = gpu_fft(256, 1).await
+        else {
+            return;
+        };
+
+        let coefficients = hann_window(100).collect::<Vec<f32>>();
+        let mut cpu_filter = FftFirFilter::with_fft_size(&coefficients, 256);
+        let mut gpu_filter =
+            FftFirFilter::with_fft_size(&coefficients, 256).with_fft(Box::new(gpu));
+
+        let input = test_signal(1000);
+        let expected = input
+            .iter()
+            .map(|sample| cpu_filter.scan(*sample))
+            .collect::<Vec<_>>();
+        let output = input
+            .iter()
+            .map(|sample| gpu_filter.scan(*sample))
+            .collect::<Vec<_>>();
+
+        assert_close(&output, &expected);
+    }
+}
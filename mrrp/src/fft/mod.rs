@@ -0,0 +1,197 @@
+//! FFT back-ends
+//!
+//! The CPU back-end ([`CpuFft`], using rustfft) is always available and is the
+//! default. With the `gpu` feature, [`gpu::GpuFft`] runs the FFT as a wgpu
+//! compute shader, which pays off for very large FFTs or many FFTs per
+//! batch, e.g. for wide spectra from fast SDRs or for
+//! [`FftFirFilter`][crate::filter::fft_fir::FftFirFilter]s with very many taps.
+//!
+//! [`plan_forward`] picks a back-end at runtime and falls back to the CPU if
+//! the GPU back-end is not available.
+//!
+//! Several analyzers can share CPU plans and worker threads through an
+//! [`FftPool`].
+
+#[cfg(feature = "gpu")]
+pub mod gpu;
+mod pool;
+
+use std::{
+    fmt::Debug,
+    str::FromStr,
+    sync::Arc,
+};
+
+use num_complex::Complex;
+use rustfft::FftPlanner;
+
+pub use self::pool::{
+    FftPool,
+    PooledFft,
+};
+
+/// An FFT of a fixed size.
+///
+/// Neither direction is normalized, and the DC bin is at index 0.
+pub trait FftBackend: Debug + Send {
+    fn size(&self) -> usize;
+
+    /// Transforms `data` in place. `data.len()` must be a multiple of the FFT
+    /// size, and each chunk of that size is transformed separately.
+    fn process_batch(&mut self, data: &mut [Complex<f32>]);
+
+    /// Inverse transforms `data` in place, like
+    /// [`process_batch`][Self::process_batch].
+    ///
+    /// By default this conjugates `data` before and after the forward
+    /// transform.
+    fn process_inverse_batch(&mut self, data: &mut [Complex<f32>]) {
+        conjugate(data);
+        self.process_batch(data);
+        conjugate(data);
+    }
+
+    #[inline]
+    fn process(&mut self, data: &mut [Complex<f32>]) {
+        assert_eq!(data.len(), self.size());
+        self.process_batch(data);
+    }
+
+    #[inline]
+    fn process_inverse(&mut self, data: &mut [Complex<f32>]) {
+        assert_eq!(data.len(), self.size());
+        self.process_inverse_batch(data);
+    }
+}
+
+impl<T: FftBackend + ?Sized> FftBackend for Box<T> {
+    #[inline]
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+
+    #[inline]
+    fn process_batch(&mut self, data: &mut [Complex<f32>]) {
+        (**self).process_batch(data);
+    }
+
+    #[inline]
+    fn process_inverse_batch(&mut self, data: &mut [Complex<f32>]) {
+        (**self).process_inverse_batch(data);
+    }
+}
+
+fn conjugate(data: &mut [Complex<f32>]) {
+    for value in data {
+        value.im = -value.im;
+    }
+}
+
+/// FFT on the CPU, using rustfft.
+pub struct CpuFft {
+    fft: Arc<dyn rustfft::Fft<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl CpuFft {
+    pub fn new(size: usize) -> Self {
+        Self::with_planner(&mut FftPlanner::new(), size)
+    }
+
+    pub fn with_planner(planner: &mut FftPlanner<f32>, size: usize) -> Self {
+        let fft = planner.plan_fft_forward(size);
+        Self {
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            fft,
+        }
+    }
+}
+
+impl Debug for CpuFft {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CpuFft")
+            .field("size", &self.fft.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FftBackend for CpuFft {
+    #[inline]
+    fn size(&self) -> usize {
+        self.fft.len()
+    }
+
+    fn process_batch(&mut self, data: &mut [Complex<f32>]) {
+        assert_eq!(data.len() % self.size(), 0);
+        self.fft.process_with_scratch(data, &mut self.scratch);
+    }
+}
+
+/// Which back-end to use for FFTs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    #[default]
+    Cpu,
+    Gpu,
+}
+
+impl BackendKind {
+    /// Whether the back-end was compiled in. The GPU back-end might still fail
+    /// at runtime, e.g. if there's no suitable adapter.
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::Cpu => true,
+            Self::Gpu => cfg!(feature = "gpu"),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("No such FFT backend: {0}")]
+pub struct ParseBackendKindError(String);
+
+impl FromStr for BackendKind {
+    type Err = ParseBackendKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(Self::Cpu),
+            "gpu" => Ok(Self::Gpu),
+            _ => Err(ParseBackendKindError(s.to_owned())),
+        }
+    }
+}
+
+/// Plans an FFT with the preferred back-end.
+///
+/// `max_batch` is the number of FFTs that are usually passed to
+/// [`FftBackend::process_batch`] at once. The GPU back-end allocates its
+/// buffers for that many, and splits larger batches.
+///
+/// If the GPU back-end is requested, but it's not compiled in, there's no
+/// suitable adapter, or it doesn't support the FFT size, this falls back to
+/// the CPU back-end.
+pub async fn plan_forward(
+    size: usize,
+    #[cfg_attr(not(feature = "gpu"), allow(unused_variables))] max_batch: usize,
+    preferred: BackendKind,
+) -> Box<dyn FftBackend> {
+    match preferred {
+        BackendKind::Cpu => {}
+        #[cfg(feature = "gpu")]
+        BackendKind::Gpu => {
+            match gpu::GpuFft::new(size, max_batch).await {
+                Ok(fft) => return Box::new(fft),
+                Err(error) => {
+                    tracing::warn!(%error, "GPU FFT not available. Falling back to CPU.");
+                }
+            }
+        }
+        #[cfg(not(feature = "gpu"))]
+        BackendKind::Gpu => {
+            tracing::warn!("Compiled without GPU support. Falling back to CPU FFT.");
+        }
+    }
+
+    Box::new(CpuFft::new(size))
+}
@@ -0,0 +1,250 @@
+//! FFT plans shared between several spectrum analyzers.
+//!
+//! An [`FftPool`] caches plans by size, so FFTs of the same size are planned
+//! only once, no matter how many analyzers use them. Optionally it runs a few
+//! worker threads, onto which [`PooledFft::process_in_pool`] moves transforms,
+//! e.g. to keep them off the thread that drives the UI.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{
+        Arc,
+        mpsc,
+    },
+    thread,
+};
+
+use num_complex::Complex;
+use parking_lot::Mutex;
+use rustfft::FftPlanner;
+use tokio::sync::oneshot;
+
+use crate::fft::FftBackend;
+
+/// Shared FFT plans with optional worker threads.
+///
+/// Clones share the plans and the workers. The workers exit when the last
+/// clone is dropped.
+#[derive(Clone)]
+pub struct FftPool {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    // the planner itself isn't `Send`, so we keep the plans
+    plans: Mutex<HashMap<usize, Arc<dyn rustfft::Fft<f32>>>>,
+    jobs: Option<mpsc::Sender<Job>>,
+    num_threads: usize,
+}
+
+struct Job {
+    fft: Arc<dyn rustfft::Fft<f32>>,
+    data: Vec<Complex<f32>>,
+    reply: oneshot::Sender<Vec<Complex<f32>>>,
+}
+
+impl FftPool {
+    /// Creates a pool without worker threads. Transforms run on the thread
+    /// that calls them.
+    pub fn new() -> Self {
+        Self::with_threads(0)
+    }
+
+    /// Creates a pool with `num_threads` worker threads.
+    pub fn with_threads(num_threads: usize) -> Self {
+        let jobs = (num_threads > 0).then(|| {
+            let (sender, receiver) = mpsc::channel();
+            let receiver = Arc::new(Mutex::new(receiver));
+
+            for i in 0..num_threads {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("fft-{i}"))
+                    .spawn(move || worker(receiver))
+                    .expect("failed to spawn FFT worker thread");
+            }
+
+            sender
+        });
+
+        Self {
+            shared: Arc::new(Shared {
+                plans: Mutex::new(HashMap::new()),
+                jobs,
+                num_threads,
+            }),
+        }
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.shared.num_threads
+    }
+
+    /// Plans a forward FFT of `size`, or reuses the plan if one of that size
+    /// exists already.
+    pub fn plan(&self, size: usize) -> PooledFft {
+        let fft = self
+            .shared
+            .plans
+            .lock()
+            .entry(size)
+            .or_insert_with(|| FftPlanner::new().plan_fft_forward(size))
+            .clone();
+        PooledFft {
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            fft,
+            pool: self.clone(),
+        }
+    }
+}
+
+impl Default for FftPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for FftPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FftPool")
+            .field("num_threads", &self.shared.num_threads)
+            .finish_non_exhaustive()
+    }
+}
+
+fn worker(receiver: Arc<Mutex<mpsc::Receiver<Job>>>) {
+    let mut scratch = vec![];
+
+    loop {
+        // the lock is only held while waiting, so the next idle worker picks up
+        // the next job
+        let Ok(mut job) = receiver.lock().recv()
+        else {
+            // the pool was dropped
+            break;
+        };
+
+        scratch.resize(job.fft.get_inplace_scratch_len(), Complex::default());
+        job.fft.process_with_scratch(&mut job.data, &mut scratch);
+
+        // the caller might have given up waiting
+        let _ = job.reply.send(job.data);
+    }
+}
+
+/// Forward FFT planned by an [`FftPool`].
+///
+/// As an [`FftBackend`] this transforms on the calling thread.
+pub struct PooledFft {
+    fft: Arc<dyn rustfft::Fft<f32>>,
+    scratch: Vec<Complex<f32>>,
+    pool: FftPool,
+}
+
+impl PooledFft {
+    /// Transforms `data` on one of the pool's workers, and returns it when
+    /// it's done.
+    ///
+    /// Like [`FftBackend::process_batch`], `data.len()` must be a multiple of
+    /// the FFT size. If the pool has no workers, this transforms on the calling
+    /// thread.
+    ///
+    /// Handing a job to a worker allocates a channel for the reply. Without
+    /// workers this doesn't allocate.
+    pub async fn process_in_pool(&mut self, mut data: Vec<Complex<f32>>) -> Vec<Complex<f32>> {
+        assert_eq!(data.len() % self.size(), 0);
+
+        if let Some(jobs) = &self.pool.shared.jobs {
+            let (reply, result) = oneshot::channel();
+            let job = Job {
+                fft: self.fft.clone(),
+                data,
+                reply,
+            };
+
+            match jobs.send(job) {
+                Ok(()) => {
+                    return result.await.expect("FFT worker thread panicked");
+                }
+                Err(mpsc::SendError(job)) => {
+                    // all workers are gone, which only happens if they panicked
+                    tracing::warn!("FFT workers not running. Transforming on this thread.");
+                    data = job.data;
+                }
+            }
+        }
+
+        self.process_batch(&mut data);
+        data
+    }
+}
+
+impl Debug for PooledFft {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PooledFft")
+            .field("size", &self.fft.len())
+            .field("pool", &self.pool)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FftBackend for PooledFft {
+    #[inline]
+    fn size(&self) -> usize {
+        self.fft.len()
+    }
+
+    fn process_batch(&mut self, data: &mut [Complex<f32>]) {
+        assert_eq!(data.len() % self.size(), 0);
+        self.fft.process_with_scratch(data, &mut self.scratch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use num_complex::Complex;
+
+    use super::FftPool;
+    use crate::fft::{
+        CpuFft,
+        FftBackend,
+    };
+
+    fn test_signal(len: usize) -> Vec<Complex<f32>> {
+        (0..len)
+            .map(|i| Complex::new((i as f32 * 0.3).sin(), (i as f32 * 0.7).cos()))
+            .collect()
+    }
+
+    #[test]
+    fn it_reuses_plans() {
+        let pool = FftPool::new();
+        let a = pool.plan(256);
+        let b = pool.clone().plan(256);
+        let c = pool.plan(512);
+
+        assert!(Arc::ptr_eq(&a.fft, &b.fft));
+        assert!(!Arc::ptr_eq(&a.fft, &c.fft));
+    }
+
+    #[tokio::test]
+    async fn it_transforms_like_the_cpu_backend() {
+        let mut expected = test_signal(4 * 64);
+        CpuFft::new(64).process_batch(&mut expected);
+
+        for num_threads in [0, 2] {
+            let pool = FftPool::with_threads(num_threads);
+            let mut fft = pool.plan(64);
+
+            let mut inline = test_signal(4 * 64);
+            fft.process_batch(&mut inline);
+            assert_eq!(inline, expected);
+
+            let pooled = fft.process_in_pool(test_signal(4 * 64)).await;
+            assert_eq!(pooled, expected);
+        }
+    }
+}
@@ -17,7 +17,10 @@ use num_traits::{
     Zero,
 };
 
-use crate::io::combinators::Scanner;
+use crate::io::combinators::{
+    GroupDelay,
+    Scanner,
+};
 
 impl<C, T> Scanner<T> for DirectForm1<C, T>
 where
@@ -45,6 +48,64 @@ where
     }
 }
 
+/// Second-order sections in series, e.g. from an
+/// [`IirDesign`][crate::filter::design::iir::IirDesign].
+#[derive(Clone, Debug)]
+pub struct BiquadCascade<T> {
+    sections: Vec<DirectForm2Transposed<f32, T>>,
+}
+
+impl<T> BiquadCascade<T>
+where
+    T: Copy + Add<T, Output = T> + Sub<T, Output = T> + ConstZero,
+    f32: Mul<T, Output = T>,
+{
+    pub fn new(sections: impl IntoIterator<Item = Coefficients<f32>>) -> Self {
+        Self {
+            sections: sections
+                .into_iter()
+                .map(DirectForm2Transposed::new)
+                .collect(),
+        }
+    }
+}
+
+impl<T> Scanner<T> for BiquadCascade<T>
+where
+    T: Copy + Add<T, Output = T> + Sub<T, Output = T> + Zero,
+    f32: Mul<T, Output = T>,
+{
+    type Output = T;
+
+    #[inline]
+    fn scan(&mut self, sample: T) -> Self::Output {
+        self.sections
+            .iter_mut()
+            .fold(sample, |sample, section| section.run(sample))
+    }
+}
+
+impl<T> GroupDelay for BiquadCascade<T> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+impl<C, T> GroupDelay for DirectForm1<C, T> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+impl<C, T> GroupDelay for DirectForm2Transposed<C, T> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
 pub fn lowpass<T>(sample_rate: f32, cutoff_frequency: f32) -> DirectForm2Transposed<f32, T>
 where
     T: Copy + Add<T, Output = T> + Sub<T, Output = T> + ConstZero,
@@ -60,3 +121,19 @@ where
         .unwrap(),
     )
 }
+
+pub fn highpass<T>(sample_rate: f32, cutoff_frequency: f32) -> DirectForm2Transposed<f32, T>
+where
+    T: Copy + Add<T, Output = T> + Sub<T, Output = T> + ConstZero,
+    f32: Mul<T, Output = T>,
+{
+    DirectForm2Transposed::new(
+        Coefficients::from_params(
+            biquad::Type::HighPass,
+            sample_rate.hz(),
+            cutoff_frequency.hz(),
+            Q_BUTTERWORTH_F32,
+        )
+        .unwrap(),
+    )
+}
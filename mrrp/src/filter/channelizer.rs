@@ -0,0 +1,749 @@
+//! Polyphase filter bank channelizer
+//!
+//! A [`PolyphaseChannelizer`] splits a wideband signal into equally spaced
+//! channels. For `M` channels, channel `k` is the signal shifted down by
+//! `k / M` of the sample rate, low-pass filtered and decimated by `M`. The
+//! filter bank does this for all channels at once with one FFT per output
+//! sample, instead of one mixer and filter per channel.
+//!
+//! [`Channelize`] does this for a stream, with one [`ChannelOutput`] stream
+//! per channel.
+//!
+//! # References
+//!
+//! - F. J. Harris, "Multirate signal processing for communication systems",
+//!   chapter 6
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+        Waker,
+    },
+};
+
+use num_complex::Complex;
+use parking_lot::Mutex;
+
+use crate::{
+    buf::{
+        BufferPolicy,
+        DEFAULT_CHUNK_SIZE,
+        UninitSlice,
+    },
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    fft::{
+        CpuFft,
+        FftBackend,
+        FftPool,
+    },
+    filter::design::{
+        Lowpass,
+        Normalize,
+        windowed_sinc::{
+            Window,
+            windowed_sinc,
+        },
+    },
+    io::{
+        AsyncReadSamples,
+        GetCenterFrequency,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        StreamLength,
+        combinators::{
+            GroupDelay,
+            LagPolicy,
+        },
+    },
+};
+
+/// Stopband attenuation in dB of the prototype filter that
+/// [`PolyphaseChannelizer::new`] designs.
+const CHANNELIZER_ATTENUATION: f32 = 60.0;
+
+/// Share of the channel spacing that the prototype filter of
+/// [`PolyphaseChannelizer::new`] passes.
+const CHANNELIZER_PASSBAND: f32 = 0.8;
+
+/// Default number of samples a [`Channelize`] buffers per channel.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 4 * DEFAULT_CHUNK_SIZE;
+
+/// Critically sampled polyphase filter bank channelizer.
+///
+/// Splits complex samples into `num_channels` channels, each with the
+/// sample rate divided by `num_channels`. Channel `k` is centered at
+/// [`channel_frequency(k)`][Self::channel_frequency], so channel 0 is
+/// centered at DC.
+///
+/// Every `num_channels` input samples produce one sample for each channel.
+/// Since the channels are critically sampled, their edges alias into each
+/// other. The default prototype filter passes 80% of the channel spacing,
+/// so only the edges are affected.
+#[derive(Debug)]
+pub struct PolyphaseChannelizer {
+    /// Coefficients of each phase, one phase after the other.
+    bank: Arc<[f32]>,
+    taps_per_phase: usize,
+    num_channels: usize,
+    num_coefficients: usize,
+    /// Past input samples of each phase, newest first.
+    histories: Vec<VecDeque<Complex<f32>>>,
+    /// The phase that gets the next input sample.
+    phase: usize,
+    fft: Box<dyn FftBackend>,
+    fft_buffer: Vec<Complex<f32>>,
+    channels: Vec<Complex<f32>>,
+}
+
+impl PolyphaseChannelizer {
+    /// Creates a channelizer with a Kaiser windowed-sinc prototype filter
+    /// that attenuates the neighbouring channels by 60 dB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_channels` is 0.
+    pub fn new(num_channels: usize) -> Self {
+        assert!(num_channels > 0, "number of channels must not be 0");
+
+        let spacing = 1.0 / num_channels as f32;
+        let transition_bandwidth = (1.0 - CHANNELIZER_PASSBAND) * spacing;
+        let lowpass =
+            Lowpass::new(0.5 * spacing, transition_bandwidth, 0.001, 0.001).assert_normalized();
+        let window = Window::kaiser(CHANNELIZER_ATTENUATION);
+        let length = window.estimate_filter_length(transition_bandwidth);
+        let prototype =
+            windowed_sinc(lowpass, length, window).expect("failed to design channelizer filter");
+
+        Self::with_prototype(num_channels, &prototype)
+    }
+
+    /// Creates a channelizer with a custom prototype filter.
+    ///
+    /// The prototype is the low-pass filter of a single channel at the input
+    /// sample rate. It should have a DC gain of 1 and cut off at half the
+    /// channel spacing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_channels` is 0, or the prototype has no coefficients.
+    pub fn with_prototype(num_channels: usize, prototype: &[f32]) -> Self {
+        assert!(num_channels > 0, "number of channels must not be 0");
+        assert!(
+            !prototype.is_empty(),
+            "prototype filter has no coefficients"
+        );
+
+        let num_coefficients = prototype.len();
+        let taps_per_phase = num_coefficients.div_ceil(num_channels);
+        let mut bank = vec![0.0; taps_per_phase * num_channels];
+        for (i, coefficient) in prototype.iter().enumerate() {
+            bank[(i % num_channels) * taps_per_phase + i / num_channels] = *coefficient;
+        }
+
+        Self {
+            bank: bank.into(),
+            taps_per_phase,
+            num_channels,
+            num_coefficients,
+            histories: (0..num_channels)
+                .map(|_| VecDeque::from(vec![Complex::default(); taps_per_phase]))
+                .collect(),
+            phase: 0,
+            fft: Box::new(CpuFft::new(num_channels)),
+            fft_buffer: vec![Complex::default(); num_channels],
+            channels: vec![Complex::default(); num_channels],
+        }
+    }
+
+    /// Uses an FFT plan from `pool`.
+    pub fn with_fft_pool(mut self, pool: &FftPool) -> Self {
+        self.fft = Box::new(pool.plan(self.num_channels));
+        self
+    }
+
+    #[inline]
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    #[inline]
+    pub fn taps_per_phase(&self) -> usize {
+        self.taps_per_phase
+    }
+
+    /// Center frequency of a channel, relative to the input sample rate.
+    ///
+    /// Channels in the upper half are at negative frequencies, e.g. channel
+    /// `num_channels - 1` is at `-1 / num_channels`. With an even number of
+    /// channels, the channel at the Nyquist frequency is at -0.5.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is not less than the number of channels.
+    #[inline]
+    pub fn channel_frequency(&self, channel: usize) -> f32 {
+        channel_frequency(channel, self.num_channels)
+    }
+
+    /// Number of input samples until the next output.
+    #[inline]
+    pub fn samples_until_output(&self) -> usize {
+        self.phase + 1
+    }
+
+    /// Scans an input sample. Every `num_channels` samples this passes one
+    /// sample of each channel to `output`, indexed by channel.
+    pub fn push(&mut self, sample: Complex<f32>, mut output: impl FnMut(&[Complex<f32>])) {
+        let history = &mut self.histories[self.phase];
+        history.pop_back();
+        history.push_front(sample);
+
+        if self.phase > 0 {
+            self.phase -= 1;
+            return;
+        }
+        self.phase = self.num_channels - 1;
+
+        for ((history, coefficients), value) in self
+            .histories
+            .iter()
+            .zip(self.bank.chunks_exact(self.taps_per_phase))
+            .zip(&mut self.fft_buffer)
+        {
+            *value = history
+                .iter()
+                .zip(coefficients)
+                .fold(Complex::default(), |sum, (x, h)| sum + *x * *h);
+        }
+        self.fft.process(&mut self.fft_buffer);
+
+        // channel `k` rotates phase `p` by `exp(2πi k p / M)`, which the forward
+        // FFT computes in bin `-k`.
+        for (channel, value) in self.channels.iter_mut().enumerate() {
+            *value = self.fft_buffer[(self.num_channels - channel) % self.num_channels];
+        }
+        output(&self.channels);
+    }
+
+    /// Clears the filter state.
+    pub fn reset(&mut self) {
+        for history in &mut self.histories {
+            history.iter_mut().for_each(|x| *x = Complex::default());
+        }
+        self.phase = 0;
+    }
+}
+
+impl GroupDelay for PolyphaseChannelizer {
+    /// Group delay in input samples.
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.5 * (self.num_coefficients - 1) as f64
+    }
+}
+
+fn channel_frequency(channel: usize, num_channels: usize) -> f32 {
+    assert!(channel < num_channels, "channel {channel} out of range");
+    if 2 * channel < num_channels {
+        channel as f32 / num_channels as f32
+    }
+    else {
+        -((num_channels - channel) as f32) / num_channels as f32
+    }
+}
+
+/// Splits an IQ stream into channels with a [`PolyphaseChannelizer`].
+///
+/// Like a [`Broadcast`][crate::io::combinators::Broadcast], the input is
+/// read by whichever channel needs more samples, and the channel samples are
+/// buffered until they're read. How a full buffer is handled is configured
+/// with a [`LagPolicy`].
+#[derive(Debug)]
+pub struct Channelize<R> {
+    input: R,
+    channelizer: PolyphaseChannelizer,
+    capacity: usize,
+    lag_policy: LagPolicy,
+}
+
+impl<R> Channelize<R> {
+    /// # Panics
+    ///
+    /// Panics if `num_channels` is 0.
+    pub fn new(input: R, num_channels: usize) -> Self {
+        Self::with_channelizer(input, PolyphaseChannelizer::new(num_channels))
+    }
+
+    pub fn with_channelizer(input: R, channelizer: PolyphaseChannelizer) -> Self {
+        Self {
+            input,
+            channelizer,
+            capacity: DEFAULT_CHANNEL_CAPACITY,
+            lag_policy: LagPolicy::default(),
+        }
+    }
+
+    /// Sets how many samples are buffered per channel.
+    ///
+    /// The capacity is capped by the [`BufferPolicy`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be greater than 0");
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn with_lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// Creates the channel streams, indexed by channel.
+    ///
+    /// A channel that isn't needed can be dropped, and its samples are
+    /// discarded.
+    pub fn channels(self) -> Vec<ChannelOutput<R>> {
+        let num_channels = self.channelizer.num_channels();
+        let capacity = self
+            .capacity
+            .min(BufferPolicy::global().max_samples::<Complex<f32>>());
+        let state = Arc::new(Mutex::new(State {
+            input: Box::pin(self.input),
+            input_state: InputState::Active,
+            channelizer: self.channelizer,
+            chunk: Vec::new(),
+            capacity,
+            lag_policy: self.lag_policy,
+            channels: (0..num_channels)
+                .map(|_| Some(ChannelState::new(capacity)))
+                .collect(),
+        }));
+
+        (0..num_channels)
+            .map(|channel| {
+                ChannelOutput {
+                    state: state.clone(),
+                    channel,
+                    frequency: channel_frequency(channel, num_channels),
+                    num_channels,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InputState {
+    Active,
+    Finished,
+    Failed,
+}
+
+#[derive(Debug)]
+struct ChannelState {
+    samples: VecDeque<Complex<f32>>,
+    num_dropped: u64,
+    waker: Option<Waker>,
+}
+
+impl ChannelState {
+    fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            num_dropped: 0,
+            waker: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State<R> {
+    input: Pin<Box<R>>,
+    input_state: InputState,
+    channelizer: PolyphaseChannelizer,
+    /// Samples are read from the input into this, before they're
+    /// channelized.
+    chunk: Vec<Complex<f32>>,
+    capacity: usize,
+    lag_policy: LagPolicy,
+    channels: Vec<Option<ChannelState>>,
+}
+
+impl<R> State<R> {
+    /// Number of samples that fit into every channel's buffer.
+    fn free(&self) -> usize {
+        self.channels
+            .iter()
+            .flatten()
+            .map(|channel| self.capacity - channel.samples.len())
+            .min()
+            .unwrap_or(self.capacity)
+    }
+
+    fn wake_all(&mut self) {
+        for channel in self.channels.iter_mut().flatten() {
+            if let Some(waker) = channel.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Channelizes the samples in `chunk` into the channel buffers.
+    fn channelize_chunk(&mut self) {
+        let capacity = self.capacity;
+        let channels = &mut self.channels;
+        for sample in self.chunk.drain(..) {
+            self.channelizer.push(sample, |outputs| {
+                for (channel, output) in channels.iter_mut().zip(outputs) {
+                    if let Some(channel) = channel {
+                        if channel.samples.len() == capacity {
+                            channel.samples.pop_front();
+                            channel.num_dropped += 1;
+                        }
+                        channel.samples.push_back(*output);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Reads from the input of a [`Channelize`] and channelizes the samples.
+fn poll_fill<R>(
+    state: &mut State<R>,
+    cx: &mut Context<'_>,
+    num_samples: usize,
+) -> Poll<Result<usize, R::Error>>
+where
+    R: AsyncReadSamples<Complex<f32>>,
+{
+    state.chunk.clear();
+    state.chunk.reserve_exact(num_samples);
+    let spare = &mut state.chunk.spare_capacity_mut()[..num_samples];
+    let mut read_buf = ReadBuf::uninit(UninitSlice::slice_mut_from_uninit(spare));
+
+    let result = state.input.as_mut().poll_read_samples(cx, &mut read_buf);
+    let num_read = read_buf.filled().len();
+    unsafe {
+        read_buf.drop_unfilled_initialized();
+        state.chunk.set_len(num_read);
+    }
+
+    match result {
+        Poll::Pending => Poll::Pending,
+        Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+        Poll::Ready(Ok(())) => {
+            state.channelize_chunk();
+            Poll::Ready(Ok(num_read))
+        }
+    }
+}
+
+/// One channel of a [`Channelize`].
+///
+/// The sample rate is the input sample rate divided by the number of
+/// channels, and the center frequency is shifted to the center of the
+/// channel.
+#[derive(Debug)]
+pub struct ChannelOutput<R> {
+    state: Arc<Mutex<State<R>>>,
+    channel: usize,
+    frequency: f32,
+    num_channels: usize,
+}
+
+impl<R> ChannelOutput<R> {
+    /// Index of this channel.
+    #[inline]
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Center frequency of this channel relative to the input sample rate.
+    /// See [`PolyphaseChannelizer::channel_frequency`].
+    #[inline]
+    pub fn channel_frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    /// Number of samples this channel skipped, because they were dropped
+    /// with [`LagPolicy::DropOldest`] before they were read.
+    pub fn num_dropped(&self) -> u64 {
+        let state = self.state.lock();
+        state.channels[self.channel].as_ref().unwrap().num_dropped
+    }
+
+    /// Number of samples that can be read without reading from the input.
+    pub fn num_buffered(&self) -> usize {
+        let state = self.state.lock();
+        state.channels[self.channel].as_ref().unwrap().samples.len()
+    }
+}
+
+impl<R> ChannelOutput<R>
+where
+    R: GetSampleRate,
+{
+    /// Center frequency of this channel relative to the input's center
+    /// frequency, in Hz.
+    pub fn frequency_offset(&self) -> f32 {
+        self.frequency * self.state.lock().input.sample_rate()
+    }
+}
+
+impl<R> Drop for ChannelOutput<R> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        state.channels[self.channel] = None;
+        // this channel might have been the one the input would wake, or the
+        // one the others were waiting for
+        state.wake_all();
+    }
+}
+
+impl<R> AsyncReadSamples<Complex<f32>> for ChannelOutput<R>
+where
+    R: AsyncReadSamples<Complex<f32>>,
+{
+    type Error = ChannelizeError<R::Error>;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<Complex<f32>>,
+    ) -> Poll<Result<(), Self::Error>> {
+        if buffer.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let mut state = self.state.lock();
+        let state = &mut *state;
+
+        loop {
+            let capacity = state.capacity;
+            let channel = state.channels[self.channel].as_mut().unwrap();
+            if !channel.samples.is_empty() {
+                let was_full = channel.samples.len() == capacity;
+                let num_samples = buffer.remaining().min(channel.samples.len());
+                let (front, back) = channel.samples.as_slices();
+                let num_front = num_samples.min(front.len());
+                buffer.put_slice(&front[..num_front]);
+                buffer.put_slice(&back[..num_samples - num_front]);
+                channel.samples.drain(..num_samples);
+
+                if was_full {
+                    // other channels might be waiting for space
+                    state.wake_all();
+                }
+                return Poll::Ready(Ok(()));
+            }
+
+            match state.input_state {
+                InputState::Active => {}
+                InputState::Finished => return Poll::Ready(Ok(())),
+                InputState::Failed => return Poll::Ready(Err(ChannelizeError::InputFailed)),
+            }
+
+            // this channel has read everything, so we need to read from the input
+            let mut num_outputs = buffer.remaining().min(capacity);
+            if state.lag_policy == LagPolicy::Block {
+                let free = state.free();
+                if free == 0 {
+                    state.channels[self.channel].as_mut().unwrap().waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                num_outputs = num_outputs.min(free);
+            }
+            let num_samples =
+                (num_outputs - 1) * self.num_channels + state.channelizer.samples_until_output();
+
+            match poll_fill(state, cx, num_samples) {
+                Poll::Pending => {
+                    state.channels[self.channel].as_mut().unwrap().waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                Poll::Ready(Err(error)) => {
+                    state.input_state = InputState::Failed;
+                    state.wake_all();
+                    return Poll::Ready(Err(ChannelizeError::Input(error)));
+                }
+                Poll::Ready(Ok(0)) => {
+                    state.input_state = InputState::Finished;
+                    state.wake_all();
+                    return Poll::Ready(Ok(()));
+                }
+                Poll::Ready(Ok(_)) => {
+                    // other channels might be waiting for these samples
+                    state.wake_all();
+                }
+            }
+        }
+    }
+}
+
+impl<R> GetSampleRate for ChannelOutput<R>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.state.lock().input.sample_rate() / self.num_channels as f32
+    }
+}
+
+impl<R> StreamLength for ChannelOutput<R>
+where
+    R: StreamLength,
+{
+    fn remaining(&self) -> Remaining {
+        let state = self.state.lock();
+        let buffered = state.channels[self.channel].as_ref().unwrap().samples.len();
+        match state.input_state {
+            InputState::Active => {
+                let samples_until_output = state.channelizer.samples_until_output();
+                state.input.remaining().map(|num_samples| {
+                    let num_outputs = num_samples
+                        .checked_sub(samples_until_output)
+                        .map_or(0, |num_samples| num_samples / self.num_channels + 1);
+                    buffered + num_outputs
+                })
+            }
+            InputState::Finished | InputState::Failed => {
+                Remaining::Finite {
+                    num_samples: buffered,
+                }
+            }
+        }
+    }
+}
+
+impl<R> GetCenterFrequency for ChannelOutput<R>
+where
+    R: GetSampleRate + GetCenterFrequency,
+{
+    #[inline]
+    fn center_frequency(&self) -> f32 {
+        let state = self.state.lock();
+        state.input.center_frequency() + self.frequency * state.input.sample_rate()
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum ChannelizeError<E> {
+    #[error("channelizer input error")]
+    Input(E),
+
+    /// The input failed while another channel was reading from it. That
+    /// channel got the original error.
+    #[error("channelizer input failed")]
+    InputFailed,
+}
+
+impl<E: ClassifyError> ClassifyError for ChannelizeError<E> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            ChannelizeError::Input(error) => error.error_kind(),
+            ChannelizeError::InputFailed => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use super::{
+        Channelize,
+        PolyphaseChannelizer,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+            GetSampleRate,
+        },
+        source::{
+            ComplexSinusoid,
+            SignalGenerator,
+        },
+    };
+
+    #[test]
+    fn it_places_the_channels() {
+        let channelizer = PolyphaseChannelizer::new(8);
+        assert_eq!(channelizer.channel_frequency(0), 0.0);
+        assert_eq!(channelizer.channel_frequency(1), 0.125);
+        assert_eq!(channelizer.channel_frequency(3), 0.375);
+        assert_eq!(channelizer.channel_frequency(4), -0.5);
+        assert_eq!(channelizer.channel_frequency(7), -0.125);
+
+        let channels = Channelize::new(
+            Cursor::<Vec<Complex<f32>>, _>::new(vec![]).with_sample_rate(8000.0),
+            8,
+        )
+        .channels();
+        assert_eq!(channels.len(), 8);
+        assert_eq!(channels[5].channel(), 5);
+        assert_eq!(channels[5].sample_rate(), 1000.0);
+        assert_eq!(channels[5].frequency_offset(), -3000.0);
+    }
+
+    #[test]
+    fn it_separates_a_tone_into_its_channel() {
+        // 80 Hz above the center of channel 3
+        let mut tone = ComplexSinusoid::new(3080.0, 8000.0);
+        let input = std::iter::repeat_with(|| tone.next())
+            .take(8000)
+            .collect::<Vec<Complex<f32>>>();
+
+        let mut channels = Cursor::new(input).with_sample_rate(8000.0).channelize(8);
+
+        let mut outputs = vec![vec![]; 8];
+        let mut buffer = [Complex::default(); 64];
+        loop {
+            let mut num_read = 0;
+            for (channel, output) in channels.iter_mut().zip(&mut outputs) {
+                let n = channel
+                    .read_samples(&mut buffer)
+                    .now_or_never()
+                    .expect("pending")
+                    .unwrap();
+                output.extend_from_slice(&buffer[..n]);
+                num_read += n;
+            }
+            if num_read == 0 {
+                break;
+            }
+        }
+
+        // once the filter settled, the tone is in channel 3 and advances by
+        // its offset relative to the channel sample rate
+        let expected = TAU * 80.0 / 1000.0;
+        for (channel, output) in outputs.iter().enumerate() {
+            assert_eq!(output.len(), 1000);
+            for pair in output[50..].windows(2) {
+                if channel == 3 {
+                    assert!((pair[1].norm() - 1.0).abs() < 0.01);
+                    assert!(((pair[1] * pair[0].conj()).arg() - expected).abs() < 1e-3);
+                }
+                else {
+                    assert!(pair[1].norm_sqr() < 1e-5);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use num_complex::Complex;
+use rustfft::FftPlanner;
+
+use crate::filter::design::{
+    Band,
+    DesiredFrequencyResponse,
+    IsNormalized,
+};
+
+/// Frequency response of a FIR filter, sampled from 0 to the Nyquist
+/// frequency.
+///
+/// This is computed with FFTs, so it's cheap to evaluate at many points. The
+/// response at the sampled frequencies is exact, even if the filter is longer
+/// than the FFT.
+#[derive(Clone, Debug)]
+pub struct FrequencyResponse {
+    /// Normalized frequencies, evenly spaced from 0 to 0.5.
+    pub frequencies: Vec<f32>,
+
+    pub magnitude: Vec<f32>,
+
+    /// Phase in radians, wrapped to `-π..=π`.
+    pub phase: Vec<f32>,
+
+    /// Group delay in samples. This is NaN where the magnitude is 0.
+    pub group_delay: Vec<f32>,
+}
+
+impl FrequencyResponse {
+    /// Evaluates the response of a filter at `n_points` frequencies.
+    pub fn new(coefficients: &[f32], n_points: usize) -> Self {
+        assert!(n_points >= 2, "need at least 2 points: {n_points}");
+
+        // the bins of this FFT are at n_points frequencies from 0 to 0.5.
+        // folding the coefficients onto the FFT length samples the DTFT
+        // without any error.
+        let fft_size = 2 * (n_points - 1);
+        let mut response = vec![Complex::<f32>::default(); fft_size];
+        // the DTFT of n h(n) gives the derivative of the response, from which
+        // the group delay is computed.
+        let mut ramp_response = vec![Complex::<f32>::default(); fft_size];
+        for (n, h) in coefficients.iter().enumerate() {
+            response[n % fft_size] += *h;
+            ramp_response[n % fft_size] += n as f32 * *h;
+        }
+
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        fft.process(&mut response);
+        fft.process(&mut ramp_response);
+
+        let peak = response.iter().map(|h| h.norm_sqr()).fold(0.0, f32::max);
+
+        let mut frequency_response = Self {
+            frequencies: Vec::with_capacity(n_points),
+            magnitude: Vec::with_capacity(n_points),
+            phase: Vec::with_capacity(n_points),
+            group_delay: Vec::with_capacity(n_points),
+        };
+        for (k, (h, ramp)) in response
+            .iter()
+            .zip(&ramp_response)
+            .take(n_points)
+            .enumerate()
+        {
+            frequency_response
+                .frequencies
+                .push(k as f32 / fft_size as f32);
+            frequency_response.magnitude.push(h.norm());
+            frequency_response.phase.push(h.arg());
+            frequency_response
+                .group_delay
+                .push(if h.norm_sqr() > 1e-12 * peak {
+                    (ramp / h).re
+                }
+                else {
+                    f32::NAN
+                });
+        }
+
+        frequency_response
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.frequencies.is_empty()
+    }
+
+    /// The magnitude in dB.
+    pub fn magnitude_db(&self) -> Vec<f32> {
+        self.magnitude
+            .iter()
+            .map(|magnitude| 20.0 * magnitude.log10())
+            .collect()
+    }
+
+    /// Measures the ripple in every band that a filter specification is
+    /// defined on, in the same order.
+    ///
+    /// Only the sampled frequencies are considered, so there should be enough
+    /// points for the narrowest band.
+    pub fn ripple<F>(&self, filter_specification: &F) -> Vec<BandRipple>
+    where
+        F: DesiredFrequencyResponse + IsNormalized,
+    {
+        filter_specification
+            .defined_on()
+            .into_iter()
+            .map(|band| {
+                let mut ripple = BandRipple {
+                    band,
+                    min_magnitude: f32::INFINITY,
+                    max_magnitude: 0.0,
+                    worst_deviation: 0.0,
+                    passed: true,
+                };
+
+                for (frequency, magnitude) in self.frequencies.iter().zip(&self.magnitude) {
+                    if *frequency < band.start || *frequency > band.end {
+                        continue;
+                    }
+                    let Some(desired) = filter_specification.frequency_response_at(*frequency)
+                    else {
+                        continue;
+                    };
+
+                    ripple.min_magnitude = ripple.min_magnitude.min(*magnitude);
+                    ripple.max_magnitude = ripple.max_magnitude.max(*magnitude);
+                    let deviation = (magnitude - desired.amplitude).abs();
+                    ripple.worst_deviation = ripple.worst_deviation.max(deviation);
+                    if deviation > desired.tolerance {
+                        ripple.passed = false;
+                    }
+                }
+
+                ripple
+            })
+            .collect()
+    }
+}
+
+/// Result of [`FrequencyResponse::ripple`] for a single band.
+#[derive(Clone, Copy, Debug)]
+pub struct BandRipple {
+    pub band: Band,
+
+    pub min_magnitude: f32,
+
+    pub max_magnitude: f32,
+
+    /// Largest absolute difference between the magnitude and the desired
+    /// amplitude.
+    pub worst_deviation: f32,
+
+    /// Whether the magnitude is within the tolerance everywhere in this band.
+    pub passed: bool,
+}
+
+impl BandRipple {
+    /// Peak-to-peak ripple in dB, e.g. of a passband.
+    pub fn ripple_db(&self) -> f32 {
+        20.0 * (self.max_magnitude / self.min_magnitude).log10()
+    }
+
+    /// Attenuation in dB, e.g. of a stopband. This is the attenuation of the
+    /// largest magnitude in the band.
+    pub fn attenuation_db(&self) -> f32 {
+        -20.0 * self.max_magnitude.log10()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use approx::assert_abs_diff_eq;
+    use num_complex::Complex;
+
+    use super::FrequencyResponse;
+    use crate::filter::design::{
+        FilterDesign,
+        Lowpass,
+        Normalize,
+        pm_remez::pm_remez,
+    };
+
+    /// The DTFT at a normalized frequency.
+    fn dtft(coefficients: &[f32], frequency: f32) -> Complex<f32> {
+        coefficients
+            .iter()
+            .enumerate()
+            .map(|(n, h)| *h * Complex::from_polar(1.0, -TAU * frequency * n as f32))
+            .sum()
+    }
+
+    #[test]
+    fn it_analyzes_a_moving_average() {
+        let design = vec![0.25f32; 4];
+        let response = design.frequency_response(9);
+
+        assert_eq!(response.len(), 9);
+        assert_abs_diff_eq!(response.frequencies[2], 0.125);
+        assert_abs_diff_eq!(response.magnitude[0], 1.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(response.magnitude[4], 0.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(response.magnitude[8], 0.0, epsilon = 1e-6);
+
+        // linear phase with a delay of 1.5 samples
+        assert_abs_diff_eq!(response.phase[1], -TAU * 0.0625 * 1.5, epsilon = 1e-5);
+        assert_abs_diff_eq!(response.group_delay[1], 1.5, epsilon = 1e-5);
+        assert!(response.group_delay[4].is_nan());
+    }
+
+    #[test]
+    fn it_matches_the_dtft() {
+        let design = pm_remez(Lowpass::new(0.2, 0.1, 0.01, 0.001).assert_normalized(), 41).unwrap();
+        let coefficients = design.coefficients();
+
+        // fewer points than coefficients fold them onto a shorter FFT
+        for n_points in [5, 257] {
+            let response = design.frequency_response(n_points);
+            for (frequency, magnitude) in response.frequencies.iter().zip(&response.magnitude) {
+                let expected = dtft(coefficients, *frequency);
+                assert_abs_diff_eq!(*magnitude, expected.norm(), epsilon = 1e-4);
+            }
+        }
+
+        // the passband is delayed by half the filter length
+        let response = design.frequency_response(257);
+        for group_delay in &response.group_delay[..50] {
+            assert_abs_diff_eq!(*group_delay, 20.0, epsilon = 1e-2);
+        }
+    }
+
+    #[test]
+    fn it_measures_the_ripple() {
+        let specification = Lowpass::new(0.2, 0.1, 0.01, 0.001).assert_normalized();
+        let design = pm_remez(specification, 41).unwrap();
+        let ripple = FrequencyResponse::new(design.coefficients(), 1025).ripple(&specification);
+
+        assert_eq!(ripple.len(), 2);
+        assert!(ripple.iter().all(|band| band.passed), "{ripple:#?}");
+        assert!(ripple[0].ripple_db() < 0.2, "{ripple:#?}");
+        assert!(ripple[1].attenuation_db() > 60.0, "{ripple:#?}");
+
+        let design = pm_remez(specification, 11).unwrap();
+        let ripple = FrequencyResponse::new(design.coefficients(), 1025).ripple(&specification);
+        assert!(!ripple[1].passed);
+        assert!(ripple[1].attenuation_db() < 60.0);
+    }
+}
@@ -269,6 +269,7 @@ mod tests {
     use crate::filter::design::{
         Lowpass,
         Normalize,
+        verify,
     };
 
     #[test]
@@ -282,11 +283,17 @@ mod tests {
         )
         .unwrap();
 
-        let h = filter_design.coefficients;
+        let h = &filter_design.coefficients;
         let n = (h.len() - 1) / 2;
         for (i, h) in h.iter().enumerate() {
             println!("{}: {h}", i as isize - n as isize);
         }
-        todo!();
+
+        let verification = verify(
+            &filter_design,
+            &Lowpass::new(0.25, 0.1, 0.05, 0.05).assert_normalized(),
+            256,
+        );
+        assert!(verification.passed(), "{verification:#?}");
     }
 }
@@ -0,0 +1,828 @@
+//! IIR filter design
+//!
+//! The analog prototype filters are designed as zeros, poles and gain with a
+//! cutoff frequency of 1 rad/s, transformed to the requested response and
+//! mapped onto the z-plane with the bilinear transform. The resulting filter is
+//! split into second-order sections, which are far less sensitive to rounding
+//! of the coefficients than one high-order filter.
+//!
+//! IIR filters need much fewer coefficients than FIR filters for the same
+//! selectivity, but they don't have linear phase.
+//!
+//! # References
+//!
+//! - <https://www.ece.rutgers.edu/~orfanidi/ece521/notes.pdf> (elliptic
+//!   filters)
+
+use std::{
+    f64::consts::PI,
+    ops::{
+        Add,
+        Mul,
+        Sub,
+    },
+};
+
+use num_complex::Complex;
+use num_traits::ConstZero;
+
+use crate::filter::{
+    biquad::{
+        BiquadCascade,
+        Coefficients,
+    },
+    design::{
+        Normalize,
+        Normalized,
+    },
+};
+
+/// Number of Landen transformations for the elliptic functions. The modulus
+/// converges quadratically, so this is plenty for `f64`.
+const NUM_LANDEN_STEPS: usize = 8;
+
+/// Imaginary parts below this are considered 0 when pairing roots.
+const REAL_TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Filter order must be at least 1")]
+    ZeroOrder,
+
+    #[error("Frequency {frequency} is not between 0 and the Nyquist frequency")]
+    InvalidFrequency { frequency: f32 },
+
+    #[error("Lower band edge {low} is not below the upper band edge {high}")]
+    InvalidBand { low: f32, high: f32 },
+
+    #[error(
+        "Invalid ripple: passband ripple {passband_ripple} dB, stopband attenuation {stopband_attenuation} dB"
+    )]
+    InvalidRipple {
+        passband_ripple: f32,
+        stopband_attenuation: f32,
+    },
+}
+
+/// Shape of the analog prototype filter.
+///
+/// Ripple and attenuation are in dB.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Prototype {
+    /// Maximally flat passband, with the slowest roll-off. The cutoff
+    /// frequency is where the response is 3 dB down.
+    Butterworth,
+
+    /// Equiripple passband. The cutoff frequency is the end of the passband,
+    /// where the response is `passband_ripple` down.
+    ChebyshevI { passband_ripple: f32 },
+
+    /// Flat passband and equiripple stopband. The cutoff frequency is the
+    /// start of the stopband, where the response is `stopband_attenuation`
+    /// down.
+    ChebyshevII { stopband_attenuation: f32 },
+
+    /// Equiripple passband and stopband, with the fastest roll-off. The cutoff
+    /// frequency is the end of the passband.
+    Elliptic {
+        passband_ripple: f32,
+        stopband_attenuation: f32,
+    },
+}
+
+/// Which frequencies the filter passes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Response {
+    Lowpass {
+        cutoff_frequency: f32,
+    },
+    Highpass {
+        cutoff_frequency: f32,
+    },
+    Bandpass {
+        low_frequency: f32,
+        high_frequency: f32,
+    },
+    Bandstop {
+        low_frequency: f32,
+        high_frequency: f32,
+    },
+}
+
+/// Specification of an IIR filter.
+///
+/// Bandpass and bandstop filters have twice the `order`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Iir {
+    pub prototype: Prototype,
+    pub order: usize,
+    pub response: Response,
+}
+
+impl Iir {
+    pub fn new(prototype: Prototype, order: usize, response: Response) -> Self {
+        Self {
+            prototype,
+            order,
+            response,
+        }
+    }
+}
+
+impl Normalize for Iir {
+    type Normalized = Normalized<Self>;
+
+    fn normalize(self, reference: f32) -> Self::Normalized {
+        let response = match self.response {
+            Response::Lowpass { cutoff_frequency } => {
+                Response::Lowpass {
+                    cutoff_frequency: cutoff_frequency / reference,
+                }
+            }
+            Response::Highpass { cutoff_frequency } => {
+                Response::Highpass {
+                    cutoff_frequency: cutoff_frequency / reference,
+                }
+            }
+            Response::Bandpass {
+                low_frequency,
+                high_frequency,
+            } => {
+                Response::Bandpass {
+                    low_frequency: low_frequency / reference,
+                    high_frequency: high_frequency / reference,
+                }
+            }
+            Response::Bandstop {
+                low_frequency,
+                high_frequency,
+            } => {
+                Response::Bandstop {
+                    low_frequency: low_frequency / reference,
+                    high_frequency: high_frequency / reference,
+                }
+            }
+        };
+        Normalized(Self { response, ..self })
+    }
+}
+
+/// A designed IIR filter as a cascade of second-order sections.
+#[derive(Clone, Debug)]
+pub struct IirDesign {
+    sections: Vec<Coefficients<f32>>,
+}
+
+impl IirDesign {
+    #[inline]
+    pub fn sections(&self) -> &[Coefficients<f32>] {
+        &self.sections
+    }
+
+    /// Creates a filter with this design, e.g. for
+    /// [`scan_with`][crate::io::AsyncReadSamplesExt::scan_with].
+    pub fn biquad_cascade<T>(&self) -> BiquadCascade<T>
+    where
+        T: Copy + Add<T, Output = T> + Sub<T, Output = T> + ConstZero,
+        f32: Mul<T, Output = T>,
+    {
+        BiquadCascade::new(self.sections.iter().copied())
+    }
+
+    /// Complex frequency response at `frequency` in cycles per sample.
+    pub fn frequency_response(&self, frequency: f32) -> Complex<f32> {
+        let z = Complex::from_polar(1.0, -2.0 * PI * f64::from(frequency));
+        let z2 = z * z;
+        let response = self
+            .sections
+            .iter()
+            .map(|section| {
+                let numerator =
+                    f64::from(section.b0) + f64::from(section.b1) * z + f64::from(section.b2) * z2;
+                let denominator = 1.0 + f64::from(section.a1) * z + f64::from(section.a2) * z2;
+                numerator / denominator
+            })
+            .product::<Complex<f64>>();
+        Complex::new(response.re as f32, response.im as f32)
+    }
+}
+
+/// Designs an IIR filter.
+///
+/// The frequencies must be normalized to cycles per sample, e.g. with
+/// [`normalize`][Normalize::normalize].
+pub fn iir(specification: Normalized<Iir>) -> Result<IirDesign, Error> {
+    let Normalized(specification) = specification;
+    if specification.order == 0 {
+        return Err(Error::ZeroOrder);
+    }
+
+    let prototype = ZerosPoles::prototype(specification.prototype, specification.order)?;
+    let digital = prototype.transform(specification.response)?.bilinear();
+    Ok(IirDesign {
+        sections: digital.sections(),
+    })
+}
+
+/// A filter as its zeros, poles and gain.
+#[derive(Clone, Debug)]
+struct ZerosPoles {
+    zeros: Vec<Complex<f64>>,
+    poles: Vec<Complex<f64>>,
+    gain: f64,
+}
+
+impl ZerosPoles {
+    fn prototype(prototype: Prototype, order: usize) -> Result<Self, Error> {
+        let n = order as f64;
+        // angles of the poles on the butterworth circle, starting next to the
+        // imaginary axis
+        let angles = (0..order).map(|i| PI * (2 * i + 1) as f64 / (2.0 * n));
+
+        let mut zeros = vec![];
+        let mut poles = vec![];
+        let mut gain = 1.0;
+
+        match prototype {
+            Prototype::Butterworth => {
+                poles.extend(angles.map(|angle| Complex::new(-angle.sin(), angle.cos())));
+            }
+            Prototype::ChebyshevI { passband_ripple } => {
+                check_ripple(passband_ripple, f32::INFINITY)?;
+                let epsilon = (10f64.powf(f64::from(passband_ripple) / 10.0) - 1.0).sqrt();
+                let mu = (1.0 / epsilon).asinh() / n;
+                poles.extend(
+                    angles.map(|angle| {
+                        Complex::new(-mu.sinh() * angle.sin(), mu.cosh() * angle.cos())
+                    }),
+                );
+                gain = real_product(poles.iter().map(|pole| -*pole));
+                if order.is_multiple_of(2) {
+                    // even orders start the passband at the bottom of the ripple
+                    gain /= (1.0 + epsilon * epsilon).sqrt();
+                }
+            }
+            Prototype::ChebyshevII {
+                stopband_attenuation,
+            } => {
+                if stopband_attenuation.is_nan() || stopband_attenuation <= 0.0 {
+                    return Err(Error::InvalidRipple {
+                        passband_ripple: 0.0,
+                        stopband_attenuation,
+                    });
+                }
+                let epsilon =
+                    1.0 / (10f64.powf(f64::from(stopband_attenuation) / 10.0) - 1.0).sqrt();
+                let mu = (1.0 / epsilon).asinh() / n;
+                for (i, angle) in angles.enumerate() {
+                    // odd orders have a zero at infinity
+                    if 2 * i + 1 != order {
+                        zeros.push(Complex::new(0.0, 1.0 / angle.cos()));
+                    }
+                    poles.push(
+                        Complex::new(-mu.sinh() * angle.sin(), mu.cosh() * angle.cos()).inv(),
+                    );
+                }
+                gain = real_product(poles.iter().map(|pole| -*pole))
+                    / real_product(zeros.iter().map(|zero| -*zero));
+            }
+            Prototype::Elliptic {
+                passband_ripple,
+                stopband_attenuation,
+            } => {
+                check_ripple(passband_ripple, stopband_attenuation)?;
+                let epsilon_pass = (10f64.powf(f64::from(passband_ripple) / 10.0) - 1.0).sqrt();
+                let epsilon_stop =
+                    (10f64.powf(f64::from(stopband_attenuation) / 10.0) - 1.0).sqrt();
+                let k1 = epsilon_pass / epsilon_stop;
+                let k = elliptic_degree(order, k1);
+                let v0 = (-Complex::<f64>::i() * asne(Complex::i() / epsilon_pass, k1) / n).re;
+
+                for i in 0..order / 2 {
+                    let u = (2 * i + 1) as f64 / n;
+                    let zero = Complex::<f64>::i() / (k * cde(u.into(), k));
+                    let pole = Complex::<f64>::i() * cde(Complex::new(u, -v0), k);
+                    zeros.extend([zero, zero.conj()]);
+                    poles.extend([pole, pole.conj()]);
+                }
+                if order % 2 == 1 {
+                    poles.push(
+                        (Complex::<f64>::i() * sne(Complex::new(0.0, v0), k))
+                            .re
+                            .into(),
+                    );
+                }
+
+                gain = real_product(poles.iter().map(|pole| -*pole))
+                    / real_product(zeros.iter().map(|zero| -*zero));
+                if order.is_multiple_of(2) {
+                    gain *= 10f64.powf(-f64::from(passband_ripple) / 20.0);
+                }
+            }
+        }
+
+        Ok(Self { zeros, poles, gain })
+    }
+
+    /// Transforms the lowpass prototype to the response, with the frequencies
+    /// prewarped for the bilinear transform.
+    fn transform(self, response: Response) -> Result<Self, Error> {
+        let excess = self.poles.len() - self.zeros.len();
+
+        match response {
+            Response::Lowpass { cutoff_frequency } => {
+                let omega = prewarp(cutoff_frequency)?;
+                Ok(Self {
+                    zeros: self.zeros.iter().map(|zero| *zero * omega).collect(),
+                    poles: self.poles.iter().map(|pole| *pole * omega).collect(),
+                    gain: self.gain * omega.powi(excess as i32),
+                })
+            }
+            Response::Highpass { cutoff_frequency } => {
+                let omega = prewarp(cutoff_frequency)?;
+                let mut zeros = self
+                    .zeros
+                    .iter()
+                    .map(|zero| omega / *zero)
+                    .collect::<Vec<_>>();
+                zeros.extend(std::iter::repeat_n(Complex::new(0.0, 0.0), excess));
+                Ok(Self {
+                    zeros,
+                    poles: self.poles.iter().map(|pole| omega / *pole).collect(),
+                    gain: self.gain * self.high_frequency_gain(),
+                })
+            }
+            Response::Bandpass {
+                low_frequency,
+                high_frequency,
+            } => {
+                let (center, bandwidth) = prewarp_band(low_frequency, high_frequency)?;
+                let transform = |root: &Complex<f64>| {
+                    let scaled = *root * bandwidth / 2.0;
+                    let offset = (scaled * scaled - center * center).sqrt();
+                    [scaled + offset, scaled - offset]
+                };
+                let mut zeros = self.zeros.iter().flat_map(transform).collect::<Vec<_>>();
+                zeros.extend(std::iter::repeat_n(Complex::new(0.0, 0.0), excess));
+                Ok(Self {
+                    zeros,
+                    poles: self.poles.iter().flat_map(transform).collect(),
+                    gain: self.gain * bandwidth.powi(excess as i32),
+                })
+            }
+            Response::Bandstop {
+                low_frequency,
+                high_frequency,
+            } => {
+                let (center, bandwidth) = prewarp_band(low_frequency, high_frequency)?;
+                let transform = |root: &Complex<f64>| {
+                    let scaled = bandwidth / 2.0 / *root;
+                    let offset = (scaled * scaled - center * center).sqrt();
+                    [scaled + offset, scaled - offset]
+                };
+                let mut zeros = self.zeros.iter().flat_map(transform).collect::<Vec<_>>();
+                for _ in 0..excess {
+                    zeros.extend([Complex::new(0.0, center), Complex::new(0.0, -center)]);
+                }
+                Ok(Self {
+                    zeros,
+                    poles: self.poles.iter().flat_map(transform).collect(),
+                    gain: self.gain * self.high_frequency_gain(),
+                })
+            }
+        }
+    }
+
+    /// Factor by which the gain changes when the zeros and poles are inverted,
+    /// so that the gain at high frequencies is what it was at DC.
+    fn high_frequency_gain(&self) -> f64 {
+        real_product(self.zeros.iter().map(|zero| -*zero))
+            / real_product(self.poles.iter().map(|pole| -*pole))
+    }
+
+    /// Maps the analog filter onto the z-plane. Zeros at infinity end up at
+    /// the Nyquist frequency.
+    fn bilinear(self) -> Self {
+        let excess = self.poles.len() - self.zeros.len();
+        let map = |root: &Complex<f64>| (1.0 + *root) / (1.0 - *root);
+
+        let gain = self.gain * real_product(self.zeros.iter().map(|zero| 1.0 - *zero))
+            / real_product(self.poles.iter().map(|pole| 1.0 - *pole));
+        let mut zeros = self.zeros.iter().map(map).collect::<Vec<_>>();
+        zeros.extend(std::iter::repeat_n(Complex::new(-1.0, 0.0), excess));
+
+        Self {
+            zeros,
+            poles: self.poles.iter().map(map).collect(),
+            gain,
+        }
+    }
+
+    /// Splits a digital filter into second-order sections.
+    ///
+    /// The poles closest to the unit circle are paired with the zeros closest
+    /// to them, and their sections come last. The gain goes into the first
+    /// section.
+    fn sections(self) -> Vec<Coefficients<f32>> {
+        let mut poles = Roots::group(&self.poles);
+        let mut zeros = Roots::group(&self.zeros);
+        debug_assert_eq!(poles.len(), zeros.len());
+
+        poles.sort_by(|a, b| a.magnitude().total_cmp(&b.magnitude()));
+
+        let mut sections = Vec::with_capacity(poles.len());
+        for section_poles in poles.into_iter().rev() {
+            let closest = zeros
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    section_poles
+                        .pairing_cost(a)
+                        .total_cmp(&section_poles.pairing_cost(b))
+                })
+                .map(|(i, _)| i);
+            let section_zeros = closest.map_or(Roots::None, |i| zeros.swap_remove(i));
+
+            let [_, a1, a2] = section_poles.polynomial();
+            let [b0, b1, b2] = section_zeros.polynomial();
+            sections.push([b0, b1, b2, a1, a2]);
+        }
+        sections.reverse();
+
+        if let Some(first) = sections.first_mut() {
+            for b in &mut first[..3] {
+                *b *= self.gain;
+            }
+        }
+
+        sections
+            .into_iter()
+            .map(|[b0, b1, b2, a1, a2]| {
+                Coefficients {
+                    a1: a1 as f32,
+                    a2: a2 as f32,
+                    b0: b0 as f32,
+                    b1: b1 as f32,
+                    b2: b2 as f32,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The roots of a section.
+#[derive(Clone, Copy, Debug)]
+enum Roots {
+    None,
+    Real(f64),
+    RealPair(f64, f64),
+    Conjugate(Complex<f64>),
+}
+
+impl Roots {
+    /// Groups roots into complex conjugate pairs and pairs of real roots.
+    fn group(roots: &[Complex<f64>]) -> Vec<Self> {
+        let mut groups = vec![];
+        let mut real = vec![];
+        for root in roots {
+            if root.im.abs() <= REAL_TOLERANCE * root.norm().max(1.0) {
+                real.push(root.re);
+            }
+            else if root.im > 0.0 {
+                // the conjugate is skipped
+                groups.push(Self::Conjugate(*root));
+            }
+        }
+
+        real.sort_by(f64::total_cmp);
+        groups.extend(real.chunks(2).map(|chunk| {
+            match *chunk {
+                [a, b] => Self::RealPair(a, b),
+                [a] => Self::Real(a),
+                _ => unreachable!(),
+            }
+        }));
+        groups
+    }
+
+    fn magnitude(&self) -> f64 {
+        match *self {
+            Self::None => 0.0,
+            Self::Real(a) => a.abs(),
+            Self::RealPair(a, b) => a.abs().max(b.abs()),
+            Self::Conjugate(root) => root.norm(),
+        }
+    }
+
+    fn order(&self) -> usize {
+        match self {
+            Self::None => 0,
+            Self::Real(_) => 1,
+            Self::RealPair(..) | Self::Conjugate(_) => 2,
+        }
+    }
+
+    /// How badly `other` fits to this group. First-order groups are paired
+    /// with each other, so that no section ends up with more zeros than poles.
+    fn pairing_cost(&self, other: &Self) -> f64 {
+        let mismatch = if self.order() == other.order() {
+            0.0
+        }
+        else {
+            1e3
+        };
+        mismatch + (self.representative() - other.representative()).norm()
+    }
+
+    fn representative(&self) -> Complex<f64> {
+        match *self {
+            Self::None => Complex::new(0.0, 0.0),
+            Self::Real(a) => a.into(),
+            Self::RealPair(a, b) => {
+                if a.abs() > b.abs() {
+                    a.into()
+                }
+                else {
+                    b.into()
+                }
+            }
+            Self::Conjugate(root) => root,
+        }
+    }
+
+    /// Coefficients of the monic polynomial with these roots, in powers of
+    /// `z^-1`.
+    fn polynomial(&self) -> [f64; 3] {
+        match *self {
+            Self::None => [1.0, 0.0, 0.0],
+            Self::Real(a) => [1.0, -a, 0.0],
+            Self::RealPair(a, b) => [1.0, -(a + b), a * b],
+            Self::Conjugate(root) => [1.0, -2.0 * root.re, root.norm_sqr()],
+        }
+    }
+}
+
+fn check_ripple(passband_ripple: f32, stopband_attenuation: f32) -> Result<(), Error> {
+    if passband_ripple > 0.0 && stopband_attenuation > passband_ripple {
+        Ok(())
+    }
+    else {
+        Err(Error::InvalidRipple {
+            passband_ripple,
+            stopband_attenuation,
+        })
+    }
+}
+
+/// Analog frequency in rad/s that the bilinear transform maps to `frequency`
+/// in cycles per sample.
+fn prewarp(frequency: f32) -> Result<f64, Error> {
+    if frequency > 0.0 && frequency < 0.5 {
+        Ok((PI * f64::from(frequency)).tan())
+    }
+    else {
+        Err(Error::InvalidFrequency { frequency })
+    }
+}
+
+/// Geometric center and bandwidth of a prewarped band.
+fn prewarp_band(low_frequency: f32, high_frequency: f32) -> Result<(f64, f64), Error> {
+    if low_frequency >= high_frequency {
+        return Err(Error::InvalidBand {
+            low: low_frequency,
+            high: high_frequency,
+        });
+    }
+    let low = prewarp(low_frequency)?;
+    let high = prewarp(high_frequency)?;
+    Ok(((low * high).sqrt(), high - low))
+}
+
+/// Product of values whose product is real, e.g. because they come in complex
+/// conjugate pairs.
+fn real_product(values: impl IntoIterator<Item = Complex<f64>>) -> f64 {
+    values.into_iter().product::<Complex<f64>>().re
+}
+
+/// Descending Landen sequence of the elliptic modulus `k`.
+fn landen(mut k: f64) -> [f64; NUM_LANDEN_STEPS] {
+    std::array::from_fn(|_| {
+        k = (k / (1.0 + (1.0 - k * k).sqrt())).powi(2);
+        k
+    })
+}
+
+/// Jacobi elliptic function `cd` with the argument in units of the quarter
+/// period.
+fn cde(u: Complex<f64>, k: f64) -> Complex<f64> {
+    ascending_landen((u * PI / 2.0).cos(), k)
+}
+
+/// Jacobi elliptic function `sn` with the argument in units of the quarter
+/// period.
+fn sne(u: Complex<f64>, k: f64) -> Complex<f64> {
+    ascending_landen((u * PI / 2.0).sin(), k)
+}
+
+fn ascending_landen(mut w: Complex<f64>, k: f64) -> Complex<f64> {
+    for v in landen(k).into_iter().rev() {
+        w = (1.0 + v) * w / (1.0 + v * w * w);
+    }
+    w
+}
+
+/// Inverse of [`cde`].
+fn acde(mut w: Complex<f64>, k: f64) -> Complex<f64> {
+    let mut previous = k;
+    for v in landen(k) {
+        w = w / (1.0 + (1.0 - w * w * previous * previous).sqrt()) * 2.0 / (1.0 + v);
+        previous = v;
+    }
+    w.acos() * 2.0 / PI
+}
+
+/// Inverse of [`sne`].
+fn asne(w: Complex<f64>, k: f64) -> Complex<f64> {
+    1.0 - acde(w, k)
+}
+
+/// Solves the degree equation for the elliptic modulus, which is the ratio of
+/// the passband edge to the stopband edge.
+fn elliptic_degree(order: usize, k1: f64) -> f64 {
+    let k1_complement = (1.0 - k1 * k1).sqrt();
+    let product = (0..order / 2)
+        .map(|i| sne(((2 * i + 1) as f64 / order as f64).into(), k1_complement).re)
+        .product::<f64>();
+    let k_complement = k1_complement.powi(order as i32) * product.powi(4);
+    (1.0 - k_complement * k_complement).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::{
+        Iir,
+        Prototype,
+        Response,
+        iir,
+    };
+    use crate::{
+        filter::design::Normalize,
+        io::combinators::Scanner,
+    };
+
+    const PROTOTYPES: [Prototype; 4] = [
+        Prototype::Butterworth,
+        Prototype::ChebyshevI {
+            passband_ripple: 1.0,
+        },
+        Prototype::ChebyshevII {
+            stopband_attenuation: 40.0,
+        },
+        Prototype::Elliptic {
+            passband_ripple: 1.0,
+            stopband_attenuation: 40.0,
+        },
+    ];
+
+    fn gain_db(specification: Iir, frequency: f32) -> f32 {
+        let design = iir(specification.assert_normalized()).unwrap();
+        20.0 * design.frequency_response(frequency).norm().log10()
+    }
+
+    #[test]
+    fn it_designs_lowpass_filters() {
+        for order in [1, 2, 5, 8] {
+            for prototype in PROTOTYPES {
+                let specification = Iir::new(
+                    prototype,
+                    order,
+                    Response::Lowpass {
+                        cutoff_frequency: 0.1,
+                    },
+                );
+                let design = iir(specification.assert_normalized()).unwrap();
+                assert_eq!(design.sections().len(), order.div_ceil(2));
+
+                let at_cutoff = gain_db(specification, 0.1);
+                match prototype {
+                    Prototype::Butterworth => {
+                        assert_abs_diff_eq!(at_cutoff, -3.01, epsilon = 0.01);
+                        assert_abs_diff_eq!(gain_db(specification, 0.0), 0.0, epsilon = 0.01);
+                    }
+                    Prototype::ChebyshevI { .. } | Prototype::Elliptic { .. } => {
+                        assert_abs_diff_eq!(at_cutoff, -1.0, epsilon = 0.01);
+                    }
+                    Prototype::ChebyshevII { .. } => {
+                        assert_abs_diff_eq!(at_cutoff, -40.0, epsilon = 0.01);
+                        assert_abs_diff_eq!(gain_db(specification, 0.0), 0.0, epsilon = 0.01);
+                    }
+                }
+
+                if order >= 5 {
+                    assert!(gain_db(specification, 0.3) < -40.0, "{prototype:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_designs_band_filters() {
+        for prototype in PROTOTYPES {
+            let bandpass = Iir::new(
+                prototype,
+                4,
+                Response::Bandpass {
+                    low_frequency: 0.1,
+                    high_frequency: 0.15,
+                },
+            );
+            assert!(gain_db(bandpass, 0.125) > -1.01, "{prototype:?}");
+            assert!(gain_db(bandpass, 0.3) < -40.0, "{prototype:?}");
+
+            let bandstop = Iir {
+                response: Response::Bandstop {
+                    low_frequency: 0.1,
+                    high_frequency: 0.15,
+                },
+                ..bandpass
+            };
+            assert!(gain_db(bandstop, 0.125) < -40.0, "{prototype:?}");
+            assert!(gain_db(bandstop, 0.3) > -1.01, "{prototype:?}");
+
+            let highpass = Iir {
+                response: Response::Highpass {
+                    cutoff_frequency: 0.2,
+                },
+                ..bandpass
+            };
+            assert!(gain_db(highpass, 0.5) > -1.01, "{prototype:?}");
+            assert!(gain_db(highpass, 0.05) < -40.0, "{prototype:?}");
+        }
+    }
+
+    #[test]
+    fn it_filters_a_tone() {
+        let design = iir(Iir::new(
+            Prototype::Elliptic {
+                passband_ripple: 0.5,
+                stopband_attenuation: 60.0,
+            },
+            6,
+            Response::Lowpass {
+                cutoff_frequency: 1000.0,
+            },
+        )
+        .normalize(48000.0))
+        .unwrap();
+        let mut filter = design.biquad_cascade::<f32>();
+
+        let rms = |filter: &mut dyn Scanner<f32, Output = f32>, frequency: f32| {
+            let mut sum = 0.0;
+            for i in 0..48000 {
+                let output =
+                    filter.scan((std::f32::consts::TAU * frequency * i as f32 / 48000.0).sin());
+                // skip the transient
+                if i >= 24000 {
+                    sum += output * output;
+                }
+            }
+            (sum / 24000.0).sqrt()
+        };
+
+        assert_abs_diff_eq!(rms(&mut filter, 500.0), 0.707, epsilon = 0.05);
+        assert!(rms(&mut filter, 5000.0) < 0.001);
+    }
+
+    #[test]
+    fn it_rejects_invalid_specifications() {
+        let lowpass = Response::Lowpass {
+            cutoff_frequency: 0.1,
+        };
+        assert!(iir(Iir::new(Prototype::Butterworth, 0, lowpass).assert_normalized()).is_err());
+        assert!(
+            iir(Iir::new(
+                Prototype::Butterworth,
+                2,
+                Response::Lowpass {
+                    cutoff_frequency: 0.6
+                }
+            )
+            .assert_normalized())
+            .is_err()
+        );
+        assert!(
+            iir(Iir::new(
+                Prototype::Elliptic {
+                    passband_ripple: 3.0,
+                    stopband_attenuation: 1.0
+                },
+                2,
+                lowpass
+            )
+            .assert_normalized())
+            .is_err()
+        );
+    }
+}
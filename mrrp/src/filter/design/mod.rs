@@ -1,11 +1,33 @@
+use std::sync::Arc;
+
 use crate::{
-    filter::fir::FirFilter,
+    filter::{
+        fft_fir::{
+            FftFilterSample,
+            FftFirFilter,
+        },
+        fir::FirFilter,
+    },
     io::GetSampleRate,
 };
 
+mod analysis;
 pub mod argmin;
 pub mod equiripple_fft;
+pub mod iir;
 pub mod pm_remez;
+mod verify;
+pub mod windowed_sinc;
+
+pub use analysis::{
+    BandRipple,
+    FrequencyResponse,
+};
+pub use verify::{
+    BandVerification,
+    Verification,
+    verify,
+};
 
 pub trait DesiredFrequencyResponse {
     fn defined_on(&self) -> impl IntoIterator<Item = Band>;
@@ -76,9 +98,78 @@ pub trait FilterDesign {
         self.coefficients().len()
     }
 
+    /// The coefficients in an [`Arc`], so that filters can share them.
+    ///
+    /// This copies the coefficients, unless the design already keeps them in
+    /// an [`Arc`], like [`SharedDesign`].
+    #[inline]
+    fn shared_coefficients(&self) -> Arc<[f32]> {
+        self.coefficients().into()
+    }
+
+    /// The frequency response at `n_points` frequencies from 0 to the Nyquist
+    /// frequency. See [`FrequencyResponse`].
+    #[inline]
+    fn frequency_response(&self, n_points: usize) -> FrequencyResponse {
+        FrequencyResponse::new(self.coefficients(), n_points)
+    }
+
     #[inline]
     fn fir_filter<S>(&self) -> FirFilter<S, f32> {
-        FirFilter::new(self.coefficients().to_owned())
+        FirFilter::shared(self.shared_coefficients())
+    }
+
+    /// A filter that convolves with FFTs, for designs with many
+    /// coefficients. See [`FftFirFilter`].
+    #[inline]
+    fn fft_fir_filter<S: FftFilterSample>(&self) -> FftFirFilter<S> {
+        FftFirFilter::new(self.coefficients())
+    }
+
+    /// Turns this design into one that all its filters share.
+    #[inline]
+    fn shared(&self) -> SharedDesign {
+        SharedDesign {
+            coefficients: self.shared_coefficients(),
+        }
+    }
+}
+
+/// A filter design whose coefficients are shared by all filters made from it.
+///
+/// Use this if many filters with the same design are needed, e.g. one per
+/// channel. Cloning it and creating filters with
+/// [`fir_filter`][FilterDesign::fir_filter] doesn't copy the coefficients.
+#[derive(Clone, Debug)]
+pub struct SharedDesign {
+    coefficients: Arc<[f32]>,
+}
+
+impl FilterDesign for SharedDesign {
+    #[inline]
+    fn coefficients(&self) -> &[f32] {
+        &self.coefficients
+    }
+
+    #[inline]
+    fn shared_coefficients(&self) -> Arc<[f32]> {
+        self.coefficients.clone()
+    }
+}
+
+impl From<Arc<[f32]>> for SharedDesign {
+    #[inline]
+    fn from(coefficients: Arc<[f32]>) -> Self {
+        Self { coefficients }
+    }
+}
+
+impl From<Vec<f32>> for SharedDesign {
+    #[inline]
+    fn from(coefficients: Vec<f32>) -> Self {
+        Self {
+            coefficients: coefficients.into(),
+        }
     }
 }
 
@@ -303,6 +394,219 @@ impl IsSymmetric for Lowpass {
     }
 }
 
+/// A high-pass filter.
+#[derive(Clone, Copy, Debug)]
+pub struct Highpass {
+    pub stopband_end: f32,
+    pub passband_start: f32,
+    pub passband_tolerance: f32,
+    pub stopband_tolerance: f32,
+}
+
+impl Highpass {
+    pub fn new(
+        cutoff_frequency: f32,
+        transition_bandwidth: f32,
+        passband_tolerance: f32,
+        stopband_tolerance: f32,
+    ) -> Self {
+        let half_transition_bandwidth = 0.5 * transition_bandwidth;
+        Self {
+            stopband_end: cutoff_frequency - half_transition_bandwidth,
+            passband_start: cutoff_frequency + half_transition_bandwidth,
+            passband_tolerance,
+            stopband_tolerance,
+        }
+    }
+
+    pub fn cutoff_frequency(&self) -> f32 {
+        0.5 * (self.stopband_end + self.passband_start)
+    }
+
+    pub fn transition_bandwidth(&self) -> f32 {
+        self.passband_start - self.stopband_end
+    }
+}
+
+impl Normalize for Highpass {
+    type Normalized = Normalized<Self>;
+
+    fn normalize(self, sample_rate: f32) -> Self::Normalized {
+        Normalized(Self {
+            stopband_end: self.stopband_end / sample_rate,
+            passband_start: self.passband_start / sample_rate,
+            passband_tolerance: self.passband_tolerance,
+            stopband_tolerance: self.stopband_tolerance,
+        })
+    }
+}
+
+impl DesiredFrequencyResponse for Highpass {
+    fn defined_on(&self) -> impl IntoIterator<Item = Band> {
+        [
+            Band {
+                start: 0.0,
+                end: self.stopband_end,
+            },
+            Band {
+                start: self.passband_start,
+                end: 0.5,
+            },
+        ]
+    }
+
+    fn frequency_response_at(&self, frequency: f32) -> Option<FrequencyResponseAt> {
+        if frequency <= self.stopband_end {
+            Some(FrequencyResponseAt {
+                amplitude: 0.0,
+                tolerance: self.stopband_tolerance,
+            })
+        }
+        else if frequency >= self.passband_start {
+            Some(FrequencyResponseAt {
+                amplitude: 1.0,
+                tolerance: self.passband_tolerance,
+            })
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl EstimateFilterLength for Normalized<Highpass> {
+    fn estimate_filter_length(&self) -> usize {
+        let n = (-20.0
+            * (self.0.passband_tolerance * self.0.stopband_tolerance)
+                .sqrt()
+                .log10()
+            - 13.0)
+            / (14.6 * (self.0.passband_start - self.0.stopband_end));
+        // an even length would have a zero at the Nyquist frequency
+        (n.ceil() as usize) | 1
+    }
+}
+
+impl IsSymmetric for Highpass {
+    fn symmetry(&self) -> Symmetry {
+        Symmetry::Positive
+    }
+}
+
+/// A band-pass filter.
+#[derive(Clone, Copy, Debug)]
+pub struct Bandpass {
+    pub lower_stopband_end: f32,
+    pub passband_start: f32,
+    pub passband_end: f32,
+    pub upper_stopband_start: f32,
+    pub passband_tolerance: f32,
+    pub stopband_tolerance: f32,
+}
+
+impl Bandpass {
+    /// Both transition bands are `transition_bandwidth` wide, and centered on
+    /// the cutoff frequencies.
+    pub fn new(
+        lower_cutoff_frequency: f32,
+        upper_cutoff_frequency: f32,
+        transition_bandwidth: f32,
+        passband_tolerance: f32,
+        stopband_tolerance: f32,
+    ) -> Self {
+        let half_transition_bandwidth = 0.5 * transition_bandwidth;
+        Self {
+            lower_stopband_end: lower_cutoff_frequency - half_transition_bandwidth,
+            passband_start: lower_cutoff_frequency + half_transition_bandwidth,
+            passband_end: upper_cutoff_frequency - half_transition_bandwidth,
+            upper_stopband_start: upper_cutoff_frequency + half_transition_bandwidth,
+            passband_tolerance,
+            stopband_tolerance,
+        }
+    }
+
+    pub fn center_frequency(&self) -> f32 {
+        0.5 * (self.passband_start + self.passband_end)
+    }
+
+    /// The width of the transition bands. If they differ, this is the
+    /// narrower one.
+    pub fn transition_bandwidth(&self) -> f32 {
+        (self.passband_start - self.lower_stopband_end)
+            .min(self.upper_stopband_start - self.passband_end)
+    }
+}
+
+impl Normalize for Bandpass {
+    type Normalized = Normalized<Self>;
+
+    fn normalize(self, sample_rate: f32) -> Self::Normalized {
+        Normalized(Self {
+            lower_stopband_end: self.lower_stopband_end / sample_rate,
+            passband_start: self.passband_start / sample_rate,
+            passband_end: self.passband_end / sample_rate,
+            upper_stopband_start: self.upper_stopband_start / sample_rate,
+            passband_tolerance: self.passband_tolerance,
+            stopband_tolerance: self.stopband_tolerance,
+        })
+    }
+}
+
+impl DesiredFrequencyResponse for Bandpass {
+    fn defined_on(&self) -> impl IntoIterator<Item = Band> {
+        [
+            Band {
+                start: 0.0,
+                end: self.lower_stopband_end,
+            },
+            Band {
+                start: self.passband_start,
+                end: self.passband_end,
+            },
+            Band {
+                start: self.upper_stopband_start,
+                end: 0.5,
+            },
+        ]
+    }
+
+    fn frequency_response_at(&self, frequency: f32) -> Option<FrequencyResponseAt> {
+        if frequency <= self.lower_stopband_end || frequency >= self.upper_stopband_start {
+            Some(FrequencyResponseAt {
+                amplitude: 0.0,
+                tolerance: self.stopband_tolerance,
+            })
+        }
+        else if frequency >= self.passband_start && frequency <= self.passband_end {
+            Some(FrequencyResponseAt {
+                amplitude: 1.0,
+                tolerance: self.passband_tolerance,
+            })
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl EstimateFilterLength for Normalized<Bandpass> {
+    fn estimate_filter_length(&self) -> usize {
+        let n = (-20.0
+            * (self.0.passband_tolerance * self.0.stopband_tolerance)
+                .sqrt()
+                .log10()
+            - 13.0)
+            / (14.6 * self.0.transition_bandwidth());
+        n.ceil() as usize
+    }
+}
+
+impl IsSymmetric for Bandpass {
+    fn symmetry(&self) -> Symmetry {
+        Symmetry::Positive
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Hilbert {
     pub allpass_begin: f32,
@@ -133,3 +133,32 @@ where
 
     pm_remez::pm_remez(&parameters)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::pm_remez;
+    use crate::filter::design::{
+        Lowpass,
+        Normalize,
+        verify,
+    };
+
+    #[test]
+    fn it_designs_a_lowpass_within_tolerance() {
+        let specification = Lowpass::new(0.2, 0.1, 0.01, 0.001).assert_normalized();
+        let design = pm_remez(specification, 41).unwrap();
+
+        let verification = verify(&design, &specification, 512);
+        assert!(verification.passed(), "{verification:#?}");
+    }
+
+    #[test]
+    fn it_detects_a_lowpass_that_is_too_short() {
+        let specification = Lowpass::new(0.2, 0.1, 0.01, 0.001).assert_normalized();
+        let design = pm_remez(specification, 11).unwrap();
+
+        let verification = verify(&design, &specification, 512);
+        assert!(!verification.passed());
+        assert!(verification.worst_deviation() > 0.001);
+    }
+}
@@ -0,0 +1,166 @@
+use std::f32::consts::TAU;
+
+use num_complex::Complex;
+
+use crate::filter::design::{
+    Band,
+    DesiredFrequencyResponse,
+    FilterDesign,
+    IsNormalized,
+};
+
+/// Measures the frequency response of a filter design against the tolerances
+/// of a filter specification.
+///
+/// The response is evaluated at `n_points` frequencies, which are spread over
+/// the bands the specification is defined on, proportionally to their width.
+/// Every band is checked at least at its edges.
+///
+/// This can be used to check a filter at runtime, e.g. when the filter length
+/// is picked by the user, before it's put into a signal chain.
+pub fn verify<D, F>(design: &D, filter_specification: &F, n_points: usize) -> Verification
+where
+    D: FilterDesign + ?Sized,
+    F: DesiredFrequencyResponse + IsNormalized,
+{
+    let coefficients = design.coefficients();
+    let bands = filter_specification
+        .defined_on()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let total_width = bands.iter().map(|band| band.end - band.start).sum::<f32>();
+
+    let bands = bands
+        .into_iter()
+        .map(|band| {
+            let width = band.end - band.start;
+            let num_points = if total_width > 0.0 {
+                (n_points as f32 * width / total_width).round() as usize
+            }
+            else {
+                0
+            }
+            .max(2);
+
+            let mut result = BandVerification {
+                band,
+                worst_deviation: 0.0,
+                worst_frequency: band.start,
+                passed: true,
+            };
+
+            for i in 0..num_points {
+                let frequency = band.start + width * i as f32 / (num_points - 1) as f32;
+                let Some(desired) = filter_specification.frequency_response_at(frequency)
+                else {
+                    continue;
+                };
+
+                let deviation = (amplitude_at(coefficients, frequency) - desired.amplitude).abs();
+                if deviation > desired.tolerance {
+                    result.passed = false;
+                }
+                if deviation > result.worst_deviation {
+                    result.worst_deviation = deviation;
+                    result.worst_frequency = frequency;
+                }
+            }
+
+            result
+        })
+        .collect();
+
+    Verification { bands }
+}
+
+/// Magnitude of the frequency response at a normalized `frequency`.
+fn amplitude_at(coefficients: &[f32], frequency: f32) -> f32 {
+    coefficients
+        .iter()
+        .enumerate()
+        .map(|(n, h)| *h * Complex::from_polar(1.0, -TAU * frequency * n as f32))
+        .sum::<Complex<f32>>()
+        .norm()
+}
+
+/// Result of [`verify`].
+#[derive(Clone, Debug)]
+pub struct Verification {
+    /// One result for every band the filter specification is defined on, in
+    /// the same order.
+    pub bands: Vec<BandVerification>,
+}
+
+impl Verification {
+    /// Whether the filter is within tolerance in all bands.
+    pub fn passed(&self) -> bool {
+        self.bands.iter().all(|band| band.passed)
+    }
+
+    /// The largest deviation over all bands.
+    pub fn worst_deviation(&self) -> f32 {
+        self.bands
+            .iter()
+            .map(|band| band.worst_deviation)
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Result of [`verify`] for a single band.
+#[derive(Clone, Copy, Debug)]
+pub struct BandVerification {
+    pub band: Band,
+
+    /// Largest absolute difference between the measured and the desired
+    /// amplitude.
+    pub worst_deviation: f32,
+
+    /// Frequency at which the largest deviation occurs.
+    pub worst_frequency: f32,
+
+    /// Whether the amplitude is within the tolerance everywhere in this band.
+    pub passed: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::verify;
+    use crate::filter::design::{
+        Lowpass,
+        Normalize,
+    };
+
+    #[test]
+    fn it_verifies_a_moving_average() {
+        // 4 taps have zeros at 1/4 and 1/2 cycles per sample
+        let design = vec![0.25f32; 4];
+
+        let verification = verify(
+            &design,
+            &Lowpass {
+                passband_end: 0.0,
+                stopband_start: 0.25,
+                passband_tolerance: 1e-3,
+                stopband_tolerance: 0.5,
+            }
+            .assert_normalized(),
+            64,
+        );
+        assert!(verification.passed(), "{verification:#?}");
+
+        let verification = verify(
+            &design,
+            &Lowpass {
+                passband_end: 0.05,
+                stopband_start: 0.25,
+                passband_tolerance: 1e-3,
+                stopband_tolerance: 1e-3,
+            }
+            .assert_normalized(),
+            64,
+        );
+        assert!(!verification.passed());
+        assert!(!verification.bands[0].passed);
+        assert!(!verification.bands[1].passed);
+    }
+}
@@ -0,0 +1,424 @@
+//! Windowed-sinc FIR filter design
+//!
+//! The ideal, piecewise constant frequency response is a sum of sinc
+//! functions, which is truncated to the filter length and multiplied with a
+//! window. This is less efficient than [Remez][super::pm_remez], i.e. it needs
+//! more coefficients for the same tolerances, but it always succeeds.
+//!
+//! The ripple in all bands is about the same, and depends on the window. The
+//! [Kaiser window][Window::Kaiser] can be adjusted for any attenuation, and is
+//! used by default with the tightest tolerance of the filter specification.
+//!
+//! # References
+//!
+//! - J. F. Kaiser, "Nonrecursive digital filter design using the I0-sinh window
+//!   function", 1974
+//! - A. V. Oppenheim, R. W. Schafer, "Discrete-time signal processing", section
+//!   7.5
+
+use std::f32::consts::PI;
+
+use crate::filter::design::{
+    Band,
+    DesignAlgorithm,
+    DesiredFrequencyResponse,
+    Estimate,
+    IsSymmetric,
+    Symmetry,
+};
+
+/// Extra attenuation in dB that the Kaiser window is designed for, because
+/// Kaiser's formulas slightly underestimate the ripple.
+const KAISER_MARGIN: f32 = 2.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the windowed-sinc method can only design filters with positive symmetry")]
+    UnsupportedSymmetry,
+    #[error("a filter with an even length can't pass the Nyquist frequency")]
+    EvenLengthAtNyquist,
+    #[error("the filter specification has no transition band to estimate the filter length from")]
+    NoTransitionBand,
+    #[error("the filter length is 0")]
+    ZeroLength,
+}
+
+/// Window that the truncated ideal impulse response is multiplied with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Window {
+    /// Attenuates the stopband by about 53 dB.
+    Hamming,
+
+    /// Attenuates the stopband by about 74 dB, with a wider transition band
+    /// than [`Hamming`][Self::Hamming].
+    Blackman,
+
+    /// Kaiser window for a stopband attenuation in dB.
+    Kaiser { attenuation: f32 },
+}
+
+impl Window {
+    /// A Kaiser window for a stopband attenuation in dB.
+    #[inline]
+    pub fn kaiser(attenuation: f32) -> Self {
+        Self::Kaiser { attenuation }
+    }
+
+    /// A Kaiser window for a tolerance (as a linear amplitude) in all bands.
+    #[inline]
+    pub fn kaiser_for_tolerance(tolerance: f32) -> Self {
+        Self::kaiser(-20.0 * tolerance.log10())
+    }
+
+    /// The stopband attenuation in dB.
+    pub fn attenuation(&self) -> f32 {
+        match self {
+            Self::Hamming => 53.0,
+            Self::Blackman => 74.0,
+            Self::Kaiser { attenuation } => *attenuation,
+        }
+    }
+
+    /// The shape parameter of a Kaiser window, according to Kaiser's formula.
+    ///
+    /// This is 0 for other windows.
+    pub fn beta(&self) -> f32 {
+        match self {
+            Self::Kaiser { attenuation } if *attenuation > 50.0 => 0.1102 * (attenuation - 8.7),
+            Self::Kaiser { attenuation } if *attenuation >= 21.0 => {
+                0.5842 * (attenuation - 21.0).powf(0.4) + 0.07886 * (attenuation - 21.0)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The filter length that is needed for a transition band with the given
+    /// normalized width.
+    pub fn estimate_filter_length(&self, transition_bandwidth: f32) -> usize {
+        let length = match self {
+            Self::Hamming => 3.3 / transition_bandwidth,
+            Self::Blackman => 5.5 / transition_bandwidth,
+            Self::Kaiser { attenuation } => {
+                (attenuation - 7.95) / (14.36 * transition_bandwidth) + 1.0
+            }
+        };
+        (length.ceil() as usize).max(1)
+    }
+
+    /// The window's samples for a filter of the given length.
+    pub fn samples(&self, length: usize) -> impl Iterator<Item = f32> {
+        let beta = self.beta();
+        let i0_beta = bessel_i0(beta);
+
+        (0..length).map(move |n| {
+            // from -1 to 1
+            let x = if length > 1 {
+                2.0 * n as f32 / (length - 1) as f32 - 1.0
+            }
+            else {
+                0.0
+            };
+
+            match self {
+                Self::Hamming => 0.54 + 0.46 * (PI * x).cos(),
+                Self::Blackman => 0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos(),
+                Self::Kaiser { .. } => bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / i0_beta,
+            }
+        })
+    }
+}
+
+/// Modified Bessel function of the first kind of order 0.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > 1e-8 * sum {
+        term *= (0.5 * x / k).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// Normalized sinc function.
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    }
+    else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Band of the ideal frequency response, between the centers of the
+/// transition bands.
+#[derive(Clone, Copy, Debug)]
+struct IdealBand {
+    start: f32,
+    end: f32,
+    amplitude: f32,
+}
+
+/// The ideal frequency response of a specification, the narrowest transition
+/// band, and the tightest tolerance.
+fn ideal_response<F>(filter_specification: &F) -> (Vec<IdealBand>, Option<f32>, f32)
+where
+    F: DesiredFrequencyResponse,
+{
+    let mut bands = filter_specification
+        .defined_on()
+        .into_iter()
+        .collect::<Vec<Band>>();
+    bands.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut ideal_bands = Vec::with_capacity(bands.len());
+    let mut transition_bandwidth: Option<f32> = None;
+    let mut tolerance = f32::INFINITY;
+
+    for (i, band) in bands.iter().enumerate() {
+        let Some(response) =
+            filter_specification.frequency_response_at(0.5 * (band.start + band.end))
+        else {
+            continue;
+        };
+        tolerance = tolerance.min(response.tolerance);
+
+        let start = if i == 0 {
+            0.0
+        }
+        else {
+            0.5 * (bands[i - 1].end + band.start)
+        };
+        let end = if let Some(next) = bands.get(i + 1) {
+            let width = next.start - band.end;
+            transition_bandwidth = Some(transition_bandwidth.map_or(width, |min| min.min(width)));
+            0.5 * (band.end + next.start)
+        }
+        else {
+            0.5
+        };
+
+        ideal_bands.push(IdealBand {
+            start,
+            end,
+            amplitude: response.amplitude,
+        });
+    }
+
+    (ideal_bands, transition_bandwidth, tolerance)
+}
+
+/// Designs a filter with the windowed-sinc method.
+///
+/// The filter specification must be normalized.
+pub fn windowed_sinc<F>(
+    filter_specification: F,
+    filter_length: usize,
+    window: Window,
+) -> Result<Vec<f32>, Error>
+where
+    F: DesiredFrequencyResponse + IsSymmetric,
+{
+    if filter_specification.symmetry() != Symmetry::Positive {
+        return Err(Error::UnsupportedSymmetry);
+    }
+    if filter_length == 0 {
+        return Err(Error::ZeroLength);
+    }
+
+    let (bands, _, _) = ideal_response(&filter_specification);
+    if filter_length.is_multiple_of(2)
+        && bands
+            .last()
+            .is_some_and(|band| band.end >= 0.5 && band.amplitude != 0.0)
+    {
+        return Err(Error::EvenLengthAtNyquist);
+    }
+
+    // impulse response of an ideal low-pass with a cutoff frequency
+    let lowpass = |cutoff: f32, t: f32| 2.0 * cutoff * sinc(2.0 * cutoff * t);
+
+    let center = 0.5 * (filter_length - 1) as f32;
+    let coefficients = window
+        .samples(filter_length)
+        .enumerate()
+        .map(|(n, window)| {
+            let t = n as f32 - center;
+            let ideal = bands
+                .iter()
+                .map(|band| band.amplitude * (lowpass(band.end, t) - lowpass(band.start, t)))
+                .sum::<f32>();
+            ideal * window
+        })
+        .collect();
+
+    Ok(coefficients)
+}
+
+/// The windowed-sinc method as a [`DesignAlgorithm`].
+///
+/// With an [`Estimate`]d filter length, the length is estimated for the
+/// window and the narrowest transition band of the specification.
+#[derive(Clone, Copy, Debug)]
+pub struct WindowedSincAlgorithm<L> {
+    pub filter_length: L,
+
+    /// The window, or `None` for a Kaiser window for the tightest tolerance of
+    /// the specification.
+    pub window: Option<Window>,
+}
+
+impl<L> WindowedSincAlgorithm<L> {
+    pub fn with_filter_length(self, filter_length: usize) -> WindowedSincAlgorithm<usize> {
+        WindowedSincAlgorithm {
+            filter_length,
+            window: self.window,
+        }
+    }
+
+    pub fn with_window(mut self, window: Window) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    fn window_for<F>(&self, filter_specification: &F) -> Window
+    where
+        F: DesiredFrequencyResponse,
+    {
+        self.window.unwrap_or_else(|| {
+            let (_, _, tolerance) = ideal_response(filter_specification);
+            Window::kaiser(-20.0 * tolerance.log10() + KAISER_MARGIN)
+        })
+    }
+}
+
+impl Default for WindowedSincAlgorithm<Estimate> {
+    fn default() -> Self {
+        Self {
+            filter_length: Estimate,
+            window: None,
+        }
+    }
+}
+
+impl<F> DesignAlgorithm<F> for WindowedSincAlgorithm<usize>
+where
+    F: DesiredFrequencyResponse + IsSymmetric,
+{
+    type Design = Vec<f32>;
+    type Error = Error;
+
+    fn design_filter(&self, filter_specification: F) -> Result<Self::Design, Self::Error> {
+        let window = self.window_for(&filter_specification);
+        windowed_sinc(filter_specification, self.filter_length, window)
+    }
+}
+
+impl<F> DesignAlgorithm<F> for WindowedSincAlgorithm<Estimate>
+where
+    F: DesiredFrequencyResponse + IsSymmetric,
+{
+    type Design = Vec<f32>;
+    type Error = Error;
+
+    fn design_filter(&self, filter_specification: F) -> Result<Self::Design, Self::Error> {
+        let window = self.window_for(&filter_specification);
+        let (bands, transition_bandwidth, _) = ideal_response(&filter_specification);
+        let transition_bandwidth = transition_bandwidth.ok_or(Error::NoTransitionBand)?;
+
+        let mut filter_length = window.estimate_filter_length(transition_bandwidth);
+        if bands.last().is_some_and(|band| band.amplitude != 0.0) {
+            filter_length |= 1;
+        }
+
+        windowed_sinc(filter_specification, filter_length, window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::{
+        Error,
+        Window,
+        WindowedSincAlgorithm,
+        windowed_sinc,
+    };
+    use crate::filter::design::{
+        Bandpass,
+        DesignAlgorithm,
+        Highpass,
+        Hilbert,
+        Lowpass,
+        Normalize,
+        verify,
+    };
+
+    #[test]
+    fn it_computes_the_kaiser_beta() {
+        assert_abs_diff_eq!(Window::kaiser(60.0).beta(), 5.653, epsilon = 1e-3);
+        assert_abs_diff_eq!(Window::kaiser(40.0).beta(), 3.395, epsilon = 1e-3);
+        assert_eq!(Window::kaiser(20.0).beta(), 0.0);
+        assert_abs_diff_eq!(
+            Window::kaiser_for_tolerance(0.001).attenuation(),
+            60.0,
+            epsilon = 1e-3
+        );
+    }
+
+    #[test]
+    fn it_designs_filters_within_tolerance() {
+        let algorithm = WindowedSincAlgorithm::default();
+
+        let lowpass = Lowpass::new(0.2, 0.1, 0.01, 0.001).assert_normalized();
+        let design = algorithm.design_filter(lowpass).unwrap();
+        let verification = verify(&design, &lowpass, 512);
+        assert!(verification.passed(), "{verification:#?}");
+
+        let highpass = Highpass::new(0.2, 0.1, 0.01, 0.001).assert_normalized();
+        let design = algorithm.design_filter(highpass).unwrap();
+        assert_eq!(design.len() % 2, 1);
+        let verification = verify(&design, &highpass, 512);
+        assert!(verification.passed(), "{verification:#?}");
+
+        let bandpass = Bandpass::new(0.125, 0.325, 0.05, 0.01, 0.001).assert_normalized();
+        let design = algorithm.design_filter(bandpass).unwrap();
+        let verification = verify(&design, &bandpass, 512);
+        assert!(verification.passed(), "{verification:#?}");
+    }
+
+    #[test]
+    fn it_designs_with_fixed_windows() {
+        for (window, tolerance) in [(Window::Hamming, 0.01), (Window::Blackman, 0.001)] {
+            let lowpass = Lowpass::new(0.2, 0.1, tolerance, tolerance).assert_normalized();
+            let design = WindowedSincAlgorithm::default()
+                .with_window(window)
+                .design_filter(lowpass)
+                .unwrap();
+            assert_eq!(
+                design.len(),
+                window.estimate_filter_length(lowpass.0.transition_bandwidth())
+            );
+
+            let verification = verify(&design, &lowpass, 512);
+            assert!(verification.passed(), "{window:?}: {verification:#?}");
+        }
+    }
+
+    #[test]
+    fn it_rejects_impossible_designs() {
+        let highpass = Highpass::new(0.2, 0.1, 0.01, 0.001).assert_normalized();
+        assert!(matches!(
+            windowed_sinc(highpass, 40, Window::Hamming),
+            Err(Error::EvenLengthAtNyquist)
+        ));
+
+        let hilbert = Hilbert::new(0.1).assert_normalized();
+        assert!(matches!(
+            windowed_sinc(hilbert, 41, Window::Hamming),
+            Err(Error::UnsupportedSymmetry)
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+//! Fractional delay using the Farrow structure
+//!
+//! A Farrow filter computes a polynomial interpolation between the last few
+//! samples, with the fractional delay as the polynomial's variable. This
+//! makes it cheap to change the delay for every sample, which is what timing
+//! recovery loops need.
+//!
+//! # References
+//!
+//! - C. W. Farrow, "A continuously variable digital delay element", 1988
+//! - L. Erup, F. M. Gardner, R. A. Harris, "Interpolation in digital modems -
+//!   Part II: Implementation and performance", 1993
+
+use std::ops::{
+    Add,
+    Mul,
+    Sub,
+};
+
+use num_traits::Zero;
+
+use crate::io::combinators::{
+    GroupDelay,
+    Scanner,
+};
+
+/// Interpolation polynomial of a [`FarrowDelay`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Piecewise parabolic with `alpha = 0.5`, as proposed by Erup et al.
+    /// Slightly cheaper than cubic.
+    Parabolic,
+
+    /// Cubic Lagrange interpolation
+    #[default]
+    Cubic,
+}
+
+/// Delays the signal by `1 + delay` samples, where `delay` is in the range
+/// `0..=1` and can be changed at any time.
+///
+/// The extra sample of delay is needed so that there is a sample on either
+/// side of the interpolated point.
+#[derive(Clone, Copy, Debug)]
+pub struct FarrowDelay<S> {
+    interpolation: Interpolation,
+    delay: f32,
+
+    /// The last 4 samples, newest first.
+    taps: [S; 4],
+}
+
+impl<S> FarrowDelay<S>
+where
+    S: Zero + Copy,
+{
+    pub fn new(interpolation: Interpolation, delay: f32) -> Self {
+        let mut farrow = Self {
+            interpolation,
+            delay: 0.0,
+            taps: [S::zero(); 4],
+        };
+        farrow.set_delay(delay);
+        farrow
+    }
+
+    #[inline]
+    pub fn cubic(delay: f32) -> Self {
+        Self::new(Interpolation::Cubic, delay)
+    }
+
+    #[inline]
+    pub fn parabolic(delay: f32) -> Self {
+        Self::new(Interpolation::Parabolic, delay)
+    }
+}
+
+impl<S> FarrowDelay<S> {
+    #[inline]
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// The fractional part of the delay.
+    #[inline]
+    pub fn delay(&self) -> f32 {
+        self.delay
+    }
+
+    /// Sets the fractional part of the delay.
+    ///
+    /// Values outside `0..=1` are clamped. The interpolation is only
+    /// accurate within this range.
+    #[inline]
+    pub fn set_delay(&mut self, delay: f32) {
+        debug_assert!(
+            (0.0..=1.0).contains(&delay),
+            "fractional delay out of range: {delay}"
+        );
+        self.delay = delay.clamp(0.0, 1.0);
+    }
+}
+
+impl<S> Scanner<S> for FarrowDelay<S>
+where
+    S: Copy + Add<S, Output = S> + Sub<S, Output = S> + Mul<f32, Output = S>,
+{
+    type Output = S;
+
+    #[inline]
+    fn scan(&mut self, sample: S) -> Self::Output {
+        self.push(sample);
+        self.interpolate()
+    }
+}
+
+impl<S> FarrowDelay<S>
+where
+    S: Copy + Add<S, Output = S> + Sub<S, Output = S> + Mul<f32, Output = S>,
+{
+    /// Shifts in a sample without interpolating.
+    #[inline]
+    pub fn push(&mut self, sample: S) {
+        self.taps = [sample, self.taps[0], self.taps[1], self.taps[2]];
+    }
+
+    /// Interpolates between the samples that were shifted in, at the current
+    /// delay.
+    ///
+    /// Together with [`push`][Self::push], this allows for more or fewer
+    /// outputs than inputs, e.g. to resample.
+    pub fn interpolate(&self) -> S {
+        let [x0, x1, x2, x3] = self.taps;
+        let d = self.delay;
+
+        // the output is x1 for a delay of 0, and x2 for a delay of 1.
+        match self.interpolation {
+            Interpolation::Parabolic => {
+                const ALPHA: f32 = 0.5;
+                let c1 = (x2 - x3 - x0) * ALPHA + x2 - x1 + x1 * ALPHA;
+                let c2 = (x3 - x2 - x1 + x0) * ALPHA;
+                (c2 * d + c1) * d + x1
+            }
+            Interpolation::Cubic => {
+                let c1 = x2 - x0 * (1.0 / 3.0) - x1 * 0.5 - x3 * (1.0 / 6.0);
+                let c2 = (x0 + x2) * 0.5 - x1;
+                let c3 = (x3 - x0) * (1.0 / 6.0) + (x1 - x2) * 0.5;
+                ((c3 * d + c2) * d + c1) * d + x1
+            }
+        }
+    }
+}
+
+impl<S> GroupDelay for FarrowDelay<S> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        1.0 + f64::from(self.delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::{
+        FarrowDelay,
+        Interpolation,
+    };
+    use crate::io::combinators::Scanner;
+
+    fn check(interpolation: Interpolation, epsilon: f32) {
+        let signal = |t: f32| (0.2 * t).sin();
+
+        for delay in [0.0, 0.25, 0.5, 0.9, 1.0] {
+            let mut farrow = FarrowDelay::new(interpolation, delay);
+            for n in 0..32 {
+                let output = farrow.scan(signal(n as f32));
+                if n >= 3 {
+                    assert_abs_diff_eq!(output, signal(n as f32 - 1.0 - delay), epsilon = epsilon);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_delays_by_fractional_samples() {
+        check(Interpolation::Cubic, 1e-4);
+        check(Interpolation::Parabolic, 1e-2);
+    }
+}
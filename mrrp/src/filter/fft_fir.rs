@@ -0,0 +1,318 @@
+//! FIR filter with fast convolution
+//!
+//! [`FirFilter`][super::fir::FirFilter] needs a multiplication per tap for
+//! every sample. [`FftFirFilter`] collects samples into blocks and convolves
+//! them with the overlap-save method, which needs `O(log n)` operations per
+//! sample. This pays off from a few dozen taps.
+//!
+//! The FFTs run on the CPU by default, but any [`FftBackend`] can be used,
+//! e.g. the GPU back-end for very long filters.
+
+use std::fmt::Debug;
+
+use num_complex::Complex;
+
+use crate::{
+    fft::{
+        CpuFft,
+        FftBackend,
+    },
+    io::combinators::{
+        GroupDelay,
+        Scanner,
+    },
+};
+
+/// Smallest FFT size that [`FftFirFilter::new`] picks.
+const MIN_FFT_SIZE: usize = 64;
+
+/// Samples that can be filtered with an [`FftFirFilter`].
+pub trait FftFilterSample: Copy {
+    fn to_complex(self) -> Complex<f32>;
+    fn from_complex(sample: Complex<f32>) -> Self;
+}
+
+impl FftFilterSample for f32 {
+    #[inline]
+    fn to_complex(self) -> Complex<f32> {
+        Complex::new(self, 0.0)
+    }
+
+    #[inline]
+    fn from_complex(sample: Complex<f32>) -> Self {
+        sample.re
+    }
+}
+
+impl FftFilterSample for Complex<f32> {
+    #[inline]
+    fn to_complex(self) -> Complex<f32> {
+        self
+    }
+
+    #[inline]
+    fn from_complex(sample: Complex<f32>) -> Self {
+        sample
+    }
+}
+
+/// FIR filter that convolves blocks of samples with FFTs (overlap-save).
+///
+/// It scans sample by sample like any other filter, but only filters once a
+/// block is complete. The output is delayed by the [block
+/// size][Self::block_size], in addition to the filter's own delay.
+pub struct FftFirFilter<S> {
+    fft: Box<dyn FftBackend>,
+    num_taps: usize,
+    /// Spectrum of the coefficients, scaled for the unnormalized inverse FFT.
+    spectrum: Box<[Complex<f32>]>,
+    /// The last `num_taps - 1` samples of the previous block, followed by the
+    /// current block.
+    input: Vec<Complex<f32>>,
+    num_input: usize,
+    work: Vec<Complex<f32>>,
+    /// Filtered previous block.
+    output: Vec<S>,
+    num_scanned: usize,
+    num_flushed: usize,
+}
+
+impl<S: FftFilterSample> FftFirFilter<S> {
+    /// Creates a filter with an FFT size that suits the number of
+    /// coefficients.
+    pub fn new(coefficients: &[f32]) -> Self {
+        let fft_size = (4 * coefficients.len())
+            .next_power_of_two()
+            .max(MIN_FFT_SIZE);
+        Self::with_fft_size(coefficients, fft_size)
+    }
+
+    /// Creates a filter with a specific FFT size. Each block is `fft_size -
+    /// coefficients.len() + 1` samples long.
+    pub fn with_fft_size(coefficients: &[f32], fft_size: usize) -> Self {
+        assert!(coefficients.len() > 1);
+        assert!(
+            fft_size >= coefficients.len(),
+            "FFT size {fft_size} is smaller than the number of coefficients {}",
+            coefficients.len()
+        );
+
+        let mut fft = CpuFft::new(fft_size);
+
+        let norm = 1.0 / fft_size as f32;
+        let mut spectrum = vec![Complex::default(); fft_size];
+        for (bin, coefficient) in spectrum.iter_mut().zip(coefficients) {
+            *bin = Complex::new(coefficient * norm, 0.0);
+        }
+        fft.process(&mut spectrum);
+
+        let block_size = fft_size - coefficients.len() + 1;
+        Self {
+            fft: Box::new(fft),
+            num_taps: coefficients.len(),
+            spectrum: spectrum.into(),
+            input: vec![Complex::default(); fft_size],
+            num_input: 0,
+            work: vec![Complex::default(); fft_size],
+            output: vec![S::from_complex(Complex::default()); block_size],
+            num_scanned: 0,
+            num_flushed: 0,
+        }
+    }
+
+    /// Runs the FFTs on another back-end, e.g. one from
+    /// [`plan_forward`][crate::fft::plan_forward].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the back-end's size is not the [FFT size][Self::fft_size].
+    pub fn with_fft(mut self, fft: Box<dyn FftBackend>) -> Self {
+        assert_eq!(
+            fft.size(),
+            self.fft_size(),
+            "FFT back-end has the wrong size"
+        );
+        self.fft = fft;
+        self
+    }
+
+    #[inline]
+    pub fn fft_size(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Number of samples that are filtered at once.
+    #[inline]
+    pub fn block_size(&self) -> usize {
+        self.output.len()
+    }
+
+    fn filter_block(&mut self) {
+        self.work.copy_from_slice(&self.input);
+        self.fft.process(&mut self.work);
+        for (bin, coefficient) in self.work.iter_mut().zip(self.spectrum.iter()) {
+            *bin *= *coefficient;
+        }
+        self.fft.process_inverse(&mut self.work);
+
+        // the first samples are wrapped around, and are discarded
+        for (output, sample) in self.output.iter_mut().zip(&self.work[self.num_taps - 1..]) {
+            *output = S::from_complex(*sample);
+        }
+
+        let block_size = self.block_size();
+        self.input.copy_within(block_size.., 0);
+        self.num_input = 0;
+    }
+
+    fn reset(&mut self) {
+        self.input.fill(Complex::default());
+        self.output.fill(S::from_complex(Complex::default()));
+        self.num_input = 0;
+        self.num_scanned = 0;
+        self.num_flushed = 0;
+    }
+}
+
+impl<S: FftFilterSample> Scanner<S> for FftFirFilter<S> {
+    type Output = S;
+
+    fn scan(&mut self, sample: S) -> Self::Output {
+        let output = self.output[self.num_input];
+        self.input[self.num_taps - 1 + self.num_input] = sample.to_complex();
+        self.num_input += 1;
+        self.num_scanned += 1;
+
+        if self.num_input == self.block_size() {
+            self.filter_block();
+        }
+
+        output
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        // the block in progress and the filter's own delay
+        if self.num_scanned > 0 && self.num_flushed < self.block_size() + self.num_taps / 2 {
+            self.num_flushed += 1;
+            Some(self.scan(S::from_complex(Complex::default())))
+        }
+        else {
+            self.reset();
+            None
+        }
+    }
+}
+
+impl<S> GroupDelay for FftFirFilter<S> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        // assumes linear phase, i.e. symmetric coefficients
+        self.output.len() as f64 + 0.5 * (self.num_taps - 1) as f64
+    }
+}
+
+impl<S> Debug for FftFirFilter<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FftFirFilter")
+            .field("fft", &self.fft)
+            .field("num_taps", &self.num_taps)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+    use rand::rngs::SmallRng;
+
+    use crate::{
+        fft::FftPool,
+        filter::{
+            fft_fir::FftFirFilter,
+            fir::{
+                FirFilter,
+                hann_window,
+            },
+        },
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+            combinators::Scanner,
+        },
+        source::white_noise,
+    };
+
+    #[test]
+    fn it_matches_the_direct_form_filter() {
+        let coefficients = hann_window(100).collect::<Vec<f32>>();
+        let mut fft_filter = FftFirFilter::new(&coefficients);
+        assert_eq!(fft_filter.fft_size(), 512);
+        assert_eq!(fft_filter.block_size(), 412);
+        let block_size = fft_filter.block_size();
+
+        let mut filter = FirFilter::new(coefficients);
+        let mut input = vec![];
+        white_noise::<SmallRng, Complex<f32>>(rand::make_rng())
+            .limit(2000)
+            .read_to_end(&mut input)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+
+        let expected = input
+            .iter()
+            .map(|sample| filter.scan(*sample))
+            .collect::<Vec<_>>();
+        let output = input
+            .iter()
+            .map(|sample| fft_filter.scan(*sample))
+            .collect::<Vec<_>>();
+
+        for sample in &output[..block_size] {
+            assert_eq!(*sample, Complex::default());
+        }
+        for (output, expected) in output[block_size..].iter().zip(&expected) {
+            assert_abs_diff_eq!(output.re, expected.re, epsilon = 1e-3);
+            assert_abs_diff_eq!(output.im, expected.im, epsilon = 1e-3);
+        }
+    }
+
+    #[test]
+    fn it_filters_with_another_backend() {
+        let coefficients = hann_window(20).collect::<Vec<f32>>();
+        let mut expected = FftFirFilter::with_fft_size(&coefficients, 64);
+        let mut filter = FftFirFilter::with_fft_size(&coefficients, 64)
+            .with_fft(Box::new(FftPool::with_threads(0).plan(64)));
+
+        for i in 0..500 {
+            let sample = Complex::new((i as f32 * 0.3).sin(), (i as f32 * 0.7).cos());
+            let (output, expected) = (filter.scan(sample), expected.scan(sample));
+            assert_abs_diff_eq!(output.re, expected.re, epsilon = 1e-5);
+            assert_abs_diff_eq!(output.im, expected.im, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn it_flushes_the_block_at_the_end_of_the_stream() {
+        let input = vec![1.0f32; 100];
+        let filter = FftFirFilter::with_fft_size(&[0.2; 9], 32);
+        let block_size = filter.block_size();
+        assert_eq!(block_size, 24);
+
+        let mut output = vec![];
+        Cursor::new(&input[..])
+            .scan_with(filter)
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(output.len(), 100 + block_size + 4);
+
+        // the last output is centered on the last input sample, so it only
+        // covers half the filter
+        assert_abs_diff_eq!(output[block_size + 50], 1.8, epsilon = 1e-5);
+        assert_abs_diff_eq!(output[block_size + 103], 1.0, epsilon = 1e-5);
+    }
+}
@@ -5,6 +5,7 @@ use std::{
         AddAssign,
         Mul,
     },
+    sync::Arc,
 };
 
 use num_traits::{
@@ -16,21 +17,34 @@ use num_traits::{
 
 use crate::{
     io::combinators::{
+        GroupDelay,
         ScanInPlaceWith,
         Scanner,
     },
     sample::Sample,
 };
 
+/// FIR filter
+///
+/// The coefficients are immutable and shared between clones of a filter, so
+/// that e.g. a filter per channel doesn't copy them for every channel.
 #[derive(Clone, Debug)]
 pub struct FirFilter<S, C> {
-    coefficients: Vec<C>,
+    coefficients: Arc<[C]>,
     delayed: VecDeque<S>,
+    num_flushed: usize,
 }
 
 impl<S, C> FirFilter<S, C> {
     #[inline]
     pub fn new(coefficients: Vec<C>) -> Self {
+        Self::shared(coefficients.into())
+    }
+
+    /// Creates a filter with coefficients that are shared with other filters,
+    /// e.g. from a [`SharedDesign`][crate::filter::design::SharedDesign].
+    #[inline]
+    pub fn shared(coefficients: Arc<[C]>) -> Self {
         assert!(coefficients.len() > 1);
 
         let delayed = VecDeque::with_capacity(coefficients.len() - 1);
@@ -38,8 +52,14 @@ impl<S, C> FirFilter<S, C> {
         Self {
             coefficients,
             delayed,
+            num_flushed: 0,
         }
     }
+
+    #[inline]
+    pub fn coefficients(&self) -> &Arc<[C]> {
+        &self.coefficients
+    }
 }
 
 impl<S, C> Scanner<S> for FirFilter<S, C>
@@ -64,6 +84,42 @@ where
 
         output
     }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        // scanning a zero would only shift the delay line, so instead we shift
+        // the coefficients and skip the products with zero.
+        let output = if self.num_flushed < self.coefficients.len() / 2 {
+            let mut products = self
+                .delayed
+                .iter()
+                .zip(&self.coefficients[1 + self.num_flushed..])
+                .map(|(delayed, coeff)| *delayed * *coeff);
+            products
+                .next()
+                .map(|first| products.fold(first, |output, product| output + product))
+        }
+        else {
+            None
+        };
+
+        if output.is_some() {
+            self.num_flushed += 1;
+        }
+        else {
+            self.delayed.clear();
+            self.num_flushed = 0;
+        }
+
+        output
+    }
+}
+
+impl<S, C> GroupDelay for FirFilter<S, C> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        // assumes linear phase, i.e. symmetric coefficients
+        0.5 * (self.coefficients.len() - 1) as f64
+    }
 }
 
 pub type FirFiltered<R, S, C> = ScanInPlaceWith<R, FirFilter<S, C>>;
@@ -138,24 +194,31 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use approx::assert_abs_diff_eq;
     use futures_util::FutureExt;
     use rand::rngs::SmallRng;
 
     use crate::{
-        filter::fir::{
-            FirFilter,
-            hann_window,
+        filter::{
+            design::FilterDesign,
+            fir::{
+                FirFilter,
+                hann_window,
+            },
         },
         io::{
             AsyncReadSamplesExt,
             Cursor,
+            combinators::ScannerExt,
         },
         source::white_noise,
     };
 
-    fn convolve(x: &[f32], h: &[f32]) -> Vec<f32> {
-        let mut y = vec![0.0; x.len()];
-        for i in 0..x.len() {
+    fn convolve(x: &[f32], h: &[f32], length: usize) -> Vec<f32> {
+        let mut y = vec![0.0; length];
+        for i in 0..length {
             for j in 0..h.len() {
                 if i >= j
                     && let Some(x) = x.get(i - j)
@@ -179,7 +242,8 @@ mod tests {
 
         let h = hann_window(5).collect::<Vec<f32>>();
 
-        let expected = convolve(&x, &h);
+        // the filter is flushed for its group delay at the end of the stream
+        let expected = convolve(&x, &h, x.len() + h.len() / 2);
 
         let mut y = vec![];
         Cursor::new(&x[..])
@@ -191,4 +255,43 @@ mod tests {
 
         assert_eq!(expected, y);
     }
+
+    #[test]
+    fn it_flushes_the_group_delay_at_the_end_of_the_stream() {
+        let x = vec![1.0f32; 100];
+
+        let mut y = vec![];
+        Cursor::new(&x[..])
+            .scan_in_place_with(FirFilter::new(vec![0.2; 9]))
+            .read_to_end(&mut y)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(y.len(), 104);
+
+        // through an intermediate buffer, and chained with a scanner without
+        // delay
+        let mut y = vec![];
+        Cursor::new(&x[..])
+            .scan_with(FirFilter::new(vec![0.2; 9]).map(|x: f32| 2.0 * x))
+            .read_to_end(&mut y)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(y.len(), 104);
+        // the last output is centered on the last input sample, so it only
+        // covers half the filter
+        assert_abs_diff_eq!(y[103], 2.0, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn filters_share_coefficients() {
+        let design = vec![0.25f32; 4].shared();
+        let a = design.fir_filter::<f32>();
+        let b = a.clone();
+        let c = design.fir_filter::<f32>();
+
+        assert!(Arc::ptr_eq(a.coefficients(), b.coefficients()));
+        assert!(Arc::ptr_eq(a.coefficients(), c.coefficients()));
+    }
 }
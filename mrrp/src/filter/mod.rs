@@ -1,6 +1,10 @@
 pub mod biquad;
+pub mod channelizer;
 pub mod design;
+pub mod farrow;
+pub mod fft_fir;
 pub mod fir;
+pub mod multistage;
 pub mod resampling;
 
 use std::{
@@ -27,7 +31,10 @@ use crate::{
         },
         fir::FirFilter,
     },
-    io::combinators::Scanner,
+    io::combinators::{
+        GroupDelay,
+        Scanner,
+    },
     util::dim::{
         Const,
         DequeLike,
@@ -89,9 +96,13 @@ pub trait MakeFilter<R> {
 }
 
 /// Hilbert filter to recover an IQ signal from a real-valued signal
+///
+/// The in-phase component is delayed by the group delay of the filter, so
+/// that it lines up with the quadrature component.
 #[derive(Clone, Debug)]
 pub struct HilbertFilter {
     hilbert: FirFilter<f32, f32>,
+    in_phase: VecDeque<f32>,
 }
 
 impl HilbertFilter {
@@ -102,10 +113,19 @@ impl HilbertFilter {
             filter_length,
         )
         .expect("failed to design hilbert filter");
+
+        let delay = filter_length / 2;
         Self {
             hilbert: hilbert.fir_filter(),
+            in_phase: std::iter::repeat_n(0.0, delay).collect(),
         }
     }
+
+    /// Delay of the output in samples.
+    #[inline]
+    pub fn delay(&self) -> usize {
+        self.in_phase.len()
+    }
 }
 
 impl Scanner<f32> for HilbertFilter {
@@ -113,7 +133,28 @@ impl Scanner<f32> for HilbertFilter {
 
     fn scan(&mut self, sample: f32) -> Self::Output {
         let q = self.hilbert.scan(sample);
-        Complex { re: sample, im: q }
+        self.in_phase.push_back(sample);
+        let i = self.in_phase.pop_front().unwrap_or(sample);
+        Complex { re: i, im: q }
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        if let Some(q) = self.hilbert.finish() {
+            self.in_phase.push_back(0.0);
+            let i = self.in_phase.pop_front().unwrap_or_default();
+            Some(Complex { re: i, im: q })
+        }
+        else {
+            self.in_phase.iter_mut().for_each(|i| *i = 0.0);
+            None
+        }
+    }
+}
+
+impl GroupDelay for HilbertFilter {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        self.delay() as f64
     }
 }
 
@@ -160,12 +201,21 @@ impl Scanner<Complex<f32>> for GoertzelFilter {
     }
 }
 
+impl GroupDelay for GoertzelFilter {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        // the output is updated once per block
+        0.5 * self.n as f64
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct MovingAverage<S> {
     length: usize,
     norm: f32,
     sum: S,
     delay: VecDeque<S>,
+    num_flushed: usize,
 }
 
 impl<S> MovingAverage<S>
@@ -179,13 +229,14 @@ where
             norm: 1.0 / (length as f32),
             sum: Zero::zero(),
             delay: VecDeque::with_capacity(length),
+            num_flushed: 0,
         }
     }
 }
 
 impl<S> Scanner<S> for MovingAverage<S>
 where
-    S: Copy + AddAssign<S> + SubAssign<S> + Mul<f32, Output = S>,
+    S: Copy + AddAssign<S> + SubAssign<S> + Mul<f32, Output = S> + Zero,
 {
     type Output = S;
 
@@ -200,4 +251,24 @@ where
 
         self.sum * self.norm
     }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        if self.num_flushed < self.length / 2 && !self.delay.is_empty() {
+            self.num_flushed += 1;
+            Some(self.scan(S::zero()))
+        }
+        else {
+            self.sum = S::zero();
+            self.delay.clear();
+            self.num_flushed = 0;
+            None
+        }
+    }
+}
+
+impl<S> GroupDelay for MovingAverage<S> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.5 * (self.length - 1) as f64
+    }
 }
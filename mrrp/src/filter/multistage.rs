@@ -0,0 +1,796 @@
+//! Multi-stage decimation
+//!
+//! Decimating by a large factor with a single FIR filter needs a very long
+//! filter, since its transition band is narrow relative to the input sample
+//! rate. It's much cheaper to decimate in stages:
+//!
+//! 1. A CIC filter does most of the decimation. It's cheap, but its passband
+//!    droops and it only suppresses the frequencies that alias into the
+//!    passband.
+//! 2. A FIR filter compensates the droop of the CIC and decimates by 2.
+//! 3. A half-band filter decimates by the last factor of 2 and removes
+//!    everything above the passband.
+//!
+//! [`design_decimator`] picks such an arrangement for a given input and output
+//! sample rate and designs the filters.
+
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    ops::{
+        Add,
+        Mul,
+    },
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use num_traits::Zero;
+use pin_project_lite::pin_project;
+
+use crate::{
+    buf::SampleBufMut,
+    filter::design::{
+        Band,
+        DesiredFrequencyResponse,
+        EstimateFilterLength,
+        FrequencyResponseAt,
+        IsSymmetric,
+        Lowpass,
+        Normalize,
+        Normalized,
+        Symmetry,
+        pm_remez::{
+            self,
+            pm_remez,
+        },
+    },
+    io::{
+        AsyncReadSamples,
+        FiniteStream,
+        GetSampleIndexMap,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        SampleIndexMap,
+        ScratchBuffer,
+        StreamLength,
+        combinators::{
+            GroupDelay,
+            Scanner,
+        },
+    },
+};
+
+/// Highest CIC order [`design_decimator`] uses.
+pub const MAX_CIC_ORDER: usize = 6;
+
+/// FIR filter that only computes the samples that are kept after decimation.
+///
+/// The first input sample produces an output, then every `factor`-th after
+/// it, like [`Decimate`][super::resampling::Decimate]. Like
+/// [`FirFilter`][super::fir::FirFilter], clones share the coefficients.
+#[derive(Clone, Debug)]
+pub struct DecimatingFir<S> {
+    coefficients: Arc<[f32]>,
+    history: VecDeque<S>,
+    factor: usize,
+    phase: usize,
+    num_flushed: usize,
+}
+
+impl<S> DecimatingFir<S> {
+    /// # Panics
+    ///
+    /// Panics if `coefficients` is empty or `factor` is 0.
+    #[inline]
+    pub fn new(coefficients: Vec<f32>, factor: usize) -> Self {
+        Self::shared(coefficients.into(), factor)
+    }
+
+    /// Creates a filter with coefficients that are shared with other filters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `coefficients` is empty or `factor` is 0.
+    pub fn shared(coefficients: Arc<[f32]>, factor: usize) -> Self {
+        assert!(!coefficients.is_empty(), "filter needs at least 1 tap");
+        assert!(factor > 0, "decimation factor must not be 0");
+        Self {
+            history: VecDeque::with_capacity(coefficients.len()),
+            coefficients,
+            factor,
+            phase: 0,
+            num_flushed: 0,
+        }
+    }
+
+    #[inline]
+    pub fn coefficients(&self) -> &[f32] {
+        &self.coefficients
+    }
+
+    #[inline]
+    pub fn decimation(&self) -> usize {
+        self.factor
+    }
+
+    /// Whether the next input sample produces an output.
+    fn advance(&mut self) -> bool {
+        let output = self.phase == 0;
+        self.phase += 1;
+        if self.phase == self.factor {
+            self.phase = 0;
+        }
+        output
+    }
+}
+
+impl<S> DecimatingFir<S>
+where
+    S: Copy + Zero + Mul<f32, Output = S>,
+{
+    fn output(&self, shift: usize) -> S {
+        // `shift` zeros were scanned after the history
+        self.history
+            .iter()
+            .zip(self.coefficients.iter().skip(shift))
+            .fold(S::zero(), |sum, (x, h)| sum + *x * *h)
+    }
+}
+
+impl<S> Scanner<S> for DecimatingFir<S>
+where
+    S: Copy + Zero + Add<Output = S> + Mul<f32, Output = S>,
+{
+    type Output = Option<S>;
+
+    fn scan(&mut self, sample: S) -> Self::Output {
+        if self.history.len() == self.coefficients.len() {
+            self.history.pop_back();
+        }
+        self.history.push_front(sample);
+
+        self.advance().then(|| self.output(0))
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        if self.num_flushed < self.coefficients.len() / 2 && !self.history.is_empty() {
+            self.num_flushed += 1;
+            Some(self.advance().then(|| self.output(self.num_flushed)))
+        }
+        else {
+            self.history.clear();
+            self.phase = 0;
+            self.num_flushed = 0;
+            None
+        }
+    }
+}
+
+impl<S> GroupDelay for DecimatingFir<S> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.5 * (self.coefficients.len() - 1) as f64
+    }
+}
+
+/// Passband of a decimator designed by [`design_decimator`].
+#[derive(Clone, Copy, Debug)]
+pub struct DecimatorSpec {
+    /// Highest frequency that is passed, in Hz. This must be below half the
+    /// output sample rate.
+    pub passband_end: f32,
+
+    /// Allowed ripple of the passband.
+    pub passband_tolerance: f32,
+
+    /// Largest amplitude with which anything outside of the passband may
+    /// alias into it.
+    pub stopband_tolerance: f32,
+}
+
+/// Error returned by [`design_decimator`].
+#[derive(Debug, thiserror::Error)]
+pub enum DecimatorDesignError {
+    #[error("Input sample rate {input} is not an integer multiple of output sample rate {output}")]
+    NotAnIntegerRatio { input: f32, output: f32 },
+
+    #[error("Output sample rate must be lower than the input sample rate")]
+    NoDecimation,
+
+    #[error("Passband end {passband_end} must be between 0 and half the output sample rate")]
+    InvalidPassband { passband_end: f32 },
+
+    #[error(
+        "A CIC filter of order {MAX_CIC_ORDER} can't suppress aliasing enough. The passband is too wide for the decimation."
+    )]
+    CicAliasing,
+
+    #[error("Failed to design filter")]
+    Design(#[from] pm_remez::Error),
+}
+
+/// How [`design_decimator`] splits up the decimation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Arrangement {
+    /// Decimation of the CIC filter. If this is 1, there is no CIC filter.
+    pub cic_ratio: usize,
+
+    /// Number of integrator/comb pairs of the CIC filter.
+    pub cic_order: usize,
+
+    /// Decimation of the compensating FIR filter, 1 or 2.
+    pub compensation_decimation: usize,
+
+    /// Whether a half-band filter decimates by another 2 at the end.
+    pub half_band: bool,
+}
+
+impl Arrangement {
+    /// Total decimation factor.
+    #[inline]
+    pub fn decimation(&self) -> usize {
+        self.cic_ratio * self.compensation_decimation * if self.half_band { 2 } else { 1 }
+    }
+}
+
+/// Chain of decimating FIR filters, e.g. designed by [`design_decimator`].
+#[derive(Clone, Debug)]
+pub struct MultistageDecimator<S> {
+    stages: Vec<DecimatingFir<S>>,
+    arrangement: Option<Arrangement>,
+}
+
+impl<S> MultistageDecimator<S> {
+    /// # Panics
+    ///
+    /// Panics if `stages` is empty.
+    pub fn new(stages: Vec<DecimatingFir<S>>) -> Self {
+        assert!(!stages.is_empty(), "decimator needs at least 1 stage");
+        Self {
+            stages,
+            arrangement: None,
+        }
+    }
+
+    #[inline]
+    pub fn stages(&self) -> &[DecimatingFir<S>] {
+        &self.stages
+    }
+
+    /// The arrangement this decimator was designed with, if it was designed
+    /// by [`design_decimator`].
+    #[inline]
+    pub fn arrangement(&self) -> Option<&Arrangement> {
+        self.arrangement.as_ref()
+    }
+
+    /// Total decimation factor.
+    pub fn decimation(&self) -> usize {
+        self.stages.iter().map(|stage| stage.decimation()).product()
+    }
+}
+
+impl<S> MultistageDecimator<S>
+where
+    S: Copy + Zero + Add<Output = S> + Mul<f32, Output = S>,
+{
+    fn scan_from(&mut self, first_stage: usize, sample: S) -> Option<S> {
+        self.stages[first_stage..]
+            .iter_mut()
+            .try_fold(sample, |sample, stage| stage.scan(sample))
+    }
+}
+
+impl<S> Scanner<S> for MultistageDecimator<S>
+where
+    S: Copy + Zero + Add<Output = S> + Mul<f32, Output = S>,
+{
+    type Output = Option<S>;
+
+    #[inline]
+    fn scan(&mut self, sample: S) -> Self::Output {
+        self.scan_from(0, sample)
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        // flush the stages one after another, so that every stage sees the tail
+        // of the stages before it
+        for i in 0..self.stages.len() {
+            if let Some(output) = self.stages[i].finish() {
+                return Some(output.and_then(|sample| self.scan_from(i + 1, sample)));
+            }
+        }
+        None
+    }
+}
+
+impl<S> GroupDelay for MultistageDecimator<S> {
+    /// Group delay in input samples.
+    fn group_delay(&self) -> f64 {
+        let mut delay = 0.0;
+        let mut decimation = 1;
+        for stage in &self.stages {
+            delay += stage.group_delay() * decimation as f64;
+            decimation *= stage.decimation();
+        }
+        delay
+    }
+}
+
+/// Designs a multi-stage decimator from `input_sample_rate` to
+/// `output_sample_rate`.
+///
+/// The decimation is split into a CIC filter, a FIR filter that compensates
+/// the droop of the CIC, and a final half-band filter, depending on the
+/// factors of 2 in the decimation. The CIC order is the lowest one that
+/// suppresses aliasing into the passband below the stopband tolerance. All
+/// stages have unity gain at DC.
+pub fn design_decimator<S>(
+    input_sample_rate: f32,
+    output_sample_rate: f32,
+    spec: DecimatorSpec,
+) -> Result<MultistageDecimator<S>, DecimatorDesignError> {
+    let ratio = input_sample_rate / output_sample_rate;
+    let decimation = ratio.round() as usize;
+    if decimation == 0 || (ratio - decimation as f32).abs() > 1e-3 * ratio {
+        return Err(DecimatorDesignError::NotAnIntegerRatio {
+            input: input_sample_rate,
+            output: output_sample_rate,
+        });
+    }
+    if decimation == 1 {
+        return Err(DecimatorDesignError::NoDecimation);
+    }
+    if !(spec.passband_end > 0.0 && spec.passband_end < 0.5 * output_sample_rate) {
+        return Err(DecimatorDesignError::InvalidPassband {
+            passband_end: spec.passband_end,
+        });
+    }
+
+    let half_band = decimation.is_multiple_of(4) && decimation >= 8;
+    let after_cic = decimation / if half_band { 2 } else { 1 };
+    let compensation_decimation = if after_cic.is_multiple_of(2) { 2 } else { 1 };
+    let cic_ratio = after_cic / compensation_decimation;
+
+    // the CIC decimates to this sample rate. everything within the passband
+    // around its multiples aliases into the passband.
+    let cic_sample_rate = input_sample_rate / cic_ratio as f32;
+    let passband_end = spec.passband_end / cic_sample_rate;
+    let cic_order = if cic_ratio > 1 {
+        (1..=MAX_CIC_ORDER)
+            .find(|order| {
+                cic_response(1.0 - passband_end, cic_ratio, *order) <= spec.stopband_tolerance
+            })
+            .ok_or(DecimatorDesignError::CicAliasing)?
+    }
+    else {
+        0
+    };
+
+    let mut stages = vec![];
+
+    if cic_ratio > 1 {
+        stages.push(DecimatingFir::new(
+            cic_coefficients(cic_ratio, cic_order),
+            cic_ratio,
+        ));
+    }
+
+    let compensation = Normalized(CicCompensation {
+        passband_end,
+        stopband_start: (1.0 / compensation_decimation as f32 - passband_end)
+            .min(0.5)
+            .max(passband_end),
+        cic_ratio,
+        cic_order,
+        passband_tolerance: spec.passband_tolerance,
+        stopband_tolerance: spec.stopband_tolerance,
+    });
+    let design = pm_remez(compensation, compensation.estimate_filter_length())?;
+    stages.push(DecimatingFir::new(
+        design.impulse_response,
+        compensation_decimation,
+    ));
+
+    if half_band {
+        // symmetric around a quarter of its input sample rate
+        let half_band_sample_rate = 2.0 * output_sample_rate;
+        let tolerance = spec.passband_tolerance.min(spec.stopband_tolerance);
+        let lowpass = Lowpass {
+            passband_end: spec.passband_end,
+            stopband_start: output_sample_rate - spec.passband_end,
+            passband_tolerance: tolerance,
+            stopband_tolerance: tolerance,
+        }
+        .normalize(half_band_sample_rate);
+        let length = lowpass.estimate_filter_length().max(3) | 1;
+        let design = pm_remez(lowpass, length)?;
+        stages.push(DecimatingFir::new(design.impulse_response, 2));
+    }
+
+    let arrangement = Arrangement {
+        cic_ratio,
+        cic_order,
+        compensation_decimation,
+        half_band,
+    };
+    debug_assert_eq!(arrangement.decimation(), decimation);
+
+    Ok(MultistageDecimator {
+        stages,
+        arrangement: Some(arrangement),
+    })
+}
+
+/// Impulse response of a CIC filter with differential delay 1, normalized to
+/// unity gain at DC.
+///
+/// Using this as a FIR filter is exact, unlike the recursive integrators of a
+/// CIC filter, which drift in floating point.
+fn cic_coefficients(ratio: usize, order: usize) -> Vec<f32> {
+    let mut coefficients = vec![1.0f64];
+    for _ in 0..order {
+        let mut next = vec![0.0; coefficients.len() + ratio - 1];
+        for (i, c) in coefficients.iter().enumerate() {
+            for n in &mut next[i..i + ratio] {
+                *n += c;
+            }
+        }
+        coefficients = next;
+    }
+
+    let gain = (ratio as f64).powi(order as i32);
+    coefficients
+        .into_iter()
+        .map(|c| (c / gain) as f32)
+        .collect()
+}
+
+/// Magnitude of a normalized CIC filter at `frequency`, in cycles per sample
+/// of its output.
+fn cic_response(frequency: f32, ratio: usize, order: usize) -> f32 {
+    if frequency == 0.0 {
+        return 1.0;
+    }
+    let ratio = ratio as f32;
+    ((PI * frequency).sin() / (ratio * (PI * frequency / ratio).sin()))
+        .abs()
+        .powi(order as i32)
+}
+
+/// Low-pass that is inverse to a CIC filter in the passband.
+///
+/// Frequencies are relative to the output sample rate of the CIC filter.
+#[derive(Clone, Copy, Debug)]
+struct CicCompensation {
+    passband_end: f32,
+    stopband_start: f32,
+    cic_ratio: usize,
+    cic_order: usize,
+    passband_tolerance: f32,
+    stopband_tolerance: f32,
+}
+
+impl DesiredFrequencyResponse for CicCompensation {
+    fn defined_on(&self) -> impl IntoIterator<Item = Band> {
+        let stopband = (self.stopband_start < 0.5).then_some(Band {
+            start: self.stopband_start,
+            end: 0.5,
+        });
+        [Band {
+            start: 0.0,
+            end: self.passband_end,
+        }]
+        .into_iter()
+        .chain(stopband)
+    }
+
+    fn frequency_response_at(&self, frequency: f32) -> Option<FrequencyResponseAt> {
+        if frequency <= self.passband_end {
+            let droop = cic_response(frequency, self.cic_ratio, self.cic_order);
+            Some(FrequencyResponseAt {
+                amplitude: 1.0 / droop,
+                // the ripple is relative to the CIC output
+                tolerance: self.passband_tolerance / droop,
+            })
+        }
+        else if frequency >= self.stopband_start && self.stopband_start < 0.5 {
+            Some(FrequencyResponseAt {
+                amplitude: 0.0,
+                tolerance: self.stopband_tolerance,
+            })
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl IsSymmetric for CicCompensation {
+    fn symmetry(&self) -> Symmetry {
+        Symmetry::Positive
+    }
+}
+
+impl EstimateFilterLength for Normalized<CicCompensation> {
+    fn estimate_filter_length(&self) -> usize {
+        // without a stopband, the response only needs to be defined up to the
+        // passband edge
+        let length = Lowpass {
+            passband_end: self.0.passband_end,
+            stopband_start: self.0.stopband_start.min(0.5),
+            passband_tolerance: self.0.passband_tolerance,
+            stopband_tolerance: self.0.stopband_tolerance,
+        }
+        .assert_normalized()
+        .estimate_filter_length();
+        length.max(3) | 1
+    }
+}
+
+pin_project! {
+    /// Stream decimated by a [`MultistageDecimator`].
+    #[derive(Clone, Debug)]
+    pub struct DecimateMultistage<R, S> {
+        #[pin]
+        inner: R,
+        decimator: MultistageDecimator<S>,
+        intermediate_buffer: ScratchBuffer<S>,
+    }
+}
+
+impl<R, S> DecimateMultistage<R, S> {
+    #[inline]
+    pub fn new(inner: R, decimator: MultistageDecimator<S>) -> Self {
+        Self {
+            inner,
+            decimator,
+            intermediate_buffer: ScratchBuffer::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn decimator(&self) -> &MultistageDecimator<S> {
+        &self.decimator
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for DecimateMultistage<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: Copy + Zero + Add<Output = S> + Mul<f32, Output = S>,
+{
+    type Error = R::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        if buffer.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            let read_length = buffer.remaining() * this.decimator.decimation();
+            let read_length = this.intermediate_buffer.reserve(read_length);
+            let mut read_buf = ReadBuf::uninit(&mut this.intermediate_buffer.buffer[..read_length]);
+
+            ready!(this.inner.as_mut().poll_read_samples(cx, &mut read_buf))?;
+
+            let filled = read_buf.filled().len();
+            if filled == 0 {
+                // end of stream
+                while buffer.has_remaining_mut() {
+                    let Some(output) = this.decimator.finish()
+                    else {
+                        break;
+                    };
+                    if let Some(sample) = output {
+                        buffer.put_sample(sample);
+                    }
+                }
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut num_samples = 0;
+            for i in 0..filled {
+                let sample = unsafe { this.intermediate_buffer.buffer[i].assume_init_read() };
+                if let Some(sample) = this.decimator.scan(sample) {
+                    buffer.put_sample(sample);
+                    num_samples += 1;
+                }
+            }
+
+            // an empty read would look like the end of the stream, so keep reading
+            // until we have an output sample.
+            if num_samples > 0 {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<R, S> GetSampleRate for DecimateMultistage<R, S>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate() / self.decimator.decimation() as f32
+    }
+}
+
+impl<R, S> GetSampleIndexMap for DecimateMultistage<R, S>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner
+            .sample_index_map()
+            .then(SampleIndexMap::delay(self.decimator.group_delay()))
+            .then(SampleIndexMap::decimation(self.decimator.decimation()))
+    }
+}
+
+impl<R, S> StreamLength for DecimateMultistage<R, S>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        let decimation = self.decimator.decimation();
+        self.inner
+            .remaining()
+            .map(|num_samples| num_samples.div_ceil(decimation))
+    }
+}
+
+impl<R, S> FiniteStream for DecimateMultistage<R, S> where R: FiniteStream {}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use super::{
+        Arrangement,
+        DecimatorDesignError,
+        DecimatorSpec,
+        MultistageDecimator,
+        cic_coefficients,
+        design_decimator,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+            GetSampleRate,
+            combinators::Scanner,
+        },
+        source::{
+            ComplexSinusoid,
+            SignalGenerator,
+        },
+    };
+
+    const SPEC: DecimatorSpec = DecimatorSpec {
+        passband_end: 15_000.0,
+        passband_tolerance: 0.01,
+        stopband_tolerance: 0.001,
+    };
+
+    /// Amplitude of a tone at `frequency` after decimating from 2.4 MHz to
+    /// 48 kHz, once the filters settled.
+    fn amplitude_after(decimator: &mut MultistageDecimator<Complex<f32>>, frequency: f32) -> f32 {
+        let mut tone = ComplexSinusoid::new(frequency, 2_400_000.0);
+        let output = std::iter::repeat_with(|| tone.next())
+            .take(120_000)
+            .filter_map(|sample| decimator.scan(sample))
+            .collect::<Vec<_>>();
+        assert_eq!(output.len(), 120_000 / 50);
+
+        let settled = &output[output.len() / 2..];
+        settled
+            .iter()
+            .map(|sample| sample.norm())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn cic_coefficients_have_unity_gain() {
+        let coefficients = cic_coefficients(4, 3);
+        assert_eq!(coefficients.len(), 3 * 3 + 1);
+        assert!((coefficients.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert_eq!(coefficients[0], 1.0 / 64.0);
+    }
+
+    #[test]
+    fn it_arranges_the_stages() {
+        let decimator = design_decimator::<Complex<f32>>(2_400_000.0, 48_000.0, SPEC).unwrap();
+        assert_eq!(
+            decimator.arrangement(),
+            Some(&Arrangement {
+                cic_ratio: 25,
+                cic_order: 5,
+                compensation_decimation: 2,
+                half_band: false,
+            })
+        );
+        assert_eq!(decimator.decimation(), 50);
+
+        let decimator = design_decimator::<f32>(2_048_000.0, 32_000.0, SPEC).unwrap();
+        let arrangement = decimator.arrangement().unwrap();
+        assert_eq!(arrangement.cic_ratio, 16);
+        assert!(arrangement.half_band);
+        assert_eq!(decimator.stages().len(), 3);
+
+        assert!(matches!(
+            design_decimator::<f32>(2_048_000.0, 48_000.0, SPEC),
+            Err(DecimatorDesignError::NotAnIntegerRatio { .. })
+        ));
+        assert!(matches!(
+            design_decimator::<f32>(
+                2_400_000.0,
+                48_000.0,
+                DecimatorSpec {
+                    passband_end: 30_000.0,
+                    ..SPEC
+                }
+            ),
+            Err(DecimatorDesignError::InvalidPassband { .. })
+        ));
+    }
+
+    #[test]
+    fn it_passes_the_passband_and_suppresses_aliases() {
+        let mut decimator = design_decimator(2_400_000.0, 48_000.0, SPEC).unwrap();
+
+        // the droop of the CIC is compensated
+        for frequency in [0.0, 5_000.0, -12_000.0, 15_000.0] {
+            let amplitude = amplitude_after(&mut decimator, frequency);
+            assert!(
+                (amplitude - 1.0).abs() < 0.02,
+                "{frequency} Hz: {amplitude}"
+            );
+        }
+
+        // these would alias to 5 kHz
+        for frequency in [53_000.0, 101_000.0, -91_000.0] {
+            let amplitude = amplitude_after(&mut decimator, frequency);
+            assert!(amplitude < 2e-3, "{frequency} Hz: {amplitude}");
+        }
+    }
+
+    #[test]
+    fn it_decimates_a_stream() {
+        let decimator = design_decimator::<f32>(2_048_000.0, 32_000.0, SPEC).unwrap();
+        let input = vec![1.0f32; 64 * 100];
+
+        let mut stream = Cursor::new(input)
+            .with_sample_rate(2_048_000.0)
+            .decimate_multistage(decimator);
+        assert_eq!(stream.sample_rate(), 32_000.0);
+
+        let mut output = vec![];
+        stream
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+
+        // the tail of the filters is flushed at the end of the stream
+        assert!(output.len() > 100);
+        assert!((output[90] - 1.0).abs() < 0.01, "{}", output[90]);
+    }
+}
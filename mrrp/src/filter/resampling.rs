@@ -1,12 +1,17 @@
 use std::{
+    collections::VecDeque,
     ops::{
+        Add,
         AddAssign,
         Div,
+        Mul,
     },
     pin::Pin,
+    sync::Arc,
     task::{
         Context,
         Poll,
+        ready,
     },
 };
 
@@ -18,12 +23,26 @@ use pin_project_lite::pin_project;
 
 use crate::{
     buf::SampleBufMut,
+    filter::design::{
+        EstimateFilterLength,
+        Lowpass,
+        Normalize,
+        pm_remez::{
+            self,
+            pm_remez,
+        },
+    },
     io::{
         AsyncReadSamples,
+        FiniteStream,
+        GetSampleIndexMap,
         GetSampleRate,
         ReadBuf,
         Remaining,
+        SampleIndexMap,
+        ScratchBuffer,
         StreamLength,
+        combinators::GroupDelay,
     },
     sample::Sample,
 };
@@ -112,6 +131,18 @@ where
     }
 }
 
+impl<R> GetSampleIndexMap for Decimate<R>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.input
+            .sample_index_map()
+            .then(SampleIndexMap::decimation(self.factor))
+    }
+}
+
 impl<R> StreamLength for Decimate<R>
 where
     R: StreamLength,
@@ -223,6 +254,18 @@ where
     }
 }
 
+impl<R> GetSampleIndexMap for Interpolate<R>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.input
+            .sample_index_map()
+            .then(SampleIndexMap::interpolation(self.factor))
+    }
+}
+
 impl<R> StreamLength for Interpolate<R>
 where
     R: StreamLength,
@@ -273,6 +316,21 @@ where
     }
 }
 
+impl<T, S> GetSampleIndexMap for AverageDecimate<T, S>
+where
+    T: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        // an output sample is the average of `decimate` input samples, so it
+        // corresponds to the input sample in the middle.
+        self.input
+            .sample_index_map()
+            .then(SampleIndexMap::delay(-0.5 * (self.decimate - 1) as f64))
+            .then(SampleIndexMap::decimation(self.decimate))
+    }
+}
+
 impl<T, S> StreamLength for AverageDecimate<T, S>
 where
     T: StreamLength,
@@ -347,3 +405,395 @@ where
         }
     }
 }
+
+/// Highest interpolation factor that [`resampling_ratio`] picks.
+pub const MAX_INTERPOLATION: usize = 1024;
+
+/// Share of the lower Nyquist frequency that a [`PolyphaseResampler`] passes.
+const RESAMPLER_PASSBAND: f32 = 0.8;
+
+/// Approximates `output_sample_rate / input_sample_rate` by a fraction
+/// `interpolation / decimation` in lowest terms, with an interpolation factor
+/// of at most [`MAX_INTERPOLATION`].
+///
+/// Sample rates that are integers, e.g. 2.4 MHz and 48 kHz, are usually
+/// matched exactly.
+pub fn resampling_ratio(input_sample_rate: f32, output_sample_rate: f32) -> (usize, usize) {
+    assert!(input_sample_rate > 0.0 && output_sample_rate > 0.0);
+
+    // the last convergent of the continued fraction that fits
+    let ratio = f64::from(output_sample_rate) / f64::from(input_sample_rate);
+    let (mut numerator, mut previous_numerator) = (1, 0);
+    let (mut denominator, mut previous_denominator) = (0, 1);
+    let mut x = ratio;
+    loop {
+        let a = x.floor() as usize;
+        let next_numerator = a * numerator + previous_numerator;
+        let next_denominator = a * denominator + previous_denominator;
+        if next_numerator > MAX_INTERPOLATION {
+            break;
+        }
+        (previous_numerator, numerator) = (numerator, next_numerator);
+        (previous_denominator, denominator) = (denominator, next_denominator);
+
+        let fraction = x - x.floor();
+        if fraction < 1e-9 {
+            break;
+        }
+        x = 1.0 / fraction;
+    }
+
+    if denominator == 0 {
+        // the ratio is larger than any interpolation factor we allow
+        (MAX_INTERPOLATION, 1)
+    }
+    else if numerator == 0 {
+        // the ratio is too small to interpolate at all
+        (1, (1.0 / ratio).round() as usize)
+    }
+    else {
+        (numerator, denominator)
+    }
+}
+
+/// Resampler by a rational factor `interpolation / decimation`.
+///
+/// Conceptually, this interpolates by inserting zeros, low-pass filters and
+/// then decimates. The polyphase filter bank only computes the samples that
+/// are kept. The low-pass is designed with [`pm_remez`] to pass 80% of the
+/// lower of the two Nyquist frequencies.
+///
+/// An input sample produces zero or more output samples.
+#[derive(Clone, Debug)]
+pub struct PolyphaseResampler<S> {
+    /// Coefficients of each phase, one phase after the other.
+    bank: Arc<[f32]>,
+    taps_per_phase: usize,
+    interpolation: usize,
+    decimation: usize,
+    num_coefficients: usize,
+    history: VecDeque<S>,
+    /// Position of the next output sample relative to the last input sample,
+    /// at the interpolated sample rate.
+    phase: usize,
+}
+
+impl<S> PolyphaseResampler<S> {
+    /// # Panics
+    ///
+    /// Panics if either factor is 0.
+    pub fn new(interpolation: usize, decimation: usize) -> Result<Self, pm_remez::Error> {
+        assert!(
+            interpolation > 0 && decimation > 0,
+            "resampling factors must not be 0"
+        );
+        let divisor = gcd(interpolation, decimation);
+        let interpolation = interpolation / divisor;
+        let decimation = decimation / divisor;
+
+        let coefficients = if interpolation == 1 && decimation == 1 {
+            vec![1.0]
+        }
+        else {
+            // relative to the interpolated sample rate
+            let nyquist = 0.5 / interpolation.max(decimation) as f32;
+            let lowpass = Lowpass {
+                passband_end: RESAMPLER_PASSBAND * nyquist,
+                stopband_start: nyquist,
+                passband_tolerance: 0.01,
+                stopband_tolerance: 0.001,
+            }
+            .assert_normalized();
+            let length = lowpass.estimate_filter_length().max(3);
+            let design = pm_remez(lowpass, length)?;
+
+            // the zeros inserted by interpolation scale the signal down
+            design
+                .impulse_response
+                .into_iter()
+                .map(|c| c * interpolation as f32)
+                .collect()
+        };
+
+        let num_coefficients = coefficients.len();
+        let taps_per_phase = num_coefficients.div_ceil(interpolation);
+        let mut bank = vec![0.0; taps_per_phase * interpolation];
+        for (i, coefficient) in coefficients.into_iter().enumerate() {
+            bank[(i % interpolation) * taps_per_phase + i / interpolation] = coefficient;
+        }
+
+        Ok(Self {
+            bank: bank.into(),
+            taps_per_phase,
+            interpolation,
+            decimation,
+            num_coefficients,
+            history: VecDeque::with_capacity(taps_per_phase),
+            phase: 0,
+        })
+    }
+
+    /// Interpolation factor, reduced to lowest terms.
+    #[inline]
+    pub fn interpolation(&self) -> usize {
+        self.interpolation
+    }
+
+    /// Decimation factor, reduced to lowest terms.
+    #[inline]
+    pub fn decimation(&self) -> usize {
+        self.decimation
+    }
+}
+
+impl<S> PolyphaseResampler<S>
+where
+    S: Copy + Zero + Add<Output = S> + Mul<f32, Output = S>,
+{
+    /// Scans an input sample, and passes the output samples it completes to
+    /// `output`.
+    pub fn push(&mut self, sample: S, mut output: impl FnMut(S)) {
+        if self.history.len() == self.taps_per_phase {
+            self.history.pop_back();
+        }
+        self.history.push_front(sample);
+
+        while self.phase < self.interpolation {
+            let coefficients = &self.bank[self.phase * self.taps_per_phase..];
+            output(
+                self.history
+                    .iter()
+                    .zip(coefficients)
+                    .fold(S::zero(), |sum, (x, h)| sum + *x * *h),
+            );
+            self.phase += self.decimation;
+        }
+        self.phase -= self.interpolation;
+    }
+
+    /// Flushes the samples that are delayed by the filter, and resets the
+    /// resampler.
+    pub fn finish(&mut self, mut output: impl FnMut(S)) {
+        if !self.history.is_empty() {
+            let num_flushed = self.group_delay().ceil() as usize;
+            for _ in 0..num_flushed {
+                self.push(S::zero(), &mut output);
+            }
+        }
+        self.history.clear();
+        self.phase = 0;
+    }
+}
+
+impl<S> GroupDelay for PolyphaseResampler<S> {
+    /// Group delay in input samples.
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.5 * (self.num_coefficients - 1) as f64 / self.interpolation as f64
+    }
+}
+
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+pin_project! {
+    /// Stream resampled by a [`PolyphaseResampler`].
+    #[derive(Clone, Debug)]
+    pub struct Resample<R, S> {
+        #[pin]
+        inner: R,
+        resampler: PolyphaseResampler<S>,
+        intermediate_buffer: ScratchBuffer<S>,
+        // output samples that didn't fit into the read buffer
+        pending: VecDeque<S>,
+    }
+}
+
+impl<R, S> Resample<R, S> {
+    #[inline]
+    pub fn new(inner: R, resampler: PolyphaseResampler<S>) -> Self {
+        Self {
+            inner,
+            resampler,
+            intermediate_buffer: ScratchBuffer::new(0),
+            pending: VecDeque::new(),
+        }
+    }
+
+    #[inline]
+    pub fn resampler(&self) -> &PolyphaseResampler<S> {
+        &self.resampler
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for Resample<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: Copy + Zero + Add<Output = S> + Mul<f32, Output = S>,
+{
+    type Error = R::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        if buffer.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            let mut num_samples = 0;
+            while buffer.has_remaining_mut()
+                && let Some(sample) = this.pending.pop_front()
+            {
+                buffer.put_sample(sample);
+                num_samples += 1;
+            }
+            // an empty read would look like the end of the stream, so keep reading
+            // until we have an output sample.
+            if num_samples > 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let read_length = (buffer.remaining() * this.resampler.decimation())
+                .div_ceil(this.resampler.interpolation());
+            let read_length = this.intermediate_buffer.reserve(read_length);
+            let mut read_buf = ReadBuf::uninit(&mut this.intermediate_buffer.buffer[..read_length]);
+
+            ready!(this.inner.as_mut().poll_read_samples(cx, &mut read_buf))?;
+
+            let filled = read_buf.filled().len();
+            let pending = &mut *this.pending;
+            if filled == 0 {
+                // end of stream
+                this.resampler.finish(|sample| pending.push_back(sample));
+                if pending.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+                continue;
+            }
+
+            for i in 0..filled {
+                let sample = unsafe { this.intermediate_buffer.buffer[i].assume_init_read() };
+                this.resampler
+                    .push(sample, |sample| pending.push_back(sample));
+            }
+        }
+    }
+}
+
+impl<R, S> GetSampleRate for Resample<R, S>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate() * self.resampler.interpolation() as f32
+            / self.resampler.decimation() as f32
+    }
+}
+
+impl<R, S> GetSampleIndexMap for Resample<R, S>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner
+            .sample_index_map()
+            .then(SampleIndexMap::delay(self.resampler.group_delay()))
+            .then(SampleIndexMap::interpolation(
+                self.resampler.interpolation(),
+            ))
+            .then(SampleIndexMap::decimation(self.resampler.decimation()))
+    }
+}
+
+impl<R, S> StreamLength for Resample<R, S>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        let interpolation = self.resampler.interpolation();
+        let decimation = self.resampler.decimation();
+        self.inner.remaining().map(|num_samples| {
+            self.pending.len() + (num_samples * interpolation).div_ceil(decimation)
+        })
+    }
+}
+
+impl<R, S> FiniteStream for Resample<R, S> where R: FiniteStream {}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use super::{
+        PolyphaseResampler,
+        resampling_ratio,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+            GetSampleRate,
+        },
+        source::{
+            ComplexSinusoid,
+            SignalGenerator,
+        },
+    };
+
+    #[test]
+    fn it_finds_the_resampling_ratio() {
+        assert_eq!(resampling_ratio(2_400_000.0, 48_000.0), (1, 50));
+        assert_eq!(resampling_ratio(44_100.0, 48_000.0), (160, 147));
+        assert_eq!(resampling_ratio(48_000.0, 32_000.0), (2, 3));
+        assert_eq!(resampling_ratio(1_000.0, 3_000.0), (3, 1));
+    }
+
+    #[test]
+    fn it_reduces_the_factors() {
+        let resampler = PolyphaseResampler::<f32>::new(4, 6).unwrap();
+        assert_eq!(resampler.interpolation(), 2);
+        assert_eq!(resampler.decimation(), 3);
+    }
+
+    #[test]
+    fn it_resamples_a_tone() {
+        let mut tone = ComplexSinusoid::new(1_000.0, 48_000.0);
+        let input = std::iter::repeat_with(|| tone.next())
+            .take(6000)
+            .collect::<Vec<Complex<f32>>>();
+
+        let mut stream = Cursor::new(input)
+            .with_sample_rate(48_000.0)
+            .resample_to(32_000.0);
+        assert_eq!(stream.sample_rate(), 32_000.0);
+
+        let mut output = vec![];
+        stream
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert!((4000..4100).contains(&output.len()));
+
+        // once the filter settled, the tone advances by its frequency relative
+        // to the new sample rate
+        let expected = std::f32::consts::TAU * 1_000.0 / 32_000.0;
+        for pair in output[200..3800].windows(2) {
+            assert!((pair[1].norm() - 1.0).abs() < 0.02);
+            assert!(((pair[1] * pair[0].conj()).arg() - expected).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Typed frequencies.
+//!
+//! Frequencies that name a point on the dial (center frequencies, bookmarks,
+//! band edges) are whole numbers of Hz. Keeping them as integers makes them
+//! exact, e.g. comparisons and lookups don't depend on float rounding.
+
+use std::{
+    fmt::{
+        self,
+        Display,
+    },
+    ops::{
+        Add,
+        AddAssign,
+        Div,
+        Mul,
+        Sub,
+        SubAssign,
+    },
+    str::FromStr,
+};
+
+/// A frequency in Hz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Frequency(u64);
+
+impl Frequency {
+    pub const ZERO: Self = Self(0);
+
+    #[inline]
+    pub const fn from_hz(hz: u64) -> Self {
+        Self(hz)
+    }
+
+    #[inline]
+    pub const fn from_khz(khz: u64) -> Self {
+        Self(khz * 1_000)
+    }
+
+    #[inline]
+    pub const fn from_mhz(mhz: u64) -> Self {
+        Self(mhz * 1_000_000)
+    }
+
+    /// Rounds to the nearest Hz. Negative frequencies become 0.
+    #[inline]
+    pub fn from_hz_f64(hz: f64) -> Self {
+        Self(hz.round() as u64)
+    }
+
+    #[inline]
+    pub const fn as_hz(&self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    pub fn as_f32(&self) -> f32 {
+        self.0 as f32
+    }
+
+    #[inline]
+    pub fn as_f64(&self) -> f64 {
+        self.0 as f64
+    }
+
+    #[inline]
+    pub const fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    #[inline]
+    pub const fn checked_sub(self, other: Self) -> Option<Self> {
+        match self.0.checked_sub(other.0) {
+            Some(hz) => Some(Self(hz)),
+            None => None,
+        }
+    }
+
+    /// Signed offset from `other` to `self` in Hz.
+    #[inline]
+    pub fn offset_from(self, other: Self) -> i64 {
+        self.0 as i64 - other.0 as i64
+    }
+}
+
+impl From<u32> for Frequency {
+    #[inline]
+    fn from(hz: u32) -> Self {
+        Self(hz.into())
+    }
+}
+
+impl From<u64> for Frequency {
+    #[inline]
+    fn from(hz: u64) -> Self {
+        Self(hz)
+    }
+}
+
+impl From<Frequency> for u64 {
+    #[inline]
+    fn from(frequency: Frequency) -> Self {
+        frequency.0
+    }
+}
+
+impl TryFrom<Frequency> for u32 {
+    type Error = std::num::TryFromIntError;
+
+    #[inline]
+    fn try_from(frequency: Frequency) -> Result<Self, Self::Error> {
+        frequency.0.try_into()
+    }
+}
+
+impl Add for Frequency {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Frequency {
+    #[inline]
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Frequency {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Frequency {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Mul<u64> for Frequency {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: u64) -> Self {
+        Self(self.0 * rhs)
+    }
+}
+
+impl Div<u64> for Frequency {
+    type Output = Self;
+
+    #[inline]
+    fn div(self, rhs: u64) -> Self {
+        Self(self.0 / rhs)
+    }
+}
+
+/// Formats with the largest SI prefix that keeps the integer part non-zero,
+/// and without trailing zeros, e.g. `7.074 MHz`.
+impl Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (divisor, unit) = match self.0 {
+            1_000_000_000.. => (1_000_000_000, "GHz"),
+            1_000_000.. => (1_000_000, "MHz"),
+            1_000.. => (1_000, "kHz"),
+            _ => (1, "Hz"),
+        };
+
+        let integer = self.0 / divisor;
+        let fraction = self.0 % divisor;
+        if fraction == 0 {
+            write!(f, "{integer} {unit}")
+        }
+        else {
+            let digits = divisor.ilog10() as usize;
+            let fraction = format!("{fraction:0digits$}");
+            write!(f, "{integer}.{} {unit}", fraction.trim_end_matches('0'))
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid frequency: {input}")]
+pub struct ParseFrequencyError {
+    input: String,
+}
+
+/// Parses a number with an optional unit, e.g. `7074000`, `7.074 MHz` or
+/// `7074k`. Without a unit the number is in Hz.
+impl FromStr for Frequency {
+    type Err = ParseFrequencyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            ParseFrequencyError {
+                input: s.to_owned(),
+            }
+        };
+
+        let trimmed = s.trim();
+        let split = trimmed
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(split);
+
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "hz" => 1,
+            "k" | "khz" => 1_000,
+            "m" | "mhz" => 1_000_000,
+            "g" | "ghz" => 1_000_000_000,
+            _ => return Err(error()),
+        };
+
+        // parse integer and fraction separately, so that e.g. 7.074 MHz is exact
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return Err(error());
+        }
+        let integer = if integer.is_empty() {
+            0
+        }
+        else {
+            integer.parse::<u64>().map_err(|_| error())?
+        };
+
+        let mut hz = integer.checked_mul(multiplier).ok_or_else(error)?;
+        let mut scale = multiplier;
+        for digit in fraction.chars() {
+            let digit = digit.to_digit(10).ok_or_else(error)?;
+            scale /= 10;
+            if scale == 0 {
+                // sub-Hz digits are ignored
+                break;
+            }
+            hz += u64::from(digit) * scale;
+        }
+
+        Ok(Self(hz))
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use std::fmt;
+
+    use serde::{
+        Deserialize,
+        Deserializer,
+        Serialize,
+        Serializer,
+        de::{
+            Error,
+            Visitor,
+        },
+    };
+
+    use super::Frequency;
+
+    /// Serializes as an integer in Hz.
+    impl Serialize for Frequency {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u64(self.0)
+        }
+    }
+
+    /// Deserializes from an integer or float in Hz, which is compatible with
+    /// plain `u32` fields, or from a string like `7.074 MHz`.
+    impl<'de> Deserialize<'de> for Frequency {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(FrequencyVisitor)
+        }
+    }
+
+    struct FrequencyVisitor;
+
+    impl<'de> Visitor<'de> for FrequencyVisitor {
+        type Value = Frequency;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "a frequency in Hz, or a string with a unit")
+        }
+
+        fn visit_u64<E: Error>(self, value: u64) -> Result<Frequency, E> {
+            Ok(Frequency(value))
+        }
+
+        fn visit_i64<E: Error>(self, value: i64) -> Result<Frequency, E> {
+            u64::try_from(value)
+                .map(Frequency)
+                .map_err(|_| E::custom("negative frequency"))
+        }
+
+        fn visit_f64<E: Error>(self, value: f64) -> Result<Frequency, E> {
+            if value >= 0.0 {
+                Ok(Frequency::from_hz_f64(value))
+            }
+            else {
+                Err(E::custom("negative frequency"))
+            }
+        }
+
+        fn visit_str<E: Error>(self, value: &str) -> Result<Frequency, E> {
+            value.parse().map_err(E::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Frequency;
+
+    #[test]
+    fn it_formats_with_si_prefixes() {
+        assert_eq!(Frequency::from_hz(440).to_string(), "440 Hz");
+        assert_eq!(Frequency::from_hz(7_074_000).to_string(), "7.074 MHz");
+        assert_eq!(Frequency::from_hz(14_000_000).to_string(), "14 MHz");
+        assert_eq!(
+            Frequency::from_hz(1_090_000_001).to_string(),
+            "1.090000001 GHz"
+        );
+    }
+
+    #[test]
+    fn it_parses_what_it_formats() {
+        for hz in [0, 440, 7_074_000, 14_000_000, 1_090_000_001, 2_462_100_000] {
+            let frequency = Frequency::from_hz(hz);
+            assert_eq!(
+                frequency.to_string().parse::<Frequency>().unwrap(),
+                frequency
+            );
+        }
+
+        assert_eq!("7074000".parse::<Frequency>().unwrap().as_hz(), 7_074_000);
+        assert_eq!("7074k".parse::<Frequency>().unwrap().as_hz(), 7_074_000);
+        assert_eq!(".5 kHz".parse::<Frequency>().unwrap().as_hz(), 500);
+        assert!("7 parsecs".parse::<Frequency>().is_err());
+        assert!("MHz".parse::<Frequency>().is_err());
+    }
+}
@@ -0,0 +1,656 @@
+//! Bridges between sample streams and byte streams.
+//!
+//! The samples are read and written as their in-memory representation, i.e.
+//! a [`Pod`] sample type with native endianness. Pick the sample type that
+//! matches the other side, e.g. `Complex<u8>` for `rtl_sdr` output, and
+//! [`convert`][super::AsyncReadSamplesExt::convert] to it if necessary.
+//!
+//! The [`std::io`] implementations block the current thread until the stream
+//! is ready, so they must not be used from within an async task.
+
+use std::{
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+        Wake,
+        Waker,
+        ready,
+    },
+    thread::{
+        self,
+        Thread,
+    },
+};
+
+use bytemuck::Pod;
+use pin_project_lite::pin_project;
+use tokio::io::{
+    AsyncRead,
+    AsyncWrite,
+};
+
+use crate::{
+    buf::{
+        DEFAULT_CHUNK_SIZE,
+        SampleBufMut,
+    },
+    io::{
+        AsyncReadSamples,
+        AsyncWriteSamples,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        StreamLength,
+    },
+};
+
+/// Wakes a thread that is blocked in [`block_on`].
+#[derive(Debug)]
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls until `poll` is ready, parking the thread in between.
+fn block_on<T>(mut poll: impl FnMut(&mut Context<'_>) -> Poll<T>) -> T {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+pin_project! {
+    /// Reads the samples of a stream as bytes.
+    ///
+    /// This implements [`Read`] and tokio's [`AsyncRead`], e.g. to pipe IQ
+    /// samples into another process.
+    #[derive(Debug)]
+    pub struct ByteReader<R, S> {
+        #[pin]
+        inner: R,
+        buffer: Vec<S>,
+        // number of bytes of `buffer` that were read
+        position: usize,
+    }
+}
+
+impl<R, S> ByteReader<R, S> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buffer: vec![],
+            position: 0,
+        }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    pub fn poll_read_bytes(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bytes: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    where
+        R: AsyncReadSamples<S>,
+        R::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        S: Pod,
+    {
+        let this = self.project();
+        if bytes.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        if *this.position == this.buffer.len() * size_of::<S>() {
+            let num_samples = bytes.len().div_ceil(size_of::<S>()).min(DEFAULT_CHUNK_SIZE);
+            this.buffer.clear();
+            this.buffer.resize(num_samples, S::zeroed());
+            *this.position = 0;
+
+            let mut read_buf = ReadBuf::new(&mut this.buffer[..]);
+            let result = this.inner.poll_read_samples(cx, &mut read_buf);
+            let num_samples = read_buf.filled().len();
+            this.buffer.truncate(num_samples);
+            ready!(result).map_err(io::Error::other)?;
+
+            if num_samples == 0 {
+                return Poll::Ready(Ok(0));
+            }
+        }
+
+        let buffered: &[u8] = bytemuck::cast_slice(this.buffer.as_slice());
+        let num_bytes = bytes.len().min(buffered.len() - *this.position);
+        bytes[..num_bytes].copy_from_slice(&buffered[*this.position..][..num_bytes]);
+        *this.position += num_bytes;
+
+        Poll::Ready(Ok(num_bytes))
+    }
+}
+
+impl<R, S> GetSampleRate for ByteReader<R, S>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate()
+    }
+}
+
+impl<R, S> Read for ByteReader<R, S>
+where
+    R: AsyncReadSamples<S> + Unpin,
+    R::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S: Pod,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        block_on(|cx| Pin::new(&mut *self).poll_read_bytes(cx, buf))
+    }
+}
+
+impl<R, S> AsyncRead for ByteReader<R, S>
+where
+    R: AsyncReadSamples<S>,
+    R::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S: Pod,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let num_bytes = ready!(self.poll_read_bytes(cx, buf.initialize_unfilled()))?;
+        buf.advance(num_bytes);
+        Poll::Ready(Ok(()))
+    }
+}
+
+pin_project! {
+    /// Writes bytes to a sink as samples.
+    ///
+    /// This implements [`Write`] and tokio's [`AsyncWrite`]. Bytes that don't
+    /// make up a whole sample are kept until the next write, and dropped if
+    /// the writer is closed before that.
+    #[derive(Debug)]
+    pub struct ByteWriter<W, S> {
+        #[pin]
+        inner: W,
+        // bytes of an incomplete sample
+        partial: Vec<u8>,
+        pending: Vec<S>,
+        position: usize,
+    }
+}
+
+impl<W, S> ByteWriter<W, S> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            partial: vec![],
+            pending: vec![],
+            position: 0,
+        }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Writes the decoded samples to the inner sink.
+    fn poll_drain(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>
+    where
+        W: AsyncWriteSamples<S>,
+        W::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut this = self.project();
+
+        while *this.position < this.pending.len() {
+            let num_written = ready!(
+                this.inner
+                    .as_mut()
+                    .poll_write_samples(cx, &this.pending[*this.position..])
+            )
+            .map_err(io::Error::other)?;
+            if num_written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            *this.position += num_written;
+        }
+
+        this.pending.clear();
+        *this.position = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    pub fn poll_write_bytes(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bytes: &[u8],
+    ) -> Poll<io::Result<usize>>
+    where
+        W: AsyncWriteSamples<S>,
+        W::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        S: Pod,
+    {
+        ready!(self.as_mut().poll_drain(cx))?;
+
+        let sample_size = size_of::<S>();
+        let bytes = &bytes[..bytes.len().min(DEFAULT_CHUNK_SIZE * sample_size)];
+        let this = self.as_mut().project();
+
+        // complete the sample that the last write started
+        let mut rest = bytes;
+        if !this.partial.is_empty() {
+            let num_bytes = (sample_size - this.partial.len()).min(rest.len());
+            this.partial.extend_from_slice(&rest[..num_bytes]);
+            rest = &rest[num_bytes..];
+            if this.partial.len() == sample_size {
+                this.pending
+                    .push(bytemuck::pod_read_unaligned(&this.partial[..]));
+                this.partial.clear();
+            }
+        }
+
+        let mut chunks = rest.chunks_exact(sample_size);
+        this.pending
+            .extend(chunks.by_ref().map(bytemuck::pod_read_unaligned::<S>));
+        this.partial.extend_from_slice(chunks.remainder());
+
+        // the bytes are taken now, so it doesn't matter if the sink isn't ready
+        // for the samples yet
+        if let Poll::Ready(Err(error)) = self.poll_drain(cx) {
+            return Poll::Ready(Err(error));
+        }
+
+        Poll::Ready(Ok(bytes.len()))
+    }
+
+    pub fn poll_flush_bytes(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>
+    where
+        W: AsyncWriteSamples<S>,
+        W::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        ready!(self.as_mut().poll_drain(cx))?;
+        self.project()
+            .inner
+            .poll_flush(cx)
+            .map_err(io::Error::other)
+    }
+
+    pub fn poll_close_bytes(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>
+    where
+        W: AsyncWriteSamples<S>,
+        W::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        ready!(self.as_mut().poll_drain(cx))?;
+        let this = self.project();
+        this.partial.clear();
+        this.inner.poll_close(cx).map_err(io::Error::other)
+    }
+}
+
+impl<W, S> Write for ByteWriter<W, S>
+where
+    W: AsyncWriteSamples<S> + Unpin,
+    W::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S: Pod,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        block_on(|cx| Pin::new(&mut *self).poll_write_bytes(cx, buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        block_on(|cx| Pin::new(&mut *self).poll_flush_bytes(cx))
+    }
+}
+
+impl<W, S> AsyncWrite for ByteWriter<W, S>
+where
+    W: AsyncWriteSamples<S>,
+    W::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S: Pod,
+{
+    #[inline]
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_bytes(cx, buf)
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush_bytes(cx)
+    }
+
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_close_bytes(cx)
+    }
+}
+
+/// Bytes read from a byte stream that weren't turned into samples yet.
+#[derive(Debug)]
+struct PartialBytes {
+    bytes: Vec<u8>,
+    filled: usize,
+}
+
+impl PartialBytes {
+    fn new() -> Self {
+        Self {
+            bytes: vec![],
+            filled: 0,
+        }
+    }
+
+    /// The unfilled part of the buffer, which has room for at least
+    /// `num_samples` samples.
+    fn unfilled<S>(&mut self, num_samples: usize) -> &mut [u8] {
+        let length = self.filled + num_samples * size_of::<S>();
+        if self.bytes.len() < length {
+            self.bytes.resize(length, 0);
+        }
+        &mut self.bytes[self.filled..]
+    }
+
+    #[inline]
+    fn has_sample<S>(&self) -> bool {
+        self.filled >= size_of::<S>()
+    }
+
+    /// Moves the whole samples into `buffer`.
+    fn read<S>(&mut self, buffer: &mut ReadBuf<S>)
+    where
+        S: Pod,
+    {
+        let sample_size = size_of::<S>();
+        let num_samples = (self.filled / sample_size).min(buffer.remaining());
+        let num_bytes = num_samples * sample_size;
+
+        for bytes in self.bytes[..num_bytes].chunks_exact(sample_size) {
+            buffer.put_sample(bytemuck::pod_read_unaligned(bytes));
+        }
+        self.bytes.copy_within(num_bytes..self.filled, 0);
+        self.filled -= num_bytes;
+    }
+}
+
+/// Reads samples from a [`Read`], e.g. the stdout of `rtl_sdr -`.
+///
+/// Like [`RawIqSource`][crate::source::file::RawIqSource], this blocks while
+/// reading. An incomplete sample at the end is dropped.
+#[derive(derive_more::Debug)]
+pub struct PodSource<R, S> {
+    #[debug(skip)]
+    inner: R,
+    partial: PartialBytes,
+    _phantom: PhantomData<fn() -> S>,
+}
+
+impl<R, S> PodSource<R, S> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            partial: PartialBytes::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for PodSource<R, S>
+where
+    R: Read + Unpin,
+    S: Pod,
+{
+    type Error = io::Error;
+
+    fn poll_read_samples(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+        let num_samples = buffer.remaining().min(DEFAULT_CHUNK_SIZE);
+        if num_samples == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        while !this.partial.has_sample::<S>() {
+            match this.inner.read(this.partial.unfilled::<S>(num_samples)) {
+                Ok(0) => break,
+                Ok(num_bytes) => this.partial.filled += num_bytes,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Poll::Ready(Err(error)),
+            }
+        }
+
+        this.partial.read(buffer);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R, S> StreamLength for PodSource<R, S> {
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        Remaining::Unknown
+    }
+}
+
+pin_project! {
+    /// Reads samples from a tokio [`AsyncRead`], e.g. a socket or the stdout
+    /// of a child process.
+    ///
+    /// An incomplete sample at the end is dropped.
+    #[derive(Debug)]
+    pub struct AsyncPodSource<R, S> {
+        #[pin]
+        inner: R,
+        partial: PartialBytes,
+        _phantom: PhantomData<fn() -> S>,
+    }
+}
+
+impl<R, S> AsyncPodSource<R, S> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            partial: PartialBytes::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for AsyncPodSource<R, S>
+where
+    R: AsyncRead,
+    S: Pod,
+{
+    type Error = io::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        let num_samples = buffer.remaining().min(DEFAULT_CHUNK_SIZE);
+        if num_samples == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        while !this.partial.has_sample::<S>() {
+            let mut read_buf = tokio::io::ReadBuf::new(this.partial.unfilled::<S>(num_samples));
+            ready!(this.inner.as_mut().poll_read(cx, &mut read_buf))?;
+            let num_bytes = read_buf.filled().len();
+            if num_bytes == 0 {
+                break;
+            }
+            this.partial.filled += num_bytes;
+        }
+
+        this.partial.read(buffer);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R, S> StreamLength for AsyncPodSource<R, S> {
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        Remaining::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{
+            Read,
+            Write,
+        },
+        pin::Pin,
+    };
+
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use super::{
+        AsyncPodSource,
+        ByteReader,
+        ByteWriter,
+        PodSource,
+    };
+    use crate::io::{
+        AsyncReadSamplesExt,
+        Cursor,
+        channel,
+    };
+
+    fn samples() -> Vec<Complex<f32>> {
+        (0..100)
+            .map(|i| Complex::new(i as f32, -(i as f32)))
+            .collect()
+    }
+
+    #[test]
+    fn it_reads_samples_as_bytes() {
+        let samples = samples();
+        let mut reader = ByteReader::<_, Complex<f32>>::new(Cursor::new(samples.clone()));
+
+        // reads that don't line up with the samples
+        let mut bytes = vec![];
+        let mut chunk = [0; 7];
+        loop {
+            let num_bytes = reader.read(&mut chunk).unwrap();
+            if num_bytes == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&chunk[..num_bytes]);
+        }
+
+        assert_eq!(bytes, bytemuck::cast_slice::<_, u8>(&samples));
+    }
+
+    #[test]
+    fn it_writes_bytes_as_samples() {
+        let samples = samples();
+        let bytes = bytemuck::cast_slice::<_, u8>(&samples);
+        let (sender, mut receiver) = channel(1000);
+
+        let mut writer = ByteWriter::<_, Complex<f32>>::new(sender);
+        for chunk in bytes.chunks(5) {
+            writer.write_all(chunk).unwrap();
+        }
+        // flushing the channel would wait for the receiver, so this closes it
+        // instead
+        std::future::poll_fn(|cx| Pin::new(&mut writer).poll_close_bytes(cx))
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+
+        let mut output = vec![];
+        receiver
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(output, samples);
+    }
+
+    #[test]
+    fn it_reads_samples_from_bytes() {
+        let samples = samples();
+        let mut bytes = bytemuck::cast_slice::<_, u8>(&samples).to_vec();
+        // an incomplete sample at the end
+        bytes.extend_from_slice(&[1, 2, 3]);
+
+        let mut output = vec![];
+        PodSource::<_, Complex<f32>>::new(std::io::Cursor::new(bytes.clone()))
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(output, samples);
+
+        let mut output = vec![];
+        AsyncPodSource::<_, Complex<f32>>::new(&bytes[..])
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(output, samples);
+    }
+}
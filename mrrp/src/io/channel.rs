@@ -0,0 +1,424 @@
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+        Waker,
+    },
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    io::{
+        AsyncReadSamples,
+        AsyncWriteSamples,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        StreamLength,
+    },
+};
+
+/// Creates a bounded channel for samples.
+///
+/// The [`SamplesSender`] is a sink and the [`SamplesReceiver`] a stream, so
+/// this connects pipelines that run on different tasks or threads. At most
+/// `capacity` samples are buffered; writes wait until the receiver has made
+/// room.
+///
+/// The sender can be cloned. The receiver ends once all senders are closed
+/// or dropped and it has read all samples.
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn channel<S>(capacity: usize) -> (SamplesSender<S>, SamplesReceiver<S>) {
+    assert!(capacity > 0, "capacity must be greater than 0");
+
+    let shared = Arc::new(Mutex::new(Shared {
+        samples: VecDeque::with_capacity(capacity),
+        capacity,
+        sample_rate: None,
+        num_senders: 1,
+        receiver_dropped: false,
+        receiver_waker: None,
+        sender_wakers: vec![],
+    }));
+
+    let sender = SamplesSender {
+        shared: shared.clone(),
+        is_closed: false,
+    };
+    let receiver = SamplesReceiver { shared };
+    (sender, receiver)
+}
+
+#[derive(Debug)]
+struct Shared<S> {
+    samples: VecDeque<S>,
+    capacity: usize,
+    sample_rate: Option<f32>,
+    num_senders: usize,
+    receiver_dropped: bool,
+    receiver_waker: Option<Waker>,
+    sender_wakers: Vec<Waker>,
+}
+
+impl<S> Shared<S> {
+    fn register_sender(&mut self, waker: &Waker) {
+        if !self
+            .sender_wakers
+            .iter()
+            .any(|sender_waker| sender_waker.will_wake(waker))
+        {
+            self.sender_wakers.push(waker.clone());
+        }
+    }
+
+    fn wake_senders(&mut self) {
+        for waker in self.sender_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn wake_receiver(&mut self) {
+        if let Some(waker) = self.receiver_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The sending half of a [`channel`].
+#[derive(Debug)]
+pub struct SamplesSender<S> {
+    shared: Arc<Mutex<Shared<S>>>,
+    is_closed: bool,
+}
+
+impl<S> SamplesSender<S> {
+    /// Sets the sample rate the receiver reports.
+    pub fn with_sample_rate(self, sample_rate: f32) -> Self {
+        self.set_sample_rate(sample_rate);
+        self
+    }
+
+    /// Sets the sample rate the receiver reports, e.g. when the sample rate
+    /// of the stream that is sent changes.
+    pub fn set_sample_rate(&self, sample_rate: f32) {
+        self.shared.lock().sample_rate = Some(sample_rate);
+    }
+
+    /// Number of samples the receiver hasn't read yet.
+    pub fn num_buffered(&self) -> usize {
+        self.shared.lock().samples.len()
+    }
+
+    /// Whether the receiver was dropped. Writes fail after that.
+    pub fn is_receiver_dropped(&self) -> bool {
+        self.shared.lock().receiver_dropped
+    }
+
+    fn close(&mut self) {
+        if !self.is_closed {
+            self.is_closed = true;
+            let mut shared = self.shared.lock();
+            shared.num_senders -= 1;
+            if shared.num_senders == 0 {
+                shared.wake_receiver();
+            }
+        }
+    }
+}
+
+impl<S> Clone for SamplesSender<S> {
+    fn clone(&self) -> Self {
+        self.shared.lock().num_senders += 1;
+        Self {
+            shared: self.shared.clone(),
+            is_closed: false,
+        }
+    }
+}
+
+impl<S> Drop for SamplesSender<S> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<S> AsyncWriteSamples<S> for SamplesSender<S>
+where
+    S: Clone,
+{
+    type Error = ChannelError;
+
+    fn poll_write_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &[S],
+    ) -> Poll<Result<usize, Self::Error>> {
+        if self.is_closed {
+            return Poll::Ready(Err(ChannelError::SenderClosed));
+        }
+
+        let mut shared = self.shared.lock();
+        if shared.receiver_dropped {
+            return Poll::Ready(Err(ChannelError::ReceiverDropped));
+        }
+        if buffer.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let free = shared.capacity - shared.samples.len();
+        if free == 0 {
+            shared.register_sender(cx.waker());
+            return Poll::Pending;
+        }
+
+        let num_samples = buffer.len().min(free);
+        shared.samples.extend(buffer[..num_samples].iter().cloned());
+        shared.wake_receiver();
+
+        Poll::Ready(Ok(num_samples))
+    }
+
+    /// Waits until the receiver has read all samples.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut shared = self.shared.lock();
+        if shared.samples.is_empty() {
+            Poll::Ready(Ok(()))
+        }
+        else if shared.receiver_dropped {
+            Poll::Ready(Err(ChannelError::ReceiverDropped))
+        }
+        else {
+            shared.register_sender(cx.waker());
+            Poll::Pending
+        }
+    }
+
+    /// Closes this sender. The receiver can still read the samples that
+    /// were sent.
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The receiving half of a [`channel`].
+#[derive(Debug)]
+pub struct SamplesReceiver<S> {
+    shared: Arc<Mutex<Shared<S>>>,
+}
+
+impl<S> SamplesReceiver<S> {
+    /// Number of samples that can be read without waiting.
+    pub fn num_buffered(&self) -> usize {
+        self.shared.lock().samples.len()
+    }
+
+    /// The sample rate the sender set, if any.
+    pub fn try_sample_rate(&self) -> Option<f32> {
+        self.shared.lock().sample_rate
+    }
+}
+
+impl<S> Drop for SamplesReceiver<S> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock();
+        shared.receiver_dropped = true;
+        shared.samples.clear();
+        shared.wake_senders();
+    }
+}
+
+impl<S> AsyncReadSamples<S> for SamplesReceiver<S>
+where
+    S: Clone,
+{
+    type Error = Infallible;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        if buffer.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let mut shared = self.shared.lock();
+        if shared.samples.is_empty() {
+            if shared.num_senders == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            shared.receiver_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let num_samples = buffer.remaining().min(shared.samples.len());
+        let (front, back) = shared.samples.as_slices();
+        let num_front = num_samples.min(front.len());
+        buffer.put_slice(&front[..num_front]);
+        buffer.put_slice(&back[..num_samples - num_front]);
+
+        shared.samples.drain(..num_samples);
+        shared.wake_senders();
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> StreamLength for SamplesReceiver<S> {
+    fn remaining(&self) -> Remaining {
+        let shared = self.shared.lock();
+        if shared.num_senders == 0 {
+            Remaining::Finite {
+                num_samples: shared.samples.len(),
+            }
+        }
+        else {
+            Remaining::Unknown
+        }
+    }
+}
+
+impl<S> GetSampleRate for SamplesReceiver<S> {
+    /// # Panics
+    ///
+    /// Panics if the sender didn't set a sample rate.
+    fn sample_rate(&self) -> f32 {
+        self.try_sample_rate()
+            .expect("sample rate of channel not set by sender")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ChannelError {
+    #[error("receiver of the channel was dropped")]
+    ReceiverDropped,
+
+    #[error("sender is closed")]
+    SenderClosed,
+}
+
+impl ClassifyError for ChannelError {
+    fn error_kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::{
+        ChannelError,
+        channel,
+    };
+    use crate::io::{
+        AsyncReadSamplesExt,
+        AsyncWriteSamplesExt,
+        Cursor,
+        GetSampleRate,
+    };
+
+    #[tokio::test]
+    async fn it_sends_samples_across_tasks() {
+        let (sender, mut receiver) = channel(100);
+        let sender = sender.with_sample_rate(48000.0);
+
+        let input = (0..10000).collect::<Vec<u32>>();
+        let task = tokio::spawn({
+            let input = input.clone();
+            async move { Cursor::new(input).pump(sender).run().await.unwrap() }
+        });
+
+        let mut output = vec![];
+        receiver.read_to_end(&mut output).await.unwrap();
+        assert_eq!(output, input);
+        assert_eq!(receiver.sample_rate(), 48000.0);
+        assert_eq!(task.await.unwrap().num_samples, 10000);
+    }
+
+    #[test]
+    fn it_waits_for_room() {
+        let (mut sender, mut receiver) = channel(4);
+
+        let num_samples = sender
+            .write(&[1, 2, 3, 4, 5])
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(num_samples, 4);
+        assert!(sender.write(&[5]).now_or_never().is_none());
+        assert!(sender.flush().now_or_never().is_none());
+
+        let mut buffer = [0; 3];
+        receiver
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(buffer, [1, 2, 3]);
+        assert_eq!(sender.write(&[5]).now_or_never(), Some(Ok(1)));
+
+        drop(receiver);
+        assert_eq!(
+            sender.write(&[6]).now_or_never(),
+            Some(Err(ChannelError::ReceiverDropped))
+        );
+    }
+
+    #[test]
+    fn it_ends_when_all_senders_are_closed() {
+        let (mut sender, mut receiver) = channel(10);
+        let mut second = sender.clone();
+
+        sender
+            .write_all(&[1, 2])
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        // the inherent `close` is private, and would shadow this
+        AsyncWriteSamplesExt::close(&mut sender)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+
+        let mut buffer = [0; 10];
+        let num_samples = receiver
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(num_samples, 2);
+        assert!(receiver.read_samples(&mut buffer).now_or_never().is_none());
+
+        second
+            .write_all(&[3])
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        drop(second);
+
+        let mut output = vec![];
+        receiver
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(output, [3]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Automatic gain control
+
+use std::ops::Mul;
+
+use crate::io::combinators::{
+    GroupDelay,
+    Power,
+    Scanner,
+};
+
+/// Default time in seconds in which the gain follows a rising signal.
+pub const DEFAULT_ATTACK: f32 = 0.005;
+
+/// Default time in seconds in which the gain follows a falling signal.
+pub const DEFAULT_DECAY: f32 = 0.5;
+
+/// Default highest gain, i.e. 100 dB.
+pub const DEFAULT_MAX_GAIN: f32 = 1e5;
+
+/// Scales samples so that their peak magnitude stays at a reference level.
+///
+/// The envelope of the signal is tracked with separate time constants for
+/// rising and falling magnitudes. A short attack keeps loud onsets from
+/// clipping, and a long decay keeps the gain from pumping between syllables or
+/// symbols. The gain is the reference level over the envelope, but at most
+/// the [max gain][Self::with_max_gain], so that noise isn't amplified without
+/// bound when the signal is gone.
+///
+/// This works for real and complex samples. Use it before demodulators that
+/// expect a certain level, or that are thrown off by fading.
+#[derive(Clone, Copy, Debug)]
+pub struct Agc {
+    sample_rate: f32,
+    attack: f32,
+    decay: f32,
+    reference_level: f32,
+    max_gain: f32,
+    envelope: Option<f32>,
+}
+
+impl Agc {
+    /// AGC with the default attack and decay, and a reference level of 1.
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            attack: smoothing(sample_rate, DEFAULT_ATTACK),
+            decay: smoothing(sample_rate, DEFAULT_DECAY),
+            reference_level: 1.0,
+            max_gain: DEFAULT_MAX_GAIN,
+            envelope: None,
+        }
+    }
+
+    /// Time in seconds in which the gain follows a rising signal.
+    pub fn with_attack(mut self, attack: f32) -> Self {
+        self.attack = smoothing(self.sample_rate, attack);
+        self
+    }
+
+    /// Time in seconds in which the gain follows a falling signal.
+    pub fn with_decay(mut self, decay: f32) -> Self {
+        self.decay = smoothing(self.sample_rate, decay);
+        self
+    }
+
+    /// Peak magnitude of the output.
+    pub fn with_reference_level(mut self, reference_level: f32) -> Self {
+        self.reference_level = reference_level;
+        self
+    }
+
+    pub fn with_max_gain(mut self, max_gain: f32) -> Self {
+        self.max_gain = max_gain;
+        self
+    }
+
+    #[inline]
+    pub fn reference_level(&self) -> f32 {
+        self.reference_level
+    }
+
+    /// The gain applied to the last sample.
+    pub fn gain(&self) -> f32 {
+        match self.envelope {
+            Some(envelope) if envelope * self.max_gain > self.reference_level => {
+                self.reference_level / envelope
+            }
+            _ => self.max_gain,
+        }
+    }
+}
+
+impl<S> Scanner<S> for Agc
+where
+    S: Power + Mul<f32, Output = S>,
+{
+    type Output = S;
+
+    #[inline]
+    fn scan(&mut self, sample: S) -> Self::Output {
+        let magnitude = sample.power().sqrt();
+
+        // start from the first sample, so the output doesn't start at the max
+        // gain
+        let envelope = self.envelope.get_or_insert(magnitude);
+        let smoothing = if magnitude > *envelope {
+            self.attack
+        }
+        else {
+            self.decay
+        };
+        *envelope += smoothing * (magnitude - *envelope);
+
+        sample * self.gain()
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.envelope = None;
+        None
+    }
+}
+
+impl GroupDelay for Agc {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Smoothing factor of a single-pole low-pass with the given time constant.
+fn smoothing(sample_rate: f32, time_constant: f32) -> f32 {
+    if time_constant > 0.0 {
+        1.0 - (-1.0 / (sample_rate * time_constant)).exp()
+    }
+    else {
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use approx::assert_abs_diff_eq;
+    use num_complex::Complex;
+
+    use super::Agc;
+    use crate::io::combinators::Scanner;
+
+    const SAMPLE_RATE: f32 = 8_000.0;
+
+    #[test]
+    fn it_follows_a_level_step() {
+        let mut agc = Agc::new(SAMPLE_RATE).with_reference_level(0.5);
+
+        let mut output = vec![];
+        for i in 0..2 * SAMPLE_RATE as usize {
+            let level = if i < SAMPLE_RATE as usize { 0.01 } else { 2.0 };
+            let sample = Complex::from_polar(level, TAU * 440.0 * i as f32 / SAMPLE_RATE);
+            output.push(agc.scan(sample));
+        }
+
+        // settled before the step, and within a few attack times after it
+        assert_abs_diff_eq!(output[7_999].norm(), 0.5, epsilon = 1e-3);
+        assert_abs_diff_eq!(output[8_400].norm(), 0.5, epsilon = 1e-3);
+        assert_abs_diff_eq!(agc.gain(), 0.25, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn it_holds_the_peaks_of_real_signals() {
+        let mut agc = Agc::new(SAMPLE_RATE);
+
+        let mut peak = 0.0f32;
+        for i in 0..SAMPLE_RATE as usize {
+            let sample = 0.1 * (TAU * 440.0 * i as f32 / SAMPLE_RATE).sin();
+            let output = agc.scan(sample);
+            if i > SAMPLE_RATE as usize / 2 {
+                peak = peak.max(output.abs());
+            }
+        }
+
+        // the envelope decays a little between the peaks, and the attack only
+        // catches up with them partially
+        assert!((0.95..1.05).contains(&peak), "peak {peak}");
+    }
+
+    #[test]
+    fn it_limits_the_gain() {
+        let mut agc = Agc::new(SAMPLE_RATE).with_max_gain(100.0);
+        agc.scan(0.5f32);
+        for _ in 0..5 * SAMPLE_RATE as usize {
+            agc.scan(0.0f32);
+        }
+        assert_eq!(agc.gain(), 100.0);
+        assert_abs_diff_eq!(agc.scan(1e-3f32), 0.1, epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use pin_project_lite::pin_project;
+
+use crate::{
+    buf::SampleBufMut,
+    io::{
+        AsyncReadSamples,
+        FiniteStream,
+        GetSampleIndexMap,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        SampleIndexMap,
+        ScratchBuffer,
+        StreamLength,
+    },
+};
+
+pin_project! {
+    /// Packs a stream of bits into bytes.
+    ///
+    /// A trailing partial byte at the end of the stream is dropped.
+    #[derive(Clone, Debug)]
+    pub struct PackBits<R> {
+        #[pin]
+        inner: R,
+        msb_first: bool,
+        byte: u8,
+        num_bits: u8,
+        slip: usize,
+        intermediate_buffer: ScratchBuffer<bool>,
+    }
+}
+
+impl<R> PackBits<R> {
+    #[inline]
+    pub fn new(inner: R, msb_first: bool) -> Self {
+        Self {
+            inner,
+            msb_first,
+            byte: 0,
+            num_bits: 0,
+            slip: 0,
+            intermediate_buffer: ScratchBuffer::new(0),
+        }
+    }
+
+    /// Drops the next bit, which moves the byte boundary one bit later.
+    ///
+    /// Call this repeatedly until a framer finds its sync word at the right
+    /// alignment.
+    #[inline]
+    pub fn bit_slip(&mut self) {
+        self.slip += 1;
+    }
+
+    /// Discards the bits of the current partial byte, so that the next byte
+    /// starts with the next bit.
+    #[inline]
+    pub fn align(&mut self) {
+        self.byte = 0;
+        self.num_bits = 0;
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R> AsyncReadSamples<u8> for PackBits<R>
+where
+    R: AsyncReadSamples<bool>,
+{
+    type Error = R::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<u8>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        if buffer.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            let read_length = 8 * buffer.remaining() - usize::from(*this.num_bits) + *this.slip;
+            let read_length = this.intermediate_buffer.reserve(read_length);
+            let mut read_buf = ReadBuf::uninit(&mut this.intermediate_buffer.buffer[..read_length]);
+
+            ready!(this.inner.as_mut().poll_read_samples(cx, &mut read_buf))?;
+
+            let bits = read_buf.filled();
+            if bits.is_empty() {
+                // end of stream
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut num_bytes = 0;
+            for &bit in bits {
+                if *this.slip > 0 {
+                    *this.slip -= 1;
+                    continue;
+                }
+
+                if *this.msb_first {
+                    *this.byte = (*this.byte << 1) | u8::from(bit);
+                }
+                else {
+                    *this.byte = (*this.byte >> 1) | (u8::from(bit) << 7);
+                }
+                *this.num_bits += 1;
+
+                if *this.num_bits == 8 {
+                    buffer.put_sample(*this.byte);
+                    *this.byte = 0;
+                    *this.num_bits = 0;
+                    num_bytes += 1;
+                }
+            }
+
+            // an empty read would look like the end of the stream, so keep reading
+            // until we have a full byte.
+            if num_bytes > 0 {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<R> GetSampleRate for PackBits<R>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate() / 8.0
+    }
+}
+
+impl<R> GetSampleIndexMap for PackBits<R>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner
+            .sample_index_map()
+            .then(SampleIndexMap::decimation(8))
+    }
+}
+
+impl<R> StreamLength for PackBits<R>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        let buffered = usize::from(self.num_bits);
+        let slip = self.slip;
+        self.inner
+            .remaining()
+            .map(|num_bits| (num_bits + buffered).saturating_sub(slip) / 8)
+    }
+}
+
+impl<R> FiniteStream for PackBits<R> where R: FiniteStream {}
+
+pin_project! {
+    /// Unpacks a stream of bytes into bits.
+    #[derive(Clone, Debug)]
+    pub struct UnpackBits<R> {
+        #[pin]
+        inner: R,
+        msb_first: bool,
+        byte: u8,
+        num_bits: u8,
+        intermediate_buffer: ScratchBuffer<u8>,
+    }
+}
+
+impl<R> UnpackBits<R> {
+    #[inline]
+    pub fn new(inner: R, msb_first: bool) -> Self {
+        Self {
+            inner,
+            msb_first,
+            byte: 0,
+            num_bits: 0,
+            intermediate_buffer: ScratchBuffer::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R> AsyncReadSamples<bool> for UnpackBits<R>
+where
+    R: AsyncReadSamples<u8>,
+{
+    type Error = R::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<bool>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+
+        // bits left over from the last byte we read
+        if *this.num_bits > 0 {
+            put_bits(this.byte, this.num_bits, *this.msb_first, buffer);
+            return Poll::Ready(Ok(()));
+        }
+
+        let read_length = buffer.remaining().div_ceil(8);
+        if read_length == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        let read_length = this.intermediate_buffer.reserve(read_length);
+        let mut read_buf = ReadBuf::uninit(&mut this.intermediate_buffer.buffer[..read_length]);
+
+        ready!(this.inner.poll_read_samples(cx, &mut read_buf))?;
+
+        for &byte in read_buf.filled() {
+            *this.byte = byte;
+            *this.num_bits = 8;
+            put_bits(this.byte, this.num_bits, *this.msb_first, buffer);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn put_bits(byte: &mut u8, num_bits: &mut u8, msb_first: bool, buffer: &mut ReadBuf<bool>) {
+    while *num_bits > 0 && buffer.has_remaining_mut() {
+        let bit = if msb_first {
+            let bit = *byte & 0x80 != 0;
+            *byte <<= 1;
+            bit
+        }
+        else {
+            let bit = *byte & 1 != 0;
+            *byte >>= 1;
+            bit
+        };
+        buffer.put_sample(bit);
+        *num_bits -= 1;
+    }
+}
+
+impl<R> GetSampleRate for UnpackBits<R>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate() * 8.0
+    }
+}
+
+impl<R> GetSampleIndexMap for UnpackBits<R>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner
+            .sample_index_map()
+            .then(SampleIndexMap::interpolation(8))
+    }
+}
+
+impl<R> StreamLength for UnpackBits<R>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        let buffered = usize::from(self.num_bits);
+        self.inner
+            .remaining()
+            .map(|num_bytes| 8 * num_bytes + buffered)
+    }
+}
+
+impl<R> FiniteStream for UnpackBits<R> where R: FiniteStream {}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use crate::io::{
+        AsyncReadSamplesExt,
+        Cursor,
+        test::SingleSampleStream,
+    };
+
+    fn bits_of(bytes: &[u8]) -> Vec<bool> {
+        bytes
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| byte & (1 << i) != 0))
+            .collect()
+    }
+
+    #[test]
+    fn it_packs_and_unpacks_bits() {
+        let bytes = vec![0xa5, 0x01, 0x80, 0xff, 0x3c];
+
+        let mut bits = vec![];
+        Cursor::new(bytes.clone())
+            .unpack_bits(true)
+            .read_to_end(&mut bits)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(bits, bits_of(&bytes));
+
+        let mut packed = vec![];
+        SingleSampleStream::new(Cursor::new(bits))
+            .pack_bits(true)
+            .read_to_end(&mut packed)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(packed, bytes);
+    }
+
+    #[test]
+    fn it_packs_lsb_first() {
+        let mut packed = vec![];
+        Cursor::new(bits_of(&[0x01, 0xa0]))
+            .pack_bits(false)
+            .read_to_end(&mut packed)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(packed, [0x80, 0x05]);
+    }
+
+    #[test]
+    fn it_realigns_after_bit_slip() {
+        // the stream starts with 3 junk bits
+        let mut bits = vec![true, false, true];
+        bits.extend(bits_of(&[0x12, 0x34]));
+
+        let mut packed = vec![];
+        let mut stream = Cursor::new(bits).pack_bits(true);
+        for _ in 0..3 {
+            stream.bit_slip();
+        }
+        stream
+            .read_to_end(&mut packed)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(packed, [0x12, 0x34]);
+    }
+}
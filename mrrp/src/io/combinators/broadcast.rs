@@ -0,0 +1,603 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+        Waker,
+    },
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    buf::{
+        BufferPolicy,
+        DEFAULT_CHUNK_SIZE,
+        UninitSlice,
+    },
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    io::{
+        AsyncReadSamples,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        StreamLength,
+    },
+};
+
+/// Default number of samples a [`Broadcast`] buffers.
+pub const DEFAULT_BROADCAST_CAPACITY: usize = 16 * DEFAULT_CHUNK_SIZE;
+
+/// What a [`Broadcast`] does when its buffer is full, because a receiver
+/// falls behind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LagPolicy {
+    /// Receivers that are ahead wait for the slowest receiver. Every receiver
+    /// gets every sample, but a receiver that isn't read stalls all others.
+    #[default]
+    Block,
+
+    /// The oldest samples are dropped, and receivers that haven't read them
+    /// yet skip them. Use this if a receiver must never hold up the others,
+    /// e.g. a display next to a demodulator.
+    DropOldest,
+}
+
+/// Splits a stream into multiple receivers that each read all samples.
+///
+/// The input is read by whichever receiver needs more samples, so no task
+/// needs to be spawned. The samples are kept in a shared ring buffer until
+/// every receiver has read them. How a full buffer is handled is configured
+/// with a [`LagPolicy`].
+#[derive(Debug)]
+pub struct Broadcast<R> {
+    input: R,
+    capacity: usize,
+    lag_policy: LagPolicy,
+}
+
+impl<R> Broadcast<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            capacity: DEFAULT_BROADCAST_CAPACITY,
+            lag_policy: LagPolicy::default(),
+        }
+    }
+
+    /// Sets how many samples are buffered for the slowest receiver.
+    ///
+    /// The capacity is capped by the [`BufferPolicy`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be greater than 0");
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn with_lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// Creates `num_receivers` receivers.
+    ///
+    /// More receivers can be added later by cloning a receiver.
+    pub fn receivers<S>(self, num_receivers: usize) -> Vec<BroadcastReceiver<R, S>> {
+        let capacity = self.capacity.min(BufferPolicy::global().max_samples::<S>());
+        let state = Arc::new(Mutex::new(State {
+            input: Box::pin(self.input),
+            input_state: InputState::Active,
+            samples: VecDeque::with_capacity(capacity),
+            chunk: Vec::new(),
+            capacity,
+            lag_policy: self.lag_policy,
+            offset: 0,
+            receivers: (0..num_receivers)
+                .map(|_| Some(ReceiverState::new(0)))
+                .collect(),
+        }));
+
+        (0..num_receivers)
+            .map(|index| {
+                BroadcastReceiver {
+                    state: state.clone(),
+                    index,
+                }
+            })
+            .collect()
+    }
+
+    /// Creates two receivers.
+    pub fn tee<S>(self) -> (BroadcastReceiver<R, S>, BroadcastReceiver<R, S>) {
+        let mut receivers = self.receivers(2);
+        let second = receivers.pop().unwrap();
+        let first = receivers.pop().unwrap();
+        (first, second)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InputState {
+    Active,
+    Finished,
+    Failed,
+}
+
+#[derive(Debug)]
+struct ReceiverState {
+    /// Position of the next sample this receiver reads, counted from the
+    /// start of the input.
+    position: u64,
+    num_dropped: u64,
+    waker: Option<Waker>,
+}
+
+impl ReceiverState {
+    fn new(position: u64) -> Self {
+        Self {
+            position,
+            num_dropped: 0,
+            waker: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State<R, S> {
+    input: Pin<Box<R>>,
+    input_state: InputState,
+    samples: VecDeque<S>,
+    /// Samples are read from the input into this, before they're moved into
+    /// the ring buffer.
+    chunk: Vec<S>,
+    capacity: usize,
+    lag_policy: LagPolicy,
+    /// Position of the first buffered sample.
+    offset: u64,
+    receivers: Vec<Option<ReceiverState>>,
+}
+
+impl<R, S> State<R, S> {
+    #[inline]
+    fn end(&self) -> u64 {
+        self.offset + self.samples.len() as u64
+    }
+
+    fn add_receiver(&mut self, position: u64) -> usize {
+        let receiver = ReceiverState::new(position);
+        if let Some(index) = self.receivers.iter().position(Option::is_none) {
+            self.receivers[index] = Some(receiver);
+            index
+        }
+        else {
+            self.receivers.push(Some(receiver));
+            self.receivers.len() - 1
+        }
+    }
+
+    /// Drops the samples that all receivers have read.
+    fn trim(&mut self) {
+        let offset = self.offset;
+        let end = self.end();
+        let min_position = self
+            .receivers
+            .iter()
+            .flatten()
+            .map(|receiver| receiver.position.max(offset))
+            .min()
+            .unwrap_or(end);
+
+        let num_samples = (min_position - offset) as usize;
+        if num_samples > 0 {
+            self.samples.drain(..num_samples);
+            self.offset = min_position;
+            // receivers that are ahead might be waiting for space
+            self.wake_all();
+        }
+    }
+
+    fn drop_oldest(&mut self, num_samples: usize) {
+        let num_samples = num_samples.min(self.samples.len());
+        self.samples.drain(..num_samples);
+        self.offset += num_samples as u64;
+    }
+
+    fn wake_all(&mut self) {
+        for receiver in self.receivers.iter_mut().flatten() {
+            if let Some(waker) = receiver.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Copies buffered samples from `position` into `buffer`.
+    fn copy_to(&self, position: u64, buffer: &mut ReadBuf<S>) -> usize
+    where
+        S: Clone,
+    {
+        let start = (position - self.offset) as usize;
+        let num_samples = buffer.remaining().min(self.samples.len() - start);
+
+        let (front, back) = self.samples.as_slices();
+        if start < front.len() {
+            let num_front = num_samples.min(front.len() - start);
+            buffer.put_slice(&front[start..][..num_front]);
+            buffer.put_slice(&back[..num_samples - num_front]);
+        }
+        else {
+            buffer.put_slice(&back[start - front.len()..][..num_samples]);
+        }
+
+        num_samples
+    }
+}
+
+/// Reads from the input of a [`Broadcast`] into the ring buffer.
+fn poll_fill<R, S>(
+    state: &mut State<R, S>,
+    cx: &mut Context<'_>,
+    num_samples: usize,
+) -> Poll<Result<usize, R::Error>>
+where
+    R: AsyncReadSamples<S>,
+{
+    state.chunk.clear();
+    state.chunk.reserve_exact(num_samples);
+    let spare = &mut state.chunk.spare_capacity_mut()[..num_samples];
+    let mut read_buf = ReadBuf::uninit(UninitSlice::slice_mut_from_uninit(spare));
+
+    let result = state.input.as_mut().poll_read_samples(cx, &mut read_buf);
+    let num_read = read_buf.filled().len();
+    unsafe {
+        read_buf.drop_unfilled_initialized();
+        state.chunk.set_len(num_read);
+    }
+
+    match result {
+        Poll::Pending => Poll::Pending,
+        Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+        Poll::Ready(Ok(())) => {
+            state.samples.extend(state.chunk.drain(..));
+            Poll::Ready(Ok(num_read))
+        }
+    }
+}
+
+/// One of the receivers of a [`Broadcast`].
+///
+/// Cloning a receiver adds a receiver that continues from the same position.
+/// Dropping a receiver releases the samples that only it still had to read.
+#[derive(Debug)]
+pub struct BroadcastReceiver<R, S> {
+    state: Arc<Mutex<State<R, S>>>,
+    index: usize,
+}
+
+impl<R, S> BroadcastReceiver<R, S> {
+    /// Number of samples this receiver skipped, because they were dropped
+    /// with [`LagPolicy::DropOldest`] before it read them.
+    pub fn num_dropped(&self) -> u64 {
+        let state = self.state.lock();
+        let receiver = state.receivers[self.index].as_ref().unwrap();
+        receiver.num_dropped + state.offset.saturating_sub(receiver.position)
+    }
+
+    /// Number of samples that are buffered, but not yet read by this
+    /// receiver.
+    pub fn num_buffered(&self) -> usize {
+        let state = self.state.lock();
+        let receiver = state.receivers[self.index].as_ref().unwrap();
+        (state.end() - receiver.position.max(state.offset)) as usize
+    }
+}
+
+impl<R, S> Clone for BroadcastReceiver<R, S> {
+    fn clone(&self) -> Self {
+        let mut state = self.state.lock();
+        let position = state.receivers[self.index].as_ref().unwrap().position;
+        let index = state.add_receiver(position);
+        drop(state);
+
+        Self {
+            state: self.state.clone(),
+            index,
+        }
+    }
+}
+
+impl<R, S> Drop for BroadcastReceiver<R, S> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        state.receivers[self.index] = None;
+        state.trim();
+        // this receiver might have been the one the input would wake
+        state.wake_all();
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for BroadcastReceiver<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: Clone,
+{
+    type Error = BroadcastError<R::Error>;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        if buffer.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let mut state = self.state.lock();
+        let state = &mut *state;
+
+        loop {
+            let offset = state.offset;
+            let end = state.end();
+            let receiver = state.receivers[self.index].as_mut().unwrap();
+            if receiver.position < offset {
+                receiver.num_dropped += offset - receiver.position;
+                receiver.position = offset;
+            }
+
+            if receiver.position < end {
+                let position = receiver.position;
+                let num_samples = state.copy_to(position, buffer);
+                state.receivers[self.index].as_mut().unwrap().position += num_samples as u64;
+                state.trim();
+                return Poll::Ready(Ok(()));
+            }
+
+            match state.input_state {
+                InputState::Active => {}
+                InputState::Finished => return Poll::Ready(Ok(())),
+                InputState::Failed => return Poll::Ready(Err(BroadcastError::InputFailed)),
+            }
+
+            // this receiver has read everything, so we need to read from the input
+            let mut num_samples = buffer
+                .remaining()
+                .max(DEFAULT_CHUNK_SIZE)
+                .min(state.capacity);
+            let free = state.capacity - state.samples.len();
+            if free < num_samples {
+                match state.lag_policy {
+                    LagPolicy::Block => {
+                        if free == 0 {
+                            state.receivers[self.index].as_mut().unwrap().waker =
+                                Some(cx.waker().clone());
+                            return Poll::Pending;
+                        }
+                        num_samples = free;
+                    }
+                    LagPolicy::DropOldest => state.drop_oldest(num_samples - free),
+                }
+            }
+
+            match poll_fill(state, cx, num_samples) {
+                Poll::Pending => {
+                    state.receivers[self.index].as_mut().unwrap().waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                Poll::Ready(Err(error)) => {
+                    state.input_state = InputState::Failed;
+                    state.wake_all();
+                    return Poll::Ready(Err(BroadcastError::Input(error)));
+                }
+                Poll::Ready(Ok(0)) => {
+                    state.input_state = InputState::Finished;
+                    state.wake_all();
+                    return Poll::Ready(Ok(()));
+                }
+                Poll::Ready(Ok(_)) => {
+                    // other receivers might be waiting for these samples
+                    state.wake_all();
+                }
+            }
+        }
+    }
+}
+
+impl<R, S> StreamLength for BroadcastReceiver<R, S>
+where
+    R: StreamLength,
+{
+    fn remaining(&self) -> Remaining {
+        let buffered = self.num_buffered();
+        let state = self.state.lock();
+        match state.input_state {
+            InputState::Active => {
+                state
+                    .input
+                    .remaining()
+                    .map(|num_samples| buffered + num_samples)
+            }
+            InputState::Finished | InputState::Failed => {
+                Remaining::Finite {
+                    num_samples: buffered,
+                }
+            }
+        }
+    }
+}
+
+impl<R, S> GetSampleRate for BroadcastReceiver<R, S>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.state.lock().input.sample_rate()
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum BroadcastError<E> {
+    #[error("broadcast input error")]
+    Input(E),
+
+    /// The input failed while another receiver was reading from it. That
+    /// receiver got the original error.
+    #[error("broadcast input failed")]
+    InputFailed,
+}
+
+impl<E: ClassifyError> ClassifyError for BroadcastError<E> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            BroadcastError::Input(error) => error.error_kind(),
+            BroadcastError::InputFailed => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::{
+        Broadcast,
+        LagPolicy,
+    };
+    use crate::io::{
+        AsyncReadSamplesExt,
+        Cursor,
+    };
+
+    fn input(length: u32) -> Cursor<Vec<u32>, u32> {
+        Cursor::new((0..length).collect())
+    }
+
+    #[test]
+    fn it_sends_all_samples_to_every_receiver() {
+        let mut receivers = input(1000).broadcast(3);
+
+        // the receivers are read at different paces, but all get the same
+        // samples
+        let mut outputs = vec![vec![]; 3];
+        let mut buffer = [0; 100];
+        loop {
+            let mut num_read = 0;
+            for ((receiver, output), chunk_size) in
+                receivers.iter_mut().zip(&mut outputs).zip([7, 100, 33])
+            {
+                let n = receiver
+                    .read_samples(&mut buffer[..chunk_size])
+                    .now_or_never()
+                    .expect("pending")
+                    .unwrap();
+                output.extend_from_slice(&buffer[..n]);
+                num_read += n;
+            }
+            if num_read == 0 {
+                break;
+            }
+        }
+
+        let expected = (0..1000).collect::<Vec<u32>>();
+        for output in outputs {
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn it_blocks_on_the_slowest_receiver() {
+        let (mut fast, mut slow) = Broadcast::new(input(1000)).with_capacity(10).tee();
+        let mut buffer = [0; 20];
+
+        let n = fast
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(n, 10);
+        assert!(fast.read_samples(&mut buffer).now_or_never().is_none());
+
+        let n = slow
+            .read_samples(&mut buffer[..4])
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(buffer[..n], [0, 1, 2, 3]);
+
+        // the slow receiver made room for 4 samples
+        let n = fast
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(buffer[..n], [10, 11, 12, 13]);
+
+        // dropping the slow receiver unblocks the fast one
+        drop(slow);
+        let n = fast
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(n, 10);
+        assert_eq!(buffer[0], 14);
+    }
+
+    #[test]
+    fn it_drops_the_oldest_samples() {
+        let (mut fast, mut slow) = Broadcast::new(input(1000))
+            .with_capacity(10)
+            .with_lag_policy(LagPolicy::DropOldest)
+            .tee();
+        let mut buffer = [0; 10];
+
+        for _ in 0..3 {
+            fast.read_samples(&mut buffer)
+                .now_or_never()
+                .expect("pending")
+                .unwrap();
+        }
+        assert_eq!(buffer[0], 20);
+        assert_eq!(slow.num_dropped(), 20);
+
+        let n = slow
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(n, 10);
+        assert_eq!(buffer[0], 20);
+        assert_eq!(slow.num_dropped(), 20);
+    }
+
+    #[test]
+    fn it_adds_receivers_by_cloning() {
+        let (mut first, second) = input(100).tee();
+        drop(second);
+
+        let mut buffer = [0; 10];
+        first
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+
+        let mut clone = first.clone();
+        assert_eq!(clone.num_buffered(), first.num_buffered());
+        let n = clone
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(n, 10);
+        assert_eq!(buffer[0], 10);
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    future::Future,
     pin::Pin,
     task::{
         Context,
@@ -12,30 +13,115 @@ use crate::io::{
     AsyncReadSamples,
     Buffer,
     FiniteStream,
+    GetSampleIndexMap,
     GetSampleRate,
     ReadBuf,
     Remaining,
+    SampleIndexMap,
     SizeHint,
     StreamLength,
 };
 
 pin_project! {
+    /// Stream wrapper that reads from the inner stream in large chunks.
+    ///
+    /// By default the buffer is only refilled once it is empty. This causes
+    /// bursty timing downstream, since every now and then a read has to wait
+    /// for a whole buffer. With [watermarks][Self::with_watermarks], the
+    /// buffer is topped up to the high watermark whenever it drops to the low
+    /// watermark, while the remaining samples are still handed out.
+    ///
+    /// Refilling only happens when the stream is polled. To refill ahead of
+    /// time, e.g. between reads of a real-time consumer like an audio
+    /// callback, drive [`prefetch`][Self::prefetch] from the consuming task,
+    /// e.g. with a periodic timer.
+    ///
+    /// The buffer size is capped by the [`BufferPolicy`][crate::buf::BufferPolicy].
     #[derive(Clone, Debug)]
     pub struct Buffered<R, S> {
         #[pin]
         inner: R,
         buffer: Buffer<S>,
+        low_watermark: usize,
+        high_watermark: usize,
     }
 }
 
 impl<R, S> Buffered<R, S> {
     #[inline]
     pub fn new(inner: R, buffer_size: usize) -> Self {
+        let buffer = Buffer::new(buffer_size);
         Self {
             inner,
-            buffer: Buffer::new(buffer_size),
+            low_watermark: 0,
+            high_watermark: buffer.capacity(),
+            buffer,
         }
     }
+
+    /// Refill the buffer up to `high` samples, whenever it has `low` or
+    /// fewer samples left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `low > high` or `high` is larger than the buffer size.
+    pub fn with_watermarks(mut self, low: usize, high: usize) -> Self {
+        assert!(
+            low <= high,
+            "low watermark {low} is above high watermark {high}"
+        );
+        assert!(
+            high <= self.buffer.capacity(),
+            "high watermark {high} is larger than the buffer size {}",
+            self.buffer.capacity()
+        );
+        self.low_watermark = low;
+        self.high_watermark = high;
+        self
+    }
+
+    #[inline]
+    pub fn low_watermark(&self) -> usize {
+        self.low_watermark
+    }
+
+    #[inline]
+    pub fn high_watermark(&self) -> usize {
+        self.high_watermark
+    }
+
+    /// Number of samples currently buffered.
+    #[inline]
+    pub fn num_buffered(&self) -> usize {
+        self.buffer.remaining()
+    }
+
+    /// Fills the buffer up to the high watermark without handing out any
+    /// samples.
+    ///
+    /// Resolves to the number of samples read, which is 0 if the buffer was
+    /// already full or the inner stream ended.
+    #[inline]
+    pub fn prefetch(&mut self) -> Prefetch<'_, R, S>
+    where
+        Self: Unpin,
+    {
+        Prefetch {
+            buffered: Pin::new(self),
+        }
+    }
+
+    pub fn poll_prefetch(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, R::Error>>
+    where
+        R: AsyncReadSamples<S>,
+    {
+        let this = self.project();
+        this.buffer
+            .poll_fill_to(cx, this.inner, *this.high_watermark)
+    }
 }
 
 impl<R, S> AsyncReadSamples<S> for Buffered<R, S>
@@ -56,6 +142,19 @@ where
             let this = self.as_mut().project();
 
             if this.buffer.read_pos < this.buffer.write_pos {
+                // top up the buffer if it's running low. if the inner stream isn't ready
+                // we hand out what we have.
+                if !inner_is_pending && this.buffer.remaining() <= *this.low_watermark {
+                    match this
+                        .buffer
+                        .poll_fill_to(cx, this.inner, *this.high_watermark)
+                    {
+                        Poll::Pending => inner_is_pending = true,
+                        Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                        Poll::Ready(Ok(_)) => {}
+                    }
+                }
+
                 // we still have data buffered, so lets cosume that first.
 
                 let num_samples = this.buffer.read(buffer);
@@ -123,6 +222,24 @@ where
     }
 }
 
+/// Future returned by [`Buffered::prefetch`].
+#[derive(Debug)]
+#[must_use]
+pub struct Prefetch<'a, R, S> {
+    buffered: Pin<&'a mut Buffered<R, S>>,
+}
+
+impl<'a, R, S> Future for Prefetch<'a, R, S>
+where
+    R: AsyncReadSamples<S>,
+{
+    type Output = Result<usize, R::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.buffered.as_mut().poll_prefetch(cx)
+    }
+}
+
 impl<R, S> GetSampleRate for Buffered<R, S>
 where
     R: GetSampleRate,
@@ -133,6 +250,16 @@ where
     }
 }
 
+impl<R, S> GetSampleIndexMap for Buffered<R, S>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<R, S> StreamLength for Buffered<R, S>
 where
     R: StreamLength,
@@ -291,4 +418,66 @@ mod tests {
             .enumerate()
             .for_each(|(i, sample)| assert_eq!(samples[10 + i], *sample));
     }
+
+    #[test]
+    fn it_tops_up_at_the_low_watermark() {
+        let samples = (0..100).collect::<Vec<_>>();
+        let mut buffered = Cursor::new(&samples[..])
+            .buffered(50)
+            .with_watermarks(20, 40);
+        let mut destination = vec![0; 10];
+
+        // the first read fills the whole buffer, since it's empty
+        buffered
+            .read_samples(&mut destination[..])
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(buffered.num_buffered(), 40);
+
+        // this leaves 20 samples, which is the low watermark
+        for _ in 0..2 {
+            buffered
+                .read_samples(&mut destination[..])
+                .now_or_never()
+                .expect("pending")
+                .unwrap();
+        }
+        assert_eq!(buffered.num_buffered(), 20);
+
+        // so the next read tops up to 40 before handing out samples
+        buffered
+            .read_samples(&mut destination[..])
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(buffered.num_buffered(), 30);
+        destination
+            .iter()
+            .enumerate()
+            .for_each(|(i, sample)| assert_eq!(samples[30 + i], *sample));
+
+        let mut rest = vec![];
+        buffered
+            .read_to_end(&mut rest)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(rest, samples[40..]);
+    }
+
+    #[test]
+    fn it_prefetches() {
+        let samples = (0..100).collect::<Vec<_>>();
+        let mut buffered = Cursor::new(&samples[..]).buffered(50);
+
+        let num_samples = buffered
+            .prefetch()
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(num_samples, 50);
+        assert_eq!(buffered.inner.position(), 50);
+        assert_eq!(buffered.num_buffered(), 50);
+    }
 }
@@ -0,0 +1,172 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+use pin_project_lite::pin_project;
+use tokio_util::sync::{
+    CancellationToken,
+    WaitForCancellationFutureOwned,
+};
+
+use crate::io::{
+    AsyncReadSamples,
+    GetSampleIndexMap,
+    GetSampleRate,
+    ReadBuf,
+    Remaining,
+    SampleIndexMap,
+    SizeHint,
+    StreamLength,
+};
+
+pin_project! {
+    /// Stream wrapper that ends the stream once a [`CancellationToken`] is
+    /// cancelled.
+    ///
+    /// A read that is pending when the token is cancelled returns EOF, so
+    /// anything downstream, e.g. a [`Forward`][crate::io::Forward], finishes
+    /// as if the source ended.
+    #[derive(Debug)]
+    pub struct UntilCancelled<R> {
+        #[pin]
+        inner: R,
+        #[pin]
+        cancelled: WaitForCancellationFutureOwned,
+        is_cancelled: bool,
+    }
+}
+
+impl<R> UntilCancelled<R> {
+    #[inline]
+    pub fn new(inner: R, token: CancellationToken) -> Self {
+        Self {
+            inner,
+            cancelled: token.cancelled_owned(),
+            is_cancelled: false,
+        }
+    }
+
+    /// Whether the stream ended because it was cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for UntilCancelled<R>
+where
+    R: AsyncReadSamples<S>,
+{
+    type Error = R::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+
+        if !*this.is_cancelled && this.cancelled.poll(cx).is_ready() {
+            *this.is_cancelled = true;
+        }
+
+        if *this.is_cancelled {
+            Poll::Ready(Ok(()))
+        }
+        else {
+            this.inner.poll_read_samples(cx, buffer)
+        }
+    }
+}
+
+impl<R> GetSampleRate for UntilCancelled<R>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate()
+    }
+}
+
+impl<R> GetSampleIndexMap for UntilCancelled<R>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
+impl<R> StreamLength for UntilCancelled<R>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        if self.is_cancelled {
+            Remaining::Finite { num_samples: 0 }
+        }
+        else {
+            // the stream can end at any time
+            Remaining::Unknown
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> SizeHint {
+        if self.is_cancelled {
+            self.remaining().size_hint()
+        }
+        else {
+            SizeHint {
+                lower_bound: 0,
+                upper_bound: self.inner.size_hint().upper_bound,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use futures_util::FutureExt;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::io::{
+        AsyncReadSamplesExt,
+        Cursor,
+    };
+
+    #[test]
+    fn it_ends_the_stream_when_cancelled() {
+        let token = CancellationToken::new();
+        let mut stream =
+            pin!(Cursor::new((0..100).collect::<Vec<u32>>()).until_cancelled(token.clone()));
+
+        let mut buffer = [0; 10];
+        let num_read = stream
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(num_read, 10);
+        assert!(!stream.is_cancelled());
+
+        token.cancel();
+        let num_read = stream
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(num_read, 0);
+        assert!(stream.is_cancelled());
+    }
+}
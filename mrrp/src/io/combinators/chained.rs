@@ -10,12 +10,18 @@ use pin_project_lite::pin_project;
 
 use crate::{
     buf::SampleBufMut,
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
     io::{
         AsyncReadSamples,
         FiniteStream,
+        GetSampleIndexMap,
         GetSampleRate,
         ReadBuf,
         Remaining,
+        SampleIndexMap,
         SizeHint,
         StreamLength,
     },
@@ -90,6 +96,18 @@ where
     }
 }
 
+/// The tail continues where the head left off, so this is the map of the
+/// head.
+impl<H, T> GetSampleIndexMap for Chained<H, T>
+where
+    H: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.head.sample_index_map()
+    }
+}
+
 impl<H, T> GetSampleRate for Chained<H, T>
 where
     H: GetSampleRate,
@@ -145,3 +163,12 @@ pub enum ChainedError<H, T> {
     Head(H),
     Tail(T),
 }
+
+impl<H: ClassifyError, T: ClassifyError> ClassifyError for ChainedError<H, T> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            ChainedError::Head(error) => error.error_kind(),
+            ChainedError::Tail(error) => error.error_kind(),
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Channel simulation
+//!
+//! [`ChannelSimulator`] impairs an IQ stream like a radio channel and receiver
+//! would, to test demodulators against something else than a perfect signal.
+
+use std::{
+    f32::consts::{
+        PI,
+        TAU,
+    },
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+use rand::{
+    Rng,
+    RngExt,
+};
+
+use crate::{
+    buf::SampleBufMut,
+    filter::farrow::{
+        FarrowDelay,
+        Interpolation,
+    },
+    io::{
+        AsyncReadSamples,
+        FiniteStream,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        StreamLength,
+    },
+    source::Gaussian,
+};
+
+pin_project! {
+    /// Adds noise, a frequency offset and timing drift to an IQ stream.
+    ///
+    /// All impairments are off by default:
+    ///
+    /// - [Noise][Self::with_snr] is additive white Gaussian noise at a
+    ///   signal-to-noise ratio relative to the
+    ///   [signal power][Self::with_signal_power].
+    /// - A [frequency offset][Self::with_frequency_offset] shifts the signal,
+    ///   as if the receiver was mistuned.
+    /// - [Timing drift][Self::with_timing_drift] resamples the signal, as if
+    ///   the receiver's sample clock was off.
+    #[derive(Debug)]
+    pub struct ChannelSimulator<R, G> {
+        #[pin]
+        input: R,
+        rng: G,
+        sample_rate: f32,
+
+        signal_power: f32,
+        snr: Option<f32>,
+        noise: Option<Gaussian>,
+
+        phase: f32,
+        phase_increment: f32,
+
+        interpolator: FarrowDelay<Complex<f32>>,
+        // input samples per output sample
+        step: f32,
+        // input samples until the next output sample
+        countdown: f32,
+
+        buffer: Vec<Complex<f32>>,
+        position: usize,
+        length: usize,
+    }
+}
+
+impl<R, G> ChannelSimulator<R, G> {
+    pub fn new(input: R, rng: G) -> Self
+    where
+        R: GetSampleRate,
+    {
+        Self {
+            sample_rate: input.sample_rate(),
+            input,
+            rng,
+            signal_power: 1.0,
+            snr: None,
+            noise: None,
+            phase: 0.0,
+            phase_increment: 0.0,
+            interpolator: FarrowDelay::new(Interpolation::Cubic, 0.0),
+            step: 1.0,
+            // the interpolator delays by a sample, which is skipped
+            countdown: 2.0,
+            buffer: vec![Complex::default(); 4096],
+            position: 0,
+            length: 0,
+        }
+    }
+
+    /// Adds noise at this signal-to-noise ratio in dB.
+    pub fn with_snr(mut self, snr: f32) -> Self {
+        self.snr = Some(snr);
+        self.update_noise();
+        self
+    }
+
+    /// The power of the input that the SNR refers to. This is 1 by default,
+    /// i.e. symbols on the unit circle.
+    pub fn with_signal_power(mut self, signal_power: f32) -> Self {
+        self.signal_power = signal_power;
+        self.update_noise();
+        self
+    }
+
+    /// Shifts the signal by this frequency in Hz.
+    pub fn with_frequency_offset(mut self, frequency_offset: f32) -> Self {
+        self.phase_increment = TAU * frequency_offset / self.sample_rate;
+        self
+    }
+
+    /// Offsets the sample clock by this many parts per million.
+    ///
+    /// For a positive drift, the clock is slow, i.e. the output has fewer
+    /// samples than the input, and the signal's symbol rate and frequencies
+    /// seem higher.
+    pub fn with_timing_drift(mut self, drift: f32) -> Self {
+        let step = 1.0 + drift * 1e-6;
+        assert!((0.5..=1.5).contains(&step), "invalid timing drift: {drift}");
+        self.step = step;
+        self
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolator = FarrowDelay::new(interpolation, 0.0);
+        self
+    }
+
+    fn update_noise(&mut self) {
+        self.noise = self
+            .snr
+            .map(|snr| Gaussian::new(self.signal_power * 10f32.powf(-snr / 10.0)));
+    }
+
+    /// The power of the added noise.
+    #[inline]
+    pub fn noise_power(&self) -> f32 {
+        self.noise.map_or(0.0, |noise| noise.power())
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.input
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+impl<R, G> AsyncReadSamples<Complex<f32>> for ChannelSimulator<R, G>
+where
+    R: AsyncReadSamples<Complex<f32>>,
+    G: Rng,
+{
+    type Error = R::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<Complex<f32>>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        let initial_filled = buffer.filled().len();
+
+        loop {
+            // output the samples up to the last input sample. the interpolated
+            // sample is between the last two input samples.
+            while *this.countdown <= 0.0 {
+                if !buffer.has_remaining_mut() {
+                    return Poll::Ready(Ok(()));
+                }
+
+                this.interpolator.set_delay(-*this.countdown);
+                let mut sample =
+                    this.interpolator.interpolate() * Complex::from_polar(1.0, *this.phase);
+                if let Some(noise) = this.noise {
+                    sample += this.rng.sample::<Complex<f32>, _>(*noise);
+                }
+                buffer.put_sample(sample);
+
+                *this.countdown += *this.step;
+                *this.phase += *this.phase_increment;
+                if *this.phase > PI {
+                    *this.phase -= TAU;
+                }
+                else if *this.phase < -PI {
+                    *this.phase += TAU;
+                }
+            }
+
+            if *this.position < *this.length {
+                this.interpolator.push(this.buffer[*this.position]);
+                *this.position += 1;
+                *this.countdown -= 1.0;
+                continue;
+            }
+
+            if buffer.filled().len() > initial_filled {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.buffer[..]);
+            ready!(this.input.as_mut().poll_read_samples(cx, &mut read_buf))?;
+            let num_read = read_buf.filled().len();
+            if num_read == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            *this.position = 0;
+            *this.length = num_read;
+        }
+    }
+}
+
+impl<R, G> GetSampleRate for ChannelSimulator<R, G> {
+    /// The nominal sample rate, i.e. without the timing drift.
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+}
+
+impl<R, G> FiniteStream for ChannelSimulator<R, G> where R: FiniteStream {}
+
+impl<R, G> StreamLength for ChannelSimulator<R, G>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        let buffered = self.length - self.position;
+        self.input
+            .remaining()
+            .map(|num_samples| ((num_samples + buffered) as f32 / self.step) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        f64::consts::TAU,
+    };
+
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+    use rand::{
+        SeedableRng,
+        rngs::SmallRng,
+    };
+
+    use super::ChannelSimulator;
+    use crate::io::{
+        AsyncReadSamples,
+        AsyncReadSamplesExt,
+        Cursor,
+        GetSampleRate,
+    };
+
+    const SAMPLE_RATE: f32 = 8000.0;
+    const NUM_SAMPLES: usize = 100_000;
+
+    fn channel(
+        input: &[Complex<f32>],
+    ) -> ChannelSimulator<
+        impl AsyncReadSamples<Complex<f32>, Error = Infallible> + GetSampleRate + Unpin + '_,
+        SmallRng,
+    > {
+        ChannelSimulator::new(
+            Cursor::new(input).with_sample_rate(SAMPLE_RATE),
+            SmallRng::seed_from_u64(1),
+        )
+    }
+
+    fn read_all<R>(mut stream: R) -> Vec<Complex<f32>>
+    where
+        R: AsyncReadSamples<Complex<f32>, Error = Infallible> + Unpin,
+    {
+        let mut output = vec![];
+        stream
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        output
+    }
+
+    /// The sample of a tone at `time` in samples.
+    fn tone(frequency: f64, time: f64) -> Complex<f32> {
+        let phase = TAU * frequency * time / f64::from(SAMPLE_RATE);
+        Complex::from_polar(1.0, phase.rem_euclid(TAU) as f32)
+    }
+
+    fn tone_samples(frequency: f64) -> Vec<Complex<f32>> {
+        (0..NUM_SAMPLES)
+            .map(|i| tone(frequency, i as f64))
+            .collect()
+    }
+
+    #[test]
+    fn it_passes_the_signal_through() {
+        let input = tone_samples(100.0);
+        let output = read_all(channel(&input));
+
+        // the last sample stays in the interpolator
+        assert_eq!(output.len(), NUM_SAMPLES - 1);
+        for (output, input) in output.iter().zip(&input) {
+            assert!((output - input).norm() < 1e-4, "{output} != {input}");
+        }
+    }
+
+    #[test]
+    fn it_adds_noise() {
+        let input = vec![Complex::new(1.0, 0.0); NUM_SAMPLES];
+        let output = read_all(channel(&input).with_signal_power(2.0).with_snr(10.0));
+
+        let noise_power = output
+            .iter()
+            .map(|sample| (*sample - 1.0).norm_sqr())
+            .sum::<f32>()
+            / output.len() as f32;
+        assert!(
+            (noise_power - 0.2).abs() < 0.01,
+            "noise power: {noise_power}"
+        );
+    }
+
+    #[test]
+    fn it_shifts_the_frequency() {
+        let input = tone_samples(100.0);
+        let output = read_all(channel(&input[..10_000]).with_frequency_offset(-250.0));
+
+        for (i, output) in output.iter().enumerate() {
+            let expected = tone(-150.0, i as f64);
+            assert!(
+                (output - expected).norm() < 1e-3,
+                "{i}: {output} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_drifts() {
+        let input = tone_samples(100.0);
+
+        for drift in [200.0, -200.0] {
+            let output = read_all(channel(&input).with_timing_drift(drift));
+
+            let step = 1.0 + f64::from(drift) * 1e-6;
+            let expected_length = (NUM_SAMPLES - 1) as f64 / step;
+            assert!((output.len() as f64 - expected_length).abs() <= 1.0);
+
+            // the output is sampled at multiples of the step
+            for (i, output) in output.iter().enumerate() {
+                let expected = tone(100.0, i as f64 * step);
+                assert!(
+                    (output - expected).norm() < 1e-3,
+                    "{i}: {output} != {expected}"
+                );
+            }
+        }
+    }
+}
@@ -12,9 +12,11 @@ use crate::{
     io::{
         AsyncReadSamples,
         FiniteStream,
+        GetSampleIndexMap,
         GetSampleRate,
         ReadBuf,
         Remaining,
+        SampleIndexMap,
         StreamLength,
         combinators::{
             ConvertScanner,
@@ -75,6 +77,16 @@ where
     }
 }
 
+impl<R, S, Q> GetSampleIndexMap for Converted<R, S, Q>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<R, S, Q> StreamLength for Converted<R, S, Q>
 where
     R: StreamLength,
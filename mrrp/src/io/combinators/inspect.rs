@@ -17,9 +17,11 @@ use tracing::Span;
 use crate::io::{
     AsyncReadSamples,
     FiniteStream,
+    GetSampleIndexMap,
     GetSampleRate,
     ReadBuf,
     Remaining,
+    SampleIndexMap,
     StreamLength,
 };
 
@@ -37,6 +39,11 @@ impl<R, I> InspectWith<R, I> {
     pub fn new(inner: R, inspector: I) -> Self {
         Self { inner, inspector }
     }
+
+    #[inline]
+    pub fn inspector(&self) -> &I {
+        &self.inspector
+    }
 }
 
 impl<R, I, S> AsyncReadSamples<S> for InspectWith<R, I>
@@ -69,6 +76,16 @@ where
     }
 }
 
+impl<R, I> GetSampleIndexMap for InspectWith<R, I>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<R, I> StreamLength for InspectWith<R, I>
 where
     R: StreamLength,
@@ -124,6 +141,16 @@ where
     }
 }
 
+impl<R, F> GetSampleIndexMap for Inspect<R, F>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<R, F> StreamLength for Inspect<R, F>
 where
     R: StreamLength,
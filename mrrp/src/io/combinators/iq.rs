@@ -0,0 +1,161 @@
+//! IQ corrections
+//!
+//! Some sources deliver IQ samples with the spectrum mirrored, e.g. because
+//! I and Q are swapped in the hardware, or because the tuner mixes with a
+//! local oscillator above the signal. These scanners undo that. They are the
+//! first step when conditioning a source, before any filtering, decimation or
+//! demodulation:
+//!
+//! ```ignore
+//! let samples = source.conjugate().decimate(4);
+//! ```
+//!
+//! - [`Conjugate`] mirrors the spectrum around 0 Hz. Use this if the displayed
+//!   spectrum looks mirrored.
+//! - [`SwapIq`] swaps the I and Q components. This mirrors the spectrum too,
+//!   but also rotates the phase by 90°. Use this if the source's I/Q wiring is
+//!   swapped and the absolute phase matters.
+//! - [`InvertSpectrum`] negates every other sample, which shifts the spectrum
+//!   by half the sample rate. For real samples this mirrors the spectrum around
+//!   a quarter of the sample rate.
+
+use std::ops::Neg;
+
+use num_complex::Complex;
+
+use crate::io::combinators::{
+    GroupDelay,
+    Scanner,
+};
+
+/// Conjugates complex samples, mirroring the spectrum around 0 Hz.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Conjugate;
+
+impl<T> Scanner<Complex<T>> for Conjugate
+where
+    T: Clone + Neg<Output = T>,
+{
+    type Output = Complex<T>;
+
+    #[inline]
+    fn scan(&mut self, sample: Complex<T>) -> Self::Output {
+        Complex::new(sample.re, -sample.im)
+    }
+}
+
+impl GroupDelay for Conjugate {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Swaps the real and imaginary parts of complex samples.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SwapIq;
+
+impl<T> Scanner<Complex<T>> for SwapIq {
+    type Output = Complex<T>;
+
+    #[inline]
+    fn scan(&mut self, sample: Complex<T>) -> Self::Output {
+        Complex::new(sample.im, sample.re)
+    }
+}
+
+impl GroupDelay for SwapIq {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Negates every other sample, shifting the spectrum by half the sample
+/// rate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InvertSpectrum {
+    odd: bool,
+}
+
+impl<S> Scanner<S> for InvertSpectrum
+where
+    S: Neg<Output = S>,
+{
+    type Output = S;
+
+    #[inline]
+    fn scan(&mut self, sample: S) -> Self::Output {
+        let odd = self.odd;
+        self.odd = !odd;
+        if odd { -sample } else { sample }
+    }
+}
+
+impl GroupDelay for InvertSpectrum {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use crate::io::{
+        AsyncReadSamples,
+        AsyncReadSamplesExt,
+        Cursor,
+    };
+
+    fn read_all<R>(mut stream: R) -> Vec<Complex<f32>>
+    where
+        R: AsyncReadSamples<Complex<f32>, Error = Infallible> + Unpin,
+    {
+        let mut output = vec![];
+        stream
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn it_conjugates_swaps_and_inverts() {
+        let input = vec![
+            Complex::new(1.0, 2.0),
+            Complex::new(3.0, -4.0),
+            Complex::new(-5.0, 6.0),
+        ];
+
+        assert_eq!(
+            read_all(Cursor::new(input.clone()).conjugate()),
+            [
+                Complex::new(1.0, -2.0),
+                Complex::new(3.0, 4.0),
+                Complex::new(-5.0, -6.0)
+            ]
+        );
+        assert_eq!(
+            read_all(Cursor::new(input.clone()).swap_iq()),
+            [
+                Complex::new(2.0, 1.0),
+                Complex::new(-4.0, 3.0),
+                Complex::new(6.0, -5.0)
+            ]
+        );
+        assert_eq!(
+            read_all(Cursor::new(input).invert_spectrum()),
+            [
+                Complex::new(1.0, 2.0),
+                Complex::new(-3.0, 4.0),
+                Complex::new(-5.0, 6.0)
+            ]
+        );
+    }
+}
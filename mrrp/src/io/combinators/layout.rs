@@ -0,0 +1,538 @@
+//! Sample layouts
+//!
+//! Some sources don't deliver one complex sample after the other:
+//!
+//! - [`UnpackI12`] decodes IQ pairs of 12-bit samples that are packed into 3
+//!   bytes, see [`I12::unpack_pair`].
+//! - [`Interleave`] turns planar blocks, i.e. first all I and then all Q
+//!   components of a block, into complex samples. [`Deinterleave`] does the
+//!   opposite, e.g. to write files for tools that expect planar data.
+
+use std::{
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+
+use crate::{
+    buf::SampleBufMut,
+    io::{
+        AsyncReadSamples,
+        FiniteStream,
+        GetSampleIndexMap,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        SampleIndexMap,
+        ScratchBuffer,
+        StreamLength,
+    },
+    sample::I12,
+};
+
+pin_project! {
+    /// Unpacks IQ pairs of 12-bit samples from a byte stream.
+    ///
+    /// Every pair takes 3 bytes. A trailing partial pair at the end of the
+    /// stream is dropped.
+    #[derive(Clone, Debug)]
+    pub struct UnpackI12<R> {
+        #[pin]
+        inner: R,
+        partial: [u8; 3],
+        num_partial: usize,
+        intermediate_buffer: ScratchBuffer<u8>,
+    }
+}
+
+impl<R> UnpackI12<R> {
+    #[inline]
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            partial: [0; 3],
+            num_partial: 0,
+            intermediate_buffer: ScratchBuffer::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R> AsyncReadSamples<Complex<I12>> for UnpackI12<R>
+where
+    R: AsyncReadSamples<u8>,
+{
+    type Error = R::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<Complex<I12>>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        if buffer.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            let read_length = 3 * buffer.remaining() - *this.num_partial;
+            let read_length = this.intermediate_buffer.reserve(read_length);
+            let mut read_buf = ReadBuf::uninit(&mut this.intermediate_buffer.buffer[..read_length]);
+
+            ready!(this.inner.as_mut().poll_read_samples(cx, &mut read_buf))?;
+
+            let bytes = read_buf.filled();
+            if bytes.is_empty() {
+                // end of stream
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut num_samples = 0;
+            for &byte in bytes {
+                this.partial[*this.num_partial] = byte;
+                *this.num_partial += 1;
+
+                if *this.num_partial == 3 {
+                    let (i, q) = I12::unpack_pair(*this.partial);
+                    buffer.put_sample(Complex::new(i, q));
+                    *this.num_partial = 0;
+                    num_samples += 1;
+                }
+            }
+
+            // an empty read would look like the end of the stream, so keep reading
+            // until we have a full pair.
+            if num_samples > 0 {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<R> GetSampleRate for UnpackI12<R>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate() / 3.0
+    }
+}
+
+impl<R> GetSampleIndexMap for UnpackI12<R>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner
+            .sample_index_map()
+            .then(SampleIndexMap::decimation(3))
+    }
+}
+
+impl<R> StreamLength for UnpackI12<R>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        let buffered = self.num_partial;
+        self.inner
+            .remaining()
+            .map(|num_bytes| (num_bytes + buffered) / 3)
+    }
+}
+
+impl<R> FiniteStream for UnpackI12<R> where R: FiniteStream {}
+
+pin_project! {
+    /// Turns planar blocks into complex samples.
+    ///
+    /// Every block of the inner stream has `block_size` I components followed
+    /// by `block_size` Q components. A trailing partial block at the end of
+    /// the stream is dropped.
+    #[derive(Clone, Debug)]
+    pub struct Interleave<R, T> {
+        #[pin]
+        inner: R,
+        block: Vec<T>,
+        num_filled: usize,
+        read_pos: usize,
+    }
+}
+
+impl<R, T> Interleave<R, T>
+where
+    T: Default + Clone,
+{
+    /// # Panics
+    ///
+    /// Panics if `block_size` is 0.
+    #[inline]
+    pub fn new(inner: R, block_size: usize) -> Self {
+        assert!(block_size > 0, "block size must not be 0");
+        Self {
+            inner,
+            block: vec![T::default(); 2 * block_size],
+            num_filled: 0,
+            read_pos: 0,
+        }
+    }
+}
+
+impl<R, T> Interleave<R, T> {
+    #[inline]
+    pub fn block_size(&self) -> usize {
+        self.block.len() / 2
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R, T> AsyncReadSamples<Complex<T>> for Interleave<R, T>
+where
+    R: AsyncReadSamples<T>,
+    T: Copy,
+{
+    type Error = R::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<Complex<T>>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        let block_size = this.block.len() / 2;
+
+        if buffer.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        // fill the block, since the first sample needs its Q component from the
+        // second half
+        while *this.num_filled < this.block.len() {
+            let mut read_buf = ReadBuf::new(&mut this.block[*this.num_filled..]);
+            ready!(this.inner.as_mut().poll_read_samples(cx, &mut read_buf))?;
+
+            let num_read = read_buf.filled().len();
+            if num_read == 0 {
+                // end of stream
+                return Poll::Ready(Ok(()));
+            }
+            *this.num_filled += num_read;
+        }
+
+        let (i, q) = this.block.split_at(block_size);
+        while *this.read_pos < block_size && buffer.has_remaining_mut() {
+            buffer.put_sample(Complex::new(i[*this.read_pos], q[*this.read_pos]));
+            *this.read_pos += 1;
+        }
+
+        if *this.read_pos == block_size {
+            *this.num_filled = 0;
+            *this.read_pos = 0;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R, T> GetSampleRate for Interleave<R, T>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate() / 2.0
+    }
+}
+
+impl<R, T> GetSampleIndexMap for Interleave<R, T>
+where
+    R: GetSampleIndexMap,
+{
+    /// This is only exact at block boundaries.
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner
+            .sample_index_map()
+            .then(SampleIndexMap::decimation(2))
+    }
+}
+
+impl<R, T> StreamLength for Interleave<R, T>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        let block_size = self.block.len() / 2;
+        let buffered = self.num_filled;
+        let read_pos = self.read_pos;
+        self.inner.remaining().map(|num_samples| {
+            // only whole blocks come out
+            (num_samples + buffered) / (2 * block_size) * block_size - read_pos
+        })
+    }
+}
+
+impl<R, T> FiniteStream for Interleave<R, T> where R: FiniteStream {}
+
+pin_project! {
+    /// Turns complex samples into planar blocks.
+    ///
+    /// Every output block has `block_size` I components followed by
+    /// `block_size` Q components. A trailing partial block at the end of the
+    /// stream is dropped.
+    #[derive(Clone, Debug)]
+    pub struct Deinterleave<R, T> {
+        #[pin]
+        inner: R,
+        block: Vec<Complex<T>>,
+        num_filled: usize,
+        read_pos: usize,
+    }
+}
+
+impl<R, T> Deinterleave<R, T>
+where
+    T: Default + Clone,
+{
+    /// # Panics
+    ///
+    /// Panics if `block_size` is 0.
+    #[inline]
+    pub fn new(inner: R, block_size: usize) -> Self {
+        assert!(block_size > 0, "block size must not be 0");
+        Self {
+            inner,
+            block: vec![Complex::default(); block_size],
+            num_filled: 0,
+            read_pos: 0,
+        }
+    }
+}
+
+impl<R, T> Deinterleave<R, T> {
+    #[inline]
+    pub fn block_size(&self) -> usize {
+        self.block.len()
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R, T> AsyncReadSamples<T> for Deinterleave<R, T>
+where
+    R: AsyncReadSamples<Complex<T>>,
+    T: Copy,
+{
+    type Error = R::Error;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<T>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        let block_size = this.block.len();
+
+        if buffer.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        // fill the block, since the Q components come after all I components
+        while *this.num_filled < block_size {
+            let mut read_buf = ReadBuf::new(&mut this.block[*this.num_filled..]);
+            ready!(this.inner.as_mut().poll_read_samples(cx, &mut read_buf))?;
+
+            let num_read = read_buf.filled().len();
+            if num_read == 0 {
+                // end of stream
+                return Poll::Ready(Ok(()));
+            }
+            *this.num_filled += num_read;
+        }
+
+        while *this.read_pos < 2 * block_size && buffer.has_remaining_mut() {
+            let sample = if *this.read_pos < block_size {
+                this.block[*this.read_pos].re
+            }
+            else {
+                this.block[*this.read_pos - block_size].im
+            };
+            buffer.put_sample(sample);
+            *this.read_pos += 1;
+        }
+
+        if *this.read_pos == 2 * block_size {
+            *this.num_filled = 0;
+            *this.read_pos = 0;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R, T> GetSampleRate for Deinterleave<R, T>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate() * 2.0
+    }
+}
+
+impl<R, T> GetSampleIndexMap for Deinterleave<R, T>
+where
+    R: GetSampleIndexMap,
+{
+    /// This is only exact at block boundaries.
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner
+            .sample_index_map()
+            .then(SampleIndexMap::interpolation(2))
+    }
+}
+
+impl<R, T> StreamLength for Deinterleave<R, T>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        let block_size = self.block.len();
+        let buffered = self.num_filled;
+        let read_pos = self.read_pos;
+        self.inner.remaining().map(|num_samples| {
+            // only whole blocks come out
+            (num_samples + buffered) / block_size * 2 * block_size - read_pos
+        })
+    }
+}
+
+impl<R, T> FiniteStream for Deinterleave<R, T> where R: FiniteStream {}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+            test::SingleSampleStream,
+        },
+        sample::{
+            FromSample,
+            I12,
+        },
+    };
+
+    #[test]
+    fn it_packs_and_unpacks_12_bit_pairs() {
+        for (i, q) in [(0, 0), (1, -1), (2047, -2048), (-1234, 567)] {
+            let (i, q) = (I12::new(i).unwrap(), I12::new(q).unwrap());
+            assert_eq!(I12::unpack_pair(I12::pack_pair(i, q)), (i, q));
+        }
+
+        // I = 0x321, Q = 0x654
+        assert_eq!(
+            I12::unpack_pair([0x21, 0x43, 0x65]),
+            (I12::new(0x321).unwrap(), I12::new(0x654).unwrap())
+        );
+        assert_eq!(f32::from_sample(I12::new(-2048).unwrap()), -1.0);
+        assert_eq!(i16::from_sample(I12::new(-1).unwrap()), -16);
+    }
+
+    #[test]
+    fn it_unpacks_a_12_bit_stream() {
+        let pairs = [(100, -200), (-2048, 2047), (0, 1)];
+        let mut bytes = pairs
+            .iter()
+            .flat_map(|(i, q)| I12::pack_pair(I12::new(*i).unwrap(), I12::new(*q).unwrap()))
+            .collect::<Vec<u8>>();
+        // partial pair at the end
+        bytes.push(0xff);
+
+        // bytes arrive one at a time, so pairs are split across reads
+        let mut samples = vec![];
+        SingleSampleStream::new(Cursor::new(bytes))
+            .unpack_i12()
+            .read_to_end(&mut samples)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+
+        assert_eq!(
+            samples,
+            pairs
+                .iter()
+                .map(|(i, q)| Complex::new(I12::new(*i).unwrap(), I12::new(*q).unwrap()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_converts_between_planar_and_interleaved() {
+        let interleaved = (0..6).map(|i| Complex::new(i, 10 + i)).collect::<Vec<_>>();
+        let planar = [0, 1, 2, 10, 11, 12, 3, 4, 5, 13, 14, 15];
+
+        let mut output = vec![];
+        SingleSampleStream::new(Cursor::new(interleaved.clone()))
+            .deinterleave(3)
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, planar);
+
+        // with a partial block at the end
+        let mut planar = planar.to_vec();
+        planar.extend([6, 16]);
+        let mut output = vec![];
+        Cursor::new(planar)
+            .interleave(3)
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, interleaved);
+    }
+}
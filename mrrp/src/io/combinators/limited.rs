@@ -12,9 +12,11 @@ use pin_project_lite::pin_project;
 use crate::io::{
     AsyncReadSamples,
     FiniteStream,
+    GetSampleIndexMap,
     GetSampleRate,
     ReadBuf,
     Remaining,
+    SampleIndexMap,
     SizeHint,
     StreamLength,
 };
@@ -78,6 +80,16 @@ where
     }
 }
 
+impl<R> GetSampleIndexMap for Limited<R>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<R> StreamLength for Limited<R>
 where
     R: StreamLength,
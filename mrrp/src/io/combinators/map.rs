@@ -13,9 +13,11 @@ use pin_project_lite::pin_project;
 use crate::io::{
     AsyncReadSamples,
     FiniteStream,
+    GetSampleIndexMap,
     GetSampleRate,
     ReadBuf,
     Remaining,
+    SampleIndexMap,
     StreamLength,
     combinators::scan::{
         FuncScanner,
@@ -76,6 +78,16 @@ where
     }
 }
 
+impl<R, S, F> GetSampleIndexMap for Map<R, S, F>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<R, S, F> StreamLength for Map<R, S, F>
 where
     R: StreamLength,
@@ -133,6 +145,16 @@ where
     }
 }
 
+impl<R, F> GetSampleIndexMap for MapInPlace<R, F>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<R, F> StreamLength for MapInPlace<R, F>
 where
     R: StreamLength,
@@ -283,6 +305,16 @@ where
     }
 }
 
+impl<R, S, F> GetSampleIndexMap for MapInPlacePod<R, S, F>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<R, S, F> StreamLength for MapInPlacePod<R, S, F>
 where
     R: StreamLength,
@@ -11,9 +11,11 @@ use pin_project_lite::pin_project;
 use crate::io::{
     AsyncReadSamples,
     FiniteStream,
+    GetSampleIndexMap,
     GetSampleRate,
     ReadBuf,
     Remaining,
+    SampleIndexMap,
     StreamLength,
 };
 
@@ -64,6 +66,16 @@ where
     }
 }
 
+impl<R, F> GetSampleIndexMap for MapErr<R, F>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<R, F> StreamLength for MapErr<R, F>
 where
     R: StreamLength,
@@ -1,22 +1,51 @@
+mod agc;
+mod bits;
+mod broadcast;
 mod buffered;
+mod cancellable;
 mod chained;
+mod channel;
 mod converted;
 mod inspect;
+mod iq;
+mod layout;
 mod limited;
 mod map;
 mod map_err;
 mod repeated;
 mod scan;
+mod scope;
+mod sink;
+mod stats;
+mod switch;
+mod tap;
 mod throttled;
 mod with_samplerate;
 mod with_span;
 mod zip_with;
 
-pub use buffered::Buffered;
+pub use agc::Agc;
+pub use bits::{
+    PackBits,
+    UnpackBits,
+};
+pub use broadcast::{
+    Broadcast,
+    BroadcastError,
+    BroadcastReceiver,
+    DEFAULT_BROADCAST_CAPACITY,
+    LagPolicy,
+};
+pub use buffered::{
+    Buffered,
+    Prefetch,
+};
+pub use cancellable::UntilCancelled;
 pub use chained::{
     Chained,
     ChainedError,
 };
+pub use channel::ChannelSimulator;
 pub use converted::Converted;
 pub use inspect::{
     Inspect,
@@ -25,6 +54,16 @@ pub use inspect::{
     LogSampleRateInspector,
     LogSamplesInspector,
 };
+pub use iq::{
+    Conjugate,
+    InvertSpectrum,
+    SwapIq,
+};
+pub use layout::{
+    Deinterleave,
+    Interleave,
+    UnpackI12,
+};
 pub use limited::Limited;
 pub use map::{
     Map,
@@ -32,15 +71,52 @@ pub use map::{
     MapInPlacePod,
 };
 pub use map_err::MapErr;
-pub use repeated::Repeated;
+pub use repeated::{
+    Repeated,
+    RepeatedError,
+};
 pub use scan::{
     Chain,
     ConvertScanner,
     FuncScanner,
+    GroupDelay,
     ScanInPlaceWith,
     ScanWith,
     Scanner,
     ScannerExt,
+    SomeScanner,
+};
+pub use scope::{
+    ScopeFrame,
+    ScopeHandle,
+    ScopeInspector,
+    Trigger,
+    TriggerValue,
+    WithScope,
+};
+pub use sink::{
+    BufferedSink,
+    ConvertedSink,
+    MappedSink,
+    ThrottledSink,
+};
+pub use stats::{
+    Power,
+    Stats,
+    StatsHandle,
+    StatsInspector,
+    WithStats,
+};
+pub use switch::{
+    Switch,
+    SwitchHandle,
+    SwitchInput,
+};
+pub use tap::{
+    TapHandle,
+    TapInspector,
+    TapSegment,
+    TapToFile,
 };
 pub use throttled::Throttled;
 pub use with_samplerate::WithSampleRate;
@@ -1,18 +1,29 @@
 use std::{
+    future::poll_fn,
     pin::Pin,
     task::{
         Context,
         Poll,
+        ready,
     },
 };
 
 use pin_project_lite::pin_project;
 
 use crate::{
-    buf::SampleBufMut,
+    buf::{
+        BufferLimitExceeded,
+        BufferPolicy,
+        PolicyAllocator,
+        SampleBufMut,
+        UninitSlice,
+    },
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
     io::{
         AsyncReadSamples,
-        AsyncReadSamplesExt,
         FiniteStream,
         ReadBuf,
         Remaining,
@@ -23,46 +34,110 @@ use crate::{
 pin_project! {
     /// Repeats another stream indefinitely.
     ///
-    /// Be warned that this will try to buffer all samples from the input stream in
-    /// order to repeat them. If the input stream is not limited in length this will
-    /// exhaust your memory.
+    /// This buffers all samples from the input stream in order to repeat
+    /// them. The buffer is limited by the [`BufferPolicy`] at the time the
+    /// stream is created, and reading fails with
+    /// [`RepeatedError::BufferLimit`] once the input stream is longer than
+    /// that. With the default policy there is no limit, so an input stream
+    /// that is not limited in length will exhaust your memory.
     #[derive(Clone, Debug)]
     pub struct Repeated<R, S> {
         #[pin]
         inner: R,
         inner_exhausted: bool,
-        buffer: Vec<S>,
+        buffer: Vec<S, PolicyAllocator>,
         read_pos: usize,
+        policy: BufferPolicy,
     }
 }
 
 impl<R, S> Repeated<R, S> {
     #[inline]
     pub fn new(inner: R) -> Self {
+        let policy = BufferPolicy::global();
         Self {
             inner,
             inner_exhausted: false,
-            buffer: vec![],
+            buffer: Vec::new_in(policy.allocator().clone()),
             read_pos: 0,
+            policy,
         }
     }
 
-    #[inline]
-    pub async fn prefetch(&mut self) -> Result<(), R::Error>
+    /// Reads the whole input stream into the buffer.
+    pub async fn prefetch(&mut self) -> Result<(), RepeatedError<R::Error>>
     where
         R: AsyncReadSamples<S> + FiniteStream + Unpin,
     {
-        self.inner.read_to_end(&mut self.buffer).await
+        poll_fn(|cx| {
+            while !self.inner_exhausted {
+                let length = self.policy.preferred_chunk_size();
+                ready!(Pin::new(&mut *self).poll_fill(cx, length))?;
+            }
+            Poll::Ready(Ok(()))
+        })
+        .await
     }
 
     #[inline]
-    pub async fn prefetched(mut self) -> Result<Self, R::Error>
+    pub async fn prefetched(mut self) -> Result<Self, RepeatedError<R::Error>>
     where
         R: AsyncReadSamples<S> + FiniteStream + Unpin,
     {
         self.prefetch().await?;
         Ok(self)
     }
+
+    /// Reads up to `length` samples from the input stream into the buffer.
+    fn poll_fill(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        length: usize,
+    ) -> Poll<Result<(), RepeatedError<R::Error>>>
+    where
+        R: AsyncReadSamples<S>,
+    {
+        let this = self.project();
+
+        let buffered = this.buffer.len();
+        this.policy.check::<S>(buffered)?;
+
+        // read at least one sample past the limit, so we notice if the stream is
+        // longer
+        let max_len = this.policy.max_samples::<S>();
+        let length = length.min(max_len.saturating_sub(buffered).max(1));
+
+        if this.buffer.capacity() - buffered < length {
+            let capacity = if buffered == 0 {
+                this.policy.buffer_size::<S>(this.inner.size_hint())
+            }
+            else {
+                2 * buffered
+            };
+            let capacity = capacity
+                .max(buffered + length)
+                .min(max_len.saturating_add(1));
+            this.buffer.reserve_exact(capacity - buffered);
+        }
+
+        let spare = &mut this.buffer.spare_capacity_mut()[..length];
+        let mut read_buf = ReadBuf::uninit(UninitSlice::slice_mut_from_uninit(spare));
+        ready!(this.inner.poll_read_samples(cx, &mut read_buf)).map_err(RepeatedError::Inner)?;
+        let num_samples = read_buf.filled().len();
+        unsafe {
+            read_buf.drop_unfilled_initialized();
+            this.buffer.set_len(buffered + num_samples);
+        }
+
+        if num_samples == 0 {
+            *this.inner_exhausted = true;
+        }
+        else {
+            this.policy.check::<S>(this.buffer.len())?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
 }
 
 impl<R, S> AsyncReadSamples<S> for Repeated<R, S>
@@ -70,51 +145,41 @@ where
     S: Clone,
     R: AsyncReadSamples<S>,
 {
-    type Error = R::Error;
+    type Error = RepeatedError<R::Error>;
 
     fn poll_read_samples(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buffer: &mut ReadBuf<S>,
     ) -> Poll<Result<(), Self::Error>> {
+        let n = buffer.remaining_mut();
+        if n == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
         loop {
             let this = self.as_mut().project();
 
-            if *this.inner_exhausted {
-                let n = buffer.remaining_mut();
-                if n == 0 {
-                    return Poll::Ready(Ok(()));
-                }
-
+            if *this.read_pos < this.buffer.len() {
                 let n = n.min(this.buffer.len() - *this.read_pos);
-
                 buffer.put_slice(&this.buffer[*this.read_pos..][..n]);
 
                 *this.read_pos += n;
-                if *this.read_pos == this.buffer.len() {
+                if *this.inner_exhausted && *this.read_pos == this.buffer.len() {
                     *this.read_pos = 0;
                 }
+
+                return Poll::Ready(Ok(()));
+            }
+            else if *this.inner_exhausted {
+                // the input stream was empty
+                return Poll::Ready(Ok(()));
             }
             else {
-                if this.buffer.is_empty() {
-                    this.buffer
-                        .reserve(this.inner.size_hint().buffer_size(buffer.remaining_mut()));
-                }
-
-                let filled_before = buffer.filled().len();
-                match this.inner.poll_read_samples(cx, buffer) {
-                    Poll::Pending => return Poll::Pending,
-                    Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
-                    Poll::Ready(Ok(())) => {
-                        if buffer.filled().len() == filled_before {
-                            *this.inner_exhausted = true;
-                        }
-                        else {
-                            this.buffer
-                                .extend(buffer.filled()[filled_before..].iter().cloned());
-                            return Poll::Ready(Ok(()));
-                        }
-                    }
+                ready!(self.as_mut().poll_fill(cx, n))?;
+                let this = self.as_mut().project();
+                if *this.inner_exhausted {
+                    *this.read_pos = 0;
                 }
             }
         }
@@ -127,3 +192,64 @@ impl<R, S> StreamLength for Repeated<R, S> {
         Remaining::Infinite
     }
 }
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+pub enum RepeatedError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error(transparent)]
+    BufferLimit(#[from] BufferLimitExceeded),
+}
+
+impl<E: ClassifyError> ClassifyError for RepeatedError<E> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            RepeatedError::Inner(error) => error.error_kind(),
+            RepeatedError::BufferLimit(error) => error.error_kind(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::{
+        Repeated,
+        RepeatedError,
+    };
+    use crate::{
+        buf::BufferPolicy,
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+        },
+    };
+
+    #[test]
+    fn it_repeats() {
+        let samples = [1u8, 2, 3];
+        let mut repeated = Repeated::new(Cursor::new(&samples[..]));
+        let mut output = [0; 8];
+        repeated
+            .read_samples_exact(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, [1, 2, 3, 1, 2, 3, 1, 2]);
+    }
+
+    #[test]
+    fn it_fails_past_the_buffer_limit() {
+        let samples = [0u32; 100];
+        let mut repeated = Repeated::new(Cursor::new(&samples[..]));
+        repeated.policy = BufferPolicy::new().with_max_buffer_bytes(Some(256));
+
+        let result = repeated
+            .prefetch()
+            .now_or_never()
+            .expect("test stream pending");
+        assert!(matches!(result, Err(RepeatedError::BufferLimit(_))));
+    }
+}
@@ -18,9 +18,11 @@ use crate::{
     io::{
         AsyncReadSamples,
         FiniteStream,
+        GetSampleIndexMap,
         GetSampleRate,
         ReadBuf,
         Remaining,
+        SampleIndexMap,
         ScratchBuffer,
         StreamLength,
     },
@@ -82,7 +84,7 @@ where
         let this = self.project();
 
         let read_length = (*this.max_buffer_size).min(buffer.remaining());
-        this.intermediate_buffer.reserve(read_length);
+        let read_length = this.intermediate_buffer.reserve(read_length);
 
         let mut read_buf = ReadBuf::uninit(&mut this.intermediate_buffer.buffer[..read_length]);
 
@@ -99,6 +101,11 @@ where
                     buffer.put_sample(sample);
                 }
 
+                if filled == 0 && read_length > 0 {
+                    // end of stream
+                    finish_into::<S, _>(this.scanner, buffer);
+                }
+
                 Poll::Ready(Ok(()))
             }
         }
@@ -115,6 +122,19 @@ where
     }
 }
 
+impl<R, S, Sc> GetSampleIndexMap for ScanWith<R, S, Sc>
+where
+    R: GetSampleIndexMap,
+    Sc: GroupDelay,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner
+            .sample_index_map()
+            .then(SampleIndexMap::delay(self.scanner.group_delay()))
+    }
+}
+
 impl<R, S, Sc> StreamLength for ScanWith<R, S, Sc>
 where
     R: StreamLength,
@@ -182,6 +202,11 @@ where
                     filled.write_sample(index, sample_out);
                 }
 
+                if buffer_end == buffer_start && buffer.has_remaining_mut() {
+                    // end of stream
+                    finish_into::<S, _>(this.scanner, buffer);
+                }
+
                 Poll::Ready(Ok(()))
             }
         }
@@ -198,6 +223,19 @@ where
     }
 }
 
+impl<R, Sc> GetSampleIndexMap for ScanInPlaceWith<R, Sc>
+where
+    R: GetSampleIndexMap,
+    Sc: GroupDelay,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner
+            .sample_index_map()
+            .then(SampleIndexMap::delay(self.scanner.group_delay()))
+    }
+}
+
 impl<R, Sc> StreamLength for ScanInPlaceWith<R, Sc>
 where
     R: StreamLength,
@@ -210,10 +248,69 @@ where
 
 impl<R, Sc> FiniteStream for ScanInPlaceWith<R, Sc> where R: FiniteStream {}
 
+/// Drains `scanner` into `buffer` after the inner stream ended.
+fn finish_into<S, Sc>(scanner: &mut Sc, buffer: &mut ReadBuf<Sc::Output>)
+where
+    Sc: Scanner<S>,
+{
+    while buffer.has_remaining_mut() {
+        let Some(sample) = scanner.finish()
+        else {
+            break;
+        };
+        buffer.put_sample(sample);
+    }
+}
+
 pub trait Scanner<S> {
     type Output;
 
     fn scan(&mut self, sample: S) -> Self::Output;
+
+    /// Drains the scanner at the end of a stream.
+    ///
+    /// Scanners with a delay, like FIR filters, still hold the last samples of
+    /// a stream when it ends. The scanning combinators call this until it
+    /// returns `None` when their inner stream ends, so that these samples are
+    /// output too. Filters usually implement this by scanning zeros for their
+    /// group delay.
+    ///
+    /// Once this returned `None`, the scanner must be ready for a new stream,
+    /// and keep returning `None` until it scans again. The length a stream
+    /// reports doesn't include these samples.
+    #[inline]
+    fn finish(&mut self) -> Option<Self::Output> {
+        None
+    }
+}
+
+/// Delay between a scanner's input and output in samples.
+///
+/// Scanning combinators use this to keep their [`SampleIndexMap`] accurate.
+/// For IIR filters this depends on frequency, and they report 0.
+pub trait GroupDelay {
+    fn group_delay(&self) -> f64;
+}
+
+impl<T: GroupDelay + ?Sized> GroupDelay for &mut T {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        (**self).group_delay()
+    }
+}
+
+impl<T: GroupDelay + ?Sized> GroupDelay for Box<T> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        (**self).group_delay()
+    }
+}
+
+impl GroupDelay for () {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
 }
 
 impl<T, S> Scanner<S> for &mut T
@@ -226,6 +323,11 @@ where
     fn scan(&mut self, sample: S) -> Self::Output {
         (&mut **self).scan(sample)
     }
+
+    #[inline]
+    fn finish(&mut self) -> Option<Self::Output> {
+        (**self).finish()
+    }
 }
 
 impl<T, S> Scanner<S> for Box<T>
@@ -238,6 +340,11 @@ where
     fn scan(&mut self, sample: S) -> Self::Output {
         (&mut **self).scan(sample)
     }
+
+    #[inline]
+    fn finish(&mut self) -> Option<Self::Output> {
+        (**self).finish()
+    }
 }
 
 impl<S> Scanner<S> for () {
@@ -270,6 +377,17 @@ pub trait ScannerExt<S>: Scanner<S> {
     {
         self.chain(FuncScanner::new(f))
     }
+
+    /// Chains a scanner that only gets the `Some` outputs of this one, e.g. a
+    /// decoder after a demodulator that outputs a bit per symbol.
+    #[inline]
+    fn chain_some<T, Q, R>(self, other: T) -> Chain<Self, SomeScanner<T>>
+    where
+        Self: Scanner<S, Output = Option<Q>> + Sized,
+        T: Scanner<Q, Output = Option<R>>,
+    {
+        self.chain(SomeScanner::new(other))
+    }
 }
 
 impl<S, T> ScannerExt<S> for T where T: Scanner<S> {}
@@ -280,6 +398,18 @@ pub struct Chain<H, T> {
     tail: T,
 }
 
+impl<H, T> Chain<H, T> {
+    #[inline]
+    pub fn head(&self) -> &H {
+        &self.head
+    }
+
+    #[inline]
+    pub fn tail(&self) -> &T {
+        &self.tail
+    }
+}
+
 impl<H, T, S> Scanner<S> for Chain<H, T>
 where
     H: Scanner<S>,
@@ -291,6 +421,58 @@ where
     fn scan(&mut self, sample: S) -> Self::Output {
         self.tail.scan(self.head.scan(sample))
     }
+
+    #[inline]
+    fn finish(&mut self) -> Option<Self::Output> {
+        // the head's remaining output still goes through the tail
+        match self.head.finish() {
+            Some(sample) => Some(self.tail.scan(sample)),
+            None => self.tail.finish(),
+        }
+    }
+}
+
+impl<H: GroupDelay, T: GroupDelay> GroupDelay for Chain<H, T> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        self.head.group_delay() + self.tail.group_delay()
+    }
+}
+
+/// Scans only the `Some` samples, and flattens the output. See
+/// [`ScannerExt::chain_some`].
+#[derive(Clone, Copy, Debug)]
+pub struct SomeScanner<T> {
+    inner: T,
+}
+
+impl<T> SomeScanner<T> {
+    #[inline]
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T, S, Q> Scanner<Option<S>> for SomeScanner<T>
+where
+    T: Scanner<S, Output = Option<Q>>,
+{
+    type Output = Option<Q>;
+
+    #[inline]
+    fn scan(&mut self, sample: Option<S>) -> Self::Output {
+        self.inner.scan(sample?)
+    }
+
+    #[inline]
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.inner.finish()
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -316,6 +498,13 @@ where
     }
 }
 
+impl<F> GroupDelay for FuncScanner<F> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct ConvertScanner<Q> {
     _phantom: PhantomData<fn() -> Q>,
@@ -342,6 +531,13 @@ where
     }
 }
 
+impl<Q> GroupDelay for ConvertScanner<Q> {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct SumScanner;
 
@@ -0,0 +1,459 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+use num_complex::Complex;
+use parking_lot::Mutex;
+use pin_project_lite::pin_project;
+
+use crate::io::{
+    AsyncReadSamples,
+    FiniteStream,
+    GetSampleIndexMap,
+    GetSampleRate,
+    ReadBuf,
+    Remaining,
+    SampleIndexMap,
+    StreamLength,
+    combinators::{
+        InspectWith,
+        Inspector,
+    },
+};
+
+/// Samples that a [`WithScope`] can trigger on.
+pub trait TriggerValue: Copy {
+    /// The value compared against the trigger level.
+    fn trigger_value(self) -> f32;
+}
+
+impl TriggerValue for f32 {
+    #[inline]
+    fn trigger_value(self) -> f32 {
+        self
+    }
+}
+
+impl TriggerValue for Complex<f32> {
+    /// Complex samples trigger on their magnitude.
+    #[inline]
+    fn trigger_value(self) -> f32 {
+        self.norm()
+    }
+}
+
+/// When the scope starts capturing a frame.
+///
+/// A trigger can always be forced with [`ScopeHandle::trigger`], regardless
+/// of the mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Trigger {
+    /// Captures frames back to back.
+    #[default]
+    FreeRun,
+
+    /// Triggers when the signal crosses `level` from below.
+    RisingEdge { level: f32 },
+
+    /// Only triggers through [`ScopeHandle::trigger`], e.g. from a detector.
+    External,
+}
+
+/// A triggered snapshot of a stream.
+#[derive(Clone, Debug)]
+pub struct ScopeFrame<S> {
+    /// The captured samples.
+    pub samples: Vec<S>,
+
+    /// Index into [`samples`][Self::samples] of the sample that triggered
+    /// the capture. The samples before it are the pre-trigger history.
+    pub trigger_offset: usize,
+
+    /// Number of samples the scope had seen before the triggering sample.
+    pub trigger_index: u64,
+
+    /// What triggered the capture.
+    pub trigger: Trigger,
+}
+
+#[derive(Debug)]
+struct Shared<S> {
+    trigger: Trigger,
+    force_trigger: bool,
+    latest: Option<Arc<ScopeFrame<S>>>,
+    num_frames: u64,
+}
+
+/// Cloneable handle to read the frames captured by a [`WithScope`] stream,
+/// and to control its trigger.
+#[derive(Debug)]
+pub struct ScopeHandle<S> {
+    shared: Arc<Mutex<Shared<S>>>,
+}
+
+impl<S> Clone for ScopeHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<S> ScopeHandle<S> {
+    /// The most recently captured frame.
+    pub fn latest(&self) -> Option<Arc<ScopeFrame<S>>> {
+        self.shared.lock().latest.clone()
+    }
+
+    /// Number of frames captured so far.
+    ///
+    /// This can be used to tell whether [`latest`][Self::latest] changed.
+    pub fn num_frames(&self) -> u64 {
+        self.shared.lock().num_frames
+    }
+
+    pub fn trigger_mode(&self) -> Trigger {
+        self.shared.lock().trigger
+    }
+
+    /// Changes the trigger mode.
+    ///
+    /// A frame that is currently being captured is finished first.
+    pub fn set_trigger_mode(&self, trigger: Trigger) {
+        self.shared.lock().trigger = trigger;
+    }
+
+    /// Triggers a capture at the next sample, or right after the frame that
+    /// is currently being captured.
+    pub fn trigger(&self) {
+        self.shared.lock().force_trigger = true;
+    }
+}
+
+/// Captures triggered frames of fixed size. See [`WithScope`].
+#[derive(Debug)]
+pub struct ScopeInspector<S> {
+    frame_length: usize,
+    pre_trigger: usize,
+    history: VecDeque<S>,
+    capture: Option<ScopeFrame<S>>,
+    previous_value: Option<f32>,
+    num_samples: u64,
+    shared: Arc<Mutex<Shared<S>>>,
+
+    /// Buffer of a frame that was replaced before it was published.
+    spare_samples: Vec<S>,
+
+    /// The frame that was published before the latest one. Once nobody holds
+    /// it anymore, its allocations are reused for the next frame.
+    recycled: Option<Arc<ScopeFrame<S>>>,
+}
+
+impl<S> ScopeInspector<S> {
+    /// Creates an inspector that captures frames of `frame_length` samples,
+    /// of which `pre_trigger` samples are from before the trigger.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pre_trigger` is not less than `frame_length`.
+    pub fn new(frame_length: usize, pre_trigger: usize, trigger: Trigger) -> Self {
+        assert!(
+            pre_trigger < frame_length,
+            "pre-trigger must be shorter than the frame"
+        );
+
+        Self {
+            frame_length,
+            pre_trigger,
+            history: VecDeque::with_capacity(pre_trigger),
+            capture: None,
+            previous_value: None,
+            num_samples: 0,
+            shared: Arc::new(Mutex::new(Shared {
+                trigger,
+                force_trigger: false,
+                latest: None,
+                num_frames: 0,
+            })),
+            spare_samples: vec![],
+            recycled: None,
+        }
+    }
+
+    pub fn handle(&self) -> ScopeHandle<S> {
+        ScopeHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    #[inline]
+    pub fn frame_length(&self) -> usize {
+        self.frame_length
+    }
+
+    #[inline]
+    pub fn pre_trigger(&self) -> usize {
+        self.pre_trigger
+    }
+
+    /// An empty buffer for a new frame, reusing an old one if possible.
+    fn frame_buffer(&mut self) -> Vec<S> {
+        let mut samples = std::mem::take(&mut self.spare_samples);
+        if samples.capacity() == 0
+            && let Some(recycled) = self.recycled.as_mut().and_then(Arc::get_mut)
+        {
+            samples = std::mem::take(&mut recycled.samples);
+        }
+        samples.clear();
+        samples.reserve(self.frame_length);
+        samples
+    }
+
+    /// Puts `frame` into an [`Arc`], reusing the one of an old frame if
+    /// possible.
+    fn publish(&mut self, frame: ScopeFrame<S>) -> Arc<ScopeFrame<S>> {
+        if let Some(mut recycled) = self.recycled.take()
+            && let Some(slot) = Arc::get_mut(&mut recycled)
+        {
+            // the old frame's buffer wasn't reused yet if the next capture
+            // hasn't started, so it's kept for that
+            let replaced = std::mem::replace(slot, frame);
+            if self.spare_samples.capacity() == 0 {
+                self.spare_samples = replaced.samples;
+            }
+            recycled
+        }
+        else {
+            Arc::new(frame)
+        }
+    }
+}
+
+impl<S> Inspector<S> for ScopeInspector<S>
+where
+    S: TriggerValue,
+{
+    fn inspect(&mut self, samples: &[S]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        // only lock once at the start and once at the end of each chunk
+        let (trigger, mut force_trigger) = {
+            let mut shared = self.shared.lock();
+            (shared.trigger, std::mem::take(&mut shared.force_trigger))
+        };
+        let mut latest = None;
+        let mut num_frames = 0;
+
+        for &sample in samples {
+            let value = sample.trigger_value();
+
+            if let Some(capture) = &mut self.capture {
+                capture.samples.push(sample);
+            }
+            else {
+                let triggered = if std::mem::take(&mut force_trigger) {
+                    Some(Trigger::External)
+                }
+                else {
+                    match trigger {
+                        Trigger::FreeRun => Some(trigger),
+                        Trigger::RisingEdge { level } => {
+                            self.previous_value
+                                .is_some_and(|previous| previous < level && value >= level)
+                                .then_some(trigger)
+                        }
+                        Trigger::External => None,
+                    }
+                };
+
+                if let Some(trigger) = triggered {
+                    let mut frame_samples = self.frame_buffer();
+                    frame_samples.extend(self.history.iter().copied());
+                    frame_samples.push(sample);
+                    self.capture = Some(ScopeFrame {
+                        trigger_offset: frame_samples.len() - 1,
+                        samples: frame_samples,
+                        trigger_index: self.num_samples,
+                        trigger,
+                    });
+                }
+            }
+
+            if self
+                .capture
+                .as_ref()
+                .is_some_and(|capture| capture.samples.len() == self.frame_length)
+            {
+                if let Some(replaced) = std::mem::replace(&mut latest, self.capture.take()) {
+                    self.spare_samples = replaced.samples;
+                }
+                num_frames += 1;
+            }
+
+            if self.pre_trigger > 0 {
+                if self.history.len() == self.pre_trigger {
+                    self.history.pop_front();
+                }
+                self.history.push_back(sample);
+            }
+
+            self.previous_value = Some(value);
+            self.num_samples += 1;
+        }
+
+        if latest.is_some() || force_trigger {
+            let latest = latest.map(|latest| self.publish(latest));
+            let mut shared = self.shared.lock();
+            if let Some(latest) = latest {
+                self.recycled = shared.latest.replace(latest);
+                shared.num_frames += num_frames;
+            }
+            // a forced trigger during a capture is kept for the next chunk
+            shared.force_trigger |= force_trigger;
+        }
+    }
+}
+
+pin_project! {
+    /// Stream wrapper that passes samples through unchanged, while capturing
+    /// triggered snapshots of fixed size, like an oscilloscope.
+    ///
+    /// Frames are read, and the trigger is controlled, through a
+    /// [`ScopeHandle`]. Only the latest frame is kept.
+    #[derive(Debug)]
+    pub struct WithScope<R, S> {
+        #[pin]
+        inner: InspectWith<R, ScopeInspector<S>>,
+    }
+}
+
+impl<R, S> WithScope<R, S> {
+    /// See [`ScopeInspector::new`].
+    #[inline]
+    pub fn new(inner: R, frame_length: usize, pre_trigger: usize, trigger: Trigger) -> Self {
+        Self {
+            inner: InspectWith::new(
+                inner,
+                ScopeInspector::new(frame_length, pre_trigger, trigger),
+            ),
+        }
+    }
+
+    #[inline]
+    pub fn handle(&self) -> ScopeHandle<S> {
+        self.inner.inspector().handle()
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for WithScope<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: TriggerValue,
+{
+    type Error = R::Error;
+
+    #[inline]
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_read_samples(cx, buffer)
+    }
+}
+
+impl<R, S> GetSampleRate for WithScope<R, S>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate()
+    }
+}
+
+impl<R, S> GetSampleIndexMap for WithScope<R, S>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
+impl<R, S> StreamLength for WithScope<R, S>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        self.inner.remaining()
+    }
+}
+
+impl<R, S> FiniteStream for WithScope<R, S> where R: FiniteStream {}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::{
+        ScopeInspector,
+        Trigger,
+    };
+    use crate::io::{
+        AsyncReadSamplesExt,
+        Cursor,
+        combinators::Inspector,
+    };
+
+    #[test]
+    fn it_triggers_on_rising_edge() {
+        // a sawtooth from 0 to 9, repeated
+        let input = (0..100).map(|i| (i % 10) as f32).collect::<Vec<_>>();
+
+        let mut stream = Cursor::new(input.clone()).scope(6, 2, Trigger::RisingEdge { level: 4.5 });
+        let handle = stream.handle();
+
+        let mut output = vec![];
+        stream
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, input);
+
+        let frame = handle.latest().unwrap();
+        assert_eq!(frame.samples, [3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(frame.trigger_offset, 2);
+        assert_eq!(frame.trigger_index, 95);
+        assert_eq!(handle.num_frames(), 10);
+    }
+
+    #[test]
+    fn it_triggers_externally() {
+        let mut inspector = ScopeInspector::new(4, 0, Trigger::External);
+        let handle = inspector.handle();
+
+        inspector.inspect(&[1.0f32; 10]);
+        assert!(handle.latest().is_none());
+
+        handle.trigger();
+        inspector.inspect(&[2.0, 3.0]);
+        inspector.inspect(&[4.0, 5.0, 6.0]);
+        let frame = handle.latest().unwrap();
+        assert_eq!(frame.samples, [2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(frame.trigger_index, 10);
+        assert_eq!(frame.trigger, Trigger::External);
+        assert_eq!(handle.num_frames(), 1);
+    }
+}
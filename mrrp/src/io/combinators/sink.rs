@@ -0,0 +1,541 @@
+//! Combinators for sinks
+//!
+//! These are the counterparts of the stream combinators for
+//! [`AsyncWriteSamples`], and are created with the methods of
+//! [`AsyncWriteSamplesExt`][crate::io::AsyncWriteSamplesExt].
+
+use std::{
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+    },
+    time::Duration,
+};
+
+use futures_util::{
+    FutureExt,
+    future::{
+        Fuse,
+        FusedFuture,
+    },
+    ready,
+};
+use pin_project_lite::pin_project;
+
+use crate::{
+    io::{
+        AsyncWriteSamples,
+        GetSampleRate,
+    },
+    util::clock::{
+        Clock,
+        TokioClock,
+    },
+};
+
+/// Samples that were accepted by a sink wrapper, but not yet written to the
+/// inner sink.
+#[derive(Clone, Debug)]
+struct Pending<S> {
+    buffer: Vec<S>,
+    position: usize,
+}
+
+impl<S> Pending<S> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buffer: Vec::with_capacity(capacity),
+            position: 0,
+        }
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.position == self.buffer.len()
+    }
+
+    /// Writes all pending samples to `inner`.
+    fn poll_drain<W>(
+        &mut self,
+        mut inner: Pin<&mut W>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), W::Error>>
+    where
+        W: AsyncWriteSamples<S>,
+    {
+        while self.position < self.buffer.len() {
+            let num_written = ready!(
+                inner
+                    .as_mut()
+                    .poll_write_samples(cx, &self.buffer[self.position..])
+            )?;
+            if num_written == 0 {
+                // the sink doesn't take any more samples, so the rest is lost
+                break;
+            }
+            self.position += num_written;
+        }
+
+        self.buffer.clear();
+        self.position = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+pin_project! {
+    /// Sink wrapper that maps the samples before writing them to the inner
+    /// sink.
+    ///
+    /// The mapped samples are kept in an intermediate buffer until the inner
+    /// sink takes them, so that `f` is called exactly once per sample.
+    #[derive(Clone, Debug)]
+    pub struct MappedSink<W, S, F> {
+        #[pin]
+        inner: W,
+        f: F,
+        pending: Pending<S>,
+        max_buffer_size: usize,
+    }
+}
+
+/// Sink wrapper that converts samples from `Q` to the `S` of the inner sink.
+pub type ConvertedSink<W, Q, S> = MappedSink<W, S, fn(Q) -> S>;
+
+impl<W, S, F> MappedSink<W, S, F> {
+    #[inline]
+    pub fn new(inner: W, f: F) -> Self {
+        Self {
+            inner,
+            f,
+            pending: Pending::new(0),
+            max_buffer_size: usize::MAX,
+        }
+    }
+
+    /// Limits how many samples are mapped per write.
+    #[inline]
+    pub fn with_max_buffer_size(mut self, max_buffer_size: usize) -> Self {
+        assert!(max_buffer_size > 0, "buffer size must be greater than 0");
+        self.max_buffer_size = max_buffer_size;
+        self
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+}
+
+impl<W, S, Q, F> AsyncWriteSamples<Q> for MappedSink<W, S, F>
+where
+    W: AsyncWriteSamples<S>,
+    Q: Copy,
+    F: FnMut(Q) -> S,
+{
+    type Error = W::Error;
+
+    fn poll_write_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &[Q],
+    ) -> Poll<Result<usize, Self::Error>> {
+        let mut this = self.project();
+
+        // samples of previous writes go first
+        ready!(this.pending.poll_drain(this.inner.as_mut(), cx))?;
+        if buffer.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let num_samples = buffer.len().min(*this.max_buffer_size);
+        this.pending
+            .buffer
+            .extend(buffer[..num_samples].iter().map(|sample| (this.f)(*sample)));
+
+        // the samples are taken now, so it doesn't matter if the inner sink
+        // isn't ready for them yet
+        if let Poll::Ready(Err(error)) = this.pending.poll_drain(this.inner, cx) {
+            return Poll::Ready(Err(error));
+        }
+
+        Poll::Ready(Ok(num_samples))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        ready!(this.pending.poll_drain(this.inner.as_mut(), cx))?;
+        this.inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        ready!(this.pending.poll_drain(this.inner.as_mut(), cx))?;
+        this.inner.poll_close(cx)
+    }
+}
+
+impl<W, S, F> GetSampleRate for MappedSink<W, S, F>
+where
+    W: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate()
+    }
+}
+
+pin_project! {
+    /// Sink wrapper that collects samples, and writes them to the inner sink
+    /// in large chunks.
+    ///
+    /// Writes that are at least as large as the buffer go straight to the
+    /// inner sink, if nothing is buffered. The buffered samples are written
+    /// when the buffer is full, or the sink is flushed or closed.
+    #[derive(Clone, Debug)]
+    pub struct BufferedSink<W, S> {
+        #[pin]
+        inner: W,
+        pending: Pending<S>,
+        buffer_size: usize,
+    }
+}
+
+impl<W, S> BufferedSink<W, S> {
+    #[inline]
+    pub fn new(inner: W, buffer_size: usize) -> Self {
+        assert!(buffer_size > 0, "buffer size must be greater than 0");
+        Self {
+            inner,
+            pending: Pending::new(buffer_size),
+            buffer_size,
+        }
+    }
+
+    /// Number of samples currently buffered.
+    #[inline]
+    pub fn num_buffered(&self) -> usize {
+        self.pending.buffer.len() - self.pending.position
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+}
+
+impl<W, S> AsyncWriteSamples<S> for BufferedSink<W, S>
+where
+    W: AsyncWriteSamples<S>,
+    S: Clone,
+{
+    type Error = W::Error;
+
+    fn poll_write_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &[S],
+    ) -> Poll<Result<usize, Self::Error>> {
+        let mut this = self.project();
+
+        if this.pending.buffer.len() == *this.buffer_size {
+            ready!(this.pending.poll_drain(this.inner.as_mut(), cx))?;
+        }
+
+        if this.pending.is_empty() && buffer.len() >= *this.buffer_size {
+            // nothing to gain from copying to our buffer first
+            return this.inner.poll_write_samples(cx, buffer);
+        }
+
+        let num_samples = buffer
+            .len()
+            .min(*this.buffer_size - this.pending.buffer.len());
+        this.pending
+            .buffer
+            .extend_from_slice(&buffer[..num_samples]);
+        Poll::Ready(Ok(num_samples))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        ready!(this.pending.poll_drain(this.inner.as_mut(), cx))?;
+        this.inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        ready!(this.pending.poll_drain(this.inner.as_mut(), cx))?;
+        this.inner.poll_close(cx)
+    }
+}
+
+impl<W, S> GetSampleRate for BufferedSink<W, S>
+where
+    W: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate()
+    }
+}
+
+pin_project! {
+    /// Sink wrapper that limits how fast samples are written, like
+    /// [`Throttled`][super::Throttled] does for streams.
+    ///
+    /// After a write, the next write waits for the duration of the samples
+    /// that were written.
+    #[derive(Debug)]
+    pub struct ThrottledSink<W, C = TokioClock>
+    where
+        C: Clock,
+    {
+        #[pin]
+        inner: W,
+        sample_duration: Duration,
+        clock: C,
+        delay: Pin<Box<Fuse<C::Sleep>>>,
+    }
+}
+
+impl<W> ThrottledSink<W> {
+    pub fn new(inner: W, sample_duration: Duration) -> Self {
+        Self::with_clock(inner, sample_duration, TokioClock)
+    }
+}
+
+impl<W, C> ThrottledSink<W, C>
+where
+    C: Clock,
+{
+    /// Throttles the sink using the given clock instead of the tokio timer.
+    pub fn with_clock(inner: W, sample_duration: Duration, clock: C) -> Self {
+        Self {
+            inner,
+            sample_duration,
+            clock,
+            delay: Box::pin(Fuse::terminated()),
+        }
+    }
+
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+}
+
+impl<W, C, S> AsyncWriteSamples<S> for ThrottledSink<W, C>
+where
+    W: AsyncWriteSamples<S>,
+    C: Clock,
+{
+    type Error = W::Error;
+
+    fn poll_write_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &[S],
+    ) -> Poll<Result<usize, Self::Error>> {
+        let this = self.project();
+
+        if !this.delay.is_terminated() {
+            ready!(this.delay.poll_unpin(cx));
+        }
+
+        let num_samples = ready!(this.inner.poll_write_samples(cx, buffer))?;
+
+        let delay = u32::try_from(num_samples)
+            .ok()
+            .and_then(|num_samples| this.sample_duration.checked_mul(num_samples))
+            .unwrap_or_else(|| Duration::from_secs(1));
+        this.delay.set(this.clock.sleep(delay).fuse());
+
+        Poll::Ready(Ok(num_samples))
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    #[inline]
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+impl<W, C> GetSampleRate for ThrottledSink<W, C>
+where
+    W: GetSampleRate,
+    C: Clock,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        pin::Pin,
+        task::{
+            Context,
+            Poll,
+        },
+        time::Duration,
+    };
+
+    use futures_util::FutureExt;
+
+    use super::ThrottledSink;
+    use crate::{
+        io::{
+            AsyncWriteSamples,
+            AsyncWriteSamplesExt,
+            GetSampleRate,
+        },
+        util::clock::MockClock,
+    };
+
+    /// Sink that takes at most `limit` samples per write, and records the
+    /// size of every write.
+    #[derive(Debug)]
+    struct VecSink<S> {
+        samples: Vec<S>,
+        writes: Vec<usize>,
+        limit: usize,
+        flushed: bool,
+    }
+
+    impl<S> VecSink<S> {
+        fn new(limit: usize) -> Self {
+            Self {
+                samples: vec![],
+                writes: vec![],
+                limit,
+                flushed: false,
+            }
+        }
+    }
+
+    impl<S: Clone + Unpin> AsyncWriteSamples<S> for VecSink<S> {
+        type Error = Infallible;
+
+        fn poll_write_samples(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buffer: &[S],
+        ) -> Poll<Result<usize, Self::Error>> {
+            let n = buffer.len().min(self.limit);
+            self.samples.extend_from_slice(&buffer[..n]);
+            self.writes.push(n);
+            self.flushed = false;
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.flushed = true;
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.poll_flush(cx)
+        }
+    }
+
+    #[test]
+    fn it_converts_and_maps_samples() {
+        let mut sink = VecSink::<i16>::new(3)
+            .with_sample_rate(48000.0)
+            .convert::<f32>()
+            .map(|sample: f32| sample * 0.5);
+        assert_eq!(sink.sample_rate(), 48000.0);
+
+        sink.write_all(&[1.0, -1.0, 0.5, 0.0, 0.25])
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        sink.flush()
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+
+        assert_eq!(
+            sink.inner().inner().inner().samples,
+            [16384, -16384, 8192, 0, 4096]
+        );
+        assert!(sink.inner().inner().inner().flushed);
+    }
+
+    #[test]
+    fn it_buffers_writes() {
+        let mut sink = VecSink::<u32>::new(usize::MAX).buffered(10);
+
+        for i in 0..4 {
+            sink.write_all(&[i; 3])
+                .now_or_never()
+                .expect("test sink pending")
+                .unwrap();
+        }
+        // the first 9 samples fit, and the tenth fills the buffer, which is
+        // written by the next write
+        assert_eq!(sink.inner().writes, [10]);
+        assert_eq!(sink.num_buffered(), 2);
+
+        // large writes go straight through once the buffer is empty
+        sink.flush()
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        sink.write_all(&[9; 20])
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        assert_eq!(sink.inner().writes, [10, 2, 20]);
+        assert_eq!(sink.inner().samples.len(), 32);
+    }
+
+    #[test]
+    fn it_paces_writes_by_sample_duration() {
+        let clock = MockClock::new();
+        let mut sink = ThrottledSink::with_clock(
+            VecSink::<u8>::new(usize::MAX),
+            Duration::from_millis(1),
+            clock.clone(),
+        );
+
+        // the first write goes through right away
+        let num_samples = sink
+            .write(&[0; 10])
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(num_samples, 10);
+
+        // then we have to wait 10 samples worth of time
+        assert!(sink.write(&[0; 10]).now_or_never().is_none());
+        clock.advance(Duration::from_millis(9));
+        assert!(sink.write(&[0; 10]).now_or_never().is_none());
+        clock.advance(Duration::from_millis(1));
+        assert!(sink.write(&[0; 5]).now_or_never().is_some());
+        assert_eq!(sink.inner().samples.len(), 15);
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    ops::{
+        Add,
+        Mul,
+    },
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+use num_complex::Complex;
+use num_traits::Zero;
+use parking_lot::Mutex;
+use pin_project_lite::pin_project;
+
+use crate::io::{
+    AsyncReadSamples,
+    FiniteStream,
+    GetSampleIndexMap,
+    GetSampleRate,
+    ReadBuf,
+    Remaining,
+    SampleIndexMap,
+    StreamLength,
+    combinators::{
+        InspectWith,
+        Inspector,
+    },
+};
+
+/// Samples for which [`WithStats`] can compute statistics.
+pub trait Power: Copy {
+    /// The squared magnitude of the sample.
+    fn power(self) -> f32;
+}
+
+impl Power for f32 {
+    #[inline]
+    fn power(self) -> f32 {
+        self * self
+    }
+}
+
+impl Power for Complex<f32> {
+    #[inline]
+    fn power(self) -> f32 {
+        self.norm_sqr()
+    }
+}
+
+/// Snapshot of the statistics collected by [`WithStats`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats<S> {
+    /// Number of samples since the last reset.
+    pub num_samples: u64,
+
+    /// Mean of the samples.
+    pub dc_offset: S,
+
+    /// Root mean square of the sample magnitudes.
+    pub rms: f32,
+
+    /// Largest sample magnitude.
+    pub peak: f32,
+}
+
+impl<S> Stats<S> {
+    /// RMS in dBFS, assuming a full scale of 1.0.
+    #[inline]
+    pub fn rms_dbfs(&self) -> f32 {
+        20.0 * self.rms.log10()
+    }
+
+    /// Peak in dBFS, assuming a full scale of 1.0.
+    #[inline]
+    pub fn peak_dbfs(&self) -> f32 {
+        20.0 * self.peak.log10()
+    }
+
+    /// Ratio between peak and RMS.
+    #[inline]
+    pub fn crest_factor(&self) -> f32 {
+        self.peak / self.rms
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Accumulator<S> {
+    num_samples: u64,
+    sum: S,
+    sum_power: f64,
+    peak_power: f32,
+}
+
+impl<S: Zero> Accumulator<S> {
+    fn new() -> Self {
+        Self {
+            num_samples: 0,
+            sum: S::zero(),
+            sum_power: 0.0,
+            peak_power: 0.0,
+        }
+    }
+}
+
+impl<S> Accumulator<S>
+where
+    S: Copy + Zero + Mul<f32, Output = S>,
+{
+    fn stats(&self) -> Stats<S> {
+        if self.num_samples == 0 {
+            Stats {
+                num_samples: 0,
+                dc_offset: S::zero(),
+                rms: 0.0,
+                peak: 0.0,
+            }
+        }
+        else {
+            let n = self.num_samples as f64;
+            Stats {
+                num_samples: self.num_samples,
+                dc_offset: self.sum * (1.0 / n) as f32,
+                rms: (self.sum_power / n).sqrt() as f32,
+                peak: self.peak_power.sqrt(),
+            }
+        }
+    }
+}
+
+/// Cloneable handle to read the statistics of a [`WithStats`] stream from
+/// anywhere, e.g. a UI task.
+#[derive(Debug)]
+pub struct StatsHandle<S> {
+    accumulator: Arc<Mutex<Accumulator<S>>>,
+}
+
+impl<S> Clone for StatsHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            accumulator: self.accumulator.clone(),
+        }
+    }
+}
+
+impl<S> StatsHandle<S>
+where
+    S: Copy + Zero + Mul<f32, Output = S>,
+{
+    /// Statistics over all samples since the last reset.
+    pub fn snapshot(&self) -> Stats<S> {
+        self.accumulator.lock().stats()
+    }
+
+    /// Returns the statistics and resets them.
+    ///
+    /// Calling this periodically gives statistics over each period, which is
+    /// what a level meter wants.
+    pub fn take(&self) -> Stats<S> {
+        let mut accumulator = self.accumulator.lock();
+        let stats = accumulator.stats();
+        *accumulator = Accumulator::new();
+        stats
+    }
+
+    pub fn reset(&self) {
+        *self.accumulator.lock() = Accumulator::new();
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsInspector<S> {
+    accumulator: Arc<Mutex<Accumulator<S>>>,
+}
+
+impl<S: Zero> StatsInspector<S> {
+    pub fn new() -> Self {
+        Self {
+            accumulator: Arc::new(Mutex::new(Accumulator::new())),
+        }
+    }
+}
+
+impl<S: Zero> Default for StatsInspector<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> StatsInspector<S> {
+    pub fn handle(&self) -> StatsHandle<S> {
+        StatsHandle {
+            accumulator: self.accumulator.clone(),
+        }
+    }
+}
+
+impl<S> Inspector<S> for StatsInspector<S>
+where
+    S: Power + Zero + Add<Output = S>,
+{
+    fn inspect(&mut self, samples: &[S]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        // sum up the chunk first, so we only lock once per chunk
+        let mut sum = S::zero();
+        let mut sum_power = 0.0;
+        let mut peak_power = 0.0f32;
+        for sample in samples {
+            let power = sample.power();
+            sum = sum + *sample;
+            sum_power += f64::from(power);
+            peak_power = peak_power.max(power);
+        }
+
+        let mut accumulator = self.accumulator.lock();
+        accumulator.num_samples += samples.len() as u64;
+        accumulator.sum = accumulator.sum + sum;
+        accumulator.sum_power += sum_power;
+        accumulator.peak_power = accumulator.peak_power.max(peak_power);
+    }
+}
+
+pin_project! {
+    /// Stream wrapper that passes samples through unchanged, while keeping
+    /// running statistics.
+    ///
+    /// The statistics are read through a [`StatsHandle`].
+    #[derive(Debug)]
+    pub struct WithStats<R, S> {
+        #[pin]
+        inner: InspectWith<R, StatsInspector<S>>,
+    }
+}
+
+impl<R, S: Zero> WithStats<R, S> {
+    #[inline]
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: InspectWith::new(inner, StatsInspector::new()),
+        }
+    }
+}
+
+impl<R, S> WithStats<R, S> {
+    #[inline]
+    pub fn handle(&self) -> StatsHandle<S> {
+        self.inner.inspector().handle()
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for WithStats<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: Power + Zero + Add<Output = S>,
+{
+    type Error = R::Error;
+
+    #[inline]
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_read_samples(cx, buffer)
+    }
+}
+
+impl<R, S> GetSampleRate for WithStats<R, S>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate()
+    }
+}
+
+impl<R, S> GetSampleIndexMap for WithStats<R, S>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
+impl<R, S> StreamLength for WithStats<R, S>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        self.inner.remaining()
+    }
+}
+
+impl<R, S> FiniteStream for WithStats<R, S> where R: FiniteStream {}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use crate::io::{
+        AsyncReadSamplesExt,
+        Cursor,
+    };
+
+    #[test]
+    fn it_passes_samples_through_and_collects_stats() {
+        let input = (0..1000)
+            .map(|i| Complex::new(0.1, 0.0) + Complex::from_polar(0.5, 0.1 * i as f32))
+            .collect::<Vec<_>>();
+
+        let mut stream = Cursor::new(input.clone()).stats();
+        let handle = stream.handle();
+
+        let mut output = vec![];
+        stream
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, input);
+
+        let stats = handle.snapshot();
+        assert_eq!(stats.num_samples, 1000);
+        assert_abs_diff_eq!(stats.dc_offset.re, 0.1, epsilon = 1e-2);
+        assert_abs_diff_eq!(stats.dc_offset.im, 0.0, epsilon = 1e-2);
+        // 0.1 dc plus a tone with amplitude 0.5
+        assert_abs_diff_eq!(stats.rms, (0.01f32 + 0.25).sqrt(), epsilon = 1e-2);
+        assert_abs_diff_eq!(stats.peak, 0.6, epsilon = 1e-3);
+
+        assert_eq!(handle.take().num_samples, 1000);
+        assert_eq!(handle.snapshot().num_samples, 0);
+    }
+}
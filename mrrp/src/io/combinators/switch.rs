@@ -0,0 +1,414 @@
+use std::{
+    ops::{
+        Add,
+        Mul,
+    },
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+    },
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use parking_lot::Mutex;
+use pin_project_lite::pin_project;
+
+use crate::{
+    buf::SampleBufMut,
+    io::{
+        AsyncReadSamples,
+        Buffer,
+        FiniteStream,
+        GetSampleIndexMap,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        SampleIndexMap,
+        SizeHint,
+        StreamLength,
+        combinators::ZipError,
+    },
+};
+
+/// Selects one of the inputs of a [`Switch`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SwitchInput {
+    #[default]
+    A,
+    B,
+}
+
+impl SwitchInput {
+    #[inline]
+    pub fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+
+    /// Gain of input B when this input is fully selected.
+    #[inline]
+    fn mix(self) -> f32 {
+        match self {
+            Self::A => 0.0,
+            Self::B => 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Control {
+    selected: SwitchInput,
+    command: Option<Command>,
+    crossfade: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Command {
+    input: SwitchInput,
+    position: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    control: Mutex<Control>,
+    position: AtomicU64,
+}
+
+/// Cloneable handle to control a [`Switch`] from another task.
+#[derive(Clone, Debug)]
+pub struct SwitchHandle {
+    shared: Arc<Shared>,
+}
+
+impl SwitchHandle {
+    /// Switches to `input` with the next sample the switch produces.
+    pub fn select(&self, input: SwitchInput) {
+        self.send(input, None);
+    }
+
+    /// Switches to `input` exactly at output sample `position`.
+    ///
+    /// If the switch has already produced this sample, it switches with the
+    /// next sample. A later call replaces a switch that hasn't happened yet.
+    pub fn select_at(&self, input: SwitchInput, position: u64) {
+        self.send(input, Some(position));
+    }
+
+    /// Switches to the other input and returns the newly selected input.
+    pub fn toggle(&self) -> SwitchInput {
+        let mut control = self.shared.control.lock();
+        let input = control.selected.other();
+        control.selected = input;
+        control.command = Some(Command {
+            input,
+            position: None,
+        });
+        input
+    }
+
+    /// The input that was selected last. The switch might not have reached
+    /// it yet.
+    pub fn selected(&self) -> SwitchInput {
+        self.shared.control.lock().selected
+    }
+
+    /// Sets the length of the crossfade in samples. 0 switches hard.
+    pub fn set_crossfade(&self, num_samples: usize) {
+        self.shared.control.lock().crossfade = num_samples;
+    }
+
+    /// Number of samples the switch has produced so far.
+    ///
+    /// This is updated once per read.
+    pub fn position(&self) -> u64 {
+        self.shared.position.load(Ordering::Relaxed)
+    }
+
+    fn send(&self, input: SwitchInput, position: Option<u64>) {
+        let mut control = self.shared.control.lock();
+        control.selected = input;
+        control.command = Some(Command { input, position });
+    }
+}
+
+pin_project! {
+    /// Selects between two streams with sample accuracy and an optional
+    /// linear crossfade.
+    ///
+    /// Both streams are read in lockstep, so the deselected stream keeps
+    /// running and is in sync when switching back. The switch is controlled
+    /// through a [`SwitchHandle`].
+    ///
+    /// Like [`ZipWith`][super::ZipWith], the stream ends when either input
+    /// ends, and stream A determines the sample rate.
+    #[derive(Debug)]
+    pub struct Switch<A, B, S> {
+        #[pin]
+        a: A,
+        a_buffer: Buffer<S>,
+        #[pin]
+        b: B,
+        b_buffer: Buffer<S>,
+        shared: Arc<Shared>,
+        pending: Option<Command>,
+        target: SwitchInput,
+        crossfade: usize,
+        mix: f32,
+        position: u64,
+    }
+}
+
+impl<A, B, S> Switch<A, B, S> {
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            a_buffer: Buffer::default(),
+            b,
+            b_buffer: Buffer::default(),
+            shared: Arc::new(Shared {
+                control: Mutex::new(Control {
+                    selected: SwitchInput::A,
+                    command: None,
+                    crossfade: 0,
+                }),
+                position: AtomicU64::new(0),
+            }),
+            pending: None,
+            target: SwitchInput::A,
+            crossfade: 0,
+            mix: 0.0,
+            position: 0,
+        }
+    }
+
+    /// Starts with `input` selected instead of A.
+    pub fn with_selected(mut self, input: SwitchInput) -> Self {
+        self.shared.control.lock().selected = input;
+        self.target = input;
+        self.mix = input.mix();
+        self
+    }
+
+    /// Crossfades over `num_samples` samples when switching.
+    pub fn with_crossfade(mut self, num_samples: usize) -> Self {
+        self.shared.control.lock().crossfade = num_samples;
+        self.crossfade = num_samples;
+        self
+    }
+
+    #[inline]
+    pub fn handle(&self) -> SwitchHandle {
+        SwitchHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    #[inline]
+    pub fn a(&self) -> &A {
+        &self.a
+    }
+
+    #[inline]
+    pub fn b(&self) -> &B {
+        &self.b
+    }
+}
+
+impl<A, B, S> AsyncReadSamples<S> for Switch<A, B, S>
+where
+    A: AsyncReadSamples<S>,
+    B: AsyncReadSamples<S>,
+    S: Copy + Add<Output = S> + Mul<f32, Output = S>,
+{
+    type Error = ZipError<A::Error, B::Error>;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+
+        let n = buffer.remaining();
+        if n == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        this.a_buffer.grow(n);
+        this.b_buffer.grow(n);
+
+        let num_samples_a = ready!(this.a_buffer.poll_fill(cx, this.a)).map_err(ZipError::Left)?;
+        let num_samples_b = ready!(this.b_buffer.poll_fill(cx, this.b)).map_err(ZipError::Right)?;
+        let num_samples = num_samples_a.min(num_samples_b);
+
+        {
+            let mut control = this.shared.control.lock();
+            if let Some(command) = control.command.take() {
+                *this.pending = Some(command);
+            }
+            *this.crossfade = control.crossfade;
+        }
+        let step = 1.0 / *this.crossfade as f32;
+
+        for (a, b) in this
+            .a_buffer
+            .drain(num_samples)
+            .zip(this.b_buffer.drain(num_samples))
+        {
+            if let Some(command) = *this.pending
+                && command
+                    .position
+                    .is_none_or(|position| position <= *this.position)
+            {
+                *this.target = command.input;
+                *this.pending = None;
+            }
+
+            let target_mix = this.target.mix();
+            if *this.mix != target_mix {
+                *this.mix = if *this.crossfade == 0 {
+                    target_mix
+                }
+                else if target_mix > *this.mix {
+                    (*this.mix + step).min(target_mix)
+                }
+                else {
+                    (*this.mix - step).max(target_mix)
+                };
+            }
+
+            let sample = if *this.mix == 0.0 {
+                a
+            }
+            else if *this.mix == 1.0 {
+                b
+            }
+            else {
+                a * (1.0 - *this.mix) + b * *this.mix
+            };
+            buffer.put_sample(sample);
+            *this.position += 1;
+        }
+
+        this.shared
+            .position
+            .store(*this.position, Ordering::Relaxed);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<A, B, S> GetSampleRate for Switch<A, B, S>
+where
+    A: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.a.sample_rate()
+    }
+}
+
+impl<A, B, S> GetSampleIndexMap for Switch<A, B, S>
+where
+    A: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.a.sample_index_map()
+    }
+}
+
+impl<A, B, S> StreamLength for Switch<A, B, S>
+where
+    A: StreamLength,
+    B: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        let a_remaining = self.a.remaining() + self.a_buffer.remaining();
+        let b_remaining = self.b.remaining() + self.b_buffer.remaining();
+        a_remaining.min(b_remaining)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> SizeHint {
+        let a_size_hint = self.a.size_hint() + self.a_buffer.remaining();
+        let b_size_hint = self.b.size_hint() + self.b_buffer.remaining();
+        a_size_hint.min(b_size_hint)
+    }
+}
+
+impl<A, B, S> FiniteStream for Switch<A, B, S>
+where
+    A: FiniteStream,
+    B: FiniteStream,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use futures_util::FutureExt;
+
+    use crate::io::{
+        AsyncReadSamplesExt,
+        Cursor,
+        combinators::SwitchInput,
+    };
+
+    fn read(
+        stream: &mut (impl AsyncReadSamplesExt<f32, Error: Debug> + Unpin),
+        num_samples: usize,
+    ) -> Vec<f32> {
+        let mut output = vec![0.0; num_samples];
+        let num_samples = stream
+            .read_samples(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        output.truncate(num_samples);
+        output
+    }
+
+    #[test]
+    fn it_switches_at_the_requested_sample() {
+        let mut stream = Cursor::new(vec![0.0f32; 100]).switch(Cursor::new(vec![1.0f32; 100]));
+        let handle = stream.handle();
+
+        assert_eq!(read(&mut stream, 10), [0.0; 10]);
+        assert_eq!(handle.position(), 10);
+
+        handle.select_at(SwitchInput::B, 15);
+        let output = read(&mut stream, 10);
+        assert_eq!(output[..5], [0.0; 5]);
+        assert_eq!(output[5..], [1.0; 5]);
+
+        assert_eq!(handle.toggle(), SwitchInput::A);
+        assert_eq!(read(&mut stream, 10), [0.0; 10]);
+    }
+
+    #[test]
+    fn it_crossfades() {
+        let mut stream = Cursor::new(vec![0.0f32; 100])
+            .switch(Cursor::new(vec![1.0f32; 100]))
+            .with_crossfade(4);
+        let handle = stream.handle();
+
+        handle.select(SwitchInput::B);
+        assert_eq!(read(&mut stream, 6), [0.25, 0.5, 0.75, 1.0, 1.0, 1.0]);
+
+        handle.select(SwitchInput::A);
+        assert_eq!(read(&mut stream, 6), [0.75, 0.5, 0.25, 0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,465 @@
+//! Dumping the samples at any point of a pipeline to a file.
+
+use std::{
+    ffi::OsString,
+    fmt::Write as _,
+    fs::File,
+    io::{
+        BufWriter,
+        Write,
+    },
+    marker::PhantomData,
+    path::{
+        Path,
+        PathBuf,
+    },
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+    },
+    time::SystemTime,
+};
+
+use bytemuck::Pod;
+use parking_lot::Mutex;
+use pin_project_lite::pin_project;
+
+use crate::io::{
+    AsyncReadSamples,
+    FiniteStream,
+    GetSampleIndexMap,
+    GetSampleRate,
+    ReadBuf,
+    Remaining,
+    SampleIndexMap,
+    StreamLength,
+    combinators::{
+        InspectWith,
+        Inspector,
+    },
+};
+
+/// A contiguous part of the stream that was written to the dump.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TapSegment {
+    /// Offset of the first sample in the dump, in samples.
+    pub file_offset: u64,
+
+    /// Position of the first sample in the stream, counted from when the tap
+    /// was created.
+    pub stream_position: u64,
+
+    pub num_samples: u64,
+
+    /// When the first sample passed the tap.
+    pub time: SystemTime,
+}
+
+#[derive(Debug)]
+struct State {
+    enabled: bool,
+    max_samples: u64,
+    num_written: u64,
+    sample_rate: Option<f32>,
+    segments: Vec<TapSegment>,
+    /// Whether the last segment is still being written to.
+    recording: bool,
+    error: Option<std::io::Error>,
+}
+
+/// Cloneable handle to turn a [`TapToFile`] on and off while the stream is
+/// running.
+#[derive(Clone, Debug)]
+pub struct TapHandle {
+    state: Arc<Mutex<State>>,
+}
+
+impl TapHandle {
+    pub fn is_enabled(&self) -> bool {
+        self.state.lock().enabled
+    }
+
+    /// Starts writing samples again. Each time the tap is enabled, a new
+    /// [`TapSegment`] starts.
+    pub fn enable(&self) {
+        self.state.lock().enabled = true;
+    }
+
+    pub fn disable(&self) {
+        self.state.lock().enabled = false;
+    }
+
+    /// Toggles the tap and returns whether it's enabled now.
+    pub fn toggle(&self) -> bool {
+        let mut state = self.state.lock();
+        state.enabled = !state.enabled;
+        state.enabled
+    }
+
+    /// Number of samples written to the dump so far.
+    pub fn num_written(&self) -> u64 {
+        self.state.lock().num_written
+    }
+
+    /// Whether `max_samples` were written. Nothing more will be written then.
+    pub fn is_full(&self) -> bool {
+        let state = self.state.lock();
+        state.num_written >= state.max_samples
+    }
+
+    pub fn segments(&self) -> Vec<TapSegment> {
+        self.state.lock().segments.clone()
+    }
+
+    /// Returns the error that stopped the tap, if any.
+    ///
+    /// The tap never fails the stream it's on. If writing the dump fails, it
+    /// stops writing and keeps the error here.
+    pub fn take_error(&self) -> Option<std::io::Error> {
+        self.state.lock().error.take()
+    }
+}
+
+/// Inspector that writes the samples it sees to a raw file.
+///
+/// This is meant for debugging: put a [`TapToFile`] in front of a decoder
+/// that misbehaves, and the exact samples it saw can be replayed later. The
+/// samples are written raw, in native byte order, so the dump of a
+/// `Complex<f32>` stream is the usual `cf32` format. Next to it a JSON sidecar
+/// with the same name plus `.json` records the sample type and an index of
+/// which parts of the stream were captured.
+#[derive(Debug)]
+pub struct TapInspector<S> {
+    writer: BufWriter<File>,
+    sidecar_path: PathBuf,
+    stream_position: u64,
+    state: Arc<Mutex<State>>,
+    _phantom: PhantomData<fn(S)>,
+}
+
+impl<S: Pod> TapInspector<S> {
+    /// Creates the file at `path`, to which at most `max_samples` samples will
+    /// be written.
+    pub fn create(path: impl AsRef<Path>, max_samples: u64) -> Result<Self, std::io::Error> {
+        let path = path.as_ref();
+        let writer = BufWriter::new(File::create(path)?);
+
+        let mut sidecar_path = OsString::from(path);
+        sidecar_path.push(".json");
+
+        let this = Self {
+            writer,
+            sidecar_path: sidecar_path.into(),
+            stream_position: 0,
+            state: Arc::new(Mutex::new(State {
+                enabled: true,
+                max_samples,
+                num_written: 0,
+                sample_rate: None,
+                segments: vec![],
+                recording: false,
+                error: None,
+            })),
+            _phantom: PhantomData,
+        };
+        this.write_sidecar(&this.state.lock())?;
+        Ok(this)
+    }
+}
+
+impl<S> TapInspector<S> {
+    pub fn handle(&self) -> TapHandle {
+        TapHandle {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Records the sample rate in the sidecar.
+    pub fn with_sample_rate(self, sample_rate: f32) -> Self {
+        self.state.lock().sample_rate = Some(sample_rate);
+        self
+    }
+
+    fn write_sidecar(&self, state: &State) -> Result<(), std::io::Error> {
+        let mut json = String::new();
+        json.push_str("{\n");
+        writeln!(
+            json,
+            "  \"sample_type\": \"{}\",",
+            std::any::type_name::<S>()
+        )
+        .unwrap();
+        writeln!(json, "  \"sample_size\": {},", size_of::<S>()).unwrap();
+        match state.sample_rate {
+            Some(sample_rate) => writeln!(json, "  \"sample_rate\": {sample_rate},").unwrap(),
+            None => json.push_str("  \"sample_rate\": null,\n"),
+        }
+        json.push_str("  \"segments\": [");
+        for (i, segment) in state.segments.iter().enumerate() {
+            let time = segment
+                .time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            json.push_str(if i == 0 { "\n" } else { ",\n" });
+            write!(
+                json,
+                "    {{ \"file_offset\": {}, \"stream_position\": {}, \"num_samples\": {}, \"time\": {}.{:06} }}",
+                segment.file_offset,
+                segment.stream_position,
+                segment.num_samples,
+                time.as_secs(),
+                time.subsec_micros(),
+            )
+            .unwrap();
+        }
+        if !state.segments.is_empty() {
+            json.push_str("\n  ");
+        }
+        json.push_str("]\n}\n");
+
+        std::fs::write(&self.sidecar_path, json)
+    }
+
+    /// Flushes the dump and updates the sidecar, after a segment ended.
+    fn finish_segment(&mut self, state: &mut State) -> Result<(), std::io::Error> {
+        state.recording = false;
+        self.writer.flush()?;
+        self.write_sidecar(state)
+    }
+}
+
+impl<S: Pod> Inspector<S> for TapInspector<S> {
+    fn inspect(&mut self, samples: &[S]) {
+        let stream_position = self.stream_position;
+        self.stream_position += samples.len() as u64;
+
+        let state = self.state.clone();
+        let mut state = state.lock();
+        if state.error.is_some() {
+            return;
+        }
+
+        let remaining = state.max_samples - state.num_written;
+        let result = if !state.enabled || remaining == 0 {
+            if state.recording {
+                self.finish_segment(&mut state)
+            }
+            else {
+                Ok(())
+            }
+        }
+        else {
+            if !state.recording {
+                state.recording = true;
+                let file_offset = state.num_written;
+                state.segments.push(TapSegment {
+                    file_offset,
+                    stream_position,
+                    num_samples: 0,
+                    time: SystemTime::now(),
+                });
+            }
+
+            let n = samples
+                .len()
+                .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+            let result = self.writer.write_all(bytemuck::cast_slice(&samples[..n]));
+            if result.is_ok() {
+                state.num_written += n as u64;
+                state.segments.last_mut().unwrap().num_samples += n as u64;
+            }
+
+            if result.is_ok() && state.num_written == state.max_samples {
+                tracing::debug!(path = ?self.sidecar_path, "sample tap full");
+                self.finish_segment(&mut state)
+            }
+            else {
+                result
+            }
+        };
+
+        if let Err(error) = result {
+            tracing::warn!(?error, "sample tap failed");
+            state.recording = false;
+            state.error = Some(error);
+        }
+    }
+}
+
+impl<S> Drop for TapInspector<S> {
+    fn drop(&mut self) {
+        let state = self.state.clone();
+        let mut state = state.lock();
+        if state.recording
+            && let Err(error) = self.finish_segment(&mut state)
+        {
+            tracing::warn!(?error, "sample tap failed");
+        }
+    }
+}
+
+pin_project! {
+    /// Stream wrapper that passes samples through unchanged, while writing
+    /// them to a file.
+    ///
+    /// The tap is turned on and off through a [`TapHandle`].
+    #[derive(Debug)]
+    pub struct TapToFile<R, S> {
+        #[pin]
+        inner: InspectWith<R, TapInspector<S>>,
+    }
+}
+
+impl<R, S: Pod> TapToFile<R, S> {
+    #[inline]
+    pub fn new(inner: R, path: impl AsRef<Path>, max_samples: u64) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            inner: InspectWith::new(inner, TapInspector::create(path, max_samples)?),
+        })
+    }
+}
+
+impl<R, S> TapToFile<R, S> {
+    #[inline]
+    pub fn handle(&self) -> TapHandle {
+        self.inner.inspector().handle()
+    }
+}
+
+impl<R: GetSampleRate, S> TapToFile<R, S> {
+    /// Records the sample rate of the inner stream in the sidecar.
+    pub fn record_sample_rate(self) -> Self {
+        let sample_rate = self.inner.sample_rate();
+        self.inner.inspector().state.lock().sample_rate = Some(sample_rate);
+        self
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for TapToFile<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: Pod,
+{
+    type Error = R::Error;
+
+    #[inline]
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_read_samples(cx, buffer)
+    }
+}
+
+impl<R, S> GetSampleRate for TapToFile<R, S>
+where
+    R: GetSampleRate,
+{
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate()
+    }
+}
+
+impl<R, S> GetSampleIndexMap for TapToFile<R, S>
+where
+    R: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
+impl<R, S> StreamLength for TapToFile<R, S>
+where
+    R: StreamLength,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        self.inner.remaining()
+    }
+}
+
+impl<R, S> FiniteStream for TapToFile<R, S> where R: FiniteStream {}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::TapInspector;
+    use crate::io::{
+        AsyncReadSamplesExt,
+        Cursor,
+        combinators::Inspector,
+    };
+
+    #[test]
+    fn it_dumps_up_to_max_samples() {
+        let path = std::env::temp_dir().join(format!("mrrp-tap-{}.f32", std::process::id()));
+
+        let input = (0..100).map(|i| i as f32).collect::<Vec<_>>();
+        let mut stream = Cursor::new(input.clone()).tap_to_file(&path, 30).unwrap();
+        let handle = stream.handle();
+
+        let mut output = vec![];
+        stream
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        drop(stream);
+        assert_eq!(output, input);
+        assert!(handle.is_full());
+
+        let dump = std::fs::read(&path).unwrap();
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&dump), &input[..30]);
+        let sidecar = std::fs::read_to_string(path.with_extension("f32.json")).unwrap();
+        assert!(sidecar.contains("\"sample_size\": 4"));
+        assert!(sidecar.contains("\"num_samples\": 30"));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(path.with_extension("f32.json")).unwrap();
+    }
+
+    #[test]
+    fn it_starts_a_segment_each_time_it_is_enabled() {
+        let path = std::env::temp_dir().join(format!("mrrp-tap-{}.u8", std::process::id()));
+
+        let mut tap = TapInspector::<u8>::create(&path, 100).unwrap();
+        let handle = tap.handle();
+        tap.inspect(&[1, 2, 3]);
+        handle.disable();
+        tap.inspect(&[4, 5]);
+        assert!(!handle.is_full());
+        assert!(handle.toggle());
+        tap.inspect(&[6]);
+        drop(tap);
+
+        let segments = handle.segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(
+            (
+                segments[0].file_offset,
+                segments[0].stream_position,
+                segments[0].num_samples
+            ),
+            (0, 0, 3)
+        );
+        assert_eq!(
+            (
+                segments[1].file_offset,
+                segments[1].stream_position,
+                segments[1].num_samples
+            ),
+            (3, 5, 1)
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), [1, 2, 3, 6]);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(path.with_extension("u8.json")).unwrap();
+    }
+}
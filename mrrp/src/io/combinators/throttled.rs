@@ -17,31 +17,54 @@ use futures_util::{
 };
 use pin_project_lite::pin_project;
 
-use crate::io::{
-    AsyncReadSamples,
-    FiniteStream,
-    GetSampleRate,
-    ReadBuf,
-    Remaining,
-    SizeHint,
-    StreamLength,
+use crate::{
+    io::{
+        AsyncReadSamples,
+        FiniteStream,
+        GetSampleIndexMap,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        SampleIndexMap,
+        SizeHint,
+        StreamLength,
+    },
+    util::clock::{
+        Clock,
+        TokioClock,
+    },
 };
 
 pin_project! {
     #[derive(Debug)]
-    pub struct Throttled<R> {
+    pub struct Throttled<R, C = TokioClock>
+    where
+        C: Clock,
+    {
         #[pin]
         inner: R,
         sample_duration: Duration,
-        delay: Pin<Box<Fuse<tokio::time::Sleep>>>,
+        clock: C,
+        delay: Pin<Box<Fuse<C::Sleep>>>,
     }
 }
 
 impl<R> Throttled<R> {
     pub fn new(inner: R, sample_duration: Duration) -> Self {
+        Self::with_clock(inner, sample_duration, TokioClock)
+    }
+}
+
+impl<R, C> Throttled<R, C>
+where
+    C: Clock,
+{
+    /// Throttles the stream using the given clock instead of the tokio timer.
+    pub fn with_clock(inner: R, sample_duration: Duration, clock: C) -> Self {
         Self {
             inner,
             sample_duration,
+            clock,
             delay: Box::pin(Fuse::terminated()),
         }
     }
@@ -55,14 +78,16 @@ impl<R> Throttled<R> {
     }
 }
 
-impl<R> Clone for Throttled<R>
+impl<R, C> Clone for Throttled<R, C>
 where
     R: Clone,
+    C: Clock + Clone,
 {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
             sample_duration: self.sample_duration,
+            clock: self.clock.clone(),
             // fixme: this unfortunately doesn't work, as Fuse doesn't let us access the inner type
             //delay: Box::pin(tokio::time::sleep_until(self.delay.deadline()).fuse()),
             delay: Box::pin(Fuse::terminated()),
@@ -70,9 +95,10 @@ where
     }
 }
 
-impl<R, S> AsyncReadSamples<S> for Throttled<R>
+impl<R, C, S> AsyncReadSamples<S> for Throttled<R, C>
 where
     R: AsyncReadSamples<S>,
+    C: Clock,
 {
     type Error = R::Error;
 
@@ -106,7 +132,7 @@ where
                             .checked_mul(num_samples)
                             .unwrap_or_else(|| Duration::from_secs(1));
 
-                        this.delay.set(tokio::time::sleep(delay).fuse());
+                        this.delay.set(this.clock.sleep(delay).fuse());
 
                         return Poll::Ready(Ok(()));
                     }
@@ -122,9 +148,10 @@ where
     }
 }
 
-impl<R> GetSampleRate for Throttled<R>
+impl<R, C> GetSampleRate for Throttled<R, C>
 where
     R: GetSampleRate,
+    C: Clock,
 {
     #[inline]
     fn sample_rate(&self) -> f32 {
@@ -132,9 +159,21 @@ where
     }
 }
 
-impl<R> StreamLength for Throttled<R>
+impl<R, C> GetSampleIndexMap for Throttled<R, C>
+where
+    R: GetSampleIndexMap,
+    C: Clock,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
+impl<R, C> StreamLength for Throttled<R, C>
 where
     R: StreamLength,
+    C: Clock,
 {
     #[inline]
     fn remaining(&self) -> Remaining {
@@ -147,4 +186,59 @@ where
     }
 }
 
-impl<R> FiniteStream for Throttled<R> where R: FiniteStream {}
+impl<R, C> FiniteStream for Throttled<R, C>
+where
+    R: FiniteStream,
+    C: Clock,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::FutureExt;
+
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            combinators::Throttled,
+            repeat,
+        },
+        util::clock::MockClock,
+    };
+
+    #[test]
+    fn it_paces_reads_by_sample_duration() {
+        let clock = MockClock::new();
+        let mut stream =
+            Throttled::with_clock(repeat(1u8), Duration::from_millis(1), clock.clone());
+        let mut buffer = [0u8; 10];
+
+        // the first read goes through right away
+        let num_samples = stream
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(num_samples, 10);
+
+        // then we have to wait 10 samples worth of time
+        assert!(stream.read_samples(&mut buffer).now_or_never().is_none());
+        clock.advance(Duration::from_millis(9));
+        assert!(stream.read_samples(&mut buffer).now_or_never().is_none());
+        clock.advance(Duration::from_millis(1));
+
+        let num_samples = stream
+            .read_samples(&mut buffer[..5])
+            .now_or_never()
+            .expect("pending")
+            .unwrap();
+        assert_eq!(num_samples, 5);
+
+        // a shorter read means a shorter wait
+        assert!(stream.read_samples(&mut buffer).now_or_never().is_none());
+        clock.advance(Duration::from_millis(5));
+        assert!(stream.read_samples(&mut buffer).now_or_never().is_some());
+    }
+}
@@ -11,10 +11,13 @@ use pin_project_lite::pin_project;
 
 use crate::io::{
     AsyncReadSamples,
+    AsyncWriteSamples,
     FiniteStream,
+    GetSampleIndexMap,
     GetSampleRate,
     ReadBuf,
     Remaining,
+    SampleIndexMap,
     StreamLength,
 };
 
@@ -32,6 +35,16 @@ impl<T> WithSampleRate<T> {
     pub fn new(inner: T, sample_rate: f32) -> Self {
         Self { inner, sample_rate }
     }
+
+    #[inline]
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
 }
 
 impl<T> GetSampleRate for WithSampleRate<T> {
@@ -41,6 +54,16 @@ impl<T> GetSampleRate for WithSampleRate<T> {
     }
 }
 
+impl<T> GetSampleIndexMap for WithSampleRate<T>
+where
+    T: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<T> StreamLength for WithSampleRate<T>
 where
     T: StreamLength,
@@ -69,6 +92,32 @@ where
     }
 }
 
+impl<T, S> AsyncWriteSamples<S> for WithSampleRate<T>
+where
+    T: AsyncWriteSamples<S>,
+{
+    type Error = T::Error;
+
+    #[inline]
+    fn poll_write_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &[S],
+    ) -> Poll<Result<usize, Self::Error>> {
+        self.project().inner.poll_write_samples(cx, buffer)
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    #[inline]
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
 impl<T> Stream for WithSampleRate<T>
 where
     T: Stream,
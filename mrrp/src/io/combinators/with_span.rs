@@ -13,9 +13,11 @@ use crate::io::{
     AsyncReadSamples,
     AsyncWriteSamples,
     FiniteStream,
+    GetSampleIndexMap,
     GetSampleRate,
     ReadBuf,
     Remaining,
+    SampleIndexMap,
     StreamLength,
 };
 
@@ -95,6 +97,16 @@ where
     }
 }
 
+impl<T> GetSampleIndexMap for WithSpan<T>
+where
+    T: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<T> StreamLength for WithSpan<T>
 where
     T: StreamLength,
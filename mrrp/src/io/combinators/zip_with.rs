@@ -16,13 +16,19 @@ use pin_project_lite::pin_project;
 
 use crate::{
     buf::SampleBufMut,
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
     io::{
         AsyncReadSamples,
         Buffer,
         FiniteStream,
+        GetSampleIndexMap,
         GetSampleRate,
         ReadBuf,
         Remaining,
+        SampleIndexMap,
         SizeHint,
         StreamLength,
         combinators::{
@@ -117,6 +123,16 @@ where
     }
 }
 
+impl<L, R, S, T, Sc> GetSampleIndexMap for ZipWith<L, R, S, T, Sc>
+where
+    L: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.left_stream.sample_index_map()
+    }
+}
+
 impl<L, R, S, T, Sc> StreamLength for ZipWith<L, R, S, T, Sc>
 where
     L: StreamLength,
@@ -151,6 +167,15 @@ pub enum ZipError<L, R> {
     Right(R),
 }
 
+impl<L: ClassifyError, R: ClassifyError> ClassifyError for ZipError<L, R> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            ZipError::Left(error) => error.error_kind(),
+            ZipError::Right(error) => error.error_kind(),
+        }
+    }
+}
+
 pin_project! {
     // todo: rename to Superposition?
     #[derive(Clone, Debug)]
@@ -197,6 +222,16 @@ where
     }
 }
 
+impl<L, R, S, T> GetSampleIndexMap for Summed<L, R, S, T>
+where
+    L: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<L, R, S, T> StreamLength for Summed<L, R, S, T>
 where
     L: StreamLength,
@@ -261,6 +296,16 @@ where
     }
 }
 
+impl<L, R, S, T> GetSampleIndexMap for Multiplied<L, R, S, T>
+where
+    L: GetSampleIndexMap,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        self.inner.sample_index_map()
+    }
+}
+
 impl<L, R, S, T> StreamLength for Multiplied<L, R, S, T>
 where
     L: StreamLength,
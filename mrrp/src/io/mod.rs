@@ -1,10 +1,18 @@
+mod bridge;
+mod channel;
 pub mod combinators;
+mod pump;
 mod read;
+mod sample_index;
+mod shutdown;
 pub mod test;
 mod write;
 
 use std::{
-    ops::Add,
+    ops::{
+        Add,
+        Deref,
+    },
     pin::Pin,
     task::{
         Context,
@@ -16,10 +24,25 @@ use std::{
 use pin_project_lite::pin_project;
 
 pub use self::{
+    bridge::*,
+    channel::*,
+    pump::*,
     read::*,
+    sample_index::*,
+    shutdown::*,
     write::*,
 };
-use crate::buf::UninitSlice;
+use crate::{
+    buf::{
+        BufferPolicy,
+        PolicyAllocator,
+        UninitSlice,
+    },
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+};
 
 pub trait GetSampleRate {
     fn sample_rate(&self) -> f32;
@@ -39,6 +62,20 @@ impl<T: GetSampleRate> GetSampleRate for &mut T {
     }
 }
 
+impl<T: GetSampleRate + ?Sized> GetSampleRate for Box<T> {
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        (**self).sample_rate()
+    }
+}
+
+impl<P: Deref<Target: GetSampleRate>> GetSampleRate for Pin<P> {
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        (**self).sample_rate()
+    }
+}
+
 pub trait GetCenterFrequency {
     fn center_frequency(&self) -> f32;
 }
@@ -96,6 +133,36 @@ where
     }
 }
 
+impl<T> StreamLength for Box<T>
+where
+    T: StreamLength + ?Sized,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        (**self).remaining()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> SizeHint {
+        (**self).size_hint()
+    }
+}
+
+impl<P> StreamLength for Pin<P>
+where
+    P: Deref<Target: StreamLength>,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        (**self).remaining()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> SizeHint {
+        (**self).size_hint()
+    }
+}
+
 pub trait FiniteStream: StreamLength {
     #[inline]
     fn len(&self) -> usize {
@@ -365,13 +432,26 @@ pub enum ForwardError<R, W> {
     Sink(#[source] W),
 }
 
+impl<R: ClassifyError, W: ClassifyError> ClassifyError for ForwardError<R, W> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            ForwardError::Source(error) => error.error_kind(),
+            ForwardError::Sink(error) => error.error_kind(),
+        }
+    }
+}
+
 /// The buffer used for [`Buffered`] and [`Forward`]. Ideally this would just be
 /// a SamplesMut, or at least have a proper API
 #[derive(Debug)]
+///
+/// The size is capped by the [`BufferPolicy`] at the time the buffer was
+/// created.
 struct Buffer<S> {
-    buffer: Box<UninitSlice<S>>,
+    buffer: Box<UninitSlice<S>, PolicyAllocator>,
     read_pos: usize,
     write_pos: usize,
+    max_len: usize,
 }
 
 impl<S> Default for Buffer<S> {
@@ -384,22 +464,26 @@ impl<S> Default for Buffer<S> {
 impl<S> Buffer<S> {
     #[inline]
     pub fn new(buffer_size: usize) -> Self {
+        let policy = BufferPolicy::global();
+        let max_len = policy.max_samples::<S>();
         Self {
-            buffer: UninitSlice::box_new(buffer_size),
+            buffer: UninitSlice::box_new_in(buffer_size.min(max_len), policy.allocator().clone()),
             read_pos: 0,
             write_pos: 0,
+            max_len,
         }
     }
 
     #[inline]
     pub fn grow(&mut self, new_size: usize) {
-        if new_size > self.buffer.len() {
+        if new_size.min(self.max_len) > self.buffer.len() {
             self.resize(new_size);
         }
     }
 
     pub fn resize(&mut self, new_size: usize) {
-        let mut buffer = UninitSlice::box_new(new_size);
+        let new_size = new_size.min(self.max_len);
+        let mut buffer = UninitSlice::box_new_in(new_size, Box::allocator(&self.buffer).clone());
 
         let mut read_pos = self.read_pos.min(new_size);
         let mut write_pos = self.write_pos.min(new_size);
@@ -464,6 +548,44 @@ impl<S> Buffer<S> {
         }
     }
 
+    /// Reads from `stream` until at least `level` samples are buffered.
+    ///
+    /// Unlike [`poll_fill`][Self::poll_fill] this also reads if there are
+    /// samples left in the buffer, moving them to the start of the buffer if
+    /// necessary. Returns the number of samples read, so 0 means that either
+    /// the buffer was already filled to `level`, or the stream ended.
+    pub fn poll_fill_to<R>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: Pin<&mut R>,
+        level: usize,
+    ) -> Poll<Result<usize, R::Error>>
+    where
+        R: AsyncReadSamples<S>,
+    {
+        let level = level.min(self.buffer.len());
+        let remaining = self.remaining();
+        if remaining >= level {
+            return Poll::Ready(Ok(0));
+        }
+
+        if self.read_pos + level > self.buffer.len() {
+            self.buffer.move_within(self.read_pos..self.write_pos, 0);
+            self.read_pos = 0;
+            self.write_pos = remaining;
+        }
+
+        let mut read_buf = ReadBuf::uninit(&mut self.buffer[self.write_pos..self.read_pos + level]);
+        ready!(stream.poll_read_samples(cx, &mut read_buf))?;
+        let num_samples = read_buf.filled().len();
+        unsafe {
+            read_buf.drop_unfilled_initialized();
+        }
+        self.write_pos += num_samples;
+
+        Poll::Ready(Ok(num_samples))
+    }
+
     pub fn drain(&mut self, num_samples: usize) -> BufferDrain<'_, S> {
         let remaining = num_samples.min(self.write_pos - self.read_pos);
         BufferDrain {
@@ -476,6 +598,11 @@ impl<S> Buffer<S> {
     pub fn remaining(&self) -> usize {
         self.write_pos - self.read_pos
     }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
 }
 
 impl<S> Clone for Buffer<S>
@@ -483,7 +610,8 @@ where
     S: Clone,
 {
     fn clone(&self) -> Self {
-        let mut buffer = UninitSlice::box_new(self.buffer.len());
+        let mut buffer =
+            UninitSlice::box_new_in(self.buffer.len(), Box::allocator(&self.buffer).clone());
 
         let filled = unsafe { self.buffer[self.read_pos..self.write_pos].assume_init_ref() };
         buffer[self.read_pos..self.write_pos].clone_from_slice(filled);
@@ -492,6 +620,7 @@ where
             buffer,
             read_pos: self.read_pos,
             write_pos: self.write_pos,
+            max_len: self.max_len,
         }
     }
 }
@@ -540,29 +669,47 @@ impl<'a, S> Iterator for BufferDrain<'a, S> {
     }
 }
 
+/// Buffer for samples that only live during a single read.
+///
+/// Like [`Buffer`], its size is capped by the [`BufferPolicy`].
 #[derive(Debug)]
-struct ScratchBuffer<S> {
-    buffer: Box<UninitSlice<S>>,
+pub(crate) struct ScratchBuffer<S> {
+    pub(crate) buffer: Box<UninitSlice<S>, PolicyAllocator>,
+    max_len: usize,
 }
 
 impl<S> ScratchBuffer<S> {
     pub fn new(buffer_size: usize) -> Self {
+        let policy = BufferPolicy::global();
+        let max_len = policy.max_samples::<S>();
         Self {
-            buffer: UninitSlice::box_new(buffer_size),
+            buffer: UninitSlice::box_new_in(buffer_size.min(max_len), policy.allocator().clone()),
+            max_len,
         }
     }
 
-    pub fn reserve(&mut self, length: usize) {
+    /// Makes room for `length` samples, but at most as many as the policy
+    /// allows.
+    ///
+    /// Returns how many samples fit into the buffer, which callers must use
+    /// as their read length.
+    pub fn reserve(&mut self, length: usize) -> usize {
+        let length = length.min(self.max_len);
         if length > self.buffer.len() {
-            *self = Self::new(length);
+            self.buffer = UninitSlice::box_new_in(length, Box::allocator(&self.buffer).clone());
         }
+        length
     }
 }
 
 impl<S> Clone for ScratchBuffer<S> {
     fn clone(&self) -> Self {
         Self {
-            buffer: UninitSlice::box_new(self.buffer.len()),
+            buffer: UninitSlice::box_new_in(
+                self.buffer.len(),
+                Box::allocator(&self.buffer).clone(),
+            ),
+            max_len: self.max_len,
         }
     }
 }
@@ -0,0 +1,334 @@
+use std::{
+    future::poll_fn,
+    marker::PhantomData,
+    pin::pin,
+    task::Poll,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::io::{
+    AsyncReadSamples,
+    AsyncWriteSamples,
+    ForwardError,
+    ReadBuf,
+    ScratchBuffer,
+    ShutdownGuard,
+};
+
+/// Default number of samples a [`Pump`] moves at once.
+pub const DEFAULT_CHUNK_SIZE: usize = 0x4000;
+
+type ProgressCallback = Box<dyn FnMut(&PumpStats) + Send>;
+
+/// Moves samples from a source into a sink.
+///
+/// This is like [`Forward`][super::Forward], but it can be cancelled, reports
+/// its progress, and flushes the sink when it's done. Call [`Pump::run`] to
+/// start it.
+///
+/// When the pump is cancelled it stops reading, but still writes the samples
+/// it already read, so nothing is lost between source and sink. The sink is
+/// flushed, but not closed, so it can be used further.
+#[derive(derive_more::Debug)]
+#[must_use]
+pub struct Pump<R, W, S> {
+    source: R,
+    sink: W,
+    chunk_size: usize,
+    cancellation: Option<CancellationToken>,
+    shutdown: Option<ShutdownGuard>,
+    #[debug(skip)]
+    progress: Option<(Duration, ProgressCallback)>,
+    _phantom: PhantomData<fn() -> S>,
+}
+
+impl<R, W, S> Pump<R, W, S> {
+    pub fn new(source: R, sink: W) -> Self {
+        Self {
+            source,
+            sink,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            cancellation: None,
+            shutdown: None,
+            progress: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sets how many samples are read and written at once.
+    ///
+    /// This is capped by the [`BufferPolicy`][crate::buf::BufferPolicy].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be 0");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Stops the pump when `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Stops the pump when the [`Shutdown`][super::Shutdown] of `guard` is
+    /// triggered. The shutdown waits until the pump flushed its sink, and its
+    /// summary includes the samples this pump wrote.
+    pub fn with_shutdown(mut self, guard: ShutdownGuard) -> Self {
+        self.cancellation = Some(guard.token());
+        self.shutdown = Some(guard);
+        self
+    }
+
+    /// Calls `callback` at most once per `interval` while samples are moved,
+    /// and once more when the pump stops.
+    pub fn with_progress(
+        mut self,
+        interval: Duration,
+        callback: impl FnMut(&PumpStats) + Send + 'static,
+    ) -> Self {
+        self.progress = Some((interval, Box::new(callback)));
+        self
+    }
+
+    /// Moves samples until the source ends or the pump is cancelled, and then
+    /// flushes the sink.
+    pub async fn run(self) -> Result<PumpStats, ForwardError<R::Error, W::Error>>
+    where
+        R: AsyncReadSamples<S>,
+        W: AsyncWriteSamples<S>,
+    {
+        let Self {
+            source,
+            sink,
+            chunk_size,
+            cancellation,
+            shutdown,
+            mut progress,
+            _phantom,
+        } = self;
+
+        let mut source = pin!(source);
+        let mut sink = pin!(sink);
+        let mut cancelled = pin!(async move {
+            match cancellation {
+                Some(token) => token.cancelled_owned().await,
+                None => std::future::pending().await,
+            }
+        });
+
+        let mut buffer = ScratchBuffer::<S>::new(0);
+        let chunk_size = buffer.reserve(chunk_size);
+
+        let start = Instant::now();
+        let mut last_report = start;
+        let mut stats = PumpStats::default();
+
+        loop {
+            let mut read_buf = ReadBuf::uninit(&mut buffer.buffer[..chunk_size]);
+            let result = poll_fn(|cx| {
+                if cancelled.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+                source
+                    .as_mut()
+                    .poll_read_samples(cx, &mut read_buf)
+                    .map(Some)
+            })
+            .await;
+
+            match result {
+                None => {
+                    stats.cancelled = true;
+                    break;
+                }
+                Some(Err(error)) => return Err(ForwardError::Source(error)),
+                Some(Ok(())) => {}
+            }
+
+            let mut samples = read_buf.filled();
+            if samples.is_empty() {
+                // if the read returned nothing, this is EOF
+                break;
+            }
+
+            while !samples.is_empty() {
+                let num_written = poll_fn(|cx| sink.as_mut().poll_write_samples(cx, samples))
+                    .await
+                    .map_err(ForwardError::Sink)?;
+                assert!(num_written <= samples.len());
+                samples = &samples[num_written..];
+                stats.num_samples += num_written;
+            }
+
+            unsafe {
+                read_buf.drop_unfilled_initialized();
+            }
+
+            if let Some((interval, callback)) = &mut progress {
+                let now = Instant::now();
+                if now.duration_since(last_report) >= *interval {
+                    last_report = now;
+                    stats.elapsed = now.duration_since(start);
+                    callback(&stats);
+                }
+            }
+        }
+
+        poll_fn(|cx| sink.as_mut().poll_flush(cx))
+            .await
+            .map_err(ForwardError::Sink)?;
+
+        stats.elapsed = start.elapsed();
+        if let Some((_, callback)) = &mut progress {
+            callback(&stats);
+        }
+        if let Some(guard) = shutdown {
+            guard.record(&stats);
+        }
+
+        Ok(stats)
+    }
+}
+
+/// Progress of a [`Pump`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PumpStats {
+    /// Number of samples written to the sink so far.
+    pub num_samples: usize,
+
+    /// Time since the pump started.
+    pub elapsed: Duration,
+
+    /// Whether the pump stopped because it was cancelled. This is only set on
+    /// the final stats.
+    pub cancelled: bool,
+}
+
+impl PumpStats {
+    /// Average samples per second.
+    pub fn rate(&self) -> f64 {
+        let elapsed = self.elapsed.as_secs_f64();
+        if elapsed > 0.0 {
+            self.num_samples as f64 / elapsed
+        }
+        else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        pin::Pin,
+        sync::Arc,
+        task::{
+            Context,
+            Poll,
+        },
+        time::Duration,
+    };
+
+    use futures_util::FutureExt;
+    use parking_lot::Mutex;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::io::{
+        AsyncReadSamplesExt,
+        AsyncWriteSamples,
+        Cursor,
+    };
+
+    #[derive(Debug, Default)]
+    struct VecSink {
+        samples: Vec<u32>,
+        flushed: bool,
+    }
+
+    impl AsyncWriteSamples<u32> for VecSink {
+        type Error = Infallible;
+
+        fn poll_write_samples(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buffer: &[u32],
+        ) -> Poll<Result<usize, Self::Error>> {
+            // accept less than offered, so the pump has to write again
+            let n = buffer.len().min(100);
+            self.samples.extend_from_slice(&buffer[..n]);
+            self.flushed = false;
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.flushed = true;
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.poll_flush(cx)
+        }
+    }
+
+    #[test]
+    fn it_pumps_everything_and_flushes() {
+        let input = (0..10_000).collect::<Vec<u32>>();
+        let mut sink = VecSink::default();
+        let reports = Arc::new(Mutex::new(vec![]));
+
+        let stats = Cursor::new(input.clone())
+            .pump(&mut sink)
+            .with_chunk_size(256)
+            .with_progress(Duration::ZERO, {
+                let reports = reports.clone();
+                move |stats| reports.lock().push(stats.num_samples)
+            })
+            .run()
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+
+        assert_eq!(stats.num_samples, input.len());
+        assert!(!stats.cancelled);
+        assert_eq!(sink.samples, input);
+        assert!(sink.flushed);
+
+        // one report per chunk and a final one
+        let reports = reports.lock();
+        assert_eq!(reports.len(), input.len().div_ceil(256) + 1);
+        assert_eq!(reports.last(), Some(&input.len()));
+    }
+
+    #[test]
+    fn it_stops_when_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let mut sink = VecSink::default();
+
+        let stats = Cursor::new((0..1000).collect::<Vec<u32>>())
+            .pump(&mut sink)
+            .with_cancellation(token)
+            .run()
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+
+        assert!(stats.cancelled);
+        assert_eq!(stats.num_samples, 0);
+        assert!(sink.samples.is_empty());
+        assert!(sink.flushed);
+    }
+}
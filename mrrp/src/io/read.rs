@@ -3,6 +3,8 @@ use std::{
     fmt::Debug,
     marker::PhantomData,
     mem::MaybeUninit,
+    ops::DerefMut,
+    path::Path,
     pin::Pin,
     task::{
         Context,
@@ -13,45 +15,92 @@ use std::{
 };
 
 use bytemuck::Pod;
+use num_complex::Complex;
+use num_traits::Zero;
+use tokio_util::sync::CancellationToken;
 use tracing::Span;
 
 use crate::{
     buf::{
+        BufferPolicy,
+        IndexOutOfBounds,
         SampleBuf,
         SampleBufMut,
+        TryPutError,
         UninitSlice,
     },
-    filter::resampling::{
-        Decimate,
-        Interpolate,
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    filter::{
+        channelizer::{
+            ChannelOutput,
+            Channelize,
+        },
+        multistage::{
+            DecimateMultistage,
+            MultistageDecimator,
+        },
+        resampling::{
+            Decimate,
+            Interpolate,
+            PolyphaseResampler,
+            Resample,
+            resampling_ratio,
+        },
     },
     io::{
         AsyncWriteSamples,
+        ByteReader,
         FiniteStream,
         Forward,
+        GetSampleIndexMap,
         GetSampleRate,
+        Pump,
         Remaining,
+        SampleIndexMap,
         StreamLength,
         combinators::{
+            Agc,
+            Broadcast,
+            BroadcastReceiver,
             Buffered,
             Chained,
+            ChannelSimulator,
+            Conjugate,
             Converted,
+            Deinterleave,
             Inspect,
             InspectWith,
+            Interleave,
+            InvertSpectrum,
             Limited,
             Map,
             MapErr,
             MapInPlace,
             MapInPlacePod,
             Multiplied,
+            PackBits,
+            Power,
             Repeated,
             ScanInPlaceWith,
             ScanWith,
             Scanner,
             Summed,
+            SwapIq,
+            Switch,
+            TapToFile,
             Throttled,
+            Trigger,
+            TriggerValue,
+            UnpackBits,
+            UnpackI12,
+            UntilCancelled,
             WithSampleRate,
+            WithScope,
             WithSpan,
+            WithStats,
             ZipWith,
         },
     },
@@ -59,6 +108,7 @@ use crate::{
         FromSample,
         Sample,
     },
+    spectrum::SpectrumAnalyzer,
 };
 
 // todo: We really must make this S: Copy. Lots of places assume this and it's a
@@ -113,7 +163,7 @@ impl<'a, S> ReadBuf<'a, S> {
         ReadBuf {
             buffer: &mut self.buffer[self.filled..][..max],
             filled: 0,
-            initialized: self.initialized - self.filled,
+            initialized: (self.initialized - self.filled).min(max),
         }
     }
 
@@ -168,10 +218,27 @@ impl<'a, S> ReadBuf<'a, S> {
         self.buffer.len() - self.filled
     }
 
+    /// # Panics
+    ///
+    /// Panics if `filled` is larger than the number of initialized samples.
     #[inline]
     pub fn set_filled(&mut self, filled: usize) {
-        assert!(filled <= self.initialized);
-        self.filled = filled;
+        self.try_set_filled(filled)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+
+    #[inline]
+    pub fn try_set_filled(&mut self, filled: usize) -> Result<(), IndexOutOfBounds> {
+        if filled > self.initialized {
+            Err(IndexOutOfBounds {
+                index: filled,
+                length: self.initialized,
+            })
+        }
+        else {
+            self.filled = filled;
+            Ok(())
+        }
     }
 
     #[inline]
@@ -179,12 +246,28 @@ impl<'a, S> ReadBuf<'a, S> {
         self.initialized = self.initialized.max(self.filled + n);
     }
 
+    /// # Panics
+    ///
+    /// Panics if `samples` doesn't fit into the remaining space.
     #[inline]
     pub fn put_slice(&mut self, samples: &[S])
     where
         S: Clone,
     {
-        assert!(samples.len() + self.filled <= self.buffer.len());
+        self.try_put_slice(samples)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+
+    pub fn try_put_slice(&mut self, samples: &[S]) -> Result<(), TryPutError>
+    where
+        S: Clone,
+    {
+        if samples.len() > self.remaining() {
+            return Err(TryPutError {
+                write_length: samples.len(),
+                available: self.remaining(),
+            });
+        }
 
         unsafe {
             self.buffer[self.filled..(self.filled + samples.len()).min(self.initialized)]
@@ -194,6 +277,7 @@ impl<'a, S> ReadBuf<'a, S> {
         self.buffer[self.filled..][..samples.len()].clone_from_slice(samples);
         self.filled += samples.len();
         self.initialized = self.initialized.max(self.filled);
+        Ok(())
     }
 
     #[inline]
@@ -208,6 +292,7 @@ impl<'a, S> ReadBuf<'a, S> {
 impl<'a, S> SampleBufMut<S> for ReadBuf<'a, S> {
     #[inline]
     unsafe fn advance_mut(&mut self, amount: usize) {
+        debug_assert!(amount <= self.remaining());
         self.filled += amount;
         self.initialized = self.initialized.max(self.filled);
     }
@@ -257,6 +342,48 @@ where
     }
 }
 
+impl<R, S> AsyncReadSamples<S> for Box<R>
+where
+    R: AsyncReadSamples<S> + Unpin + ?Sized,
+{
+    type Error = <R as AsyncReadSamples<S>>::Error;
+
+    #[inline]
+    fn poll_read_samples(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut **self).poll_read_samples(cx, buffer)
+    }
+}
+
+impl<P, S> AsyncReadSamples<S> for Pin<P>
+where
+    P: DerefMut<Target: AsyncReadSamples<S>> + Unpin,
+{
+    type Error = <P::Target as AsyncReadSamples<S>>::Error;
+
+    #[inline]
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().as_mut().poll_read_samples(cx, buffer)
+    }
+}
+
+/// Type-erased sample stream.
+///
+/// Useful if the pipeline is only known at runtime, e.g. when the user can
+/// switch between demodulators. Create one with
+/// [`boxed`][AsyncReadSamplesExt::boxed]. Since the sample rate is not part of
+/// the trait object, use
+/// [`with_sample_rate`][AsyncReadSamplesExt::with_sample_rate] on the boxed
+/// stream if later combinators need it.
+pub type DynReadSamples<S, E> = Pin<Box<dyn AsyncReadSamples<S, Error = E> + Send>>;
+
 pub trait IntoReadSamples<S> {
     type ReadSamples: AsyncReadSamples<S>;
 
@@ -318,6 +445,7 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         ReadToEnd {
             read_samples: Pin::new(self),
             buffer,
+            policy: BufferPolicy::global(),
         }
     }
 
@@ -344,6 +472,27 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         MapErr::new(self, f)
     }
 
+    /// Converts errors into the type-erased [`Error`][crate::Error], which
+    /// keeps the [`ErrorKind`][crate::error::ErrorKind] of the original
+    /// error.
+    #[inline]
+    fn erase_err(self) -> MapErr<Self, fn(Self::Error) -> crate::Error>
+    where
+        Self::Error: ClassifyError + std::error::Error + Send + Sync + 'static,
+        Self: Sized,
+    {
+        MapErr::new(self, crate::Error::erase as fn(Self::Error) -> crate::Error)
+    }
+
+    /// Boxes the stream into a [`DynReadSamples`].
+    #[inline]
+    fn boxed(self) -> DynReadSamples<S, Self::Error>
+    where
+        Self: Sized + Send + 'static,
+    {
+        Box::pin(self)
+    }
+
     #[inline]
     fn scan_with<Sc>(self, scanner: Sc) -> ScanWith<Self, S, Sc>
     where
@@ -405,6 +554,77 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         MapInPlacePod::new(self, f)
     }
 
+    /// Scales the samples to a peak magnitude of 1 with an [`Agc`] with the
+    /// default attack and decay.
+    ///
+    /// Use [`scan_in_place_with`][Self::scan_in_place_with] for an [`Agc`]
+    /// with other parameters.
+    #[inline]
+    fn agc(self) -> ScanInPlaceWith<Self, Agc>
+    where
+        Agc: Scanner<S, Output = S>,
+        Self: Sized + GetSampleRate,
+    {
+        let agc = Agc::new(self.sample_rate());
+        ScanInPlaceWith::new(self, agc)
+    }
+
+    /// Conjugates the samples, which mirrors the spectrum.
+    ///
+    /// Use this directly after a source whose spectrum appears mirrored,
+    /// before any filtering or demodulation.
+    #[inline]
+    fn conjugate(self) -> ScanInPlaceWith<Self, Conjugate>
+    where
+        Conjugate: Scanner<S, Output = S>,
+        Self: Sized,
+    {
+        ScanInPlaceWith::new(self, Conjugate)
+    }
+
+    /// Swaps I and Q, for sources with swapped I/Q wiring.
+    #[inline]
+    fn swap_iq(self) -> ScanInPlaceWith<Self, SwapIq>
+    where
+        SwapIq: Scanner<S, Output = S>,
+        Self: Sized,
+    {
+        ScanInPlaceWith::new(self, SwapIq)
+    }
+
+    /// Negates every other sample, which shifts the spectrum by half the
+    /// sample rate.
+    #[inline]
+    fn invert_spectrum(self) -> ScanInPlaceWith<Self, InvertSpectrum>
+    where
+        InvertSpectrum: Scanner<S, Output = S>,
+        Self: Sized,
+    {
+        ScanInPlaceWith::new(self, InvertSpectrum::default())
+    }
+
+    /// Adds noise, a frequency offset and timing drift to IQ samples, like a
+    /// radio channel would. The impairments are set on the returned
+    /// [`ChannelSimulator`].
+    #[inline]
+    fn simulate_channel<G>(self, rng: G) -> ChannelSimulator<Self, G>
+    where
+        Self: Sized + GetSampleRate,
+    {
+        ChannelSimulator::new(self, rng)
+    }
+
+    /// Estimates the power spectrum of IQ samples, with FFTs of `fft_size`.
+    /// Windowing, overlap and averaging are set on the returned
+    /// [`SpectrumAnalyzer`].
+    #[inline]
+    fn analyze_spectrum(self, fft_size: usize) -> SpectrumAnalyzer<Self>
+    where
+        Self: Sized + GetSampleRate,
+    {
+        SpectrumAnalyzer::new(self, fft_size)
+    }
+
     #[inline]
     fn inspect_with<I>(self, inspector: I) -> InspectWith<Self, I>
     where
@@ -422,6 +642,114 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         Inspect::new(self, f)
     }
 
+    /// Passes the samples through unchanged, while keeping running
+    /// statistics (DC offset, RMS, peak and sample count).
+    ///
+    /// The statistics can be read with a handle obtained from
+    /// [`WithStats::handle`].
+    #[inline]
+    fn stats(self) -> WithStats<Self, S>
+    where
+        S: Power + Zero,
+        Self: Sized,
+    {
+        WithStats::new(self)
+    }
+
+    /// Passes the samples through unchanged, while capturing triggered
+    /// snapshots of `frame_length` samples, like an oscilloscope.
+    ///
+    /// Each frame starts `pre_trigger` samples before its trigger. Frames are
+    /// read with a handle obtained from [`WithScope::handle`].
+    #[inline]
+    fn scope(self, frame_length: usize, pre_trigger: usize, trigger: Trigger) -> WithScope<Self, S>
+    where
+        S: TriggerValue,
+        Self: Sized,
+    {
+        WithScope::new(self, frame_length, pre_trigger, trigger)
+    }
+
+    /// Passes the samples through unchanged, while writing up to
+    /// `max_samples` of them to a raw file at `path`, with a JSON sidecar.
+    ///
+    /// The tap can be turned on and off with a handle obtained from
+    /// [`TapToFile::handle`]. See [`TapInspector`] for the file format.
+    #[inline]
+    fn tap_to_file(
+        self,
+        path: impl AsRef<Path>,
+        max_samples: u64,
+    ) -> Result<TapToFile<Self, S>, std::io::Error>
+    where
+        S: Pod,
+        Self: Sized,
+    {
+        TapToFile::new(self, path, max_samples)
+    }
+
+    /// Packs a bit stream into bytes, e.g. for the output of a symbol slicer.
+    ///
+    /// Use [`PackBits::bit_slip`] to change the byte alignment.
+    #[inline]
+    fn pack_bits(self, msb_first: bool) -> PackBits<Self>
+    where
+        Self: AsyncReadSamples<bool> + Sized,
+    {
+        PackBits::new(self, msb_first)
+    }
+
+    /// Unpacks a byte stream into bits.
+    #[inline]
+    fn unpack_bits(self, msb_first: bool) -> UnpackBits<Self>
+    where
+        Self: AsyncReadSamples<u8> + Sized,
+    {
+        UnpackBits::new(self, msb_first)
+    }
+
+    /// Unpacks a byte stream of IQ pairs of 12-bit samples, 3 bytes per pair.
+    ///
+    /// See [`I12::unpack_pair`](crate::sample::I12::unpack_pair) for the
+    /// layout.
+    #[inline]
+    fn unpack_i12(self) -> UnpackI12<Self>
+    where
+        Self: AsyncReadSamples<u8> + Sized,
+    {
+        UnpackI12::new(self)
+    }
+
+    /// Turns planar blocks of `block_size` I components followed by
+    /// `block_size` Q components into complex samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is 0.
+    #[inline]
+    fn interleave(self, block_size: usize) -> Interleave<Self, S>
+    where
+        Self: Sized,
+        S: Copy + Default,
+    {
+        Interleave::new(self, block_size)
+    }
+
+    /// Turns complex samples into planar blocks of `block_size` I components
+    /// followed by `block_size` Q components.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is 0.
+    #[inline]
+    fn deinterleave<T>(self, block_size: usize) -> Deinterleave<Self, T>
+    where
+        Self: AsyncReadSamples<Complex<T>> + Sized,
+        T: Copy + Default,
+    {
+        Deinterleave::new(self, block_size)
+    }
+
     #[inline]
     fn buffered(self, buffer_size: usize) -> Buffered<Self, S>
     where
@@ -430,6 +758,51 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         Buffered::new(self, buffer_size)
     }
 
+    /// Splits the stream into `num_receivers` streams that each get all
+    /// samples.
+    ///
+    /// This uses the default capacity and [`LagPolicy::Block`]. Use
+    /// [`Broadcast`] to configure these.
+    ///
+    /// [`LagPolicy::Block`]: crate::io::combinators::LagPolicy::Block
+    #[inline]
+    fn broadcast(self, num_receivers: usize) -> Vec<BroadcastReceiver<Self, S>>
+    where
+        Self: Sized,
+    {
+        Broadcast::new(self).receivers(num_receivers)
+    }
+
+    /// Splits the stream into two streams that both get all samples.
+    ///
+    /// See [`broadcast`][Self::broadcast].
+    #[inline]
+    fn tee(self) -> (BroadcastReceiver<Self, S>, BroadcastReceiver<Self, S>)
+    where
+        Self: Sized,
+    {
+        Broadcast::new(self).tee()
+    }
+
+    /// Splits IQ samples into `num_channels` equally spaced channels with a
+    /// [`PolyphaseChannelizer`]. The channels are returned by index, with
+    /// channel 0 centered at DC.
+    ///
+    /// Use [`Channelize`] to configure the buffering.
+    ///
+    /// [`PolyphaseChannelizer`]: crate::filter::channelizer::PolyphaseChannelizer
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_channels` is 0.
+    #[inline]
+    fn channelize(self, num_channels: usize) -> Vec<ChannelOutput<Self>>
+    where
+        Self: Sized,
+    {
+        Channelize::new(self, num_channels).channels()
+    }
+
     #[inline]
     fn forward<W>(self, sink: W, buffer_size: usize) -> Forward<Self, W, S>
     where
@@ -439,6 +812,28 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         Forward::new(self, sink, buffer_size)
     }
 
+    /// Moves all samples into `sink`, with cancellation and progress
+    /// reporting. See [`Pump`].
+    #[inline]
+    fn pump<W>(self, sink: W) -> Pump<Self, W, S>
+    where
+        Self: Sized,
+        W: AsyncWriteSamples<S>,
+    {
+        Pump::new(self, sink)
+    }
+
+    /// Reads the samples as bytes with [`Read`][std::io::Read] or tokio's
+    /// [`AsyncRead`][tokio::io::AsyncRead].
+    #[inline]
+    fn into_byte_reader(self) -> ByteReader<Self, S>
+    where
+        Self: Sized,
+        S: Pod,
+    {
+        ByteReader::new(self)
+    }
+
     #[inline]
     fn with_span(self, span: Span) -> WithSpan<Self>
     where
@@ -447,6 +842,15 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         WithSpan::new(self, span)
     }
 
+    /// Ends the stream once `token` is cancelled, e.g. on shutdown.
+    #[inline]
+    fn until_cancelled(self, token: CancellationToken) -> UntilCancelled<Self>
+    where
+        Self: Sized,
+    {
+        UntilCancelled::new(self, token)
+    }
+
     #[inline]
     fn decimate(self, factor: usize) -> Decimate<Self>
     where
@@ -464,6 +868,16 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         self.decimate((sample_rate / target_sample_rate).round() as usize)
     }
 
+    /// Decimates with a [`MultistageDecimator`], e.g. one designed by
+    /// [`design_decimator`][crate::filter::multistage::design_decimator].
+    #[inline]
+    fn decimate_multistage(self, decimator: MultistageDecimator<S>) -> DecimateMultistage<Self, S>
+    where
+        Self: Sized,
+    {
+        DecimateMultistage::new(self, decimator)
+    }
+
     #[inline]
     fn interpolate(self, factor: usize) -> Interpolate<Self>
     where
@@ -481,6 +895,33 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         self.interpolate((target_sample_rate / sample_rate).round() as usize)
     }
 
+    /// Resamples by `interpolation / decimation` with a [`PolyphaseResampler`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if either factor is 0, or the filter can't be designed.
+    #[inline]
+    fn resample(self, interpolation: usize, decimation: usize) -> Resample<Self, S>
+    where
+        Self: Sized,
+    {
+        let resampler = PolyphaseResampler::new(interpolation, decimation)
+            .expect("failed to design resampling filter");
+        Resample::new(self, resampler)
+    }
+
+    /// Resamples to `target_sample_rate`, which may be any rational multiple
+    /// of the sample rate, e.g. from 2.4 MHz to 48 kHz. See
+    /// [`resampling_ratio`] for how the ratio is approximated.
+    #[inline]
+    fn resample_to(self, target_sample_rate: f32) -> Resample<Self, S>
+    where
+        Self: Sized + GetSampleRate,
+    {
+        let (interpolation, decimation) = resampling_ratio(self.sample_rate(), target_sample_rate);
+        self.resample(interpolation, decimation)
+    }
+
     #[inline]
     fn throttle(self, sample_duration: Duration) -> Throttled<Self>
     where
@@ -551,6 +992,20 @@ pub trait AsyncReadSamplesExt<S>: AsyncReadSamples<S> {
         ZipWith::new(self, other, scanner)
     }
 
+    /// Selects between this stream (A) and `other` (B), with an optional
+    /// crossfade.
+    ///
+    /// Both streams are read in lockstep. Use [`Switch::handle`] to switch
+    /// between them, e.g. from a UI task.
+    #[inline]
+    fn switch<B>(self, other: B) -> Switch<Self, B, S>
+    where
+        Self: Sized,
+        B: AsyncReadSamples<S> + Sized,
+    {
+        Switch::new(self, other)
+    }
+
     /// Repeats a stream indefinitely.
     ///
     /// Refer to [`Repeated`] about memory usage.
@@ -720,6 +1175,7 @@ where
 {
     read_samples: Pin<&'a mut R>,
     buffer: &'a mut Vec<S>,
+    policy: BufferPolicy,
 }
 
 impl<'a, 'b, R, S> Future for ReadToEnd<'a, R, S>
@@ -732,12 +1188,15 @@ where
         loop {
             let this = &mut *self;
 
+            // the vector itself is owned by the caller, so its length isn't limited, but we
+            // don't reserve huge amounts upfront just because a stream claims to be long.
             let size_hint = this.read_samples.size_hint();
-            if let Some(upper_bound) = size_hint.upper_bound {
-                this.buffer.reserve_exact(upper_bound);
+            let additional = this.policy.buffer_size::<S>(size_hint);
+            if size_hint.upper_bound.is_some() {
+                this.buffer.reserve_exact(additional);
             }
             else {
-                this.buffer.reserve(size_hint.lower_bound);
+                this.buffer.reserve(additional);
             }
 
             let (poll, num_samples_read) = this.buffer.with_read_buf(|read_buf| {
@@ -803,6 +1262,15 @@ pub enum EofError<E> {
     Other(#[from] E),
 }
 
+impl<E: ClassifyError> ClassifyError for EofError<E> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            EofError::Eof { .. } => ErrorKind::Eof,
+            EofError::Other(error) => error.error_kind(),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Repeat<S> {
     pub sample: S,
@@ -824,6 +1292,13 @@ where
     }
 }
 
+impl<S> GetSampleIndexMap for Repeat<S> {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
+    }
+}
+
 impl<S> StreamLength for Repeat<S> {
     #[inline]
     fn remaining(&self) -> Remaining {
@@ -851,6 +1326,13 @@ impl<S> AsyncReadSamples<S> for NullSource {
     }
 }
 
+impl GetSampleIndexMap for NullSource {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
+    }
+}
+
 impl StreamLength for NullSource {
     #[inline]
     fn remaining(&self) -> Remaining {
@@ -884,6 +1366,13 @@ where
     }
 }
 
+impl<S> GetSampleIndexMap for Silence<S> {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
+    }
+}
+
 impl<S> StreamLength for Silence<S> {
     #[inline]
     fn remaining(&self) -> Remaining {
@@ -924,8 +1413,26 @@ where
         self.position
     }
 
+    /// # Panics
+    ///
+    /// Panics if `position` is past the end of the buffer.
     pub fn set_position(&mut self, position: usize) {
-        assert!(position <= self.buffer.as_ref().len());
+        self.try_set_position(position)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+
+    pub fn try_set_position(&mut self, position: usize) -> Result<(), IndexOutOfBounds> {
+        let length = self.buffer.as_ref().len();
+        if position > length {
+            Err(IndexOutOfBounds {
+                index: position,
+                length,
+            })
+        }
+        else {
+            self.position = position;
+            Ok(())
+        }
     }
 
     pub fn data(&self) -> &B {
@@ -958,6 +1465,13 @@ where
     }
 }
 
+impl<B, S> GetSampleIndexMap for Cursor<B, S> {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
+    }
+}
+
 impl<B, S> StreamLength for Cursor<B, S>
 where
     B: AsRef<[S]>,
@@ -1010,6 +1524,13 @@ where
     }
 }
 
+impl<B, S> GetSampleIndexMap for BufSource<B, S> {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
+    }
+}
+
 impl<B, S> StreamLength for BufSource<B, S>
 where
     B: SampleBuf<S>,
@@ -1036,9 +1557,29 @@ mod tests {
 
     use crate::io::read::{
         AsyncReadSamplesExt,
+        Cursor,
+        DynReadSamples,
         repeat,
+        silence,
     };
 
+    #[test]
+    fn cursor_sets_position() {
+        let mut cursor = Cursor::new([1u8, 2, 3, 4]);
+        cursor.set_position(2);
+        assert_eq!(cursor.position(), 2);
+        assert!(cursor.try_set_position(5).is_err());
+        assert_eq!(cursor.position(), 2);
+
+        let mut output = [0u8; 2];
+        cursor
+            .read_samples_exact(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .expect("test stream error");
+        assert_eq!(output, [3, 4]);
+    }
+
     #[test]
     fn repeat_outputs_repeated_samples() {
         let mut input = repeat(12u8);
@@ -1112,4 +1653,30 @@ mod tests {
             assert_eq!(*sample, -23);
         });
     }
+
+    #[test]
+    fn boxed_streams_can_be_selected_at_runtime() {
+        for invert in [false, true] {
+            let input: DynReadSamples<i16, _> = if invert {
+                repeat(12i16).map(|sample| -sample).boxed()
+            }
+            else {
+                repeat(12i16).boxed()
+            };
+
+            let mut output = [0i16; 100];
+            input
+                .chain(silence())
+                .map(|sample| sample * 2)
+                .read_samples_exact(&mut output)
+                .now_or_never()
+                .expect("test stream pending")
+                .expect("test stream error");
+
+            let expected = if invert { -24 } else { 24 };
+            output.iter().for_each(|sample| {
+                assert_eq!(*sample, expected);
+            });
+        }
+    }
 }
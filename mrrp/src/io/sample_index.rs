@@ -0,0 +1,151 @@
+use std::{
+    ops::Deref,
+    pin::Pin,
+    time::Duration,
+};
+
+/// Maps sample indices at the source of a pipeline to sample indices at the
+/// output of a stream.
+///
+/// Rate-changing combinators (e.g.
+/// [`Decimate`][crate::filter::resampling::Decimate]) and filters with a group
+/// delay change how sample indices relate to each other. Streams that implement
+/// [`GetSampleIndexMap`] keep track of this, so that a decoder that found an
+/// event at output sample `n` can tell where that event was in the input, and
+/// vice versa.
+///
+/// The mapping is affine: `output = scale * input + offset`. Indices are
+/// fractional, since e.g. decimation doesn't map every input sample to an
+/// output sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampleIndexMap {
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Default for SampleIndexMap {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl SampleIndexMap {
+    pub const IDENTITY: Self = Self {
+        scale: 1.0,
+        offset: 0.0,
+    };
+
+    #[inline]
+    pub fn decimation(factor: usize) -> Self {
+        Self {
+            scale: 1.0 / factor as f64,
+            offset: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn interpolation(factor: usize) -> Self {
+        Self {
+            scale: factor as f64,
+            offset: 0.0,
+        }
+    }
+
+    /// Output lags the input by `num_samples`.
+    #[inline]
+    pub fn delay(num_samples: f64) -> Self {
+        Self {
+            scale: 1.0,
+            offset: num_samples,
+        }
+    }
+
+    /// Applies `next` after this mapping.
+    #[inline]
+    pub fn then(self, next: Self) -> Self {
+        Self {
+            scale: self.scale * next.scale,
+            offset: self.offset * next.scale + next.offset,
+        }
+    }
+
+    /// Output index at which the input sample with index `input` appears.
+    #[inline]
+    pub fn to_output(&self, input: f64) -> f64 {
+        self.scale * input + self.offset
+    }
+
+    /// Input index that corresponds to output sample `output`.
+    #[inline]
+    pub fn to_input(&self, output: f64) -> f64 {
+        (output - self.offset) / self.scale
+    }
+
+    /// Time since the first input sample, at which the output sample `output`
+    /// was received.
+    pub fn to_input_time(&self, output: f64, input_sample_rate: f32) -> Duration {
+        Duration::from_secs_f64(self.to_input(output).max(0.0) / f64::from(input_sample_rate))
+    }
+}
+
+/// Streams that know how their output sample indices relate to the sample
+/// indices of the source of the pipeline.
+///
+/// Sources return [`SampleIndexMap::IDENTITY`].
+pub trait GetSampleIndexMap {
+    fn sample_index_map(&self) -> SampleIndexMap;
+}
+
+impl<T: GetSampleIndexMap + ?Sized> GetSampleIndexMap for &T {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        (**self).sample_index_map()
+    }
+}
+
+impl<T: GetSampleIndexMap + ?Sized> GetSampleIndexMap for &mut T {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        (**self).sample_index_map()
+    }
+}
+
+impl<T: GetSampleIndexMap + ?Sized> GetSampleIndexMap for Box<T> {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        (**self).sample_index_map()
+    }
+}
+
+impl<P> GetSampleIndexMap for Pin<P>
+where
+    P: Deref<Target: GetSampleIndexMap>,
+{
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        (**self).sample_index_map()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GetSampleIndexMap;
+    use crate::{
+        filter::fir::FirFilter,
+        io::{
+            AsyncReadSamplesExt,
+            repeat,
+        },
+    };
+
+    #[test]
+    fn it_maps_through_decimation_and_filter_delay() {
+        let stream = repeat(0.0f32)
+            .decimate(4)
+            .scan_in_place_with(FirFilter::<f32, f32>::new(vec![0.2; 5]));
+
+        let map = stream.sample_index_map();
+        assert_eq!(map.to_output(100.0), 27.0);
+        assert_eq!(map.to_input(27.0), 100.0);
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    pin::pin,
+    sync::Arc,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use crate::io::PumpStats;
+
+/// Stops pipelines gracefully, e.g. on Ctrl-C.
+///
+/// Every pipeline holds a [`ShutdownGuard`] while it runs. When the shutdown
+/// is triggered, the pipelines stop reading through the guard's
+/// [`token`][ShutdownGuard::token], flush their sinks and drop their guards.
+/// [`Shutdown::shutdown`] waits for that, so files are complete before the
+/// program exits.
+///
+/// A [`Pump`][super::Pump] does all of this with
+/// [`with_shutdown`][super::Pump::with_shutdown]. Other streams can be ended
+/// with [`until_cancelled`][super::AsyncReadSamplesExt::until_cancelled].
+#[derive(Debug)]
+pub struct Shutdown {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    token: CancellationToken,
+    state: Mutex<State>,
+    finished: Notify,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    num_running: usize,
+    summary: ShutdownSummary,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                token: CancellationToken::new(),
+                state: Mutex::new(State::default()),
+                finished: Notify::new(),
+            }),
+        }
+    }
+
+    /// Token that is cancelled when the shutdown is triggered. Cancelling it
+    /// triggers the shutdown, e.g. from a signal handler.
+    #[inline]
+    pub fn token(&self) -> CancellationToken {
+        self.shared.token.clone()
+    }
+
+    #[inline]
+    pub fn trigger(&self) {
+        self.shared.token.cancel();
+    }
+
+    #[inline]
+    pub fn is_triggered(&self) -> bool {
+        self.shared.token.is_cancelled()
+    }
+
+    /// Registers a pipeline. The shutdown waits until the guard is dropped.
+    pub fn guard(&self) -> ShutdownGuard {
+        self.shared.state.lock().num_running += 1;
+        ShutdownGuard {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Triggers the shutdown, and waits until all pipelines stopped, but at
+    /// most for `timeout`.
+    pub async fn shutdown(self, timeout: Duration) -> ShutdownSummary {
+        let start = Instant::now();
+        self.trigger();
+
+        let all_stopped = async {
+            loop {
+                let mut finished = pin!(self.shared.finished.notified());
+                finished.as_mut().enable();
+                if self.shared.state.lock().num_running == 0 {
+                    break;
+                }
+                finished.await;
+            }
+        };
+        if tokio::time::timeout(timeout, all_stopped).await.is_err() {
+            tracing::warn!("Timed out waiting for pipelines to stop");
+        }
+
+        let state = self.shared.state.lock();
+        ShutdownSummary {
+            num_timed_out: state.num_running,
+            elapsed: start.elapsed(),
+            ..state.summary
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A pipeline that a [`Shutdown`] waits for.
+#[derive(Debug)]
+pub struct ShutdownGuard {
+    shared: Arc<Shared>,
+}
+
+impl ShutdownGuard {
+    /// Token that is cancelled when the shutdown is triggered.
+    #[inline]
+    pub fn token(&self) -> CancellationToken {
+        self.shared.token.clone()
+    }
+
+    #[inline]
+    pub fn is_triggered(&self) -> bool {
+        self.shared.token.is_cancelled()
+    }
+
+    /// Adds the final stats of a pump to the [`ShutdownSummary`].
+    pub fn record(&self, stats: &PumpStats) {
+        self.shared.state.lock().summary.num_samples += stats.num_samples;
+    }
+}
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.num_running -= 1;
+        state.summary.num_stopped += 1;
+        drop(state);
+        self.shared.finished.notify_waiters();
+    }
+}
+
+/// What happened during a [`Shutdown`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShutdownSummary {
+    /// Number of pipelines that stopped, including the ones that stopped
+    /// before the shutdown.
+    pub num_stopped: usize,
+
+    /// Number of pipelines that were still running after the timeout.
+    pub num_timed_out: usize,
+
+    /// Number of samples the pumps wrote in total.
+    pub num_samples: usize,
+
+    /// How long the shutdown took.
+    pub elapsed: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        pin::Pin,
+        task::{
+            Context,
+            Poll,
+        },
+        time::Duration,
+    };
+
+    use super::Shutdown;
+    use crate::io::{
+        AsyncReadSamplesExt,
+        AsyncWriteSamples,
+        Cursor,
+        PumpStats,
+    };
+
+    /// Accepts all samples. A [`NullSink`][crate::io::NullSink] never accepts
+    /// any.
+    struct DiscardSink;
+
+    impl<S> AsyncWriteSamples<S> for DiscardSink {
+        type Error = Infallible;
+
+        fn poll_write_samples(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buffer: &[S],
+        ) -> Poll<Result<usize, Self::Error>> {
+            Poll::Ready(Ok(buffer.len()))
+        }
+
+        fn poll_flush(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn it_waits_for_pipelines_to_stop() {
+        let shutdown = Shutdown::new();
+
+        let guard = shutdown.guard();
+        tokio::spawn(async move {
+            guard.token().cancelled().await;
+            // e.g. flushing a sink
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            guard.record(&PumpStats {
+                num_samples: 42,
+                ..Default::default()
+            });
+        });
+
+        let stats = Cursor::new((0..1000).collect::<Vec<u32>>())
+            .pump(DiscardSink)
+            .with_shutdown(shutdown.guard())
+            .run()
+            .await
+            .unwrap();
+        assert!(!stats.cancelled);
+
+        let hung = shutdown.guard();
+
+        let summary = shutdown.shutdown(Duration::from_millis(100)).await;
+        assert_eq!(summary.num_stopped, 2);
+        assert_eq!(summary.num_timed_out, 1);
+        assert_eq!(summary.num_samples, 1042);
+        drop(hung);
+    }
+}
@@ -6,6 +6,24 @@ use std::{
         Context,
         Poll,
     },
+    time::Duration,
+};
+
+use bytemuck::Pod;
+
+use crate::{
+    io::{
+        ByteWriter,
+        GetSampleRate,
+        combinators::{
+            BufferedSink,
+            ConvertedSink,
+            MappedSink,
+            ThrottledSink,
+            WithSampleRate,
+        },
+    },
+    sample::FromSample,
 };
 
 pub trait AsyncWriteSamples<S> {
@@ -120,6 +138,70 @@ pub trait AsyncWriteSamplesExt<S>: AsyncWriteSamples<S> {
             buffer,
         }
     }
+
+    #[inline]
+    fn with_sample_rate(self, sample_rate: f32) -> WithSampleRate<Self>
+    where
+        Self: Sized,
+    {
+        WithSampleRate::new(self, sample_rate)
+    }
+
+    /// Maps the samples with `f` before they're written to this sink.
+    #[inline]
+    fn map<Q, F>(self, f: F) -> MappedSink<Self, S, F>
+    where
+        F: FnMut(Q) -> S,
+        Self: Sized,
+    {
+        MappedSink::new(self, f)
+    }
+
+    /// Converts samples of type `Q` to the sample type of this sink.
+    #[inline]
+    fn convert<Q>(self) -> ConvertedSink<Self, Q, S>
+    where
+        S: FromSample<Q>,
+        Self: Sized,
+    {
+        MappedSink::new(self, S::from_sample as fn(Q) -> S)
+    }
+
+    #[inline]
+    fn buffered(self, buffer_size: usize) -> BufferedSink<Self, S>
+    where
+        Self: Sized,
+    {
+        BufferedSink::new(self, buffer_size)
+    }
+
+    #[inline]
+    fn throttle(self, sample_duration: Duration) -> ThrottledSink<Self>
+    where
+        Self: Sized,
+    {
+        ThrottledSink::new(self, sample_duration)
+    }
+
+    #[inline]
+    fn throttle_to_sample_rate(self) -> ThrottledSink<Self>
+    where
+        Self: Sized + GetSampleRate,
+    {
+        let sample_duration = Duration::from_secs_f32(1.0 / self.sample_rate());
+        self.throttle(sample_duration)
+    }
+
+    /// Writes bytes to this sink with [`Write`][std::io::Write] or tokio's
+    /// [`AsyncWrite`][tokio::io::AsyncWrite].
+    #[inline]
+    fn into_byte_writer(self) -> ByteWriter<Self, S>
+    where
+        Self: Sized,
+        S: Pod,
+    {
+        ByteWriter::new(self)
+    }
 }
 
 impl<W, S> AsyncWriteSamplesExt<S> for W where W: AsyncWriteSamples<S> + ?Sized {}
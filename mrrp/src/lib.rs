@@ -1,14 +1,70 @@
 #![feature(allocator_api)]
 #![feature(get_mut_unchecked)]
 
+pub mod analysis;
 #[cfg(feature = "audio")]
 pub mod audio;
 pub mod buf;
 pub mod chunk;
+pub mod coding;
+pub mod error;
+pub mod fft;
 pub mod filter;
+pub mod frequency;
 pub mod io;
+pub mod measure;
 pub mod modem;
+pub mod prelude;
 pub mod sample;
 pub mod sink;
 pub mod source;
+pub mod spectrum;
+pub mod sync;
 pub mod util;
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+};
+
+pub use self::error::Error;
+use crate::source::{
+    ComplexSinusoid,
+    SignalGenerator,
+    SignalGeneratorReadSamples,
+    SineWave,
+    file::{
+        FromWavSamples,
+        WavSource,
+    },
+};
+
+/// Endless sine wave of `frequency` (in Hz) as a stream.
+#[inline]
+pub fn sine(frequency: f32, sample_rate: f32) -> SignalGeneratorReadSamples<SineWave> {
+    SineWave::new(frequency, sample_rate).into_read_samples()
+}
+
+/// Endless complex sinusoid of `frequency` (in Hz) as a stream. Negative
+/// frequencies rotate clockwise.
+#[inline]
+pub fn complex_sinusoid(
+    frequency: f32,
+    sample_rate: f32,
+) -> SignalGeneratorReadSamples<ComplexSinusoid> {
+    ComplexSinusoid::new(frequency, sample_rate).into_read_samples()
+}
+
+/// Opens a WAV file as a stream.
+///
+/// Stereo files are read as IQ samples if `S` is complex.
+#[inline]
+pub fn from_wav<S>(
+    path: impl AsRef<Path>,
+) -> Result<WavSource<BufReader<File>, S>, source::file::Error>
+where
+    S: FromWavSamples,
+{
+    WavSource::from_path(path)
+}
@@ -0,0 +1,549 @@
+//! Signal measurements
+//!
+//! The meters in this module are [`Inspector`]s, so they measure a stream
+//! without changing it, when attached with
+//! [`inspect_with`][crate::io::AsyncReadSamplesExt::inspect_with]. Each
+//! measures over a window of samples, and publishes the measurement of the
+//! last complete window through a [`MeasurementHandle`], e.g. for a squelch,
+//! or a test that asserts the level of a signal.
+//!
+//! - [`PowerMeter`] measures the RMS and peak power.
+//! - [`OccupiedBandwidthMeter`] measures the bandwidth that contains most of
+//!   the power of an IQ stream.
+//! - [`SnrMeter`] estimates the signal-to-noise ratio of a signal in an IQ
+//!   stream, from the noise outside of the signal's band.
+
+use std::{
+    ops::Range,
+    sync::Arc,
+};
+
+use num_complex::Complex;
+use parking_lot::Mutex;
+
+use crate::{
+    io::combinators::{
+        Inspector,
+        Power,
+    },
+    spectrum::{
+        Spectrum,
+        WelchEstimator,
+    },
+};
+
+/// Bins next to the signal's band that [`SnrMeter`] doesn't count as noise,
+/// because the window leaks the signal into them.
+const GUARD_BINS: usize = 2;
+
+/// Cloneable handle to the latest measurement of a meter.
+#[derive(Debug)]
+pub struct MeasurementHandle<T> {
+    latest: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> Clone for MeasurementHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            latest: self.latest.clone(),
+        }
+    }
+}
+
+impl<T> MeasurementHandle<T> {
+    fn new() -> Self {
+        Self {
+            latest: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn publish(&self, measurement: T) {
+        *self.latest.lock() = Some(measurement);
+    }
+
+    /// The measurement of the last complete window, if there was one yet.
+    pub fn latest(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.latest.lock().clone()
+    }
+
+    /// Returns the latest measurement, and clears it, so that the next call
+    /// only returns a new one.
+    pub fn take(&self) -> Option<T> {
+        self.latest.lock().take()
+    }
+}
+
+/// Measurement of a [`PowerMeter`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerMeasurement {
+    /// Mean power of the samples, i.e. the square of their RMS.
+    pub power: f32,
+
+    /// Power of the strongest sample.
+    pub peak_power: f32,
+}
+
+impl PowerMeasurement {
+    /// Root mean square of the sample magnitudes.
+    #[inline]
+    pub fn rms(&self) -> f32 {
+        self.power.sqrt()
+    }
+
+    /// Mean power in dBFS, assuming a full scale of 1.0.
+    #[inline]
+    pub fn power_dbfs(&self) -> f32 {
+        10.0 * self.power.log10()
+    }
+
+    /// Peak power in dBFS, assuming a full scale of 1.0.
+    #[inline]
+    pub fn peak_power_dbfs(&self) -> f32 {
+        10.0 * self.peak_power.log10()
+    }
+
+    /// Ratio between the peak and mean power in dB.
+    #[inline]
+    pub fn peak_to_average_ratio_db(&self) -> f32 {
+        10.0 * (self.peak_power / self.power).log10()
+    }
+}
+
+/// Measures the RMS and peak power over windows of samples.
+///
+/// Unlike [`WithStats`][crate::io::combinators::WithStats], which
+/// accumulates until it's reset, this measures consecutive windows of fixed
+/// length.
+#[derive(Debug)]
+pub struct PowerMeter {
+    window_length: usize,
+    num_samples: usize,
+    sum_power: f64,
+    peak_power: f32,
+    handle: MeasurementHandle<PowerMeasurement>,
+}
+
+impl PowerMeter {
+    pub fn new(window_length: usize) -> Self {
+        assert!(window_length > 0, "window length must be greater than 0");
+
+        Self {
+            window_length,
+            num_samples: 0,
+            sum_power: 0.0,
+            peak_power: 0.0,
+            handle: MeasurementHandle::new(),
+        }
+    }
+
+    #[inline]
+    pub fn window_length(&self) -> usize {
+        self.window_length
+    }
+
+    #[inline]
+    pub fn handle(&self) -> MeasurementHandle<PowerMeasurement> {
+        self.handle.clone()
+    }
+}
+
+impl<S: Power> Inspector<S> for PowerMeter {
+    fn inspect(&mut self, samples: &[S]) {
+        for sample in samples {
+            let power = sample.power();
+            self.sum_power += f64::from(power);
+            self.peak_power = self.peak_power.max(power);
+            self.num_samples += 1;
+
+            if self.num_samples == self.window_length {
+                self.handle.publish(PowerMeasurement {
+                    power: (self.sum_power / self.window_length as f64) as f32,
+                    peak_power: self.peak_power,
+                });
+                self.num_samples = 0;
+                self.sum_power = 0.0;
+                self.peak_power = 0.0;
+            }
+        }
+    }
+}
+
+/// Measurement of an [`OccupiedBandwidthMeter`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OccupiedBandwidth {
+    /// Lower edge in Hz, relative to the center of the stream.
+    pub lower: f32,
+
+    /// Upper edge in Hz, relative to the center of the stream.
+    pub upper: f32,
+
+    /// Total power of the stream in dBFS.
+    pub power: f32,
+}
+
+impl OccupiedBandwidth {
+    #[inline]
+    pub fn bandwidth(&self) -> f32 {
+        self.upper - self.lower
+    }
+
+    #[inline]
+    pub fn center(&self) -> f32 {
+        0.5 * (self.lower + self.upper)
+    }
+}
+
+/// Measures the occupied bandwidth of an IQ stream.
+///
+/// The occupied bandwidth is the band that contains 99% of the power, with
+/// 0.5% below and 0.5% above it. The edges are interpolated within the bins
+/// of the spectrum, but the spectrum's resolution still limits how narrow a
+/// signal can be measured.
+#[derive(Debug)]
+pub struct OccupiedBandwidthMeter {
+    estimator: WelchEstimator,
+    fraction: f32,
+    handle: MeasurementHandle<OccupiedBandwidth>,
+}
+
+impl OccupiedBandwidthMeter {
+    /// Measures over 8 half-overlapping segments of `fft_size` samples.
+    pub fn new(sample_rate: f32, fft_size: usize) -> Self {
+        Self::with_estimator(
+            WelchEstimator::new(sample_rate, fft_size)
+                .with_overlap(0.5)
+                .with_averaging(8),
+        )
+    }
+
+    /// Measures every spectrum that `estimator` produces.
+    pub fn with_estimator(estimator: WelchEstimator) -> Self {
+        Self {
+            estimator,
+            fraction: 0.99,
+            handle: MeasurementHandle::new(),
+        }
+    }
+
+    /// The fraction of the power in the occupied bandwidth, 0.99 by default.
+    pub fn with_fraction(mut self, fraction: f32) -> Self {
+        assert!(
+            fraction > 0.0 && fraction < 1.0,
+            "fraction must be in 0..1: {fraction}"
+        );
+        self.fraction = fraction;
+        self
+    }
+
+    #[inline]
+    pub fn fraction(&self) -> f32 {
+        self.fraction
+    }
+
+    #[inline]
+    pub fn handle(&self) -> MeasurementHandle<OccupiedBandwidth> {
+        self.handle.clone()
+    }
+
+    fn measure(&self, spectrum: &Spectrum) -> OccupiedBandwidth {
+        let power = linear_power(spectrum);
+        let total = power.iter().sum::<f32>();
+        let tail = 0.5 * (1.0 - self.fraction) * total;
+        let bin_width = spectrum.bin_width();
+
+        let (lower_bin, lower_fraction) = crossing(power.iter(), tail);
+        let (upper_bin, upper_fraction) = crossing(power.iter().rev(), tail);
+        let upper_bin = power.len() - 1 - upper_bin;
+
+        OccupiedBandwidth {
+            lower: spectrum.frequency(lower_bin) + (lower_fraction - 0.5) * bin_width,
+            upper: spectrum.frequency(upper_bin) + (0.5 - upper_fraction) * bin_width,
+            power: spectrum.band_power(0..spectrum.len()),
+        }
+    }
+}
+
+impl Inspector<Complex<f32>> for OccupiedBandwidthMeter {
+    fn inspect(&mut self, mut samples: &[Complex<f32>]) {
+        while !samples.is_empty() {
+            if let Some(spectrum) = self.estimator.push(&mut samples) {
+                self.handle.publish(self.measure(&spectrum));
+            }
+        }
+    }
+}
+
+/// Measurement of an [`SnrMeter`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SnrMeasurement {
+    /// Power of the signal, without the noise.
+    pub signal_power: f32,
+
+    /// Power of the noise in the signal's band.
+    pub noise_power: f32,
+}
+
+impl SnrMeasurement {
+    #[inline]
+    pub fn snr(&self) -> f32 {
+        self.signal_power / self.noise_power
+    }
+
+    #[inline]
+    pub fn snr_db(&self) -> f32 {
+        10.0 * self.snr().log10()
+    }
+}
+
+/// Estimates the signal-to-noise ratio of a signal in an IQ stream.
+///
+/// The noise is estimated from the bins outside of the signal's band, so the
+/// rest of the stream should only contain noise, e.g. after a channel filter
+/// that is wider than the signal. The noise in the signal's band is assumed to
+/// be at the same level.
+#[derive(Debug)]
+pub struct SnrMeter {
+    estimator: WelchEstimator,
+    signal_band: Range<f32>,
+    handle: MeasurementHandle<SnrMeasurement>,
+}
+
+impl SnrMeter {
+    /// Measures a signal in `signal_band` (in Hz, relative to the center of
+    /// the stream) over 8 half-overlapping segments of `fft_size` samples.
+    pub fn new(sample_rate: f32, fft_size: usize, signal_band: Range<f32>) -> Self {
+        Self::with_estimator(
+            WelchEstimator::new(sample_rate, fft_size)
+                .with_overlap(0.5)
+                .with_averaging(8),
+            signal_band,
+        )
+    }
+
+    /// Measures every spectrum that `estimator` produces.
+    pub fn with_estimator(estimator: WelchEstimator, signal_band: Range<f32>) -> Self {
+        assert!(
+            signal_band.start < signal_band.end,
+            "empty signal band: {signal_band:?}"
+        );
+        Self {
+            estimator,
+            signal_band,
+            handle: MeasurementHandle::new(),
+        }
+    }
+
+    #[inline]
+    pub fn signal_band(&self) -> Range<f32> {
+        self.signal_band.clone()
+    }
+
+    #[inline]
+    pub fn handle(&self) -> MeasurementHandle<SnrMeasurement> {
+        self.handle.clone()
+    }
+
+    fn measure(&self, spectrum: &Spectrum) -> Option<SnrMeasurement> {
+        // the bins with their center in the signal's band
+        let bin_width = spectrum.bin_width();
+        let center = (spectrum.len() / 2) as f32;
+        let bin = |frequency: f32| {
+            ((frequency / bin_width).ceil() + center).clamp(0.0, spectrum.len() as f32) as usize
+        };
+        let signal_bins = bin(self.signal_band.start)..bin(self.signal_band.end);
+        if signal_bins.is_empty() {
+            return None;
+        }
+
+        let power = linear_power(spectrum);
+        let guarded = signal_bins.start.saturating_sub(GUARD_BINS)
+            ..(signal_bins.end + GUARD_BINS).min(power.len());
+        let noise_bins = power[..guarded.start]
+            .iter()
+            .chain(&power[guarded.end..])
+            .copied()
+            .collect::<Vec<_>>();
+        if noise_bins.is_empty() {
+            return None;
+        }
+
+        // the power in the bins counts the noise once per resolution
+        // bandwidth
+        let bins_per_resolution_bandwidth = spectrum.resolution_bandwidth / bin_width;
+        let noise_per_bin = noise_bins.iter().sum::<f32>() / noise_bins.len() as f32;
+        let noise_power = noise_per_bin * signal_bins.len() as f32 / bins_per_resolution_bandwidth;
+        let total_power = power[signal_bins].iter().sum::<f32>() / bins_per_resolution_bandwidth;
+
+        Some(SnrMeasurement {
+            signal_power: (total_power - noise_power).max(0.0),
+            noise_power,
+        })
+    }
+}
+
+impl Inspector<Complex<f32>> for SnrMeter {
+    fn inspect(&mut self, mut samples: &[Complex<f32>]) {
+        while !samples.is_empty() {
+            if let Some(spectrum) = self.estimator.push(&mut samples)
+                && let Some(measurement) = self.measure(&spectrum)
+            {
+                self.handle.publish(measurement);
+            }
+        }
+    }
+}
+
+/// The linear power of each bin.
+fn linear_power(spectrum: &Spectrum) -> Vec<f32> {
+    spectrum
+        .power
+        .iter()
+        .map(|power| 10f32.powf(power / 10.0))
+        .collect()
+}
+
+/// Index of the bin in which the cumulative power exceeds `tail`, and how far
+/// into that bin this happens.
+fn crossing<'a>(power: impl Iterator<Item = &'a f32>, tail: f32) -> (usize, f32) {
+    let mut cumulative = 0.0;
+    let mut last = 0;
+    for (i, power) in power.enumerate() {
+        if cumulative + power > tail {
+            return (i, (tail - cumulative) / power);
+        }
+        cumulative += power;
+        last = i;
+    }
+    (last, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+    use rand::{
+        RngExt,
+        SeedableRng,
+        rngs::SmallRng,
+    };
+
+    use super::{
+        OccupiedBandwidthMeter,
+        PowerMeter,
+        SnrMeter,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+            combinators::Inspector,
+        },
+        source::Gaussian,
+        spectrum::WelchEstimator,
+    };
+
+    const SAMPLE_RATE: f32 = 16000.0;
+    const FFT_SIZE: usize = 256;
+
+    fn tone(frequency: f32, amplitude: f32, phase: f32, num_samples: usize) -> Vec<Complex<f32>> {
+        (0..num_samples)
+            .map(|i| {
+                Complex::from_polar(amplitude, TAU * frequency * i as f32 / SAMPLE_RATE + phase)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_measures_power_without_changing_the_stream() {
+        let mut input = tone(1000.0, 0.5, 0.0, 3500);
+        input[2100] = Complex::new(0.0, 0.8);
+
+        let meter = PowerMeter::new(1000);
+        let handle = meter.handle();
+        let mut stream = Cursor::new(input.clone()).inspect_with(meter);
+
+        let mut output = vec![];
+        stream
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, input);
+
+        // the last of 3 complete windows has the peak
+        let measurement = handle.take().unwrap();
+        assert!((measurement.power - 0.25).abs() < 1e-3, "{measurement:?}");
+        assert!(
+            (measurement.peak_power - 0.64).abs() < 1e-6,
+            "{measurement:?}"
+        );
+        assert!((measurement.power_dbfs() + 6.02).abs() < 0.02);
+        assert!(handle.take().is_none());
+    }
+
+    #[test]
+    fn it_measures_the_occupied_bandwidth() {
+        // tones every 62.5 Hz from -1 kHz to 1 kHz. they're 4 bins apart, so
+        // the window doesn't make them interfere.
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut input = vec![Complex::default(); 8192];
+        for k in -16..=16 {
+            let tone = tone(k as f32 * 62.5, 0.1, TAU * rng.random::<f32>(), input.len());
+            for (input, tone) in input.iter_mut().zip(tone) {
+                *input += tone;
+            }
+        }
+
+        let mut meter = OccupiedBandwidthMeter::new(SAMPLE_RATE, 1024);
+        let handle = meter.handle();
+        meter.inspect(&input);
+
+        // the window spreads the outermost tones by a bin
+        let measurement = handle.latest().unwrap();
+        assert!(
+            (measurement.bandwidth() - 2016.0).abs() < 5.0,
+            "{measurement:?}"
+        );
+        assert!(measurement.center().abs() < 1.0, "{measurement:?}");
+        // 33 tones with a power of 0.01
+        assert!(
+            (measurement.power - 10.0 * 0.33f32.log10()).abs() < 0.05,
+            "{measurement:?}"
+        );
+    }
+
+    #[test]
+    fn it_estimates_the_snr() {
+        let noise = Gaussian::new(0.16);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let input = tone(100.0, 1.0, 0.0, 8192)
+            .into_iter()
+            .map(|sample| sample + rng.sample::<Complex<f32>, _>(noise))
+            .collect::<Vec<_>>();
+
+        let mut meter = SnrMeter::with_estimator(
+            WelchEstimator::new(SAMPLE_RATE, FFT_SIZE)
+                .with_overlap(0.5)
+                .with_averaging(32),
+            -500.0..500.0,
+        );
+        let handle = meter.handle();
+        // in chunks, like they come from a stream
+        for chunk in input.chunks(1000) {
+            meter.inspect(chunk);
+        }
+
+        // the noise is spread over 16 kHz, and 1 kHz of it is in the band
+        let measurement = handle.latest().unwrap();
+        assert!(
+            (measurement.noise_power - 0.01).abs() < 0.0005,
+            "{measurement:?}"
+        );
+        assert!((measurement.snr_db() - 20.0).abs() < 0.3, "{measurement:?}");
+    }
+}
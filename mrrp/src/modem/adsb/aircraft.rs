@@ -0,0 +1,244 @@
+//! Table of aircraft seen recently
+
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use crate::modem::adsb::{
+    Frame,
+    cpr::{
+        CprFormat,
+        CprPosition,
+        Position,
+        decode_global,
+    },
+    message::{
+        IcaoAddress,
+        Message,
+        MessageKind,
+    },
+};
+
+/// Even and odd positions must be received within this time to be decoded
+/// together.
+const MAX_CPR_PAIR_AGE: Duration = Duration::from_secs(10);
+
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60);
+pub const DEFAULT_MAX_TRACK_LENGTH: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrackPoint {
+    pub position: Position,
+
+    /// Altitude in feet
+    pub altitude: Option<i32>,
+
+    pub time: Instant,
+}
+
+#[derive(Clone, Debug)]
+pub struct Aircraft {
+    pub icao: IcaoAddress,
+    pub callsign: Option<String>,
+
+    /// Altitude in feet
+    pub altitude: Option<i32>,
+
+    /// Past positions, oldest first. The last one is the current position.
+    pub track: VecDeque<TrackPoint>,
+
+    pub last_seen: Instant,
+    pub num_messages: usize,
+
+    even: Option<(CprPosition, Instant)>,
+    odd: Option<(CprPosition, Instant)>,
+}
+
+impl Aircraft {
+    fn new(icao: IcaoAddress, now: Instant) -> Self {
+        Self {
+            icao,
+            callsign: None,
+            altitude: None,
+            track: VecDeque::new(),
+            last_seen: now,
+            num_messages: 0,
+            even: None,
+            odd: None,
+        }
+    }
+
+    #[inline]
+    pub fn position(&self) -> Option<Position> {
+        self.track.back().map(|point| point.position)
+    }
+
+    fn update_position(&mut self, position: CprPosition, now: Instant) -> Option<Position> {
+        match position.format {
+            CprFormat::Even => self.even = Some((position, now)),
+            CprFormat::Odd => self.odd = Some((position, now)),
+        }
+
+        let (even, even_time) = self.even?;
+        let (odd, odd_time) = self.odd?;
+
+        let pair_age = if even_time > odd_time {
+            even_time - odd_time
+        }
+        else {
+            odd_time - even_time
+        };
+        if pair_age > MAX_CPR_PAIR_AGE {
+            return None;
+        }
+
+        decode_global(even, odd, position.format)
+    }
+}
+
+/// Keeps track of aircraft from received frames.
+///
+/// Aircraft that haven't been heard from in a while are removed with
+/// [`expire`][Self::expire].
+#[derive(Clone, Debug)]
+pub struct AircraftTable {
+    aircraft: HashMap<IcaoAddress, Aircraft>,
+    max_age: Duration,
+    max_track_length: usize,
+}
+
+impl Default for AircraftTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_AGE, DEFAULT_MAX_TRACK_LENGTH)
+    }
+}
+
+impl AircraftTable {
+    pub fn new(max_age: Duration, max_track_length: usize) -> Self {
+        Self {
+            aircraft: HashMap::new(),
+            max_age,
+            max_track_length,
+        }
+    }
+
+    /// Decodes a frame and updates the table. Returns the updated aircraft, if
+    /// the frame was a valid extended squitter.
+    pub fn update(&mut self, frame: &Frame, now: Instant) -> Option<&Aircraft> {
+        let message = Message::decode(frame)?;
+        Some(self.update_with_message(message, now))
+    }
+
+    pub fn update_with_message(&mut self, message: Message, now: Instant) -> &Aircraft {
+        let aircraft = self
+            .aircraft
+            .entry(message.icao)
+            .or_insert_with(|| Aircraft::new(message.icao, now));
+        aircraft.last_seen = now;
+        aircraft.num_messages += 1;
+
+        match message.kind {
+            MessageKind::Identification { callsign } => {
+                aircraft.callsign = Some(callsign);
+            }
+            MessageKind::AirbornePosition { altitude, position } => {
+                aircraft.altitude = altitude.or(aircraft.altitude);
+
+                if let Some(position) = aircraft.update_position(position, now) {
+                    // aircraft often repeat the same position, which would just
+                    // clutter the track.
+                    let is_duplicate = aircraft.track.back().is_some_and(|last| {
+                        last.position == position && last.altitude == aircraft.altitude
+                    });
+
+                    if !is_duplicate {
+                        if aircraft.track.len() == self.max_track_length {
+                            aircraft.track.pop_front();
+                        }
+                        aircraft.track.push_back(TrackPoint {
+                            position,
+                            altitude: aircraft.altitude,
+                            time: now,
+                        });
+                    }
+                }
+            }
+            MessageKind::Other { .. } => {}
+        }
+
+        aircraft
+    }
+
+    /// Removes aircraft that haven't been seen for longer than the maximum
+    /// age.
+    pub fn expire(&mut self, now: Instant) {
+        self.aircraft.retain(|_, aircraft| {
+            now.saturating_duration_since(aircraft.last_seen) <= self.max_age
+        });
+    }
+
+    #[inline]
+    pub fn get(&self, icao: IcaoAddress) -> Option<&Aircraft> {
+        self.aircraft.get(&icao)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Aircraft> {
+        self.aircraft.values()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.aircraft.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.aircraft.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{
+        Duration,
+        Instant,
+    };
+
+    use super::AircraftTable;
+    use crate::modem::adsb::message::{
+        IcaoAddress,
+        tests::frame,
+    };
+
+    #[test]
+    fn it_tracks_positions_and_expires_aircraft() {
+        let mut table = AircraftTable::new(Duration::from_secs(60), 16);
+        let now = Instant::now();
+        let icao = IcaoAddress(0x40621d);
+
+        table.update(&frame("8D40621D58C386435CC412692AD6"), now);
+        assert!(table.get(icao).unwrap().position().is_none());
+
+        let even = frame("8D40621D58C382D690C8AC2863A7");
+        table.update(&even, now + Duration::from_secs(1));
+        table.update(&even, now + Duration::from_secs(2));
+
+        let aircraft = table.get(icao).unwrap();
+        assert_eq!(aircraft.num_messages, 3);
+        assert_eq!(aircraft.altitude, Some(38000));
+        assert_eq!(aircraft.track.len(), 1);
+        assert!(aircraft.position().is_some());
+
+        table.expire(now + Duration::from_secs(30));
+        assert_eq!(table.len(), 1);
+        table.expire(now + Duration::from_secs(90));
+        assert!(table.is_empty());
+    }
+}
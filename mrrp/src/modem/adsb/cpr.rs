@@ -0,0 +1,136 @@
+//! Compact Position Reporting (CPR)
+//!
+//! Airborne positions are transmitted alternately in an even and an odd
+//! format. A pair of even and odd positions received within a few seconds
+//! from each other can be decoded to a global position.
+//!
+//! <https://mode-s.org/1090mhz/content/ads-b/3-airborne-position.html>
+
+use std::f64::consts::PI;
+
+/// Number of latitude zones between the equator and a pole.
+const NZ: f64 = 15.0;
+
+/// CPR coordinates are 17 bit.
+const CPR_SCALE: f64 = (1 << 17) as f64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CprFormat {
+    Even,
+    Odd,
+}
+
+/// An encoded position as transmitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CprPosition {
+    pub format: CprFormat,
+    pub latitude: u32,
+    pub longitude: u32,
+}
+
+/// A decoded position in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Decodes a global position from an even and an odd position.
+///
+/// `latest` is the format of the more recently received position, which
+/// determines which of the two is decoded. Returns `None` if the two
+/// positions lie in different longitude zones, in which case another pair is
+/// needed.
+pub fn decode_global(even: CprPosition, odd: CprPosition, latest: CprFormat) -> Option<Position> {
+    let lat_even = f64::from(even.latitude) / CPR_SCALE;
+    let lon_even = f64::from(even.longitude) / CPR_SCALE;
+    let lat_odd = f64::from(odd.latitude) / CPR_SCALE;
+    let lon_odd = f64::from(odd.longitude) / CPR_SCALE;
+
+    let d_lat_even = 360.0 / (4.0 * NZ);
+    let d_lat_odd = 360.0 / (4.0 * NZ - 1.0);
+
+    // latitude zone index
+    let j = (59.0 * lat_even - 60.0 * lat_odd + 0.5).floor();
+
+    let wrap = |latitude: f64| {
+        if latitude >= 270.0 {
+            latitude - 360.0
+        }
+        else {
+            latitude
+        }
+    };
+    let latitude_even = wrap(d_lat_even * (j.rem_euclid(60.0) + lat_even));
+    let latitude_odd = wrap(d_lat_odd * (j.rem_euclid(59.0) + lat_odd));
+
+    let nl = number_of_longitude_zones(latitude_even);
+    if nl != number_of_longitude_zones(latitude_odd) {
+        return None;
+    }
+
+    let m = (lon_even * (nl - 1.0) - lon_odd * nl + 0.5).floor();
+
+    let (latitude, n, lon) = match latest {
+        CprFormat::Even => (latitude_even, nl.max(1.0), lon_even),
+        CprFormat::Odd => (latitude_odd, (nl - 1.0).max(1.0), lon_odd),
+    };
+
+    let mut longitude = 360.0 / n * (m.rem_euclid(n) + lon);
+    if longitude >= 180.0 {
+        longitude -= 360.0;
+    }
+
+    Some(Position {
+        latitude,
+        longitude,
+    })
+}
+
+/// The NL function: number of longitude zones at a latitude.
+fn number_of_longitude_zones(latitude: f64) -> f64 {
+    let latitude = latitude.abs();
+    if latitude == 0.0 {
+        59.0
+    }
+    else if latitude == 87.0 {
+        2.0
+    }
+    else if latitude > 87.0 {
+        1.0
+    }
+    else {
+        let a = 1.0 - (PI / (2.0 * NZ)).cos();
+        let b = (PI / 180.0 * latitude).cos().powi(2);
+        (2.0 * PI / (1.0 - a / b).acos()).floor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::{
+        CprFormat,
+        CprPosition,
+        decode_global,
+    };
+
+    #[test]
+    fn it_decodes_global_position() {
+        let even = CprPosition {
+            format: CprFormat::Even,
+            latitude: 93000,
+            longitude: 51372,
+        };
+        let odd = CprPosition {
+            format: CprFormat::Odd,
+            latitude: 74158,
+            longitude: 50194,
+        };
+
+        let position = decode_global(even, odd, CprFormat::Even).unwrap();
+        assert_abs_diff_eq!(position.latitude, 52.25720, epsilon = 1e-4);
+        assert_abs_diff_eq!(position.longitude, 3.91937, epsilon = 1e-4);
+    }
+}
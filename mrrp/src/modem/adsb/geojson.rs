@@ -0,0 +1,162 @@
+//! GeoJSON export of the aircraft table
+//!
+//! The exported file is a `FeatureCollection` with a `Point` feature for the
+//! current position of each aircraft and a `LineString` feature for its track.
+//! It can be loaded into most map viewers.
+
+use std::{
+    path::PathBuf,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use serde_json::{
+    Value,
+    json,
+};
+
+use crate::modem::adsb::aircraft::{
+    Aircraft,
+    AircraftTable,
+};
+
+/// Converts the aircraft table into a GeoJSON `FeatureCollection`.
+///
+/// Aircraft without a decoded position are skipped. `now` is used to compute
+/// the `seen` property, which is the number of seconds since the aircraft was
+/// last heard.
+pub fn to_geojson(table: &AircraftTable, now: Instant) -> Value {
+    let mut features = vec![];
+
+    for aircraft in table.iter() {
+        let Some(position) = aircraft.position()
+        else {
+            continue;
+        };
+
+        features.push(json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Point",
+                // GeoJSON uses longitude first
+                "coordinates": [position.longitude, position.latitude],
+            },
+            "properties": properties(aircraft, now),
+        }));
+
+        if aircraft.track.len() > 1 {
+            let coordinates = aircraft
+                .track
+                .iter()
+                .map(|point| json!([point.position.longitude, point.position.latitude]))
+                .collect::<Vec<_>>();
+
+            features.push(json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": coordinates,
+                },
+                "properties": {
+                    "icao": aircraft.icao.to_string(),
+                    "track": true,
+                },
+            }));
+        }
+    }
+
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+fn properties(aircraft: &Aircraft, now: Instant) -> Value {
+    json!({
+        "icao": aircraft.icao.to_string(),
+        "callsign": aircraft.callsign,
+        "altitude": aircraft.altitude,
+        "seen": now.saturating_duration_since(aircraft.last_seen).as_secs_f32(),
+        "messages": aircraft.num_messages,
+    })
+}
+
+/// Writes the aircraft table to a GeoJSON file at a fixed interval.
+///
+/// The file is written to a temporary file first and then renamed, so that
+/// viewers polling the file never see a partially written file.
+#[derive(Clone, Debug)]
+pub struct GeoJsonExporter {
+    path: PathBuf,
+    interval: Duration,
+    last_export: Option<Instant>,
+}
+
+impl GeoJsonExporter {
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+            last_export: None,
+        }
+    }
+
+    /// Exports the table if the interval has passed since the last export.
+    /// Returns whether the file was written.
+    pub fn export_if_due(&mut self, table: &AircraftTable, now: Instant) -> std::io::Result<bool> {
+        let due = self
+            .last_export
+            .is_none_or(|last_export| now.saturating_duration_since(last_export) >= self.interval);
+
+        if due {
+            self.export(table, now)?;
+        }
+
+        Ok(due)
+    }
+
+    pub fn export(&mut self, table: &AircraftTable, now: Instant) -> std::io::Result<()> {
+        let json = serde_json::to_vec(&to_geojson(table, now))?;
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, json)?;
+        std::fs::rename(&temporary, &self.path)?;
+
+        self.last_export = Some(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{
+        Duration,
+        Instant,
+    };
+
+    use super::to_geojson;
+    use crate::modem::adsb::{
+        aircraft::AircraftTable,
+        message::tests::frame,
+    };
+
+    #[test]
+    fn it_exports_aircraft_positions() {
+        let mut table = AircraftTable::default();
+        let now = Instant::now();
+        table.update(&frame("8D40621D58C386435CC412692AD6"), now);
+        table.update(&frame("8D40621D58C382D690C8AC2863A7"), now);
+        // no position for this one
+        table.update(&frame("8D4840D6202CC371C32CE0576098"), now);
+
+        let geojson = to_geojson(&table, now + Duration::from_secs(2));
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["properties"]["icao"], "40621D");
+        assert_eq!(features[0]["properties"]["altitude"], 38000);
+        assert_eq!(features[0]["geometry"]["type"], "Point");
+    }
+}
@@ -0,0 +1,210 @@
+//! ADS-B (DF17/DF18 extended squitter) message decoding
+//!
+//! <https://mode-s.org/1090mhz/content/ads-b/1-basics.html>
+
+use std::fmt::{
+    Debug,
+    Display,
+};
+
+use crate::modem::adsb::{
+    Frame,
+    cpr::{
+        CprFormat,
+        CprPosition,
+    },
+};
+
+/// CRC-24 generator polynomial for Mode S parity.
+const CRC24_GENERATOR: u32 = 0x1fff409;
+
+const CALLSIGN_CHARSET: &[u8; 64] =
+    b"#ABCDEFGHIJKLMNOPQRSTUVWXYZ##### ###############0123456789######";
+
+/// 24-bit ICAO aircraft address.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IcaoAddress(pub u32);
+
+impl Display for IcaoAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:06X}", self.0)
+    }
+}
+
+impl Debug for IcaoAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IcaoAddress({self})")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub icao: IcaoAddress,
+    pub kind: MessageKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageKind {
+    /// Type codes 1 to 4
+    Identification { callsign: String },
+
+    /// Type codes 9 to 18 (barometric altitude) and 20 to 22 (GNSS altitude)
+    AirbornePosition {
+        /// Altitude in feet
+        altitude: Option<i32>,
+        position: CprPosition,
+    },
+
+    /// Anything we don't decode yet.
+    Other { type_code: u8 },
+}
+
+impl Message {
+    /// Decodes an extended squitter.
+    ///
+    /// Returns `None` if the frame is not a DF17/DF18 frame or if the parity
+    /// check fails.
+    pub fn decode(frame: &Frame) -> Option<Self> {
+        let Frame::ModeSLong { data } = frame
+        else {
+            return None;
+        };
+
+        let downlink_format = data[0] >> 3;
+        if downlink_format != 17 && downlink_format != 18 {
+            return None;
+        }
+
+        if crc24(&data[..11]) != u32::from_be_bytes([0, data[11], data[12], data[13]]) {
+            return None;
+        }
+
+        let icao = IcaoAddress(u32::from_be_bytes([0, data[1], data[2], data[3]]));
+
+        let mut me = [0u8; 8];
+        me[1..].copy_from_slice(&data[4..11]);
+        let me = u64::from_be_bytes(me);
+        let type_code = (me >> 51) as u8;
+
+        let kind = match type_code {
+            1..=4 => {
+                let callsign = (0..8)
+                    .map(|i| {
+                        let index = (me >> (42 - 6 * i)) & 0x3f;
+                        char::from(CALLSIGN_CHARSET[index as usize])
+                    })
+                    .collect::<String>()
+                    .trim_end()
+                    .to_owned();
+                MessageKind::Identification { callsign }
+            }
+            9..=18 | 20..=22 => {
+                let altitude_code = ((me >> 36) & 0xfff) as u16;
+                let altitude = if type_code < 19 {
+                    decode_altitude(altitude_code)
+                }
+                else {
+                    // GNSS altitude is in meters
+                    (altitude_code != 0).then(|| (f32::from(altitude_code) * 3.28084) as i32)
+                };
+
+                let format = if (me >> 34) & 1 == 0 {
+                    CprFormat::Even
+                }
+                else {
+                    CprFormat::Odd
+                };
+
+                MessageKind::AirbornePosition {
+                    altitude,
+                    position: CprPosition {
+                        format,
+                        latitude: ((me >> 17) & 0x1ffff) as u32,
+                        longitude: (me & 0x1ffff) as u32,
+                    },
+                }
+            }
+            _ => MessageKind::Other { type_code },
+        };
+
+        Some(Self { icao, kind })
+    }
+}
+
+/// Decodes a 12-bit altitude code. Only 25 ft increments (Q-bit set) are
+/// supported.
+fn decode_altitude(code: u16) -> Option<i32> {
+    if code & 0x10 == 0 {
+        // gillham coded or no altitude information
+        return None;
+    }
+
+    let n = ((code & 0xfe0) >> 1) | (code & 0xf);
+    Some(i32::from(n) * 25 - 1000)
+}
+
+pub fn crc24(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for byte in data {
+        crc ^= u32::from(*byte) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= CRC24_GENERATOR;
+            }
+        }
+    }
+    crc & 0xff_ffff
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::{
+        IcaoAddress,
+        Message,
+        MessageKind,
+    };
+    use crate::modem::adsb::{
+        Frame,
+        cpr::CprFormat,
+    };
+
+    pub fn frame(hex: &str) -> Frame {
+        let mut data = [0u8; 14];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..][..2], 16).unwrap();
+        }
+        Frame::ModeSLong { data }
+    }
+
+    #[test]
+    fn it_decodes_identification() {
+        let message = Message::decode(&frame("8D4840D6202CC371C32CE0576098")).unwrap();
+        assert_eq!(message.icao, IcaoAddress(0x4840d6));
+        assert_eq!(
+            message.kind,
+            MessageKind::Identification {
+                callsign: "KLM1023".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn it_decodes_airborne_position() {
+        let message = Message::decode(&frame("8D40621D58C382D690C8AC2863A7")).unwrap();
+        assert_eq!(message.icao, IcaoAddress(0x40621d));
+        let MessageKind::AirbornePosition { altitude, position } = message.kind
+        else {
+            panic!("unexpected message: {message:?}");
+        };
+        assert_eq!(altitude, Some(38000));
+        assert_eq!(position.format, CprFormat::Even);
+        assert_eq!(position.latitude, 93000);
+        assert_eq!(position.longitude, 51372);
+    }
+
+    #[test]
+    fn it_rejects_corrupted_frames() {
+        assert!(Message::decode(&frame("8D4840D6202CC371C32CE0576099")).is_none());
+    }
+}
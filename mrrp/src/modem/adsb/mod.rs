@@ -0,0 +1,925 @@
+//! 1090 MHz Mode-S demodulator
+//!
+//! <https://www.radartutorial.eu/13.ssr/sr24.en.html>
+//! <https://www.idc-online.com/technical_references/pdfs/electronic_engineering/Mode_S_Reply_Encoding.pdf>
+#![allow(dead_code)]
+
+pub mod aircraft;
+pub mod cpr;
+pub mod geojson;
+pub mod message;
+
+use std::{
+    borrow::Borrow,
+    f32::consts::TAU,
+    fmt::Debug,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+    },
+    time::Duration,
+};
+
+use futures_util::Stream;
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+
+use crate::{
+    io::{
+        AsyncReadSamples,
+        ReadBuf,
+    },
+    modem::{
+        adsb::message::IcaoAddress,
+        dedup::Dedup,
+    },
+};
+
+/// Preamble: 8 µs / 16 samples
+const PREAMBLE_SAMPLES: usize = 16;
+
+/// The preamble's pulses (samples 0, 2, 7 and 9) with the mean removed.
+const PREAMBLE_TEMPLATE: [f32; PREAMBLE_SAMPLES] = [
+    0.75, -0.25, 0.75, -0.25, -0.25, -0.25, -0.25, 0.75, -0.25, 0.75, -0.25, -0.25, -0.25, -0.25,
+    -0.25, -0.25,
+];
+
+/// Euclidean norm of [`PREAMBLE_TEMPLATE`], i.e. √3.
+const PREAMBLE_TEMPLATE_NORM: f32 = 1.732_050_8;
+
+/// Default threshold for [`PreambleDetector::Correlation`].
+///
+/// On noise this triggers less often than [`PreambleDetector::Comparison`],
+/// and it still finds most preambles at 6 dB SNR.
+pub const DEFAULT_CORRELATION_THRESHOLD: f32 = 0.7;
+
+/// Sample rate: 2 samples/µs
+pub const SAMPLE_RATE: u32 = 2_000_000;
+
+/// Mode S downlink frequency: 1090 MHz
+pub const DOWNLINK_FREQUENCY: u32 = 1_090_000_000;
+
+/// Mode S uplink frequency: 1030 MHz
+pub const UPLINK_FREQUENCY: u32 = 1_030_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Frame {
+    ModeAc { data: [u8; 2] },
+    ModeSShort { data: [u8; 7] },
+    ModeSLong { data: [u8; 14] },
+}
+
+impl AsRef<[u8]> for Frame {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Frame::ModeAc { data } => &data[..],
+            Frame::ModeSShort { data } => &data[..],
+            Frame::ModeSLong { data } => &data[..],
+        }
+    }
+}
+
+/// A frame and how well it was received.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DemodulatedFrame {
+    pub frame: Frame,
+
+    /// Estimated signal-to-noise ratio in dB.
+    ///
+    /// Every bit has a pulse in one half and a gap in the other, so this is
+    /// estimated from the mean power of the pulses and the gaps over the whole
+    /// message.
+    pub snr: f32,
+
+    /// When the frame arrived, and at which frequency. Only set by a
+    /// [`DemodulateStream`] with
+    /// [`with_timing`][DemodulateStream::with_timing].
+    pub timing: Option<FrameTiming>,
+}
+
+/// Time of arrival and frequency of a frame.
+///
+/// mrrp doesn't locate aircraft from these, but with timestamps from several
+/// receivers they can be used for multilateration (MLAT) experiments.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameTiming {
+    /// Index of the sample at which the preamble started, counted from the
+    /// start of the stream.
+    ///
+    /// This is interpolated to a fraction of a sample from where the energy of
+    /// the preamble's pulses is centered.
+    pub sample_index: f64,
+
+    /// Frequency offset of the frame from the tuned frequency, in Hz.
+    ///
+    /// This includes the Doppler shift as well as the errors of the
+    /// transponder's and the receiver's oscillators.
+    pub frequency_offset: f32,
+}
+
+impl Borrow<Frame> for DemodulatedFrame {
+    fn borrow(&self) -> &Frame {
+        &self.frame
+    }
+}
+
+impl Frame {
+    /// Address of the aircraft that sent an extended squitter (DF17/DF18).
+    ///
+    /// Other downlink formats overlay the address with the parity, so it
+    /// can't be read directly. This doesn't check the parity.
+    pub fn extended_squitter_address(&self) -> Option<IcaoAddress> {
+        let Frame::ModeSLong { data } = self
+        else {
+            return None;
+        };
+
+        let downlink_format = data[0] >> 3;
+        (downlink_format == 17 || downlink_format == 18)
+            .then(|| IcaoAddress(u32::from_be_bytes([0, data[1], data[2], data[3]])))
+    }
+}
+
+/// Default window in which identical frames are dropped.
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_millis(500);
+
+/// Settings for [`deduplicate`].
+#[derive(Clone, Copy, Debug)]
+pub struct DedupConfig {
+    /// Identical frames within this window are dropped. Re-triggering on the
+    /// same squitter produces duplicates within microseconds, but aircraft
+    /// also repeat some squitters unchanged.
+    pub window: Duration,
+
+    /// Pass on at most one extended squitter per aircraft in this interval.
+    /// [`Duration::ZERO`] disables rate limiting.
+    pub rate_limit: Duration,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_DEDUP_WINDOW,
+            rate_limit: Duration::ZERO,
+        }
+    }
+}
+
+type FrameKey<F, E> = fn(&Result<F, E>) -> Option<Frame>;
+type AircraftKey<F, E> = fn(&Result<F, E>) -> Option<IcaoAddress>;
+type Deduplicated<S, F, E> = Dedup<Dedup<S, FrameKey<F, E>, Frame>, AircraftKey<F, E>, IcaoAddress>;
+
+/// Drops duplicate frames and rate-limits aircraft.
+///
+/// This works on [`Frame`]s and [`DemodulatedFrame`]s. Frames are compared
+/// without their SNR. Errors are passed through.
+pub fn deduplicate<S, F, E>(stream: S, config: DedupConfig) -> Deduplicated<S, F, E>
+where
+    S: Stream<Item = Result<F, E>>,
+    F: Borrow<Frame>,
+{
+    let frames = Dedup::new(
+        stream,
+        config.window,
+        (|item: &Result<F, E>| item.as_ref().ok().map(|frame| *frame.borrow())) as FrameKey<F, E>,
+    );
+    Dedup::new(
+        frames,
+        config.rate_limit,
+        (|item: &Result<F, E>| item.as_ref().ok()?.borrow().extended_squitter_address())
+            as AircraftKey<F, E>,
+    )
+}
+
+enum DemodFail {
+    NotEnoughSamples,
+    Invalid,
+}
+
+#[derive(Debug)]
+pub struct Demodulator {
+    quality: Quality,
+    preamble_detector: PreambleDetector,
+    num_errors: usize,
+    max_errors: usize,
+    pulse_power: f32,
+    gap_power: f32,
+
+    /// Where the preamble of the frame that was just read starts.
+    preamble_start: usize,
+}
+
+impl Default for Demodulator {
+    fn default() -> Self {
+        Self::new(Default::default(), 5)
+    }
+}
+
+impl Demodulator {
+    pub fn new(quality: Quality, max_errors: usize) -> Self {
+        Self {
+            quality,
+            preamble_detector: Default::default(),
+            num_errors: 0,
+            max_errors,
+            pulse_power: 0.0,
+            gap_power: 0.0,
+            preamble_start: 0,
+        }
+    }
+
+    pub fn with_preamble_detector(mut self, preamble_detector: PreambleDetector) -> Self {
+        self.preamble_detector = preamble_detector;
+        self
+    }
+
+    pub fn next(&mut self, cursor: &mut Cursor<f32>) -> Option<DemodulatedFrame> {
+        while self.preamble_detector.find(cursor) {
+            //tracing::debug!(?cursor.position, "found preamble");
+
+            let preamble_start = cursor.position - PREAMBLE_SAMPLES;
+            let mut frame_cursor = *cursor;
+
+            match self.read_frame(&mut frame_cursor) {
+                Ok(frame) => {
+                    // found a frame!
+                    // set main cursor to the position of the frame cursor
+                    cursor.position = frame_cursor.position;
+                    self.preamble_start = preamble_start;
+                    return Some(DemodulatedFrame {
+                        frame,
+                        snr: self.snr(),
+                        timing: None,
+                    });
+                }
+                Err(DemodFail::NotEnoughSamples) => {
+                    // rewind to the start of the preamble, so it's found again once there
+                    // are more samples
+                    cursor.position = preamble_start;
+                    return None;
+                }
+                Err(DemodFail::Invalid) => {
+                    // find next preamble starting from end of previous preamble
+                    // so the main cursor stays unchanged and we do nothing here
+                }
+            }
+        }
+
+        None
+    }
+
+    /// SNR of the frame that was just read.
+    fn snr(&self) -> f32 {
+        // the pulses contain the noise too
+        10.0 * ((self.pulse_power - self.gap_power).max(0.0) / self.gap_power).log10()
+    }
+
+    fn read_frame(&mut self, cursor: &mut Cursor<f32>) -> Result<Frame, DemodFail> {
+        self.num_errors = 0;
+        self.pulse_power = 0.0;
+        self.gap_power = 0.0;
+
+        let first_byte = self.read_byte(cursor)?;
+
+        if first_byte & 0x80 == 0 {
+            Ok(Frame::ModeSShort {
+                data: self.read_frame_rest(first_byte, cursor)?,
+            })
+        }
+        else {
+            Ok(Frame::ModeSLong {
+                data: self.read_frame_rest(first_byte, cursor)?,
+            })
+        }
+    }
+
+    fn read_frame_rest<const N: usize>(
+        &mut self,
+        first_byte: u8,
+        cursor: &mut Cursor<f32>,
+    ) -> Result<[u8; N], DemodFail> {
+        let mut data = [0u8; N];
+        data[0] = first_byte;
+        for i in 1..N {
+            data[i] = self.read_byte(cursor)?;
+        }
+        Ok(data)
+    }
+
+    fn read_bit(&mut self, cursor: &mut Cursor<f32>) -> Result<bool, bool> {
+        // these should exist, since we read a preamble first
+        let a = cursor.samples[cursor.position - 2];
+        let b = cursor.samples[cursor.position - 1];
+
+        let c = cursor.samples[cursor.position];
+        let d = cursor.samples[cursor.position + 1];
+
+        cursor.advance(2);
+
+        let bit_p = a > b;
+        let bit = c > d;
+
+        // only ratios are needed, so the sums don't have to be divided
+        self.pulse_power += c.max(d);
+        self.gap_power += c.min(d);
+
+        // todo: this could be implemented with a few bitmask really
+
+        match self.quality {
+            Quality::NoChecks => Ok(bit),
+            Quality::HalfBit => {
+                if bit && bit_p && b > c {
+                    Err(bit)
+                }
+                else if !bit && !bit_p && b < c {
+                    Err(bit)
+                }
+                else {
+                    Ok(bit)
+                }
+            }
+            Quality::OneBit => {
+                if bit && bit_p && c > b {
+                    Ok(true)
+                }
+                else if bit && !bit_p && d < b {
+                    Ok(true)
+                }
+                else if !bit && bit_p && d > b {
+                    Ok(false)
+                }
+                else if !bit && !bit_p && c < b {
+                    Ok(false)
+                }
+                else {
+                    Err(bit)
+                }
+            }
+            Quality::TwoBits => {
+                if bit && bit_p && c > b && d < a {
+                    Ok(true)
+                }
+                else if bit && !bit_p && c > a && d < b {
+                    Ok(true)
+                }
+                else if !bit && bit_p && c < a && d > b {
+                    Ok(false)
+                }
+                else if !bit && !bit_p && c < b && d > a {
+                    Ok(false)
+                }
+                else {
+                    Err(bit)
+                }
+            }
+        }
+    }
+
+    fn read_byte(&mut self, cursor: &mut Cursor<f32>) -> Result<u8, DemodFail> {
+        let mut byte = 0;
+
+        if cursor.remaining().len() < 2 * 8 {
+            Err(DemodFail::NotEnoughSamples)
+        }
+        else {
+            for _ in 0..8 {
+                byte <<= 1;
+                let bit = self.read_bit(cursor).or_else(|bit| {
+                    self.num_errors += 1;
+                    if self.num_errors <= self.max_errors {
+                        Ok(bit)
+                    }
+                    else {
+                        // rtl_adsb.c does change the previous bits, but I don't get how that works.
+                        // Wouldn't that break the next bit reads?
+                        //
+                        // <https://github.com/rtlsdrblog/rtl-sdr-blog/blob/240bd0e1e6d9f64361b6949047468958cd08aa31/src/rtl_adsb.c#L300>
+                        Err(DemodFail::Invalid)
+                    }
+                })?;
+
+                if bit {
+                    byte |= 1;
+                }
+            }
+
+            Ok(byte)
+        }
+    }
+}
+
+/// How the demodulator finds the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PreambleDetector {
+    /// Checks that every pulse is higher than the gaps around it.
+    ///
+    /// This is what `rtl_adsb` does. It's cheap, but a single noisy sample
+    /// makes it miss a preamble, so it fails at low SNR.
+    Comparison,
+
+    /// Correlates the samples with the pulse pattern of the preamble.
+    ///
+    /// The correlation is normalized, so it doesn't depend on the signal's
+    /// amplitude or the noise floor, and ranges from -1 to 1. A preamble is
+    /// detected where it's at least `threshold`.
+    Correlation { threshold: f32 },
+}
+
+impl Default for PreambleDetector {
+    fn default() -> Self {
+        Self::Correlation {
+            threshold: DEFAULT_CORRELATION_THRESHOLD,
+        }
+    }
+}
+
+impl PreambleDetector {
+    /// Advances the cursor to the end of the next preamble.
+    ///
+    /// Returns `false` if there is no preamble in the remaining samples, with
+    /// the cursor at the first sample that might still start one.
+    fn find(&self, cursor: &mut Cursor<f32>) -> bool {
+        loop {
+            let remaining = cursor.remaining();
+            if remaining.len() < PREAMBLE_SAMPLES {
+                break false;
+            }
+
+            match *self {
+                Self::Comparison => {
+                    if is_preamble(remaining) {
+                        cursor.advance(PREAMBLE_SAMPLES);
+                        break true;
+                    }
+                }
+                Self::Correlation { threshold } => {
+                    let correlation = correlate_preamble(remaining);
+                    if correlation >= threshold {
+                        // pulses that straddle two samples cross the threshold
+                        // twice, and the second might be the better fit
+                        if remaining.len() > PREAMBLE_SAMPLES
+                            && correlate_preamble(&remaining[1..]) > correlation
+                        {
+                            cursor.advance(1);
+                        }
+                        cursor.advance(PREAMBLE_SAMPLES);
+                        break true;
+                    }
+                }
+            }
+
+            cursor.advance(1);
+        }
+    }
+}
+
+/// Normalized correlation of the first [`PREAMBLE_SAMPLES`] samples with the
+/// preamble.
+fn correlate_preamble(samples: &[f32]) -> f32 {
+    let samples = &samples[..PREAMBLE_SAMPLES];
+    let mean = samples.iter().sum::<f32>() / PREAMBLE_SAMPLES as f32;
+
+    let mut dot = 0.0;
+    let mut energy = 0.0;
+    for (sample, template) in samples.iter().zip(&PREAMBLE_TEMPLATE) {
+        let sample = sample - mean;
+        dot += sample * template;
+        energy += sample * sample;
+    }
+
+    if energy > 0.0 {
+        dot / (energy.sqrt() * PREAMBLE_TEMPLATE_NORM)
+    }
+    else {
+        0.0
+    }
+}
+
+/// Refines the start of a preamble to a fraction of a sample.
+///
+/// The pulses are only a sample long, so a signal that arrives between two
+/// samples spreads each pulse over both of them. The centroid of the pulses'
+/// amplitudes moves with it. Returns `start` unchanged if the samples around
+/// the preamble aren't available.
+fn refine_preamble_start(samples: &[f32], start: usize) -> f64 {
+    // the pulses and one sample on each side. the centroid of a preamble that
+    // starts right at `start` is in the middle.
+    let Some(window) = start
+        .checked_sub(1)
+        .and_then(|first| samples.get(first..start + 11))
+    else {
+        return start as f64;
+    };
+    let amplitude = |power: f32| power.max(0.0).sqrt();
+
+    // samples 4 and 5 are always in a gap
+    let noise = 0.5 * (amplitude(window[5]) + amplitude(window[6]));
+
+    let mut weight = 0.0;
+    let mut moment = 0.0;
+    for (i, power) in window.iter().enumerate() {
+        let pulse = (amplitude(*power) - noise).max(0.0);
+        weight += pulse;
+        moment += pulse * (i as f32 - 1.0);
+    }
+
+    if weight > 0.0 {
+        start as f64 + f64::from(moment / weight - 4.5)
+    }
+    else {
+        start as f64
+    }
+}
+
+/// Frequency offset of a frame in Hz, from the phase advance between
+/// neighbouring samples.
+///
+/// The products are weighted by the amplitude of the samples, so the gaps
+/// hardly contribute.
+fn frequency_offset(samples: &[Complex<f32>]) -> f32 {
+    let rotation = samples
+        .windows(2)
+        .map(|pair| pair[1] * pair[0].conj())
+        .sum::<Complex<f32>>();
+    rotation.arg() * SAMPLE_RATE as f32 / TAU
+}
+
+fn is_preamble(samples: &[f32]) -> bool {
+    let mut low = f32::MIN;
+    let mut high = f32::MAX;
+
+    for i in 0..PREAMBLE_SAMPLES {
+        match i {
+            0 | 2 | 7 | 9 => {
+                high = samples[i];
+            }
+            _ => {
+                low = samples[i];
+            }
+        }
+
+        if high <= low {
+            return false;
+        }
+    }
+
+    true
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quality {
+    NoChecks,
+    HalfBit,
+    #[default]
+    OneBit,
+    TwoBits,
+}
+
+pin_project! {
+    #[derive(Debug)]
+    pub struct DemodulateStream<T> {
+        #[pin]
+        stream: T,
+        demodulator: Demodulator,
+        samples: Vec<Complex<f32>>,
+        buffer: Vec<f32>,
+        read_pos: usize,
+        write_pos: usize,
+        num_samples: usize,
+        // index in the stream of the first sample in the buffer
+        buffer_start: u64,
+        timing: bool,
+    }
+}
+
+impl<T: AsyncReadSamples<Complex<f32>>> DemodulateStream<T> {
+    pub fn new(stream: T, demodulator: Demodulator, buffer_size: usize) -> Self {
+        Self {
+            stream,
+            demodulator,
+            samples: vec![Complex::default(); buffer_size],
+            buffer: vec![0.0; buffer_size],
+            read_pos: 0,
+            write_pos: 0,
+            num_samples: 0,
+            buffer_start: 0,
+            timing: false,
+        }
+    }
+
+    /// Measures the time of arrival and the frequency of every frame. See
+    /// [`FrameTiming`].
+    pub fn with_timing(mut self) -> Self {
+        self.timing = true;
+        self
+    }
+}
+
+impl<T: AsyncReadSamples<Complex<f32>>> Stream for DemodulateStream<T> {
+    type Item = Result<DemodulatedFrame, T::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let this = self.as_mut().project();
+
+            /*tracing::debug!(
+                read_pos = *this.read_pos,
+                write_pos = *this.write_pos,
+                num_samples = *this.num_samples
+            );*/
+
+            if *this.read_pos < *this.num_samples {
+                let mut cursor = Cursor {
+                    samples: &this.buffer[..*this.num_samples],
+                    position: *this.read_pos,
+                };
+
+                if let Some(mut frame) = this.demodulator.next(&mut cursor) {
+                    if *this.timing {
+                        let start = this.demodulator.preamble_start;
+                        frame.timing = Some(FrameTiming {
+                            sample_index: *this.buffer_start as f64
+                                + refine_preamble_start(cursor.samples, start),
+                            frequency_offset: frequency_offset(
+                                &this.samples[start..cursor.position],
+                            ),
+                        });
+                    }
+                    *this.read_pos = cursor.position;
+                    return Poll::Ready(Some(Ok(frame)));
+                }
+                else {
+                    // keep a sample before a preamble that is cut off, for the timing
+                    let position = cursor.position.saturating_sub(1);
+                    this.buffer.copy_within(position..*this.num_samples, 0);
+                    this.samples.copy_within(position..*this.num_samples, 0);
+                    *this.buffer_start += position as u64;
+                    *this.write_pos = *this.num_samples - position;
+                    *this.read_pos = 0;
+                    *this.num_samples = 0;
+                }
+            }
+            else {
+                let mut read_buf = ReadBuf::new(&mut this.samples[*this.write_pos..]);
+                match this.stream.poll_read_samples(cx, &mut read_buf) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Err(error)) => return Poll::Ready(Some(Err(error))),
+                    Poll::Ready(Ok(())) => {
+                        let num_read = read_buf.filled().len();
+                        if num_read == 0 {
+                            return Poll::Ready(None);
+                        }
+
+                        let end = *this.write_pos + num_read;
+                        for (power, sample) in this.buffer[*this.write_pos..end]
+                            .iter_mut()
+                            .zip(&this.samples[*this.write_pos..end])
+                        {
+                            *power = sample.norm_sqr();
+                        }
+
+                        *this.num_samples = end;
+                        *this.read_pos = 0;
+                        *this.write_pos = 0;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// todo: use SampleBuf instead, buf we need examine past samples sometimes.
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cursor<'a, S> {
+    pub samples: &'a [S],
+    pub position: usize,
+}
+
+impl<'a, S> Cursor<'a, S> {
+    #[inline(always)]
+    pub fn advance(&mut self, amount: usize) {
+        self.position += amount;
+    }
+
+    #[inline(always)]
+    pub fn remaining(&self) -> &[S] {
+        &self.samples[self.position..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        f32::consts::TAU,
+        time::Duration,
+    };
+
+    use futures_util::{
+        FutureExt,
+        StreamExt,
+        stream,
+    };
+    use num_complex::Complex;
+
+    use super::{
+        DEFAULT_CORRELATION_THRESHOLD,
+        DedupConfig,
+        DemodulateStream,
+        Demodulator,
+        Frame,
+        PreambleDetector,
+        Quality,
+        correlate_preamble,
+        deduplicate,
+        is_preamble,
+    };
+    use crate::{
+        io,
+        modem::adsb::{
+            Cursor,
+            SAMPLE_RATE,
+            message::tests::frame,
+        },
+    };
+
+    fn modulate(data: &[u8], mut sample: impl FnMut(bool) -> f32) -> Vec<f32> {
+        let mut samples = vec![];
+
+        // 0, 2, 7, 9 are high
+        let mut preamble: u16 = 0b1010_0001_0100_0000;
+        for _ in 0..16 {
+            if preamble & 0x8000 == 0 {
+                samples.push(sample(false));
+            }
+            else {
+                samples.push(sample(true));
+            }
+            preamble <<= 1;
+        }
+
+        for mut byte in data.iter().copied() {
+            for _ in 0..8 {
+                if byte & 0x80 == 0 {
+                    // bit=0 raising edge
+                    samples.push(sample(false));
+                    samples.push(sample(true));
+                }
+                else {
+                    // bit=1 falling edge
+                    samples.push(sample(true));
+                    samples.push(sample(false));
+                }
+                byte <<= 1;
+            }
+        }
+
+        samples
+    }
+
+    fn signal(signal: bool) -> f32 {
+        if signal { 1.0 } else { 0.0 }
+    }
+
+    #[test]
+    fn it_demodulates_a_frame() {
+        let input = b"\x8d\x40\x74\xb5\x23\x15\xa6\x76\xdd\x13\xa0\x66\x29\x67";
+
+        let samples = modulate(input, signal);
+
+        let mut demodulator = Demodulator::new(Quality::NoChecks, 0);
+        let mut cursor = Cursor {
+            samples: &samples[..],
+            position: 0,
+        };
+
+        let frame = demodulator
+            .next(&mut cursor)
+            .expect("no frame demodulated")
+            .frame;
+        match frame {
+            Frame::ModeSLong { data } => {
+                assert_eq!(&data, input);
+            }
+            _ => panic!("unexpected frame: {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn it_detects_a_noisy_preamble() {
+        let input = b"\x8d\x40\x74\xb5\x23\x15\xa6\x76\xdd\x13\xa0\x66\x29\x67";
+
+        let mut samples = modulate(input, signal);
+        // a noise spike in the gap after the second pulse
+        samples[3] = 1.2;
+
+        assert!(!is_preamble(&samples));
+        assert!(correlate_preamble(&samples) >= DEFAULT_CORRELATION_THRESHOLD);
+
+        let mut demodulator = Demodulator::new(Quality::NoChecks, 0).with_preamble_detector(
+            PreambleDetector::Correlation {
+                threshold: DEFAULT_CORRELATION_THRESHOLD,
+            },
+        );
+        let mut cursor = Cursor {
+            samples: &samples[..],
+            position: 0,
+        };
+        let frame = demodulator
+            .next(&mut cursor)
+            .expect("no frame demodulated")
+            .frame;
+        assert_eq!(frame, Frame::ModeSLong { data: *input });
+    }
+
+    #[test]
+    fn it_estimates_the_snr() {
+        let input = b"\x8d\x40\x74\xb5\x23\x15\xa6\x76\xdd\x13\xa0\x66\x29\x67";
+
+        // signal power 1.0 on top of a noise floor of 0.1
+        let samples = modulate(input, |high| if high { 1.1 } else { 0.1 });
+
+        let mut demodulator = Demodulator::default();
+        let mut cursor = Cursor {
+            samples: &samples[..],
+            position: 0,
+        };
+        let frame = demodulator.next(&mut cursor).expect("no frame demodulated");
+        assert_eq!(frame.frame, Frame::ModeSLong { data: *input });
+        assert!((frame.snr - 10.0).abs() < 1e-3, "snr: {}", frame.snr);
+    }
+
+    #[test]
+    fn it_measures_the_time_of_arrival_and_frequency() {
+        let input = b"\x8d\x40\x74\xb5\x23\x15\xa6\x76\xdd\x13\xa0\x66\x29\x67";
+        let delay = 0.3;
+        let frequency = 25e3;
+
+        // the frame arrives 0.3 samples after sample 100, so every pulse is
+        // spread over two samples
+        let mut amplitudes = vec![0.0; 100];
+        let mut previous = 0.0;
+        for amplitude in modulate(input, signal).into_iter().chain([0.0; 16]) {
+            amplitudes.push((1.0 - delay) * amplitude + delay * previous);
+            previous = amplitude;
+        }
+        let samples = amplitudes
+            .iter()
+            .enumerate()
+            .map(|(i, amplitude)| {
+                let phase = TAU * frequency * i as f32 / SAMPLE_RATE as f32;
+                Complex::from_polar(*amplitude, phase)
+            })
+            .collect::<Vec<_>>();
+
+        // the frame doesn't fit into the first buffer
+        let mut stream = DemodulateStream::new(
+            io::Cursor::new(samples),
+            Demodulator::new(Quality::NoChecks, 0),
+            256,
+        )
+        .with_timing();
+        let frame = stream
+            .next()
+            .now_or_never()
+            .expect("stream pending")
+            .expect("no frame demodulated")
+            .unwrap();
+        assert_eq!(frame.frame, Frame::ModeSLong { data: *input });
+
+        let timing = frame.timing.expect("no timing");
+        assert!(
+            (timing.sample_index - 100.3).abs() < 0.01,
+            "sample index: {}",
+            timing.sample_index
+        );
+        assert!(
+            (timing.frequency_offset - frequency).abs() < 10.0,
+            "frequency offset: {}",
+            timing.frequency_offset
+        );
+    }
+
+    #[test]
+    fn it_drops_duplicates_and_rate_limits_aircraft() {
+        let identification = frame("8D4840D6202CC371C32CE0576098");
+        let position = frame("8D4840D658C382D690C8AC2863A7");
+        let other_aircraft = frame("8D40621D58C382D690C8AC2863A7");
+
+        let frames = [identification, identification, position, other_aircraft].map(Ok::<_, ()>);
+        let stream = deduplicate(
+            stream::iter(frames),
+            DedupConfig {
+                window: Duration::from_secs(1),
+                rate_limit: Duration::from_secs(1),
+            },
+        );
+
+        let frames = stream
+            .collect::<Vec<_>>()
+            .now_or_never()
+            .expect("test stream pending");
+        assert_eq!(frames, [Ok(identification), Ok(other_aircraft)]);
+    }
+}
@@ -0,0 +1,372 @@
+//! Audio frequency-shift keying (AFSK)
+//!
+//! Bits are transmitted as one of two audio tones: mark (`true`) and space
+//! (`false`). This is the physical layer used by packet radio.
+//!
+//! The demodulator mixes the tones down to baseband, low-pass filters them
+//! with a bandwidth that depends on the shift and baud rate, and then uses an
+//! FM discriminator to decide between mark and space. Static tuning errors,
+//! which are common on HF, can be compensated with
+//! [`set_tuning_offset`][AfskDemodulator::set_tuning_offset]. Slow drift is
+//! tracked by the slicer.
+
+use std::f32::consts::TAU;
+
+use ::biquad::DirectForm2Transposed;
+use num_complex::Complex;
+
+use crate::{
+    buf::SampleBufMut,
+    filter::biquad,
+    io::combinators::Scanner,
+    modem::fm,
+};
+
+/// Tones and baud rate of an AFSK modem.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AfskConfig {
+    pub baud_rate: f32,
+    pub mark: f32,
+    pub space: f32,
+}
+
+impl AfskConfig {
+    /// Bell 202, as used for 1200 baud VHF packet radio.
+    pub const BELL_202: Self = Self {
+        baud_rate: 1200.0,
+        mark: 1200.0,
+        space: 2200.0,
+    };
+
+    /// 300 baud with 200 Hz shift, as used for HF packet radio.
+    pub const HF_300: Self = Self {
+        baud_rate: 300.0,
+        mark: 1600.0,
+        space: 1800.0,
+    };
+
+    #[inline]
+    pub fn center_frequency(&self) -> f32 {
+        0.5 * (self.mark + self.space)
+    }
+
+    /// Difference between space and mark frequency. This is negative if the
+    /// mark tone is above the space tone.
+    #[inline]
+    pub fn shift(&self) -> f32 {
+        self.space - self.mark
+    }
+
+    #[inline]
+    pub fn samples_per_symbol(&self, sample_rate: f32) -> f32 {
+        sample_rate / self.baud_rate
+    }
+}
+
+impl Default for AfskConfig {
+    fn default() -> Self {
+        Self::BELL_202
+    }
+}
+
+/// Phase-continuous AFSK modulator producing complex audio.
+#[derive(Clone, Copy, Debug)]
+pub struct AfskModulator {
+    config: AfskConfig,
+    sample_rate: f32,
+    phase: f32,
+    symbol_clock: f32,
+}
+
+impl AfskModulator {
+    pub fn new(config: AfskConfig, sample_rate: f32) -> Self {
+        Self {
+            config,
+            sample_rate,
+            phase: 0.0,
+            symbol_clock: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn config(&self) -> &AfskConfig {
+        &self.config
+    }
+
+    /// Writes the samples for one bit into `output`.
+    ///
+    /// The number of samples per bit doesn't need to be an integer. The
+    /// fractional part is carried over to the next bit.
+    pub fn modulate<B>(&mut self, bit: bool, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        let frequency = if bit {
+            self.config.mark
+        }
+        else {
+            self.config.space
+        };
+        let phase_increment = TAU * frequency / self.sample_rate;
+
+        self.symbol_clock += self.config.samples_per_symbol(self.sample_rate);
+        while self.symbol_clock >= 1.0 {
+            output.put_sample(Complex::from_polar(1.0, self.phase));
+            self.phase = (self.phase + phase_increment) % TAU;
+            self.symbol_clock -= 1.0;
+        }
+    }
+
+    pub fn modulate_bits<B>(&mut self, bits: impl IntoIterator<Item = bool>, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        for bit in bits {
+            self.modulate(bit, output);
+        }
+    }
+}
+
+/// AFSK demodulator.
+///
+/// Scans complex audio and outputs a bit at the center of each symbol.
+#[derive(Clone, Debug)]
+pub struct AfskDemodulator {
+    config: AfskConfig,
+    sample_rate: f32,
+    tuning_offset: f32,
+    oscillator: Complex<f32>,
+    oscillator_step: Complex<f32>,
+    channel_filter: [DirectForm2Transposed<f32, Complex<f32>>; 2],
+    discriminator: fm::DifferentiateAndAccessPhase,
+    symbol_filter: DirectForm2Transposed<f32, f32>,
+    slicer: Slicer,
+    symbol_step: f32,
+    symbol_clock: f32,
+    last_decision: bool,
+}
+
+impl AfskDemodulator {
+    pub fn new(config: AfskConfig, sample_rate: f32) -> Self {
+        // carson's rule, with the baseband centered between the tones
+        let bandwidth = 0.5 * config.shift().abs() + config.baud_rate;
+
+        let mut demodulator = Self {
+            config,
+            sample_rate,
+            tuning_offset: 0.0,
+            oscillator: Complex::ONE,
+            oscillator_step: Complex::ONE,
+            channel_filter: [
+                biquad::lowpass(sample_rate, bandwidth),
+                biquad::lowpass(sample_rate, bandwidth),
+            ],
+            discriminator: fm::DifferentiateAndAccessPhase::new(sample_rate, 0.5 * config.shift()),
+            symbol_filter: biquad::lowpass(sample_rate, config.baud_rate),
+            slicer: Slicer::new(),
+            symbol_step: config.baud_rate / sample_rate,
+            symbol_clock: 0.0,
+            last_decision: false,
+        };
+        demodulator.update_oscillator();
+        demodulator
+    }
+
+    #[inline]
+    pub fn config(&self) -> &AfskConfig {
+        &self.config
+    }
+
+    /// Frequency offset of the received tones, in Hz.
+    #[inline]
+    pub fn tuning_offset(&self) -> f32 {
+        self.tuning_offset
+    }
+
+    /// Sets the frequency offset of the received tones, e.g. if the receiver
+    /// is slightly mistuned on HF.
+    pub fn set_tuning_offset(&mut self, tuning_offset: f32) {
+        self.tuning_offset = tuning_offset;
+        self.update_oscillator();
+    }
+
+    /// Remaining frequency error in Hz, estimated from the mark and space
+    /// levels seen by the slicer.
+    ///
+    /// This can be used to adjust the [tuning
+    /// offset][Self::set_tuning_offset].
+    pub fn frequency_error(&self) -> f32 {
+        self.slicer.threshold() * 0.5 * self.config.shift()
+    }
+
+    fn update_oscillator(&mut self) {
+        let frequency = self.config.center_frequency() + self.tuning_offset;
+        self.oscillator_step = Complex::from_polar(1.0, -TAU * frequency / self.sample_rate);
+    }
+}
+
+impl Scanner<Complex<f32>> for AfskDemodulator {
+    type Output = Option<bool>;
+
+    fn scan(&mut self, sample: Complex<f32>) -> Self::Output {
+        let mut baseband = sample * self.oscillator;
+        self.oscillator *= self.oscillator_step;
+        // keep the oscillator from drifting in amplitude
+        self.oscillator /= self.oscillator.norm();
+
+        for filter in &mut self.channel_filter {
+            baseband = filter.scan(baseband);
+        }
+
+        // +1 for space, -1 for mark
+        let frequency = self.symbol_filter.scan(self.discriminator.scan(baseband));
+        let decision = self.slicer.scan(frequency);
+
+        // move the symbol clock towards the transitions, which are at the
+        // symbol boundaries.
+        if decision != self.last_decision {
+            let error = if self.symbol_clock < 0.5 {
+                self.symbol_clock
+            }
+            else {
+                self.symbol_clock - 1.0
+            };
+            self.symbol_clock -= 0.3 * error;
+            self.last_decision = decision;
+        }
+
+        let before = self.symbol_clock;
+        self.symbol_clock = (self.symbol_clock + self.symbol_step).rem_euclid(1.0);
+
+        (before < 0.5 && self.symbol_clock >= 0.5).then_some(decision)
+    }
+}
+
+/// Real audio, e.g. from an FM receiver, is treated as complex audio with the
+/// negative frequencies mirrored. The channel filter suppresses them.
+impl Scanner<f32> for AfskDemodulator {
+    type Output = Option<bool>;
+
+    #[inline]
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        Scanner::<Complex<f32>>::scan(self, Complex::new(sample, 0.0))
+    }
+}
+
+/// Decides between mark and space, tracking both levels so that the
+/// threshold follows frequency drift.
+#[derive(Clone, Copy, Debug)]
+struct Slicer {
+    mark_level: f32,
+    space_level: f32,
+}
+
+impl Slicer {
+    /// How fast the levels follow the signal, per sample.
+    const TRACKING: f32 = 0.002;
+
+    fn new() -> Self {
+        Self {
+            mark_level: -1.0,
+            space_level: 1.0,
+        }
+    }
+
+    #[inline]
+    fn threshold(&self) -> f32 {
+        0.5 * (self.mark_level + self.space_level)
+    }
+}
+
+impl Scanner<f32> for Slicer {
+    /// `true` for mark
+    type Output = bool;
+
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        let mark = sample < self.threshold();
+        let level = if mark {
+            &mut self.mark_level
+        }
+        else {
+            &mut self.space_level
+        };
+        *level += Self::TRACKING * (sample - *level);
+        mark
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use num_complex::Complex;
+
+    use super::{
+        AfskConfig,
+        AfskDemodulator,
+        AfskModulator,
+    };
+    use crate::io::combinators::Scanner;
+
+    const SAMPLE_RATE: f32 = 8000.0;
+
+    fn bits(n: usize) -> Vec<bool> {
+        let mut state = 0x1234_5678u32;
+        (0..n)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                state >> 31 != 0
+            })
+            .collect()
+    }
+
+    /// Returns the number of bit errors at the best alignment, allowing the
+    /// receiver to be up to 8 bits early or late.
+    fn count_errors(sent: &[bool], received: &[bool], skip: usize) -> usize {
+        (0..16)
+            .map(|lag| {
+                sent[skip + 8..]
+                    .iter()
+                    .zip(received.iter().skip(skip + lag))
+                    .filter(|(a, b)| a != b)
+                    .count()
+            })
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn it_decodes_through_a_drifting_hf_channel() {
+        let config = AfskConfig::HF_300;
+        let sent = bits(600);
+
+        let mut signal = vec![];
+        let mut modulator = AfskModulator::new(config, SAMPLE_RATE);
+        modulator.modulate_bits(sent.iter().copied(), &mut signal);
+
+        // the receiver is mistuned by 60 Hz, and then drifts by another 30 Hz
+        // over the transmission. also add some noise.
+        let mut phase = 0.0f32;
+        let mut noise_state = 0xdead_beefu32;
+        let num_samples = signal.len();
+        for (i, sample) in signal.iter_mut().enumerate() {
+            let offset = 60.0 + 30.0 * i as f32 / num_samples as f32;
+            phase = (phase + TAU * offset / SAMPLE_RATE) % TAU;
+            noise_state = noise_state.wrapping_mul(1664525).wrapping_add(1013904223);
+            let noise = (noise_state >> 8) as f32 / (1 << 24) as f32 - 0.5;
+            *sample = *sample * Complex::from_polar(1.0, phase) + Complex::new(0.2 * noise, 0.0);
+        }
+
+        let mut demodulator = AfskDemodulator::new(config, SAMPLE_RATE);
+        demodulator.set_tuning_offset(60.0);
+
+        let received = signal
+            .iter()
+            .filter_map(|sample| demodulator.scan(*sample))
+            .collect::<Vec<_>>();
+
+        assert!(received.len().abs_diff(sent.len()) < 4);
+        assert_eq!(count_errors(&sent, &received, 32), 0);
+        assert!((demodulator.frequency_error() - 30.0).abs() < 15.0);
+    }
+}
@@ -0,0 +1,446 @@
+//! Amplitude modulation
+
+use std::f32::consts::{
+    PI,
+    TAU,
+};
+
+use num_complex::Complex;
+
+use crate::io::combinators::{
+    GroupDelay,
+    Scanner,
+};
+
+/// AM modulator producing complex baseband.
+///
+/// The output is `carrier_level + modulation_index * sample`. With a carrier
+/// level of 1 this is regular full-carrier AM, where a modulation index above
+/// 1 overmodulates. With a carrier level of 0 it's DSB-SC.
+#[derive(Clone, Copy, Debug)]
+pub struct AmModulator {
+    modulation_index: f32,
+    carrier_level: f32,
+}
+
+impl Default for AmModulator {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl AmModulator {
+    /// Full-carrier AM with the given modulation index.
+    pub fn new(modulation_index: f32) -> Self {
+        Self {
+            modulation_index,
+            carrier_level: 1.0,
+        }
+    }
+
+    /// Double-sideband suppressed-carrier.
+    pub fn dsb_sc() -> Self {
+        Self {
+            modulation_index: 1.0,
+            carrier_level: 0.0,
+        }
+    }
+
+    pub fn with_carrier_level(mut self, carrier_level: f32) -> Self {
+        self.carrier_level = carrier_level;
+        self
+    }
+
+    #[inline]
+    pub fn modulation_index(&self) -> f32 {
+        self.modulation_index
+    }
+
+    #[inline]
+    pub fn set_modulation_index(&mut self, modulation_index: f32) {
+        self.modulation_index = modulation_index;
+    }
+
+    #[inline]
+    pub fn carrier_level(&self) -> f32 {
+        self.carrier_level
+    }
+
+    #[inline]
+    pub fn set_carrier_level(&mut self, carrier_level: f32) {
+        self.carrier_level = carrier_level;
+    }
+}
+
+impl Scanner<f32> for AmModulator {
+    type Output = Complex<f32>;
+
+    #[inline]
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        Complex {
+            re: self.carrier_level + self.modulation_index * sample,
+            im: 0.0,
+        }
+    }
+}
+
+impl GroupDelay for AmModulator {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Time constant of the carrier level that demodulators remove by default, in
+/// seconds.
+///
+/// This is long compared to audio, but short enough to follow fading.
+pub const DEFAULT_CARRIER_TIME_CONSTANT: f32 = 0.05;
+
+/// Loop bandwidth of the [`CoherentDemodulator`]'s PLL by default, in Hz.
+pub const DEFAULT_LOOP_BANDWIDTH: f32 = 100.0;
+
+/// Tracks the carrier level of a demodulated AM signal, and removes it.
+///
+/// The carrier level is the average of the demodulated signal, measured with
+/// a single-pole low-pass. The output is relative to it, i.e. `(x - c) / c`,
+/// so it's the modulation of the signal regardless of how strong it is
+/// received. This undoes the [`AmModulator`], apart from its modulation index.
+#[derive(Clone, Copy, Debug)]
+pub struct CarrierRemoval {
+    alpha: f32,
+    carrier_level: Option<f32>,
+}
+
+impl CarrierRemoval {
+    /// The time constant is in seconds.
+    pub fn new(sample_rate: f32, time_constant: f32) -> Self {
+        Self {
+            alpha: 1.0 - (-1.0 / (sample_rate * time_constant)).exp(),
+            carrier_level: None,
+        }
+    }
+
+    /// The measured carrier level, if any sample was scanned yet.
+    #[inline]
+    pub fn carrier_level(&self) -> Option<f32> {
+        self.carrier_level
+    }
+}
+
+impl Scanner<f32> for CarrierRemoval {
+    type Output = f32;
+
+    #[inline]
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        // start from the first sample, so a steady carrier needs no time to
+        // settle
+        let carrier_level = self.carrier_level.get_or_insert(sample);
+        *carrier_level += self.alpha * (sample - *carrier_level);
+
+        if carrier_level.abs() > f32::EPSILON {
+            (sample - *carrier_level) / *carrier_level
+        }
+        else {
+            0.0
+        }
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.carrier_level = None;
+        None
+    }
+}
+
+impl GroupDelay for CarrierRemoval {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// AM demodulator that detects the envelope, i.e. the magnitude of the
+/// signal.
+///
+/// This doesn't need to know the carrier's phase or frequency, so it works
+/// even if the signal isn't tuned exactly. But it distorts if the signal is
+/// overmodulated or the carrier fades selectively, and can't demodulate
+/// DSB-SC.
+#[derive(Clone, Copy, Debug)]
+pub struct EnvelopeDemodulator {
+    carrier_removal: Option<CarrierRemoval>,
+}
+
+impl EnvelopeDemodulator {
+    /// Demodulator that removes the carrier with the
+    /// [default time constant][DEFAULT_CARRIER_TIME_CONSTANT].
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            carrier_removal: Some(CarrierRemoval::new(
+                sample_rate,
+                DEFAULT_CARRIER_TIME_CONSTANT,
+            )),
+        }
+    }
+
+    /// Demodulator that outputs the envelope as is, including the carrier.
+    pub fn without_carrier_removal() -> Self {
+        Self {
+            carrier_removal: None,
+        }
+    }
+
+    #[inline]
+    pub fn carrier_removal(&self) -> Option<&CarrierRemoval> {
+        self.carrier_removal.as_ref()
+    }
+}
+
+impl Scanner<Complex<f32>> for EnvelopeDemodulator {
+    type Output = f32;
+
+    #[inline]
+    fn scan(&mut self, sample: Complex<f32>) -> Self::Output {
+        let envelope = sample.norm();
+        match &mut self.carrier_removal {
+            Some(carrier_removal) => carrier_removal.scan(envelope),
+            None => envelope,
+        }
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        if let Some(carrier_removal) = &mut self.carrier_removal {
+            carrier_removal.finish();
+        }
+        None
+    }
+}
+
+impl GroupDelay for EnvelopeDemodulator {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+pub type AmDemodulator = EnvelopeDemodulator;
+
+/// Coherent (synchronous) AM demodulator.
+///
+/// A PLL locks onto the carrier, and the demodulated signal is the in-phase
+/// component relative to it. Unlike the [`EnvelopeDemodulator`] this doesn't
+/// distort when the signal is overmodulated or the carrier fades selectively.
+/// The signal must be tuned to within a few loop bandwidths.
+///
+/// With the carrier removal turned off, it can demodulate DSB-SC too, if it's
+/// given a pilot carrier to lock onto.
+#[derive(Clone, Copy, Debug)]
+pub struct CoherentDemodulator {
+    sample_rate: f32,
+    /// Proportional gain of the loop filter.
+    alpha: f32,
+    /// Integral gain of the loop filter.
+    beta: f32,
+    /// Phase of the carrier in radians.
+    phase: f32,
+    /// Frequency of the carrier in radians per sample.
+    frequency: f32,
+    /// Average magnitude of the signal, which normalizes the phase error.
+    level_alpha: f32,
+    level: Option<f32>,
+    carrier_removal: Option<CarrierRemoval>,
+}
+
+impl CoherentDemodulator {
+    /// Demodulator with the [default loop bandwidth][DEFAULT_LOOP_BANDWIDTH],
+    /// that removes the carrier with the
+    /// [default time constant][DEFAULT_CARRIER_TIME_CONSTANT].
+    pub fn new(sample_rate: f32) -> Self {
+        Self::with_loop_bandwidth(sample_rate, DEFAULT_LOOP_BANDWIDTH)
+    }
+
+    /// The loop bandwidth is in Hz. A wider loop locks faster and follows
+    /// drifting carriers, but lets through more noise.
+    pub fn with_loop_bandwidth(sample_rate: f32, loop_bandwidth: f32) -> Self {
+        // critically damped second-order loop
+        let damping = std::f32::consts::FRAC_1_SQRT_2;
+        let theta = loop_bandwidth / sample_rate / (damping + 0.25 / damping);
+        let denominator = 1.0 + 2.0 * damping * theta + theta * theta;
+
+        Self {
+            sample_rate,
+            alpha: 4.0 * damping * theta / denominator,
+            beta: 4.0 * theta * theta / denominator,
+            phase: 0.0,
+            frequency: 0.0,
+            level_alpha: 1.0 - (-1.0 / (sample_rate * DEFAULT_CARRIER_TIME_CONSTANT)).exp(),
+            level: None,
+            carrier_removal: Some(CarrierRemoval::new(
+                sample_rate,
+                DEFAULT_CARRIER_TIME_CONSTANT,
+            )),
+        }
+    }
+
+    /// Outputs the in-phase component as is, including the carrier.
+    pub fn without_carrier_removal(mut self) -> Self {
+        self.carrier_removal = None;
+        self
+    }
+
+    #[inline]
+    pub fn carrier_removal(&self) -> Option<&CarrierRemoval> {
+        self.carrier_removal.as_ref()
+    }
+
+    /// Frequency of the carrier the PLL is locked to, relative to the center,
+    /// in Hz.
+    #[inline]
+    pub fn frequency_offset(&self) -> f32 {
+        self.frequency * self.sample_rate / TAU
+    }
+}
+
+impl Scanner<Complex<f32>> for CoherentDemodulator {
+    type Output = f32;
+
+    fn scan(&mut self, sample: Complex<f32>) -> Self::Output {
+        let rotated = sample * Complex::from_polar(1.0, -self.phase);
+
+        // an overmodulated signal flips its phase where its envelope crosses
+        // 0, so the phase isn't measured with `arg`, which would jump by pi
+        // there. the quadrature component still averages to the phase error.
+        let level = self.level.get_or_insert(sample.norm());
+        *level += self.level_alpha * (sample.norm() - *level);
+        let error = if *level > f32::EPSILON {
+            (rotated.im / *level).clamp(-1.0, 1.0)
+        }
+        else {
+            0.0
+        };
+
+        self.frequency += self.beta * error;
+        self.phase += self.frequency + self.alpha * error;
+        if self.phase > PI {
+            self.phase -= TAU;
+        }
+        else if self.phase < -PI {
+            self.phase += TAU;
+        }
+
+        match &mut self.carrier_removal {
+            Some(carrier_removal) => carrier_removal.scan(rotated.re),
+            None => rotated.re,
+        }
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.phase = 0.0;
+        self.frequency = 0.0;
+        self.level = None;
+        if let Some(carrier_removal) = &mut self.carrier_removal {
+            carrier_removal.finish();
+        }
+        None
+    }
+}
+
+impl GroupDelay for CoherentDemodulator {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use approx::assert_abs_diff_eq;
+    use num_complex::Complex;
+
+    use super::{
+        AmModulator,
+        CoherentDemodulator,
+        EnvelopeDemodulator,
+    };
+    use crate::io::combinators::Scanner;
+
+    const SAMPLE_RATE: f32 = 24_000.0;
+
+    /// Modulates a 1 kHz tone at a modulation index of 0.5 onto a carrier
+    /// that is `frequency_offset` off and has the given level, and returns
+    /// the demodulated tone after one second.
+    fn demodulate(
+        demodulator: &mut impl Scanner<Complex<f32>, Output = f32>,
+        frequency_offset: f32,
+        carrier_level: f32,
+    ) -> Vec<(f32, f32)> {
+        let mut modulator = AmModulator::new(0.5);
+        (0..SAMPLE_RATE as usize + 240)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE;
+                let audio = (TAU * 1000.0 * t).sin();
+                let carrier = Complex::from_polar(carrier_level, TAU * frequency_offset * t + 1.0);
+                (audio, demodulator.scan(modulator.scan(audio) * carrier))
+            })
+            .skip(SAMPLE_RATE as usize)
+            .collect()
+    }
+
+    #[test]
+    fn envelope_follows_audio() {
+        let mut modulator = AmModulator::new(0.5);
+
+        for i in 0..100 {
+            let audio = (TAU * i as f32 / 25.0).sin();
+            let envelope = modulator.scan(audio).norm();
+            assert_abs_diff_eq!(envelope, 1.0 + 0.5 * audio, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn envelope_demodulator_removes_the_carrier() {
+        let mut demodulator = EnvelopeDemodulator::new(SAMPLE_RATE);
+        for (audio, output) in demodulate(&mut demodulator, 300.0, 0.1) {
+            assert_abs_diff_eq!(output, 0.5 * audio, epsilon = 0.01);
+        }
+        assert_abs_diff_eq!(
+            demodulator
+                .carrier_removal()
+                .unwrap()
+                .carrier_level()
+                .unwrap(),
+            0.1,
+            epsilon = 1e-3
+        );
+    }
+
+    #[test]
+    fn coherent_demodulator_locks_to_the_carrier() {
+        let mut demodulator = CoherentDemodulator::new(SAMPLE_RATE);
+        for (audio, output) in demodulate(&mut demodulator, 150.0, 2.0) {
+            assert_abs_diff_eq!(output, 0.5 * audio, epsilon = 0.01);
+        }
+        assert_abs_diff_eq!(demodulator.frequency_offset(), 150.0, epsilon = 0.1);
+    }
+
+    #[test]
+    fn coherent_demodulator_handles_overmodulation() {
+        // the envelope of an overmodulated signal is folded where the signal
+        // crosses 0, but the in-phase component isn't.
+        let mut modulator = AmModulator::new(1.5);
+        let mut demodulator = CoherentDemodulator::new(SAMPLE_RATE).without_carrier_removal();
+        let mut max_error = 0.0f32;
+        for i in 0..2 * SAMPLE_RATE as usize {
+            let t = i as f32 / SAMPLE_RATE;
+            let audio = (TAU * 1000.0 * t).sin();
+            let carrier = Complex::from_polar(1.0, TAU * 50.0 * t + 1.0);
+            let output = demodulator.scan(modulator.scan(audio) * carrier);
+            if i > SAMPLE_RATE as usize {
+                max_error = max_error.max((output - (1.0 + 1.5 * audio)).abs());
+            }
+        }
+        assert!(max_error < 0.01, "max error {max_error}");
+    }
+}
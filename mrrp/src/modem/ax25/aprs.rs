@@ -0,0 +1,299 @@
+//! APRS payloads
+//!
+//! APRS packets are AX.25 UI frames, whose first byte of the information
+//! field tells the type of the data. Position reports (uncompressed and
+//! compressed), messages and status reports are parsed. Other types, e.g.
+//! Mic-E, objects and telemetry, are returned as is.
+//!
+//! # References
+//!
+//! - <http://www.aprs.org/doc/APRS101.PDF>
+
+use crate::modem::ax25::{
+    Ax25Frame,
+    PROTOCOL_NONE,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AprsError {
+    #[error("not an APRS frame")]
+    NotAprs,
+
+    #[error("empty APRS packet")]
+    Empty,
+
+    #[error("invalid APRS position")]
+    InvalidPosition,
+
+    #[error("invalid APRS message")]
+    InvalidMessage,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AprsPacket {
+    Position(PositionReport),
+    Message(Message),
+    Status(String),
+    /// A data type that isn't parsed.
+    Other {
+        data_type: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl AprsPacket {
+    /// Parses the information field of a frame.
+    pub fn parse(info: &[u8]) -> Result<Self, AprsError> {
+        let (&data_type, data) = info.split_first().ok_or(AprsError::Empty)?;
+        let text = || String::from_utf8_lossy(data);
+
+        match data_type {
+            b'!' | b'=' | b'/' | b'@' => {
+                let messaging = matches!(data_type, b'=' | b'@');
+                let (timestamp, data) = if matches!(data_type, b'/' | b'@') {
+                    let timestamp = data.get(..7).ok_or(AprsError::InvalidPosition)?;
+                    (
+                        Some(String::from_utf8_lossy(timestamp).into_owned()),
+                        &data[7..],
+                    )
+                }
+                else {
+                    (None, data)
+                };
+                let (position, comment) = Position::parse(data)?;
+                Ok(Self::Position(PositionReport {
+                    position,
+                    timestamp,
+                    messaging,
+                    comment: String::from_utf8_lossy(comment).into_owned(),
+                }))
+            }
+            b':' => Message::parse(&text()).map(Self::Message),
+            b'>' => Ok(Self::Status(text().into_owned())),
+            _ => {
+                Ok(Self::Other {
+                    data_type,
+                    data: data.to_owned(),
+                })
+            }
+        }
+    }
+
+    /// Parses the information field of a UI frame without a layer 3
+    /// protocol.
+    pub fn from_frame(frame: &Ax25Frame) -> Result<Self, AprsError> {
+        if !frame.is_ui() || frame.protocol != Some(PROTOCOL_NONE) {
+            return Err(AprsError::NotAprs);
+        }
+        Self::parse(&frame.info)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionReport {
+    pub position: Position,
+    /// The timestamp as it was sent, e.g. `092345z`.
+    pub timestamp: Option<String>,
+    /// Whether the station can receive messages.
+    pub messaging: bool,
+    pub comment: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    /// Degrees north.
+    pub latitude: f64,
+    /// Degrees east.
+    pub longitude: f64,
+    /// `/` for the primary table, `\` for the alternate table, or an overlay
+    /// character.
+    pub symbol_table: char,
+    pub symbol_code: char,
+}
+
+impl Position {
+    const UNCOMPRESSED_LENGTH: usize = 19;
+    const COMPRESSED_LENGTH: usize = 13;
+
+    /// Parses an uncompressed or compressed position, and returns the rest of
+    /// the data.
+    fn parse(data: &[u8]) -> Result<(Self, &[u8]), AprsError> {
+        if data.first().is_some_and(u8::is_ascii_digit) {
+            let position = data
+                .get(..Self::UNCOMPRESSED_LENGTH)
+                .ok_or(AprsError::InvalidPosition)?;
+            let latitude = parse_coordinate(&position[..8], 2, b'N', b'S')?;
+            let longitude = parse_coordinate(&position[9..18], 3, b'E', b'W')?;
+            Ok((
+                Self {
+                    latitude,
+                    longitude,
+                    symbol_table: position[8].into(),
+                    symbol_code: position[18].into(),
+                },
+                &data[Self::UNCOMPRESSED_LENGTH..],
+            ))
+        }
+        else {
+            let position = data
+                .get(..Self::COMPRESSED_LENGTH)
+                .ok_or(AprsError::InvalidPosition)?;
+            let latitude = 90.0 - f64::from(decode_base91(&position[1..5])?) / 380926.0;
+            let longitude = -180.0 + f64::from(decode_base91(&position[5..9])?) / 190463.0;
+            Ok((
+                Self {
+                    latitude,
+                    longitude,
+                    symbol_table: position[0].into(),
+                    symbol_code: position[9].into(),
+                },
+                &data[Self::COMPRESSED_LENGTH..],
+            ))
+        }
+    }
+}
+
+/// Parses `DDMM.mmN` or `DDDMM.mmE`. Spaces, which reduce the precision, are
+/// read as 0.
+fn parse_coordinate(
+    data: &[u8],
+    degree_digits: usize,
+    positive: u8,
+    negative: u8,
+) -> Result<f64, AprsError> {
+    let (hemisphere, digits) = data.split_last().ok_or(AprsError::InvalidPosition)?;
+    if !digits
+        .iter()
+        .all(|c| c.is_ascii_digit() || matches!(c, b'.' | b' '))
+    {
+        return Err(AprsError::InvalidPosition);
+    }
+    let digits = digits
+        .iter()
+        .map(|c| if *c == b' ' { '0' } else { char::from(*c) })
+        .collect::<String>();
+    let degrees = digits[..degree_digits]
+        .parse::<f64>()
+        .map_err(|_| AprsError::InvalidPosition)?;
+    let minutes = digits[degree_digits..]
+        .parse::<f64>()
+        .map_err(|_| AprsError::InvalidPosition)?;
+
+    let value = degrees + minutes / 60.0;
+    match *hemisphere {
+        c if c == positive => Ok(value),
+        c if c == negative => Ok(-value),
+        _ => Err(AprsError::InvalidPosition),
+    }
+}
+
+fn decode_base91(data: &[u8]) -> Result<u32, AprsError> {
+    data.iter().try_fold(0, |value, c| {
+        if (b'!'..=b'{').contains(c) {
+            Ok(value * 91 + u32::from(c - b'!'))
+        }
+        else {
+            Err(AprsError::InvalidPosition)
+        }
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub addressee: String,
+    pub text: String,
+    /// Message number, which the addressee acknowledges.
+    pub id: Option<String>,
+}
+
+impl Message {
+    /// Parses `ADDRESSEE:text{id`, with the addressee padded to 9
+    /// characters.
+    fn parse(data: &str) -> Result<Self, AprsError> {
+        let (addressee, text) = data
+            .split_at_checked(9)
+            .and_then(|(addressee, rest)| Some((addressee, rest.strip_prefix(':')?)))
+            .ok_or(AprsError::InvalidMessage)?;
+        let (text, id) = match text.rsplit_once('{') {
+            Some((text, id)) => (text, Some(id.to_owned())),
+            None => (text, None),
+        };
+
+        Ok(Self {
+            addressee: addressee.trim_end().to_owned(),
+            text: text.to_owned(),
+            id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::{
+        AprsError,
+        AprsPacket,
+        Message,
+    };
+    use crate::modem::ax25::Ax25Frame;
+
+    #[test]
+    fn it_parses_positions() {
+        let AprsPacket::Position(report) =
+            AprsPacket::parse(b"@092345z4903.50N/07201.75W>Test 1234").unwrap()
+        else {
+            panic!("not a position report");
+        };
+        assert_abs_diff_eq!(report.position.latitude, 49.058333, epsilon = 1e-6);
+        assert_abs_diff_eq!(report.position.longitude, -72.029167, epsilon = 1e-6);
+        assert_eq!(report.position.symbol_code, '>');
+        assert_eq!(report.timestamp.as_deref(), Some("092345z"));
+        assert!(report.messaging);
+        assert_eq!(report.comment, "Test 1234");
+
+        let AprsPacket::Position(report) = AprsPacket::parse(b"!/5L!!<*e7>7P[").unwrap()
+        else {
+            panic!("not a position report");
+        };
+        assert_abs_diff_eq!(report.position.latitude, 49.5, epsilon = 1e-6);
+        assert_abs_diff_eq!(report.position.longitude, -72.75, epsilon = 1e-4);
+        assert_eq!(report.position.symbol_table, '/');
+        assert!(!report.messaging);
+
+        assert!(matches!(
+            AprsPacket::parse(b"!4903.50X/07201.75W-"),
+            Err(AprsError::InvalidPosition)
+        ));
+    }
+
+    #[test]
+    fn it_parses_messages() {
+        assert_eq!(
+            AprsPacket::parse(b":N0CALL-9 :Hello there{42").unwrap(),
+            AprsPacket::Message(Message {
+                addressee: "N0CALL-9".to_owned(),
+                text: "Hello there".to_owned(),
+                id: Some("42".to_owned()),
+            })
+        );
+        assert_eq!(
+            AprsPacket::parse(b">Net tonight").unwrap(),
+            AprsPacket::Status("Net tonight".to_owned())
+        );
+
+        let frame = Ax25Frame::ui(
+            "N0CALL".parse().unwrap(),
+            "APRS".parse().unwrap(),
+            vec![],
+            b"`c51!f?>/]\"4V}=".to_vec(),
+        );
+        assert!(matches!(
+            AprsPacket::from_frame(&frame).unwrap(),
+            AprsPacket::Other {
+                data_type: b'`',
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,525 @@
+//! AX.25 packet radio
+//!
+//! AX.25 frames are HDLC frames (see [`hdlc`][crate::coding::hdlc]) with the
+//! addresses of the destination, the source and up to 8 digipeaters, followed
+//! by a control byte, a protocol identifier and the payload. On VHF they're
+//! sent NRZI coded with Bell 202 AFSK at 1200 baud, which is what APRS uses.
+//!
+//! [`Ax25FrameStream`] decodes frames from audio, e.g. the output of an FM
+//! demodulator. [`Ax25Decoder`] does the same for a bit stream, and
+//! [`encode_frames`] produces the bits to send frames with an
+//! [`AfskModulator`][crate::modem::afsk::AfskModulator].
+//!
+//! # References
+//!
+//! - <https://www.tapr.org/pdf/AX25.2.2.pdf>
+
+pub mod aprs;
+
+use std::{
+    fmt::Display,
+    pin::Pin,
+    str::FromStr,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use futures_util::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{
+    coding::{
+        hdlc::{
+            HdlcDeframer,
+            HdlcEncoder,
+        },
+        line::{
+            DiffDecode,
+            DiffEncode,
+            Transition,
+        },
+    },
+    io::{
+        AsyncReadSamples,
+        GetSampleRate,
+        ReadBuf,
+        combinators::Scanner,
+    },
+    modem::afsk::{
+        AfskConfig,
+        AfskDemodulator,
+    },
+};
+
+/// Control byte of an unnumbered information (UI) frame.
+pub const CONTROL_UI: u8 = 0x03;
+
+/// Protocol identifier for frames without a layer 3 protocol, as used by
+/// APRS.
+pub const PROTOCOL_NONE: u8 = 0xf0;
+
+pub const MAX_DIGIPEATERS: usize = 8;
+
+const ADDRESS_LENGTH: usize = 7;
+
+const BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Ax25Error {
+    #[error("AX.25 frame too short")]
+    TooShort,
+
+    #[error("AX.25 frame has too many digipeaters")]
+    TooManyDigipeaters,
+
+    #[error("invalid AX.25 address: {0}")]
+    InvalidAddress(String),
+}
+
+/// A station address, i.e. a callsign and a secondary station identifier
+/// (SSID).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Ax25Address {
+    /// Up to 6 upper case letters and digits.
+    pub callsign: String,
+
+    /// 0 to 15
+    pub ssid: u8,
+
+    /// The command/response bit of the destination and source address, or the
+    /// has-been-repeated bit of a digipeater.
+    pub flag: bool,
+}
+
+impl Ax25Address {
+    pub fn new(callsign: &str, ssid: u8) -> Result<Self, Ax25Error> {
+        if callsign.is_empty()
+            || callsign.len() > 6
+            || !callsign.bytes().all(|c| c.is_ascii_alphanumeric())
+            || ssid > 15
+        {
+            return Err(Ax25Error::InvalidAddress(format!("{callsign}-{ssid}")));
+        }
+
+        Ok(Self {
+            callsign: callsign.to_ascii_uppercase(),
+            ssid,
+            flag: false,
+        })
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let callsign = bytes[..6]
+            .iter()
+            .map(|byte| char::from(byte >> 1))
+            .collect::<String>()
+            .trim_end()
+            .to_owned();
+        Self {
+            callsign,
+            ssid: (bytes[6] >> 1) & 0x0f,
+            flag: bytes[6] & 0x80 != 0,
+        }
+    }
+
+    fn encode(&self, is_last: bool, output: &mut Vec<u8>) {
+        let callsign = self.callsign.as_bytes();
+        output.extend((0..6).map(|i| callsign.get(i).copied().unwrap_or(b' ') << 1));
+        // the reserved bits are set
+        output.push(0x60 | u8::from(self.flag) << 7 | (self.ssid & 0x0f) << 1 | u8::from(is_last));
+    }
+}
+
+impl Display for Ax25Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.callsign)?;
+        if self.ssid != 0 {
+            write!(f, "-{}", self.ssid)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Ax25Address {
+    type Err = Ax25Error;
+
+    /// Parses addresses like `N0CALL-9`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('-') {
+            Some((callsign, ssid)) => {
+                let ssid = ssid
+                    .parse()
+                    .map_err(|_| Ax25Error::InvalidAddress(s.to_owned()))?;
+                Self::new(callsign, ssid)
+            }
+            None => Self::new(s, 0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ax25Frame {
+    pub destination: Ax25Address,
+    pub source: Ax25Address,
+    pub digipeaters: Vec<Ax25Address>,
+    pub control: u8,
+    /// Protocol identifier, which only information frames have.
+    pub protocol: Option<u8>,
+    pub info: Vec<u8>,
+}
+
+impl Ax25Frame {
+    /// Unnumbered information frame without a layer 3 protocol, as used by
+    /// APRS.
+    pub fn ui(
+        source: Ax25Address,
+        destination: Ax25Address,
+        digipeaters: Vec<Ax25Address>,
+        info: Vec<u8>,
+    ) -> Self {
+        Self {
+            // a command frame
+            destination: Ax25Address {
+                flag: true,
+                ..destination
+            },
+            source: Ax25Address {
+                flag: false,
+                ..source
+            },
+            digipeaters,
+            control: CONTROL_UI,
+            protocol: Some(PROTOCOL_NONE),
+            info,
+        }
+    }
+
+    /// Parses the contents of an HDLC frame, i.e. without the FCS.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Ax25Error> {
+        // the last address has the lowest bit set
+        let num_addresses = bytes
+            .chunks_exact(ADDRESS_LENGTH)
+            .position(|address| address[6] & 1 != 0)
+            .ok_or(Ax25Error::TooShort)?
+            + 1;
+        if num_addresses < 2 {
+            return Err(Ax25Error::TooShort);
+        }
+        if num_addresses > 2 + MAX_DIGIPEATERS {
+            return Err(Ax25Error::TooManyDigipeaters);
+        }
+
+        let mut addresses = bytes[..num_addresses * ADDRESS_LENGTH]
+            .chunks_exact(ADDRESS_LENGTH)
+            .map(Ax25Address::decode);
+        let destination = addresses.next().unwrap();
+        let source = addresses.next().unwrap();
+        let digipeaters = addresses.collect();
+
+        let rest = &bytes[num_addresses * ADDRESS_LENGTH..];
+        let (&control, rest) = rest.split_first().ok_or(Ax25Error::TooShort)?;
+
+        // information frames, and unnumbered information frames, ignoring the
+        // poll/final bit
+        let has_protocol = control & 1 == 0 || control & !0x10 == CONTROL_UI;
+        let (protocol, info) = if has_protocol {
+            let (&protocol, info) = rest.split_first().ok_or(Ax25Error::TooShort)?;
+            (Some(protocol), info)
+        }
+        else {
+            (None, rest)
+        };
+
+        Ok(Self {
+            destination,
+            source,
+            digipeaters,
+            control,
+            protocol,
+            info: info.to_owned(),
+        })
+    }
+
+    /// Encodes the frame for an HDLC encoder, i.e. without the FCS.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut output = vec![];
+        self.destination.encode(false, &mut output);
+        self.source.encode(self.digipeaters.is_empty(), &mut output);
+        for (i, digipeater) in self.digipeaters.iter().enumerate() {
+            digipeater.encode(i + 1 == self.digipeaters.len(), &mut output);
+        }
+        output.push(self.control);
+        output.extend(self.protocol);
+        output.extend_from_slice(&self.info);
+        output
+    }
+
+    #[inline]
+    pub fn is_ui(&self) -> bool {
+        self.control & !0x10 == CONTROL_UI
+    }
+}
+
+impl Display for Ax25Frame {
+    /// Formats the frame like TNC2 monitors do, e.g.
+    /// `N0CALL-9>APRS,WIDE1-1*:payload`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}>{}", self.source, self.destination)?;
+
+        // only the last digipeater that repeated the frame is marked
+        let last_repeated = self.digipeaters.iter().rposition(|digi| digi.flag);
+        for (i, digipeater) in self.digipeaters.iter().enumerate() {
+            write!(f, ",{digipeater}")?;
+            if Some(i) == last_repeated {
+                write!(f, "*")?;
+            }
+        }
+
+        write!(f, ":{}", String::from_utf8_lossy(&self.info))
+    }
+}
+
+/// Decodes AX.25 frames from NRZI coded bits, as they come out of the
+/// [`AfskDemodulator`].
+///
+/// Frames with a wrong FCS, or that aren't valid AX.25 frames, are dropped.
+#[derive(Clone, Debug)]
+pub struct Ax25Decoder {
+    nrzi: DiffDecode,
+    deframer: HdlcDeframer,
+}
+
+impl Default for Ax25Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ax25Decoder {
+    pub fn new() -> Self {
+        Self {
+            nrzi: DiffDecode::new(Transition::OnZero),
+            deframer: HdlcDeframer::new(),
+        }
+    }
+
+    #[inline]
+    pub fn deframer(&self) -> &HdlcDeframer {
+        &self.deframer
+    }
+}
+
+impl Scanner<bool> for Ax25Decoder {
+    type Output = Option<Ax25Frame>;
+
+    fn scan(&mut self, bit: bool) -> Self::Output {
+        let frame = self.deframer.scan(self.nrzi.scan(bit))?;
+        Ax25Frame::parse(&frame)
+            .inspect_err(|error| tracing::debug!(?error, "invalid AX.25 frame"))
+            .ok()
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.deframer.finish();
+        None
+    }
+}
+
+/// Encodes frames into NRZI coded bits, including a preamble of flags.
+pub fn encode_frames<'a>(frames: impl IntoIterator<Item = &'a Ax25Frame>) -> Vec<bool> {
+    let mut encoder = HdlcEncoder::new();
+    for frame in frames {
+        encoder.push(&frame.encode());
+    }
+
+    let mut nrzi = DiffEncode::new(Transition::OnZero);
+    encoder
+        .finish()
+        .into_iter()
+        .map(|bit| nrzi.scan(bit))
+        .collect()
+}
+
+pin_project! {
+    /// Demodulates and decodes AX.25 frames from audio.
+    ///
+    /// This is a [`Stream`] of frames, which ends with the audio stream.
+    #[derive(Debug)]
+    pub struct Ax25FrameStream<R, S> {
+        #[pin]
+        input: R,
+        demodulator: AfskDemodulator,
+        decoder: Ax25Decoder,
+        buffer: Vec<S>,
+        position: usize,
+        length: usize,
+    }
+}
+
+impl<R, S> Ax25FrameStream<R, S>
+where
+    R: GetSampleRate,
+    S: Clone + Default,
+{
+    /// Decodes 1200 baud packet radio.
+    pub fn new(input: R) -> Self {
+        Self::with_config(input, AfskConfig::BELL_202)
+    }
+
+    pub fn with_config(input: R, config: AfskConfig) -> Self {
+        let demodulator = AfskDemodulator::new(config, input.sample_rate());
+        Self {
+            input,
+            demodulator,
+            decoder: Ax25Decoder::new(),
+            buffer: vec![S::default(); BUFFER_SIZE],
+            position: 0,
+            length: 0,
+        }
+    }
+}
+
+impl<R, S> Ax25FrameStream<R, S> {
+    #[inline]
+    pub fn demodulator(&self) -> &AfskDemodulator {
+        &self.demodulator
+    }
+
+    #[inline]
+    pub fn demodulator_mut(&mut self) -> &mut AfskDemodulator {
+        &mut self.demodulator
+    }
+
+    #[inline]
+    pub fn decoder(&self) -> &Ax25Decoder {
+        &self.decoder
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+impl<R, S> Stream for Ax25FrameStream<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: Copy,
+    AfskDemodulator: Scanner<S, Output = Option<bool>>,
+{
+    type Item = Result<Ax25Frame, R::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            while *this.position < *this.length {
+                let sample = this.buffer[*this.position];
+                *this.position += 1;
+                if let Some(bit) = this.demodulator.scan(sample)
+                    && let Some(frame) = this.decoder.scan(bit)
+                {
+                    return Poll::Ready(Some(Ok(frame)));
+                }
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.buffer[..]);
+            ready!(this.input.as_mut().poll_read_samples(cx, &mut read_buf))?;
+            let num_read = read_buf.filled().len();
+            if num_read == 0 {
+                return Poll::Ready(None);
+            }
+            *this.position = 0;
+            *this.length = num_read;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{
+        FutureExt,
+        StreamExt,
+    };
+
+    use super::{
+        Ax25Address,
+        Ax25Decoder,
+        Ax25Frame,
+        Ax25FrameStream,
+        encode_frames,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+            combinators::Scanner,
+        },
+        modem::afsk::{
+            AfskConfig,
+            AfskModulator,
+        },
+    };
+
+    fn frame(info: &str) -> Ax25Frame {
+        let mut digipeater: Ax25Address = "WIDE1-1".parse().unwrap();
+        digipeater.flag = true;
+        Ax25Frame::ui(
+            "N0CALL-9".parse().unwrap(),
+            "APRS".parse().unwrap(),
+            vec![digipeater, "WIDE2-1".parse().unwrap()],
+            info.as_bytes().to_owned(),
+        )
+    }
+
+    #[test]
+    fn it_parses_encoded_frames() {
+        let frame = frame("!4903.50N/07201.75W-Test");
+        let parsed = Ax25Frame::parse(&frame.encode()).unwrap();
+        assert_eq!(parsed, frame);
+        assert!(parsed.is_ui());
+        assert_eq!(
+            parsed.to_string(),
+            "N0CALL-9>APRS,WIDE1-1*,WIDE2-1:!4903.50N/07201.75W-Test"
+        );
+    }
+
+    #[test]
+    fn it_decodes_inverted_bits() {
+        let frames = [frame("first"), frame("second")];
+        let mut decoder = Ax25Decoder::new();
+        let decoded = encode_frames(&frames)
+            .into_iter()
+            .filter_map(|bit| decoder.scan(!bit))
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, frames);
+    }
+
+    #[test]
+    fn it_decodes_frames_from_audio() {
+        const SAMPLE_RATE: f32 = 48_000.0;
+
+        let frames = [frame(">on the air"), frame(":N0CALL   :hi{1")];
+        let mut signal = vec![];
+        AfskModulator::new(AfskConfig::BELL_202, SAMPLE_RATE)
+            .modulate_bits(encode_frames(&frames), &mut signal);
+
+        // an FM receiver outputs real audio
+        let audio = signal
+            .into_iter()
+            .map(|sample| 0.5 * sample.re)
+            .collect::<Vec<f32>>();
+
+        let stream =
+            Ax25FrameStream::<_, f32>::new(Cursor::new(&audio[..]).with_sample_rate(SAMPLE_RATE));
+        let decoded = stream
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .now_or_never()
+            .expect("test stream pending");
+        assert_eq!(decoded, frames);
+    }
+}
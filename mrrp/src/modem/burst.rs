@@ -0,0 +1,255 @@
+//! Burst processing
+//!
+//! Burst modes like ADS-B or AIS send short transmissions that start with a
+//! known preamble. The receiver usually isn't tuned exactly to the
+//! transmitter, so the burst arrives with a carrier frequency offset (CFO)
+//! and an unknown phase. Both can be estimated from the preamble, and
+//! removed from the whole burst before making symbol decisions.
+
+use std::f32::consts::PI;
+
+use num_complex::Complex;
+
+/// Frequencies are first searched on a grid with this many points per
+/// preamble sample, and then refined.
+const GRID_OVERSAMPLING: usize = 8;
+
+/// Iterations of the golden-section search that refines the frequency. Each
+/// one shrinks the interval by a factor of 0.618.
+const REFINE_ITERATIONS: usize = 24;
+
+/// Carrier offset of a burst.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CarrierOffset {
+    /// Frequency offset in radians per sample.
+    pub frequency: f32,
+
+    /// Phase offset at the first sample of the burst, in radians.
+    pub phase: f32,
+
+    /// Normalized correlation of the de-rotated burst with the preamble,
+    /// between 0 and 1. This tells how well the preamble was matched.
+    pub correlation: f32,
+}
+
+impl CarrierOffset {
+    /// Frequency offset in Hz.
+    #[inline]
+    pub fn frequency_hz(&self, sample_rate: f32) -> f32 {
+        self.frequency * sample_rate / (2.0 * PI)
+    }
+
+    /// Removes the offset from `burst`, which must start at the same sample
+    /// as the one the offset was estimated from.
+    pub fn derotate(&self, burst: &mut [Complex<f32>]) {
+        // the angle is computed in f64 for every sample, so it doesn't drift
+        // over long bursts
+        let frequency = f64::from(self.frequency);
+        let phase = f64::from(self.phase);
+        for (i, sample) in burst.iter_mut().enumerate() {
+            let angle = -(phase + frequency * i as f64);
+            *sample *= Complex::from_polar(1.0, angle as f32);
+        }
+    }
+}
+
+/// A known preamble, to estimate the carrier offset of bursts with.
+#[derive(Clone, Debug)]
+pub struct Preamble {
+    samples: Vec<Complex<f32>>,
+    energy: f32,
+    max_offset: f32,
+}
+
+impl Preamble {
+    /// Creates a preamble from its baseband samples, at the sample rate of the
+    /// bursts.
+    ///
+    /// For on-off keying like ADS-B, these are just 1 and 0.
+    pub fn new(samples: impl IntoIterator<Item = Complex<f32>>) -> Self {
+        let samples = samples.into_iter().collect::<Vec<_>>();
+        let energy = samples.iter().map(|sample| sample.norm_sqr()).sum();
+        Self {
+            samples,
+            energy,
+            max_offset: PI,
+        }
+    }
+
+    /// Only searches frequency offsets up to `max_offset` radians per sample.
+    ///
+    /// By default all offsets up to half the sample rate are searched. Setting
+    /// this to the largest offset that's expected makes false matches less
+    /// likely for short preambles.
+    pub fn with_max_offset(mut self, max_offset: f32) -> Self {
+        self.max_offset = max_offset.clamp(0.0, PI);
+        self
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Estimates the carrier offset of `burst`, which starts with the
+    /// preamble.
+    ///
+    /// This finds the frequency at which the burst, mixed with the conjugate
+    /// preamble, has the most energy, which is the maximum-likelihood estimate
+    /// for a burst in white noise. It works for preambles with gaps, like
+    /// on-off keying, too.
+    ///
+    /// Returns `None` if the burst is shorter than the preamble, or if either
+    /// has no energy.
+    pub fn estimate_offset(&self, burst: &[Complex<f32>]) -> Option<CarrierOffset> {
+        let burst = burst.get(..self.samples.len())?;
+        let burst_energy = burst.iter().map(|sample| sample.norm_sqr()).sum::<f32>();
+        if self.energy <= 0.0 || burst_energy <= 0.0 {
+            return None;
+        }
+
+        // after this, the preamble's modulation is removed and only the
+        // carrier offset is left
+        let mixed = burst
+            .iter()
+            .zip(&self.samples)
+            .map(|(sample, preamble)| sample * preamble.conj())
+            .collect::<Vec<_>>();
+
+        // coarse search on a grid
+        let num_points = (GRID_OVERSAMPLING * mixed.len()).max(2);
+        let step = 2.0 * self.max_offset / (num_points - 1) as f32;
+        let (best, _) = (0..num_points)
+            .map(|i| {
+                let frequency = -self.max_offset + step * i as f32;
+                (frequency, dtft(&mixed, frequency).norm_sqr())
+            })
+            .fold(
+                (0.0, f32::MIN),
+                |best, point| {
+                    if point.1 > best.1 { point } else { best }
+                },
+            );
+
+        // refine with a golden-section search around the best grid point
+        let ratio = 0.5 * (5.0f32.sqrt() - 1.0);
+        let mut low = (best - step).max(-self.max_offset);
+        let mut high = (best + step).min(self.max_offset);
+        for _ in 0..REFINE_ITERATIONS {
+            let a = high - ratio * (high - low);
+            let b = low + ratio * (high - low);
+            if dtft(&mixed, a).norm_sqr() > dtft(&mixed, b).norm_sqr() {
+                high = b;
+            }
+            else {
+                low = a;
+            }
+        }
+        let frequency = 0.5 * (low + high);
+
+        let peak = dtft(&mixed, frequency);
+        Some(CarrierOffset {
+            frequency,
+            phase: peak.arg(),
+            correlation: peak.norm() / (self.energy * burst_energy).sqrt(),
+        })
+    }
+
+    /// Estimates the carrier offset of `burst`, and removes it.
+    pub fn derotate(&self, burst: &mut [Complex<f32>]) -> Option<CarrierOffset> {
+        let offset = self.estimate_offset(burst)?;
+        offset.derotate(burst);
+        Some(offset)
+    }
+}
+
+/// Evaluates the discrete-time Fourier transform of `x` at `frequency`
+/// (radians per sample).
+fn dtft(x: &[Complex<f32>], frequency: f32) -> Complex<f32> {
+    x.iter()
+        .enumerate()
+        .map(|(i, x)| x * Complex::from_polar(1.0, -frequency * i as f32))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex;
+
+    use super::{
+        CarrierOffset,
+        Preamble,
+    };
+
+    /// Pseudo-random QPSK symbols
+    fn symbols(n: usize) -> Vec<Complex<f32>> {
+        (0..n as u32)
+            .map(|i| {
+                let x = i.wrapping_mul(2654435761) >> 30;
+                Complex::from_polar(1.0, std::f32::consts::FRAC_PI_2 * x as f32)
+            })
+            .collect()
+    }
+
+    fn rotate(signal: &[Complex<f32>], frequency: f32, phase: f32) -> Vec<Complex<f32>> {
+        let mut signal = signal.to_vec();
+        CarrierOffset {
+            frequency: -frequency,
+            phase: -phase,
+            correlation: 1.0,
+        }
+        .derotate(&mut signal);
+        signal
+    }
+
+    #[test]
+    fn it_derotates_a_psk_burst() {
+        let burst = symbols(96);
+        let preamble = Preamble::new(burst[..32].iter().copied());
+
+        let mut received = rotate(&burst, 0.05, 1.0);
+        let offset = preamble.derotate(&mut received).unwrap();
+
+        assert!((offset.frequency - 0.05).abs() < 1e-4, "{offset:?}");
+        assert!((offset.phase - 1.0).abs() < 1e-3, "{offset:?}");
+        assert!(offset.correlation > 0.999, "{offset:?}");
+        for (received, sent) in received.iter().zip(&burst) {
+            assert!((received - sent).norm() < 1e-2, "{received} != {sent}");
+        }
+    }
+
+    #[test]
+    fn it_estimates_the_offset_of_an_on_off_keyed_burst() {
+        // the ADS-B preamble at 2 samples/µs
+        let pulses = [0, 2, 7, 9];
+        let preamble = Preamble::new(
+            (0..16).map(|i| Complex::from(if pulses.contains(&i) { 1.0 } else { 0.0 })),
+        )
+        .with_max_offset(0.5);
+
+        let mut burst = (0..16)
+            .map(|i| Complex::from(if pulses.contains(&i) { 1.0 } else { 0.0 }))
+            .collect::<Vec<_>>();
+        burst.extend(symbols(32).iter().map(|x| Complex::from(x.re.max(0.0))));
+
+        let received = rotate(&burst, -0.2, -2.0);
+        let offset = preamble.estimate_offset(&received).unwrap();
+        assert!((offset.frequency + 0.2).abs() < 1e-4, "{offset:?}");
+        assert!((offset.phase + 2.0).abs() < 1e-3, "{offset:?}");
+    }
+
+    #[test]
+    fn it_needs_the_whole_preamble() {
+        let preamble = Preamble::new(symbols(32));
+        assert_eq!(preamble.estimate_offset(&symbols(31)), None);
+        assert_eq!(
+            preamble.estimate_offset(&[Complex::new(0.0, 0.0); 32]),
+            None
+        );
+    }
+}
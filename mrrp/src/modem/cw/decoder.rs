@@ -0,0 +1,421 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use futures_util::Stream;
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+
+use crate::{
+    filter::GoertzelFilter,
+    io::{
+        AsyncReadSamples,
+        GetSampleRate,
+        ReadBuf,
+        combinators::Scanner,
+    },
+    modem::cw::{
+        dit_duration,
+        morse,
+    },
+};
+
+/// Default bandwidth of the tone detector in Hz.
+pub const DEFAULT_BANDWIDTH: f32 = 100.0;
+
+/// Speed that is assumed until the decoder has seen dits and dahs.
+const INITIAL_WORDS_PER_MINUTE: f32 = 20.0;
+
+/// Number of recent marks the speed is estimated from.
+const HISTORY_LENGTH: usize = 16;
+
+/// How fast the noise level follows the tone detector, per block.
+const NOISE_ALPHA: f32 = 0.05;
+
+/// How fast the signal level decays towards the noise level, per block.
+const PEAK_ALPHA: f32 = 0.02;
+
+/// The signal level must be this many times the noise level for the key to
+/// be down.
+const MIN_SNR: f32 = 4.0;
+
+/// Decodes text from a CW tone.
+///
+/// A [`GoertzelFilter`] measures the tone in blocks of 1 / `bandwidth`
+/// seconds. The key is down if the tone is closer to the signal level than to
+/// the noise level, which are both tracked. The length of a dit is estimated
+/// from the recent marks, once they contain dits and dahs.
+#[derive(Clone, Debug)]
+pub struct CwDecoder {
+    sample_rate: f32,
+    frequency: f32,
+    filter: GoertzelFilter,
+    block_length: usize,
+    block_position: usize,
+
+    noise_level: Option<f32>,
+    signal_level: f32,
+    key_down: bool,
+    /// Duration of the current mark or space in seconds.
+    duration: f32,
+
+    /// Length of a dit in seconds.
+    dit_length: f32,
+    marks: VecDeque<f32>,
+    /// Lengths of the marks of the current character.
+    elements: Vec<f32>,
+    /// Whether a character was decoded since the last word space.
+    in_word: bool,
+}
+
+impl CwDecoder {
+    pub fn new(frequency: f32, sample_rate: f32) -> Self {
+        let block_length = (sample_rate / DEFAULT_BANDWIDTH) as usize;
+        Self {
+            sample_rate,
+            frequency,
+            filter: GoertzelFilter::new(sample_rate, frequency, DEFAULT_BANDWIDTH),
+            block_length,
+            block_position: 0,
+            noise_level: None,
+            signal_level: 0.0,
+            key_down: false,
+            duration: 0.0,
+            dit_length: dit_duration(INITIAL_WORDS_PER_MINUTE),
+            marks: VecDeque::with_capacity(HISTORY_LENGTH),
+            elements: vec![],
+            in_word: false,
+        }
+    }
+
+    /// Bandwidth of the tone detector in Hz. A narrower detector rejects more
+    /// noise, but can't follow fast keying.
+    pub fn with_bandwidth(mut self, bandwidth: f32) -> Self {
+        self.filter = GoertzelFilter::new(self.sample_rate, self.frequency, bandwidth);
+        self.block_length = (self.sample_rate / bandwidth) as usize;
+        self.block_position = 0;
+        self
+    }
+
+    /// The speed to assume until it's estimated from the signal.
+    pub fn with_words_per_minute(mut self, words_per_minute: f32) -> Self {
+        assert!(words_per_minute > 0.0, "invalid speed");
+        self.dit_length = dit_duration(words_per_minute);
+        self
+    }
+
+    /// The estimated speed.
+    #[inline]
+    pub fn words_per_minute(&self) -> f32 {
+        dit_duration(1.0) / self.dit_length
+    }
+
+    #[inline]
+    pub fn is_key_down(&self) -> bool {
+        self.key_down
+    }
+
+    /// Updates the dit length after a mark.
+    fn push_mark(&mut self, length: f32) {
+        if self.marks.len() == HISTORY_LENGTH {
+            self.marks.pop_front();
+        }
+        self.marks.push_back(length);
+        self.elements.push(length);
+
+        let (shortest, longest) = self
+            .marks
+            .iter()
+            .fold((f32::INFINITY, 0.0f32), |(shortest, longest), mark| {
+                (shortest.min(*mark), longest.max(*mark))
+            });
+
+        // only dits or only dahs can't tell the speed
+        if longest >= 2.0 * shortest {
+            let split = (shortest * longest).sqrt();
+            let sum = self
+                .marks
+                .iter()
+                .map(|mark| if *mark < split { *mark } else { *mark / 3.0 })
+                .sum::<f32>();
+            self.dit_length = sum / self.marks.len() as f32;
+        }
+    }
+
+    /// Decodes the marks of the current character.
+    fn end_character(&mut self) -> Option<char> {
+        let code = self
+            .elements
+            .drain(..)
+            .map(|mark| {
+                if mark < 2.0 * self.dit_length {
+                    '.'
+                }
+                else {
+                    '-'
+                }
+            })
+            .collect::<String>();
+        self.in_word = true;
+        morse::decode(&code)
+    }
+
+    /// Processes the tone level of a block.
+    fn push_block(&mut self, level: f32) -> Option<char> {
+        let noise_level = *self.noise_level.get_or_insert(level);
+        if level > self.signal_level {
+            self.signal_level = level;
+        }
+        else {
+            self.signal_level += PEAK_ALPHA * (noise_level - self.signal_level);
+        }
+
+        let key_down = self.signal_level > MIN_SNR * noise_level
+            && level > 0.5 * (self.signal_level + noise_level);
+        if !key_down {
+            self.noise_level = Some(noise_level + NOISE_ALPHA * (level - noise_level));
+        }
+
+        if key_down != self.key_down {
+            if self.key_down {
+                self.push_mark(self.duration);
+            }
+            self.key_down = key_down;
+            self.duration = 0.0;
+        }
+        self.duration += self.block_length as f32 / self.sample_rate;
+
+        if self.key_down {
+            None
+        }
+        else if !self.elements.is_empty() && self.duration > 2.0 * self.dit_length {
+            self.end_character()
+        }
+        else if self.in_word && self.duration > 5.0 * self.dit_length {
+            self.in_word = false;
+            Some(' ')
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl Scanner<Complex<f32>> for CwDecoder {
+    type Output = Option<char>;
+
+    fn scan(&mut self, sample: Complex<f32>) -> Self::Output {
+        let output = self.filter.scan(sample);
+
+        // the filter's output changes once per block
+        self.block_position += 1;
+        if self.block_position == self.block_length {
+            self.block_position = 0;
+            self.push_block(output.norm())
+        }
+        else {
+            None
+        }
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        if self.key_down {
+            self.push_mark(self.duration);
+            self.key_down = false;
+        }
+        let character = if self.elements.is_empty() {
+            None
+        }
+        else {
+            self.end_character()
+        };
+
+        self.duration = 0.0;
+        self.in_word = false;
+        character.map(Some)
+    }
+}
+
+impl Scanner<f32> for CwDecoder {
+    type Output = Option<char>;
+
+    #[inline]
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        Scanner::<Complex<f32>>::scan(self, Complex::new(sample, 0.0))
+    }
+
+    #[inline]
+    fn finish(&mut self) -> Option<Self::Output> {
+        Scanner::<Complex<f32>>::finish(self)
+    }
+}
+
+pin_project! {
+    /// Decodes CW from audio.
+    ///
+    /// This is a [`Stream`] of characters, which ends with the audio stream.
+    #[derive(Debug)]
+    pub struct CwStream<R, S> {
+        #[pin]
+        input: R,
+        decoder: CwDecoder,
+        buffer: Vec<S>,
+        position: usize,
+        length: usize,
+    }
+}
+
+impl<R, S> CwStream<R, S>
+where
+    S: Clone + Default,
+{
+    /// Decodes a tone at `frequency` (in Hz) with the default settings.
+    pub fn new(input: R, frequency: f32) -> Self
+    where
+        R: GetSampleRate,
+    {
+        let decoder = CwDecoder::new(frequency, input.sample_rate());
+        Self::with_decoder(input, decoder)
+    }
+
+    pub fn with_decoder(input: R, decoder: CwDecoder) -> Self {
+        Self {
+            input,
+            decoder,
+            buffer: vec![S::default(); 4096],
+            position: 0,
+            length: 0,
+        }
+    }
+}
+
+impl<R, S> CwStream<R, S> {
+    #[inline]
+    pub fn decoder(&self) -> &CwDecoder {
+        &self.decoder
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+impl<R, S> Stream for CwStream<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: Copy,
+    CwDecoder: Scanner<S, Output = Option<char>>,
+{
+    type Item = Result<char, R::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            while *this.position < *this.length {
+                let sample = this.buffer[*this.position];
+                *this.position += 1;
+                if let Some(character) = this.decoder.scan(sample) {
+                    return Poll::Ready(Some(Ok(character)));
+                }
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.buffer[..]);
+            ready!(this.input.as_mut().poll_read_samples(cx, &mut read_buf))?;
+            let num_read = read_buf.filled().len();
+            if num_read == 0 {
+                // the last character if the signal ends without a space
+                let character = this.decoder.finish().flatten();
+                return Poll::Ready(character.map(Ok));
+            }
+            *this.position = 0;
+            *this.length = num_read;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{
+        FutureExt,
+        TryStreamExt,
+    };
+
+    use super::{
+        CwDecoder,
+        CwStream,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+            combinators::Scanner,
+        },
+        modem::cw::CwEncoder,
+    };
+
+    const SAMPLE_RATE: f32 = 8000.0;
+    const FREQUENCY: f32 = 700.0;
+    const TEXT: &str = "CQ CQ DE N0CALL/P K 599 73";
+
+    /// Keys `TEXT` at `words_per_minute` and adds some noise.
+    fn audio(words_per_minute: f32) -> Vec<f32> {
+        let mut signal = vec![];
+        let mut encoder = CwEncoder::new(words_per_minute, FREQUENCY, SAMPLE_RATE);
+        encoder.push_space(5, &mut signal);
+        encoder.push_text(TEXT, &mut signal);
+        encoder.push_space(5, &mut signal);
+
+        let mut noise_state = 0x1234_5678u32;
+        signal
+            .into_iter()
+            .map(|sample| {
+                noise_state = noise_state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (noise_state >> 8) as f32 / (1 << 24) as f32 - 0.5;
+                sample.re + noise
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_adapts_to_the_speed() {
+        for (words_per_minute, initial_words_per_minute) in [(12.0, 20.0), (35.0, 20.0)] {
+            let mut decoder = CwDecoder::new(FREQUENCY, SAMPLE_RATE)
+                .with_words_per_minute(initial_words_per_minute);
+            let decoded = audio(words_per_minute)
+                .into_iter()
+                .filter_map(|sample| decoder.scan(sample))
+                .collect::<String>();
+
+            assert_eq!(decoded.trim_end(), TEXT, "{words_per_minute} WPM");
+            assert!(
+                (decoder.words_per_minute() - words_per_minute).abs() < 0.1 * words_per_minute,
+                "estimated {} WPM instead of {words_per_minute}",
+                decoder.words_per_minute()
+            );
+        }
+    }
+
+    #[test]
+    fn it_decodes_a_stream() {
+        let audio = audio(25.0);
+        // cut off in the middle of the space after the last character
+        let audio = &audio[..audio.len() - 5 * 384 - 500];
+
+        let stream =
+            CwStream::<_, f32>::new(Cursor::new(audio).with_sample_rate(SAMPLE_RATE), FREQUENCY);
+        let decoded = stream
+            .try_collect::<String>()
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(decoded, TEXT);
+    }
+}
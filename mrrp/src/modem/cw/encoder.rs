@@ -0,0 +1,111 @@
+use std::f32::consts::TAU;
+
+use num_complex::Complex;
+
+use crate::{
+    buf::SampleBufMut,
+    modem::cw::{
+        DEFAULT_RISE_TIME,
+        Envelope,
+        dit_duration,
+        morse,
+    },
+};
+
+/// Keys a tone from text.
+///
+/// The output are complex samples of a tone at `frequency`, which is 0 for a
+/// keyed carrier on baseband. For audio, use the real part of the samples.
+#[derive(Clone, Copy, Debug)]
+pub struct CwEncoder {
+    sample_rate: f32,
+    /// Length of a dit in samples.
+    dit_length: usize,
+    envelope: Envelope,
+    phase: f32,
+    phase_increment: f32,
+    /// Whether a character was sent since the last word space.
+    in_word: bool,
+}
+
+impl CwEncoder {
+    pub fn new(words_per_minute: f32, frequency: f32, sample_rate: f32) -> Self {
+        assert!(words_per_minute > 0.0, "invalid speed");
+
+        Self {
+            sample_rate,
+            dit_length: ((dit_duration(words_per_minute) * sample_rate).round() as usize).max(1),
+            envelope: Envelope::default(),
+            phase: 0.0,
+            phase_increment: TAU * frequency / sample_rate,
+            in_word: false,
+        }
+        .with_rise_time(DEFAULT_RISE_TIME)
+    }
+
+    /// Sets the rise and fall time of the envelope in seconds. It should be
+    /// shorter than a dit.
+    pub fn with_rise_time(mut self, rise_time: f32) -> Self {
+        assert!(rise_time >= 0.0, "invalid rise time");
+        self.envelope
+            .set_rise_length((rise_time * self.sample_rate).round() as usize);
+        self
+    }
+
+    fn push_samples<B>(&mut self, key_down: bool, num_dits: usize, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        for _ in 0..num_dits * self.dit_length {
+            let envelope = self.envelope.next(key_down);
+            output.put_sample(Complex::from_polar(envelope, self.phase));
+            self.phase = (self.phase + self.phase_increment) % TAU;
+        }
+    }
+
+    /// Keeps the key up for `num_dits` dits, e.g. before the first character.
+    pub fn push_space<B>(&mut self, num_dits: usize, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        self.push_samples(false, num_dits, output);
+    }
+
+    /// Sends a character, followed by the space between characters.
+    /// Characters without a code are skipped.
+    pub fn push_char<B>(&mut self, character: char, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        if character.is_whitespace() {
+            // the 3 dits after the last character make it 7
+            if self.in_word {
+                self.push_space(4, output);
+                self.in_word = false;
+            }
+            return;
+        }
+
+        let Some(code) = morse::encode(character)
+        else {
+            return;
+        };
+
+        for element in code.chars() {
+            let num_dits = if element == '-' { 3 } else { 1 };
+            self.push_samples(true, num_dits, output);
+            self.push_space(1, output);
+        }
+        self.push_space(2, output);
+        self.in_word = true;
+    }
+
+    pub fn push_text<B>(&mut self, text: &str, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        for character in text.chars() {
+            self.push_char(character, output);
+        }
+    }
+}
@@ -0,0 +1,555 @@
+//! CW (Morse) keying and decoding
+//!
+//! [`CwKeyer`] turns key presses into a keying envelope. Key events are sent
+//! through a [`KeyInput`] from any task or thread, while the keyer runs as a
+//! [`SignalGenerator`] wherever the samples are needed, e.g. in an audio
+//! output.
+//!
+//! The envelope rises and falls with raised-cosine edges, which keeps the
+//! keying clicks out of the neighbouring channels. It can be multiplied with a
+//! tone for a sidetone (see [`Sidetone`]), or used as the amplitude of a
+//! baseband signal for transmission.
+//!
+//! [`CwEncoder`] keys a tone from text with the same envelope, and
+//! [`CwDecoder`] reads text from a received tone, adapting to the sender's
+//! speed.
+
+mod decoder;
+mod encoder;
+pub mod morse;
+
+use std::{
+    f32::consts::PI,
+    str::FromStr,
+};
+
+pub use decoder::{
+    CwDecoder,
+    CwStream,
+    DEFAULT_BANDWIDTH,
+};
+pub use encoder::CwEncoder;
+use tokio::sync::mpsc;
+
+use crate::{
+    io::GetSampleRate,
+    source::{
+        SignalGenerator,
+        SineWave,
+    },
+};
+
+/// Default rise and fall time of the envelope, in seconds.
+pub const DEFAULT_RISE_TIME: f32 = 0.005;
+
+/// Length of a dit in seconds at a speed (PARIS timing).
+#[inline]
+pub fn dit_duration(words_per_minute: f32) -> f32 {
+    1.2 / words_per_minute
+}
+
+/// Keying envelope with raised-cosine edges.
+#[derive(Clone, Copy, Debug, Default)]
+struct Envelope {
+    /// Length of an edge in samples.
+    rise_length: usize,
+
+    /// Position on the edge, from 0 (key up) to `rise_length` (key down).
+    ramp: usize,
+}
+
+impl Envelope {
+    fn set_rise_length(&mut self, rise_length: usize) {
+        self.rise_length = rise_length;
+        self.ramp = self.ramp.min(rise_length);
+    }
+
+    fn next(&mut self, key_down: bool) -> f32 {
+        if key_down {
+            self.ramp = (self.ramp + 1).min(self.rise_length);
+        }
+        else {
+            self.ramp = self.ramp.saturating_sub(1);
+        }
+
+        if self.rise_length == 0 {
+            if key_down { 1.0 } else { 0.0 }
+        }
+        else {
+            0.5 - 0.5 * (PI * self.ramp as f32 / self.rise_length as f32).cos()
+        }
+    }
+}
+
+/// How the keys are turned into Morse elements.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeyerMode {
+    /// The envelope follows the key directly.
+    #[default]
+    Straight,
+
+    /// Iambic keyer. Holding a paddle repeats its element, squeezing both
+    /// alternates between them. Releasing both paddles stops after the
+    /// current element.
+    IambicA,
+
+    /// Like [`IambicA`][Self::IambicA], but if both paddles were squeezed
+    /// during an element, the opposite element is sent after it, even if the
+    /// paddles are released in the meantime.
+    IambicB,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("No such keyer mode: {0}")]
+pub struct ParseKeyerModeError(String);
+
+impl FromStr for KeyerMode {
+    type Err = ParseKeyerModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "straight" => Ok(Self::Straight),
+            "iambic-a" => Ok(Self::IambicA),
+            "iambic-b" | "iambic" => Ok(Self::IambicB),
+            _ => Err(ParseKeyerModeError(s.to_owned())),
+        }
+    }
+}
+
+/// A key of the keyer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// Keys the transmitter for as long as it's held, in every mode.
+    Straight,
+
+    /// Dit paddle. In [`KeyerMode::Straight`] this works like a straight key.
+    Dit,
+
+    /// Dah paddle. In [`KeyerMode::Straight`] this works like a straight key.
+    Dah,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyEvent {
+    Pressed(Key),
+    Released(Key),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("The CW keyer was dropped")]
+pub struct KeyerDropped;
+
+/// Sends key events to a [`CwKeyer`].
+#[derive(Clone, Debug)]
+pub struct KeyInput {
+    sender: mpsc::UnboundedSender<KeyEvent>,
+}
+
+impl KeyInput {
+    pub fn send(&self, event: KeyEvent) -> Result<(), KeyerDropped> {
+        self.sender.send(event).map_err(|_| KeyerDropped)
+    }
+
+    #[inline]
+    pub fn press(&self, key: Key) -> Result<(), KeyerDropped> {
+        self.send(KeyEvent::Pressed(key))
+    }
+
+    #[inline]
+    pub fn release(&self, key: Key) -> Result<(), KeyerDropped> {
+        self.send(KeyEvent::Released(key))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Element {
+    Dit,
+    Dah,
+}
+
+/// The element that is being sent, followed by the space of one dit.
+#[derive(Clone, Copy, Debug)]
+struct Sending {
+    element: Element,
+    mark_remaining: usize,
+    space_remaining: usize,
+}
+
+/// Generates a CW keying envelope from key events.
+///
+/// The envelope is between 0 (key up) and 1 (key down). Key events are read
+/// from the queue every sample, so the timing is as precise as the samples are
+/// pulled from the keyer.
+#[derive(Debug)]
+pub struct CwKeyer {
+    mode: KeyerMode,
+    sample_rate: f32,
+    words_per_minute: f32,
+    rise_time: f32,
+
+    /// Length of a dit in samples.
+    dit_length: usize,
+
+    envelope: Envelope,
+
+    events: mpsc::UnboundedReceiver<KeyEvent>,
+    key_input: KeyInput,
+
+    straight: bool,
+    dit: bool,
+    dah: bool,
+    dit_memory: bool,
+    dah_memory: bool,
+    sending: Option<Sending>,
+    last_element: Option<Element>,
+}
+
+impl CwKeyer {
+    pub fn new(mode: KeyerMode, words_per_minute: f32, sample_rate: f32) -> Self {
+        assert!(words_per_minute > 0.0, "invalid speed");
+
+        let (sender, events) = mpsc::unbounded_channel();
+
+        let mut keyer = Self {
+            mode,
+            sample_rate,
+            words_per_minute,
+            rise_time: DEFAULT_RISE_TIME,
+            dit_length: 0,
+            envelope: Envelope::default(),
+            events,
+            key_input: KeyInput { sender },
+            straight: false,
+            dit: false,
+            dah: false,
+            dit_memory: false,
+            dah_memory: false,
+            sending: None,
+            last_element: None,
+        };
+        keyer.update_lengths();
+        keyer
+    }
+
+    /// Sets the rise and fall time of the envelope in seconds. With 0 the
+    /// envelope is a hard on-off keying.
+    pub fn with_rise_time(mut self, rise_time: f32) -> Self {
+        assert!(rise_time >= 0.0, "invalid rise time");
+        self.rise_time = rise_time;
+        self.update_lengths();
+        self
+    }
+
+    /// Returns a handle through which key events are sent to this keyer.
+    #[inline]
+    pub fn key_input(&self) -> KeyInput {
+        self.key_input.clone()
+    }
+
+    #[inline]
+    pub fn mode(&self) -> KeyerMode {
+        self.mode
+    }
+
+    #[inline]
+    pub fn set_mode(&mut self, mode: KeyerMode) {
+        self.mode = mode;
+    }
+
+    #[inline]
+    pub fn words_per_minute(&self) -> f32 {
+        self.words_per_minute
+    }
+
+    /// Sets the speed. A dit is 1.2 / `words_per_minute` seconds long (PARIS
+    /// timing). An element that is being sent keeps its length.
+    pub fn set_words_per_minute(&mut self, words_per_minute: f32) {
+        assert!(words_per_minute > 0.0, "invalid speed");
+        self.words_per_minute = words_per_minute;
+        self.update_lengths();
+    }
+
+    /// Whether the key is down, ignoring the envelope's edges.
+    #[inline]
+    pub fn is_key_down(&self) -> bool {
+        self.straight
+            || (self.mode == KeyerMode::Straight && (self.dit || self.dah))
+            || self
+                .sending
+                .is_some_and(|sending| sending.mark_remaining > 0)
+    }
+
+    /// Wraps the keyer into a generator that keys a tone of `frequency` (in
+    /// Hz).
+    pub fn sidetone(self, frequency: f32) -> Sidetone {
+        Sidetone {
+            tone: SineWave::new(frequency, self.sample_rate),
+            keyer: self,
+        }
+    }
+
+    fn update_lengths(&mut self) {
+        self.dit_length =
+            ((dit_duration(self.words_per_minute) * self.sample_rate).round() as usize).max(1);
+        self.envelope
+            .set_rise_length((self.rise_time * self.sample_rate).round() as usize);
+    }
+
+    fn handle_event(&mut self, event: KeyEvent) {
+        let sending = self.sending.map(|sending| sending.element);
+
+        match event {
+            KeyEvent::Pressed(Key::Straight) => self.straight = true,
+            KeyEvent::Released(Key::Straight) => self.straight = false,
+            KeyEvent::Pressed(Key::Dit) => {
+                self.dit = true;
+                // a quick tap during a dah isn't lost
+                if sending == Some(Element::Dah) {
+                    self.dit_memory = true;
+                }
+            }
+            KeyEvent::Released(Key::Dit) => self.dit = false,
+            KeyEvent::Pressed(Key::Dah) => {
+                self.dah = true;
+                if sending == Some(Element::Dit) {
+                    self.dah_memory = true;
+                }
+            }
+            KeyEvent::Released(Key::Dah) => self.dah = false,
+        }
+    }
+
+    /// Picks the next element after the last one has ended.
+    fn next_element(&mut self) -> Option<Element> {
+        let dit = self.dit || self.dit_memory;
+        let dah = self.dah || self.dah_memory;
+
+        let element = match (dit, dah) {
+            (true, true) if self.last_element == Some(Element::Dit) => Element::Dah,
+            (true, _) => Element::Dit,
+            (false, true) => Element::Dah,
+            (false, false) => {
+                self.last_element = None;
+                return None;
+            }
+        };
+
+        match element {
+            Element::Dit => self.dit_memory = false,
+            Element::Dah => self.dah_memory = false,
+        }
+
+        Some(element)
+    }
+
+    /// Advances the iambic keyer by one sample and returns whether the key is
+    /// down.
+    fn step_iambic(&mut self) -> bool {
+        if self.sending.is_none() {
+            self.sending = self.next_element().map(|element| {
+                Sending {
+                    element,
+                    mark_remaining: match element {
+                        Element::Dit => self.dit_length,
+                        Element::Dah => 3 * self.dit_length,
+                    },
+                    space_remaining: self.dit_length,
+                }
+            });
+        }
+
+        let Some(sending) = &mut self.sending
+        else {
+            return false;
+        };
+
+        if self.mode == KeyerMode::IambicB && self.dit && self.dah {
+            match sending.element {
+                Element::Dit => self.dah_memory = true,
+                Element::Dah => self.dit_memory = true,
+            }
+        }
+
+        let key_down = if sending.mark_remaining > 0 {
+            sending.mark_remaining -= 1;
+            true
+        }
+        else {
+            sending.space_remaining -= 1;
+            false
+        };
+
+        if sending.mark_remaining == 0 && sending.space_remaining == 0 {
+            self.last_element = Some(sending.element);
+            self.sending = None;
+        }
+
+        key_down
+    }
+}
+
+impl GetSampleRate for CwKeyer {
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+}
+
+impl SignalGenerator for CwKeyer {
+    type Sample = f32;
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.update_lengths();
+    }
+
+    fn next(&mut self) -> f32 {
+        while let Ok(event) = self.events.try_recv() {
+            self.handle_event(event);
+        }
+
+        let key_down = match self.mode {
+            KeyerMode::Straight => self.straight || self.dit || self.dah,
+            KeyerMode::IambicA | KeyerMode::IambicB => self.step_iambic() || self.straight,
+        };
+
+        self.envelope.next(key_down)
+    }
+}
+
+/// A tone keyed by a [`CwKeyer`].
+#[derive(Debug)]
+pub struct Sidetone {
+    keyer: CwKeyer,
+    tone: SineWave,
+}
+
+impl Sidetone {
+    #[inline]
+    pub fn keyer(&self) -> &CwKeyer {
+        &self.keyer
+    }
+
+    #[inline]
+    pub fn keyer_mut(&mut self) -> &mut CwKeyer {
+        &mut self.keyer
+    }
+
+    #[inline]
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.tone.set_frequency(frequency);
+    }
+}
+
+impl GetSampleRate for Sidetone {
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.keyer.sample_rate
+    }
+}
+
+impl SignalGenerator for Sidetone {
+    type Sample = f32;
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.keyer.set_sample_rate(sample_rate);
+        self.tone.set_sample_rate(sample_rate);
+    }
+
+    #[inline]
+    fn next(&mut self) -> f32 {
+        self.keyer.next() * self.tone.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CwKeyer,
+        Key,
+        KeyerMode,
+    };
+    use crate::source::SignalGenerator;
+
+    /// 1000 Hz at 10 WPM gives dits of 120 samples.
+    const SAMPLE_RATE: f32 = 1000.0;
+    const DIT: usize = 120;
+
+    /// Runs the keyer and returns the lengths of alternating marks and spaces,
+    /// starting with a mark.
+    fn run_lengths(keyer: &mut CwKeyer, num_samples: usize) -> Vec<usize> {
+        let mut lengths = vec![];
+        let mut key_down = true;
+        let mut length = 0;
+
+        for _ in 0..num_samples {
+            let envelope = keyer.next();
+            if (envelope > 0.5) == key_down {
+                length += 1;
+            }
+            else {
+                lengths.push(length);
+                key_down = !key_down;
+                length = 1;
+            }
+        }
+
+        lengths.push(length);
+        lengths
+    }
+
+    #[test]
+    fn it_shapes_the_envelope() {
+        let mut keyer = CwKeyer::new(KeyerMode::Straight, 10.0, SAMPLE_RATE).with_rise_time(0.01);
+        let input = keyer.key_input();
+
+        input.press(Key::Straight).unwrap();
+        let rising = (0..20).map(|_| keyer.next()).collect::<Vec<_>>();
+        assert!(rising.windows(2).all(|w| w[0] <= w[1]));
+        assert!(rising[0] < 0.1);
+        assert_eq!(rising[10], 1.0);
+
+        input.release(Key::Straight).unwrap();
+        let falling = (0..20).map(|_| keyer.next()).collect::<Vec<_>>();
+        assert!(falling.windows(2).all(|w| w[0] >= w[1]));
+        assert_eq!(falling[10], 0.0);
+    }
+
+    #[test]
+    fn held_paddles_repeat_and_alternate() {
+        let mut keyer = CwKeyer::new(KeyerMode::IambicA, 10.0, SAMPLE_RATE).with_rise_time(0.0);
+        let input = keyer.key_input();
+
+        input.press(Key::Dit).unwrap();
+        assert_eq!(run_lengths(&mut keyer, 4 * DIT), [DIT, DIT, DIT, DIT]);
+
+        input.press(Key::Dah).unwrap();
+        input.release(Key::Dit).unwrap();
+        assert_eq!(run_lengths(&mut keyer, 4 * DIT), [3 * DIT, DIT]);
+
+        // squeezed, the dah is followed by a dit
+        input.press(Key::Dit).unwrap();
+        assert_eq!(run_lengths(&mut keyer, 6 * DIT), [DIT, DIT, 3 * DIT, DIT]);
+    }
+
+    #[test]
+    fn mode_b_completes_the_squeeze() {
+        for (mode, expected) in [
+            (KeyerMode::IambicA, &[DIT, 9 * DIT][..]),
+            (KeyerMode::IambicB, &[DIT, DIT, 3 * DIT, 5 * DIT][..]),
+        ] {
+            let mut keyer = CwKeyer::new(mode, 10.0, SAMPLE_RATE).with_rise_time(0.0);
+            let input = keyer.key_input();
+
+            input.press(Key::Dit).unwrap();
+            input.press(Key::Dah).unwrap();
+            keyer.next();
+            input.release(Key::Dit).unwrap();
+            input.release(Key::Dah).unwrap();
+
+            let mut lengths = run_lengths(&mut keyer, 10 * DIT - 1);
+            lengths[0] += 1;
+            assert_eq!(lengths, expected, "{mode:?}");
+        }
+    }
+}
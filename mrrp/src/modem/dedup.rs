@@ -0,0 +1,199 @@
+//! Deduplication of decoded packets.
+//!
+//! Receivers often decode the same packet more than once, e.g. when a
+//! demodulator re-triggers on the same burst, or a station repeats a packet.
+//! [`Dedup`] drops packets whose key was seen recently. Keying by the packet
+//! content removes duplicates, and keying by the sender rate-limits each
+//! sender.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use futures_util::Stream;
+use pin_project_lite::pin_project;
+
+use crate::util::clock::{
+    Clock,
+    TokioClock,
+};
+
+pin_project! {
+    /// Drops items whose key was passed on less than `window` ago.
+    ///
+    /// The key is computed by a closure. Items for which it returns `None`
+    /// (e.g. errors) are always passed on. The window starts when an item is
+    /// passed on, so a key that keeps repeating is passed on once per window.
+    #[derive(Debug)]
+    pub struct Dedup<St, F, K, C = TokioClock> {
+        #[pin]
+        stream: St,
+        key: F,
+        window: Duration,
+        seen: HashMap<K, Instant>,
+        last_prune: Option<Instant>,
+        num_dropped: usize,
+        clock: C,
+    }
+}
+
+impl<St, F, K> Dedup<St, F, K, TokioClock> {
+    pub fn new(stream: St, window: Duration, key: F) -> Self {
+        Self::with_clock(stream, window, key, TokioClock)
+    }
+}
+
+impl<St, F, K, C> Dedup<St, F, K, C> {
+    /// Deduplicates using the given clock instead of the tokio timer.
+    pub fn with_clock(stream: St, window: Duration, key: F, clock: C) -> Self {
+        Self {
+            stream,
+            key,
+            window,
+            seen: HashMap::new(),
+            last_prune: None,
+            num_dropped: 0,
+            clock,
+        }
+    }
+
+    #[inline]
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Number of items dropped so far.
+    #[inline]
+    pub fn num_dropped(&self) -> usize {
+        self.num_dropped
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &St {
+        &self.stream
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> St {
+        self.stream
+    }
+}
+
+impl<St, F, K, C> Stream for Dedup<St, F, K, C>
+where
+    St: Stream,
+    F: FnMut(&St::Item) -> Option<K>,
+    K: Hash + Eq,
+    C: Clock,
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            let Some(item) = ready!(this.stream.as_mut().poll_next(cx))
+            else {
+                return Poll::Ready(None);
+            };
+
+            let Some(key) = (this.key)(&item)
+            else {
+                return Poll::Ready(Some(item));
+            };
+
+            let now = this.clock.now();
+            let window = *this.window;
+
+            // forget keys that are out of the window, so the map doesn't grow forever
+            if this
+                .last_prune
+                .is_none_or(|last_prune| now.saturating_duration_since(last_prune) > window)
+            {
+                this.seen
+                    .retain(|_, seen| now.saturating_duration_since(*seen) < window);
+                *this.last_prune = Some(now);
+            }
+
+            match this.seen.get_mut(&key) {
+                Some(seen) if now.saturating_duration_since(*seen) < window => {
+                    *this.num_dropped += 1;
+                }
+                Some(seen) => {
+                    *seen = now;
+                    return Poll::Ready(Some(item));
+                }
+                None => {
+                    this.seen.insert(key, now);
+                    return Poll::Ready(Some(item));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.stream.size_hint().1)
+    }
+}
+
+pub trait DedupExt: Stream {
+    /// Drops items whose key was passed on less than `window` ago. See
+    /// [`Dedup`].
+    #[inline]
+    fn dedup_by_key<F, K>(self, window: Duration, key: F) -> Dedup<Self, F, K>
+    where
+        F: FnMut(&Self::Item) -> Option<K>,
+        K: Hash + Eq,
+        Self: Sized,
+    {
+        Dedup::new(self, window, key)
+    }
+}
+
+impl<St: Stream> DedupExt for St {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::{
+        FutureExt,
+        StreamExt,
+        stream,
+    };
+
+    use super::Dedup;
+    use crate::util::clock::MockClock;
+
+    #[test]
+    fn it_drops_duplicates_within_the_window() {
+        let clock = MockClock::new();
+        let mut stream = Dedup::with_clock(
+            stream::iter([Ok(1), Ok(1), Err(()), Ok(2), Ok(1), Ok(1)]),
+            Duration::from_secs(1),
+            |item: &Result<u32, ()>| item.ok(),
+            clock.clone(),
+        );
+
+        let mut next = || stream.next().now_or_never().expect("test stream pending");
+        assert_eq!(next(), Some(Ok(1)));
+        assert_eq!(next(), Some(Err(())));
+        assert_eq!(next(), Some(Ok(2)));
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(next(), Some(Ok(1)));
+        assert_eq!(next(), None);
+        assert_eq!(stream.num_dropped(), 2);
+    }
+}
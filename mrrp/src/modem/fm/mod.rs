@@ -1,3 +1,5 @@
+mod stereo;
+
 use std::f32::consts::{
     PI,
     TAU,
@@ -5,8 +7,15 @@ use std::f32::consts::{
 
 use num_complex::Complex;
 use num_traits::Zero;
+pub use stereo::WbfmStereoDecoder;
 
-use crate::io::combinators::Scanner;
+use crate::io::{
+    GetSampleRate,
+    combinators::{
+        GroupDelay,
+        Scanner,
+    },
+};
 
 /// https://wirelesspi.com/frequency-modulation-fm-and-demodulation-using-dsp-techniques/
 #[derive(Clone, Copy, Debug)]
@@ -34,6 +43,13 @@ impl Scanner<Complex<f32>> for DifferentiateAndAccessPhase {
     }
 }
 
+impl GroupDelay for DifferentiateAndAccessPhase {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.5
+    }
+}
+
 /// https://wirelesspi.com/frequency-modulation-fm-and-demodulation-using-dsp-techniques/
 /// buggy
 #[derive(Clone, Copy, Debug)]
@@ -71,6 +87,13 @@ impl Scanner<Complex<f32>> for AccessPhaseAndDifferentiate {
     }
 }
 
+impl GroupDelay for AccessPhaseAndDifferentiate {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.5
+    }
+}
+
 /// [Slide 12](https://cci.usc.edu/wp-content/uploads/2017/09/CLASS-6-FM-modulation.pdf)
 #[derive(Clone, Copy, Debug)]
 pub struct DifferentiateAndDivide {
@@ -115,35 +138,294 @@ impl Scanner<Complex<f32>> for DifferentiateAndDivide {
     }
 }
 
+impl GroupDelay for DifferentiateAndDivide {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        // central difference
+        1.0
+    }
+}
+
 pub type FmDemodulator = DifferentiateAndDivide;
 
+/// Common FM deviation presets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FmPreset {
+    /// Narrowband FM for 12.5 kHz channels: 2.5 kHz deviation.
+    Narrow,
+
+    /// Narrowband FM for 25 kHz channels: 5 kHz deviation.
+    Standard,
+
+    /// Broadcast FM: 75 kHz deviation.
+    Broadcast,
+}
+
+impl FmPreset {
+    /// Peak frequency deviation in Hz.
+    pub fn frequency_deviation(&self) -> f32 {
+        match self {
+            FmPreset::Narrow => 2_500.0,
+            FmPreset::Standard => 5_000.0,
+            FmPreset::Broadcast => 75_000.0,
+        }
+    }
+}
+
+/// Time constants of the pre- and de-emphasis of broadcast FM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EmphasisPreset {
+    /// 50 µs, used in Europe, Australia and most other regions.
+    Europe,
+
+    /// 75 µs, used in the Americas and South Korea.
+    Americas,
+}
+
+impl EmphasisPreset {
+    /// Time constant in seconds.
+    pub const fn time_constant(&self) -> f32 {
+        match self {
+            EmphasisPreset::Europe => 50e-6,
+            EmphasisPreset::Americas => 75e-6,
+        }
+    }
+}
+
+/// First-order pre-emphasis filter.
+///
+/// This is the exact inverse of a [`Deemphasis`] filter with the same time
+/// constant, i.e. it boosts high frequencies by 6 dB/octave above
+/// `1 / (2 pi time_constant)`.
+#[derive(Clone, Copy, Debug)]
+pub struct Preemphasis {
+    alpha: f32,
+    delayed: f32,
+}
+
+impl Preemphasis {
+    /// Time constant in seconds, e.g. `75e-6` for US broadcast FM.
+    pub fn new(sample_rate: f32, time_constant: f32) -> Self {
+        Self {
+            alpha: 1.0 - (-1.0 / (sample_rate * time_constant)).exp(),
+            delayed: 0.0,
+        }
+    }
+
+    pub fn from_preset(sample_rate: f32, preset: EmphasisPreset) -> Self {
+        Self::new(sample_rate, preset.time_constant())
+    }
+
+    /// Filter for samples of `stream`.
+    pub fn for_stream<R: GetSampleRate>(stream: &R, time_constant: f32) -> Self {
+        Self::new(stream.sample_rate(), time_constant)
+    }
+}
+
+impl Scanner<f32> for Preemphasis {
+    type Output = f32;
+
+    #[inline]
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        let output = (sample - (1.0 - self.alpha) * self.delayed) / self.alpha;
+        self.delayed = sample;
+        output
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.delayed = 0.0;
+        None
+    }
+}
+
+impl GroupDelay for Preemphasis {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// First-order de-emphasis filter.
+///
+/// A single-pole low-pass that undoes [`Preemphasis`] with the same time
+/// constant.
+#[derive(Clone, Copy, Debug)]
+pub struct Deemphasis {
+    alpha: f32,
+    output: f32,
+}
+
+impl Deemphasis {
+    /// Time constant in seconds, e.g. `75e-6` for US broadcast FM.
+    pub fn new(sample_rate: f32, time_constant: f32) -> Self {
+        Self {
+            alpha: 1.0 - (-1.0 / (sample_rate * time_constant)).exp(),
+            output: 0.0,
+        }
+    }
+
+    pub fn from_preset(sample_rate: f32, preset: EmphasisPreset) -> Self {
+        Self::new(sample_rate, preset.time_constant())
+    }
+
+    /// Filter for samples of `stream`.
+    pub fn for_stream<R: GetSampleRate>(stream: &R, time_constant: f32) -> Self {
+        Self::new(stream.sample_rate(), time_constant)
+    }
+}
+
+impl Scanner<f32> for Deemphasis {
+    type Output = f32;
+
+    #[inline]
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        self.output += self.alpha * (sample - self.output);
+        self.output
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.output = 0.0;
+        None
+    }
+}
+
+impl GroupDelay for Deemphasis {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct FmModulator {
-    delay: f32,
+    phase: f32,
     frequency_modulation_factor: f32,
-    carrier_frequency: f32,
+    sample_rate: f32,
+    pre_emphasis: Option<Preemphasis>,
 }
 
 impl FmModulator {
     pub fn new(sample_rate: f32, frequency_deviation: f32) -> Self {
         Self {
-            delay: 0.0,
-            frequency_modulation_factor: sample_rate / (TAU * frequency_deviation),
-            carrier_frequency: 0.0,
+            phase: 0.0,
+            frequency_modulation_factor: TAU * frequency_deviation / sample_rate,
+            sample_rate,
+            pre_emphasis: None,
         }
     }
+
+    pub fn from_preset(sample_rate: f32, preset: FmPreset) -> Self {
+        Self::new(sample_rate, preset.frequency_deviation())
+    }
+
+    /// Apply pre-emphasis with the given time constant (in seconds) before
+    /// modulating.
+    pub fn with_pre_emphasis(mut self, time_constant: f32) -> Self {
+        self.pre_emphasis = Some(Preemphasis::new(self.sample_rate, time_constant));
+        self
+    }
+
+    #[inline]
+    pub fn frequency_deviation(&self) -> f32 {
+        self.frequency_modulation_factor * self.sample_rate / TAU
+    }
+
+    #[inline]
+    pub fn set_frequency_deviation(&mut self, frequency_deviation: f32) {
+        self.frequency_modulation_factor = TAU * frequency_deviation / self.sample_rate;
+    }
 }
 
 impl Scanner<f32> for FmModulator {
     type Output = Complex<f32>;
 
     fn scan(&mut self, sample: f32) -> Self::Output {
-        let f = self.delay + self.frequency_modulation_factor * sample;
-        self.delay = f;
-        Complex {
-            re: 0.0,
-            im: f + self.carrier_frequency,
+        let sample = if let Some(pre_emphasis) = &mut self.pre_emphasis {
+            pre_emphasis.scan(sample)
+        }
+        else {
+            sample
+        };
+
+        // keep the phase wrapped, otherwise we lose precision over time
+        self.phase =
+            (self.phase + self.frequency_modulation_factor * sample + PI).rem_euclid(TAU) - PI;
+        Complex::from_polar(1.0, self.phase)
+    }
+}
+
+impl GroupDelay for FmModulator {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use approx::assert_abs_diff_eq;
+
+    use super::{
+        Deemphasis,
+        EmphasisPreset,
+        FmDemodulator,
+        FmModulator,
+        FmPreset,
+        Preemphasis,
+    };
+    use crate::io::combinators::Scanner;
+
+    #[test]
+    fn demodulator_recovers_modulated_audio() {
+        let sample_rate = 48_000.0;
+
+        for preset in [FmPreset::Narrow, FmPreset::Standard] {
+            let deviation = preset.frequency_deviation();
+            let mut modulator = FmModulator::from_preset(sample_rate, preset);
+            let mut demodulator = FmDemodulator::new(sample_rate, deviation);
+
+            let audio = (0..2000)
+                .map(|i| 0.5 * (TAU * 440.0 * i as f32 / sample_rate).sin())
+                .collect::<Vec<_>>();
+            let demodulated = audio
+                .iter()
+                .map(|sample| demodulator.scan(modulator.scan(*sample)))
+                .collect::<Vec<_>>();
+
+            // the demodulator differentiates over two samples, so its output is
+            // the mean of the current and previous sample.
+            for (expected, actual) in audio
+                .windows(2)
+                .map(|pair| 0.5 * (pair[0] + pair[1]))
+                .zip(&demodulated[1..])
+                .skip(2)
+            {
+                assert_abs_diff_eq!(expected, *actual, epsilon = 0.02);
+            }
+        }
+    }
+
+    #[test]
+    fn de_emphasis_undoes_pre_emphasis() {
+        let sample_rate = 48_000.0;
+        let mut pre_emphasis = Preemphasis::from_preset(sample_rate, EmphasisPreset::Americas);
+        let mut de_emphasis = Deemphasis::from_preset(sample_rate, EmphasisPreset::Americas);
+
+        for i in 0..1000 {
+            let sample = (TAU * 5000.0 * i as f32 / sample_rate).sin();
+            let output = de_emphasis.scan(pre_emphasis.scan(sample));
+            assert_abs_diff_eq!(sample, output, epsilon = 1e-4);
         }
-        .exp()
+
+        // a tone at the corner frequency is attenuated by 3 dB
+        let corner_frequency = 1.0 / (TAU * EmphasisPreset::Europe.time_constant());
+        let mut de_emphasis = Deemphasis::from_preset(sample_rate, EmphasisPreset::Europe);
+        let peak = (0..4800)
+            .map(|i| de_emphasis.scan((TAU * corner_frequency * i as f32 / sample_rate).sin()))
+            .skip(2400)
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert_abs_diff_eq!(peak, std::f32::consts::FRAC_1_SQRT_2, epsilon = 0.05);
     }
 }
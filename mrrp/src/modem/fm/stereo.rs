@@ -0,0 +1,310 @@
+//! Stereo decoding of broadcast FM.
+//!
+//! The demodulated signal of a stereo broadcast (the multiplex signal, or MPX)
+//! contains the sum of both channels up to 15 kHz, a 19 kHz pilot tone, and
+//! their difference on a suppressed 38 kHz carrier, which is in phase with the
+//! doubled pilot.
+
+use std::f32::consts::{
+    FRAC_1_SQRT_2,
+    PI,
+    TAU,
+};
+
+use num_complex::Complex;
+
+use crate::{
+    filter::{
+        design::{
+            Estimate,
+            FilterDesign,
+            Lowpass,
+            Normalize,
+            pm_remez::{
+                self,
+                pm_remez,
+            },
+        },
+        fir::FirFilter,
+    },
+    io::combinators::{
+        GroupDelay,
+        Scanner,
+    },
+    modem::fm::{
+        Deemphasis,
+        EmphasisPreset,
+    },
+};
+
+/// Frequency of the pilot tone in Hz.
+pub const PILOT_FREQUENCY: f32 = 19_000.0;
+
+/// Bandwidth of the audio channels in Hz.
+pub const AUDIO_BANDWIDTH: f32 = 15_000.0;
+
+/// Highest frequency of the multiplex signal in Hz, i.e. the upper edge of the
+/// L-R subcarrier.
+pub const MPX_BANDWIDTH: f32 = 2.0 * PILOT_FREQUENCY + AUDIO_BANDWIDTH;
+
+/// Default time constant of the de-emphasis in seconds.
+pub const DEFAULT_DE_EMPHASIS: f32 = EmphasisPreset::Europe.time_constant();
+
+/// Default bandwidth of the pilot PLL in Hz.
+pub const DEFAULT_PILOT_LOOP_BANDWIDTH: f32 = 20.0;
+
+/// Amplitude of the pilot relative to the full deviation. Stations use 8 to
+/// 10%.
+const NOMINAL_PILOT_LEVEL: f32 = 0.1;
+
+/// The decoder switches to stereo if the pilot is stronger than this.
+const PILOT_THRESHOLD: f32 = 0.03;
+
+/// Time constant of the pilot level measurement in seconds.
+const PILOT_LEVEL_TIME_CONSTANT: f32 = 0.05;
+
+/// Decodes stereo audio from the multiplex signal of a broadcast FM station.
+///
+/// The input is the output of an [`FmDemodulator`][super::FmDemodulator] with
+/// a deviation of 75 kHz, at a sample rate of at least twice the
+/// [`MPX_BANDWIDTH`], e.g. 240 kHz. The output are pairs of left and right
+/// samples at the same rate, which are usually decimated to an audio rate
+/// afterwards.
+///
+/// A PLL locks to the pilot, and its doubled phase demodulates the L-R
+/// subcarrier. Without a pilot both channels are the mono signal.
+#[derive(Clone, Debug)]
+pub struct WbfmStereoDecoder {
+    sample_rate: f32,
+    /// Proportional gain of the loop filter.
+    alpha: f32,
+    /// Integral gain of the loop filter.
+    beta: f32,
+    /// Phase of the pilot in radians.
+    phase: f32,
+    /// Frequency of the pilot in radians per sample.
+    frequency: f32,
+    level_alpha: f32,
+    pilot_level: f32,
+    /// Low-pass for L+R in the real part and L-R in the imaginary part, so
+    /// that both are delayed the same.
+    lowpass: FirFilter<Complex<f32>, f32>,
+    de_emphasis: Option<[Deemphasis; 2]>,
+}
+
+impl WbfmStereoDecoder {
+    /// Decoder with the [default de-emphasis][DEFAULT_DE_EMPHASIS] and the
+    /// [default loop bandwidth][DEFAULT_PILOT_LOOP_BANDWIDTH].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sample rate is too low for the multiplex signal.
+    pub fn new(sample_rate: f32) -> Result<Self, pm_remez::Error> {
+        assert!(
+            sample_rate >= 2.0 * MPX_BANDWIDTH,
+            "sample rate too low for the FM multiplex signal: {sample_rate}"
+        );
+
+        // passes the audio, and removes the pilot and everything above it
+        let lowpass = Lowpass {
+            passband_end: AUDIO_BANDWIDTH,
+            stopband_start: PILOT_FREQUENCY,
+            passband_tolerance: 0.01,
+            stopband_tolerance: 0.001,
+        }
+        .normalize(sample_rate);
+        let lowpass = pm_remez(lowpass, Estimate)?.fir_filter();
+
+        Ok(Self {
+            sample_rate,
+            alpha: 0.0,
+            beta: 0.0,
+            phase: 0.0,
+            frequency: TAU * PILOT_FREQUENCY / sample_rate,
+            level_alpha: 1.0 - (-1.0 / (sample_rate * PILOT_LEVEL_TIME_CONSTANT)).exp(),
+            pilot_level: 0.0,
+            lowpass,
+            de_emphasis: Some([Deemphasis::new(sample_rate, DEFAULT_DE_EMPHASIS); 2]),
+        }
+        .with_loop_bandwidth(DEFAULT_PILOT_LOOP_BANDWIDTH))
+    }
+
+    /// The loop bandwidth of the pilot PLL in Hz. A wider loop locks faster,
+    /// but lets through more noise.
+    pub fn with_loop_bandwidth(mut self, loop_bandwidth: f32) -> Self {
+        // critically damped second-order loop
+        let damping = FRAC_1_SQRT_2;
+        let theta = loop_bandwidth / self.sample_rate / (damping + 0.25 / damping);
+        let denominator = 1.0 + 2.0 * damping * theta + theta * theta;
+        self.alpha = 4.0 * damping * theta / denominator;
+        self.beta = 4.0 * theta * theta / denominator;
+        self
+    }
+
+    /// Apply de-emphasis with the given time constant (in seconds) to both
+    /// channels, e.g. `75e-6` for the US.
+    pub fn with_de_emphasis(mut self, time_constant: f32) -> Self {
+        self.de_emphasis = Some([Deemphasis::new(self.sample_rate, time_constant); 2]);
+        self
+    }
+
+    pub fn with_de_emphasis_preset(self, preset: EmphasisPreset) -> Self {
+        self.with_de_emphasis(preset.time_constant())
+    }
+
+    pub fn without_de_emphasis(mut self) -> Self {
+        self.de_emphasis = None;
+        self
+    }
+
+    /// Whether a pilot was found, i.e. the output is stereo.
+    #[inline]
+    pub fn is_stereo(&self) -> bool {
+        self.pilot_level > PILOT_THRESHOLD
+    }
+
+    /// Amplitude of the pilot, relative to the full deviation.
+    #[inline]
+    pub fn pilot_level(&self) -> f32 {
+        self.pilot_level
+    }
+
+    /// Frequency of the pilot the PLL is locked to, in Hz.
+    #[inline]
+    pub fn pilot_frequency(&self) -> f32 {
+        self.frequency * self.sample_rate / TAU
+    }
+
+    /// Turns the filtered sum and difference into left and right.
+    fn output(&mut self, filtered: Complex<f32>) -> (f32, f32) {
+        let sum = filtered.re;
+        let difference = if self.is_stereo() { filtered.im } else { 0.0 };
+        let (left, right) = (sum + difference, sum - difference);
+
+        match &mut self.de_emphasis {
+            Some([de_emphasis_left, de_emphasis_right]) => {
+                (de_emphasis_left.scan(left), de_emphasis_right.scan(right))
+            }
+            None => (left, right),
+        }
+    }
+}
+
+impl Scanner<f32> for WbfmStereoDecoder {
+    type Output = (f32, f32);
+
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        let (sin, cos) = self.phase.sin_cos();
+
+        // the pilot is a sine, so the product with the cosine averages to
+        // its phase error. its amplitude is known well enough, so that it
+        // doesn't need to be measured for normalizing.
+        let error = sample * cos / (0.5 * NOMINAL_PILOT_LEVEL);
+        self.pilot_level += self.level_alpha * (2.0 * sample * sin - self.pilot_level);
+
+        // sin(2 phase) is the subcarrier. the product has L-R at half its
+        // amplitude.
+        let subcarrier = 2.0 * sin * cos;
+        let filtered = self
+            .lowpass
+            .scan(Complex::new(sample, 2.0 * sample * subcarrier));
+
+        self.frequency += self.beta * error;
+        self.phase += self.frequency + self.alpha * error;
+        if self.phase > PI {
+            self.phase -= TAU;
+        }
+        else if self.phase < -PI {
+            self.phase += TAU;
+        }
+
+        self.output(filtered)
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        if let Some(filtered) = self.lowpass.finish() {
+            Some(self.output(filtered))
+        }
+        else {
+            self.phase = 0.0;
+            self.frequency = TAU * PILOT_FREQUENCY / self.sample_rate;
+            self.pilot_level = 0.0;
+            for de_emphasis in self.de_emphasis.iter_mut().flatten() {
+                de_emphasis.finish();
+            }
+            None
+        }
+    }
+}
+
+impl GroupDelay for WbfmStereoDecoder {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        self.lowpass.group_delay()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::WbfmStereoDecoder;
+    use crate::io::combinators::Scanner;
+
+    const SAMPLE_RATE: f32 = 240_000.0;
+
+    /// Multiplex signal with a 1 kHz tone on the left channel.
+    fn multiplex(with_pilot: bool) -> impl Iterator<Item = f32> {
+        (0..SAMPLE_RATE as usize / 2).map(move |i| {
+            let t = i as f32 / SAMPLE_RATE;
+            let left = 0.5 * (TAU * 1000.0 * t).sin();
+            let right = 0.0;
+            // start with an arbitrary phase, so that the PLL has to lock
+            let pilot = TAU * (19_000.0 * t).fract() + 1.0;
+            if with_pilot {
+                0.9 * (0.5 * (left + right) + 0.5 * (left - right) * (2.0 * pilot).sin())
+                    + 0.1 * pilot.sin()
+            }
+            else {
+                0.9 * 0.5 * (left + right)
+            }
+        })
+    }
+
+    fn rms(samples: impl Iterator<Item = f32>) -> f32 {
+        let (sum, count) = samples.fold((0.0, 0), |(sum, count), x| (sum + x * x, count + 1));
+        (sum / count as f32).sqrt()
+    }
+
+    #[test]
+    fn it_separates_channels() {
+        let mut decoder = WbfmStereoDecoder::new(SAMPLE_RATE)
+            .unwrap()
+            .without_de_emphasis();
+        let output = multiplex(true)
+            .map(|sample| decoder.scan(sample))
+            .collect::<Vec<_>>();
+
+        assert!(decoder.is_stereo());
+        assert!((decoder.pilot_level() - 0.1).abs() < 0.01);
+        assert!((decoder.pilot_frequency() - 19_000.0).abs() < 1.0);
+
+        // after the PLL locked
+        let locked = &output[output.len() - 24_000..];
+        let left = rms(locked.iter().map(|(left, _)| *left));
+        let right = rms(locked.iter().map(|(_, right)| *right));
+        assert!((left - 0.45 * 0.5f32.sqrt()).abs() < 0.02, "left: {left}");
+        assert!(right < 0.01 * left, "right: {right}");
+    }
+
+    #[test]
+    fn it_falls_back_to_mono() {
+        let mut decoder = WbfmStereoDecoder::new(SAMPLE_RATE).unwrap();
+        let output = multiplex(false)
+            .map(|sample| decoder.scan(sample))
+            .collect::<Vec<_>>();
+
+        assert!(!decoder.is_stereo());
+        assert!(output.iter().all(|(left, right)| left == right));
+    }
+}
@@ -1,6 +1,18 @@
 #[cfg(feature = "adsb")]
 pub mod adsb;
 
+pub mod afsk;
+pub mod am;
+pub mod ax25;
+pub mod burst;
+pub mod cw;
+pub mod dedup;
 pub mod dtmf;
 pub mod fm;
+pub mod pocsag;
+pub mod psk;
+pub mod race;
+pub mod rtty;
+pub mod ssb;
 pub mod sstv;
+pub mod wefax;
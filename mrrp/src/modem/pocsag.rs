@@ -0,0 +1,450 @@
+//! POCSAG pager protocol
+//!
+//! POCSAG is binary FSK with ±4.5 kHz deviation at 512, 1200 or 2400 baud. A
+//! binary 1 is sent as the lower frequency. The [`AfskDemodulator`] does the
+//! demodulation, with the tones placed around 0 Hz (see [`fsk_config`]).
+//!
+//! A transmission starts with a preamble of at least 576 alternating bits,
+//! followed by batches. A batch is a sync codeword followed by 8 frames of 2
+//! codewords each. Each codeword is 32 bits long, protected by a BCH(31, 21)
+//! code and an even parity bit.
+//!
+//! A message starts with an address codeword in the frame given by the lowest
+//! 3 bits of the address, followed by any number of message codewords, which
+//! can continue into the next batch. It ends with the next address or idle
+//! codeword.
+//!
+//! # References
+//!
+//! - ITU-R M.584-2, Codes and formats for radio paging
+//!
+//! [`AfskDemodulator`]: crate::modem::afsk::AfskDemodulator
+
+use crate::{
+    coding::{
+        Polarity,
+        sync::SyncWord,
+    },
+    io::combinators::Scanner,
+    modem::{
+        afsk::AfskConfig,
+        race::{
+            LockDetect,
+            LockState,
+        },
+    },
+};
+
+pub const SYNC_CODEWORD: u32 = 0x7cd2_15d8;
+pub const IDLE_CODEWORD: u32 = 0x7a89_c197;
+pub const DEVIATION: f32 = 4500.0;
+pub const PREAMBLE_LENGTH: usize = 576;
+pub const CODEWORDS_PER_BATCH: usize = 16;
+
+/// Generator polynomial of the BCH(31, 21) code:
+/// `x^10 + x^9 + x^8 + x^6 + x^5 + x^3 + 1`
+const BCH_GENERATOR: u32 = 0b111_0110_1001;
+
+/// Maximum number of wrong bits in a sync codeword.
+const MAX_SYNC_ERRORS: u32 = 2;
+
+const NUMERIC_CHARACTERS: &[u8; 16] = b"0123456789*U -)(";
+
+/// FSK parameters for POCSAG at `baud_rate`, for use with the
+/// [`AfskModulator`][crate::modem::afsk::AfskModulator] and
+/// [`AfskDemodulator`][crate::modem::afsk::AfskDemodulator] on complex
+/// baseband.
+pub fn fsk_config(baud_rate: f32) -> AfskConfig {
+    AfskConfig {
+        baud_rate,
+        mark: -DEVIATION,
+        space: DEVIATION,
+    }
+}
+
+/// A decoded page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// 21-bit address, also called RIC.
+    pub address: u32,
+
+    /// Function bits. By convention 0 is used for numeric and 3 for
+    /// alphanumeric messages.
+    pub function: u8,
+
+    /// Payload bits in the order they were received.
+    pub data: Vec<bool>,
+
+    /// Whether any codeword of the message had errors that could not be
+    /// corrected.
+    pub has_errors: bool,
+}
+
+impl Message {
+    /// Decodes the payload as numeric message, with 4 bits per character.
+    pub fn numeric(&self) -> String {
+        self.data
+            .chunks_exact(4)
+            .map(|bits| char::from(NUMERIC_CHARACTERS[usize::from(lsb_first(bits))]))
+            .collect::<String>()
+            .trim_end()
+            .to_owned()
+    }
+
+    /// Decodes the payload as alphanumeric message, with 7-bit ASCII
+    /// characters.
+    pub fn alphanumeric(&self) -> String {
+        self.data
+            .chunks_exact(7)
+            .map(|bits| char::from(lsb_first(bits)))
+            .filter(|c| *c != '\0')
+            .collect()
+    }
+
+    /// Decodes the payload based on the function bits.
+    pub fn text(&self) -> String {
+        if self.function == 0 {
+            self.numeric()
+        }
+        else {
+            self.alphanumeric()
+        }
+    }
+}
+
+fn lsb_first(bits: &[bool]) -> u8 {
+    bits.iter()
+        .rev()
+        .fold(0, |byte, bit| (byte << 1) | u8::from(*bit))
+}
+
+fn bch_remainder(mut value: u32) -> u32 {
+    // value has the 21 data bits in bits 30..10
+    for i in (10..31).rev() {
+        if value & (1 << i) != 0 {
+            value ^= BCH_GENERATOR << (i - 10);
+        }
+    }
+    value
+}
+
+/// Computes check bits and parity for the 21 data bits in bits 31..11 of
+/// `codeword`.
+pub fn encode_codeword(codeword: u32) -> u32 {
+    let data = codeword & 0xffff_f800;
+    let codeword = data | (bch_remainder(data >> 1) << 1);
+    codeword | (codeword.count_ones() & 1)
+}
+
+fn is_valid(codeword: u32) -> bool {
+    bch_remainder(codeword >> 1) == 0 && codeword.count_ones().is_multiple_of(2)
+}
+
+/// Corrects up to one wrong bit in a codeword.
+pub fn correct_codeword(codeword: u32) -> Option<u32> {
+    if is_valid(codeword) {
+        return Some(codeword);
+    }
+    (0..32)
+        .map(|i| codeword ^ (1 << i))
+        .find(|codeword| is_valid(*codeword))
+}
+
+/// Encodes messages into a bit stream, including preamble.
+#[derive(Clone, Debug, Default)]
+pub struct PocsagEncoder {
+    codewords: Vec<u32>,
+}
+
+impl PocsagEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a message with the given address, function bits and payload.
+    pub fn push(&mut self, address: u32, function: u8, data: &[bool]) {
+        let frame = (address & 7) as usize;
+        while self.codewords.len() % CODEWORDS_PER_BATCH != 2 * frame {
+            self.codewords.push(IDLE_CODEWORD);
+        }
+
+        self.codewords.push(encode_codeword(
+            ((address >> 3) & 0x3ffff) << 13 | u32::from(function & 3) << 11,
+        ));
+
+        for chunk in data.chunks(20) {
+            let bits = chunk
+                .iter()
+                .chain(std::iter::repeat(&false))
+                .take(20)
+                .fold(0, |bits, bit| (bits << 1) | u32::from(*bit));
+            self.codewords.push(encode_codeword(1 << 31 | bits << 11));
+        }
+    }
+
+    pub fn push_numeric(&mut self, address: u32, text: &str) {
+        let mut data = text
+            .bytes()
+            .filter_map(|c| NUMERIC_CHARACTERS.iter().position(|n| *n == c))
+            .flat_map(|index| (0..4).map(move |i| index & (1 << i) != 0))
+            .collect::<Vec<_>>();
+
+        // pad with spaces
+        while data.len() % 20 != 0 {
+            data.extend([false, false, true, true]);
+        }
+
+        self.push(address, 0, &data);
+    }
+
+    pub fn push_alphanumeric(&mut self, address: u32, text: &str) {
+        let data = text
+            .bytes()
+            .flat_map(|c| (0..7).map(move |i| c & (1 << i) != 0))
+            .collect::<Vec<_>>();
+        self.push(address, 3, &data);
+    }
+
+    /// Returns the bits of the transmission.
+    pub fn finish(mut self) -> Vec<bool> {
+        // terminate the last message
+        self.codewords.push(IDLE_CODEWORD);
+        while !self.codewords.len().is_multiple_of(CODEWORDS_PER_BATCH) {
+            self.codewords.push(IDLE_CODEWORD);
+        }
+
+        let mut bits = (0..PREAMBLE_LENGTH).map(|i| i % 2 == 0).collect::<Vec<_>>();
+        for batch in self.codewords.chunks(CODEWORDS_PER_BATCH) {
+            for codeword in std::iter::once(&SYNC_CODEWORD).chain(batch) {
+                bits.extend((0..32).rev().map(|i| codeword & (1 << i) != 0));
+            }
+        }
+        bits
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    /// Looking for a sync codeword.
+    Hunting,
+    /// Receiving the codewords of a batch.
+    Batch { index: usize },
+    /// Expecting the sync codeword of the next batch.
+    Sync,
+}
+
+/// POCSAG decoder.
+///
+/// Scans the demodulated bits and outputs complete messages. The polarity of
+/// the bits is detected from the sync codeword.
+#[derive(Clone, Debug)]
+pub struct PocsagDecoder {
+    sync_word: SyncWord,
+    state: State,
+    polarity: Polarity,
+    register: u32,
+    num_bits: usize,
+    message: Option<Message>,
+    /// Codewords without bit errors since the last sync.
+    num_valid_codewords: usize,
+}
+
+impl Default for PocsagDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PocsagDecoder {
+    pub fn new() -> Self {
+        Self {
+            sync_word: SyncWord::new(SYNC_CODEWORD.into(), 32)
+                .with_max_errors(MAX_SYNC_ERRORS)
+                .with_inverted(true),
+            state: State::Hunting,
+            polarity: Polarity::Normal,
+            register: 0,
+            num_bits: 0,
+            message: None,
+            num_valid_codewords: 0,
+        }
+    }
+
+    fn handle_codeword(&mut self, codeword: u32, index: usize) -> Option<Message> {
+        let Some(corrected) = correct_codeword(codeword)
+        else {
+            if let Some(message) = &mut self.message {
+                message.has_errors = true;
+                // keep the bit count right, so that characters stay aligned
+                message.data.extend([false; 20]);
+            }
+            return None;
+        };
+        if corrected == codeword {
+            self.num_valid_codewords += 1;
+        }
+        let codeword = corrected;
+
+        if codeword == IDLE_CODEWORD {
+            self.message.take()
+        }
+        else if codeword & (1 << 31) == 0 {
+            let address = ((codeword >> 13) & 0x3ffff) << 3 | (index / 2) as u32;
+            let function = ((codeword >> 11) & 3) as u8;
+            self.message.replace(Message {
+                address,
+                function,
+                data: vec![],
+                has_errors: false,
+            })
+        }
+        else {
+            if let Some(message) = &mut self.message {
+                message
+                    .data
+                    .extend((11..31).rev().map(|i| codeword & (1 << i) != 0));
+            }
+            None
+        }
+    }
+}
+
+impl Scanner<bool> for PocsagDecoder {
+    type Output = Option<Message>;
+
+    fn scan(&mut self, bit: bool) -> Self::Output {
+        match self.state {
+            State::Hunting => {
+                if self.sync_word.scan(bit) {
+                    self.polarity = self.sync_word.last_match().unwrap_or_default();
+                    self.state = State::Batch { index: 0 };
+                    self.num_bits = 0;
+                    self.num_valid_codewords = 0;
+                }
+                None
+            }
+            State::Batch { index } => {
+                self.register = (self.register << 1) | u32::from(self.polarity.apply(bit));
+                self.num_bits += 1;
+                if self.num_bits < 32 {
+                    return None;
+                }
+                self.num_bits = 0;
+
+                let message = self.handle_codeword(self.register, index);
+                self.state = if index + 1 == CODEWORDS_PER_BATCH {
+                    State::Sync
+                }
+                else {
+                    State::Batch { index: index + 1 }
+                };
+                message
+            }
+            State::Sync => {
+                self.register = (self.register << 1) | u32::from(self.polarity.apply(bit));
+                self.num_bits += 1;
+                if self.num_bits < 32 {
+                    return None;
+                }
+                self.num_bits = 0;
+
+                if (self.register ^ SYNC_CODEWORD).count_ones() <= MAX_SYNC_ERRORS {
+                    self.state = State::Batch { index: 0 };
+                    None
+                }
+                else {
+                    // end of transmission
+                    self.state = State::Hunting;
+                    self.sync_word.reset();
+                    self.message.take()
+                }
+            }
+        }
+    }
+}
+
+impl LockDetect for PocsagDecoder {
+    /// Locked after the sync codeword, and valid once a codeword without bit
+    /// errors follows. Noise often passes the error correction, but rarely
+    /// without errors.
+    fn lock_state(&self) -> LockState {
+        match self.state {
+            State::Hunting => LockState::Searching,
+            _ if self.num_valid_codewords > 0 => LockState::Valid,
+            _ => LockState::Locked,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        IDLE_CODEWORD,
+        PocsagDecoder,
+        PocsagEncoder,
+        SYNC_CODEWORD,
+        correct_codeword,
+        encode_codeword,
+        fsk_config,
+    };
+    use crate::{
+        io::combinators::Scanner,
+        modem::afsk::{
+            AfskDemodulator,
+            AfskModulator,
+        },
+    };
+
+    #[test]
+    fn it_encodes_valid_codewords() {
+        assert_eq!(encode_codeword(SYNC_CODEWORD), SYNC_CODEWORD);
+        assert_eq!(encode_codeword(IDLE_CODEWORD), IDLE_CODEWORD);
+        assert_eq!(correct_codeword(IDLE_CODEWORD ^ 0x400), Some(IDLE_CODEWORD));
+    }
+
+    #[test]
+    fn it_decodes_messages() {
+        let mut encoder = PocsagEncoder::new();
+        encoder.push_alphanumeric(1234567, "Hello, pager!");
+        encoder.push_numeric(42, "0123-456");
+        let mut bits = encoder.finish();
+
+        // flip a bit in the first message, and invert everything
+        bits[576 + 32 * 16 + 5] ^= true;
+        let bits = bits.into_iter().map(|bit| !bit);
+
+        let mut decoder = PocsagDecoder::new();
+        let messages = bits.filter_map(|bit| decoder.scan(bit)).collect::<Vec<_>>();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].address, 1234567);
+        assert_eq!(messages[0].alphanumeric(), "Hello, pager!");
+        assert!(!messages[0].has_errors);
+        assert_eq!(messages[1].address, 42);
+        assert_eq!(messages[1].numeric(), "0123-456");
+    }
+
+    #[test]
+    fn it_decodes_a_modulated_transmission() {
+        const SAMPLE_RATE: f32 = 48_000.0;
+
+        let mut encoder = PocsagEncoder::new();
+        encoder.push_alphanumeric(2000000, "Test");
+        let bits = encoder.finish();
+
+        let mut signal = vec![];
+        AfskModulator::new(fsk_config(1200.0), SAMPLE_RATE).modulate_bits(bits, &mut signal);
+
+        let mut demodulator = AfskDemodulator::new(fsk_config(1200.0), SAMPLE_RATE);
+        let mut decoder = PocsagDecoder::new();
+        let messages = signal
+            .into_iter()
+            .filter_map(|sample| demodulator.scan(sample))
+            .filter_map(|bit| decoder.scan(bit))
+            .collect::<Vec<_>>();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].address, 2000000);
+        assert_eq!(messages[0].text(), "Test");
+    }
+}
@@ -0,0 +1,476 @@
+//! PSK31 and PSK63
+//!
+//! Keyboard-to-keyboard mode with differential BPSK at 31.25 or 62.5 baud. A 0
+//! is sent as a phase reversal, during which the amplitude follows a cosine
+//! through zero, and a 1 as a steady carrier. Characters are
+//! [varicode][crate::coding::varicode] coded, and the transmitter idles with
+//! reversals.
+//!
+//! [`PskDemodulator`] mixes the signal down with a Costas loop, which tracks
+//! the carrier, and integrates over a symbol. It recovers the symbol timing
+//! from the amplitude dips of the reversals, and compares the phase of
+//! consecutive symbols.
+
+use std::{
+    f32::consts::{
+        FRAC_1_SQRT_2,
+        PI,
+        TAU,
+    },
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use futures_util::Stream;
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+
+use crate::{
+    buf::SampleBufMut,
+    coding::varicode::{
+        self,
+        VaricodeDecoder,
+    },
+    filter::MovingAverage,
+    io::{
+        AsyncReadSamples,
+        GetSampleRate,
+        ReadBuf,
+        combinators::Scanner,
+    },
+};
+
+/// Number of timing bins per symbol.
+const NUM_SYNC_BINS: usize = 16;
+
+/// How fast the timing bins follow the signal's amplitude.
+const SYNC_ALPHA: f32 = 0.2;
+
+/// How fast the power measurement for the Costas loop's error follows the
+/// signal.
+const POWER_ALPHA: f32 = 0.05;
+
+const BUFFER_SIZE: usize = 4096;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PskMode {
+    #[default]
+    Psk31,
+    Psk63,
+}
+
+impl PskMode {
+    #[inline]
+    pub const fn baud_rate(&self) -> f32 {
+        match self {
+            Self::Psk31 => 31.25,
+            Self::Psk63 => 62.5,
+        }
+    }
+
+    /// Default bandwidth of the carrier loop in Hz.
+    #[inline]
+    pub const fn loop_bandwidth(&self) -> f32 {
+        self.baud_rate() / 4.0
+    }
+}
+
+/// Modulates PSK31 or PSK63 on a carrier, producing complex samples.
+///
+/// For audio, use the real part of the samples.
+#[derive(Clone, Copy, Debug)]
+pub struct PskModulator {
+    samples_per_symbol: f32,
+    clock: f32,
+    phase: f32,
+    phase_increment: f32,
+    /// Sign of the carrier, which is flipped by a 0.
+    amplitude: f32,
+}
+
+impl PskModulator {
+    pub fn new(mode: PskMode, frequency: f32, sample_rate: f32) -> Self {
+        Self {
+            samples_per_symbol: sample_rate / mode.baud_rate(),
+            clock: 0.0,
+            phase: 0.0,
+            phase_increment: TAU * frequency / sample_rate,
+            amplitude: 1.0,
+        }
+    }
+
+    pub fn push_bit<B>(&mut self, bit: bool, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        self.clock += self.samples_per_symbol;
+        let num_samples = self.clock as usize;
+        self.clock -= num_samples as f32;
+
+        for i in 0..num_samples {
+            let amplitude = if bit {
+                self.amplitude
+            }
+            else {
+                self.amplitude * (PI * i as f32 / num_samples as f32).cos()
+            };
+            output.put_sample(Complex::from_polar(amplitude, self.phase));
+            self.phase = (self.phase + self.phase_increment) % TAU;
+        }
+
+        if !bit {
+            self.amplitude = -self.amplitude;
+        }
+    }
+
+    pub fn push_bits<B>(&mut self, bits: impl IntoIterator<Item = bool>, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        for bit in bits {
+            self.push_bit(bit, output);
+        }
+    }
+
+    /// Sends reversals for `num_symbols` symbols. Receivers synchronize to
+    /// these, so they're sent before the text.
+    pub fn push_idle<B>(&mut self, num_symbols: usize, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        self.push_bits(std::iter::repeat_n(false, num_symbols), output);
+    }
+
+    /// Sends a steady carrier for `num_symbols` symbols, which marks the end
+    /// of a transmission.
+    pub fn push_carrier<B>(&mut self, num_symbols: usize, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        self.push_bits(std::iter::repeat_n(true, num_symbols), output);
+    }
+
+    pub fn push_text<B>(&mut self, text: &str, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        let mut bits = vec![];
+        varicode::encode_str(text, &mut bits);
+        self.push_bits(bits, output);
+    }
+}
+
+/// Demodulates PSK31 or PSK63 into bits.
+///
+/// Scans complex or real audio, and outputs a bit per symbol.
+#[derive(Clone, Debug)]
+pub struct PskDemodulator {
+    sample_rate: f32,
+    /// Input samples per timing bin.
+    decimation: f32,
+
+    /// Phase of the local carrier in radians.
+    phase: f32,
+    phase_increment: f32,
+    /// Frequency correction of the Costas loop in radians per sample.
+    frequency_offset: f32,
+    /// Proportional gain of the loop filter.
+    alpha: f32,
+    /// Integral gain of the loop filter.
+    beta: f32,
+    power: f32,
+
+    matched_filter: MovingAverage<Complex<f32>>,
+    decimation_clock: f32,
+
+    /// Amplitude per timing bin.
+    sync: [f32; NUM_SYNC_BINS],
+    /// Position in the symbol, in bins.
+    symbol_clock: f32,
+    last_symbol: Complex<f32>,
+}
+
+impl PskDemodulator {
+    /// Demodulates a signal on a carrier of `frequency` Hz, which is 0 for
+    /// complex baseband.
+    pub fn new(mode: PskMode, frequency: f32, sample_rate: f32) -> Self {
+        let samples_per_symbol = sample_rate / mode.baud_rate();
+        Self {
+            sample_rate,
+            decimation: samples_per_symbol / NUM_SYNC_BINS as f32,
+            phase: 0.0,
+            phase_increment: TAU * frequency / sample_rate,
+            frequency_offset: 0.0,
+            alpha: 0.0,
+            beta: 0.0,
+            power: 0.0,
+            matched_filter: MovingAverage::new((samples_per_symbol.round() as usize).max(1)),
+            decimation_clock: 0.0,
+            sync: [0.0; NUM_SYNC_BINS],
+            symbol_clock: 0.0,
+            last_symbol: Complex::new(0.0, 0.0),
+        }
+        .with_loop_bandwidth(mode.loop_bandwidth())
+    }
+
+    /// The bandwidth of the carrier loop in Hz. A wider loop follows larger
+    /// offsets, but lets through more noise.
+    pub fn with_loop_bandwidth(mut self, loop_bandwidth: f32) -> Self {
+        // critically damped second-order loop, running once per timing bin
+        let damping = FRAC_1_SQRT_2;
+        let theta =
+            loop_bandwidth * self.decimation / self.sample_rate / (damping + 0.25 / damping);
+        let denominator = 1.0 + 2.0 * damping * theta + theta * theta;
+        self.alpha = 4.0 * damping * theta / denominator;
+        self.beta = 4.0 * theta * theta / denominator;
+        self
+    }
+
+    /// Offset of the carrier from the nominal frequency in Hz, as tracked by
+    /// the Costas loop.
+    #[inline]
+    pub fn frequency_offset(&self) -> f32 {
+        self.frequency_offset * self.sample_rate / TAU
+    }
+
+    /// Updates the Costas loop with a filtered sample.
+    fn track_carrier(&mut self, sample: Complex<f32>) {
+        self.power += POWER_ALPHA * (sample.norm_sqr() - self.power);
+        if self.power > 0.0 {
+            // doesn't depend on the sign of the symbol
+            let error = sample.re * sample.im / self.power;
+            self.frequency_offset += self.beta * error / self.decimation;
+            self.phase = (self.phase + self.alpha * error).rem_euclid(TAU);
+        }
+    }
+
+    /// Updates the timing with a filtered sample, and returns whether it is
+    /// the symbol's sampling point.
+    fn track_timing(&mut self, sample: Complex<f32>) -> bool {
+        let bin = (self.symbol_clock.max(0.0) as usize).min(NUM_SYNC_BINS - 1);
+        self.sync[bin] += SYNC_ALPHA * (sample.norm() - self.sync[bin]);
+
+        self.symbol_clock += 1.0;
+        if self.symbol_clock < NUM_SYNC_BINS as f32 {
+            return false;
+        }
+        self.symbol_clock -= NUM_SYNC_BINS as f32;
+
+        // the amplitude dips in the middle of a reversal, so the sampling
+        // point is in the middle between the dips. if the later half of the
+        // symbol is stronger, the symbol is sampled too early.
+        let (early, late) = self.sync.split_at(NUM_SYNC_BINS / 2);
+        let early = early.iter().sum::<f32>();
+        let late = late.iter().sum::<f32>();
+        if early + late > 0.0 {
+            self.symbol_clock += (late - early) / (early + late);
+        }
+        true
+    }
+}
+
+impl Scanner<Complex<f32>> for PskDemodulator {
+    type Output = Option<bool>;
+
+    fn scan(&mut self, sample: Complex<f32>) -> Self::Output {
+        let mixed = sample * Complex::from_polar(1.0, -self.phase);
+        self.phase = (self.phase + self.phase_increment + self.frequency_offset).rem_euclid(TAU);
+        let filtered = self.matched_filter.scan(mixed);
+
+        self.decimation_clock += 1.0;
+        if self.decimation_clock < self.decimation {
+            return None;
+        }
+        self.decimation_clock -= self.decimation;
+
+        self.track_carrier(filtered);
+        if !self.track_timing(filtered) {
+            return None;
+        }
+
+        // a 0 reverses the phase
+        let bit = (filtered * self.last_symbol.conj()).re > 0.0;
+        self.last_symbol = filtered;
+        Some(bit)
+    }
+}
+
+impl Scanner<f32> for PskDemodulator {
+    type Output = Option<bool>;
+
+    #[inline]
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        Scanner::<Complex<f32>>::scan(self, Complex::new(sample, 0.0))
+    }
+}
+
+pin_project! {
+    /// Decodes PSK31 or PSK63 from audio.
+    ///
+    /// This is a [`Stream`] of characters, which ends with the audio stream.
+    #[derive(Debug)]
+    pub struct PskStream<R, S> {
+        #[pin]
+        input: R,
+        demodulator: PskDemodulator,
+        decoder: VaricodeDecoder,
+        buffer: Vec<S>,
+        position: usize,
+        length: usize,
+    }
+}
+
+impl<R, S> PskStream<R, S>
+where
+    R: GetSampleRate,
+    S: Clone + Default,
+{
+    /// Decodes a signal on a carrier of `frequency` Hz.
+    pub fn new(input: R, mode: PskMode, frequency: f32) -> Self {
+        let demodulator = PskDemodulator::new(mode, frequency, input.sample_rate());
+        Self {
+            input,
+            demodulator,
+            decoder: VaricodeDecoder::new(),
+            buffer: vec![S::default(); BUFFER_SIZE],
+            position: 0,
+            length: 0,
+        }
+    }
+}
+
+impl<R, S> PskStream<R, S> {
+    #[inline]
+    pub fn demodulator(&self) -> &PskDemodulator {
+        &self.demodulator
+    }
+
+    #[inline]
+    pub fn demodulator_mut(&mut self) -> &mut PskDemodulator {
+        &mut self.demodulator
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+impl<R, S> Stream for PskStream<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: Copy,
+    PskDemodulator: Scanner<S, Output = Option<bool>>,
+{
+    type Item = Result<char, R::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            while *this.position < *this.length {
+                let sample = this.buffer[*this.position];
+                *this.position += 1;
+                if let Some(bit) = this.demodulator.scan(sample)
+                    && let Some(character) = this.decoder.scan(bit)
+                {
+                    return Poll::Ready(Some(Ok(character)));
+                }
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.buffer[..]);
+            ready!(this.input.as_mut().poll_read_samples(cx, &mut read_buf))?;
+            let num_read = read_buf.filled().len();
+            if num_read == 0 {
+                return Poll::Ready(None);
+            }
+            *this.position = 0;
+            *this.length = num_read;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{
+        FutureExt,
+        TryStreamExt,
+    };
+    use num_complex::Complex;
+
+    use super::{
+        PskDemodulator,
+        PskMode,
+        PskModulator,
+        PskStream,
+    };
+    use crate::{
+        coding::varicode::VaricodeDecoder,
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+            combinators::Scanner,
+        },
+    };
+
+    const SAMPLE_RATE: f32 = 8000.0;
+    const TEXT: &str = "CQ CQ de N0CALL: PSK test 73 <3";
+
+    fn transmission(mode: PskMode, frequency: f32, sample_rate: f32) -> Vec<Complex<f32>> {
+        let mut signal = vec![];
+        let mut modulator = PskModulator::new(mode, frequency, sample_rate);
+        modulator.push_idle(32, &mut signal);
+        modulator.push_text(TEXT, &mut signal);
+        modulator.push_idle(16, &mut signal);
+        modulator.push_carrier(8, &mut signal);
+        signal
+    }
+
+    #[test]
+    fn it_decodes_baseband() {
+        let mut demodulator = PskDemodulator::new(PskMode::Psk63, 0.0, 1000.0);
+        let mut decoder = VaricodeDecoder::new();
+        let decoded = transmission(PskMode::Psk63, 0.0, 1000.0)
+            .into_iter()
+            .filter_map(|sample| demodulator.scan(sample))
+            .filter_map(|bit| decoder.scan(bit))
+            .collect::<String>();
+        assert_eq!(decoded.trim_start(), TEXT);
+    }
+
+    #[test]
+    fn it_tracks_the_carrier() {
+        // mistuned by 3 Hz
+        let signal = transmission(PskMode::Psk31, 1003.0, SAMPLE_RATE);
+
+        // add some noise
+        let mut noise_state = 0x1234_5678u32;
+        let audio = signal
+            .into_iter()
+            .map(|sample| {
+                noise_state = noise_state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (noise_state >> 8) as f32 / (1 << 24) as f32 - 0.5;
+                sample.re + noise
+            })
+            .collect::<Vec<f32>>();
+
+        let mut stream = PskStream::<_, f32>::new(
+            Cursor::new(&audio[..]).with_sample_rate(SAMPLE_RATE),
+            PskMode::Psk31,
+            1000.0,
+        );
+        let decoded = (&mut stream)
+            .try_collect::<String>()
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(decoded.trim_start(), TEXT);
+
+        let offset = stream.demodulator().frequency_offset();
+        assert!((offset - 3.0).abs() < 0.5, "offset: {offset}");
+    }
+}
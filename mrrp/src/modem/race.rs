@@ -0,0 +1,293 @@
+//! Decoding a channel with several candidate decoders at once.
+//!
+//! Often it's not known which mode a signal uses, e.g. which of the POCSAG
+//! baud rates, or which SSTV mode if the VIS code was missed. Instead of
+//! guessing, [`race_decoders`] feeds every sample to all candidates, and
+//! keeps whichever locks first. The others are dropped.
+//!
+//! Decoders are [`Scanner`]s, so a candidate is usually a demodulator
+//! chained with a decoder, e.g. with
+//! [`chain_some`][crate::io::combinators::ScannerExt::chain_some]. The race
+//! itself is a scanner too, which can be put on a stream with
+//! [`scan_with`][crate::io::AsyncReadSamplesExt::scan_with].
+
+use std::collections::VecDeque;
+
+use crate::io::combinators::{
+    Chain,
+    Scanner,
+    SomeScanner,
+};
+
+/// Outputs that a candidate produces before the race is decided are kept, so
+/// the winner's first message is not lost. This limits how many.
+const MAX_PENDING: usize = 16;
+
+/// How sure a decoder is that it's receiving a signal it can decode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockState {
+    #[default]
+    Searching,
+
+    /// Synchronized, e.g. found a sync word, but nothing was validated yet.
+    Locked,
+
+    /// Decoded data that passed a check, e.g. a CRC.
+    Valid,
+}
+
+/// A decoder that can tell whether it's locked onto a signal.
+pub trait LockDetect {
+    fn lock_state(&self) -> LockState;
+}
+
+impl<T: LockDetect + ?Sized> LockDetect for &mut T {
+    #[inline]
+    fn lock_state(&self) -> LockState {
+        (**self).lock_state()
+    }
+}
+
+impl<T: LockDetect + ?Sized> LockDetect for Box<T> {
+    #[inline]
+    fn lock_state(&self) -> LockState {
+        (**self).lock_state()
+    }
+}
+
+/// A demodulator and decoder is locked if the decoder is.
+impl<H, T: LockDetect> LockDetect for Chain<H, T> {
+    #[inline]
+    fn lock_state(&self) -> LockState {
+        self.tail().lock_state()
+    }
+}
+
+impl<T: LockDetect> LockDetect for SomeScanner<T> {
+    #[inline]
+    fn lock_state(&self) -> LockState {
+        self.inner().lock_state()
+    }
+}
+
+/// A decoder with lock detection.
+///
+/// This exists so that candidates of different types can be raced as
+/// `Box<dyn Decoder<S, Output = Option<T>>>`.
+pub trait Decoder<S>: Scanner<S> + LockDetect {}
+
+impl<S, D: Scanner<S> + LockDetect + ?Sized> Decoder<S> for D {}
+
+/// Races `candidates` until one reaches [`LockState::Valid`]. See
+/// [`RaceDecoders`].
+pub fn race_decoders<D, T>(candidates: impl IntoIterator<Item = D>) -> RaceDecoders<D, T> {
+    RaceDecoders::new(candidates)
+}
+
+#[derive(Debug)]
+struct Candidate<D, T> {
+    index: usize,
+    decoder: D,
+    pending: VecDeque<T>,
+}
+
+/// Scanner that feeds each sample to several candidate decoders, until one of
+/// them locks.
+///
+/// Before the race is decided, nothing is output. Once a candidate reaches
+/// the [threshold][Self::with_threshold], the other candidates are dropped,
+/// and the winner's outputs are passed on, starting with the last few it
+/// produced before it won. If several candidates lock on the same sample,
+/// the one that was passed first wins.
+#[derive(Debug)]
+pub struct RaceDecoders<D, T> {
+    candidates: Vec<Candidate<D, T>>,
+    threshold: LockState,
+    winner: Option<usize>,
+}
+
+impl<D, T> RaceDecoders<D, T> {
+    pub fn new(candidates: impl IntoIterator<Item = D>) -> Self {
+        Self {
+            candidates: candidates
+                .into_iter()
+                .enumerate()
+                .map(|(index, decoder)| {
+                    Candidate {
+                        index,
+                        decoder,
+                        pending: VecDeque::new(),
+                    }
+                })
+                .collect(),
+            threshold: LockState::Valid,
+            winner: None,
+        }
+    }
+
+    /// Decides the race when a candidate reaches `threshold`.
+    ///
+    /// [`LockState::Locked`] decides faster, but is more likely to pick a
+    /// candidate that locked onto noise.
+    pub fn with_threshold(mut self, threshold: LockState) -> Self {
+        self.threshold = threshold.max(LockState::Locked);
+        self
+    }
+
+    /// Index of the candidate that won, in the order they were passed in.
+    #[inline]
+    pub fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    /// The decoder that won.
+    pub fn winning_decoder(&self) -> Option<&D> {
+        self.winner
+            .and(self.candidates.first())
+            .map(|candidate| &candidate.decoder)
+    }
+
+    pub fn into_winning_decoder(self) -> Option<D> {
+        self.winner
+            .and(self.candidates.into_iter().next())
+            .map(|candidate| candidate.decoder)
+    }
+}
+
+impl<D, S, T> Scanner<S> for RaceDecoders<D, T>
+where
+    D: Scanner<S, Output = Option<T>> + LockDetect,
+    S: Clone,
+{
+    type Output = Option<T>;
+
+    fn scan(&mut self, sample: S) -> Self::Output {
+        if self.winner.is_some() {
+            let winner = &mut self.candidates[0];
+            if let Some(output) = winner.decoder.scan(sample) {
+                winner.pending.push_back(output);
+            }
+            return winner.pending.pop_front();
+        }
+
+        for candidate in &mut self.candidates {
+            if let Some(output) = candidate.decoder.scan(sample.clone()) {
+                if candidate.pending.len() == MAX_PENDING {
+                    candidate.pending.pop_front();
+                }
+                candidate.pending.push_back(output);
+            }
+        }
+
+        let position = self
+            .candidates
+            .iter()
+            .position(|candidate| candidate.decoder.lock_state() >= self.threshold)?;
+        let winner = self.candidates.swap_remove(position);
+        tracing::debug!(winner = winner.index, "decoder race decided");
+
+        self.winner = Some(winner.index);
+        self.candidates.clear();
+        self.candidates.push(winner);
+        self.candidates[0].pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        LockDetect,
+        LockState,
+        RaceDecoders,
+    };
+    use crate::{
+        io::combinators::{
+            Scanner,
+            ScannerExt,
+        },
+        modem::{
+            afsk::{
+                AfskDemodulator,
+                AfskModulator,
+            },
+            pocsag::{
+                PocsagDecoder,
+                PocsagEncoder,
+                fsk_config,
+            },
+        },
+    };
+
+    #[test]
+    fn it_picks_the_right_pocsag_baud_rate() {
+        const SAMPLE_RATE: f32 = 48_000.0;
+
+        let mut encoder = PocsagEncoder::new();
+        encoder.push_alphanumeric(1234567, "Which baud rate?");
+        let bits = encoder.finish();
+
+        let mut signal = vec![];
+        AfskModulator::new(fsk_config(1200.0), SAMPLE_RATE).modulate_bits(bits, &mut signal);
+
+        let mut race = RaceDecoders::new([512.0, 1200.0, 2400.0].map(|baud_rate| {
+            // the demodulator also scans complex samples
+            let demodulator = AfskDemodulator::new(fsk_config(baud_rate), SAMPLE_RATE);
+            ScannerExt::<f32>::chain_some(demodulator, PocsagDecoder::new())
+        }));
+        let messages = signal
+            .into_iter()
+            .filter_map(|sample| race.scan(sample))
+            .collect::<Vec<_>>();
+
+        assert_eq!(race.winner(), Some(1));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].text(), "Which baud rate?");
+    }
+
+    #[test]
+    fn it_decides_on_the_threshold() {
+        let mut race = RaceDecoders::new([LockAfter::new(3), LockAfter::new(2)])
+            .with_threshold(LockState::Locked);
+        assert_eq!(race.scan(()), None);
+        assert_eq!(race.scan(()), Some(2));
+        assert_eq!(race.winner(), Some(1));
+        assert_eq!(race.scan(()), Some(3));
+    }
+
+    /// Locks after a number of samples, and then outputs how many samples it
+    /// has seen.
+    #[derive(Debug)]
+    struct LockAfter {
+        lock_after: usize,
+        num_samples: usize,
+    }
+
+    impl LockAfter {
+        fn new(lock_after: usize) -> Self {
+            Self {
+                lock_after,
+                num_samples: 0,
+            }
+        }
+    }
+
+    impl Scanner<()> for LockAfter {
+        type Output = Option<usize>;
+
+        fn scan(&mut self, _sample: ()) -> Self::Output {
+            self.num_samples += 1;
+            (self.num_samples >= self.lock_after).then_some(self.num_samples)
+        }
+    }
+
+    impl LockDetect for LockAfter {
+        fn lock_state(&self) -> LockState {
+            if self.num_samples >= self.lock_after {
+                LockState::Locked
+            }
+            else {
+                LockState::Searching
+            }
+        }
+    }
+}
@@ -0,0 +1,641 @@
+//! RTTY (radioteletype)
+//!
+//! Characters are sent as 5 bit Baudot (ITA2) codes with binary FSK, usually
+//! at 45.45 baud with a shift of 170 Hz. Each code is framed by a start bit
+//! (space) and 1.5 stop bits (mark), and the line idles on mark. Letters and
+//! figures share the codes, and the LTRS and FIGS codes switch between them.
+//!
+//! The [`RttyDecoder`] measures the mark and space tones with
+//! [`GoertzelFilter`]s, whose blocks are as long as the inverse of the shift,
+//! so that each filter has a null at the other tone. [`RttyModulator`]
+//! produces phase-continuous FSK, either as audio tones or on complex
+//! baseband (see [`RttyConfig::baseband`]).
+
+use std::{
+    f32::consts::TAU,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use futures_util::Stream;
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+
+use crate::{
+    buf::SampleBufMut,
+    filter::GoertzelFilter,
+    io::{
+        AsyncReadSamples,
+        GetSampleRate,
+        ReadBuf,
+        combinators::Scanner,
+    },
+};
+
+pub const LTRS: u8 = 0x1f;
+pub const FIGS: u8 = 0x1b;
+
+const NUL: u8 = 0x00;
+const SPACE: u8 = 0x04;
+
+/// Characters of the letters shift, indexed by code.
+const LETTERS: &[u8; 32] = b"\0E\nA SIU\rDRJNFCKTZLWHYPQOBG\0MXV\0";
+
+/// Characters of the figures shift, indexed by code, with the US TTY
+/// assignments that are common in amateur radio.
+const FIGURES: &[u8; 32] = b"\x003\n- \x0787\r$4',!:(5\")2#6019?&\0./;\0";
+
+const BUFFER_SIZE: usize = 4096;
+
+/// Tones, baud rate and framing of an RTTY signal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RttyConfig {
+    pub baud_rate: f32,
+    pub mark: f32,
+    pub space: f32,
+    /// Length of the stop bit in bits, usually 1.5.
+    pub stop_bits: f32,
+}
+
+impl RttyConfig {
+    /// 45.45 baud with 170 Hz shift and the usual audio tones of an SSB
+    /// receiver.
+    pub const AMATEUR: Self = Self {
+        baud_rate: 45.45,
+        mark: 2125.0,
+        space: 2295.0,
+        stop_bits: 1.5,
+    };
+
+    /// Tones around 0 Hz for complex baseband, with mark on the higher
+    /// frequency.
+    pub fn baseband(baud_rate: f32, shift: f32) -> Self {
+        Self {
+            baud_rate,
+            mark: 0.5 * shift,
+            space: -0.5 * shift,
+            stop_bits: 1.5,
+        }
+    }
+
+    /// Swaps mark and space, e.g. for a signal received on the other
+    /// sideband.
+    pub fn reversed(self) -> Self {
+        Self {
+            mark: self.space,
+            space: self.mark,
+            ..self
+        }
+    }
+
+    #[inline]
+    pub fn shift(&self) -> f32 {
+        (self.space - self.mark).abs()
+    }
+
+    #[inline]
+    pub fn samples_per_bit(&self, sample_rate: f32) -> f32 {
+        sample_rate / self.baud_rate
+    }
+}
+
+impl Default for RttyConfig {
+    fn default() -> Self {
+        Self::AMATEUR
+    }
+}
+
+/// Turns Baudot codes into characters, keeping track of the shift.
+#[derive(Clone, Copy, Debug)]
+pub struct BaudotDecoder {
+    figures: bool,
+    unshift_on_space: bool,
+}
+
+impl Default for BaudotDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BaudotDecoder {
+    pub fn new() -> Self {
+        Self {
+            figures: false,
+            unshift_on_space: true,
+        }
+    }
+
+    /// Whether a space switches back to letters (USOS), which is the default.
+    pub fn with_unshift_on_space(mut self, unshift_on_space: bool) -> Self {
+        self.unshift_on_space = unshift_on_space;
+        self
+    }
+
+    #[inline]
+    pub fn is_figures(&self) -> bool {
+        self.figures
+    }
+}
+
+impl Scanner<u8> for BaudotDecoder {
+    type Output = Option<char>;
+
+    fn scan(&mut self, code: u8) -> Self::Output {
+        match code & 0x1f {
+            LTRS => {
+                self.figures = false;
+                None
+            }
+            FIGS => {
+                self.figures = true;
+                None
+            }
+            NUL => None,
+            code => {
+                if code == SPACE && self.unshift_on_space {
+                    self.figures = false;
+                }
+                let table = if self.figures { FIGURES } else { LETTERS };
+                Some(char::from(table[usize::from(code)]))
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.figures = false;
+        None
+    }
+}
+
+/// Turns characters into Baudot codes, inserting LTRS and FIGS where needed.
+///
+/// Lower case letters are sent as upper case, characters that Baudot doesn't
+/// have are skipped.
+#[derive(Clone, Copy, Debug)]
+pub struct BaudotEncoder {
+    /// `None` until the first shift is sent, so that the receiver's shift is
+    /// known.
+    figures: Option<bool>,
+    unshift_on_space: bool,
+}
+
+impl Default for BaudotEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BaudotEncoder {
+    pub fn new() -> Self {
+        Self {
+            figures: None,
+            unshift_on_space: true,
+        }
+    }
+
+    /// Whether the receiver switches back to letters after a space (USOS),
+    /// which is the default.
+    pub fn with_unshift_on_space(mut self, unshift_on_space: bool) -> Self {
+        self.unshift_on_space = unshift_on_space;
+        self
+    }
+
+    /// Appends the codes for `character` to `output`.
+    pub fn encode(&mut self, character: char, output: &mut Vec<u8>) {
+        let Ok(byte) = u8::try_from(character.to_ascii_uppercase())
+        else {
+            return;
+        };
+        if byte == 0 {
+            return;
+        }
+        let position = |table: &[u8; 32]| table.iter().position(|c| *c == byte).map(|i| i as u8);
+
+        let (code, figures) = match (position(LETTERS), position(FIGURES)) {
+            // in both shifts, e.g. space and line breaks
+            (Some(code), Some(_)) => (code, self.figures),
+            (Some(code), None) => (code, Some(false)),
+            (None, Some(code)) => (code, Some(true)),
+            (None, None) => return,
+        };
+
+        if let Some(figures) = figures
+            && self.figures != Some(figures)
+        {
+            output.push(if figures { FIGS } else { LTRS });
+            self.figures = Some(figures);
+        }
+        output.push(code);
+
+        if code == SPACE && self.unshift_on_space && self.figures.is_some() {
+            self.figures = Some(false);
+        }
+    }
+
+    pub fn encode_str(&mut self, text: &str, output: &mut Vec<u8>) {
+        for character in text.chars() {
+            self.encode(character, output);
+        }
+    }
+}
+
+/// Phase-continuous FSK modulator for RTTY, producing complex samples.
+///
+/// For audio, use the real part of the samples.
+#[derive(Clone, Copy, Debug)]
+pub struct RttyModulator {
+    config: RttyConfig,
+    sample_rate: f32,
+    phase: f32,
+    clock: f32,
+    encoder: BaudotEncoder,
+}
+
+impl RttyModulator {
+    pub fn new(config: RttyConfig, sample_rate: f32) -> Self {
+        Self {
+            config,
+            sample_rate,
+            phase: 0.0,
+            clock: 0.0,
+            encoder: BaudotEncoder::new(),
+        }
+    }
+
+    #[inline]
+    pub fn config(&self) -> &RttyConfig {
+        &self.config
+    }
+
+    /// Writes `length` bits of mark or space into `output`.
+    fn push_tone<B>(&mut self, mark: bool, length: f32, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        let frequency = if mark {
+            self.config.mark
+        }
+        else {
+            self.config.space
+        };
+        let phase_increment = TAU * frequency / self.sample_rate;
+
+        self.clock += length * self.config.samples_per_bit(self.sample_rate);
+        while self.clock >= 1.0 {
+            output.put_sample(Complex::from_polar(1.0, self.phase));
+            self.phase = (self.phase + phase_increment) % TAU;
+            self.clock -= 1.0;
+        }
+    }
+
+    /// Idles on mark for `num_bits` bits, e.g. before the first character.
+    pub fn push_idle<B>(&mut self, num_bits: usize, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        self.push_tone(true, num_bits as f32, output);
+    }
+
+    /// Sends a Baudot code with its start and stop bits.
+    pub fn push_code<B>(&mut self, code: u8, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        self.push_tone(false, 1.0, output);
+        for i in 0..5 {
+            self.push_tone(code & (1 << i) != 0, 1.0, output);
+        }
+        self.push_tone(true, self.config.stop_bits, output);
+    }
+
+    pub fn push_text<B>(&mut self, text: &str, output: &mut B)
+    where
+        B: SampleBufMut<Complex<f32>>,
+    {
+        let mut codes = vec![];
+        self.encoder.encode_str(text, &mut codes);
+        for code in codes {
+            self.push_code(code, output);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    /// Waiting for a start bit.
+    Idle,
+    /// Receiving a character. Bit 0 is the start bit, bits 1 to 5 the code,
+    /// and bit 6 the stop bit.
+    Receiving {
+        bit: usize,
+        code: u8,
+        /// Samples until the next bit is sampled.
+        countdown: f32,
+    },
+}
+
+/// RTTY demodulator and decoder.
+///
+/// Scans complex or real audio, and outputs characters.
+#[derive(Clone, Debug)]
+pub struct RttyDecoder {
+    samples_per_bit: f32,
+    mark_filter: GoertzelFilter,
+    space_filter: GoertzelFilter,
+    state: State,
+    last_mark: bool,
+    baudot: BaudotDecoder,
+    num_framing_errors: usize,
+}
+
+impl RttyDecoder {
+    pub fn new(config: RttyConfig, sample_rate: f32) -> Self {
+        let bandwidth = config.shift();
+        Self {
+            samples_per_bit: config.samples_per_bit(sample_rate),
+            mark_filter: GoertzelFilter::new(sample_rate, config.mark, bandwidth),
+            space_filter: GoertzelFilter::new(sample_rate, config.space, bandwidth),
+            state: State::Idle,
+            last_mark: true,
+            baudot: BaudotDecoder::new(),
+            num_framing_errors: 0,
+        }
+    }
+
+    pub fn with_baudot_decoder(mut self, baudot: BaudotDecoder) -> Self {
+        self.baudot = baudot;
+        self
+    }
+
+    /// Number of characters that were dropped because their stop bit was
+    /// missing.
+    #[inline]
+    pub fn num_framing_errors(&self) -> usize {
+        self.num_framing_errors
+    }
+
+    /// Demodulates a sample and outputs a code after its stop bit.
+    fn demodulate(&mut self, sample: Complex<f32>) -> Option<u8> {
+        let mark = self.mark_filter.scan(sample).norm() > self.space_filter.scan(sample).norm();
+        let falling_edge = self.last_mark && !mark;
+        self.last_mark = mark;
+
+        match &mut self.state {
+            State::Idle => {
+                if falling_edge {
+                    // the filters see the edge about a block late, and the
+                    // bits are delayed the same, so the middle of the start
+                    // bit is half a bit from here.
+                    self.state = State::Receiving {
+                        bit: 0,
+                        code: 0,
+                        countdown: 0.5 * self.samples_per_bit,
+                    };
+                }
+                None
+            }
+            State::Receiving {
+                bit,
+                code,
+                countdown,
+            } => {
+                *countdown -= 1.0;
+                if *countdown > 0.0 {
+                    return None;
+                }
+                *countdown += self.samples_per_bit;
+
+                match *bit {
+                    // a glitch, not a start bit
+                    0 if mark => {
+                        self.state = State::Idle;
+                        None
+                    }
+                    6 => {
+                        let code = *code;
+                        self.state = State::Idle;
+                        if mark {
+                            Some(code)
+                        }
+                        else {
+                            self.num_framing_errors += 1;
+                            None
+                        }
+                    }
+                    index => {
+                        if index > 0 {
+                            *code |= u8::from(mark) << (index - 1);
+                        }
+                        *bit += 1;
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Scanner<Complex<f32>> for RttyDecoder {
+    type Output = Option<char>;
+
+    #[inline]
+    fn scan(&mut self, sample: Complex<f32>) -> Self::Output {
+        let code = self.demodulate(sample)?;
+        self.baudot.scan(code)
+    }
+}
+
+impl Scanner<f32> for RttyDecoder {
+    type Output = Option<char>;
+
+    #[inline]
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        Scanner::<Complex<f32>>::scan(self, Complex::new(sample, 0.0))
+    }
+}
+
+pin_project! {
+    /// Decodes RTTY from audio.
+    ///
+    /// This is a [`Stream`] of characters, which ends with the audio stream.
+    #[derive(Debug)]
+    pub struct RttyStream<R, S> {
+        #[pin]
+        input: R,
+        decoder: RttyDecoder,
+        buffer: Vec<S>,
+        position: usize,
+        length: usize,
+    }
+}
+
+impl<R, S> RttyStream<R, S>
+where
+    R: GetSampleRate,
+    S: Clone + Default,
+{
+    pub fn new(input: R, config: RttyConfig) -> Self {
+        let decoder = RttyDecoder::new(config, input.sample_rate());
+        Self {
+            input,
+            decoder,
+            buffer: vec![S::default(); BUFFER_SIZE],
+            position: 0,
+            length: 0,
+        }
+    }
+}
+
+impl<R, S> RttyStream<R, S> {
+    #[inline]
+    pub fn decoder(&self) -> &RttyDecoder {
+        &self.decoder
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+impl<R, S> Stream for RttyStream<R, S>
+where
+    R: AsyncReadSamples<S>,
+    S: Copy,
+    RttyDecoder: Scanner<S, Output = Option<char>>,
+{
+    type Item = Result<char, R::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            while *this.position < *this.length {
+                let sample = this.buffer[*this.position];
+                *this.position += 1;
+                if let Some(character) = this.decoder.scan(sample) {
+                    return Poll::Ready(Some(Ok(character)));
+                }
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.buffer[..]);
+            ready!(this.input.as_mut().poll_read_samples(cx, &mut read_buf))?;
+            let num_read = read_buf.filled().len();
+            if num_read == 0 {
+                return Poll::Ready(None);
+            }
+            *this.position = 0;
+            *this.length = num_read;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{
+        FutureExt,
+        TryStreamExt,
+    };
+    use num_complex::Complex;
+
+    use super::{
+        BaudotDecoder,
+        BaudotEncoder,
+        FIGS,
+        LTRS,
+        RttyConfig,
+        RttyDecoder,
+        RttyModulator,
+        RttyStream,
+    };
+    use crate::io::{
+        AsyncReadSamplesExt,
+        Cursor,
+        combinators::Scanner,
+    };
+
+    const TEXT: &str = "RYRY CQ DE N0CALL/P 599, 73!\r\n";
+
+    #[test]
+    fn it_converts_baudot() {
+        let mut codes = vec![];
+        BaudotEncoder::new().encode_str("a1 2b", &mut codes);
+        // USOS: the 2 needs another FIGS after the space
+        assert_eq!(
+            codes,
+            [LTRS, 0x03, FIGS, 0x17, 0x04, FIGS, 0x13, LTRS, 0x19]
+        );
+
+        let mut decoder = BaudotDecoder::new();
+        let decoded = codes
+            .into_iter()
+            .filter_map(|code| decoder.scan(code))
+            .collect::<String>();
+        assert_eq!(decoded, "A1 2B");
+    }
+
+    #[test]
+    fn it_decodes_baseband() {
+        const SAMPLE_RATE: f32 = 2000.0;
+        let config = RttyConfig::baseband(45.45, 170.0);
+
+        let mut signal = vec![];
+        let mut modulator = RttyModulator::new(config, SAMPLE_RATE);
+        modulator.push_idle(10, &mut signal);
+        modulator.push_text(TEXT, &mut signal);
+        modulator.push_idle(2, &mut signal);
+
+        // mistuned by 10 Hz
+        for (i, sample) in signal.iter_mut().enumerate() {
+            *sample *=
+                Complex::from_polar(1.0, std::f32::consts::TAU * 10.0 * i as f32 / SAMPLE_RATE);
+        }
+
+        let mut decoder = RttyDecoder::new(config, SAMPLE_RATE);
+        let decoded = signal
+            .into_iter()
+            .filter_map(|sample| decoder.scan(sample))
+            .collect::<String>();
+        assert_eq!(decoded, TEXT);
+        assert_eq!(decoder.num_framing_errors(), 0);
+    }
+
+    #[test]
+    fn it_decodes_audio() {
+        const SAMPLE_RATE: f32 = 8000.0;
+        let config = RttyConfig::AMATEUR;
+
+        let mut signal = vec![];
+        let mut modulator = RttyModulator::new(config, SAMPLE_RATE);
+        modulator.push_idle(10, &mut signal);
+        modulator.push_text(TEXT, &mut signal);
+        modulator.push_idle(2, &mut signal);
+
+        // add some noise
+        let mut noise_state = 0x1234_5678u32;
+        let audio = signal
+            .into_iter()
+            .map(|sample| {
+                noise_state = noise_state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (noise_state >> 8) as f32 / (1 << 24) as f32 - 0.5;
+                sample.re + 0.5 * noise
+            })
+            .collect::<Vec<f32>>();
+
+        let stream = RttyStream::<_, f32>::new(
+            Cursor::new(&audio[..]).with_sample_rate(SAMPLE_RATE),
+            config,
+        );
+        let decoded = stream
+            .try_collect::<String>()
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(decoded, TEXT);
+    }
+}
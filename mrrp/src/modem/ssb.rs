@@ -0,0 +1,298 @@
+//! Single-sideband modulation
+
+use std::collections::VecDeque;
+
+use ::biquad::DirectForm2Transposed;
+use num_complex::Complex;
+
+use crate::{
+    filter::{
+        HilbertFilter,
+        biquad,
+    },
+    io::combinators::{
+        GroupDelay,
+        Scanner,
+    },
+};
+
+pub const DEFAULT_PASSBAND_LOW: f32 = 300.0;
+pub const DEFAULT_PASSBAND_HIGH: f32 = 2700.0;
+
+/// Maximum length of the Hilbert filter.
+///
+/// The length needed to be flat down to the lower passband edge grows with
+/// the sample rate. Up to about 96 kHz (with the default passband) the filter
+/// isn't capped. Above that the sidebands are only separated from about
+/// `sample_rate / MAX_HILBERT_LENGTH` upwards, so resample to an audio
+/// sample rate first.
+pub const MAX_HILBERT_LENGTH: usize = 511;
+
+/// Designs the Hilbert filter that is flat from about `low` upwards.
+fn hilbert_filter(sample_rate: f32, low: f32) -> HilbertFilter {
+    let filter_length = ((sample_rate / low).ceil() as usize | 1).min(MAX_HILBERT_LENGTH);
+    let transition_bandwidth = (2.0 * low / sample_rate).max(2.0 / filter_length as f32);
+    HilbertFilter::new(transition_bandwidth, filter_length)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Sideband {
+    #[default]
+    Upper,
+    Lower,
+}
+
+/// SSB modulator using the phasing method.
+///
+/// The audio is band-limited first, then turned into an analytic signal with a
+/// [`HilbertFilter`]. The analytic signal only has positive frequencies, which
+/// is the upper sideband. For the lower sideband it is conjugated. There is no
+/// carrier, so the output is complex baseband centered on the suppressed
+/// carrier frequency.
+#[derive(Clone, Debug)]
+pub struct SsbModulator {
+    highpass: DirectForm2Transposed<f32, f32>,
+    lowpass: DirectForm2Transposed<f32, f32>,
+    hilbert: HilbertFilter,
+    sideband: Sideband,
+}
+
+impl SsbModulator {
+    pub fn new(sample_rate: f32, sideband: Sideband) -> Self {
+        Self::with_passband(
+            sample_rate,
+            sideband,
+            DEFAULT_PASSBAND_LOW,
+            DEFAULT_PASSBAND_HIGH,
+        )
+    }
+
+    /// Create a modulator that passes audio between `low` and `high` (in Hz).
+    ///
+    /// This is meant for audio sample rates. See [`MAX_HILBERT_LENGTH`] for
+    /// higher sample rates.
+    pub fn with_passband(sample_rate: f32, sideband: Sideband, low: f32, high: f32) -> Self {
+        assert!(
+            0.0 < low && low < high && high < 0.5 * sample_rate,
+            "invalid passband"
+        );
+
+        Self {
+            highpass: biquad::highpass(sample_rate, low),
+            lowpass: biquad::lowpass(sample_rate, high),
+            hilbert: hilbert_filter(sample_rate, low),
+            sideband,
+        }
+    }
+
+    #[inline]
+    pub fn sideband(&self) -> Sideband {
+        self.sideband
+    }
+
+    #[inline]
+    pub fn set_sideband(&mut self, sideband: Sideband) {
+        self.sideband = sideband;
+    }
+
+    /// Delay of the output in samples.
+    #[inline]
+    pub fn delay(&self) -> usize {
+        self.hilbert.delay()
+    }
+}
+
+impl Scanner<f32> for SsbModulator {
+    type Output = Complex<f32>;
+
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        let sample = self.lowpass.scan(self.highpass.scan(sample));
+        let analytic = self.hilbert.scan(sample);
+        match self.sideband {
+            Sideband::Upper => analytic,
+            Sideband::Lower => analytic.conj(),
+        }
+    }
+}
+
+impl GroupDelay for SsbModulator {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        self.hilbert.group_delay()
+    }
+}
+
+/// SSB demodulator using the phasing method.
+///
+/// The input is complex baseband centered on the suppressed carrier. The
+/// quadrature component is shifted by 90° with a [`HilbertFilter`] and then
+/// added to or subtracted from the (delayed) in-phase component, which cancels
+/// the unwanted sideband.
+#[derive(Clone, Debug)]
+pub struct SsbDemodulator {
+    hilbert: HilbertFilter,
+    in_phase: VecDeque<f32>,
+    sideband: Sideband,
+}
+
+impl SsbDemodulator {
+    pub fn new(sample_rate: f32, sideband: Sideband) -> Self {
+        Self::with_low_cutoff(sample_rate, sideband, DEFAULT_PASSBAND_LOW)
+    }
+
+    /// Create a demodulator that suppresses the unwanted sideband from `low`
+    /// (in Hz) upwards. Below that the sidebands aren't separated.
+    ///
+    /// This is meant for audio sample rates. See [`MAX_HILBERT_LENGTH`] for
+    /// higher sample rates.
+    pub fn with_low_cutoff(sample_rate: f32, sideband: Sideband, low: f32) -> Self {
+        assert!(0.0 < low && low < 0.25 * sample_rate, "invalid cutoff");
+
+        let hilbert = hilbert_filter(sample_rate, low);
+
+        Self {
+            in_phase: std::iter::repeat_n(0.0, hilbert.delay()).collect(),
+            hilbert,
+            sideband,
+        }
+    }
+
+    #[inline]
+    pub fn sideband(&self) -> Sideband {
+        self.sideband
+    }
+
+    #[inline]
+    pub fn set_sideband(&mut self, sideband: Sideband) {
+        self.sideband = sideband;
+    }
+
+    /// Delay of the output in samples.
+    #[inline]
+    pub fn delay(&self) -> usize {
+        self.hilbert.delay()
+    }
+}
+
+impl Scanner<Complex<f32>> for SsbDemodulator {
+    type Output = f32;
+
+    fn scan(&mut self, sample: Complex<f32>) -> Self::Output {
+        let shifted = self.hilbert.scan(sample.im).im;
+        self.in_phase.push_back(sample.re);
+        let in_phase = self.in_phase.pop_front().unwrap_or(sample.re);
+
+        match self.sideband {
+            Sideband::Upper => 0.5 * (in_phase - shifted),
+            Sideband::Lower => 0.5 * (in_phase + shifted),
+        }
+    }
+}
+
+impl GroupDelay for SsbDemodulator {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        self.hilbert.group_delay()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use approx::assert_abs_diff_eq;
+
+    use super::{
+        DEFAULT_PASSBAND_LOW,
+        MAX_HILBERT_LENGTH,
+        Sideband,
+        SsbDemodulator,
+        SsbModulator,
+    };
+    use crate::io::combinators::Scanner;
+
+    fn mean_frequency(sideband: Sideband) -> f32 {
+        let sample_rate = 48000.0;
+        let tone = 1000.0;
+        let mut modulator = SsbModulator::new(sample_rate, sideband);
+
+        let output = (0..4800)
+            .map(|i| modulator.scan((TAU * tone * i as f32 / sample_rate).cos()))
+            .skip(1000)
+            .collect::<Vec<_>>();
+
+        let phase_difference = output
+            .windows(2)
+            .map(|pair| (pair[1] * pair[0].conj()).arg())
+            .sum::<f32>()
+            / (output.len() - 1) as f32;
+
+        phase_difference / TAU * sample_rate
+    }
+
+    #[test]
+    fn it_selects_sideband() {
+        assert_abs_diff_eq!(mean_frequency(Sideband::Upper), 1000.0, epsilon = 20.0);
+        assert_abs_diff_eq!(mean_frequency(Sideband::Lower), -1000.0, epsilon = 20.0);
+    }
+
+    fn demodulated_rms(modulated: Sideband, demodulated: Sideband) -> f32 {
+        let sample_rate = 48000.0;
+        let tone = 1000.0;
+        let mut modulator = SsbModulator::new(sample_rate, modulated);
+        let mut demodulator = SsbDemodulator::new(sample_rate, demodulated);
+
+        let output = (0..4800)
+            .map(|i| {
+                let audio = (TAU * tone * i as f32 / sample_rate).cos();
+                demodulator.scan(modulator.scan(audio))
+            })
+            .skip(2000)
+            .collect::<Vec<_>>();
+
+        (output.iter().map(|x| x * x).sum::<f32>() / output.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn it_demodulates_the_selected_sideband() {
+        for sideband in [Sideband::Upper, Sideband::Lower] {
+            let other = match sideband {
+                Sideband::Upper => Sideband::Lower,
+                Sideband::Lower => Sideband::Upper,
+            };
+            assert_abs_diff_eq!(
+                demodulated_rms(sideband, sideband),
+                0.5f32.sqrt(),
+                epsilon = 0.05
+            );
+            assert!(demodulated_rms(sideband, other) < 0.05);
+        }
+    }
+
+    #[test]
+    fn it_caps_the_filter_length_at_high_sample_rates() {
+        let sample_rate = 2_400_000.0;
+        let tone = 100_000.0;
+        let mut modulator = SsbModulator::with_passband(
+            sample_rate,
+            Sideband::Upper,
+            DEFAULT_PASSBAND_LOW,
+            200_000.0,
+        );
+        assert_eq!(modulator.delay(), MAX_HILBERT_LENGTH / 2);
+
+        let output = (0..4800)
+            .map(|i| modulator.scan((TAU * tone * i as f32 / sample_rate).cos()))
+            .skip(1000)
+            .collect::<Vec<_>>();
+        let phase_difference = output
+            .windows(2)
+            .map(|pair| (pair[1] * pair[0].conj()).arg())
+            .sum::<f32>()
+            / (output.len() - 1) as f32;
+        assert_abs_diff_eq!(phase_difference / TAU * sample_rate, tone, epsilon = 2000.0);
+
+        let demodulator = SsbDemodulator::new(sample_rate, Sideband::Upper);
+        assert_eq!(demodulator.delay(), MAX_HILBERT_LENGTH / 2);
+    }
+}
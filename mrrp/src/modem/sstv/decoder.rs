@@ -1,5 +1,6 @@
 use std::{
     cmp::Ordering,
+    convert::Infallible,
     pin::Pin,
     task::{
         Context,
@@ -12,6 +13,10 @@ use num_complex::Complex;
 use pin_project_lite::pin_project;
 
 use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
     filter::GoertzelFilter,
     io::{
         AsyncReadSamples,
@@ -30,6 +35,7 @@ use crate::{
             VIS_HIGH_TONE,
             VIS_LOW_TONE,
             image::FrameBufferMut,
+            line_time::LineTimeDetect,
             modes::{
                 DefaultModes,
                 ModeSelectError,
@@ -294,27 +300,67 @@ where
                                 header_state: HeaderState::VisBit { bit },
                             },
                         ) => {
-                            let Some(bit_value) = bit_value
-                            else {
-                                return Poll::Ready(Err(DecodeError::InvalidVis));
+                            // only the vis code can be bad here, not the stream
+                            let result: Result<_, DecodeError<Infallible>> = match bit_value {
+                                None => Err(DecodeError::InvalidVis),
+                                Some(bit_value) if *bit == 7 => {
+                                    // parity bit
+                                    let vis_code = VisCode::new(*this.vis_code).unwrap();
+                                    this.select_mode
+                                        .mode_specification_with_parity(vis_code, bit_value)
+                                        .map(Some)
+                                        .map_err(DecodeError::from)
+                                }
+                                Some(bit_value) => {
+                                    if bit_value {
+                                        *this.vis_code |= 1 << *bit;
+                                    }
+                                    Ok(None)
+                                }
                             };
 
-                            if *bit == 7 {
-                                // parity bit
-
-                                let vis_code = VisCode::new(*this.vis_code).unwrap();
-                                let mode = this
-                                    .select_mode
-                                    .mode_specification_with_parity(vis_code, bit_value)?;
-                                tracing::debug!(mode = %mode.name);
-
-                                *this.mode = Some(mode);
-                                this.frame_buffer
-                                    .set_size(mode.pixels_per_line, mode.num_lines);
+                            match result {
+                                Ok(Some(mode)) => {
+                                    tracing::debug!(mode = %mode.name);
+                                    *this.mode = Some(mode);
+                                    this.frame_buffer
+                                        .set_size(mode.pixels_per_line, mode.num_lines);
+                                }
+                                Ok(None) => {}
+                                Err(error) => {
+                                    tracing::warn!(?error, "bad vis code, measuring line time");
+                                    skip_to = Some(State::Header {
+                                        header_state: HeaderState::MeasureLineTime,
+                                    });
+                                }
                             }
-                            else if bit_value {
-                                *this.vis_code |= 1 << *bit;
+                        }
+                        (
+                            AcceptedPulse::LineTime { line_time, elapsed },
+                            State::Header {
+                                header_state: HeaderState::MeasureLineTime,
+                            },
+                        ) => {
+                            let line_time = line_time.ok_or(DecodeError::NoSync)?;
+                            let mode = this
+                                .select_mode
+                                .mode_specification_by_line_time(line_time)?;
+                            tracing::debug!(line_time, mode = %mode.name);
+                            *this.mode = Some(mode);
+                            this.frame_buffer
+                                .set_size(mode.pixels_per_line, mode.num_lines);
+
+                            // the lines that were sent while we measured are lost, so
+                            // continue with the next one
+                            let y = (elapsed / mode.line_time).round() as usize;
+                            if y >= mode.num_lines {
+                                *this.state = None;
+                                return Poll::Ready(Ok(()));
                             }
+                            skip_to = Some(State::Line {
+                                y,
+                                line_state: LineState::Sync,
+                            });
                         }
                         (
                             AcceptedPulse::Channel { value },
@@ -376,10 +422,25 @@ pub enum DecodeError<S> {
     Stream(S),
     Eof,
     InvalidVis,
+    /// The VIS code was bad, and no sync pulses were found to measure the
+    /// line time instead.
+    NoSync,
     ModeSelect(#[from] ModeSelectError),
 }
 
-#[derive(Clone, Copy, Debug)]
+impl<S: ClassifyError> ClassifyError for DecodeError<S> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            DecodeError::Stream(error) => error.error_kind(),
+            DecodeError::Eof => ErrorKind::Eof,
+            DecodeError::InvalidVis | DecodeError::NoSync | DecodeError::ModeSelect(_) => {
+                ErrorKind::Decode
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 enum PulseAcceptor {
     Leader {
         locked: bool,
@@ -399,6 +460,7 @@ enum PulseAcceptor {
         sample_sum: f32,
         num_samples: usize,
     },
+    MeasureLineTime(Box<LineTimeDetect>),
 }
 
 impl PulseAcceptor {
@@ -415,6 +477,9 @@ impl PulseAcceptor {
                     HeaderState::LeaderBreak | HeaderState::VisStart | HeaderState::VisStop => {
                         Self::Sync
                     }
+                    HeaderState::MeasureLineTime => {
+                        Self::MeasureLineTime(Box::new(LineTimeDetect::new(sample_rate)))
+                    }
                     HeaderState::VisBit { bit: _ } => {
                         let remaining = (VIS_BIT_TIME * sample_rate) as usize;
                         tracing::debug!("vis bit samples: {remaining}");
@@ -545,6 +610,14 @@ impl PulseAcceptor {
                     });
                 }
             }
+            PulseAcceptor::MeasureLineTime(detect) => {
+                if let Poll::Ready(line_time) = detect.measure(sample) {
+                    return Poll::Ready(AcceptedPulse::LineTime {
+                        line_time,
+                        elapsed: detect.elapsed(),
+                    });
+                }
+            }
         }
 
         Poll::Pending
@@ -553,9 +626,19 @@ impl PulseAcceptor {
 
 #[derive(Clone, Copy, Debug)]
 enum AcceptedPulse {
-    Leader { length: usize },
+    Leader {
+        length: usize,
+    },
     Sync,
     Porch,
-    VisBit { bit: Option<bool> },
-    Channel { value: f32 },
+    VisBit {
+        bit: Option<bool>,
+    },
+    Channel {
+        value: f32,
+    },
+    LineTime {
+        line_time: Option<f32>,
+        elapsed: f32,
+    },
 }
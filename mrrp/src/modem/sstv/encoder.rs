@@ -164,6 +164,9 @@ impl Pulse {
                         };
                         Pulse::new(if bit { VIS_HIGH_TONE } else { VIS_LOW_TONE }, VIS_BIT_TIME)
                     }
+                    HeaderState::MeasureLineTime => {
+                        unreachable!("the encoder always sends the VIS code")
+                    }
                 }
             }
             State::Line { y, line_state } => {
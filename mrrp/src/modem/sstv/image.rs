@@ -72,6 +72,14 @@ impl FrameBuffer for RgbImage {
 pub trait FrameBufferMut {
     fn set_size(&mut self, width: usize, height: usize);
     fn set_channel(&mut self, x: usize, y: usize, channel: Channel, value: u8);
+
+    /// Sets all channels of a pixel to the same value. This is used by
+    /// grayscale decoders.
+    fn set_gray(&mut self, x: usize, y: usize, value: u8) {
+        self.set_channel(x, y, Channel::Red, value);
+        self.set_channel(x, y, Channel::Green, value);
+        self.set_channel(x, y, Channel::Blue, value);
+    }
 }
 
 impl<F> FrameBufferMut for &mut F
@@ -85,6 +93,10 @@ where
     fn set_channel(&mut self, x: usize, y: usize, channel: Channel, value: u8) {
         (&mut **self).set_channel(x, y, channel, value);
     }
+
+    fn set_gray(&mut self, x: usize, y: usize, value: u8) {
+        (**self).set_gray(x, y, value);
+    }
 }
 
 impl FrameBufferMut for RgbImage {
@@ -0,0 +1,221 @@
+//! Measuring the line time of an SSTV signal, for when the VIS code is
+//! missing or corrupted.
+//!
+//! The sync pulses repeat once per line, so the autocorrelation of a sync
+//! detector's output has its first peak at the line time. That's usually
+//! enough to tell the modes apart, see
+//! [`SelectMode::mode_specification_by_line_time`][super::modes::SelectMode::mode_specification_by_line_time].
+
+use std::task::Poll;
+
+use num_complex::Complex;
+
+use crate::{
+    io::combinators::Scanner,
+    modem::{
+        fm,
+        sstv::{
+            PORCH_TONE,
+            SYNC_TONE,
+        },
+    },
+};
+
+/// The sync detector output is averaged over bins of this length, which is
+/// also the resolution of the autocorrelation before interpolation.
+const BIN_TIME: f32 = 1e-3;
+
+/// How long to listen. This covers 3 lines of the slowest mode (Scottie DX).
+pub const MEASURE_TIME: f32 = 3.5;
+
+/// Shorter than the line time of any mode.
+const MIN_LINE_TIME: f32 = 0.05;
+
+/// Longer than the line time of any mode.
+const MAX_LINE_TIME: f32 = 1.1;
+
+/// Peaks of the autocorrelation that are at least this fraction of the
+/// largest one are considered. The first of them is the line time, the others
+/// are multiples of it.
+const PEAK_THRESHOLD: f32 = 0.5;
+
+#[derive(Clone, Debug)]
+pub struct LineTimeDetect {
+    sample_rate: f32,
+    frequency: fm::DifferentiateAndAccessPhase,
+    samples_per_bin: usize,
+    num_bins: usize,
+    bin_sum: f32,
+    bin_length: usize,
+    bins: Vec<f32>,
+}
+
+impl LineTimeDetect {
+    pub fn new(sample_rate: f32) -> Self {
+        let samples_per_bin = ((BIN_TIME * sample_rate).round() as usize).max(1);
+        let num_bins = (MEASURE_TIME * sample_rate) as usize / samples_per_bin;
+
+        Self {
+            sample_rate,
+            frequency: fm::DifferentiateAndAccessPhase::new(sample_rate, 1.0),
+            samples_per_bin,
+            num_bins,
+            bin_sum: 0.0,
+            bin_length: 0,
+            bins: Vec::with_capacity(num_bins),
+        }
+    }
+
+    /// Time in seconds since the measurement started.
+    pub fn elapsed(&self) -> f32 {
+        (self.bins.len() * self.samples_per_bin + self.bin_length) as f32 / self.sample_rate
+    }
+
+    /// Feeds a sample to the detector.
+    ///
+    /// After [`MEASURE_TIME`] this is ready with the line time in seconds, or
+    /// with `None` if there are no periodic sync pulses.
+    pub fn measure(&mut self, sample: Complex<f32>) -> Poll<Option<f32>> {
+        // sync detector: whether the instantaneous frequency is closer to the
+        // sync tone than to the porch and channel tones
+        let frequency = self.frequency.scan(sample);
+        if frequency < 0.5 * (SYNC_TONE + PORCH_TONE) {
+            self.bin_sum += 1.0;
+        }
+
+        self.bin_length += 1;
+        if self.bin_length == self.samples_per_bin {
+            self.bins.push(self.bin_sum / self.samples_per_bin as f32);
+            self.bin_sum = 0.0;
+            self.bin_length = 0;
+
+            if self.bins.len() == self.num_bins {
+                let bin_time = self.samples_per_bin as f32 / self.sample_rate;
+                let line_time = first_peak(
+                    &mut self.bins,
+                    (MIN_LINE_TIME / bin_time) as usize,
+                    (MAX_LINE_TIME / bin_time) as usize,
+                )
+                .map(|lag| lag * bin_time);
+                return Poll::Ready(line_time);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Returns the lag of the first large peak of the autocorrelation of `x`,
+/// between `min_lag` and `max_lag`, interpolated to a fraction of a bin.
+fn first_peak(x: &mut [f32], min_lag: usize, max_lag: usize) -> Option<f32> {
+    let n = x.len();
+    let max_lag = max_lag.min(n / 2);
+    if min_lag < 1 || min_lag + 2 > max_lag {
+        return None;
+    }
+
+    let mean = x.iter().sum::<f32>() / n as f32;
+    x.iter_mut().for_each(|x| *x -= mean);
+
+    let autocorrelation = (min_lag - 1..=max_lag + 1)
+        .map(|lag| {
+            let sum = x.iter().zip(&x[lag..]).map(|(a, b)| a * b).sum::<f32>();
+            sum / (n - lag) as f32
+        })
+        .collect::<Vec<_>>();
+
+    let max = autocorrelation.iter().copied().fold(0.0, f32::max);
+    if max <= 0.0 {
+        return None;
+    }
+
+    let i = (1..autocorrelation.len() - 1).find(|i| {
+        let r = &autocorrelation[i - 1..=i + 1];
+        r[1] >= PEAK_THRESHOLD * max && r[1] >= r[0] && r[1] >= r[2]
+    })?;
+
+    // parabolic interpolation around the peak
+    let r = &autocorrelation[i - 1..=i + 1];
+    let denominator = r[0] - 2.0 * r[1] + r[2];
+    let offset = if denominator == 0.0 {
+        0.0
+    }
+    else {
+        (0.5 * (r[0] - r[2]) / denominator).clamp(-0.5, 0.5)
+    };
+
+    Some((min_lag - 1 + i) as f32 + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+
+    use super::LineTimeDetect;
+    use crate::{
+        modem::sstv::{
+            PORCH_TONE,
+            SYNC_TONE,
+            modes::ModeSpecification,
+        },
+        source::{
+            ComplexSinusoid,
+            SignalGenerator,
+        },
+    };
+
+    /// Sync pulses followed by a porch and random-ish channel tones.
+    fn line_signal(mode: &ModeSpecification, sample_rate: f32) -> impl Iterator<Item = f32> {
+        let sync = (mode.sync_time * sample_rate) as usize;
+        let porch = (mode.porch_time * sample_rate) as usize;
+        let pixel = ((mode.pixel_time * sample_rate) as usize).max(1);
+        let line = (mode.line_time * sample_rate).round() as usize;
+
+        (0..).map(move |i: usize| {
+            let t = i % line;
+            if t < sync {
+                SYNC_TONE
+            }
+            else if t < sync + porch {
+                PORCH_TONE
+            }
+            else {
+                let x = (i / pixel) as u32;
+                1500.0 + (x.wrapping_mul(2654435761) >> 22) as f32 % 800.0
+            }
+        })
+    }
+
+    fn measure(mode: &ModeSpecification) -> f32 {
+        let sample_rate = 11_025.0;
+        let mut detect = LineTimeDetect::new(sample_rate);
+        let mut sinusoid = ComplexSinusoid::new(PORCH_TONE, sample_rate);
+
+        for frequency in line_signal(mode, sample_rate) {
+            sinusoid.set_frequency(frequency);
+            if let Poll::Ready(line_time) = detect.measure(sinusoid.next()) {
+                return line_time.expect("no line time detected");
+            }
+        }
+        unreachable!();
+    }
+
+    #[test]
+    fn it_measures_the_line_time() {
+        for mode in [
+            ModeSpecification::R36,
+            ModeSpecification::M2,
+            ModeSpecification::S1,
+            ModeSpecification::PD160,
+            ModeSpecification::SDX,
+        ] {
+            let line_time = measure(&mode);
+            assert!(
+                (line_time - mode.line_time).abs() < 1e-3,
+                "{}: measured {line_time}, expected {}",
+                mode.name,
+                mode.line_time
+            );
+        }
+    }
+}
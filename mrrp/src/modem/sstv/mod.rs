@@ -8,6 +8,7 @@
 mod decoder;
 mod encoder;
 pub mod image;
+mod line_time;
 pub mod modes;
 pub mod state;
 
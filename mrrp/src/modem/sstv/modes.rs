@@ -78,6 +78,12 @@ pub struct ModeSpecification {
 }
 
 impl ModeSpecification {
+    /// Whether a measured line time matches this mode's, within
+    /// [`LINE_TIME_TOLERANCE`].
+    pub fn matches_line_time(&self, line_time: f32) -> bool {
+        (line_time - self.line_time).abs() <= LINE_TIME_TOLERANCE * self.line_time
+    }
+
     // N7CXI, 2000
     pub const M1: Self = Self {
         name: "Martin M1",
@@ -479,42 +485,68 @@ impl ModeSpecification {
     };
 }
 
+/// All built-in modes.
+///
+/// Some modes have the same line time. They're ordered such that the more
+/// common one comes first, which is what [`builtin_mode_by_line_time`] picks.
+pub const BUILTIN_MODES: [&ModeSpecification; 25] = [
+    &ModeSpecification::R36,
+    &ModeSpecification::R24,
+    &ModeSpecification::R72,
+    &ModeSpecification::R24BW,
+    &ModeSpecification::R12BW,
+    &ModeSpecification::R8BW,
+    &ModeSpecification::M1,
+    &ModeSpecification::M2,
+    &ModeSpecification::M3,
+    &ModeSpecification::M4,
+    &ModeSpecification::S1,
+    &ModeSpecification::S2,
+    &ModeSpecification::SDX,
+    &ModeSpecification::W2120,
+    &ModeSpecification::W2180,
+    &ModeSpecification::PD50,
+    &ModeSpecification::PD90,
+    &ModeSpecification::PD120,
+    &ModeSpecification::PD160,
+    &ModeSpecification::PD180,
+    &ModeSpecification::PD240,
+    &ModeSpecification::PD290,
+    &ModeSpecification::P3,
+    &ModeSpecification::P5,
+    &ModeSpecification::P7,
+];
+
+/// How far a measured line time may be off, relative to the mode's. The
+/// closest modes (PD-160 and Pasokon P7) are about 2% apart.
+pub const LINE_TIME_TOLERANCE: f32 = 0.01;
+
 pub fn builtin_mode_specification(vis_code: VisCode) -> Option<&'static ModeSpecification> {
     static MAP: OnceLock<HashMap<VisCode, &'static ModeSpecification>> = OnceLock::new();
     let map = MAP.get_or_init(|| {
-        let mut map = HashMap::new();
-        let mut insert = |mode: &'static ModeSpecification| map.insert(mode.vis_code, mode);
-        insert(&ModeSpecification::R8BW);
-        insert(&ModeSpecification::R24);
-        insert(&ModeSpecification::R12BW);
-        insert(&ModeSpecification::R36);
-        insert(&ModeSpecification::R24BW);
-        insert(&ModeSpecification::R72);
-        insert(&ModeSpecification::M4);
-        insert(&ModeSpecification::M3);
-        insert(&ModeSpecification::M2);
-        insert(&ModeSpecification::M1);
-        insert(&ModeSpecification::W2180);
-        insert(&ModeSpecification::S2);
-        insert(&ModeSpecification::S1);
-        insert(&ModeSpecification::W2120);
-        insert(&ModeSpecification::SDX);
-        insert(&ModeSpecification::PD50);
-        insert(&ModeSpecification::PD290);
-        insert(&ModeSpecification::PD120);
-        insert(&ModeSpecification::PD180);
-        insert(&ModeSpecification::PD240);
-        insert(&ModeSpecification::PD160);
-        insert(&ModeSpecification::PD90);
-        insert(&ModeSpecification::P3);
-        insert(&ModeSpecification::P5);
-        insert(&ModeSpecification::P7);
-        map
+        BUILTIN_MODES
+            .iter()
+            .map(|mode| (mode.vis_code, *mode))
+            .collect()
     });
 
     map.get(&vis_code).copied()
 }
 
+/// Returns the built-in mode whose line time is closest to `line_time`, if
+/// it's within [`LINE_TIME_TOLERANCE`].
+pub fn builtin_mode_by_line_time(line_time: f32) -> Option<&'static ModeSpecification> {
+    BUILTIN_MODES
+        .iter()
+        .copied()
+        .filter(|mode| mode.matches_line_time(line_time))
+        .min_by(|a, b| {
+            (a.line_time - line_time)
+                .abs()
+                .total_cmp(&(b.line_time - line_time).abs())
+        })
+}
+
 #[derive(Clone, Copy, Debug, thiserror::Error)]
 #[error("mode select error")]
 pub enum ModeSelectError {
@@ -523,6 +555,9 @@ pub enum ModeSelectError {
     UnknownMode {
         vis_code: VisCode,
     },
+    UnknownLineTime {
+        line_time: f32,
+    },
 }
 
 pub trait SelectMode {
@@ -542,6 +577,18 @@ pub trait SelectMode {
             self.mode_specification(vis_code)
         }
     }
+
+    /// Selects a mode by its line time, for when the VIS code couldn't be
+    /// decoded.
+    ///
+    /// By default this doesn't select any mode, so the decoder fails on a bad
+    /// VIS code.
+    fn mode_specification_by_line_time(
+        &self,
+        line_time: f32,
+    ) -> Result<ModeSpecification, ModeSelectError> {
+        Err(ModeSelectError::UnknownLineTime { line_time })
+    }
 }
 
 impl<T> SelectMode for &T
@@ -561,6 +608,14 @@ where
     ) -> Result<ModeSpecification, ModeSelectError> {
         (&**self).mode_specification_with_parity(vis_code, parity)
     }
+
+    #[inline]
+    fn mode_specification_by_line_time(
+        &self,
+        line_time: f32,
+    ) -> Result<ModeSpecification, ModeSelectError> {
+        (**self).mode_specification_by_line_time(line_time)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -572,6 +627,15 @@ impl SelectMode for DefaultModes {
             .copied()
             .ok_or_else(|| ModeSelectError::UnknownMode { vis_code })
     }
+
+    fn mode_specification_by_line_time(
+        &self,
+        line_time: f32,
+    ) -> Result<ModeSpecification, ModeSelectError> {
+        builtin_mode_by_line_time(line_time)
+            .copied()
+            .ok_or(ModeSelectError::UnknownLineTime { line_time })
+    }
 }
 
 impl SelectMode for ModeSpecification {
@@ -583,6 +647,18 @@ impl SelectMode for ModeSpecification {
             Err(ModeSelectError::UnknownMode { vis_code })
         }
     }
+
+    fn mode_specification_by_line_time(
+        &self,
+        line_time: f32,
+    ) -> Result<ModeSpecification, ModeSelectError> {
+        if self.matches_line_time(line_time) {
+            Ok(*self)
+        }
+        else {
+            Err(ModeSelectError::UnknownLineTime { line_time })
+        }
+    }
 }
 
 #[cfg(test)]
@@ -590,6 +666,7 @@ mod tests {
     use crate::modem::sstv::modes::{
         ModeSpecification,
         VisCode,
+        builtin_mode_by_line_time,
     };
 
     #[test]
@@ -620,4 +697,15 @@ mod tests {
         assert_eq!(ModeSpecification::P5.vis_code, VisCode(0x72));
         assert_eq!(ModeSpecification::P7.vis_code, VisCode(0x73));
     }
+
+    #[test]
+    fn it_selects_modes_by_line_time() {
+        let select = |line_time| builtin_mode_by_line_time(line_time).map(|mode| mode.short_name);
+        assert_eq!(select(0.2268), Some("M2"));
+        assert_eq!(select(0.4464), Some("M1"));
+        assert_eq!(select(0.1501), Some("R36"));
+        assert_eq!(select(0.805), Some("PD160"));
+        assert_eq!(select(0.818), Some("P7"));
+        assert_eq!(select(0.2), None);
+    }
 }
@@ -35,7 +35,7 @@ impl State {
                             *header_state = HeaderState::VisStop;
                         }
                     }
-                    HeaderState::VisStop => {
+                    HeaderState::VisStop | HeaderState::MeasureLineTime => {
                         state = State::Line {
                             y: 0,
                             line_state: LineState::Sync,
@@ -87,8 +87,13 @@ pub enum HeaderState {
     LeaderBreak,
     Leader2,
     VisStart,
-    VisBit { bit: u8 },
+    VisBit {
+        bit: u8,
+    },
     VisStop,
+    /// Not part of the header, but where the decoder goes if the VIS code is
+    /// bad, to detect the mode by its line time instead.
+    MeasureLineTime,
 }
 
 #[derive(Clone, Copy, Debug)]
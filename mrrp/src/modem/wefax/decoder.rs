@@ -0,0 +1,663 @@
+use std::{
+    f32::consts::TAU,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+
+use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    io::{
+        AsyncReadSamples,
+        GetSampleRate,
+        ReadBuf,
+        combinators::Scanner,
+    },
+    modem::{
+        fm,
+        sstv::image::FrameBufferMut,
+        wefax::{
+            BLACK_TONE,
+            DEFAULT_LINES_PER_MINUTE,
+            DEFAULT_MAX_LINES,
+            Ioc,
+            PHASING_PULSE_LENGTH,
+            START_TONE_IOC_288,
+            START_TONE_IOC_576,
+            STOP_TONE,
+            WHITE_TONE,
+        },
+    },
+    util::unlerp,
+};
+
+/// Bandwidth of the start and stop tone detector. This determines its block
+/// length.
+const TONE_BANDWIDTH: f32 = 25.0;
+
+/// Fraction of the signal energy that must be in a tone for it to be
+/// detected.
+const TONE_THRESHOLD: f32 = 0.5;
+
+/// Minimum duration of start and stop tones. The tones are transmitted for 5
+/// seconds, but we don't need to wait that long.
+const MIN_TONE_TIME: f32 = 1.0;
+
+/// Number of phasing lines used to find the line start.
+const PHASING_LINES: usize = 8;
+
+/// Tones that the [`ToneDetector`] listens for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tone {
+    Start(Ioc),
+    Stop,
+}
+
+impl Tone {
+    const ALL: [Self; 3] = [
+        Self::Start(Ioc::Ioc576),
+        Self::Start(Ioc::Ioc288),
+        Self::Stop,
+    ];
+
+    fn frequency(&self) -> f32 {
+        match self {
+            Self::Start(Ioc::Ioc576) => START_TONE_IOC_576,
+            Self::Start(Ioc::Ioc288) => START_TONE_IOC_288,
+            Self::Stop => STOP_TONE,
+        }
+    }
+}
+
+/// Detects start and stop tones in the demodulated luminance signal.
+///
+/// Runs a Goertzel filter per tone over blocks of samples, and compares the
+/// power in each tone to the total power in the block.
+#[derive(Clone, Debug)]
+struct ToneDetector {
+    coefficients: [f32; 3],
+    states: [[f32; 2]; 3],
+    energy: f32,
+    i: usize,
+    block_length: usize,
+}
+
+impl ToneDetector {
+    fn new(sample_rate: f32) -> Self {
+        Self {
+            coefficients: Tone::ALL.map(|tone| 2.0 * (TAU * tone.frequency() / sample_rate).cos()),
+            states: Default::default(),
+            energy: 0.0,
+            i: 0,
+            block_length: ((sample_rate / TONE_BANDWIDTH) as usize).max(1),
+        }
+    }
+
+    fn block_time(&self, sample_rate: f32) -> f32 {
+        self.block_length as f32 / sample_rate
+    }
+}
+
+impl Scanner<f32> for ToneDetector {
+    /// At the end of each block, this is `Some` with the detected tone, if
+    /// any.
+    type Output = Option<Option<Tone>>;
+
+    fn scan(&mut self, sample: f32) -> Self::Output {
+        for (coefficient, state) in self.coefficients.iter().zip(&mut self.states) {
+            let s = sample + coefficient * state[0] - state[1];
+            state[1] = state[0];
+            state[0] = s;
+        }
+        self.energy += sample * sample;
+
+        self.i += 1;
+        if self.i < self.block_length {
+            return None;
+        }
+
+        // for a pure sinusoid the goertzel power is (A * N / 2)^2 and the
+        // energy is A^2 * N / 2, so this normalizes to 1.
+        let norm = 0.5 * self.block_length as f32 * self.energy;
+        let mut detected = None;
+        if norm > 0.0 {
+            for ((tone, coefficient), state) in
+                Tone::ALL.iter().zip(&self.coefficients).zip(&self.states)
+            {
+                let power =
+                    state[0] * state[0] + state[1] * state[1] - coefficient * state[0] * state[1];
+                if power / norm > TONE_THRESHOLD {
+                    detected = Some(*tone);
+                }
+            }
+        }
+
+        self.states = Default::default();
+        self.energy = 0.0;
+        self.i = 0;
+
+        Some(detected)
+    }
+}
+
+/// Keeps track of the position within a line, in samples.
+#[derive(Clone, Copy, Debug)]
+struct LineClock {
+    samples_per_line: f64,
+    position: f64,
+}
+
+impl LineClock {
+    fn new(sample_rate: f32, lines_per_minute: f32) -> Self {
+        Self {
+            samples_per_line: 60.0 * f64::from(sample_rate) / f64::from(lines_per_minute),
+            position: 0.0,
+        }
+    }
+
+    /// Position within the line in the range `0..1`.
+    #[inline]
+    fn fraction(&self) -> f32 {
+        (self.position / self.samples_per_line) as f32
+    }
+
+    /// Moves to the next sample. Returns `true` if a new line started.
+    #[inline]
+    fn advance(&mut self) -> bool {
+        self.position += 1.0;
+        if self.position >= self.samples_per_line {
+            self.position -= self.samples_per_line;
+            true
+        }
+        else {
+            false
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Average {
+    sum: f32,
+    count: usize,
+}
+
+impl Average {
+    #[inline]
+    fn push(&mut self, value: f32) {
+        self.sum += value;
+        self.count += 1;
+    }
+
+    #[inline]
+    fn get(&self) -> Option<f32> {
+        (self.count > 0).then(|| self.sum / self.count as f32)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum State {
+    StartTone {
+        tone: Option<Ioc>,
+        num_blocks: usize,
+    },
+    Phasing {
+        ioc: Ioc,
+        clock: LineClock,
+        bins: Vec<f32>,
+        num_lines: usize,
+    },
+    Image {
+        ioc: Ioc,
+        clock: LineClock,
+        x: usize,
+        pixel: Average,
+        pulse: Average,
+        rest: Average,
+        /// Remaining phasing lines are skipped until the first image line.
+        started: bool,
+        stop_tone: Option<(usize, usize)>,
+    },
+}
+
+/// Information about a received transmission.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transmission {
+    pub ioc: Ioc,
+
+    /// Number of lines written to the frame buffer.
+    pub num_lines: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Receiver {
+    sample_rate: f32,
+    lines_per_minute: f32,
+    max_lines: usize,
+    demodulator: fm::DifferentiateAndAccessPhase,
+    tone_detector: ToneDetector,
+    min_tone_blocks: usize,
+    state: State,
+    y: usize,
+}
+
+impl Receiver {
+    fn new(sample_rate: f32) -> Self {
+        let tone_detector = ToneDetector::new(sample_rate);
+        let min_tone_blocks =
+            (MIN_TONE_TIME / tone_detector.block_time(sample_rate)).ceil() as usize;
+
+        Self {
+            sample_rate,
+            lines_per_minute: DEFAULT_LINES_PER_MINUTE,
+            max_lines: DEFAULT_MAX_LINES,
+            demodulator: fm::DifferentiateAndAccessPhase::new(sample_rate, 1.0),
+            tone_detector,
+            min_tone_blocks,
+            state: State::StartTone {
+                tone: None,
+                num_blocks: 0,
+            },
+            y: 0,
+        }
+    }
+
+    fn ioc(&self) -> Option<Ioc> {
+        match &self.state {
+            State::StartTone { .. } => None,
+            State::Phasing { ioc, .. } | State::Image { ioc, .. } => Some(*ioc),
+        }
+    }
+
+    /// Processes a sample. Returns the transmission when it's complete.
+    fn push<F>(&mut self, sample: Complex<f32>, frame_buffer: &mut F) -> Option<Transmission>
+    where
+        F: FrameBufferMut,
+    {
+        let frequency = self.demodulator.scan(sample);
+        let luminance = unlerp(frequency, BLACK_TONE, WHITE_TONE).clamp(0.0, 1.0);
+        let tone = self.tone_detector.scan(luminance - 0.5);
+
+        match &mut self.state {
+            State::StartTone {
+                tone: current,
+                num_blocks,
+            } => {
+                match tone {
+                    None => {}
+                    Some(Some(Tone::Start(ioc))) => {
+                        if *current == Some(ioc) {
+                            *num_blocks += 1;
+                        }
+                        else {
+                            *current = Some(ioc);
+                            *num_blocks = 1;
+                        }
+                    }
+                    Some(_) => {
+                        if let Some(ioc) = *current
+                            && *num_blocks >= self.min_tone_blocks
+                        {
+                            tracing::debug!(?ioc, "start tone");
+
+                            let clock = LineClock::new(self.sample_rate, self.lines_per_minute);
+                            self.state = State::Phasing {
+                                ioc,
+                                clock,
+                                bins: vec![0.0; clock.samples_per_line.ceil() as usize],
+                                num_lines: 0,
+                            };
+                        }
+                        else {
+                            *current = None;
+                            *num_blocks = 0;
+                        }
+                    }
+                }
+            }
+            State::Phasing {
+                ioc,
+                clock,
+                bins,
+                num_lines,
+            } => {
+                let index = (clock.position as usize).min(bins.len() - 1);
+                bins[index] += luminance;
+
+                if clock.advance() {
+                    *num_lines += 1;
+
+                    if *num_lines == PHASING_LINES {
+                        let line_start = find_phasing_pulse(bins, clock.samples_per_line);
+                        tracing::debug!(line_start, "phasing");
+
+                        let mut clock = *clock;
+                        clock.position =
+                            (clock.position - line_start).rem_euclid(clock.samples_per_line);
+
+                        frame_buffer.set_size(ioc.pixels_per_line(), self.max_lines);
+
+                        self.state = State::Image {
+                            ioc: *ioc,
+                            clock,
+                            x: 0,
+                            pixel: Average::default(),
+                            pulse: Average::default(),
+                            rest: Average::default(),
+                            started: false,
+                            stop_tone: None,
+                        };
+                    }
+                }
+            }
+            State::Image {
+                ioc,
+                clock,
+                x,
+                pixel,
+                pulse,
+                rest,
+                started,
+                stop_tone,
+            } => {
+                match tone {
+                    None => {}
+                    Some(Some(Tone::Stop)) => {
+                        let (_, num_blocks) = stop_tone.get_or_insert((self.y, 0));
+                        *num_blocks += 1;
+
+                        if *num_blocks >= self.min_tone_blocks {
+                            let (num_lines, _) = stop_tone.unwrap();
+                            tracing::debug!(num_lines, "stop tone");
+                            return Some(Transmission {
+                                ioc: *ioc,
+                                num_lines,
+                            });
+                        }
+                    }
+                    Some(_) => *stop_tone = None,
+                }
+
+                let pixels_per_line = ioc.pixels_per_line();
+                let fraction = clock.fraction();
+
+                let pixel_x =
+                    ((fraction * pixels_per_line as f32) as usize).min(pixels_per_line - 1);
+                if pixel_x != *x {
+                    write_pixel(frame_buffer, *x, self.y, pixel);
+                    *x = pixel_x;
+                }
+                pixel.push(luminance);
+
+                if fraction < PHASING_PULSE_LENGTH {
+                    pulse.push(luminance);
+                }
+                else {
+                    rest.push(luminance);
+                }
+
+                if clock.advance() {
+                    write_pixel(frame_buffer, *x, self.y, pixel);
+                    *x = 0;
+
+                    if !*started {
+                        let is_phasing_line = match (pulse.get(), rest.get()) {
+                            (Some(pulse), Some(rest)) => pulse > 0.5 && rest < 0.5,
+                            // partial line right after phasing
+                            (None, _) => true,
+                            (Some(_), None) => false,
+                        };
+                        *started = !is_phasing_line;
+                    }
+
+                    if *started {
+                        self.y += 1;
+                        if self.y == self.max_lines {
+                            return Some(Transmission {
+                                ioc: *ioc,
+                                num_lines: self.y,
+                            });
+                        }
+                    }
+
+                    *pulse = Average::default();
+                    *rest = Average::default();
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn write_pixel<F>(frame_buffer: &mut F, x: usize, y: usize, pixel: &mut Average)
+where
+    F: FrameBufferMut,
+{
+    if let Some(value) = pixel.get() {
+        frame_buffer.set_gray(x, y, (value * 255.0).clamp(0.0, 255.0) as u8);
+    }
+    *pixel = Average::default();
+}
+
+/// Finds the start of the white phasing pulse in the luminance accumulated
+/// over several phasing lines. The pulse marks the start of a line.
+fn find_phasing_pulse(bins: &[f32], samples_per_line: f64) -> f64 {
+    let window = ((samples_per_line * f64::from(PHASING_PULSE_LENGTH)) as usize).max(1);
+
+    let mut sum: f32 = bins[..window].iter().sum();
+    let mut best = (sum, 0);
+
+    for start in 1..bins.len() {
+        sum += bins[(start + window - 1) % bins.len()] - bins[start - 1];
+        if sum > best.0 {
+            best = (sum, start);
+        }
+    }
+
+    best.1 as f64
+}
+
+pin_project! {
+    /// Decodes a WEFAX transmission into a frame buffer.
+    ///
+    /// The input is the complex audio signal. The frame buffer is resized to
+    /// the width of the image and the maximum number of lines once the start
+    /// tone was received, and lines are written as they're received. The
+    /// future resolves when the stop tone was received or the maximum number
+    /// of lines is reached.
+    #[derive(Clone, Debug)]
+    pub struct WefaxDecoder<R, F> {
+        #[pin]
+        input: R,
+        frame_buffer: F,
+        receiver: Receiver,
+        done: bool,
+    }
+}
+
+impl<R, F> WefaxDecoder<R, F>
+where
+    R: GetSampleRate,
+{
+    pub fn new(input: R, frame_buffer: F) -> Self {
+        let receiver = Receiver::new(input.sample_rate());
+        Self {
+            input,
+            frame_buffer,
+            receiver,
+            done: false,
+        }
+    }
+}
+
+impl<R, F> WefaxDecoder<R, F> {
+    pub fn with_lines_per_minute(mut self, lines_per_minute: f32) -> Self {
+        self.receiver.lines_per_minute = lines_per_minute;
+        self
+    }
+
+    /// The frame buffer is allocated with this height.
+    pub fn with_max_lines(mut self, max_lines: usize) -> Self {
+        self.receiver.max_lines = max_lines;
+        self
+    }
+
+    /// The IOC, once the start tone was received.
+    #[inline]
+    pub fn ioc(&self) -> Option<Ioc> {
+        self.receiver.ioc()
+    }
+
+    /// Number of complete lines written to the frame buffer so far.
+    #[inline]
+    pub fn num_lines(&self) -> usize {
+        self.receiver.y
+    }
+
+    #[inline]
+    pub fn frame_buffer(&self) -> &F {
+        &self.frame_buffer
+    }
+}
+
+impl<R, F> Future for WefaxDecoder<R, F>
+where
+    R: AsyncReadSamples<Complex<f32>>,
+    F: FrameBufferMut,
+{
+    type Output = Result<Transmission, DecodeError<R::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        assert!(!*this.done, "polled after completion");
+
+        let mut samples = [Complex::default(); 256];
+
+        loop {
+            let mut read_buf = ReadBuf::new(&mut samples[..]);
+            ready!(this.input.as_mut().poll_read_samples(cx, &mut read_buf))
+                .map_err(DecodeError::Stream)?;
+            if read_buf.filled().is_empty() {
+                return Poll::Ready(Err(DecodeError::Eof));
+            }
+
+            for sample in read_buf.filled() {
+                if let Some(transmission) = this.receiver.push(*sample, this.frame_buffer) {
+                    *this.done = true;
+                    return Poll::Ready(Ok(transmission));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("wefax decoder error")]
+pub enum DecodeError<S> {
+    Stream(S),
+    Eof,
+}
+
+impl<S: ClassifyError> ClassifyError for DecodeError<S> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            DecodeError::Stream(error) => error.error_kind(),
+            DecodeError::Eof => ErrorKind::Eof,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use futures_util::FutureExt;
+    use image::RgbImage;
+    use num_complex::Complex;
+
+    use super::WefaxDecoder;
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+        },
+        modem::wefax::{
+            BLACK_TONE,
+            Ioc,
+            START_TONE_IOC_576,
+            STOP_TONE,
+            WHITE_TONE,
+        },
+        util::lerp,
+    };
+
+    const SAMPLE_RATE: f32 = 8000.0;
+    const SAMPLES_PER_LINE: usize = 4000;
+
+    fn modulate(luminance: impl IntoIterator<Item = f32>) -> Vec<Complex<f32>> {
+        let mut phase = 0.0f32;
+        luminance
+            .into_iter()
+            .map(|luminance| {
+                phase = (phase + TAU * lerp(luminance, BLACK_TONE, WHITE_TONE) / SAMPLE_RATE) % TAU;
+                Complex::from_polar(1.0, phase)
+            })
+            .collect()
+    }
+
+    fn tone(frequency: f32, seconds: f32) -> impl Iterator<Item = f32> {
+        (0..(seconds * SAMPLE_RATE) as usize).map(move |i| {
+            if (2.0 * frequency * i as f32 / SAMPLE_RATE) as usize % 2 == 0 {
+                1.0
+            }
+            else {
+                0.0
+            }
+        })
+    }
+
+    #[test]
+    fn it_decodes_a_transmission() {
+        let pulse_length = SAMPLES_PER_LINE / 20;
+        let phasing_line =
+            (0..SAMPLES_PER_LINE).map(move |i| if i < pulse_length { 1.0 } else { 0.0 });
+        let image_line =
+            (0..SAMPLES_PER_LINE).map(|i| if i < SAMPLES_PER_LINE / 2 { 0.0 } else { 1.0 });
+
+        let luminance = tone(START_TONE_IOC_576, 2.0)
+            .chain(std::iter::repeat_n(0.0, 1234))
+            .chain(std::iter::repeat_n(phasing_line, 12).flatten())
+            .chain(std::iter::repeat_n(image_line, 5).flatten())
+            .chain(tone(STOP_TONE, 2.0));
+        let signal = modulate(luminance);
+
+        let mut decoder = WefaxDecoder::new(
+            Cursor::new(signal).with_sample_rate(SAMPLE_RATE),
+            RgbImage::default(),
+        )
+        .with_max_lines(10);
+        let transmission = (&mut decoder)
+            .now_or_never()
+            .expect("test stream pending")
+            .expect("decoder error");
+
+        assert_eq!(transmission.ioc, Ioc::Ioc576);
+        assert_eq!(transmission.num_lines, 5);
+
+        let image = decoder.frame_buffer();
+        assert_eq!(image.width() as usize, Ioc::Ioc576.pixels_per_line());
+        for y in 0..5 {
+            assert!(image.get_pixel(300, y).0[0] < 32);
+            assert!(image.get_pixel(1500, y).0[0] > 224);
+        }
+    }
+}
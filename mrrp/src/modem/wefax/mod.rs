@@ -0,0 +1,83 @@
+//! HF weather fax (WEFAX)
+//!
+//! Images are transmitted line by line as a frequency modulated audio tone,
+//! where 1500 Hz is black and 2300 Hz is white. A transmission starts with a
+//! start tone that selects the index of cooperation (IOC), followed by phasing
+//! lines that are used to find the start of a line, the image itself, and a
+//! stop tone.
+//!
+//! The decoded image is written to a
+//! [`FrameBufferMut`][crate::modem::sstv::image::FrameBufferMut], like the
+//! SSTV decoder does.
+//!
+//! # References
+//!
+//! - WMO-No. 386, Manual on the Global Telecommunication System, Attachment
+//!   II-8
+//! - <https://en.wikipedia.org/wiki/Radiofax>
+
+mod decoder;
+
+use std::f32::consts::PI;
+
+pub use decoder::{
+    DecodeError,
+    Transmission,
+    WefaxDecoder,
+};
+
+pub const BLACK_TONE: f32 = 1500.0;
+pub const WHITE_TONE: f32 = 2300.0;
+
+/// Start tone for IOC 576. The start tone alternates between black and white
+/// at this rate.
+pub const START_TONE_IOC_576: f32 = 300.0;
+
+/// Start tone for IOC 288.
+pub const START_TONE_IOC_288: f32 = 675.0;
+
+pub const STOP_TONE: f32 = 450.0;
+
+pub const DEFAULT_LINES_PER_MINUTE: f32 = 120.0;
+
+/// Default height of the frame buffer. At 120 lines per minute this is 12.5
+/// minutes.
+pub const DEFAULT_MAX_LINES: usize = 1500;
+
+/// Length of the white pulse at the start of each phasing line, relative to
+/// the line length.
+pub const PHASING_PULSE_LENGTH: f32 = 0.05;
+
+/// Index of cooperation
+///
+/// This determines the horizontal resolution of the image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Ioc {
+    #[default]
+    Ioc576,
+    Ioc288,
+}
+
+impl Ioc {
+    #[inline]
+    pub fn index_of_cooperation(&self) -> u32 {
+        match self {
+            Self::Ioc576 => 576,
+            Self::Ioc288 => 288,
+        }
+    }
+
+    /// Number of pixels per line, which is the IOC times pi.
+    #[inline]
+    pub fn pixels_per_line(&self) -> usize {
+        (self.index_of_cooperation() as f32 * PI).round() as usize
+    }
+
+    #[inline]
+    pub fn start_tone(&self) -> f32 {
+        match self {
+            Self::Ioc576 => START_TONE_IOC_576,
+            Self::Ioc288 => START_TONE_IOC_288,
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Commonly used traits and types
+//!
+//! Most pipelines need a handful of extension traits in scope. Importing the
+//! prelude brings them in at once:
+//!
+//! ```
+//! use mrrp::prelude::*;
+//! ```
+
+pub use num_complex::Complex;
+
+pub use crate::{
+    complex_sinusoid,
+    filter::design::{
+        FilterDesign,
+        Normalize,
+    },
+    from_wav,
+    io::{
+        AsyncReadSamples,
+        AsyncReadSamplesExt,
+        AsyncWriteSamples,
+        AsyncWriteSamplesExt,
+        Cursor,
+        FiniteStream,
+        GetSampleIndexMap,
+        GetSampleRate,
+        StreamLength,
+        combinators::{
+            GroupDelay,
+            Scanner,
+            ScannerExt,
+        },
+    },
+    sample::{
+        FromSample,
+        IntoSample,
+        Sample,
+    },
+    sine,
+    source::SignalGenerator,
+};
@@ -0,0 +1,437 @@
+//! Block floating point codec for IQ samples
+//!
+//! Raw `Complex<f32>` samples take 8 bytes each, which is a lot to send over
+//! the network or to record. Plain 8-bit samples are 4 times smaller, but
+//! weak signals lose most of their resolution. This codec splits the stream
+//! into blocks, and gives each block one shared exponent, so every block uses
+//! the full range of the mantissas.
+//!
+//! Measured on white noise (see the tests), the quantization noise is about
+//! 40 dB below the signal with 8-bit mantissas, and about 64 dB below it with
+//! 12-bit mantissas, independent of the signal level. With the default block
+//! size of 64 samples that's 2.05 or 3.05 bytes per sample.
+//!
+//! # Format
+//!
+//! The stream starts with the magic bytes `BFP1` and one byte with the number
+//! of mantissa bits (8 or 12). Then blocks follow, each with:
+//!
+//! - the number of samples, `u16` little endian,
+//! - the exponent, `i8`,
+//! - the samples, real part first. 8-bit mantissas are one `i8` each. 12-bit
+//!   mantissas are packed in pairs into 3 bytes, `re << 12 | im`, little
+//!   endian.
+//!
+//! A sample is `mantissa * 2^exponent`.
+
+use num_complex::Complex;
+
+/// Magic bytes at the start of a stream.
+pub const MAGIC: [u8; 4] = *b"BFP1";
+
+/// Length of the stream header.
+pub const HEADER_SIZE: usize = MAGIC.len() + 1;
+
+/// Length of a block header.
+const BLOCK_HEADER_SIZE: usize = 3;
+
+pub const DEFAULT_BLOCK_SIZE: usize = 64;
+
+/// Exponents are kept in this range, so that `2^exponent` and its inverse
+/// are normal `f32`s.
+const MIN_EXPONENT: i32 = -120;
+const MAX_EXPONENT: i32 = 120;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BfpError {
+    #[error("not a block floating point stream")]
+    InvalidMagic,
+
+    #[error("unsupported mantissa size: {bits} bits")]
+    UnsupportedMantissaBits { bits: u8 },
+
+    #[error("block with {num_samples} samples is larger than the maximum of {max}")]
+    BlockTooLarge { num_samples: usize, max: usize },
+}
+
+/// Size of the mantissas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MantissaBits {
+    Eight,
+    #[default]
+    Twelve,
+}
+
+impl MantissaBits {
+    #[inline]
+    pub fn bits(&self) -> u8 {
+        match self {
+            Self::Eight => 8,
+            Self::Twelve => 12,
+        }
+    }
+
+    pub fn from_bits(bits: u8) -> Result<Self, BfpError> {
+        match bits {
+            8 => Ok(Self::Eight),
+            12 => Ok(Self::Twelve),
+            _ => Err(BfpError::UnsupportedMantissaBits { bits }),
+        }
+    }
+
+    /// Largest magnitude of a mantissa.
+    #[inline]
+    fn max_mantissa(&self) -> i32 {
+        (1 << (self.bits() - 1)) - 1
+    }
+
+    /// Encoded size of a sample in bytes.
+    #[inline]
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            Self::Eight => 2,
+            Self::Twelve => 3,
+        }
+    }
+}
+
+/// Encodes samples into a block floating point stream.
+///
+/// Samples are buffered until a block is full. Call [`flush`][Self::flush]
+/// to write a partial block.
+#[derive(Clone, Debug)]
+pub struct BfpEncoder {
+    mantissa_bits: MantissaBits,
+    block_size: usize,
+    block: Vec<Complex<f32>>,
+    header_written: bool,
+}
+
+impl BfpEncoder {
+    pub fn new(mantissa_bits: MantissaBits) -> Self {
+        Self {
+            mantissa_bits,
+            block_size: DEFAULT_BLOCK_SIZE,
+            block: Vec::with_capacity(DEFAULT_BLOCK_SIZE),
+            header_written: false,
+        }
+    }
+
+    /// Sets the number of samples per block.
+    ///
+    /// Smaller blocks follow the signal level more closely, but each block
+    /// adds 3 bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is 0 or larger than [`u16::MAX`].
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        assert!(
+            (1..=usize::from(u16::MAX)).contains(&block_size),
+            "invalid block size: {block_size}"
+        );
+        self.block_size = block_size;
+        self
+    }
+
+    #[inline]
+    pub fn mantissa_bits(&self) -> MantissaBits {
+        self.mantissa_bits
+    }
+
+    /// Encodes `samples`, appending all full blocks to `output`.
+    pub fn encode(&mut self, mut samples: &[Complex<f32>], output: &mut Vec<u8>) {
+        self.write_header(output);
+
+        while !samples.is_empty() {
+            let n = samples.len().min(self.block_size - self.block.len());
+            self.block.extend_from_slice(&samples[..n]);
+            samples = &samples[n..];
+
+            if self.block.len() == self.block_size {
+                encode_block(self.mantissa_bits, &self.block, output);
+                self.block.clear();
+            }
+        }
+    }
+
+    /// Appends the buffered samples to `output` as a partial block.
+    pub fn flush(&mut self, output: &mut Vec<u8>) {
+        self.write_header(output);
+
+        if !self.block.is_empty() {
+            encode_block(self.mantissa_bits, &self.block, output);
+            self.block.clear();
+        }
+    }
+
+    fn write_header(&mut self, output: &mut Vec<u8>) {
+        if !self.header_written {
+            output.extend_from_slice(&MAGIC);
+            output.push(self.mantissa_bits.bits());
+            self.header_written = true;
+        }
+    }
+}
+
+fn encode_block(mantissa_bits: MantissaBits, block: &[Complex<f32>], output: &mut Vec<u8>) {
+    let max_mantissa = mantissa_bits.max_mantissa();
+    let peak = block
+        .iter()
+        .map(|sample| sample.re.abs().max(sample.im.abs()))
+        .fold(0.0f32, f32::max);
+
+    // the smallest exponent at which the peak still fits into the mantissa
+    let mut exponent = if peak > 0.0 && peak.is_finite() {
+        ((peak / max_mantissa as f32).log2().ceil() as i32).clamp(MIN_EXPONENT, MAX_EXPONENT)
+    }
+    else {
+        MIN_EXPONENT
+    };
+    if exponent < MAX_EXPONENT && peak * 2.0f32.powi(-exponent) > max_mantissa as f32 {
+        exponent += 1;
+    }
+    let scale = 2.0f32.powi(-exponent);
+    let quantize = |x: f32| ((x * scale).round() as i32).clamp(-max_mantissa, max_mantissa);
+
+    output.extend_from_slice(&(block.len() as u16).to_le_bytes());
+    output.push(exponent as i8 as u8);
+
+    for sample in block {
+        let re = quantize(sample.re);
+        let im = quantize(sample.im);
+        match mantissa_bits {
+            MantissaBits::Eight => {
+                output.push(re as i8 as u8);
+                output.push(im as i8 as u8);
+            }
+            MantissaBits::Twelve => {
+                let packed = ((re as u32 & 0xfff) << 12) | (im as u32 & 0xfff);
+                output.extend_from_slice(&packed.to_le_bytes()[..3]);
+            }
+        }
+    }
+}
+
+/// Decodes a block floating point stream.
+#[derive(Clone, Debug, Default)]
+pub struct BfpDecoder {
+    mantissa_bits: Option<MantissaBits>,
+    max_block_size: Option<usize>,
+}
+
+impl BfpDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rejects blocks with more than `max_block_size` samples, e.g. to limit
+    /// how much a peer on the network can make the decoder buffer.
+    pub fn with_max_block_size(mut self, max_block_size: usize) -> Self {
+        self.max_block_size = Some(max_block_size);
+        self
+    }
+
+    /// The mantissa size, once the stream header was decoded.
+    #[inline]
+    pub fn mantissa_bits(&self) -> Option<MantissaBits> {
+        self.mantissa_bits
+    }
+
+    /// Decodes all complete blocks at the start of `input`, appending the
+    /// samples to `output`.
+    ///
+    /// Returns the number of bytes consumed. The rest of `input` is an
+    /// incomplete block, which must be passed again with more data appended.
+    pub fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<Complex<f32>>,
+    ) -> Result<usize, BfpError> {
+        let mut position = 0;
+
+        let mantissa_bits = match self.mantissa_bits {
+            Some(mantissa_bits) => mantissa_bits,
+            None => {
+                let Some(header) = input.get(..HEADER_SIZE)
+                else {
+                    return Ok(0);
+                };
+                if header[..MAGIC.len()] != MAGIC {
+                    return Err(BfpError::InvalidMagic);
+                }
+                let mantissa_bits = MantissaBits::from_bits(header[MAGIC.len()])?;
+                self.mantissa_bits = Some(mantissa_bits);
+                position = HEADER_SIZE;
+                mantissa_bits
+            }
+        };
+
+        while let Some(header) = input.get(position..position + BLOCK_HEADER_SIZE) {
+            let num_samples = usize::from(u16::from_le_bytes([header[0], header[1]]));
+            if let Some(max) = self.max_block_size
+                && num_samples > max
+            {
+                return Err(BfpError::BlockTooLarge { num_samples, max });
+            }
+
+            let exponent = i32::from(header[2] as i8).clamp(MIN_EXPONENT, MAX_EXPONENT);
+            let scale = 2.0f32.powi(exponent);
+
+            let start = position + BLOCK_HEADER_SIZE;
+            let end = start + num_samples * mantissa_bits.bytes_per_sample();
+            let Some(payload) = input.get(start..end)
+            else {
+                break;
+            };
+
+            match mantissa_bits {
+                MantissaBits::Eight => {
+                    output.extend(payload.chunks_exact(2).map(|iq| {
+                        Complex::new(
+                            f32::from(iq[0] as i8) * scale,
+                            f32::from(iq[1] as i8) * scale,
+                        )
+                    }));
+                }
+                MantissaBits::Twelve => {
+                    output.extend(payload.chunks_exact(3).map(|iq| {
+                        let packed = u32::from_le_bytes([iq[0], iq[1], iq[2], 0]);
+                        // sign-extend the 12-bit halves
+                        let re = ((packed << 8) as i32) >> 20;
+                        let im = ((packed << 20) as i32) >> 20;
+                        Complex::new(re as f32 * scale, im as f32 * scale)
+                    }));
+                }
+            }
+
+            position = end;
+        }
+
+        Ok(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex;
+
+    use super::{
+        BfpDecoder,
+        BfpEncoder,
+        BfpError,
+        DEFAULT_BLOCK_SIZE,
+        MantissaBits,
+    };
+
+    /// Pseudo-random complex samples, uniform in [-amplitude, amplitude].
+    fn noise(n: usize, amplitude: f32) -> Vec<Complex<f32>> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            amplitude * ((state >> 40) as f32 / (1u64 << 23) as f32 - 1.0)
+        };
+        (0..n).map(|_| Complex::new(next(), next())).collect()
+    }
+
+    fn round_trip(mantissa_bits: MantissaBits, samples: &[Complex<f32>]) -> Vec<Complex<f32>> {
+        let mut encoder = BfpEncoder::new(mantissa_bits);
+        let mut encoded = vec![];
+        // in uneven chunks, to check that blocks are assembled across calls
+        for chunk in samples.chunks(100) {
+            encoder.encode(chunk, &mut encoded);
+        }
+        encoder.flush(&mut encoded);
+
+        let mut decoder = BfpDecoder::new();
+        let mut decoded = vec![];
+        let consumed = decoder.decode(&encoded, &mut decoded).unwrap();
+        assert_eq!(consumed, encoded.len());
+        decoded
+    }
+
+    fn snr(signal: &[Complex<f32>], decoded: &[Complex<f32>]) -> f32 {
+        assert_eq!(signal.len(), decoded.len());
+        let signal_power = signal.iter().map(|x| x.norm_sqr()).sum::<f32>();
+        let noise_power = signal
+            .iter()
+            .zip(decoded)
+            .map(|(x, y)| (x - y).norm_sqr())
+            .sum::<f32>();
+        10.0 * (signal_power / noise_power).log10()
+    }
+
+    #[test]
+    fn it_has_the_documented_snr() {
+        let signal = noise(10_000, 1.0);
+
+        let snr_8 = snr(&signal, &round_trip(MantissaBits::Eight, &signal));
+        let snr_12 = snr(&signal, &round_trip(MantissaBits::Twelve, &signal));
+        assert!(snr_8 > 40.0, "8 bit: {snr_8} dB");
+        assert!(snr_12 > 64.0, "12 bit: {snr_12} dB");
+    }
+
+    #[test]
+    fn it_keeps_the_snr_of_weak_signals() {
+        // a strong burst followed by a signal 80 dB weaker. the weak signal
+        // starts with a new block, otherwise the block it shares with the burst
+        // is lost.
+        let burst_length = 16 * DEFAULT_BLOCK_SIZE;
+        let mut signal = noise(burst_length, 1.0);
+        signal.extend(noise(1_000, 1e-4));
+
+        let decoded = round_trip(MantissaBits::Eight, &signal);
+        let snr_weak = snr(&signal[burst_length..], &decoded[burst_length..]);
+        assert!(snr_weak > 40.0, "weak signal: {snr_weak} dB");
+    }
+
+    #[test]
+    fn it_decodes_incrementally() {
+        let signal = noise(1_000, 0.5);
+        let mut encoder = BfpEncoder::new(MantissaBits::Twelve).with_block_size(32);
+        let mut encoded = vec![];
+        encoder.encode(&signal, &mut encoded);
+        encoder.flush(&mut encoded);
+
+        let mut expected = vec![];
+        BfpDecoder::new().decode(&encoded, &mut expected).unwrap();
+
+        // one byte at a time
+        let mut decoder = BfpDecoder::new();
+        let mut decoded = vec![];
+        let mut input = vec![];
+        for byte in encoded {
+            input.push(byte);
+            let consumed = decoder.decode(&input, &mut decoded).unwrap();
+            input.drain(..consumed);
+        }
+
+        assert!(input.is_empty());
+        assert_eq!(decoded, expected);
+        assert!(snr(&signal, &decoded) > 64.0);
+    }
+
+    #[test]
+    fn it_rejects_invalid_streams() {
+        let mut output = vec![];
+        assert!(matches!(
+            BfpDecoder::new().decode(b"RIFF\x08", &mut output),
+            Err(BfpError::InvalidMagic)
+        ));
+        assert!(matches!(
+            BfpDecoder::new().decode(b"BFP1\x10", &mut output),
+            Err(BfpError::UnsupportedMantissaBits { bits: 16 })
+        ));
+        assert!(matches!(
+            BfpDecoder::new()
+                .with_max_block_size(64)
+                .decode(b"BFP1\x08\x00\x01\x00", &mut output),
+            Err(BfpError::BlockTooLarge {
+                num_samples: 256,
+                max: 64
+            })
+        ));
+    }
+}
@@ -3,6 +3,7 @@ use num_complex::Complex;
 use crate::sample::{
     FromSample,
     types::{
+        I12,
         I24,
         I48,
         U24,
@@ -18,6 +19,13 @@ macro_rules! conversion_fn {
         }
     };
 
+    ($Rep:ty, $s:ident to_i12 { $body:expr }) => {
+        #[inline]
+        pub fn to_i12($s: $Rep) -> I12 {
+            $body
+        }
+    };
+
     ($Rep:ty, $s:ident to_i16 { $body:expr }) => {
         #[inline]
         pub fn to_i16($s: $Rep) -> i16 {
@@ -229,6 +237,44 @@ conversions!(i16, i16 {
     }
 });
 
+conversions!(I12, i12 {
+    s to_i8 { (s.inner() >> 4) as i8 }
+    s to_i16 { s.inner() << 4 }
+    s to_i32 { (s.inner() as i32) << 20 }
+    s to_u8 {
+        super::i8::to_u8(to_i8(s))
+    }
+    s to_u16 {
+        super::i16::to_u16(to_i16(s))
+    }
+    s to_f32 {
+        s.inner() as f32 / 2_048.0
+    }
+    s to_f64 {
+        s.inner() as f64 / 2_048.0
+    }
+});
+
+// Conversions to `I12` only exist from the types that 12-bit samples are
+// usually converted from. They're in their own modules, because the modules
+// for these types are already defined above.
+conversions!(i8, i8_to_i12 {
+    s to_i12 { I12::new_unchecked((s as i16) << 4) }
+});
+
+conversions!(i16, i16_to_i12 {
+    s to_i12 { I12::new_unchecked(s >> 4) }
+});
+
+// assumes `-1.0 <= s < 1.0`, like the other float conversions
+conversions!(f32, f32_to_i12 {
+    s to_i12 { I12::new_unchecked((s * 2_048.0) as i16) }
+});
+
+conversions!(f64, f64_to_i12 {
+    s to_i12 { I12::new_unchecked((s * 2_048.0) as i16) }
+});
+
 conversions!(I24, i24 {
     s to_i8 { (s.inner() >> 16) as i8 }
     s to_i16 { (s.inner() >> 8) as i16 }
@@ -666,3 +712,15 @@ impl_from_sample! {f64, to_f64 from
     {u8:u8} {u16:u16} {U24:u24} {u32:u32} {U48:u48} {u64:u64}
     {f32:f32}
 }
+
+impl_from_sample! {I12, to_i12 from
+    {i8:i8_to_i12} {i16:i16_to_i12} {f32:f32_to_i12} {f64:f64_to_i12}
+}
+
+impl_from_sample! {i8, to_i8 from {I12:i12}}
+impl_from_sample! {i16, to_i16 from {I12:i12}}
+impl_from_sample! {i32, to_i32 from {I12:i12}}
+impl_from_sample! {u8, to_u8 from {I12:i12}}
+impl_from_sample! {u16, to_u16 from {I12:i12}}
+impl_from_sample! {f32, to_f32 from {I12:i12}}
+impl_from_sample! {f64, to_f64 from {I12:i12}}
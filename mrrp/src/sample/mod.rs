@@ -1,3 +1,4 @@
+pub mod bfp;
 mod conversion;
 mod types;
 
@@ -5,6 +6,7 @@ pub use num_complex::Complex;
 
 pub use self::types::{
     I11,
+    I12,
     I20,
     I24,
     I48,
@@ -85,6 +87,7 @@ macro_rules! impl_sample {
 // Expands to `Sample` implementations for all of the following types.
 impl_sample! {
     i8:  Signed: i8,  Float: f32, EQUILIBRIUM: 0,
+    I12: Signed: I12, Float: f32, EQUILIBRIUM: types::i12::EQUILIBRIUM,
     i16: Signed: i16, Float: f32, EQUILIBRIUM: 0,
     //I24: Signed: I24, Float: f32, EQUILIBRIUM: types::i24::EQUILIBRIUM,
     i32: Signed: i32, Float: f32, EQUILIBRIUM: 0,
@@ -3,6 +3,7 @@
 
 pub use self::{
     i11::I11,
+    i12::I12,
     i20::I20,
     i24::I24,
     i48::I48,
@@ -243,13 +244,45 @@ pub mod i11 {
     impl_neg!(I11);
 }
 
+pub mod i12 {
+    new_sample_type!(I12: i16, eq: 0, min: -2048, max: 2047, total: 4096,
+                     from: i8);
+    impl_neg!(I12);
+
+    impl I12 {
+        /// Unpacks an IQ pair from 3 bytes.
+        ///
+        /// This is the packed layout that many 12-bit SDRs use on the wire:
+        /// The low 8 bits of I, then the high 4 bits of I in the low nibble
+        /// and the low 4 bits of Q in the high nibble, then the high 8 bits of
+        /// Q.
+        #[inline]
+        pub fn unpack_pair(bytes: [u8; 3]) -> (I12, I12) {
+            let i = u16::from(bytes[0]) | (u16::from(bytes[1] & 0x0f) << 8);
+            let q = u16::from(bytes[1] >> 4) | (u16::from(bytes[2]) << 4);
+            // shift the sign bit into place, and back with sign extension
+            (I12((i << 4) as i16 >> 4), I12((q << 4) as i16 >> 4))
+        }
+
+        /// Packs an IQ pair into 3 bytes, the inverse of
+        /// [`unpack_pair`][Self::unpack_pair].
+        #[inline]
+        pub fn pack_pair(i: I12, q: I12) -> [u8; 3] {
+            let i = i.0 as u16 & 0x0fff;
+            let q = q.0 as u16 & 0x0fff;
+            [i as u8, ((i >> 8) | (q << 4)) as u8, (q >> 4) as u8]
+        }
+    }
+}
+
 pub mod i20 {
     use super::{
         I11,
+        I12,
         U11,
     };
     new_sample_type!(I20: i32, eq: 0, min: -524_288, max: 524_287, total: 1_048_576,
-                     from: i8, {I11:i16}, i16, u8, {U11:i16}, u16);
+                     from: i8, {I11:i16}, {I12:i16}, i16, u8, {U11:i16}, u16);
 }
 
 pub mod i24 {
@@ -0,0 +1,404 @@
+//! Archiving a session for re-processing later.
+//!
+//! Recording the raw wideband IQ continuously takes a lot of space, but only
+//! keeping the decoded output means a better decoder can't be tried on it
+//! later. An [`ArchiveSession`] does both: It records the decimated channels
+//! continuously, and snapshots of the raw IQ at a low duty cycle, e.g. 1 s
+//! every minute.
+//!
+//! Everything goes into one directory. Each stream is written with a
+//! [`TapInspector`], so it's a raw file with a JSON index next to it, and a
+//! `manifest.json` lists the streams.
+
+use std::{
+    fmt::Write as _,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Arc,
+    time::{
+        Duration,
+        SystemTime,
+    },
+};
+
+use bytemuck::Pod;
+use parking_lot::Mutex;
+
+use crate::{
+    frequency::Frequency,
+    io::{
+        GetSampleRate,
+        combinators::{
+            InspectWith,
+            Inspector,
+            TapHandle,
+            TapInspector,
+        },
+    },
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ArchiveConfig {
+    /// How long each snapshot of the raw IQ is.
+    pub raw_duration: Duration,
+
+    /// How often a snapshot of the raw IQ is taken.
+    pub raw_period: Duration,
+
+    /// Center frequency of the raw IQ.
+    pub center_frequency: Option<Frequency>,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            raw_duration: Duration::from_secs(1),
+            raw_period: Duration::from_secs(60),
+            center_frequency: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ManifestStream {
+    name: String,
+    file: String,
+    sample_rate: f32,
+    frequency: Option<Frequency>,
+    is_raw: bool,
+}
+
+#[derive(Debug)]
+struct Manifest {
+    path: PathBuf,
+    created: SystemTime,
+    config: ArchiveConfig,
+    streams: Vec<ManifestStream>,
+}
+
+impl Manifest {
+    fn write(&self) -> Result<(), std::io::Error> {
+        let created = self
+            .created
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let frequency = |frequency: Option<Frequency>| {
+            frequency.map_or_else(
+                || "null".to_owned(),
+                |frequency| frequency.as_hz().to_string(),
+            )
+        };
+
+        let mut json = String::new();
+        json.push_str("{\n");
+        writeln!(json, "  \"created\": {},", created.as_secs()).unwrap();
+        writeln!(
+            json,
+            "  \"center_frequency\": {},",
+            frequency(self.config.center_frequency)
+        )
+        .unwrap();
+        writeln!(
+            json,
+            "  \"raw_duration\": {},",
+            self.config.raw_duration.as_secs_f64()
+        )
+        .unwrap();
+        writeln!(
+            json,
+            "  \"raw_period\": {},",
+            self.config.raw_period.as_secs_f64()
+        )
+        .unwrap();
+        json.push_str("  \"streams\": [");
+        for (i, stream) in self.streams.iter().enumerate() {
+            json.push_str(if i == 0 { "\n" } else { ",\n" });
+            write!(
+                json,
+                "    {{ \"name\": \"{}\", \"kind\": \"{}\", \"file\": \"{}\", \"index\": \"{}.json\", \"sample_rate\": {}, \"frequency\": {} }}",
+                stream.name,
+                if stream.is_raw { "raw" } else { "channel" },
+                stream.file,
+                stream.file,
+                stream.sample_rate,
+                frequency(stream.frequency),
+            )
+            .unwrap();
+        }
+        if !self.streams.is_empty() {
+            json.push_str("\n  ");
+        }
+        json.push_str("]\n}\n");
+
+        std::fs::write(&self.path, json)
+    }
+}
+
+/// A directory with recordings of the raw IQ and the channels decoded from
+/// it. See the [module documentation][self].
+#[derive(Clone, Debug)]
+pub struct ArchiveSession {
+    directory: PathBuf,
+    manifest: Arc<Mutex<Manifest>>,
+}
+
+impl ArchiveSession {
+    /// Creates the session directory, if it doesn't exist yet, and writes an
+    /// empty manifest.
+    pub fn create(
+        directory: impl AsRef<Path>,
+        config: ArchiveConfig,
+    ) -> Result<Self, std::io::Error> {
+        let directory = directory.as_ref().to_owned();
+        std::fs::create_dir_all(&directory)?;
+
+        let manifest = Manifest {
+            path: directory.join("manifest.json"),
+            created: SystemTime::now(),
+            config,
+            streams: vec![],
+        };
+        manifest.write()?;
+
+        Ok(Self {
+            directory,
+            manifest: Arc::new(Mutex::new(manifest)),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Records snapshots of the raw IQ `stream` as it's read.
+    ///
+    /// The stream position is used as the clock, so the snapshots are evenly
+    /// spaced in the signal, even if the stream is read faster or slower than
+    /// real time.
+    pub fn record_raw<R, S>(
+        &self,
+        stream: R,
+    ) -> Result<InspectWith<R, DutyCycleInspector<S>>, std::io::Error>
+    where
+        R: GetSampleRate,
+        S: Pod,
+    {
+        let sample_rate = stream.sample_rate();
+        let config = self.manifest.lock().config;
+        let tap = self.add_stream("raw", sample_rate, config.center_frequency, true)?;
+
+        let period = (config.raw_period.as_secs_f64() * f64::from(sample_rate)) as u64;
+        let on = (config.raw_duration.as_secs_f64() * f64::from(sample_rate)) as u64;
+        Ok(InspectWith::new(
+            stream,
+            DutyCycleInspector::new(tap, on, period),
+        ))
+    }
+
+    /// Records all of a decimated channel `stream`, which is centered at
+    /// `frequency`.
+    ///
+    /// `name` must be unique within the session, and is used as the file
+    /// name.
+    pub fn record_channel<R, S>(
+        &self,
+        name: &str,
+        frequency: Frequency,
+        stream: R,
+    ) -> Result<InspectWith<R, TapInspector<S>>, std::io::Error>
+    where
+        R: GetSampleRate,
+        S: Pod,
+    {
+        let tap = self.add_stream(name, stream.sample_rate(), Some(frequency), false)?;
+        Ok(InspectWith::new(stream, tap))
+    }
+
+    fn add_stream<S: Pod>(
+        &self,
+        name: &str,
+        sample_rate: f32,
+        frequency: Option<Frequency>,
+        is_raw: bool,
+    ) -> Result<TapInspector<S>, std::io::Error> {
+        // the name is used for the file name, and written to the manifest
+        // without escaping
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid stream name: {name:?}"),
+            ));
+        }
+
+        let mut manifest = self.manifest.lock();
+        if manifest.streams.iter().any(|stream| stream.name == name) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("duplicate stream name: {name:?}"),
+            ));
+        }
+
+        let file = format!("{name}.bin");
+        let tap = TapInspector::create(self.directory.join(&file), u64::MAX)?
+            .with_sample_rate(sample_rate);
+
+        manifest.streams.push(ManifestStream {
+            name: name.to_owned(),
+            file,
+            sample_rate,
+            frequency,
+            is_raw,
+        });
+        manifest.write()?;
+
+        Ok(tap)
+    }
+}
+
+/// Inspector that passes only the first `on` samples of every `period` to a
+/// [`TapInspector`].
+#[derive(Debug)]
+pub struct DutyCycleInspector<S> {
+    tap: TapInspector<S>,
+    handle: TapHandle,
+    on: u64,
+    period: u64,
+    position: u64,
+}
+
+impl<S> DutyCycleInspector<S> {
+    pub fn new(tap: TapInspector<S>, on: u64, period: u64) -> Self {
+        assert!(period > 0, "duty cycle period must not be 0");
+        let handle = tap.handle();
+        Self {
+            tap,
+            handle,
+            on,
+            period,
+            position: 0,
+        }
+    }
+
+    pub fn handle(&self) -> TapHandle {
+        self.handle.clone()
+    }
+}
+
+impl<S: Pod> Inspector<S> for DutyCycleInspector<S> {
+    fn inspect(&mut self, mut samples: &[S]) {
+        // split the chunk where the tap turns on or off
+        while !samples.is_empty() {
+            let phase = self.position % self.period;
+            let (enabled, until) = if phase < self.on {
+                (true, self.on - phase)
+            }
+            else {
+                (false, self.period - phase)
+            };
+            let n = samples
+                .len()
+                .min(usize::try_from(until).unwrap_or(usize::MAX));
+
+            if enabled {
+                self.handle.enable();
+            }
+            else {
+                self.handle.disable();
+            }
+            self.tap.inspect(&samples[..n]);
+
+            self.position += n as u64;
+            samples = &samples[n..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::FutureExt;
+
+    use super::{
+        ArchiveConfig,
+        ArchiveSession,
+    };
+    use crate::{
+        frequency::Frequency,
+        io::{
+            AsyncReadSamplesExt,
+            Cursor,
+        },
+    };
+
+    #[test]
+    fn it_records_raw_snapshots_and_channels() {
+        let directory = std::env::temp_dir().join(format!("mrrp-archive-{}", std::process::id()));
+        let session = ArchiveSession::create(
+            &directory,
+            ArchiveConfig {
+                raw_duration: Duration::from_secs(1),
+                raw_period: Duration::from_secs(4),
+                center_frequency: Some(Frequency::from_mhz(100)),
+            },
+        )
+        .unwrap();
+
+        let input = (0..100).map(|i| i as f32).collect::<Vec<_>>();
+        let raw = Cursor::new(input.clone()).with_sample_rate(10.0);
+        let mut raw = session.record_raw(raw).unwrap();
+        let channel = Cursor::new(input.clone()).with_sample_rate(10.0);
+        let mut channel = session
+            .record_channel("channel", Frequency::from_mhz(101), channel)
+            .unwrap();
+        assert!(
+            session
+                .record_channel::<_, f32>(
+                    "channel",
+                    Frequency::from_mhz(101),
+                    Cursor::new(vec![0.0f32]).with_sample_rate(1.0)
+                )
+                .is_err()
+        );
+
+        let mut output = vec![];
+        raw.read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, input);
+        output.clear();
+        channel
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, input);
+        drop((raw, channel));
+
+        let read = |file| {
+            let bytes = std::fs::read(directory.join(file)).unwrap();
+            bytemuck::cast_slice::<u8, f32>(&bytes).to_vec()
+        };
+        let expected_raw = [0..10, 40..50, 80..90]
+            .into_iter()
+            .flatten()
+            .map(|i| i as f32)
+            .collect::<Vec<_>>();
+        assert_eq!(read("raw.bin"), expected_raw);
+        assert_eq!(read("channel.bin"), input);
+
+        let manifest = std::fs::read_to_string(directory.join("manifest.json")).unwrap();
+        assert!(manifest.contains("\"center_frequency\": 100000000"));
+        assert!(manifest.contains("\"name\": \"raw\""));
+        assert!(manifest.contains("\"frequency\": 101000000"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+//! Writing block floating point compressed IQ, e.g. to a socket.
+//!
+//! See [`sample::bfp`][crate::sample::bfp] for the format, and
+//! [`BfpAsyncReader`][crate::source::bfp::BfpAsyncReader] for the other end.
+
+use std::{
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+use tokio::io::AsyncWrite;
+
+use crate::{
+    io::AsyncWriteSamples,
+    sample::bfp::{
+        BfpEncoder,
+        MantissaBits,
+    },
+};
+
+pin_project! {
+    /// Compresses IQ samples and writes them to an [`AsyncWrite`].
+    ///
+    /// Samples are buffered until a block is full, so
+    /// [`flush`][crate::io::AsyncWriteSamplesExt::flush] must be called to
+    /// send a partial block, e.g. when latency matters.
+    #[derive(Debug)]
+    pub struct BfpAsyncWriter<W> {
+        #[pin]
+        writer: W,
+        encoder: BfpEncoder,
+        buffer: Vec<u8>,
+        write_pos: usize,
+    }
+}
+
+impl<W> BfpAsyncWriter<W> {
+    pub fn new(writer: W, mantissa_bits: MantissaBits) -> Self {
+        Self::with_encoder(writer, BfpEncoder::new(mantissa_bits))
+    }
+
+    pub fn with_encoder(writer: W, encoder: BfpEncoder) -> Self {
+        Self {
+            writer,
+            encoder,
+            buffer: vec![],
+            write_pos: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: AsyncWrite> BfpAsyncWriter<W> {
+    /// Writes all encoded bytes that are buffered.
+    fn poll_write_buffer(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        let mut this = self.project();
+
+        while *this.write_pos < this.buffer.len() {
+            let num_bytes_written = ready!(
+                this.writer
+                    .as_mut()
+                    .poll_write(cx, &this.buffer[*this.write_pos..])
+            )?;
+            if num_bytes_written == 0 {
+                return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+            }
+            *this.write_pos += num_bytes_written;
+        }
+
+        this.buffer.clear();
+        *this.write_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite> AsyncWriteSamples<Complex<f32>> for BfpAsyncWriter<W> {
+    type Error = std::io::Error;
+
+    fn poll_write_samples(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &[Complex<f32>],
+    ) -> Poll<Result<usize, Self::Error>> {
+        // only take new samples once the previous ones are written
+        ready!(self.as_mut().poll_write_buffer(cx))?;
+
+        let this = self.as_mut().project();
+        this.encoder.encode(buffer, this.buffer);
+
+        // the samples are taken either way, so it doesn't matter if this is
+        // pending
+        if let Poll::Ready(Err(error)) = self.poll_write_buffer(cx) {
+            return Poll::Ready(Err(error));
+        }
+
+        Poll::Ready(Ok(buffer.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_write_buffer(cx))?;
+
+        let this = self.as_mut().project();
+        this.encoder.flush(this.buffer);
+        ready!(self.as_mut().poll_write_buffer(cx))?;
+
+        self.project().writer.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        self.project().writer.poll_shutdown(cx)
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     fs::File,
     io::{
+        self,
         BufWriter,
         Seek,
         Write,
@@ -12,64 +13,181 @@ use std::{
         Context,
         Poll,
     },
+    time::Duration,
 };
 
 use num_complex::Complex;
 
-use crate::io::{
-    AsyncReadSamples,
-    AsyncReadSamplesExt,
-    AsyncWriteSamples,
-    ForwardError,
-    GetSampleRate,
+pub use crate::util::{
+    raw_iq::RawIqFormat,
+    riff::WavMetadata,
+    wav::{
+        WavSampleFormat,
+        WavSpec,
+    },
+};
+use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    frequency::Frequency,
+    io::{
+        AsyncReadSamples,
+        AsyncReadSamplesExt,
+        AsyncWriteSamples,
+        AsyncWriteSamplesExt,
+        ForwardError,
+        GetCenterFrequency,
+        GetSampleRate,
+    },
+    util::wav,
 };
 
 #[derive(Debug, thiserror::Error)]
 #[error("wav sink error")]
 pub enum Error {
-    Hound(#[from] hound::Error),
+    Io(#[from] io::Error),
     Closed,
+    UnexpectedChannelCount { channels: u16, expected: u16 },
+    UnexpectedSampleFormat { sample_format: WavSampleFormat },
 }
 
+impl ClassifyError for Error {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            Error::Io(error) => error.error_kind(),
+            Error::Closed => ErrorKind::Io,
+            _ => ErrorKind::Decode,
+        }
+    }
+}
+
+/// Writes samples to a WAV file.
+///
+/// `f32` and `f64` samples can be written in any format, with integers
+/// scaled from `[-1, 1)`. Integer samples are written as they are, so they
+/// need an integer format of at least their size, e.g. `i32` can be written as
+/// 24 bit integers, if the samples fit. Complex samples are written as 2
+/// channels, I and Q.
+///
+/// The sizes in the header are only written when the sink is closed or
+/// dropped. Until then, the file looks like it was streamed, which
+/// [`WavSource`][crate::source::file::WavSource] can read.
 #[derive(derive_more::Debug)]
 pub struct WavSink<W, S>
 where
     W: Write + Seek,
 {
     #[debug(skip)]
-    inner: Option<hound::WavWriter<W>>,
+    writer: W,
+    spec: WavSpec,
+    metadata: WavMetadata,
+    data_size: u64,
+    is_closed: bool,
+    #[debug(skip)]
+    buffer: Vec<u8>,
     _phantom: PhantomData<fn(S)>,
 }
 
 impl<W, S> WavSink<W, S>
 where
     W: Write + Seek,
+    S: IntoWavSamples,
 {
-    #[inline]
-    pub fn new(inner: hound::WavWriter<W>) -> Self {
-        Self {
-            inner: Some(inner),
+    pub fn new(mut writer: W, spec: WavSpec) -> Result<Self, Error> {
+        S::check_spec(&spec)?;
+        wav::write_header(&mut writer, &spec)?;
+        Ok(Self {
+            writer,
+            spec,
+            metadata: WavMetadata::default(),
+            data_size: 0,
+            is_closed: false,
+            buffer: vec![],
             _phantom: PhantomData,
-        }
+        })
     }
 
+    /// Writes the samples in their default format, e.g. 32 bit floats for
+    /// `f32`.
     #[inline]
-    fn writer_mut(&mut self) -> Result<&mut hound::WavWriter<W>, Error> {
-        self.inner.as_mut().ok_or(Error::Closed)
+    pub fn from_writer(writer: W, sample_rate: f32) -> Result<Self, Error> {
+        Self::new(writer, S::spec(sample_rate as u32))
+    }
+
+    #[inline]
+    pub fn from_writer_with_format(
+        writer: W,
+        sample_rate: f32,
+        sample_format: WavSampleFormat,
+    ) -> Result<Self, Error> {
+        Self::new(
+            writer,
+            WavSpec {
+                sample_format,
+                ..S::spec(sample_rate as u32)
+            },
+        )
     }
 }
 
 impl<W, S> WavSink<W, S>
 where
     W: Write + Seek,
-    S: IntoWavSamples,
 {
     #[inline]
-    pub fn from_writer(writer: W, sample_rate: f32) -> Result<Self, Error> {
-        Ok(Self::new(hound::WavWriter::new(
-            writer,
-            S::spec(sample_rate as u32),
-        )?))
+    pub fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    /// Sets the metadata that is written when the sink is closed.
+    #[inline]
+    pub fn with_metadata(mut self, metadata: WavMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    #[inline]
+    pub fn metadata(&self) -> &WavMetadata {
+        &self.metadata
+    }
+
+    #[inline]
+    pub fn metadata_mut(&mut self) -> &mut WavMetadata {
+        &mut self.metadata
+    }
+
+    /// Finalizes the file and appends the metadata.
+    fn finish(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Ok(());
+        }
+        self.is_closed = true;
+
+        wav::finish(&mut self.writer, self.data_size)?;
+
+        if !self.metadata.is_empty() {
+            let num_frames = self.data_size / self.spec.block_align() as u64;
+            let duration =
+                Duration::from_secs_f64(num_frames as f64 / f64::from(self.spec.sample_rate));
+            self.metadata
+                .append_to(&mut self.writer, self.spec.sample_rate, duration)?;
+        }
+
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W, S> Drop for WavSink<W, S>
+where
+    W: Write + Seek,
+{
+    fn drop(&mut self) {
+        if let Err(error) = self.finish() {
+            tracing::error!(?error, "failed to finish wav file");
+        }
     }
 }
 
@@ -79,10 +197,18 @@ where
 {
     #[inline]
     pub fn from_path(path: impl AsRef<Path>, sample_rate: f32) -> Result<Self, Error> {
-        Ok(Self::new(hound::WavWriter::create(
-            path,
-            S::spec(sample_rate as u32),
-        )?))
+        let file = File::create(path)?;
+        Self::from_writer(BufWriter::new(file), sample_rate)
+    }
+
+    #[inline]
+    pub fn from_path_with_format(
+        path: impl AsRef<Path>,
+        sample_rate: f32,
+        sample_format: WavSampleFormat,
+    ) -> Result<Self, Error> {
+        let file = File::create(path)?;
+        Self::from_writer_with_format(BufWriter::new(file), sample_rate, sample_format)
     }
 }
 
@@ -98,12 +224,18 @@ where
         _cx: &mut Context<'_>,
         buffer: &[S],
     ) -> Poll<Result<usize, Self::Error>> {
-        let writer = self.writer_mut()?;
+        let this = &mut *self;
+        if this.is_closed {
+            return Poll::Ready(Err(Error::Closed));
+        }
+
+        this.buffer.clear();
         for sample in buffer {
-            // todo: should we return the error even if we have written some samples? we
-            // could return Ok(n) here if n > 0, otherwise Err(_).
-            sample.write_samples(writer)?;
+            sample.write_frame(this.spec.sample_format, &mut this.buffer);
         }
+        this.writer.write_all(&this.buffer)?;
+        this.data_size += this.buffer.len() as u64;
+
         Poll::Ready(Ok(buffer.len()))
     }
 
@@ -112,7 +244,10 @@ where
         mut self: Pin<&mut Self>,
         _cx: &mut Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        self.writer_mut()?.flush()?;
+        if self.is_closed {
+            return Poll::Ready(Err(Error::Closed));
+        }
+        self.writer.flush()?;
         Poll::Ready(Ok(()))
     }
 
@@ -121,64 +256,70 @@ where
         mut self: Pin<&mut Self>,
         _cx: &mut Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        if let Some(writer) = self.inner.take() {
-            writer.finalize()?;
-        }
+        self.finish()?;
         Poll::Ready(Ok(()))
     }
 }
 
+/// Samples that a [`WavSink`] can write.
 pub trait IntoWavSamples {
-    fn spec(sample_rate: u32) -> hound::WavSpec;
+    /// Spec with the default format for this type.
+    fn spec(sample_rate: u32) -> WavSpec;
+
+    fn check_spec(spec: &WavSpec) -> Result<(), Error>;
 
-    fn write_samples<W>(&self, writer: &mut hound::WavWriter<W>) -> Result<(), Error>
-    where
-        W: Write + Seek;
+    /// Encodes a frame, i.e. one sample of each channel.
+    fn write_frame(&self, sample_format: WavSampleFormat, output: &mut Vec<u8>);
 }
 
 macro_rules! impl_into_wav_samples {
-    {$(($T:ty, $bits:expr, $format:expr);)*} => {
+    {$(($T:ty, $encode:ident, $as:ty, $default:expr, [$($format:pat),*]);)*} => {
         $(
             impl IntoWavSamples for $T {
                 #[inline]
-                fn spec(sample_rate: u32) -> hound::WavSpec {
-                    hound::WavSpec {
+                fn spec(sample_rate: u32) -> WavSpec {
+                    WavSpec {
                         channels: 1,
                         sample_rate,
-                        bits_per_sample: $bits,
-                        sample_format: $format,
+                        sample_format: $default,
                     }
                 }
 
                 #[inline]
-                fn write_samples<W>(&self, writer: &mut hound::WavWriter<W>) -> Result<(), Error>
-                where
-                    W: Write + Seek,
-                {
-                    writer.write_sample(*self)?;
-                    Ok(())
+                fn check_spec(spec: &WavSpec) -> Result<(), Error> {
+                    spec_expect_channels(spec, 1)?;
+                    spec_expect_sample_format(spec, |format| matches!(format, $($format)|*))
+                }
+
+                // some types are encoded as they are
+                #[allow(clippy::unnecessary_cast)]
+                #[inline]
+                fn write_frame(&self, sample_format: WavSampleFormat, output: &mut Vec<u8>) {
+                    sample_format.$encode(*self as $as, output);
                 }
             }
 
             impl IntoWavSamples for Complex<$T> {
                 #[inline]
-                fn spec(sample_rate: u32) -> hound::WavSpec {
-                    hound::WavSpec {
+                fn spec(sample_rate: u32) -> WavSpec {
+                    WavSpec {
                         channels: 2,
                         sample_rate,
-                        bits_per_sample: $bits,
-                        sample_format: $format,
+                        sample_format: $default,
                     }
                 }
 
                 #[inline]
-                fn write_samples<W>(&self, writer: &mut hound::WavWriter<W>) -> Result<(), Error>
-                where
-                    W: Write + Seek,
-                {
-                    writer.write_sample(self.re)?;
-                    writer.write_sample(self.im)?;
-                    Ok(())
+                fn check_spec(spec: &WavSpec) -> Result<(), Error> {
+                    spec_expect_channels(spec, 2)?;
+                    spec_expect_sample_format(spec, |format| matches!(format, $($format)|*))
+                }
+
+                #[allow(clippy::unnecessary_cast)]
+                #[inline]
+                fn write_frame(&self, sample_format: WavSampleFormat, output: &mut Vec<u8>) {
+                    sample_format.$encode(self.re as $as, output);
+                    sample_format.$encode(self.im as $as, output);
                 }
             }
         )*
@@ -186,10 +327,123 @@ macro_rules! impl_into_wav_samples {
 }
 
 impl_into_wav_samples! {
-    (i8, 8, hound::SampleFormat::Int);
-    (i16, 16, hound::SampleFormat::Int);
-    (i32, 32, hound::SampleFormat::Int);
-    (f32, 32, hound::SampleFormat::Float);
+    (i8, encode_int, i32, WavSampleFormat::U8, [WavSampleFormat::U8, WavSampleFormat::I16, WavSampleFormat::I24, WavSampleFormat::I32]);
+    (i16, encode_int, i32, WavSampleFormat::I16, [WavSampleFormat::I16, WavSampleFormat::I24, WavSampleFormat::I32]);
+    (i32, encode_int, i32, WavSampleFormat::I32, [WavSampleFormat::I24, WavSampleFormat::I32]);
+    (f32, encode_float, f64, WavSampleFormat::F32, [WavSampleFormat::U8, WavSampleFormat::I16, WavSampleFormat::I24, WavSampleFormat::I32, WavSampleFormat::F32, WavSampleFormat::F64]);
+    (f64, encode_float, f64, WavSampleFormat::F64, [WavSampleFormat::U8, WavSampleFormat::I16, WavSampleFormat::I24, WavSampleFormat::I32, WavSampleFormat::F32, WavSampleFormat::F64]);
+}
+
+#[inline]
+fn spec_expect_channels(spec: &WavSpec, expected: u16) -> Result<(), Error> {
+    if spec.channels == expected {
+        Ok(())
+    }
+    else {
+        Err(Error::UnexpectedChannelCount {
+            channels: spec.channels,
+            expected,
+        })
+    }
+}
+
+#[inline]
+fn spec_expect_sample_format(
+    spec: &WavSpec,
+    supported: impl FnOnce(WavSampleFormat) -> bool,
+) -> Result<(), Error> {
+    if supported(spec.sample_format) {
+        Ok(())
+    }
+    else {
+        Err(Error::UnexpectedSampleFormat {
+            sample_format: spec.sample_format,
+        })
+    }
+}
+
+/// Writes samples to a headerless IQ file, e.g. in the `cu8` format of
+/// `rtl_sdr`.
+///
+/// Nothing but the samples is written, so the sample rate and center
+/// frequency have to be kept elsewhere, e.g. in the file name.
+#[derive(derive_more::Debug)]
+pub struct RawIqSink<W> {
+    #[debug(skip)]
+    writer: W,
+    format: RawIqFormat,
+    #[debug(skip)]
+    buffer: Vec<u8>,
+}
+
+impl<W> RawIqSink<W>
+where
+    W: Write,
+{
+    pub fn new(writer: W, format: RawIqFormat) -> Self {
+        Self {
+            writer,
+            format,
+            buffer: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn format(&self) -> RawIqFormat {
+        self.format
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl RawIqSink<BufWriter<File>> {
+    #[inline]
+    pub fn from_path(path: impl AsRef<Path>, format: RawIqFormat) -> Result<Self, io::Error> {
+        let file = File::create(path)?;
+        Ok(Self::new(BufWriter::new(file), format))
+    }
+}
+
+impl<W> AsyncWriteSamples<Complex<f32>> for RawIqSink<W>
+where
+    W: Write + Unpin,
+{
+    type Error = io::Error;
+
+    fn poll_write_samples(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buffer: &[Complex<f32>],
+    ) -> Poll<Result<usize, Self::Error>> {
+        let this = &mut *self;
+
+        this.buffer.clear();
+        for sample in buffer {
+            this.format.encode(*sample, &mut this.buffer);
+        }
+        this.writer.write_all(&this.buffer)?;
+
+        Poll::Ready(Ok(buffer.len()))
+    }
+
+    #[inline]
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(self.writer.flush())
+    }
+
+    #[inline]
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(self.writer.flush())
+    }
 }
 
 pub async fn write_stream_to_wav<R, S>(
@@ -202,6 +456,106 @@ where
 {
     let sink =
         WavSink::<_, S>::from_path(path, source.sample_rate()).map_err(ForwardError::Sink)?;
-    source.forward(sink, 0x4000).await?;
+    source.pump(sink).run().await?;
+    Ok(())
+}
+
+/// Like [`write_stream_to_wav`], but tags the file with the center frequency
+/// of the source and the current time.
+pub async fn write_capture_to_wav<R, S>(
+    path: impl AsRef<Path>,
+    source: R,
+) -> Result<(), ForwardError<R::Error, Error>>
+where
+    R: AsyncReadSamples<S> + GetSampleRate + GetCenterFrequency,
+    S: IntoWavSamples,
+{
+    let metadata =
+        WavMetadata::for_capture(Frequency::from_hz_f64(source.center_frequency().into()));
+    let mut sink = WavSink::<_, S>::from_path(path, source.sample_rate())
+        .map_err(ForwardError::Sink)?
+        .with_metadata(metadata);
+    source.pump(&mut sink).run().await?;
+    sink.close().await.map_err(ForwardError::Sink)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use super::{
+        RawIqFormat,
+        RawIqSink,
+        WavMetadata,
+        WavSink,
+    };
+    use crate::{
+        frequency::Frequency,
+        io::{
+            AsyncReadSamplesExt,
+            AsyncWriteSamplesExt,
+            GetCenterFrequency,
+        },
+        source::file::{
+            RawIqSource,
+            WavSource,
+        },
+    };
+
+    #[test]
+    fn it_round_trips_the_center_frequency() {
+        let samples = [Complex::new(0.5f32, -0.5), Complex::new(0.25, 0.0)];
+        let mut file = Cursor::new(vec![]);
+
+        let mut sink = WavSink::<_, Complex<f32>>::from_writer(&mut file, 48000.0)
+            .unwrap()
+            .with_metadata(WavMetadata::for_capture(Frequency::from_khz(7_100)));
+        sink.write_all(&samples)
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        sink.close()
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        drop(sink);
+
+        let mut source = WavSource::<_, Complex<f32>>::from_seekable_reader(file).unwrap();
+        assert_eq!(source.center_frequency(), 7_100_000.0);
+        assert!(source.metadata().capture_time.is_some());
+
+        let mut output = vec![];
+        source
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, samples);
+    }
+
+    #[test]
+    fn it_round_trips_raw_iq() {
+        let samples = [Complex::new(0.5f32, -0.5), Complex::new(0.25, 0.0)];
+
+        let mut sink = RawIqSink::new(vec![], RawIqFormat::Cs16);
+        sink.write_all(&samples)
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        let file = sink.into_inner();
+        assert_eq!(file.len(), 8);
+
+        let mut source = RawIqSource::new(&file[..], RawIqFormat::Cs16, 48000.0, 0.0);
+        let mut output = vec![];
+        source
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, samples);
+    }
+}
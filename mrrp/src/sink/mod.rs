@@ -1,4 +1,8 @@
+pub mod archive;
+pub mod bfp;
 pub mod file;
 pub mod raw;
 #[cfg(feature = "rtlsdr")]
 pub mod rtl_tcp;
+#[cfg(feature = "sigmf")]
+pub mod sigmf;
@@ -12,7 +12,13 @@ use num_complex::Complex;
 use pin_project_lite::pin_project;
 use tokio::io::AsyncWrite;
 
-use crate::io::AsyncWriteSamples;
+use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    io::AsyncWriteSamples,
+};
 
 #[derive(Debug, thiserror::Error)]
 #[error("raw sample writer error")]
@@ -21,6 +27,15 @@ pub enum RawWriterError<E> {
     Encode(#[source] E),
 }
 
+impl<E> ClassifyError for RawWriterError<E> {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            RawWriterError::Writer(error) => error.error_kind(),
+            RawWriterError::Encode(_) => ErrorKind::Decode,
+        }
+    }
+}
+
 pin_project! {
     #[derive(Clone, Debug)]
     pub struct RawAsyncWriter<W> {
@@ -1,3 +1,20 @@
+//! Serving streams to `rtl_tcp` clients.
+//!
+//! [`serve_connection`] serves a stream to a client that already connected.
+//! [`RtlTcpServer`] listens for clients, one at a time like `rtl_tcp` does,
+//! and tells the application about the commands they send, so it can e.g.
+//! retune when the client changes the frequency.
+
+use std::{
+    io,
+    net::SocketAddr,
+    pin::pin,
+};
+
+use futures_util::future::{
+    Either,
+    select,
+};
 use num_complex::Complex;
 use rtlsdr_async::{
     DongleInfo,
@@ -7,9 +24,27 @@ use rtlsdr_async::{
         server::ConnectionHandler,
     },
 };
-use tokio::net::TcpStream;
+use tokio::{
+    io::{
+        AsyncReadExt,
+        AsyncWriteExt,
+    },
+    net::{
+        TcpListener,
+        TcpStream,
+        ToSocketAddrs,
+        tcp::{
+            ReadHalf,
+            WriteHalf,
+        },
+    },
+};
 
 use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
     io::{
         AsyncReadSamples,
         AsyncReadSamplesExt,
@@ -20,8 +55,12 @@ use crate::{
         Sample,
     },
     source::rtlsdr::convert_complex_to_iq,
+    util::raw_iq::RawIqFormat,
 };
 
+/// Most samples that are sent to the client at once.
+const SAMPLES_PER_WRITE: usize = 0x4000;
+
 pub type Error<H> = rtlsdr_async::rtl_tcp::server::Error<H>;
 
 pub struct StreamHandler<R, S> {
@@ -86,3 +125,341 @@ where
     )
     .await
 }
+
+#[derive(Debug, thiserror::Error)]
+#[error("rtl_tcp server error")]
+pub enum ServerError<E> {
+    Io(#[source] io::Error),
+    Stream(#[source] E),
+}
+
+impl<E> ClassifyError for ServerError<E>
+where
+    E: ClassifyError,
+{
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            ServerError::Io(error) => error.error_kind(),
+            ServerError::Stream(error) => error.error_kind(),
+        }
+    }
+}
+
+/// A command that an `rtl_tcp` client sent.
+///
+/// Gains are in tenths of a dB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RtlTcpCommand {
+    SetFrequency(u32),
+    SetSampleRate(u32),
+    SetGainMode {
+        manual: bool,
+    },
+    SetGain(i32),
+    /// Frequency correction in ppm.
+    SetFrequencyCorrection(i32),
+    SetIfGain {
+        stage: u16,
+        gain: i16,
+    },
+    SetTestMode(bool),
+    SetAgcMode(bool),
+    SetDirectSampling(u32),
+    SetOffsetTuning(bool),
+    SetRtlXtal(u32),
+    SetTunerXtal(u32),
+    SetGainByIndex(u32),
+    SetBiasTee(bool),
+    Unknown {
+        command: u8,
+        parameter: u32,
+    },
+}
+
+impl RtlTcpCommand {
+    /// Parses a command, which is a byte for the command and a big-endian
+    /// parameter.
+    pub fn from_bytes(bytes: [u8; 5]) -> Self {
+        let parameter = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+        match bytes[0] {
+            0x01 => Self::SetFrequency(parameter),
+            0x02 => Self::SetSampleRate(parameter),
+            0x03 => {
+                Self::SetGainMode {
+                    manual: parameter != 0,
+                }
+            }
+            0x04 => Self::SetGain(parameter as i32),
+            0x05 => Self::SetFrequencyCorrection(parameter as i32),
+            0x06 => {
+                Self::SetIfGain {
+                    stage: (parameter >> 16) as u16,
+                    gain: parameter as u16 as i16,
+                }
+            }
+            0x07 => Self::SetTestMode(parameter != 0),
+            0x08 => Self::SetAgcMode(parameter != 0),
+            0x09 => Self::SetDirectSampling(parameter),
+            0x0a => Self::SetOffsetTuning(parameter != 0),
+            0x0b => Self::SetRtlXtal(parameter),
+            0x0c => Self::SetTunerXtal(parameter),
+            0x0d => Self::SetGainByIndex(parameter),
+            0x0e => Self::SetBiasTee(parameter != 0),
+            command => Self::Unknown { command, parameter },
+        }
+    }
+}
+
+/// What happened on an [`RtlTcpServer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerEvent {
+    Connected(SocketAddr),
+    Command {
+        address: SocketAddr,
+        command: RtlTcpCommand,
+    },
+    Disconnected(SocketAddr),
+}
+
+/// Listens for `rtl_tcp` clients, e.g. SDR++ or gqrx, and sends them samples.
+///
+/// Like `rtl_tcp`, one client is served at a time. The stream continues where
+/// the last client left off when the next one connects. Commands don't
+/// change anything by themselves, but are passed to the [event
+/// handler][Self::with_event_handler].
+#[derive(derive_more::Debug)]
+pub struct RtlTcpServer {
+    listener: TcpListener,
+    tuner_type: u32,
+    tuner_gain_count: u32,
+    #[debug(skip)]
+    event_handler: Box<dyn FnMut(ServerEvent) + Send>,
+}
+
+impl RtlTcpServer {
+    /// `rtl_tcp`'s code for an R820T tuner, which most dongles have.
+    pub const TUNER_R820T: u32 = 5;
+
+    pub async fn bind(address: impl ToSocketAddrs) -> Result<Self, io::Error> {
+        Ok(Self::from_listener(TcpListener::bind(address).await?))
+    }
+
+    pub fn from_listener(listener: TcpListener) -> Self {
+        Self {
+            listener,
+            tuner_type: Self::TUNER_R820T,
+            tuner_gain_count: 29,
+            event_handler: Box::new(|_| {}),
+        }
+    }
+
+    /// The tuner that is reported to clients. Some clients only offer gain
+    /// settings for the tuners they know.
+    pub fn with_tuner(mut self, tuner_type: u32, tuner_gain_count: u32) -> Self {
+        self.tuner_type = tuner_type;
+        self.tuner_gain_count = tuner_gain_count;
+        self
+    }
+
+    /// Called for connections and the commands that clients send.
+    pub fn with_event_handler(
+        mut self,
+        event_handler: impl FnMut(ServerEvent) + Send + 'static,
+    ) -> Self {
+        self.event_handler = Box::new(event_handler);
+        self
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.listener.local_addr()
+    }
+
+    /// Serves `stream` to clients until it ends.
+    pub async fn serve<R>(&mut self, mut stream: R) -> Result<(), ServerError<R::Error>>
+    where
+        R: AsyncReadSamples<Complex<f32>> + Unpin,
+    {
+        let mut buffer = vec![];
+        loop {
+            let (mut connection, address) =
+                self.listener.accept().await.map_err(ServerError::Io)?;
+            connection.set_nodelay(true).map_err(ServerError::Io)?;
+            tracing::debug!(%address, "rtl_tcp client connected");
+            (self.event_handler)(ServerEvent::Connected(address));
+
+            let result = self
+                .serve_client(&mut connection, address, &mut stream, &mut buffer)
+                .await;
+
+            tracing::debug!(%address, "rtl_tcp client disconnected");
+            (self.event_handler)(ServerEvent::Disconnected(address));
+
+            match result {
+                Ok(ClientEnd::Disconnected) => {}
+                Ok(ClientEnd::StreamEnded) => return Ok(()),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    async fn serve_client<R>(
+        &mut self,
+        connection: &mut TcpStream,
+        address: SocketAddr,
+        stream: &mut R,
+        buffer: &mut Vec<u8>,
+    ) -> Result<ClientEnd, ServerError<R::Error>>
+    where
+        R: AsyncReadSamples<Complex<f32>> + Unpin,
+    {
+        let mut header = [0; 12];
+        header[..4].copy_from_slice(b"RTL0");
+        header[4..8].copy_from_slice(&self.tuner_type.to_be_bytes());
+        header[8..].copy_from_slice(&self.tuner_gain_count.to_be_bytes());
+        if connection.write_all(&header).await.is_err() {
+            return Ok(ClientEnd::Disconnected);
+        }
+
+        let (reader, writer) = connection.split();
+        let commands = pin!(read_commands(reader, address, &mut *self.event_handler));
+        let samples = pin!(write_samples(writer, stream, buffer));
+
+        match select(commands, samples).await {
+            // the client closed the connection
+            Either::Left(((), _)) => Ok(ClientEnd::Disconnected),
+            Either::Right((result, _)) => result,
+        }
+    }
+}
+
+enum ClientEnd {
+    Disconnected,
+    StreamEnded,
+}
+
+async fn read_commands(
+    mut reader: ReadHalf<'_>,
+    address: SocketAddr,
+    event_handler: &mut (dyn FnMut(ServerEvent) + Send),
+) {
+    let mut command = [0; 5];
+    while reader.read_exact(&mut command).await.is_ok() {
+        let command = RtlTcpCommand::from_bytes(command);
+        tracing::trace!(%address, ?command, "rtl_tcp command");
+        event_handler(ServerEvent::Command { address, command });
+    }
+}
+
+async fn write_samples<R>(
+    mut writer: WriteHalf<'_>,
+    stream: &mut R,
+    buffer: &mut Vec<u8>,
+) -> Result<ClientEnd, ServerError<R::Error>>
+where
+    R: AsyncReadSamples<Complex<f32>> + Unpin,
+{
+    let mut samples = vec![Complex::default(); SAMPLES_PER_WRITE];
+    loop {
+        let num_samples = stream
+            .read_samples(&mut samples)
+            .await
+            .map_err(ServerError::Stream)?;
+        if num_samples == 0 {
+            // the connection is closed when it's dropped
+            let _ = writer.flush().await;
+            return Ok(ClientEnd::StreamEnded);
+        }
+
+        buffer.clear();
+        for sample in &samples[..num_samples] {
+            RawIqFormat::Cu8.encode(*sample, buffer);
+        }
+        if writer.write_all(buffer).await.is_err() {
+            return Ok(ClientEnd::Disconnected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use num_complex::Complex;
+    use tokio::{
+        io::{
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+        net::TcpStream,
+    };
+
+    use super::{
+        RtlTcpCommand,
+        RtlTcpServer,
+        ServerEvent,
+    };
+    use crate::io::Cursor;
+
+    #[test]
+    fn it_parses_commands() {
+        assert_eq!(
+            RtlTcpCommand::from_bytes([0x01, 0x05, 0xf5, 0xe1, 0x00]),
+            RtlTcpCommand::SetFrequency(100_000_000)
+        );
+        assert_eq!(
+            RtlTcpCommand::from_bytes([0x04, 0xff, 0xff, 0xff, 0xf6]),
+            RtlTcpCommand::SetGain(-10)
+        );
+        assert_eq!(
+            RtlTcpCommand::from_bytes([0x06, 0x00, 0x02, 0xff, 0xce]),
+            RtlTcpCommand::SetIfGain {
+                stage: 2,
+                gain: -50
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_serves_a_client() {
+        let samples = vec![Complex::new(0.5f32, -0.5); 1000];
+        let (sender, events) = mpsc::channel();
+        let mut server = RtlTcpServer::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .with_event_handler(move |event| sender.send(event).unwrap());
+
+        // the command is sent before the server accepts the connection, so that
+        // it's read before the stream ends
+        let mut connection = TcpStream::connect(server.local_addr().unwrap())
+            .await
+            .unwrap();
+        connection
+            .write_all(&[0x01, 0x05, 0xf5, 0xe1, 0x00])
+            .await
+            .unwrap();
+
+        let client = async {
+            let mut received = vec![];
+            connection.read_to_end(&mut received).await.unwrap();
+            received
+        };
+        let (result, received) = tokio::join!(server.serve(Cursor::new(&samples[..])), client);
+        result.unwrap();
+
+        assert_eq!(&received[..4], b"RTL0");
+        assert_eq!(received.len(), 12 + 2 * samples.len());
+        assert_eq!(&received[12..14], [192, 64]);
+
+        let events = events.try_iter().collect::<Vec<_>>();
+        assert!(matches!(events[0], ServerEvent::Connected(_)));
+        assert!(matches!(
+            events[1],
+            ServerEvent::Command {
+                command: RtlTcpCommand::SetFrequency(100_000_000),
+                ..
+            }
+        ));
+        assert!(matches!(events[2], ServerEvent::Disconnected(_)));
+    }
+}
@@ -0,0 +1,251 @@
+//! Recording to [SigMF](https://sigmf.org) files.
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{
+        Path,
+        PathBuf,
+    },
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use num_complex::Complex;
+
+pub use crate::util::sigmf::{
+    Annotation,
+    Capture,
+    Error,
+    SigmfMetadata,
+};
+use crate::{
+    io::{
+        AsyncReadSamples,
+        AsyncReadSamplesExt,
+        AsyncWriteSamples,
+        AsyncWriteSamplesExt,
+        ForwardError,
+        GetCenterFrequency,
+        GetSampleRate,
+    },
+    sink::file::{
+        RawIqFormat,
+        RawIqSink,
+    },
+    util::sigmf,
+};
+
+/// Records samples to a SigMF data file, and writes the metadata file when
+/// it's closed or dropped.
+///
+/// Annotations can be added while recording, e.g. for signals that were
+/// detected, with [`position`][Self::position] as their start.
+#[derive(Debug)]
+pub struct SigmfSink {
+    data: RawIqSink<BufWriter<File>>,
+    meta_path: PathBuf,
+    metadata: SigmfMetadata,
+    num_samples_written: u64,
+    is_closed: bool,
+}
+
+impl SigmfSink {
+    /// Creates the data file. `path` can be the name of either file, or the
+    /// name without an extension.
+    ///
+    /// The datatype in the metadata is replaced with the one of `format`.
+    pub fn create(
+        path: impl AsRef<Path>,
+        format: RawIqFormat,
+        mut metadata: SigmfMetadata,
+    ) -> Result<Self, Error> {
+        let (meta_path, data_path) = sigmf::paths(path);
+        metadata.global.datatype = sigmf::datatype(format).to_owned();
+        Ok(Self {
+            data: RawIqSink::from_path(data_path, format)?,
+            meta_path,
+            metadata,
+            num_samples_written: 0,
+            is_closed: false,
+        })
+    }
+
+    /// Records 32 bit floats, with a capture that starts now.
+    pub fn for_capture(
+        path: impl AsRef<Path>,
+        sample_rate: f32,
+        center_frequency: f32,
+    ) -> Result<Self, Error> {
+        let format = RawIqFormat::Cf32;
+        let metadata =
+            SigmfMetadata::for_capture(format, sample_rate.into(), center_frequency.into());
+        Self::create(path, format, metadata)
+    }
+
+    #[inline]
+    pub fn metadata(&self) -> &SigmfMetadata {
+        &self.metadata
+    }
+
+    #[inline]
+    pub fn metadata_mut(&mut self) -> &mut SigmfMetadata {
+        &mut self.metadata
+    }
+
+    /// Index of the next sample that is written.
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.num_samples_written
+    }
+
+    #[inline]
+    pub fn add_annotation(&mut self, annotation: Annotation) {
+        self.metadata.annotations.push(annotation);
+    }
+
+    /// Starts a new capture at the next sample, e.g. after retuning.
+    pub fn add_capture(&mut self, capture: Capture) {
+        let capture = Capture {
+            sample_start: self.num_samples_written,
+            ..capture
+        };
+        self.metadata.captures.push(capture);
+    }
+
+    /// Writes the metadata file. The data file is flushed by the caller.
+    fn finish(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Ok(());
+        }
+        self.is_closed = true;
+
+        // the specification wants annotations sorted by their start
+        self.metadata
+            .annotations
+            .sort_by_key(|annotation| annotation.sample_start);
+        std::fs::write(&self.meta_path, self.metadata.to_json()?)?;
+        Ok(())
+    }
+}
+
+impl Drop for SigmfSink {
+    fn drop(&mut self) {
+        if let Err(error) = self.finish() {
+            tracing::error!(?error, "failed to write sigmf metadata");
+        }
+    }
+}
+
+impl AsyncWriteSamples<Complex<f32>> for SigmfSink {
+    type Error = Error;
+
+    fn poll_write_samples(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &[Complex<f32>],
+    ) -> Poll<Result<usize, Self::Error>> {
+        let num_written = ready!(Pin::new(&mut self.data).poll_write_samples(cx, buffer))?;
+        self.num_samples_written += num_written as u64;
+        Poll::Ready(Ok(num_written))
+    }
+
+    #[inline]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.data).poll_flush(cx).map_err(Into::into)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(Pin::new(&mut self.data).poll_close(cx))?;
+        self.finish()?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Records a stream to a SigMF recording, tagged with the center frequency of
+/// the source and the current time.
+pub async fn write_capture_to_sigmf<R>(
+    path: impl AsRef<Path>,
+    source: R,
+) -> Result<(), ForwardError<R::Error, Error>>
+where
+    R: AsyncReadSamples<Complex<f32>> + GetSampleRate + GetCenterFrequency,
+{
+    let mut sink = SigmfSink::for_capture(path, source.sample_rate(), source.center_frequency())
+        .map_err(ForwardError::Sink)?;
+    source.pump(&mut sink).run().await?;
+    sink.close().await.map_err(ForwardError::Sink)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use super::{
+        Annotation,
+        SigmfSink,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            AsyncWriteSamplesExt,
+            GetCenterFrequency,
+            GetSampleRate,
+        },
+        source::sigmf::SigmfSource,
+    };
+
+    #[test]
+    fn it_round_trips_recordings() {
+        let path = std::env::temp_dir().join(format!("mrrp-sigmf-{}", std::process::id()));
+        let samples = (0..100)
+            .map(|i| Complex::new(i as f32, -i as f32))
+            .collect::<Vec<_>>();
+
+        let mut sink = SigmfSink::for_capture(&path, 48_000.0, 14.074e6).unwrap();
+        sink.write_all(&samples[..50])
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        sink.add_annotation(Annotation {
+            sample_start: sink.position(),
+            sample_count: Some(10),
+            label: Some("FT8".to_owned()),
+            ..Default::default()
+        });
+        sink.write_all(&samples[50..])
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        sink.close()
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        drop(sink);
+
+        let mut source = SigmfSource::from_path(path.with_extension("sigmf-meta")).unwrap();
+        assert_eq!(source.sample_rate(), 48_000.0);
+        assert_eq!(source.center_frequency(), 14.074e6);
+        assert_eq!(source.num_samples(), Some(100));
+        assert_eq!(source.annotations()[0].sample_start, 50);
+        assert_eq!(source.annotations()[0].label.as_deref(), Some("FT8"));
+
+        let mut output = vec![];
+        source
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output, samples);
+
+        let (meta_path, data_path) = super::sigmf::paths(&path);
+        std::fs::remove_file(meta_path).unwrap();
+        std::fs::remove_file(data_path).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+//! Reading block floating point compressed IQ, e.g. from a socket.
+//!
+//! See [`sample::bfp`][crate::sample::bfp] for the format, and
+//! [`BfpAsyncWriter`][crate::sink::bfp::BfpAsyncWriter] for the other end.
+
+use std::{
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+use tokio::io::AsyncRead;
+
+use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    io::{
+        AsyncReadSamples,
+        ReadBuf,
+        Remaining,
+        StreamLength,
+    },
+    sample::bfp::{
+        BfpDecoder,
+        BfpError,
+        DEFAULT_BLOCK_SIZE,
+    },
+};
+
+/// How many bytes are read from the inner reader at once.
+const READ_SIZE: usize = 0x4000;
+
+#[derive(Debug, thiserror::Error)]
+#[error("block floating point reader error")]
+pub enum BfpReaderError {
+    Reader(#[source] std::io::Error),
+    Decode(#[from] BfpError),
+    Truncated,
+}
+
+impl ClassifyError for BfpReaderError {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            BfpReaderError::Reader(error) => error.error_kind(),
+            BfpReaderError::Decode(_) => ErrorKind::Decode,
+            BfpReaderError::Truncated => ErrorKind::Eof,
+        }
+    }
+}
+
+pin_project! {
+    /// Reads compressed IQ samples from an [`AsyncRead`].
+    #[derive(Debug)]
+    pub struct BfpAsyncReader<R> {
+        #[pin]
+        reader: R,
+        decoder: BfpDecoder,
+        input: Vec<u8>,
+        samples: Vec<Complex<f32>>,
+        read_pos: usize,
+        eof: bool,
+    }
+}
+
+impl<R> BfpAsyncReader<R> {
+    /// Creates a reader that accepts blocks up to 64 times the default block
+    /// size.
+    pub fn new(reader: R) -> Self {
+        Self::with_decoder(
+            reader,
+            BfpDecoder::new().with_max_block_size(64 * DEFAULT_BLOCK_SIZE),
+        )
+    }
+
+    pub fn with_decoder(reader: R, decoder: BfpDecoder) -> Self {
+        Self {
+            reader,
+            decoder,
+            input: vec![],
+            samples: vec![],
+            read_pos: 0,
+            eof: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead> AsyncReadSamples<Complex<f32>> for BfpAsyncReader<R> {
+    type Error = BfpReaderError;
+
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<Complex<f32>>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        loop {
+            if *this.read_pos < this.samples.len() {
+                let samples = &this.samples[*this.read_pos..];
+                let n = samples.len().min(buffer.remaining());
+                buffer.put_slice(&samples[..n]);
+                *this.read_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+
+            this.samples.clear();
+            *this.read_pos = 0;
+            let num_bytes_decoded = this.decoder.decode(this.input, this.samples)?;
+            this.input.drain(..num_bytes_decoded);
+            if !this.samples.is_empty() {
+                continue;
+            }
+
+            if *this.eof {
+                return if this.input.is_empty() {
+                    Poll::Ready(Ok(()))
+                }
+                else {
+                    Poll::Ready(Err(BfpReaderError::Truncated))
+                };
+            }
+
+            let length = this.input.len();
+            this.input.resize(length + READ_SIZE, 0);
+            let mut read_buf = tokio::io::ReadBuf::new(&mut this.input[length..]);
+            let result = this.reader.as_mut().poll_read(cx, &mut read_buf);
+            let num_bytes_read = read_buf.filled().len();
+            this.input.truncate(length + num_bytes_read);
+
+            match result {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(BfpReaderError::Reader(error))),
+                Poll::Ready(Ok(())) => {
+                    if num_bytes_read == 0 {
+                        *this.eof = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<R> StreamLength for BfpAsyncReader<R> {
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        Remaining::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use super::{
+        BfpAsyncReader,
+        BfpReaderError,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            AsyncWriteSamplesExt,
+        },
+        sample::bfp::MantissaBits,
+        sink::bfp::BfpAsyncWriter,
+    };
+
+    #[test]
+    fn it_round_trips_through_a_byte_stream() {
+        let input = (0..1000)
+            .map(|i| Complex::from_polar(1.0, 0.1 * i as f32))
+            .collect::<Vec<_>>();
+
+        let mut writer = BfpAsyncWriter::new(vec![], MantissaBits::Twelve);
+        writer
+            .write_all(&input)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        writer
+            .close()
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        let bytes = writer.into_inner();
+        // 3 bytes per sample, plus the headers
+        assert_eq!(bytes.len(), 5 + 16 * 3 + 1000 * 3);
+
+        let mut output = vec![];
+        BfpAsyncReader::new(&bytes[..])
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(output.len(), input.len());
+        for (input, output) in input.iter().zip(&output) {
+            assert!((input - output).norm() < 1e-3, "{input} != {output}");
+        }
+
+        // cut off in the middle of a block
+        let mut output = vec![];
+        let result = BfpAsyncReader::new(&bytes[..bytes.len() - 1])
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending");
+        assert!(matches!(result, Err(BfpReaderError::Truncated)));
+    }
+}
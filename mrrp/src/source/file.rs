@@ -1,6 +1,12 @@
 use std::{
     fs::File,
-    io::BufReader,
+    io::{
+        self,
+        BufReader,
+        Read,
+        Seek,
+        SeekFrom,
+    },
     marker::PhantomData,
     path::Path,
     pin::Pin,
@@ -8,72 +14,148 @@ use std::{
         Context,
         Poll,
     },
+    time::Duration,
 };
 
 use num_complex::Complex;
 
+pub use crate::util::{
+    raw_iq::RawIqFormat,
+    riff::WavMetadata,
+    wav::{
+        WavSampleFormat,
+        WavSpec,
+    },
+};
 use crate::{
     buf::SampleBufMut,
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
     io::{
         AsyncReadSamples,
         FiniteStream,
+        GetCenterFrequency,
+        GetSampleIndexMap,
         GetSampleRate,
         ReadBuf,
         Remaining,
+        SampleIndexMap,
         StreamLength,
     },
+    util::wav::{
+        self,
+        WavHeader,
+    },
 };
 
+/// Most frames that are read from the file at once.
+const MAX_FRAMES_PER_READ: usize = 0x1000;
+
 #[derive(Debug, thiserror::Error)]
 #[error("wav source error")]
 pub enum Error {
-    Hound(#[from] hound::Error),
-    UnexpectedChannelCount {
-        channels: u16,
-        expected: u16,
-    },
-    UnexpectedBitsPerSample {
-        bits_per_sample: u16,
-        expected: u16,
-    },
-    UnexpectedSampleFormat {
-        sample_format: hound::SampleFormat,
-        expected: hound::SampleFormat,
-    },
+    Io(#[from] io::Error),
+    Wav(#[from] wav::Error),
+    UnexpectedChannelCount { channels: u16, expected: u16 },
+    UnexpectedSampleFormat { sample_format: WavSampleFormat },
 }
 
+impl ClassifyError for Error {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            Error::Io(error) | Error::Wav(wav::Error::Io(error)) => error.error_kind(),
+            _ => ErrorKind::Decode,
+        }
+    }
+}
+
+/// Reads samples from a WAV file.
+///
+/// Integer and float files can be read as `f32` or `f64`, which scales
+/// integers to `[-1, 1)`. Integer types only read files with matching
+/// integers, which they don't scale: `i8` reads 8 bit files, `i16` 16 bit
+/// files, and `i32` 24 and 32 bit files. Complex samples read files with 2
+/// channels, I and Q.
 #[derive(derive_more::Debug)]
 pub struct WavSource<R, S> {
     #[debug(skip)]
-    inner: hound::WavReader<R>,
-    spec: hound::WavSpec,
-    num_samples_read: usize,
+    inner: R,
+    header: WavHeader,
+    metadata: WavMetadata,
+    num_frames_read: u64,
+    #[debug(skip)]
+    buffer: Vec<u8>,
     _phantom: PhantomData<fn() -> S>,
 }
 
 impl<R, S> WavSource<R, S>
 where
-    R: std::io::Read,
+    R: Read,
     S: FromWavSamples,
 {
-    pub fn new(inner: hound::WavReader<R>) -> Result<Self, Error> {
-        let spec = inner.spec();
-        S::check_spec(&spec)?;
+    /// Reads the header. Use
+    /// [`from_seekable_reader`][Self::from_seekable_reader] to read the
+    /// metadata as well.
+    pub fn from_reader(mut reader: R) -> Result<Self, Error> {
+        let header = WavHeader::read_from(&mut reader)?;
+        S::check_spec(&header.spec)?;
         Ok(Self {
-            inner,
-            spec,
-            num_samples_read: 0,
+            inner: reader,
+            header,
+            metadata: WavMetadata::default(),
+            num_frames_read: 0,
+            buffer: vec![],
             _phantom: PhantomData,
         })
     }
 
+    /// Reads the metadata chunks as well, which needs a seekable reader.
+    ///
+    /// The length of the file is used to fix the number of samples, if the
+    /// file wasn't finalized.
+    pub fn from_seekable_reader(mut reader: R) -> Result<Self, Error>
+    where
+        R: Seek,
+    {
+        let metadata = WavMetadata::read_from(&mut reader)?;
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut source = Self::from_reader(reader)?.with_metadata(metadata);
+        source.header.fix_data_size(file_size);
+        Ok(source)
+    }
+}
+
+impl<R, S> WavSource<R, S> {
     #[inline]
-    pub fn from_reader(reader: R) -> Result<Self, Error> {
-        Self::new(hound::WavReader::new(reader)?)
+    pub fn spec(&self) -> &WavSpec {
+        &self.header.spec
     }
 
-    pub fn spec(&self) -> &hound::WavSpec {
-        &self.spec
+    #[inline]
+    pub fn metadata(&self) -> &WavMetadata {
+        &self.metadata
+    }
+
+    #[inline]
+    pub fn with_metadata(mut self, metadata: WavMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Number of samples in the file, if known.
+    #[inline]
+    pub fn num_samples(&self) -> Option<u64> {
+        self.header.num_frames()
+    }
+
+    /// Index of the next sample that is read.
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.num_frames_read
     }
 }
 
@@ -81,27 +163,41 @@ impl<S> WavSource<BufReader<File>, S>
 where
     S: FromWavSamples,
 {
+    /// Opens a WAV file and reads its metadata.
     #[inline]
     pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
-        Self::new(hound::WavReader::open(path)?)
+        Self::from_seekable_reader(BufReader::new(File::open(path)?))
     }
 }
 
 impl<R, S> WavSource<R, S>
 where
-    R: std::io::Read + std::io::Seek,
+    R: Seek,
 {
-    pub fn seek(&mut self, time: u32) -> Result<(), Error> {
-        self.inner
-            .seek(time)
-            .map_err(|error| Error::Hound(error.into()))?;
+    /// Seeks to the sample with the given index, so that it's read next.
+    ///
+    /// Seeking past the end seeks to the end.
+    pub fn seek_to_sample(&mut self, index: u64) -> Result<(), Error> {
+        let index = self
+            .header
+            .num_frames()
+            .map_or(index, |num_frames| index.min(num_frames));
+        let offset = self.header.data_start + index * self.header.spec.block_align() as u64;
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.num_frames_read = index;
         Ok(())
     }
+
+    /// Seeks to the sample at `time` from the start of the file.
+    pub fn seek_to_time(&mut self, time: Duration) -> Result<(), Error> {
+        let index = (time.as_secs_f64() * f64::from(self.header.spec.sample_rate)).round();
+        self.seek_to_sample(index as u64)
+    }
 }
 
 impl<R, S> AsyncReadSamples<S> for WavSource<R, S>
 where
-    R: std::io::Read + Unpin,
+    R: Read + Unpin,
     S: FromWavSamples,
 {
     type Error = Error;
@@ -112,15 +208,25 @@ where
         buffer: &mut ReadBuf<S>,
     ) -> Poll<Result<(), Self::Error>> {
         let this = &mut *self;
-        let mut samples = this.inner.samples();
-
-        while buffer.has_remaining_mut() {
-            let Some(sample) = S::from_samples(&mut samples)?
-            else {
-                break;
-            };
-            buffer.put_sample(sample);
-            this.num_samples_read += 1;
+        let spec = this.header.spec;
+        let block_align = spec.block_align();
+
+        let mut num_frames = buffer.remaining().min(MAX_FRAMES_PER_READ);
+        if let Some(total) = this.header.num_frames() {
+            num_frames = num_frames.min(total.saturating_sub(this.num_frames_read) as usize);
+        }
+        if num_frames == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        this.buffer.resize(num_frames * block_align, 0);
+        let num_bytes = read_full(&mut this.inner, &mut this.buffer)?;
+
+        // a partial frame can only be at the end of a truncated file, and is
+        // dropped
+        for frame in this.buffer[..num_bytes].chunks_exact(block_align) {
+            buffer.put_sample(S::from_frame(frame, spec.sample_format));
+            this.num_frames_read += 1;
         }
 
         Poll::Ready(Ok(()))
@@ -130,82 +236,295 @@ where
 impl<R, S> GetSampleRate for WavSource<R, S> {
     #[inline]
     fn sample_rate(&self) -> f32 {
-        self.spec.sample_rate as f32
+        self.spec().sample_rate as f32
+    }
+}
+
+impl<R, S> GetCenterFrequency for WavSource<R, S> {
+    /// The center frequency from the metadata, or 0 if the recording wasn't
+    /// tagged with one.
+    #[inline]
+    fn center_frequency(&self) -> f32 {
+        self.metadata
+            .center_frequency
+            .map_or(0.0, |frequency| frequency.as_f32())
+    }
+}
+
+impl<R, S> GetSampleIndexMap for WavSource<R, S> {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
     }
 }
 
 impl<R, S> StreamLength for WavSource<R, S>
 where
-    R: std::io::Read,
+    R: Read,
 {
     fn remaining(&self) -> Remaining {
-        Remaining::Finite {
-            num_samples: usize::try_from(self.inner.len()).unwrap()
-                / usize::from(self.spec.channels)
-                - self.num_samples_read,
+        match self.header.num_frames() {
+            Some(num_frames) => {
+                Remaining::Finite {
+                    num_samples: usize::try_from(num_frames - self.num_frames_read).unwrap(),
+                }
+            }
+            // streamed files go until the end of the file
+            None => Remaining::Unknown,
         }
     }
 }
 
-impl<R, S> FiniteStream for WavSource<R, S> where R: std::io::Read {}
+impl<R, S> FiniteStream for WavSource<R, S> where R: Read {}
 
-pub trait FromWavSamples: Sized {
-    type WavSample: hound::Sample;
+/// Reads samples from a headerless IQ file, e.g. an `rtl_sdr` capture.
+///
+/// The file doesn't tell its sample rate and center frequency, so they're
+/// passed in when the source is created.
+#[derive(derive_more::Debug)]
+pub struct RawIqSource<R> {
+    #[debug(skip)]
+    inner: R,
+    format: RawIqFormat,
+    sample_rate: f32,
+    center_frequency: f32,
+    num_samples: Option<u64>,
+    num_samples_read: u64,
+    #[debug(skip)]
+    buffer: Vec<u8>,
+}
+
+impl<R> RawIqSource<R>
+where
+    R: Read,
+{
+    /// Reads the file until its end.
+    pub fn new(reader: R, format: RawIqFormat, sample_rate: f32, center_frequency: f32) -> Self {
+        Self {
+            inner: reader,
+            format,
+            sample_rate,
+            center_frequency,
+            num_samples: None,
+            num_samples_read: 0,
+            buffer: vec![],
+        }
+    }
 
-    fn check_spec(spec: &hound::WavSpec) -> Result<(), Error>;
-    fn from_samples<'a, R>(
-        samples: &mut hound::WavSamples<'a, R, Self::WavSample>,
-    ) -> Result<Option<Self>, Error>
+    /// Determines the number of samples from the length of the file.
+    pub fn from_seekable_reader(
+        mut reader: R,
+        format: RawIqFormat,
+        sample_rate: f32,
+        center_frequency: f32,
+    ) -> Result<Self, io::Error>
     where
-        R: std::io::Read;
+        R: Seek,
+    {
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut source = Self::new(reader, format, sample_rate, center_frequency);
+        source.num_samples = Some(file_size / format.bytes_per_sample() as u64);
+        Ok(source)
+    }
+}
+
+impl<R> RawIqSource<R> {
+    #[inline]
+    pub fn format(&self) -> RawIqFormat {
+        self.format
+    }
+
+    /// Number of samples in the file, if known.
+    #[inline]
+    pub fn num_samples(&self) -> Option<u64> {
+        self.num_samples
+    }
+
+    /// Index of the next sample that is read.
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.num_samples_read
+    }
+}
+
+impl RawIqSource<BufReader<File>> {
+    /// Opens a raw IQ file. Use [`RawIqFormat::from_path`] to guess the format
+    /// from the file extension.
+    #[inline]
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        format: RawIqFormat,
+        sample_rate: f32,
+        center_frequency: f32,
+    ) -> Result<Self, io::Error> {
+        Self::from_seekable_reader(
+            BufReader::new(File::open(path)?),
+            format,
+            sample_rate,
+            center_frequency,
+        )
+    }
+}
+
+impl<R> RawIqSource<R>
+where
+    R: Seek,
+{
+    /// Seeks to the sample with the given index, so that it's read next.
+    ///
+    /// Seeking past the end seeks to the end.
+    pub fn seek_to_sample(&mut self, index: u64) -> Result<(), io::Error> {
+        let index = self
+            .num_samples
+            .map_or(index, |num_samples| index.min(num_samples));
+        self.inner.seek(SeekFrom::Start(
+            index * self.format.bytes_per_sample() as u64,
+        ))?;
+        self.num_samples_read = index;
+        Ok(())
+    }
+
+    /// Seeks to the sample at `time` from the start of the file.
+    pub fn seek_to_time(&mut self, time: Duration) -> Result<(), io::Error> {
+        let index = (time.as_secs_f64() * f64::from(self.sample_rate)).round();
+        self.seek_to_sample(index as u64)
+    }
+}
+
+impl<R> AsyncReadSamples<Complex<f32>> for RawIqSource<R>
+where
+    R: Read + Unpin,
+{
+    type Error = io::Error;
+
+    fn poll_read_samples(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<Complex<f32>>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+        let bytes_per_sample = this.format.bytes_per_sample();
+
+        let mut num_samples = buffer.remaining().min(MAX_FRAMES_PER_READ);
+        if let Some(total) = this.num_samples {
+            num_samples = num_samples.min(total.saturating_sub(this.num_samples_read) as usize);
+        }
+        if num_samples == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        this.buffer.resize(num_samples * bytes_per_sample, 0);
+        let num_bytes = read_full(&mut this.inner, &mut this.buffer)?;
+
+        // a partial sample at the end of the file is dropped
+        for bytes in this.buffer[..num_bytes].chunks_exact(bytes_per_sample) {
+            buffer.put_sample(this.format.decode(bytes));
+            this.num_samples_read += 1;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R> GetSampleRate for RawIqSource<R> {
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+}
+
+impl<R> GetCenterFrequency for RawIqSource<R> {
+    #[inline]
+    fn center_frequency(&self) -> f32 {
+        self.center_frequency
+    }
+}
+
+impl<R> GetSampleIndexMap for RawIqSource<R> {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
+    }
+}
+
+impl<R> StreamLength for RawIqSource<R>
+where
+    R: Read,
+{
+    fn remaining(&self) -> Remaining {
+        match self.num_samples {
+            Some(num_samples) => {
+                Remaining::Finite {
+                    num_samples: usize::try_from(num_samples - self.num_samples_read).unwrap(),
+                }
+            }
+            None => Remaining::Unknown,
+        }
+    }
+}
+
+impl<R> FiniteStream for RawIqSource<R> where R: Read {}
+
+/// Reads until `buffer` is full or the reader is at its end, and returns how
+/// many bytes were read.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, io::Error> {
+    let mut num_read = 0;
+    while num_read < buffer.len() {
+        match reader.read(&mut buffer[num_read..]) {
+            Ok(0) => break,
+            Ok(n) => num_read += n,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(num_read)
+}
+
+/// Samples that a [`WavSource`] can read.
+pub trait FromWavSamples: Sized {
+    fn check_spec(spec: &WavSpec) -> Result<(), Error>;
+
+    /// Decodes a frame, i.e. one sample of each channel.
+    fn from_frame(frame: &[u8], sample_format: WavSampleFormat) -> Self;
 }
 
 macro_rules! impl_from_wav_samples {
-    {$(($T:ty, $bits:expr, $format:expr);)*} => {
+    {$(($T:ty, $decode:ident, [$($format:pat),*]);)*} => {
         $(
             impl FromWavSamples for $T {
-                type WavSample = $T;
-
                 #[inline]
-                fn check_spec(spec: &hound::WavSpec) -> Result<(), Error> {
+                fn check_spec(spec: &WavSpec) -> Result<(), Error> {
                     spec_expect_channels(spec, 1)?;
-                    spec_expect_bits_per_sample(spec, $bits)?;
-                    spec_expect_sample_format(spec, $format)?;
+                    spec_expect_sample_format(spec, |format| matches!(format, $($format)|*))?;
                     Ok(())
                 }
 
+                // some types are decoded as they are
+                #[allow(clippy::unnecessary_cast)]
                 #[inline]
-                fn from_samples<'a, R>(
-                    samples: &mut hound::WavSamples<'a, R, Self::WavSample>,
-                ) -> Result<Option<Self>, Error>
-                where
-                    R: std::io::Read,
-                {
-                    samples.next().transpose().map_err(Into::into)
+                fn from_frame(frame: &[u8], sample_format: WavSampleFormat) -> Self {
+                    sample_format.$decode(frame) as $T
                 }
             }
 
             impl FromWavSamples for Complex<$T> {
-                type WavSample = $T;
-
                 #[inline]
-                fn check_spec(spec: &hound::WavSpec) -> Result<(), Error> {
+                fn check_spec(spec: &WavSpec) -> Result<(), Error> {
                     spec_expect_channels(spec, 2)?;
-                    spec_expect_bits_per_sample(spec, $bits)?;
-                    spec_expect_sample_format(spec, $format)?;
+                    spec_expect_sample_format(spec, |format| matches!(format, $($format)|*))?;
                     Ok(())
                 }
 
+                #[allow(clippy::unnecessary_cast)]
                 #[inline]
-                fn from_samples<'a, R>(
-                    samples: &mut hound::WavSamples<'a, R, Self::WavSample>,
-                ) -> Result<Option<Self>, Error>
-                where
-                    R: std::io::Read,
-                {
-                    let re_opt = samples.next().transpose()?;
-                    let im_opt = samples.next().transpose()?;
-                    Ok(re_opt.zip(im_opt).map(|(re, im)| Complex { re, im }))
+                fn from_frame(frame: &[u8], sample_format: WavSampleFormat) -> Self {
+                    let (re, im) = frame.split_at(sample_format.bytes_per_sample());
+                    Complex {
+                        re: sample_format.$decode(re) as $T,
+                        im: sample_format.$decode(im) as $T,
+                    }
                 }
             }
         )*
@@ -213,14 +532,15 @@ macro_rules! impl_from_wav_samples {
 }
 
 impl_from_wav_samples! {
-    (i8, 8, hound::SampleFormat::Int);
-    (i16, 16, hound::SampleFormat::Int);
-    (i32, 32, hound::SampleFormat::Int);
-    (f32, 32, hound::SampleFormat::Float);
+    (i8, decode_int, [WavSampleFormat::U8]);
+    (i16, decode_int, [WavSampleFormat::I16]);
+    (i32, decode_int, [WavSampleFormat::I24, WavSampleFormat::I32]);
+    (f32, decode_float, [WavSampleFormat::U8, WavSampleFormat::I16, WavSampleFormat::I24, WavSampleFormat::I32, WavSampleFormat::F32, WavSampleFormat::F64]);
+    (f64, decode_float, [WavSampleFormat::U8, WavSampleFormat::I16, WavSampleFormat::I24, WavSampleFormat::I32, WavSampleFormat::F32, WavSampleFormat::F64]);
 }
 
 #[inline]
-fn spec_expect_channels(spec: &hound::WavSpec, expected: u16) -> Result<(), Error> {
+fn spec_expect_channels(spec: &WavSpec, expected: u16) -> Result<(), Error> {
     if spec.channels == expected {
         Ok(())
     }
@@ -232,31 +552,198 @@ fn spec_expect_channels(spec: &hound::WavSpec, expected: u16) -> Result<(), Erro
     }
 }
 
-#[inline]
-fn spec_expect_bits_per_sample(spec: &hound::WavSpec, expected: u16) -> Result<(), Error> {
-    if spec.bits_per_sample == expected {
-        Ok(())
-    }
-    else {
-        Err(Error::UnexpectedBitsPerSample {
-            bits_per_sample: spec.bits_per_sample,
-            expected,
-        })
-    }
-}
-
 #[inline]
 fn spec_expect_sample_format(
-    spec: &hound::WavSpec,
-    expected: hound::SampleFormat,
+    spec: &WavSpec,
+    supported: impl FnOnce(WavSampleFormat) -> bool,
 ) -> Result<(), Error> {
-    if spec.sample_format == expected {
+    if supported(spec.sample_format) {
         Ok(())
     }
     else {
         Err(Error::UnexpectedSampleFormat {
             sample_format: spec.sample_format,
-            expected,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{
+            Cursor,
+            Write,
+        },
+        time::Duration,
+    };
+
+    use futures_util::FutureExt;
+    use num_complex::Complex;
+
+    use super::{
+        RawIqFormat,
+        RawIqSource,
+        WavSampleFormat,
+        WavSource,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamplesExt,
+            AsyncWriteSamplesExt,
+            GetCenterFrequency,
+            GetSampleRate,
+            Remaining,
+            StreamLength,
+        },
+        sink::file::WavSink,
+        util::wav,
+    };
+
+    fn write_wav<S>(samples: &[S], sample_format: WavSampleFormat) -> Cursor<Vec<u8>>
+    where
+        S: crate::sink::file::IntoWavSamples,
+    {
+        let mut file = Cursor::new(vec![]);
+        let mut sink =
+            WavSink::<_, S>::from_writer_with_format(&mut file, 1000.0, sample_format).unwrap();
+        sink.write_all(samples)
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        sink.close()
+            .now_or_never()
+            .expect("test sink pending")
+            .unwrap();
+        drop(sink);
+        file.set_position(0);
+        file
+    }
+
+    fn read_all<R, S>(source: &mut WavSource<R, S>) -> Vec<S>
+    where
+        R: std::io::Read + Unpin,
+        S: super::FromWavSamples,
+    {
+        let mut output = vec![];
+        source
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn it_reads_24_bit_iq_as_floats() {
+        let samples = [Complex::new(0.5f32, -0.5), Complex::new(-1.0, 0.123)];
+        let file = write_wav(&samples, WavSampleFormat::I24);
+
+        let mut source = WavSource::<_, Complex<f32>>::from_seekable_reader(file).unwrap();
+        assert_eq!(source.spec().sample_format, WavSampleFormat::I24);
+        assert_eq!(source.spec().channels, 2);
+
+        let output = read_all(&mut source);
+        assert_eq!(output.len(), 2);
+        for (output, expected) in output.iter().zip(&samples) {
+            assert!((output - expected).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn it_seeks() {
+        let samples = (0..100)
+            .map(f64::from)
+            .map(|x| x / 100.0)
+            .collect::<Vec<_>>();
+        let file = write_wav(&samples, WavSampleFormat::F64);
+
+        let mut source = WavSource::<_, f64>::from_seekable_reader(file).unwrap();
+        assert_eq!(source.num_samples(), Some(100));
+        assert!(matches!(
+            source.remaining(),
+            Remaining::Finite { num_samples: 100 }
+        ));
+
+        source.seek_to_sample(40).unwrap();
+        assert!(matches!(
+            source.remaining(),
+            Remaining::Finite { num_samples: 60 }
+        ));
+        let mut buffer = [0.0];
+        source
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(buffer[0], 0.4);
+        assert_eq!(source.position(), 41);
+
+        source.seek_to_time(Duration::from_millis(10)).unwrap();
+        source
+            .read_samples(&mut buffer)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(buffer[0], 0.1);
+
+        source.seek_to_sample(1000).unwrap();
+        assert!(matches!(
+            source.remaining(),
+            Remaining::Finite { num_samples: 0 }
+        ));
+        assert!(read_all(&mut source).is_empty());
+    }
+
+    #[test]
+    fn it_reads_files_that_were_not_finalized() {
+        let spec = wav::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            sample_format: WavSampleFormat::I16,
+        };
+        let mut file = Cursor::new(vec![]);
+        wav::write_header(&mut file, &spec).unwrap();
+        file.write_all(&[0x00, 0x40, 0x00, 0xc0, 0x00]).unwrap();
+        file.set_position(0);
+
+        // the size is taken from the file, and the partial sample is dropped
+        let mut source = WavSource::<_, f32>::from_seekable_reader(file.clone()).unwrap();
+        assert_eq!(source.num_samples(), Some(2));
+        assert_eq!(read_all(&mut source), [0.5, -0.5]);
+
+        // without seeking, the file is read until its end
+        let mut source = WavSource::<_, i16>::from_reader(file).unwrap();
+        assert!(matches!(source.remaining(), Remaining::Unknown));
+        assert_eq!(read_all(&mut source), [0x4000, -0x4000]);
+    }
+
+    #[test]
+    fn it_reads_raw_iq_files() {
+        // 3 samples from rtl_sdr and a partial one
+        let file = Cursor::new(vec![0, 255, 128, 127, 64, 192, 12]);
+        let mut source =
+            RawIqSource::from_seekable_reader(file, RawIqFormat::Cu8, 2_048_000.0, 100e6).unwrap();
+        assert_eq!(source.sample_rate(), 2_048_000.0);
+        assert_eq!(source.center_frequency(), 100e6);
+        assert!(matches!(
+            source.remaining(),
+            Remaining::Finite { num_samples: 3 }
+        ));
+
+        source.seek_to_sample(1).unwrap();
+        let mut output = vec![];
+        source
+            .read_to_end(&mut output)
+            .now_or_never()
+            .expect("test stream pending")
+            .unwrap();
+        assert_eq!(
+            output,
+            [
+                Complex::new(0.5 / 128.0, -0.5 / 128.0),
+                Complex::new(-63.5 / 128.0, 64.5 / 128.0)
+            ]
+        );
+        assert_eq!(source.position(), 3);
+    }
+}
@@ -1,8 +1,12 @@
+pub mod bfp;
 pub mod file;
 mod noise;
 #[cfg(feature = "rtlsdr")]
 pub mod rtlsdr;
+#[cfg(feature = "sigmf")]
+pub mod sigmf;
 mod sine;
+mod waveform;
 
 use std::{
     convert::Infallible,
@@ -16,14 +20,17 @@ use std::{
 use futures_util::Stream;
 pub use noise::*;
 pub use sine::*;
+pub use waveform::*;
 
 use crate::{
     buf::SamplesMut,
     io::{
         AsyncReadSamples,
+        GetSampleIndexMap,
         GetSampleRate,
         ReadBuf,
         Remaining,
+        SampleIndexMap,
         StreamLength,
     },
 };
@@ -149,6 +156,13 @@ where
     }
 }
 
+impl<G> GetSampleIndexMap for SignalGeneratorReadSamples<G> {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
+    }
+}
+
 impl<G> StreamLength for SignalGeneratorReadSamples<G> {
     #[inline]
     fn remaining(&self) -> Remaining {
@@ -1,5 +1,10 @@
 use std::{
     convert::Infallible,
+    f32::consts::TAU,
+    ops::{
+        Add,
+        Mul,
+    },
     pin::Pin,
     task::{
         Context,
@@ -8,6 +13,7 @@ use std::{
 };
 
 use num_complex::Complex;
+use num_traits::Zero;
 use pin_project_lite::pin_project;
 use rand::{
     Rng,
@@ -66,6 +72,17 @@ impl<R, D> StreamLength for Noise<R, D> {
     }
 }
 
+/// Additive white Gaussian noise with the given power.
+///
+/// This is [`white_noise`] with a normal distribution, which is what the
+/// thermal noise of a receiver looks like.
+pub fn gaussian_noise<R>(rng: R, power: f32) -> Noise<R, Gaussian>
+where
+    R: Rng,
+{
+    Noise::new(rng, Gaussian::new(power))
+}
+
 pub fn white_noise<R, S>(rng: R) -> Noise<R, S::Distribution>
 where
     R: Rng,
@@ -138,3 +155,205 @@ where
         Complex { re, im }
     }
 }
+
+/// Normal distribution with a mean of 0.
+///
+/// Complex samples are circularly symmetric, i.e. the power is split evenly
+/// between the real and imaginary part. The samples are generated with the
+/// Box-Muller transform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gaussian {
+    power: f32,
+}
+
+impl Gaussian {
+    /// The power is the variance of the samples.
+    pub fn new(power: f32) -> Self {
+        assert!(power >= 0.0, "invalid noise power: {power}");
+        Self { power }
+    }
+
+    #[inline]
+    pub fn power(&self) -> f32 {
+        self.power
+    }
+
+    /// Two independent samples with a variance of 1.
+    fn sample_pair<R: Rng + ?Sized>(rng: &mut R) -> (f32, f32) {
+        // the radius must not be 0, so that the logarithm is finite
+        let radius = (-2.0 * (1.0 - rng.random::<f32>()).ln()).sqrt();
+        let (sin, cos) = (TAU * rng.random::<f32>()).sin_cos();
+        (radius * cos, radius * sin)
+    }
+}
+
+impl Default for Gaussian {
+    #[inline]
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl Distribution<f32> for Gaussian {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+        let (sample, _) = Self::sample_pair(rng);
+        self.power.sqrt() * sample
+    }
+}
+
+impl Distribution<Complex<f32>> for Gaussian {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Complex<f32> {
+        let (re, im) = Self::sample_pair(rng);
+        (0.5 * self.power).sqrt() * Complex::new(re, im)
+    }
+}
+
+/// Power gain of the pink noise filter for white noise with a power of 1.
+const PINK_NOISE_GAIN: f32 = 9.318;
+
+pin_project! {
+    /// Pink noise, i.e. noise with a power spectral density proportional to
+    /// 1 / f.
+    ///
+    /// Gaussian white noise is filtered with Paul Kellet's refined filter,
+    /// which is accurate within 0.05 dB above 9 Hz at a sample rate of 44.1
+    /// kHz. The spectrum is relative to the sample rate, so at lower sample
+    /// rates the lowest frequencies are white.
+    #[derive(Clone, Debug)]
+    pub struct PinkNoise<R, S> {
+        rng: R,
+        white: Gaussian,
+        state: [S; 7],
+    }
+}
+
+impl<R, S> PinkNoise<R, S>
+where
+    S: Zero + Copy,
+{
+    /// Pink noise with the given power.
+    pub fn new(rng: R, power: f32) -> Self {
+        Self {
+            rng,
+            white: Gaussian::new(power / PINK_NOISE_GAIN),
+            state: [S::zero(); 7],
+        }
+    }
+}
+
+impl<R, S> PinkNoise<R, S>
+where
+    R: Rng,
+    S: Copy + Add<S, Output = S> + Mul<f32, Output = S>,
+    Gaussian: Distribution<S>,
+{
+    pub fn next_sample(&mut self) -> S {
+        let white: S = self.rng.sample(self.white);
+        let [b0, b1, b2, b3, b4, b5, b6] = self.state;
+
+        let b0 = b0 * 0.99886 + white * 0.0555179;
+        let b1 = b1 * 0.99332 + white * 0.0750759;
+        let b2 = b2 * 0.96900 + white * 0.153852;
+        let b3 = b3 * 0.86650 + white * 0.3104856;
+        let b4 = b4 * 0.55000 + white * 0.5329522;
+        let b5 = b5 * -0.7616 + white * -0.016898;
+        let output = b0 + b1 + b2 + b3 + b4 + b5 + b6 + white * 0.5362;
+
+        self.state = [b0, b1, b2, b3, b4, b5, white * 0.115926];
+        output
+    }
+}
+
+impl<R, S> AsyncReadSamples<S> for PinkNoise<R, S>
+where
+    R: Rng,
+    S: Copy + Add<S, Output = S> + Mul<f32, Output = S>,
+    Gaussian: Distribution<S>,
+{
+    type Error = Infallible;
+
+    #[inline]
+    fn poll_read_samples(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<S>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        buffer.fill_with(|| this.next_sample());
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R, S> StreamLength for PinkNoise<R, S> {
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        Remaining::Infinite
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex;
+    use rand::{
+        RngExt,
+        SeedableRng,
+        rngs::SmallRng,
+    };
+
+    use super::{
+        Gaussian,
+        PinkNoise,
+    };
+
+    const NUM_SAMPLES: usize = 100_000;
+
+    /// Power and the correlation of neighbouring samples.
+    fn measure(samples: &[f32]) -> (f32, f32) {
+        let power = samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32;
+        let correlation = samples
+            .windows(2)
+            .map(|pair| pair[0] * pair[1])
+            .sum::<f32>()
+            / samples.len() as f32;
+        (power, correlation / power)
+    }
+
+    #[test]
+    fn gaussian_noise_has_the_power() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let gaussian = Gaussian::new(0.5);
+
+        let samples = (0..NUM_SAMPLES)
+            .map(|_| rng.sample::<f32, _>(gaussian))
+            .collect::<Vec<_>>();
+        let (power, correlation) = measure(&samples);
+        assert!((power - 0.5).abs() < 0.01, "power: {power}");
+        assert!(correlation.abs() < 0.02, "correlation: {correlation}");
+
+        // about 0.3% of the samples are beyond 3 standard deviations
+        let outliers = samples
+            .iter()
+            .filter(|x| x.abs() > 3.0 * 0.5f32.sqrt())
+            .count();
+        assert!((150..400).contains(&outliers), "outliers: {outliers}");
+
+        let power = (0..NUM_SAMPLES)
+            .map(|_| rng.sample::<Complex<f32>, _>(gaussian).norm_sqr())
+            .sum::<f32>()
+            / NUM_SAMPLES as f32;
+        assert!((power - 0.5).abs() < 0.01, "complex power: {power}");
+    }
+
+    #[test]
+    fn pink_noise_is_correlated() {
+        let mut pink_noise = PinkNoise::<_, f32>::new(SmallRng::seed_from_u64(1), 0.5);
+        let samples = (0..NUM_SAMPLES)
+            .map(|_| pink_noise.next_sample())
+            .collect::<Vec<_>>();
+
+        // the power at low frequencies makes the measured power vary
+        let (power, correlation) = measure(&samples);
+        assert!((power - 0.5).abs() < 0.1, "power: {power}");
+        assert!(correlation > 0.7, "correlation: {correlation}");
+    }
+}
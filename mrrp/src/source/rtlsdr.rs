@@ -26,12 +26,18 @@ use crate::{
         SampleBufMut,
         TryAdvanceError,
     },
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
     io::{
         AsyncReadSamples,
         GetCenterFrequency,
+        GetSampleIndexMap,
         GetSampleRate,
         ReadBuf,
         Remaining,
+        SampleIndexMap,
         SizeHint,
         StreamLength,
     },
@@ -40,6 +46,13 @@ use crate::{
 
 pub type Error = rtlsdr_async::Error;
 
+impl ClassifyError for Error {
+    #[inline]
+    fn error_kind(&self) -> ErrorKind {
+        ErrorKind::Hardware
+    }
+}
+
 #[inline]
 fn try_advance_chunk<S>(chunk: &mut Chunk<S>, amount: usize) -> Result<(), TryAdvanceError> {
     if amount <= chunk.len() {
@@ -194,6 +207,13 @@ impl GetCenterFrequency for RtlSdrSource {
     }
 }
 
+impl GetSampleIndexMap for RtlSdrSource {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
+    }
+}
+
 impl StreamLength for RtlSdrSource {
     #[inline]
     fn remaining(&self) -> Remaining {
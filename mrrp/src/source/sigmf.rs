@@ -0,0 +1,200 @@
+//! Playback of [SigMF](https://sigmf.org) recordings.
+
+use std::{
+    fs::File,
+    io::{
+        self,
+        BufReader,
+        Read,
+        Seek,
+    },
+    path::Path,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+    time::Duration,
+};
+
+use num_complex::Complex;
+
+pub use crate::util::sigmf::{
+    Annotation,
+    Capture,
+    Error,
+    SigmfMetadata,
+};
+use crate::{
+    io::{
+        AsyncReadSamples,
+        FiniteStream,
+        GetCenterFrequency,
+        GetSampleIndexMap,
+        GetSampleRate,
+        ReadBuf,
+        Remaining,
+        SampleIndexMap,
+        StreamLength,
+    },
+    source::file::RawIqSource,
+    util::sigmf,
+};
+
+/// Reads the samples of a SigMF recording.
+///
+/// The sample rate and datatype are taken from the metadata. The center
+/// frequency is the one of the capture that the next sample belongs to, so it
+/// changes if the recording was retuned.
+#[derive(Debug)]
+pub struct SigmfSource<R> {
+    inner: RawIqSource<R>,
+    metadata: SigmfMetadata,
+}
+
+impl<R> SigmfSource<R>
+where
+    R: Read + Seek,
+{
+    pub fn from_reader(metadata: SigmfMetadata, data: R) -> Result<Self, Error> {
+        let format = metadata.raw_iq_format()?;
+        let sample_rate = metadata.sample_rate()? as f32;
+        let center_frequency = center_frequency(&metadata, 0);
+        let inner = RawIqSource::from_seekable_reader(data, format, sample_rate, center_frequency)?;
+        Ok(Self { inner, metadata })
+    }
+
+    /// Seeks to the sample with the given index, so that it's read next.
+    #[inline]
+    pub fn seek_to_sample(&mut self, index: u64) -> Result<(), io::Error> {
+        self.inner.seek_to_sample(index)
+    }
+
+    /// Seeks to the sample at `time` from the start of the recording.
+    #[inline]
+    pub fn seek_to_time(&mut self, time: Duration) -> Result<(), io::Error> {
+        self.inner.seek_to_time(time)
+    }
+}
+
+impl SigmfSource<BufReader<File>> {
+    /// Opens a recording. `path` can be the metadata or data file, or their
+    /// name without an extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let (meta_path, data_path) = sigmf::paths(path);
+        let metadata = SigmfMetadata::read_from_path(meta_path)?;
+        Self::from_reader(metadata, BufReader::new(File::open(data_path)?))
+    }
+}
+
+impl<R> SigmfSource<R> {
+    #[inline]
+    pub fn metadata(&self) -> &SigmfMetadata {
+        &self.metadata
+    }
+
+    #[inline]
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.metadata.annotations
+    }
+
+    /// The capture that the next sample belongs to.
+    #[inline]
+    pub fn capture(&self) -> Option<&Capture> {
+        self.metadata.capture_at(self.inner.position())
+    }
+
+    /// Number of samples in the recording.
+    #[inline]
+    pub fn num_samples(&self) -> Option<u64> {
+        self.inner.num_samples()
+    }
+
+    /// Index of the next sample that is read.
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.inner.position()
+    }
+}
+
+impl<R> AsyncReadSamples<Complex<f32>> for SigmfSource<R>
+where
+    R: Read + Unpin,
+{
+    type Error = io::Error;
+
+    fn poll_read_samples(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buffer: &mut ReadBuf<Complex<f32>>,
+    ) -> Poll<Result<(), Self::Error>> {
+        // don't read past the start of the next capture, so that the center
+        // frequency is right for all samples that are returned
+        let position = self.inner.position();
+        let next_capture = self
+            .metadata
+            .captures
+            .iter()
+            .map(|capture| capture.sample_start)
+            .find(|sample_start| *sample_start > position);
+
+        let Some(next_capture) = next_capture
+        else {
+            return Pin::new(&mut self.inner).poll_read_samples(cx, buffer);
+        };
+
+        let mut read_buf = buffer.take((next_capture - position) as usize);
+        ready!(Pin::new(&mut self.inner).poll_read_samples(cx, &mut read_buf))?;
+
+        let initialized = read_buf.initialized().len();
+        let filled = read_buf.filled().len();
+        unsafe {
+            buffer.assume_init(initialized);
+        }
+        buffer.set_filled(buffer.filled().len() + filled);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R> GetSampleRate for SigmfSource<R> {
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.inner.sample_rate()
+    }
+}
+
+impl<R> GetCenterFrequency for SigmfSource<R> {
+    /// The center frequency of the capture that the next sample belongs to,
+    /// or 0 if it has none.
+    #[inline]
+    fn center_frequency(&self) -> f32 {
+        center_frequency(&self.metadata, self.inner.position())
+    }
+}
+
+impl<R> GetSampleIndexMap for SigmfSource<R> {
+    #[inline]
+    fn sample_index_map(&self) -> SampleIndexMap {
+        SampleIndexMap::IDENTITY
+    }
+}
+
+impl<R> StreamLength for SigmfSource<R>
+where
+    R: Read,
+{
+    #[inline]
+    fn remaining(&self) -> Remaining {
+        self.inner.remaining()
+    }
+}
+
+impl<R> FiniteStream for SigmfSource<R> where R: Read {}
+
+fn center_frequency(metadata: &SigmfMetadata, sample_index: u64) -> f32 {
+    metadata
+        .capture_at(sample_index)
+        .and_then(|capture| capture.frequency)
+        .unwrap_or_default() as f32
+}
@@ -49,6 +49,7 @@ impl SineWave {
 
     #[inline]
     pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
         self.step = step_from_frequency_and_sample_rate(frequency, self.sample_rate);
     }
 }
@@ -58,6 +59,7 @@ impl SignalGenerator for SineWave {
 
     #[inline]
     fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
         self.step = step_from_frequency_and_sample_rate(self.frequency, sample_rate);
     }
 
@@ -115,6 +117,7 @@ impl ComplexSinusoid {
 
     #[inline]
     pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
         self.step = step_from_frequency_and_sample_rate(frequency, self.sample_rate);
     }
 }
@@ -124,6 +127,7 @@ impl SignalGenerator for ComplexSinusoid {
 
     #[inline]
     fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
         self.step = step_from_frequency_and_sample_rate(self.frequency, sample_rate);
     }
 
@@ -0,0 +1,687 @@
+//! Square, triangle and sawtooth waves, chirps and multi-tone signals
+//!
+//! The square, triangle and sawtooth waves can be band-limited, which
+//! removes most of the aliasing of their harmonics above the Nyquist
+//! frequency. This uses polynomial band-limited steps (PolyBLEP) for the
+//! jumps of the square and sawtooth wave, and ramps (PolyBLAMP) for the
+//! corners of the triangle wave.
+//!
+//! # References
+//!
+//! - V. Välimäki, A. Huovilainen, "Antialiasing oscillators in subtractive
+//!   synthesis", 2007
+//! - F. Esqueda, V. Välimäki, S. Bilbao, "Rounding corners with BLAMP", 2016
+
+use std::f32::consts::TAU;
+
+use crate::{
+    io::GetSampleRate,
+    source::{
+        SignalGenerator,
+        SineWave,
+    },
+};
+
+/// Phase in cycles, shared by the waveforms.
+#[derive(Clone, Copy, Debug)]
+struct Phasor {
+    frequency: f32,
+    sample_rate: f32,
+    /// Phase in `0..1`.
+    phase: f32,
+    /// Phase increment per sample, i.e. the normalized frequency.
+    step: f32,
+}
+
+impl Phasor {
+    fn new(frequency: f32, sample_rate: f32) -> Self {
+        Self {
+            frequency,
+            sample_rate,
+            phase: 0.0,
+            step: frequency / sample_rate,
+        }
+    }
+
+    /// Sets the phase in radians.
+    fn set_phase(&mut self, phase: f32) {
+        self.phase = (phase / TAU).rem_euclid(1.0);
+    }
+
+    fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+        self.step = frequency / self.sample_rate;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.step = self.frequency / sample_rate;
+    }
+
+    /// Returns the phase and the phase increment, and advances the phase.
+    fn next(&mut self) -> (f32, f32) {
+        let phase = self.phase;
+        self.phase = (self.phase + self.step).rem_euclid(1.0);
+        (phase, self.step.abs())
+    }
+}
+
+/// Correction for a jump of -2 at phase 0.
+fn poly_blep(phase: f32, step: f32) -> f32 {
+    if phase < step {
+        let x = phase / step;
+        2.0 * x - x * x - 1.0
+    }
+    else if phase > 1.0 - step {
+        let x = (phase - 1.0) / step;
+        x * x + 2.0 * x + 1.0
+    }
+    else {
+        0.0
+    }
+}
+
+/// Correction for a change of the slope by 2 per sample at phase 0, i.e. the
+/// integral of [`poly_blep`].
+fn poly_blamp(phase: f32, step: f32) -> f32 {
+    if phase < step {
+        let x = 1.0 - phase / step;
+        x * x * x / 3.0
+    }
+    else if phase > 1.0 - step {
+        let x = (phase - 1.0) / step + 1.0;
+        x * x * x / 3.0
+    }
+    else {
+        0.0
+    }
+}
+
+macro_rules! impl_waveform {
+    ($T:ident) => {
+        impl $T {
+            /// Sets the phase in radians. A phase of 0 starts the wave like a
+            /// sine wave.
+            #[inline]
+            pub fn with_phase(mut self, phase: f32) -> Self {
+                self.phasor.set_phase(phase);
+                self
+            }
+
+            /// Removes most of the aliasing, at the cost of slightly rounding
+            /// the wave.
+            #[inline]
+            pub fn with_band_limiting(mut self, band_limited: bool) -> Self {
+                self.band_limited = band_limited;
+                self
+            }
+
+            #[inline]
+            pub fn is_band_limited(&self) -> bool {
+                self.band_limited
+            }
+
+            /// The phase in radians.
+            #[inline]
+            pub fn phase(&self) -> f32 {
+                self.phasor.phase * TAU
+            }
+
+            #[inline]
+            pub fn set_phase(&mut self, phase: f32) {
+                self.phasor.set_phase(phase);
+            }
+
+            #[inline]
+            pub fn frequency(&self) -> f32 {
+                self.phasor.frequency
+            }
+
+            #[inline]
+            pub fn set_frequency(&mut self, frequency: f32) {
+                self.phasor.set_frequency(frequency);
+            }
+        }
+
+        impl GetSampleRate for $T {
+            #[inline]
+            fn sample_rate(&self) -> f32 {
+                self.phasor.sample_rate
+            }
+        }
+    };
+}
+
+/// Square wave between -1 and 1.
+#[derive(Clone, Copy, Debug)]
+pub struct SquareWave {
+    phasor: Phasor,
+    duty_cycle: f32,
+    band_limited: bool,
+}
+
+impl SquareWave {
+    #[inline]
+    pub fn new(frequency: f32, sample_rate: f32) -> Self {
+        Self {
+            phasor: Phasor::new(frequency, sample_rate),
+            duty_cycle: 0.5,
+            band_limited: false,
+        }
+    }
+
+    /// The fraction of each period that the wave is high. This is 0.5 by
+    /// default.
+    #[inline]
+    pub fn with_duty_cycle(mut self, duty_cycle: f32) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&duty_cycle),
+            "invalid duty cycle: {duty_cycle}"
+        );
+        self.duty_cycle = duty_cycle;
+        self
+    }
+
+    #[inline]
+    pub fn duty_cycle(&self) -> f32 {
+        self.duty_cycle
+    }
+}
+
+impl_waveform!(SquareWave);
+
+impl SignalGenerator for SquareWave {
+    type Sample = f32;
+
+    #[inline]
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.phasor.set_sample_rate(sample_rate);
+    }
+
+    fn next(&mut self) -> Self::Sample {
+        let (phase, step) = self.phasor.next();
+        let mut output = if phase < self.duty_cycle { 1.0 } else { -1.0 };
+        if self.band_limited {
+            output += poly_blep(phase, step);
+            output -= poly_blep((phase - self.duty_cycle).rem_euclid(1.0), step);
+        }
+        output
+    }
+}
+
+/// Triangle wave between -1 and 1.
+#[derive(Clone, Copy, Debug)]
+pub struct TriangleWave {
+    phasor: Phasor,
+    band_limited: bool,
+}
+
+impl TriangleWave {
+    #[inline]
+    pub fn new(frequency: f32, sample_rate: f32) -> Self {
+        Self {
+            phasor: Phasor::new(frequency, sample_rate),
+            band_limited: false,
+        }
+    }
+}
+
+impl_waveform!(TriangleWave);
+
+impl SignalGenerator for TriangleWave {
+    type Sample = f32;
+
+    #[inline]
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.phasor.set_sample_rate(sample_rate);
+    }
+
+    fn next(&mut self) -> Self::Sample {
+        let (phase, step) = self.phasor.next();
+
+        // the minimum is at 0, and the maximum at 0.5
+        let phase = (phase + 0.25).rem_euclid(1.0);
+        let mut output = 1.0 - 4.0 * (phase - 0.5).abs();
+        if self.band_limited {
+            // the slope changes by 8 per period
+            output += 4.0 * step * poly_blamp(phase, step);
+            output -= 4.0 * step * poly_blamp((phase + 0.5).rem_euclid(1.0), step);
+        }
+        output
+    }
+}
+
+/// Rising sawtooth wave between -1 and 1.
+#[derive(Clone, Copy, Debug)]
+pub struct SawtoothWave {
+    phasor: Phasor,
+    band_limited: bool,
+}
+
+impl SawtoothWave {
+    #[inline]
+    pub fn new(frequency: f32, sample_rate: f32) -> Self {
+        Self {
+            phasor: Phasor::new(frequency, sample_rate),
+            band_limited: false,
+        }
+    }
+}
+
+impl_waveform!(SawtoothWave);
+
+impl SignalGenerator for SawtoothWave {
+    type Sample = f32;
+
+    #[inline]
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.phasor.set_sample_rate(sample_rate);
+    }
+
+    fn next(&mut self) -> Self::Sample {
+        let (phase, step) = self.phasor.next();
+
+        // the jump is at 0.5
+        let phase = (phase + 0.5).rem_euclid(1.0);
+        let mut output = 2.0 * phase - 1.0;
+        if self.band_limited {
+            output -= poly_blep(phase, step);
+        }
+        output
+    }
+}
+
+/// How the frequency of a [`Chirp`] changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Sweep {
+    /// The frequency changes by the same amount of Hz per second.
+    #[default]
+    Linear,
+
+    /// The frequency changes by the same ratio per second, i.e. each octave
+    /// takes the same time.
+    Logarithmic,
+}
+
+/// Sine wave whose frequency sweeps from a start to an end frequency.
+///
+/// When the sweep ends, it starts over with a continuous phase.
+#[derive(Clone, Copy, Debug)]
+pub struct Chirp {
+    sweep: Sweep,
+    start_frequency: f32,
+    end_frequency: f32,
+    duration: f32,
+    sample_rate: f32,
+    /// Progress of the sweep in `0..1`.
+    position: f32,
+    /// Phase in radians.
+    phase: f32,
+}
+
+impl Chirp {
+    /// Sweeps from `start_frequency` to `end_frequency` in `duration` seconds.
+    ///
+    /// For a logarithmic sweep, both frequencies must be positive, or both
+    /// negative.
+    pub fn new(
+        sweep: Sweep,
+        start_frequency: f32,
+        end_frequency: f32,
+        duration: f32,
+        sample_rate: f32,
+    ) -> Self {
+        assert!(duration > 0.0, "invalid sweep duration: {duration}");
+        if sweep == Sweep::Logarithmic {
+            assert!(
+                start_frequency * end_frequency > 0.0,
+                "logarithmic sweep through 0 Hz: {start_frequency} to {end_frequency}"
+            );
+        }
+
+        Self {
+            sweep,
+            start_frequency,
+            end_frequency,
+            duration,
+            sample_rate,
+            position: 0.0,
+            phase: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn linear(
+        start_frequency: f32,
+        end_frequency: f32,
+        duration: f32,
+        sample_rate: f32,
+    ) -> Self {
+        Self::new(
+            Sweep::Linear,
+            start_frequency,
+            end_frequency,
+            duration,
+            sample_rate,
+        )
+    }
+
+    #[inline]
+    pub fn logarithmic(
+        start_frequency: f32,
+        end_frequency: f32,
+        duration: f32,
+        sample_rate: f32,
+    ) -> Self {
+        Self::new(
+            Sweep::Logarithmic,
+            start_frequency,
+            end_frequency,
+            duration,
+            sample_rate,
+        )
+    }
+
+    #[inline]
+    pub fn sweep(&self) -> Sweep {
+        self.sweep
+    }
+
+    /// The frequency of the next sample in Hz.
+    pub fn frequency(&self) -> f32 {
+        match self.sweep {
+            Sweep::Linear => {
+                self.start_frequency + (self.end_frequency - self.start_frequency) * self.position
+            }
+            Sweep::Logarithmic => {
+                self.start_frequency
+                    * (self.end_frequency / self.start_frequency).powf(self.position)
+            }
+        }
+    }
+}
+
+impl SignalGenerator for Chirp {
+    type Sample = f32;
+
+    #[inline]
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn next(&mut self) -> Self::Sample {
+        let output = self.phase.sin();
+
+        self.phase = (self.phase + TAU * self.frequency() / self.sample_rate).rem_euclid(TAU);
+        self.position += 1.0 / (self.duration * self.sample_rate);
+        if self.position >= 1.0 {
+            self.position -= 1.0;
+        }
+
+        output
+    }
+}
+
+impl GetSampleRate for Chirp {
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+}
+
+/// Sum of sine waves, e.g. to measure the intermodulation of a system.
+#[derive(Clone, Debug)]
+pub struct MultiTone {
+    sample_rate: f32,
+    tones: Vec<(SineWave, f32)>,
+}
+
+impl MultiTone {
+    /// A signal without any tones.
+    #[inline]
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            tones: vec![],
+        }
+    }
+
+    /// Tones with the same amplitude, so that the sum's peak is at most 1.
+    pub fn from_frequencies(frequencies: impl IntoIterator<Item = f32>, sample_rate: f32) -> Self {
+        let mut multi_tone = Self::new(sample_rate);
+        for frequency in frequencies {
+            multi_tone.push_tone(frequency, 1.0);
+        }
+
+        let amplitude = 1.0 / multi_tone.tones.len().max(1) as f32;
+        for (_, tone_amplitude) in &mut multi_tone.tones {
+            *tone_amplitude = amplitude;
+        }
+        multi_tone
+    }
+
+    #[inline]
+    pub fn with_tone(mut self, frequency: f32, amplitude: f32) -> Self {
+        self.push_tone(frequency, amplitude);
+        self
+    }
+
+    pub fn push_tone(&mut self, frequency: f32, amplitude: f32) {
+        self.tones
+            .push((SineWave::new(frequency, self.sample_rate), amplitude));
+    }
+
+    #[inline]
+    pub fn num_tones(&self) -> usize {
+        self.tones.len()
+    }
+}
+
+impl SignalGenerator for MultiTone {
+    type Sample = f32;
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for (tone, _) in &mut self.tones {
+            tone.set_sample_rate(sample_rate);
+        }
+    }
+
+    fn next(&mut self) -> Self::Sample {
+        self.tones
+            .iter_mut()
+            .map(|(tone, amplitude)| *amplitude * tone.next())
+            .sum()
+    }
+}
+
+impl GetSampleRate for MultiTone {
+    #[inline]
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+}
+
+#[inline]
+pub fn square(frequency: f32, sample_rate: f32) -> SquareWave {
+    SquareWave::new(frequency, sample_rate)
+}
+
+#[inline]
+pub fn triangle(frequency: f32, sample_rate: f32) -> TriangleWave {
+    TriangleWave::new(frequency, sample_rate)
+}
+
+#[inline]
+pub fn sawtooth(frequency: f32, sample_rate: f32) -> SawtoothWave {
+    SawtoothWave::new(frequency, sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use approx::assert_abs_diff_eq;
+    use num_complex::Complex;
+
+    use super::{
+        Chirp,
+        MultiTone,
+        SawtoothWave,
+        SquareWave,
+        TriangleWave,
+    };
+    use crate::{
+        io::GetSampleRate,
+        source::{
+            SignalGenerator,
+            SineWave,
+        },
+    };
+
+    const SAMPLE_RATE: f32 = 8000.0;
+
+    fn generate(generator: &mut impl SignalGenerator<Sample = f32>, n: usize) -> Vec<f32> {
+        (0..n).map(|_| generator.next()).collect()
+    }
+
+    /// Amplitude of the component at `frequency`.
+    fn amplitude(samples: &[f32], frequency: f32) -> f32 {
+        let sum = samples
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                let phase = (TAU * frequency * i as f32 / SAMPLE_RATE).rem_euclid(TAU);
+                *sample * Complex::from_polar(1.0, -phase)
+            })
+            .sum::<Complex<f32>>();
+        2.0 * sum.norm() / samples.len() as f32
+    }
+
+    /// The strongest alias of the harmonics above the Nyquist frequency.
+    fn worst_alias(samples: &[f32], frequency: f32) -> f32 {
+        (4..40)
+            .map(|harmonic| {
+                let alias = (harmonic as f32 * frequency) % SAMPLE_RATE;
+                amplitude(samples, alias.min(SAMPLE_RATE - alias))
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn it_generates_waveforms() {
+        // 8 samples per period
+        let square = generate(&mut SquareWave::new(1000.0, SAMPLE_RATE), 8);
+        assert_eq!(square, [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+
+        let square = generate(
+            &mut SquareWave::new(1000.0, SAMPLE_RATE).with_duty_cycle(0.25),
+            8,
+        );
+        assert_eq!(square, [1.0, 1.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0]);
+
+        let triangle = generate(&mut TriangleWave::new(1000.0, SAMPLE_RATE), 8);
+        for (output, expected) in triangle
+            .iter()
+            .zip([0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0, -0.5])
+        {
+            assert_abs_diff_eq!(*output, expected, epsilon = 1e-6);
+        }
+
+        let sawtooth = generate(&mut SawtoothWave::new(1000.0, SAMPLE_RATE), 8);
+        for (output, expected) in sawtooth
+            .iter()
+            .zip([0.0, 0.25, 0.5, 0.75, -1.0, -0.75, -0.5, -0.25])
+        {
+            assert_abs_diff_eq!(*output, expected, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn band_limiting_reduces_aliasing() {
+        const FREQUENCY: f32 = 1234.0;
+
+        let check = |naive: &[f32], band_limited: &[f32], fundamental: f32| {
+            assert_abs_diff_eq!(amplitude(naive, FREQUENCY), fundamental, epsilon = 0.01);
+            let naive_alias = worst_alias(naive, FREQUENCY);
+            let band_limited_alias = worst_alias(band_limited, FREQUENCY);
+            assert!(
+                band_limited_alias < naive_alias / 3.0,
+                "{band_limited_alias} vs {naive_alias}"
+            );
+        };
+
+        let square = SquareWave::new(FREQUENCY, SAMPLE_RATE);
+        check(
+            &generate(&mut square.with_band_limiting(false), 8000),
+            &generate(&mut square.with_band_limiting(true), 8000),
+            4.0 / TAU * 2.0,
+        );
+
+        let triangle = TriangleWave::new(FREQUENCY, SAMPLE_RATE);
+        check(
+            &generate(&mut triangle.with_band_limiting(false), 8000),
+            &generate(&mut triangle.with_band_limiting(true), 8000),
+            32.0 / (TAU * TAU),
+        );
+
+        let sawtooth = SawtoothWave::new(FREQUENCY, SAMPLE_RATE);
+        check(
+            &generate(&mut sawtooth.with_band_limiting(false), 8000),
+            &generate(&mut sawtooth.with_band_limiting(true), 8000),
+            4.0 / TAU,
+        );
+    }
+
+    #[test]
+    fn chirp_sweeps() {
+        let mut chirp = Chirp::linear(100.0, 1100.0, 1.0, SAMPLE_RATE);
+        generate(&mut chirp, 4000);
+        assert_abs_diff_eq!(chirp.frequency(), 600.0, epsilon = 0.5);
+        // it starts over after a second
+        generate(&mut chirp, 8000);
+        assert_abs_diff_eq!(chirp.frequency(), 600.0, epsilon = 0.5);
+
+        let mut chirp = Chirp::logarithmic(100.0, 1600.0, 1.0, SAMPLE_RATE);
+        generate(&mut chirp, 4000);
+        assert_abs_diff_eq!(chirp.frequency(), 400.0, epsilon = 0.5);
+
+        // the next 25 ms sweep from 400 Hz to 429 Hz
+        let samples = generate(&mut chirp, 200);
+        assert!(amplitude(&samples, 414.0) > 0.9);
+    }
+
+    #[test]
+    fn multi_tone_is_a_sum_of_sine_waves() {
+        let mut multi_tone = MultiTone::from_frequencies([300.0, 1000.0], SAMPLE_RATE);
+        let mut low = SineWave::new(300.0, SAMPLE_RATE);
+        let mut high = SineWave::new(1000.0, SAMPLE_RATE);
+
+        for _ in 0..1000 {
+            let expected = 0.5 * (low.next() + high.next());
+            assert_abs_diff_eq!(multi_tone.next(), expected, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn it_sets_the_sample_rate() {
+        let mut square = SquareWave::new(1000.0, 16000.0);
+        square.set_sample_rate(SAMPLE_RATE);
+        assert_eq!(square.sample_rate(), SAMPLE_RATE);
+        assert_eq!(
+            generate(&mut square, 8),
+            generate(&mut SquareWave::new(1000.0, SAMPLE_RATE), 8)
+        );
+
+        let mut multi_tone = MultiTone::new(16000.0).with_tone(1000.0, 0.5);
+        multi_tone.set_sample_rate(SAMPLE_RATE);
+        assert_eq!(multi_tone.sample_rate(), SAMPLE_RATE);
+        let samples = generate(&mut multi_tone, 8000);
+        assert_abs_diff_eq!(amplitude(&samples, 1000.0), 0.5, epsilon = 1e-3);
+
+        let mut chirp = Chirp::linear(100.0, 1100.0, 1.0, 16000.0);
+        chirp.set_sample_rate(SAMPLE_RATE);
+        generate(&mut chirp, 4000);
+        assert_abs_diff_eq!(chirp.frequency(), 600.0, epsilon = 0.5);
+    }
+}
@@ -0,0 +1,616 @@
+//! Spectrum analysis
+//!
+//! [`SpectrumAnalyzer`] estimates the power spectrum of an IQ stream with
+//! Welch's method: The stream is cut into overlapping segments, each is
+//! windowed and transformed, and the power of several segments is averaged.
+//! The resulting [`Spectrum`]s are in dBFS, i.e. a complex tone with an
+//! amplitude of 1 shows up at 0 dB, no matter which window is used.
+
+use std::{
+    f64::consts::TAU,
+    ops::Range,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use futures_util::Stream;
+use num_complex::Complex;
+use pin_project_lite::pin_project;
+
+use crate::{
+    fft::{
+        CpuFft,
+        FftBackend,
+        FftPool,
+    },
+    io::{
+        AsyncReadSamples,
+        GetSampleRate,
+        ReadBuf,
+    },
+};
+
+const BUFFER_SIZE: usize = 4096;
+
+/// Window applied to each segment before it's transformed.
+///
+/// Windows trade frequency resolution for leakage: A window with low
+/// sidelobes keeps strong signals from spilling into distant bins, but makes
+/// every signal wider.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Window {
+    /// No window. This has the best resolution, but strong leakage.
+    Rectangular,
+
+    /// A good default with sidelobes at -31 dB.
+    #[default]
+    Hann,
+
+    /// 4-term Blackman-Harris window with sidelobes at -92 dB, e.g. to find
+    /// weak signals next to strong ones.
+    BlackmanHarris,
+
+    /// Flat-top window, which measures the amplitude of tones accurately,
+    /// even if they're between two bins.
+    FlatTop,
+}
+
+impl Window {
+    /// Coefficients of the cosine terms, with alternating signs.
+    fn coefficients(&self) -> &'static [f64] {
+        match self {
+            Self::Rectangular => &[1.0],
+            Self::Hann => &[0.5, 0.5],
+            Self::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Self::FlatTop => {
+                &[
+                    0.21557895,
+                    0.41663158,
+                    0.277263158,
+                    0.083578947,
+                    0.006947368,
+                ]
+            }
+        }
+    }
+
+    /// The window for a segment of `size` samples.
+    ///
+    /// This is the periodic form of the window, which is the one to use for
+    /// spectral analysis.
+    pub fn samples(&self, size: usize) -> Vec<f32> {
+        let coefficients = self.coefficients();
+        (0..size)
+            .map(|n| {
+                let x = TAU * n as f64 / size as f64;
+                coefficients
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f64 * x).cos()
+                    })
+                    .sum::<f64>() as f32
+            })
+            .collect()
+    }
+
+    /// The equivalent noise bandwidth in bins.
+    ///
+    /// This is the width of a rectangular filter that lets through as much
+    /// noise as a bin does with this window, e.g. 1.5 bins for a Hann window.
+    pub fn equivalent_noise_bandwidth(&self, size: usize) -> f32 {
+        let window = self.samples(size);
+        let sum = window.iter().sum::<f32>();
+        let sum_of_squares = window.iter().map(|w| w * w).sum::<f32>();
+        size as f32 * sum_of_squares / (sum * sum)
+    }
+}
+
+/// Power spectrum computed by a [`SpectrumAnalyzer`].
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    /// Power of each bin in dBFS.
+    ///
+    /// The bins go from `-sample_rate / 2` to `sample_rate / 2`, with DC in
+    /// the bin at `len() / 2`.
+    pub power: Vec<f32>,
+
+    pub sample_rate: f32,
+
+    /// Bandwidth in Hz that noise is measured in, per bin. Subtract this in
+    /// dB from the power to get the noise density in dBFS/Hz.
+    pub resolution_bandwidth: f32,
+
+    /// Number of segments that were averaged.
+    pub num_averaged: usize,
+}
+
+impl Spectrum {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.power.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.power.is_empty()
+    }
+
+    /// Spacing of the bins in Hz.
+    #[inline]
+    pub fn bin_width(&self) -> f32 {
+        self.sample_rate / self.len() as f32
+    }
+
+    /// The frequency of a bin in Hz, relative to the center.
+    #[inline]
+    pub fn frequency(&self, bin: usize) -> f32 {
+        (bin as f32 - (self.len() / 2) as f32) * self.bin_width()
+    }
+
+    /// The bin closest to `frequency` in Hz, if it's in the spectrum.
+    pub fn bin(&self, frequency: f32) -> Option<usize> {
+        let bin = (frequency / self.bin_width()).round() + (self.len() / 2) as f32;
+        (0.0..self.len() as f32)
+            .contains(&bin)
+            .then_some(bin as usize)
+    }
+
+    /// Total power in dBFS of a range of bins.
+    ///
+    /// Neighbouring bins overlap, so summing up their power would count noise
+    /// more than once. This corrects for that, so that the power of all bins
+    /// is the power of the signal.
+    pub fn band_power(&self, bins: Range<usize>) -> f32 {
+        let bins_per_resolution_bandwidth = self.resolution_bandwidth / self.bin_width();
+        let power = self.power[bins]
+            .iter()
+            .map(|power| 10f32.powf(power / 10.0))
+            .sum::<f32>();
+        10.0 * (power / bins_per_resolution_bandwidth).max(1e-20).log10()
+    }
+
+    /// Frequency in Hz and power in dBFS of the strongest bin.
+    pub fn peak(&self) -> Option<(f32, f32)> {
+        self.power
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(bin, power)| (self.frequency(bin), *power))
+    }
+}
+
+/// Welch's method on samples that are pushed into it.
+///
+/// This is the estimator behind [`SpectrumAnalyzer`], for when the samples
+/// don't come from a stream, e.g. in an
+/// [`Inspector`][crate::io::combinators::Inspector]. By default segments
+/// don't overlap, aren't averaged, and use a [Hann window][Window::Hann].
+#[derive(Debug)]
+pub struct WelchEstimator {
+    sample_rate: f32,
+
+    fft: Box<dyn FftBackend>,
+    fft_size: usize,
+    window_kind: Window,
+    window: Vec<f32>,
+    hop_size: usize,
+    num_averages: usize,
+
+    /// Samples of the current segment.
+    segment: Vec<Complex<f32>>,
+    transform: Vec<Complex<f32>>,
+    /// Sum of the power of the transformed segments.
+    accumulator: Vec<f32>,
+    num_accumulated: usize,
+}
+
+impl WelchEstimator {
+    pub fn new(sample_rate: f32, fft_size: usize) -> Self {
+        assert!(fft_size > 0, "FFT size must be greater than 0");
+
+        Self {
+            sample_rate,
+            fft: Box::new(CpuFft::new(fft_size)),
+            fft_size,
+            window_kind: Window::default(),
+            window: Window::default().samples(fft_size),
+            hop_size: fft_size,
+            num_averages: 1,
+            segment: Vec::with_capacity(fft_size),
+            transform: vec![Complex::default(); fft_size],
+            accumulator: vec![0.0; fft_size],
+            num_accumulated: 0,
+        }
+    }
+
+    pub fn with_window(mut self, window: Window) -> Self {
+        self.window_kind = window;
+        self.window = window.samples(self.fft_size);
+        self
+    }
+
+    /// Overlaps consecutive segments by this fraction, e.g. 0.5 for half of
+    /// them.
+    ///
+    /// With a window, overlapping segments use samples that the window would
+    /// otherwise mostly discard, so the same number of samples gives a
+    /// smoother estimate.
+    pub fn with_overlap(mut self, overlap: f32) -> Self {
+        assert!(
+            (0.0..1.0).contains(&overlap),
+            "overlap must be in 0..1: {overlap}"
+        );
+        self.hop_size = (((1.0 - overlap) * self.fft_size as f32).round() as usize).max(1);
+        self
+    }
+
+    /// Averages the power of this many segments for each spectrum.
+    ///
+    /// This reduces the variance of the noise in the spectrum, but produces
+    /// fewer spectra.
+    pub fn with_averaging(mut self, num_averages: usize) -> Self {
+        assert!(num_averages > 0, "must average at least 1 segment");
+        self.num_averages = num_averages;
+        self
+    }
+
+    /// Uses an FFT plan from `pool`.
+    pub fn with_fft_pool(mut self, pool: &FftPool) -> Self {
+        self.fft = Box::new(pool.plan(self.fft_size));
+        self
+    }
+
+    #[inline]
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    #[inline]
+    pub fn window(&self) -> Window {
+        self.window_kind
+    }
+
+    /// Number of samples between the starts of consecutive segments.
+    #[inline]
+    pub fn hop_size(&self) -> usize {
+        self.hop_size
+    }
+
+    #[inline]
+    pub fn num_averages(&self) -> usize {
+        self.num_averages
+    }
+
+    /// Takes samples from the front of `samples` until a spectrum is
+    /// complete, and returns it. If `samples` runs out before that, this
+    /// returns `None`, and the next call continues where this one stopped.
+    pub fn push(&mut self, samples: &mut &[Complex<f32>]) -> Option<Spectrum> {
+        while !samples.is_empty() {
+            let num_samples = samples.len().min(self.fft_size - self.segment.len());
+            let (segment, rest) = samples.split_at(num_samples);
+            self.segment.extend_from_slice(segment);
+            *samples = rest;
+
+            if self.segment.len() < self.fft_size {
+                break;
+            }
+
+            for ((output, sample), window) in self
+                .transform
+                .iter_mut()
+                .zip(&self.segment)
+                .zip(&self.window)
+            {
+                *output = *sample * *window;
+            }
+            self.fft.process(&mut self.transform);
+            for (power, bin) in self.accumulator.iter_mut().zip(&self.transform) {
+                *power += bin.norm_sqr();
+            }
+            self.num_accumulated += 1;
+            self.segment.drain(..self.hop_size);
+
+            if self.num_accumulated == self.num_averages {
+                return Some(self.spectrum());
+            }
+        }
+
+        None
+    }
+
+    /// Computes the spectrum from the accumulated segments, and resets them.
+    fn spectrum(&mut self) -> Spectrum {
+        // a tone at the center of a bin has the sum of the window as amplitude
+        // in that bin
+        let gain = self.window.iter().sum::<f32>();
+        let normalization = 1.0 / (gain * gain * self.num_accumulated as f32);
+
+        // the FFT has DC in bin 0, and the negative frequencies in the upper
+        // half
+        let size = self.fft_size;
+        let power = (0..size)
+            .map(|bin| {
+                let power = self.accumulator[(bin + size - size / 2) % size];
+                10.0 * (power * normalization).max(1e-20).log10()
+            })
+            .collect();
+
+        let spectrum = Spectrum {
+            power,
+            sample_rate: self.sample_rate,
+            resolution_bandwidth: self.window_kind.equivalent_noise_bandwidth(size)
+                * self.sample_rate
+                / size as f32,
+            num_averaged: self.num_accumulated,
+        };
+
+        self.accumulator.fill(0.0);
+        self.num_accumulated = 0;
+
+        spectrum
+    }
+
+    /// Discards the current segment and the accumulated power.
+    pub fn reset(&mut self) {
+        self.segment.clear();
+        self.accumulator.fill(0.0);
+        self.num_accumulated = 0;
+    }
+}
+
+pin_project! {
+    /// Estimates the power spectrum of an IQ stream.
+    ///
+    /// This is a [`Stream`] of [`Spectrum`]s, which ends with the IQ stream.
+    /// See [`WelchEstimator`] for the defaults.
+    #[derive(Debug)]
+    pub struct SpectrumAnalyzer<R> {
+        #[pin]
+        input: R,
+        estimator: WelchEstimator,
+        buffer: Vec<Complex<f32>>,
+        position: usize,
+        length: usize,
+    }
+}
+
+impl<R> SpectrumAnalyzer<R> {
+    pub fn new(input: R, fft_size: usize) -> Self
+    where
+        R: GetSampleRate,
+    {
+        let estimator = WelchEstimator::new(input.sample_rate(), fft_size);
+        Self::with_estimator(input, estimator)
+    }
+
+    pub fn with_estimator(input: R, estimator: WelchEstimator) -> Self {
+        Self {
+            input,
+            estimator,
+            buffer: vec![Complex::default(); BUFFER_SIZE],
+            position: 0,
+            length: 0,
+        }
+    }
+
+    pub fn with_window(mut self, window: Window) -> Self {
+        self.estimator = self.estimator.with_window(window);
+        self
+    }
+
+    /// See [`WelchEstimator::with_overlap`].
+    pub fn with_overlap(mut self, overlap: f32) -> Self {
+        self.estimator = self.estimator.with_overlap(overlap);
+        self
+    }
+
+    /// See [`WelchEstimator::with_averaging`].
+    pub fn with_averaging(mut self, num_averages: usize) -> Self {
+        self.estimator = self.estimator.with_averaging(num_averages);
+        self
+    }
+
+    /// Uses an FFT plan from `pool`.
+    pub fn with_fft_pool(mut self, pool: &FftPool) -> Self {
+        self.estimator = self.estimator.with_fft_pool(pool);
+        self
+    }
+
+    #[inline]
+    pub fn estimator(&self) -> &WelchEstimator {
+        &self.estimator
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.input
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+impl<R> Stream for SpectrumAnalyzer<R>
+where
+    R: AsyncReadSamples<Complex<f32>>,
+{
+    type Item = Result<Spectrum, R::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if *this.position < *this.length {
+                let mut samples = &this.buffer[*this.position..*this.length];
+                let spectrum = this.estimator.push(&mut samples);
+                *this.position = *this.length - samples.len();
+                if let Some(spectrum) = spectrum {
+                    return Poll::Ready(Some(Ok(spectrum)));
+                }
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.buffer[..]);
+            ready!(this.input.as_mut().poll_read_samples(cx, &mut read_buf))?;
+            let num_read = read_buf.filled().len();
+            if num_read == 0 {
+                return Poll::Ready(None);
+            }
+            *this.position = 0;
+            *this.length = num_read;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        f32::consts::TAU,
+    };
+
+    use futures_util::{
+        FutureExt,
+        StreamExt,
+    };
+    use num_complex::Complex;
+    use rand::{
+        RngExt,
+        SeedableRng,
+        rngs::SmallRng,
+    };
+
+    use super::{
+        Spectrum,
+        SpectrumAnalyzer,
+        Window,
+    };
+    use crate::{
+        io::{
+            AsyncReadSamples,
+            AsyncReadSamplesExt,
+            Cursor,
+        },
+        source::Gaussian,
+    };
+
+    const SAMPLE_RATE: f32 = 6400.0;
+    const FFT_SIZE: usize = 64;
+
+    fn analyzer(
+        samples: &[Complex<f32>],
+    ) -> SpectrumAnalyzer<impl AsyncReadSamples<Complex<f32>, Error = Infallible> + Unpin + '_>
+    {
+        SpectrumAnalyzer::new(Cursor::new(samples).with_sample_rate(SAMPLE_RATE), FFT_SIZE)
+    }
+
+    fn collect<R>(analyzer: SpectrumAnalyzer<R>) -> Vec<Spectrum>
+    where
+        R: AsyncReadSamples<Complex<f32>, Error = Infallible> + Unpin,
+    {
+        analyzer
+            .map(|spectrum| spectrum.unwrap())
+            .collect::<Vec<_>>()
+            .now_or_never()
+            .expect("test stream pending")
+    }
+
+    fn tone(frequency: f32, num_samples: usize) -> Vec<Complex<f32>> {
+        (0..num_samples)
+            .map(|i| Complex::from_polar(1.0, TAU * frequency * i as f32 / SAMPLE_RATE))
+            .collect()
+    }
+
+    #[test]
+    fn full_scale_tones_are_at_0_dbfs() {
+        let samples = tone(-800.0, FFT_SIZE);
+
+        for window in [
+            Window::Rectangular,
+            Window::Hann,
+            Window::BlackmanHarris,
+            Window::FlatTop,
+        ] {
+            let spectra = collect(analyzer(&samples).with_window(window));
+            assert_eq!(spectra.len(), 1);
+
+            let (frequency, power) = spectra[0].peak().unwrap();
+            assert_eq!(frequency, -800.0);
+            assert_eq!(spectra[0].bin(frequency), Some(FFT_SIZE / 2 - 8));
+            assert!(power.abs() < 0.01, "{window:?}: {power} dBFS");
+
+            let total = spectra[0].band_power(0..FFT_SIZE);
+            assert!(total.abs() < 0.01, "{window:?}: {total} dBFS");
+        }
+    }
+
+    #[test]
+    fn flat_top_measures_tones_between_bins() {
+        // half way between 2 bins
+        let samples = tone(850.0, FFT_SIZE);
+
+        let spectra = collect(analyzer(&samples).with_window(Window::FlatTop));
+        let (_, power) = spectra[0].peak().unwrap();
+        assert!(power.abs() < 0.05, "{power} dBFS");
+
+        let spectra = collect(analyzer(&samples).with_window(Window::Hann));
+        let (_, power) = spectra[0].peak().unwrap();
+        assert!((power + 1.42).abs() < 0.05, "{power} dBFS");
+    }
+
+    #[test]
+    fn it_overlaps_and_averages() {
+        let samples = tone(100.0, 10 * FFT_SIZE);
+        let spectra = collect(analyzer(&samples).with_overlap(0.75).with_averaging(4));
+
+        // 37 segments, 16 samples apart
+        assert_eq!(spectra.len(), 9);
+        assert!(spectra.iter().all(|spectrum| spectrum.num_averaged == 4));
+    }
+
+    #[test]
+    fn it_measures_noise_in_the_resolution_bandwidth() {
+        let noise = Gaussian::new(0.01);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let samples = (0..100 * FFT_SIZE)
+            .map(|_| rng.sample::<Complex<f32>, _>(noise))
+            .collect::<Vec<_>>();
+
+        for window in [Window::Hann, Window::BlackmanHarris, Window::FlatTop] {
+            let spectra = collect(
+                analyzer(&samples)
+                    .with_window(window)
+                    .with_overlap(0.5)
+                    .with_averaging(16),
+            );
+
+            // noise in a bin is the noise density times the resolution
+            // bandwidth
+            let expected = 0.01 * spectra[0].resolution_bandwidth / SAMPLE_RATE;
+            let mean = spectra
+                .iter()
+                .flat_map(|spectrum| &spectrum.power)
+                .map(|power| 10f32.powf(power / 10.0))
+                .sum::<f32>()
+                / (spectra.len() * FFT_SIZE) as f32;
+            assert!(
+                (mean / expected - 1.0).abs() < 0.05,
+                "{window:?}: {mean} != {expected}"
+            );
+        }
+
+        assert!((Window::Hann.equivalent_noise_bandwidth(FFT_SIZE) - 1.5).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,388 @@
+//! Carrier recovery
+//!
+//! [`Pll`] locks to a carrier, e.g. of an AM signal, and [`CostasLoop`] locks
+//! to the suppressed carrier of a BPSK or QPSK signal. Both rotate their input
+//! onto the recovered carrier, and output it with the estimated frequency
+//! offset.
+
+use std::f32::consts::{
+    PI,
+    SQRT_2,
+    TAU,
+};
+
+use num_complex::Complex;
+
+use crate::{
+    io::combinators::{
+        GroupDelay,
+        Scanner,
+    },
+    sync::{
+        DEFAULT_DAMPING,
+        LoopFilter,
+    },
+};
+
+/// Loop state shared by [`Pll`] and [`CostasLoop`].
+#[derive(Clone, Copy, Debug)]
+struct CarrierLoop {
+    sample_rate: f32,
+    loop_bandwidth: f32,
+    loop_filter: LoopFilter,
+    /// Phase of the carrier in radians.
+    phase: f32,
+    /// Frequency the loop starts with, in radians per sample.
+    initial_frequency: f32,
+    /// The signal level, which normalizes the phase error.
+    level_alpha: f32,
+    level: Option<f32>,
+}
+
+impl CarrierLoop {
+    fn new(sample_rate: f32, loop_bandwidth: f32) -> Self {
+        Self {
+            sample_rate,
+            loop_bandwidth,
+            loop_filter: LoopFilter::new(loop_bandwidth / sample_rate, DEFAULT_DAMPING),
+            phase: 0.0,
+            initial_frequency: 0.0,
+            level_alpha: (loop_bandwidth / sample_rate).min(1.0),
+            level: None,
+        }
+    }
+
+    fn set_damping(&mut self, damping: f32) {
+        self.loop_filter = LoopFilter::new(self.loop_bandwidth / self.sample_rate, damping);
+    }
+
+    fn set_initial_frequency(&mut self, frequency: f32) {
+        self.initial_frequency = TAU * frequency / self.sample_rate;
+    }
+
+    /// Frequency in radians per sample.
+    #[inline]
+    fn frequency(&self) -> f32 {
+        self.initial_frequency + self.loop_filter.integrator()
+    }
+
+    #[inline]
+    fn frequency_offset(&self) -> f32 {
+        self.frequency() * self.sample_rate / TAU
+    }
+
+    #[inline]
+    fn rotate(&self, sample: Complex<f32>) -> Complex<f32> {
+        sample * Complex::from_polar(1.0, -self.phase)
+    }
+
+    /// Updates the measured level, and returns it.
+    fn update_level(&mut self, level: f32) -> f32 {
+        let average = self.level.get_or_insert(level);
+        *average += self.level_alpha * (level - *average);
+        *average
+    }
+
+    fn advance(&mut self, error: f32) {
+        self.phase += self.initial_frequency + self.loop_filter.filter(error);
+        if self.phase > PI {
+            self.phase -= TAU;
+        }
+        else if self.phase < -PI {
+            self.phase += TAU;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.loop_filter.reset();
+        self.phase = 0.0;
+        self.level = None;
+    }
+}
+
+/// Phase-locked loop that locks to a carrier.
+///
+/// Outputs the input rotated onto the carrier, which is then on the real axis,
+/// and the carrier's frequency in Hz.
+#[derive(Clone, Copy, Debug)]
+pub struct Pll {
+    inner: CarrierLoop,
+}
+
+impl Pll {
+    /// The loop bandwidth is in Hz. A wider loop locks faster and follows
+    /// drifting carriers, but lets through more noise.
+    pub fn new(sample_rate: f32, loop_bandwidth: f32) -> Self {
+        Self {
+            inner: CarrierLoop::new(sample_rate, loop_bandwidth),
+        }
+    }
+
+    pub fn with_damping(mut self, damping: f32) -> Self {
+        self.inner.set_damping(damping);
+        self
+    }
+
+    /// The frequency in Hz that the loop starts from, e.g. if the carrier is
+    /// known to be off.
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.inner.set_initial_frequency(frequency);
+        self
+    }
+
+    /// Frequency of the carrier the loop is locked to, in Hz.
+    #[inline]
+    pub fn frequency_offset(&self) -> f32 {
+        self.inner.frequency_offset()
+    }
+
+    /// Phase of the carrier in radians.
+    #[inline]
+    pub fn phase(&self) -> f32 {
+        self.inner.phase
+    }
+}
+
+impl Scanner<Complex<f32>> for Pll {
+    type Output = (Complex<f32>, f32);
+
+    fn scan(&mut self, sample: Complex<f32>) -> Self::Output {
+        let rotated = self.inner.rotate(sample);
+
+        // the quadrature component is the phase error, relative to the
+        // carrier's magnitude
+        let level = self.inner.update_level(sample.norm());
+        let error = if level > f32::EPSILON {
+            (rotated.im / level).clamp(-1.0, 1.0)
+        }
+        else {
+            0.0
+        };
+        self.inner.advance(error);
+
+        (rotated, self.inner.frequency_offset())
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.inner.reset();
+        None
+    }
+}
+
+impl GroupDelay for Pll {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Modulation that a [`CostasLoop`] locks to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CostasMode {
+    /// Symbols on the real axis. The loop can lock to either of them.
+    #[default]
+    Bpsk,
+
+    /// Symbols in the middle of the quadrants. The loop can lock to any of
+    /// the 4 rotations.
+    Qpsk,
+}
+
+/// Costas loop that recovers the suppressed carrier of a PSK signal.
+///
+/// The input should be filtered to the signal's bandwidth. Outputs the input
+/// rotated onto the carrier, i.e. with the symbols on the real axis for BPSK,
+/// or in the middle of the quadrants for QPSK, and the carrier's frequency in
+/// Hz.
+#[derive(Clone, Copy, Debug)]
+pub struct CostasLoop {
+    mode: CostasMode,
+    inner: CarrierLoop,
+}
+
+impl CostasLoop {
+    /// The loop bandwidth is in Hz. A wider loop locks faster and follows
+    /// larger offsets, but lets through more noise.
+    pub fn new(mode: CostasMode, sample_rate: f32, loop_bandwidth: f32) -> Self {
+        Self {
+            mode,
+            inner: CarrierLoop::new(sample_rate, loop_bandwidth),
+        }
+    }
+
+    #[inline]
+    pub fn bpsk(sample_rate: f32, loop_bandwidth: f32) -> Self {
+        Self::new(CostasMode::Bpsk, sample_rate, loop_bandwidth)
+    }
+
+    #[inline]
+    pub fn qpsk(sample_rate: f32, loop_bandwidth: f32) -> Self {
+        Self::new(CostasMode::Qpsk, sample_rate, loop_bandwidth)
+    }
+
+    pub fn with_damping(mut self, damping: f32) -> Self {
+        self.inner.set_damping(damping);
+        self
+    }
+
+    /// The frequency in Hz that the loop starts from, e.g. if the carrier is
+    /// known to be off.
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.inner.set_initial_frequency(frequency);
+        self
+    }
+
+    #[inline]
+    pub fn mode(&self) -> CostasMode {
+        self.mode
+    }
+
+    /// Frequency of the carrier the loop is locked to, in Hz.
+    #[inline]
+    pub fn frequency_offset(&self) -> f32 {
+        self.inner.frequency_offset()
+    }
+
+    /// Phase of the carrier in radians.
+    #[inline]
+    pub fn phase(&self) -> f32 {
+        self.inner.phase
+    }
+}
+
+impl Scanner<Complex<f32>> for CostasLoop {
+    type Output = (Complex<f32>, f32);
+
+    fn scan(&mut self, sample: Complex<f32>) -> Self::Output {
+        let rotated = self.inner.rotate(sample);
+
+        // both errors don't depend on the symbol, and are about the phase
+        // error for small errors.
+        let error = match self.mode {
+            CostasMode::Bpsk => {
+                let power = self.inner.update_level(sample.norm_sqr());
+                if power > f32::EPSILON {
+                    rotated.re * rotated.im / power
+                }
+                else {
+                    0.0
+                }
+            }
+            CostasMode::Qpsk => {
+                let level = self.inner.update_level(sample.norm());
+                if level > f32::EPSILON {
+                    (rotated.re.signum() * rotated.im - rotated.im.signum() * rotated.re)
+                        / (SQRT_2 * level)
+                }
+                else {
+                    0.0
+                }
+            }
+        };
+        self.inner.advance(error.clamp(-1.0, 1.0));
+
+        (rotated, self.inner.frequency_offset())
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.inner.reset();
+        None
+    }
+}
+
+impl GroupDelay for CostasLoop {
+    #[inline]
+    fn group_delay(&self) -> f64 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use num_complex::Complex;
+
+    use super::{
+        CostasLoop,
+        Pll,
+    };
+    use crate::io::combinators::Scanner;
+
+    const SAMPLE_RATE: f32 = 8000.0;
+    const NUM_SAMPLES: usize = 16000;
+
+    /// A signal at `frequency` Hz with some noise.
+    fn signal(
+        frequency: f32,
+        phase: f32,
+        symbol: impl Fn(u32) -> Complex<f32>,
+    ) -> impl Iterator<Item = Complex<f32>> {
+        let mut state = 0x1234_5678u32;
+        let mut random = move || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            state >> 8
+        };
+
+        let mut current = Complex::new(1.0, 0.0);
+        (0..NUM_SAMPLES).map(move |i| {
+            // a new symbol every 8 samples
+            if i % 8 == 0 {
+                current = symbol(random());
+            }
+            let noise = Complex::new(random() as f32, random() as f32) / (1 << 24) as f32
+                - Complex::new(0.5, 0.5);
+            current * Complex::from_polar(1.0, TAU * frequency * i as f32 / SAMPLE_RATE + phase)
+                + 0.2 * noise
+        })
+    }
+
+    fn sign(bit: bool) -> f32 {
+        if bit { 1.0 } else { -1.0 }
+    }
+
+    #[test]
+    fn pll_locks_to_a_carrier() {
+        let mut pll = Pll::new(SAMPLE_RATE, 50.0);
+        let output = signal(30.0, 1.0, |_| Complex::new(0.5, 0.0))
+            .map(|sample| pll.scan(sample))
+            .collect::<Vec<_>>();
+
+        let (_, frequency) = output[NUM_SAMPLES - 1];
+        assert!((frequency - 30.0).abs() < 0.5, "frequency: {frequency}");
+
+        let average = output[NUM_SAMPLES - 2000..]
+            .iter()
+            .map(|(sample, _)| sample)
+            .sum::<Complex<f32>>();
+        assert!(average.arg().abs() < 0.05, "phase: {}", average.arg());
+    }
+
+    #[test]
+    fn costas_loop_locks_to_bpsk() {
+        let mut costas_loop = CostasLoop::bpsk(SAMPLE_RATE, 50.0);
+        let output = signal(20.0, 2.0, |bits| Complex::new(sign(bits & 1 != 0), 0.0))
+            .map(|sample| costas_loop.scan(sample))
+            .collect::<Vec<_>>();
+
+        assert!((costas_loop.frequency_offset() - 20.0).abs() < 0.5);
+        for (sample, _) in &output[NUM_SAMPLES - 2000..] {
+            assert!(sample.re.abs() > 0.5 && sample.im.abs() < 0.4, "{sample}");
+        }
+    }
+
+    #[test]
+    fn costas_loop_locks_to_qpsk() {
+        let mut costas_loop = CostasLoop::qpsk(SAMPLE_RATE, 50.0);
+        let output = signal(-20.0, 0.3, |bits| {
+            Complex::new(sign(bits & 1 != 0), sign(bits & 2 != 0))
+        })
+        .map(|sample| costas_loop.scan(sample))
+        .collect::<Vec<_>>();
+
+        assert!((costas_loop.frequency_offset() + 20.0).abs() < 0.5);
+        for (sample, _) in &output[NUM_SAMPLES - 2000..] {
+            assert!(sample.re.abs() > 0.5 && sample.im.abs() > 0.5, "{sample}");
+        }
+    }
+}
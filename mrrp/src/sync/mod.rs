@@ -0,0 +1,66 @@
+//! Synchronization
+//!
+//! Loops that recover the carrier and the symbol timing of a signal from the
+//! signal itself, for demodulators of digital modes.
+
+mod carrier;
+mod timing;
+
+pub use carrier::{
+    CostasLoop,
+    CostasMode,
+    Pll,
+};
+pub use timing::{
+    SymbolSync,
+    TimingDetector,
+    TimingSample,
+};
+
+/// Default damping of the loops, which makes them critically damped.
+pub const DEFAULT_DAMPING: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Proportional-integral loop filter of a second-order loop.
+///
+/// The loop's phase is corrected by the output, and its frequency by the
+/// integrator.
+#[derive(Clone, Copy, Debug)]
+pub struct LoopFilter {
+    /// Proportional gain.
+    alpha: f32,
+    /// Integral gain.
+    beta: f32,
+    integrator: f32,
+}
+
+impl LoopFilter {
+    /// `bandwidth` is the loop's noise bandwidth relative to the rate at which
+    /// it's updated, and the error is expected to have a gain of 1.
+    pub fn new(bandwidth: f32, damping: f32) -> Self {
+        let theta = bandwidth / (damping + 0.25 / damping);
+        let denominator = 1.0 + 2.0 * damping * theta + theta * theta;
+        Self {
+            alpha: 4.0 * damping * theta / denominator,
+            beta: 4.0 * theta * theta / denominator,
+            integrator: 0.0,
+        }
+    }
+
+    /// Filters an error, and returns the correction.
+    #[inline]
+    pub fn filter(&mut self, error: f32) -> f32 {
+        self.integrator += self.beta * error;
+        self.alpha * error + self.integrator
+    }
+
+    /// The integrated error, i.e. the frequency correction.
+    #[inline]
+    pub fn integrator(&self) -> f32 {
+        self.integrator
+    }
+
+    #[inline]
+    pub fn reset(&mut self) {
+        self.integrator = 0.0;
+    }
+}
@@ -0,0 +1,359 @@
+//! Symbol timing recovery
+//!
+//! A timing error detector measures from the interpolated samples whether the
+//! symbols are sampled too early or too late, and a loop adjusts the time of
+//! the next sample. A [`FarrowDelay`] interpolates between the input samples.
+//!
+//! # References
+//!
+//! - F. M. Gardner, "A BPSK/QPSK timing-error detector for sampled receivers",
+//!   1986
+//! - K. H. Mueller, M. Müller, "Timing recovery in digital synchronous data
+//!   receivers", 1976
+
+use std::ops::{
+    Add,
+    Mul,
+    Sub,
+};
+
+use num_complex::Complex;
+use num_traits::Zero;
+
+use crate::{
+    filter::farrow::{
+        FarrowDelay,
+        Interpolation,
+    },
+    io::combinators::Scanner,
+    sync::{
+        DEFAULT_DAMPING,
+        LoopFilter,
+    },
+};
+
+/// Default loop bandwidth, relative to the symbol rate.
+pub const DEFAULT_LOOP_BANDWIDTH: f32 = 0.01;
+
+/// Default limit of the timing correction, relative to the symbol period.
+pub const DEFAULT_MAX_DEVIATION: f32 = 0.1;
+
+/// Approximate slope of both detectors in amplitude per symbol of timing error,
+/// for raised-cosine pulses with an amplitude of 1.
+const DETECTOR_GAIN: f32 = 2.0;
+
+/// Samples whose timing can be recovered, i.e. real or complex samples.
+pub trait TimingSample:
+    Copy + Zero + Add<Self, Output = Self> + Sub<Self, Output = Self> + Mul<f32, Output = Self>
+{
+    /// Gardner error `Re{(previous - current) conj(middle)}`, where `middle`
+    /// is the sample halfway between the symbols.
+    fn gardner_error(previous: Self, middle: Self, current: Self) -> f32;
+
+    /// Mueller & Müller error `Re{conj(d(previous)) current - conj(d(current))
+    /// previous}`, where `d` is the sign of each component.
+    fn mueller_muller_error(previous: Self, current: Self) -> f32;
+}
+
+impl TimingSample for f32 {
+    #[inline]
+    fn gardner_error(previous: Self, middle: Self, current: Self) -> f32 {
+        (previous - current) * middle
+    }
+
+    #[inline]
+    fn mueller_muller_error(previous: Self, current: Self) -> f32 {
+        previous.signum() * current - current.signum() * previous
+    }
+}
+
+impl TimingSample for Complex<f32> {
+    #[inline]
+    fn gardner_error(previous: Self, middle: Self, current: Self) -> f32 {
+        ((previous - current) * middle.conj()).re
+    }
+
+    #[inline]
+    fn mueller_muller_error(previous: Self, current: Self) -> f32 {
+        f32::mueller_muller_error(previous.re, current.re)
+            + f32::mueller_muller_error(previous.im, current.im)
+    }
+}
+
+/// Timing error detector of a [`SymbolSync`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TimingDetector {
+    /// Uses a sample between the symbols, and doesn't depend on the carrier
+    /// phase. Needs at least 2 samples per symbol.
+    #[default]
+    Gardner,
+
+    /// Decision directed, using only the symbols. Needs the carrier to be
+    /// recovered.
+    MuellerMuller,
+}
+
+/// Recovers the symbol timing, and outputs one sample per symbol.
+///
+/// The input should be matched filtered, with symbols of an amplitude of about
+/// 1, e.g. after an AGC.
+#[derive(Clone, Copy, Debug)]
+pub struct SymbolSync<S> {
+    detector: TimingDetector,
+    samples_per_symbol: f32,
+    max_deviation: f32,
+    loop_filter: LoopFilter,
+    interpolator: FarrowDelay<S>,
+
+    /// Input samples until the next interpolated sample.
+    countdown: f32,
+    /// Length of the current symbol in input samples.
+    interval: f32,
+    /// Whether the next interpolated sample is between two symbols.
+    next_is_middle: bool,
+    middle: S,
+    previous: S,
+}
+
+impl<S> SymbolSync<S>
+where
+    S: TimingSample,
+{
+    pub fn new(detector: TimingDetector, samples_per_symbol: f32) -> Self {
+        let min_samples_per_symbol = match detector {
+            TimingDetector::Gardner => 2.0,
+            TimingDetector::MuellerMuller => 1.0,
+        };
+        assert!(
+            samples_per_symbol >= min_samples_per_symbol,
+            "too few samples per symbol for {detector:?}: {samples_per_symbol}"
+        );
+
+        let mut symbol_sync = Self {
+            detector,
+            samples_per_symbol,
+            max_deviation: DEFAULT_MAX_DEVIATION,
+            loop_filter: LoopFilter::new(DEFAULT_LOOP_BANDWIDTH, DEFAULT_DAMPING),
+            interpolator: FarrowDelay::new(Interpolation::Cubic, 0.0),
+            countdown: 0.0,
+            interval: 0.0,
+            next_is_middle: false,
+            middle: S::zero(),
+            previous: S::zero(),
+        };
+        symbol_sync.reset();
+        symbol_sync
+    }
+
+    #[inline]
+    pub fn gardner(samples_per_symbol: f32) -> Self {
+        Self::new(TimingDetector::Gardner, samples_per_symbol)
+    }
+
+    #[inline]
+    pub fn mueller_muller(samples_per_symbol: f32) -> Self {
+        Self::new(TimingDetector::MuellerMuller, samples_per_symbol)
+    }
+
+    /// The loop bandwidth relative to the symbol rate, and the loop's
+    /// damping. A wider loop locks faster, but jitters more.
+    pub fn with_loop_bandwidth(mut self, loop_bandwidth: f32, damping: f32) -> Self {
+        self.loop_filter = LoopFilter::new(loop_bandwidth, damping);
+        self
+    }
+
+    /// Limits the correction of the symbol period, relative to the nominal
+    /// period. This keeps the loop from running away in noise.
+    pub fn with_max_deviation(mut self, max_deviation: f32) -> Self {
+        assert!(
+            (0.0..0.5).contains(&max_deviation),
+            "invalid maximum deviation: {max_deviation}"
+        );
+        self.max_deviation = max_deviation;
+        self
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolator = FarrowDelay::new(interpolation, 0.0);
+        self
+    }
+
+    fn reset(&mut self) {
+        self.loop_filter.reset();
+        self.interval = self.samples_per_symbol;
+        self.countdown = self.step();
+        self.next_is_middle = false;
+        self.middle = S::zero();
+        self.previous = S::zero();
+    }
+}
+
+impl<S> SymbolSync<S> {
+    #[inline]
+    pub fn detector(&self) -> TimingDetector {
+        self.detector
+    }
+
+    /// The estimated symbol period in input samples.
+    #[inline]
+    pub fn samples_per_symbol(&self) -> f32 {
+        self.samples_per_symbol * (1.0 + self.loop_filter.integrator())
+    }
+
+    /// Input samples to the next interpolated sample.
+    fn step(&self) -> f32 {
+        match self.detector {
+            TimingDetector::Gardner => 0.5 * self.interval,
+            TimingDetector::MuellerMuller => self.interval,
+        }
+    }
+}
+
+impl<S> Scanner<S> for SymbolSync<S>
+where
+    S: TimingSample,
+{
+    type Output = Option<S>;
+
+    fn scan(&mut self, sample: S) -> Self::Output {
+        // the interpolator delays by a sample, so that the interpolated sample
+        // is between the last two input samples.
+        self.countdown -= 1.0;
+        self.interpolator
+            .set_delay((-self.countdown).clamp(0.0, 1.0));
+        let interpolated = self.interpolator.scan(sample);
+        if self.countdown > 0.0 {
+            return None;
+        }
+
+        if self.next_is_middle {
+            self.middle = interpolated;
+            self.next_is_middle = false;
+            self.countdown += self.step();
+            return None;
+        }
+
+        let error = match self.detector {
+            TimingDetector::Gardner => S::gardner_error(self.previous, self.middle, interpolated),
+            TimingDetector::MuellerMuller => S::mueller_muller_error(self.previous, interpolated),
+        };
+
+        // a negative error means the symbol was sampled too late, so the
+        // next one is sampled earlier
+        let correction = self
+            .loop_filter
+            .filter(error / DETECTOR_GAIN)
+            .clamp(-self.max_deviation, self.max_deviation);
+        self.interval = self.samples_per_symbol * (1.0 + correction);
+        self.previous = interpolated;
+        self.next_is_middle = self.detector == TimingDetector::Gardner;
+        self.countdown += self.step();
+
+        Some(interpolated)
+    }
+
+    fn finish(&mut self) -> Option<Self::Output> {
+        self.reset();
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use num_complex::Complex;
+
+    use super::{
+        SymbolSync,
+        TimingSample,
+    };
+    use crate::io::combinators::Scanner;
+
+    const NUM_SYMBOLS: usize = 2000;
+
+    /// Raised-cosine pulse with a roll-off of 0.35, `t` in symbols.
+    fn raised_cosine(t: f32) -> f32 {
+        const BETA: f32 = 0.35;
+        if t.abs() < 1e-6 {
+            return 1.0;
+        }
+        let sinc = (PI * t).sin() / (PI * t);
+        let denominator = 1.0 - (2.0 * BETA * t).powi(2);
+        if denominator.abs() < 1e-4 {
+            PI / 4.0 * sinc
+        }
+        else {
+            sinc * (PI * BETA * t).cos() / denominator
+        }
+    }
+
+    /// Pulse shapes random symbols, with a clock that is off and an unknown
+    /// offset.
+    fn transmit<S: TimingSample>(symbol: impl Fn(u32) -> S, samples_per_symbol: f32) -> Vec<S> {
+        let mut state = 0x1234_5678u32;
+        let symbols = (0..NUM_SYMBOLS)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                symbol(state >> 16)
+            })
+            .collect::<Vec<_>>();
+
+        let num_samples = (NUM_SYMBOLS as f32 * samples_per_symbol) as usize;
+        (0..num_samples)
+            .map(|i| {
+                let t = (i as f32 - 3.3) / samples_per_symbol;
+                let k = t as usize;
+                (k.saturating_sub(8)..(k + 9).min(NUM_SYMBOLS)).fold(S::zero(), |sum, k| {
+                    sum + symbols[k] * raised_cosine(t - k as f32)
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gardner_locks_to_bpsk() {
+        let bit = |bits: u32| if bits & 1 != 0 { 1.0 } else { -1.0 };
+
+        for samples_per_symbol in [8.0, 8.04, 7.95] {
+            let mut symbol_sync = SymbolSync::gardner(8.0);
+            let symbols = transmit(bit, samples_per_symbol)
+                .into_iter()
+                .filter_map(|sample| symbol_sync.scan(sample))
+                .collect::<Vec<f32>>();
+
+            // a few symbols are skipped or repeated while the loop acquires the
+            // clock offset
+            assert!(
+                symbols.len().abs_diff(NUM_SYMBOLS) <= 4,
+                "{samples_per_symbol}: {} symbols",
+                symbols.len()
+            );
+            assert!((symbol_sync.samples_per_symbol() - samples_per_symbol).abs() < 0.01);
+            let worst = symbols[NUM_SYMBOLS - 500..]
+                .iter()
+                .fold(f32::INFINITY, |worst, symbol| worst.min(symbol.abs()));
+            assert!(worst > 0.9, "{samples_per_symbol}: {worst}");
+        }
+    }
+
+    #[test]
+    fn mueller_muller_locks_to_qpsk() {
+        let component = |bit: u32| if bit != 0 { 1.0 } else { -1.0 };
+        let symbol = |bits: u32| Complex::new(component(bits & 1), component(bits & 2));
+
+        let mut symbol_sync = SymbolSync::mueller_muller(4.0);
+        let symbols = transmit(symbol, 4.02)
+            .into_iter()
+            .filter_map(|sample| symbol_sync.scan(sample))
+            .collect::<Vec<_>>();
+
+        assert!((symbol_sync.samples_per_symbol() - 4.02).abs() < 0.01);
+        let worst = symbols[NUM_SYMBOLS - 500..]
+            .iter()
+            .fold(f32::INFINITY, |worst, symbol| {
+                worst.min(symbol.re.abs()).min(symbol.im.abs())
+            });
+        assert!(worst > 0.9, "{worst}");
+    }
+}
@@ -0,0 +1,199 @@
+//! Clocks for time-dependent combinators.
+//!
+//! Combinators like [`Throttled`][crate::io::combinators::Throttled] need to
+//! sleep. By default they use the tokio timer ([`TokioClock`]), but for tests
+//! a [`MockClock`] can be used, which only advances when told so.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+        Waker,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use parking_lot::Mutex;
+
+pub trait Clock {
+    type Sleep: Future<Output = ()> + Debug;
+
+    fn now(&self) -> Instant;
+
+    fn sleep_until(&self, deadline: Instant) -> Self::Sleep;
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        self.sleep_until(self.now() + duration)
+    }
+}
+
+/// The real clock, backed by the tokio timer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    type Sleep = tokio::time::Sleep;
+
+    #[inline]
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    #[inline]
+    fn sleep_until(&self, deadline: Instant) -> Self::Sleep {
+        tokio::time::sleep_until(deadline.into())
+    }
+
+    #[inline]
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        tokio::time::sleep(duration)
+    }
+}
+
+/// A clock that only advances manually.
+///
+/// Cloning the clock gives a handle to the same clock. Sleeps are woken when
+/// the clock is [advanced][Self::advance] past their deadline.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    state: Arc<Mutex<MockClockState>>,
+}
+
+#[derive(Debug)]
+struct MockClockState {
+    now: Instant,
+    sleeping: Vec<(Instant, Waker)>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockClockState {
+                now: Instant::now(),
+                sleeping: vec![],
+            })),
+        }
+    }
+
+    /// Advances the clock and wakes all sleeps whose deadline has passed.
+    pub fn advance(&self, duration: Duration) {
+        let wakers = {
+            let mut state = self.state.lock();
+            state.now += duration;
+            let now = state.now;
+
+            let mut wakers = vec![];
+            state.sleeping.retain(|(deadline, waker)| {
+                if *deadline <= now {
+                    wakers.push(waker.clone());
+                    false
+                }
+                else {
+                    true
+                }
+            });
+            wakers
+        };
+
+        // wake outside of the lock, in case a waker polls the sleep right away
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Number of sleeps that are currently waiting to be woken.
+    pub fn num_sleeping(&self) -> usize {
+        self.state.lock().sleeping.len()
+    }
+}
+
+impl Clock for MockClock {
+    type Sleep = MockSleep;
+
+    fn now(&self) -> Instant {
+        self.state.lock().now
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Self::Sleep {
+        MockSleep {
+            clock: self.clone(),
+            deadline,
+        }
+    }
+}
+
+/// Sleep future returned by [`MockClock`].
+#[derive(Debug)]
+pub struct MockSleep {
+    clock: MockClock,
+    deadline: Instant,
+}
+
+impl MockSleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl Future for MockSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.clock.state.lock();
+
+        if state.now >= self.deadline {
+            Poll::Ready(())
+        }
+        else {
+            let deadline = self.deadline;
+            if !state
+                .sleeping
+                .iter()
+                .any(|(other, waker)| *other == deadline && waker.will_wake(cx.waker()))
+            {
+                state.sleeping.push((deadline, cx.waker().clone()));
+            }
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::FutureExt;
+
+    use super::{
+        Clock,
+        MockClock,
+    };
+
+    #[test]
+    fn mock_sleep_completes_after_advance() {
+        let clock = MockClock::new();
+        let mut sleep = clock.sleep(Duration::from_millis(10));
+
+        assert!((&mut sleep).now_or_never().is_none());
+        assert_eq!(clock.num_sleeping(), 1);
+
+        clock.advance(Duration::from_millis(9));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_millis(1));
+        assert!((&mut sleep).now_or_never().is_some());
+    }
+}
@@ -4,7 +4,14 @@ use std::ops::{
 };
 
 //pub mod array_vecdeque;
+pub mod clock;
 pub mod dim;
+pub mod raw_iq;
+pub mod riff;
+#[cfg(feature = "sigmf")]
+pub mod sigmf;
+pub mod watchdog;
+pub mod wav;
 
 #[inline(always)]
 pub fn lerp(t: f32, a: f32, b: f32) -> f32 {
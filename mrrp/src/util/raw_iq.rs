@@ -0,0 +1,178 @@
+//! Sample formats of headerless IQ recordings.
+//!
+//! These files only contain interleaved I and Q samples, so the format, sample
+//! rate and center frequency have to be known from elsewhere. The format is
+//! usually in the file extension, e.g. `cu8` for `rtl_sdr` captures or `cf32`
+//! for gqrx recordings.
+
+use std::{
+    fmt::Display,
+    path::Path,
+    str::FromStr,
+};
+
+use num_complex::Complex;
+
+/// Encoding of the I and Q samples in a raw IQ file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RawIqFormat {
+    /// Unsigned 8 bit integers with an offset of 127.5, as written by
+    /// `rtl_sdr`.
+    Cu8,
+    /// Signed 8 bit integers, as written by `hackrf_transfer`.
+    Cs8,
+    /// Signed 16 bit little-endian integers.
+    Cs16,
+    /// 32 bit little-endian floats.
+    Cf32,
+}
+
+impl RawIqFormat {
+    /// Size of a complex sample in bytes.
+    #[inline]
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            Self::Cu8 | Self::Cs8 => 2,
+            Self::Cs16 => 4,
+            Self::Cf32 => 8,
+        }
+    }
+
+    /// Guesses the format from the extension of `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        path.as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| extension.to_ascii_lowercase().parse().ok())
+    }
+
+    /// The usual file extension.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Cu8 => "cu8",
+            Self::Cs8 => "cs8",
+            Self::Cs16 => "cs16",
+            Self::Cf32 => "cf32",
+        }
+    }
+
+    /// Decodes a sample from `bytes_per_sample` bytes. Integers are scaled to
+    /// `[-1, 1)`.
+    #[inline]
+    pub fn decode(&self, bytes: &[u8]) -> Complex<f32> {
+        match self {
+            Self::Cu8 => {
+                Complex::new(
+                    (f32::from(bytes[0]) - 127.5) / 128.0,
+                    (f32::from(bytes[1]) - 127.5) / 128.0,
+                )
+            }
+            Self::Cs8 => {
+                Complex::new(
+                    f32::from(bytes[0] as i8) / 128.0,
+                    f32::from(bytes[1] as i8) / 128.0,
+                )
+            }
+            Self::Cs16 => {
+                Complex::new(
+                    f32::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32768.0,
+                    f32::from(i16::from_le_bytes([bytes[2], bytes[3]])) / 32768.0,
+                )
+            }
+            Self::Cf32 => {
+                Complex::new(
+                    f32::from_le_bytes(bytes[..4].try_into().unwrap()),
+                    f32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+                )
+            }
+        }
+    }
+
+    /// Encodes a sample and appends it to `output`. Integer formats clip
+    /// samples outside of `[-1, 1)`.
+    #[inline]
+    pub fn encode(&self, sample: Complex<f32>, output: &mut Vec<u8>) {
+        match self {
+            Self::Cu8 => {
+                for x in [sample.re, sample.im] {
+                    output.push((x * 128.0 + 127.5).round().clamp(0.0, 255.0) as u8);
+                }
+            }
+            Self::Cs8 => {
+                for x in [sample.re, sample.im] {
+                    output.push((x * 128.0).round().clamp(-128.0, 127.0) as i8 as u8);
+                }
+            }
+            Self::Cs16 => {
+                for x in [sample.re, sample.im] {
+                    let x = (x * 32768.0).round().clamp(-32768.0, 32767.0) as i16;
+                    output.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+            Self::Cf32 => {
+                output.extend_from_slice(&sample.re.to_le_bytes());
+                output.extend_from_slice(&sample.im.to_le_bytes());
+            }
+        }
+    }
+}
+
+impl Display for RawIqFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown raw IQ format: {0}")]
+pub struct UnknownRawIqFormat(pub String);
+
+impl FromStr for RawIqFormat {
+    type Err = UnknownRawIqFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cu8" => Ok(Self::Cu8),
+            "cs8" => Ok(Self::Cs8),
+            "cs16" => Ok(Self::Cs16),
+            "cf32" | "cfile" | "raw" => Ok(Self::Cf32),
+            _ => Err(UnknownRawIqFormat(s.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex;
+
+    use super::RawIqFormat;
+
+    #[test]
+    fn it_round_trips_samples() {
+        let sample = Complex::new(0.5, -0.25);
+        for format in [
+            RawIqFormat::Cu8,
+            RawIqFormat::Cs8,
+            RawIqFormat::Cs16,
+            RawIqFormat::Cf32,
+        ] {
+            let mut bytes = vec![];
+            format.encode(sample, &mut bytes);
+            assert_eq!(bytes.len(), format.bytes_per_sample());
+            let decoded = format.decode(&bytes);
+            assert!((decoded - sample).norm() < 1e-2, "{format}: {decoded}");
+        }
+    }
+
+    #[test]
+    fn it_decodes_rtl_sdr_samples() {
+        // rtl_sdr has no zero, the closest values are 127 and 128
+        let sample = RawIqFormat::Cu8.decode(&[0, 255]);
+        assert_eq!(sample, Complex::new(-127.5 / 128.0, 127.5 / 128.0));
+        assert_eq!(
+            RawIqFormat::from_path("/tmp/capture.CU8"),
+            Some(RawIqFormat::Cu8)
+        );
+        assert_eq!(RawIqFormat::from_path("/tmp/capture.wav"), None);
+    }
+}
@@ -0,0 +1,558 @@
+//! Metadata chunks in RIFF/WAVE files.
+//!
+//! [`wav`][super::wav] only handles the format and data chunks, so this reads
+//! and writes the chunks that SDR software uses to tag recordings:
+//!
+//! - `auxi`, written by SDR#, HDSDR and SDRuno. It holds the center frequency
+//!   and the start and stop time of the recording.
+//! - `LIST`/`INFO`, the generic RIFF tags. We use `ICRD` (creation date),
+//!   `ISFT` (software) and `ICMT` (comment).
+//! - `bext` (Broadcast Wave), of which only the origination time is read.
+//!
+//! All other chunks except the format and data chunks are kept as they are,
+//! so they survive reading a file and writing it back.
+//!
+//! Times are written as UTC.
+
+use std::{
+    io::{
+        self,
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    time::{
+        Duration,
+        SystemTime,
+    },
+};
+
+use crate::frequency::Frequency;
+
+/// Chunks larger than this are skipped instead of being kept in memory.
+const MAX_KEPT_CHUNK_SIZE: u32 = 0x10_0000;
+
+const AUXI_SIZE: usize = 164;
+const BEXT_ORIGINATION_DATE_OFFSET: usize = 320;
+
+/// Metadata of a WAV recording.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WavMetadata {
+    /// Frequency the receiver was tuned to.
+    pub center_frequency: Option<Frequency>,
+
+    /// Time the first sample was captured.
+    pub capture_time: Option<SystemTime>,
+
+    pub software: Option<String>,
+
+    pub comment: Option<String>,
+
+    /// Other `INFO` tags, e.g. `IART` (artist).
+    pub info: Vec<([u8; 4], String)>,
+
+    /// Other chunks, e.g. `bext`.
+    pub chunks: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl WavMetadata {
+    /// Metadata for a recording that starts now.
+    pub fn for_capture(center_frequency: Frequency) -> Self {
+        Self {
+            center_frequency: Some(center_frequency),
+            capture_time: Some(SystemTime::now()),
+            software: Some(concat!("mrrp ", env!("CARGO_PKG_VERSION")).to_owned()),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Reads the metadata chunks of a RIFF/WAVE file.
+    ///
+    /// This starts at the beginning of `reader`, and leaves it at an
+    /// unspecified position.
+    pub fn read_from<R>(reader: &mut R) -> Result<Self, io::Error>
+    where
+        R: Read + Seek,
+    {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a RIFF/WAVE file",
+            ));
+        }
+        let riff_end = 8 + u64::from(u32_at(&header, 4));
+
+        let mut metadata = Self::default();
+        let mut info_time = None;
+        let mut bext_time = None;
+        let mut position = 12;
+
+        while position + 8 <= riff_end {
+            let mut chunk_header = [0; 8];
+            match reader.read_exact(&mut chunk_header) {
+                Ok(()) => {}
+                // recordings that were not finalized have wrong sizes
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error),
+            }
+            let id: [u8; 4] = chunk_header[0..4].try_into().unwrap();
+            let size = u32_at(&chunk_header, 4);
+
+            match &id {
+                b"fmt " | b"fact" | b"data" | b"JUNK" | b"PAD " => {}
+                _ if size > MAX_KEPT_CHUNK_SIZE => {
+                    tracing::debug!(id = %String::from_utf8_lossy(&id), size, "skipping large chunk");
+                }
+                _ => {
+                    let mut data = vec![0; size as usize];
+                    match reader.read_exact(&mut data) {
+                        Ok(()) => {}
+                        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                        Err(error) => return Err(error),
+                    }
+
+                    match &id {
+                        b"LIST" if data.starts_with(b"INFO") => {
+                            info_time = metadata.parse_info(&data[4..]);
+                        }
+                        b"auxi" => metadata.parse_auxi(&data),
+                        b"bext" => {
+                            bext_time = parse_bext_time(&data);
+                            metadata.chunks.push((id, data));
+                        }
+                        _ => metadata.chunks.push((id, data)),
+                    }
+                }
+            }
+
+            position += 8 + u64::from(size) + u64::from(size & 1);
+            reader.seek(SeekFrom::Start(position))?;
+        }
+
+        metadata.capture_time = metadata.capture_time.or(info_time).or(bext_time);
+        Ok(metadata)
+    }
+
+    /// Appends the metadata chunks to a finished RIFF/WAVE file and updates
+    /// the RIFF header.
+    ///
+    /// `sample_rate` and `duration` are written to the `auxi` chunk.
+    pub fn append_to<W>(
+        &self,
+        writer: &mut W,
+        sample_rate: u32,
+        duration: Duration,
+    ) -> Result<(), io::Error>
+    where
+        W: Write + Seek,
+    {
+        let end = writer.seek(SeekFrom::End(0))?;
+        if end & 1 == 1 {
+            // chunks are word aligned
+            writer.write_all(&[0])?;
+        }
+
+        if let Some(info) = self.info_chunk() {
+            write_chunk(writer, b"LIST", &info)?;
+        }
+        if self.center_frequency.is_some() || self.capture_time.is_some() {
+            write_chunk(writer, b"auxi", &self.auxi_chunk(sample_rate, duration))?;
+        }
+        for (id, data) in &self.chunks {
+            write_chunk(writer, id, data)?;
+        }
+
+        let end = writer.stream_position()?;
+        let riff_size = u32::try_from(end - 8)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "RIFF file too large"))?;
+        writer.seek(SeekFrom::Start(4))?;
+        writer.write_all(&riff_size.to_le_bytes())?;
+        writer.seek(SeekFrom::Start(end))?;
+
+        Ok(())
+    }
+
+    /// Parses the entries of a `LIST`/`INFO` chunk and returns the creation
+    /// date.
+    fn parse_info(&mut self, mut data: &[u8]) -> Option<SystemTime> {
+        let mut creation_time = None;
+
+        while data.len() >= 8 {
+            let id: [u8; 4] = data[0..4].try_into().unwrap();
+            let size = u32_at(data, 4) as usize;
+            let Some(value) = data.get(8..8 + size)
+            else {
+                break;
+            };
+            let value = zstr(value);
+
+            match &id {
+                b"ICRD" => creation_time = parse_date_time(&value),
+                b"ISFT" => self.software = Some(value),
+                b"ICMT" => self.comment = Some(value),
+                _ => self.info.push((id, value)),
+            }
+
+            data = data.get(8 + size + (size & 1)..).unwrap_or_default();
+        }
+
+        creation_time
+    }
+
+    fn info_chunk(&self) -> Option<Vec<u8>> {
+        let creation_time = self
+            .capture_time
+            .map(|capture_time| DateTime::from_system_time(capture_time).to_iso8601());
+
+        let entries = creation_time
+            .as_ref()
+            .map(|value| (b"ICRD", value))
+            .into_iter()
+            .chain(self.software.as_ref().map(|value| (b"ISFT", value)))
+            .chain(self.comment.as_ref().map(|value| (b"ICMT", value)))
+            .chain(self.info.iter().map(|(id, value)| (id, value)))
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return None;
+        }
+
+        let mut data = b"INFO".to_vec();
+        for (id, value) in entries {
+            let mut value = value.as_bytes().to_vec();
+            value.push(0);
+            write_chunk(&mut data, id, &value).unwrap();
+        }
+        Some(data)
+    }
+
+    fn parse_auxi(&mut self, data: &[u8]) {
+        if data.len() < 36 {
+            tracing::debug!(len = data.len(), "auxi chunk too short");
+            return;
+        }
+
+        self.capture_time =
+            DateTime::from_systemtime(&data[0..16]).and_then(|start| start.to_system_time());
+        let center_frequency = u32_at(data, 32);
+        if center_frequency != 0 {
+            self.center_frequency = Some(Frequency::from(center_frequency));
+        }
+    }
+
+    fn auxi_chunk(&self, sample_rate: u32, duration: Duration) -> Vec<u8> {
+        let mut data = Vec::with_capacity(AUXI_SIZE);
+
+        match self.capture_time {
+            Some(start) => {
+                data.extend_from_slice(&DateTime::from_system_time(start).to_systemtime());
+                data.extend_from_slice(
+                    &DateTime::from_system_time(start + duration).to_systemtime(),
+                );
+            }
+            None => data.resize(32, 0),
+        }
+
+        let center_frequency = self
+            .center_frequency
+            .map_or(0, |frequency| u32::try_from(frequency).unwrap_or(u32::MAX));
+        data.extend_from_slice(&center_frequency.to_le_bytes());
+        data.extend_from_slice(&sample_rate.to_le_bytes());
+
+        // IF frequency, bandwidth, IQ offset, unused fields and the name of
+        // the next file
+        data.resize(AUXI_SIZE, 0);
+        data
+    }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, id: &[u8; 4], data: &[u8]) -> Result<(), io::Error> {
+    let size = u32::try_from(data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "RIFF chunk too large"))?;
+    writer.write_all(id)?;
+    writer.write_all(&size.to_le_bytes())?;
+    writer.write_all(data)?;
+    if size & 1 == 1 {
+        writer.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+#[inline]
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..][..4].try_into().unwrap())
+}
+
+#[inline]
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..][..2].try_into().unwrap())
+}
+
+/// Text that is padded with NULs.
+fn zstr(data: &[u8]) -> String {
+    let end = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim().to_owned()
+}
+
+/// Parses the origination date (`yyyy-mm-dd`) and time (`hh:mm:ss`) of a
+/// `bext` chunk.
+fn parse_bext_time(data: &[u8]) -> Option<SystemTime> {
+    let date = data.get(BEXT_ORIGINATION_DATE_OFFSET..BEXT_ORIGINATION_DATE_OFFSET + 18)?;
+    let date = std::str::from_utf8(date).ok()?;
+    parse_date_time(&format!("{}T{}", date.get(..10)?, date.get(10..)?))
+}
+
+/// Parses `yyyy-mm-dd`, optionally followed by `Thh:mm:ss` or ` hh:mm:ss`.
+///
+/// Some software uses other separators in the date, so any single character
+/// is accepted between the numbers.
+pub(crate) fn parse_date_time(text: &str) -> Option<SystemTime> {
+    let number = |range: std::ops::Range<usize>| text.get(range)?.parse::<u16>().ok();
+
+    let mut date_time = DateTime {
+        year: number(0..4)?,
+        month: number(5..7)?,
+        day: number(8..10)?,
+        ..Default::default()
+    };
+    if text.len() >= 19 {
+        date_time.hour = number(11..13)?;
+        date_time.minute = number(14..16)?;
+        date_time.second = number(17..19)?;
+    }
+
+    date_time.to_system_time()
+}
+
+/// Formats a time as `yyyy-mm-ddThh:mm:ssZ`.
+pub(crate) fn format_date_time(time: SystemTime) -> String {
+    DateTime::from_system_time(time).to_iso8601()
+}
+
+/// Broken down UTC time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct DateTime {
+    year: u16,
+    month: u16,
+    day: u16,
+    hour: u16,
+    minute: u16,
+    second: u16,
+    millisecond: u16,
+}
+
+impl DateTime {
+    fn from_system_time(time: SystemTime) -> Self {
+        // recordings from before 1970 are not a thing
+        let since_epoch = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let seconds = since_epoch.as_secs();
+        let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+        let seconds_of_day = seconds % 86400;
+
+        Self {
+            year: year as u16,
+            month: month as u16,
+            day: day as u16,
+            hour: (seconds_of_day / 3600) as u16,
+            minute: (seconds_of_day / 60 % 60) as u16,
+            second: (seconds_of_day % 60) as u16,
+            millisecond: since_epoch.subsec_millis() as u16,
+        }
+    }
+
+    fn to_system_time(self) -> Option<SystemTime> {
+        if !(1..=12).contains(&self.month)
+            || !(1..=31).contains(&self.day)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 60
+        {
+            return None;
+        }
+
+        let days = days_from_civil(self.year.into(), self.month.into(), self.day.into());
+        let seconds = days * 86400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second);
+        let seconds = u64::try_from(seconds).ok()?;
+
+        Some(
+            SystemTime::UNIX_EPOCH
+                + Duration::from_secs(seconds)
+                + Duration::from_millis(self.millisecond.min(999).into()),
+        )
+    }
+
+    /// Parses a Windows `SYSTEMTIME`.
+    fn from_systemtime(data: &[u8]) -> Option<Self> {
+        let year = u16_at(data, 0);
+        if year == 0 {
+            return None;
+        }
+
+        // skip the day of the week at offset 4
+        Some(Self {
+            year,
+            month: u16_at(data, 2),
+            day: u16_at(data, 6),
+            hour: u16_at(data, 8),
+            minute: u16_at(data, 10),
+            second: u16_at(data, 12),
+            millisecond: u16_at(data, 14),
+        })
+    }
+
+    /// Formats as a Windows `SYSTEMTIME`.
+    fn to_systemtime(self) -> [u8; 16] {
+        let days = days_from_civil(self.year.into(), self.month.into(), self.day.into());
+        // 1970-01-01 was a Thursday, and Sunday is 0
+        let day_of_week = (days + 4).rem_euclid(7) as u16;
+
+        let fields = [
+            self.year,
+            self.month,
+            day_of_week,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.millisecond,
+        ];
+        let mut data = [0; 16];
+        for (bytes, field) in data.chunks_exact_mut(2).zip(fields) {
+            bytes.copy_from_slice(&field.to_le_bytes());
+        }
+        data
+    }
+
+    fn to_iso8601(self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+// date conversions from http://howardhinnant.github.io/date_algorithms.html
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    }
+    else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        time::{
+            Duration,
+            SystemTime,
+        },
+    };
+
+    use super::{
+        DateTime,
+        WavMetadata,
+        parse_date_time,
+    };
+    use crate::frequency::Frequency;
+
+    #[test]
+    fn it_converts_dates() {
+        // 2024-02-29T13:14:15.5Z
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1709212455500);
+        let date_time = DateTime::from_system_time(time);
+        assert_eq!(date_time.to_iso8601(), "2024-02-29T13:14:15Z");
+        assert_eq!(date_time.to_system_time(), Some(time));
+
+        // a Thursday
+        assert_eq!(date_time.to_systemtime()[4..6], [4, 0]);
+        assert_eq!(
+            DateTime::from_systemtime(&date_time.to_systemtime()),
+            Some(date_time)
+        );
+
+        assert_eq!(
+            parse_date_time("2024-02-29"),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1709164800))
+        );
+        assert_eq!(parse_date_time("2024-13-01"), None);
+    }
+
+    #[test]
+    fn it_round_trips_metadata() {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 8,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut file = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut file, spec).unwrap();
+        // an odd number of bytes, so the chunks after the data need padding
+        for sample in [1i8, -1, 2] {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let metadata = WavMetadata {
+            center_frequency: Some(Frequency::from_khz(145_500)),
+            capture_time: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            software: Some("test".to_owned()),
+            comment: None,
+            info: vec![(*b"IART", "someone".to_owned())],
+            chunks: vec![(*b"test", vec![1, 2, 3])],
+        };
+        metadata
+            .append_to(&mut file, spec.sample_rate, Duration::from_secs(1))
+            .unwrap();
+
+        assert_eq!(WavMetadata::read_from(&mut file).unwrap(), metadata);
+
+        // hound still reads the samples
+        file.set_position(0);
+        let mut reader = hound::WavReader::new(file).unwrap();
+        let samples = reader
+            .samples::<i8>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(samples, [1, -1, 2]);
+    }
+}
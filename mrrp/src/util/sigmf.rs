@@ -0,0 +1,391 @@
+//! Metadata of [SigMF](https://sigmf.org) recordings.
+//!
+//! A recording is a pair of files: `name.sigmf-data` with the raw samples, and
+//! `name.sigmf-meta` with JSON metadata. The metadata has a global object with
+//! the datatype and sample rate, a list of captures, which tell the center
+//! frequency from a sample onwards, and a list of annotations of sample
+//! ranges.
+//!
+//! Only the `core` namespace is parsed. Fields of other namespaces are kept, so
+//! they're written back unchanged.
+
+use std::{
+    ffi::OsString,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::SystemTime,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::{
+    Map,
+    Value,
+};
+
+use crate::{
+    error::{
+        ClassifyError,
+        ErrorKind,
+    },
+    util::{
+        raw_iq::RawIqFormat,
+        riff,
+    },
+};
+
+/// Version of the specification that is written.
+pub const SIGMF_VERSION: &str = "1.0.0";
+
+pub const META_EXTENSION: &str = "sigmf-meta";
+pub const DATA_EXTENSION: &str = "sigmf-data";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("io error")]
+    Io(#[from] io::Error),
+
+    #[error("invalid SigMF metadata")]
+    Json(#[from] serde_json::Error),
+
+    #[error("unsupported SigMF datatype: {0}")]
+    UnsupportedDatatype(String),
+
+    #[error("unsupported number of channels: {0}")]
+    UnsupportedChannelCount(u32),
+
+    #[error("SigMF metadata has no sample rate")]
+    MissingSampleRate,
+}
+
+impl ClassifyError for Error {
+    fn error_kind(&self) -> ErrorKind {
+        match self {
+            Error::Io(error) => error.error_kind(),
+            _ => ErrorKind::Decode,
+        }
+    }
+}
+
+/// Contents of a `.sigmf-meta` file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SigmfMetadata {
+    pub global: Global,
+
+    #[serde(default)]
+    pub captures: Vec<Capture>,
+
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+impl SigmfMetadata {
+    pub fn new(format: RawIqFormat, sample_rate: f64) -> Self {
+        Self {
+            global: Global {
+                datatype: datatype(format).to_owned(),
+                sample_rate: Some(sample_rate),
+                version: SIGMF_VERSION.to_owned(),
+                ..Default::default()
+            },
+            captures: vec![],
+            annotations: vec![],
+        }
+    }
+
+    /// Metadata for a recording that starts now, at the given center
+    /// frequency.
+    pub fn for_capture(format: RawIqFormat, sample_rate: f64, center_frequency: f64) -> Self {
+        let mut metadata = Self::new(format, sample_rate);
+        metadata.global.recorder = Some(concat!("mrrp ", env!("CARGO_PKG_VERSION")).to_owned());
+        metadata.captures.push(Capture {
+            frequency: Some(center_frequency),
+            datetime: Some(riff::format_date_time(SystemTime::now())),
+            ..Default::default()
+        });
+        metadata
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, Error> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn read_from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_json(&std::fs::read(path)?)
+    }
+
+    /// Format of the samples in the data file.
+    ///
+    /// Only single-channel complex datatypes are supported, and multi-byte
+    /// samples must be little-endian.
+    pub fn raw_iq_format(&self) -> Result<RawIqFormat, Error> {
+        if let Some(num_channels) = self.global.num_channels
+            && num_channels != 1
+        {
+            return Err(Error::UnsupportedChannelCount(num_channels));
+        }
+
+        match self.global.datatype.as_str() {
+            "cu8" => Ok(RawIqFormat::Cu8),
+            "ci8" => Ok(RawIqFormat::Cs8),
+            "ci16_le" => Ok(RawIqFormat::Cs16),
+            "cf32_le" => Ok(RawIqFormat::Cf32),
+            datatype => Err(Error::UnsupportedDatatype(datatype.to_owned())),
+        }
+    }
+
+    pub fn sample_rate(&self) -> Result<f64, Error> {
+        self.global.sample_rate.ok_or(Error::MissingSampleRate)
+    }
+
+    /// The capture that the sample with the given index belongs to.
+    pub fn capture_at(&self, sample_index: u64) -> Option<&Capture> {
+        self.captures
+            .iter()
+            .take_while(|capture| capture.sample_start <= sample_index)
+            .last()
+    }
+}
+
+/// The `global` object.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Global {
+    /// Format of the samples, e.g. `cf32_le`.
+    #[serde(rename = "core:datatype")]
+    pub datatype: String,
+
+    #[serde(
+        rename = "core:sample_rate",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sample_rate: Option<f64>,
+
+    #[serde(rename = "core:version")]
+    pub version: String,
+
+    #[serde(
+        rename = "core:num_channels",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub num_channels: Option<u32>,
+
+    #[serde(
+        rename = "core:description",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<String>,
+
+    #[serde(
+        rename = "core:author",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub author: Option<String>,
+
+    /// Software that made the recording.
+    #[serde(
+        rename = "core:recorder",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recorder: Option<String>,
+
+    /// Description of the hardware that made the recording.
+    #[serde(rename = "core:hw", default, skip_serializing_if = "Option::is_none")]
+    pub hardware: Option<String>,
+
+    /// Fields that aren't parsed.
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// A segment of the recording with the same parameters.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Capture {
+    /// Index of the first sample of this capture.
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+
+    /// Center frequency in Hz.
+    #[serde(
+        rename = "core:frequency",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub frequency: Option<f64>,
+
+    /// Time of the first sample, in ISO 8601 format.
+    #[serde(
+        rename = "core:datetime",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub datetime: Option<String>,
+
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl Capture {
+    /// Parses the time of the first sample. Fractions of seconds are ignored.
+    pub fn time(&self) -> Option<SystemTime> {
+        riff::parse_date_time(self.datetime.as_deref()?)
+    }
+}
+
+/// Describes a range of samples, e.g. a detected signal.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+
+    /// Number of samples, or until the end of the recording if not set.
+    #[serde(
+        rename = "core:sample_count",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sample_count: Option<u64>,
+
+    /// Lowest frequency of the annotated signal in Hz.
+    #[serde(
+        rename = "core:freq_lower_edge",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub freq_lower_edge: Option<f64>,
+
+    /// Highest frequency of the annotated signal in Hz.
+    #[serde(
+        rename = "core:freq_upper_edge",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub freq_upper_edge: Option<f64>,
+
+    /// Short label, e.g. the decoded callsign.
+    #[serde(
+        rename = "core:label",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub label: Option<String>,
+
+    #[serde(
+        rename = "core:comment",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub comment: Option<String>,
+
+    /// Software that made the annotation.
+    #[serde(
+        rename = "core:generator",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub generator: Option<String>,
+
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Datatype of a format in SigMF notation.
+pub fn datatype(format: RawIqFormat) -> &'static str {
+    match format {
+        RawIqFormat::Cu8 => "cu8",
+        RawIqFormat::Cs8 => "ci8",
+        RawIqFormat::Cs16 => "ci16_le",
+        RawIqFormat::Cf32 => "cf32_le",
+    }
+}
+
+/// Paths of the metadata and data files of a recording.
+///
+/// `path` can be either of the files, or the name without an extension.
+pub fn paths(path: impl AsRef<Path>) -> (PathBuf, PathBuf) {
+    let path = path.as_ref();
+    let base = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(META_EXTENSION | DATA_EXTENSION | "sigmf") => path.with_extension(""),
+        _ => path.to_owned(),
+    };
+
+    let with_extension = |extension: &str| {
+        let mut path = OsString::from(base.clone());
+        path.push(".");
+        path.push(extension);
+        PathBuf::from(path)
+    };
+    (
+        with_extension(META_EXTENSION),
+        with_extension(DATA_EXTENSION),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{
+        SigmfMetadata,
+        paths,
+    };
+    use crate::util::raw_iq::RawIqFormat;
+
+    #[test]
+    fn it_parses_metadata() {
+        let json = br#"{
+            "global": {
+                "core:datatype": "ci16_le",
+                "core:sample_rate": 2400000,
+                "core:version": "1.0.0",
+                "core:hw": "RTL-SDR",
+                "antenna:type": "dipole"
+            },
+            "captures": [
+                {"core:sample_start": 0, "core:frequency": 144800000, "core:datetime": "2024-05-01T12:30:00.5Z"},
+                {"core:sample_start": 1000, "core:frequency": 145000000}
+            ],
+            "annotations": [
+                {"core:sample_start": 200, "core:sample_count": 300, "core:label": "APRS"}
+            ]
+        }"#;
+
+        let metadata = SigmfMetadata::from_json(json).unwrap();
+        assert_eq!(metadata.raw_iq_format().unwrap(), RawIqFormat::Cs16);
+        assert_eq!(metadata.sample_rate().unwrap(), 2.4e6);
+        assert_eq!(metadata.global.hardware.as_deref(), Some("RTL-SDR"));
+        assert_eq!(metadata.global.other["antenna:type"], "dipole");
+        assert!(metadata.captures[0].time().is_some());
+        assert_eq!(metadata.capture_at(999).unwrap().frequency, Some(144.8e6));
+        assert_eq!(metadata.capture_at(1000).unwrap().frequency, Some(145e6));
+        assert_eq!(metadata.annotations[0].label.as_deref(), Some("APRS"));
+
+        // unknown fields are written back
+        let round_tripped = SigmfMetadata::from_json(&metadata.to_json().unwrap()).unwrap();
+        assert_eq!(round_tripped, metadata);
+    }
+
+    #[test]
+    fn it_finds_both_files() {
+        let expected = (
+            PathBuf::from("/tmp/rec.2024.sigmf-meta"),
+            PathBuf::from("/tmp/rec.2024.sigmf-data"),
+        );
+        assert_eq!(paths("/tmp/rec.2024"), expected);
+        assert_eq!(paths("/tmp/rec.2024.sigmf-meta"), expected);
+        assert_eq!(paths("/tmp/rec.2024.sigmf-data"), expected);
+    }
+}
@@ -0,0 +1,316 @@
+//! Health checks for long-running pipelines.
+//!
+//! A [`Watchdog`] is checked periodically with the counters of a pipeline. It
+//! measures the memory and CPU time of the process, and fails if the memory
+//! keeps growing, which is usually a leak or a buffer growing without bound,
+//! or if the pipeline drops too many samples.
+//!
+//! Memory and CPU time are read from `/proc/self` and are only available on
+//! Linux.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Memory grew in each of the last {num_checks} checks, by {growth} bytes in total")]
+    MemoryGrowth { num_checks: usize, growth: u64 },
+
+    #[error("Dropped {num_dropped} of {num_samples} samples")]
+    TooManyDrops { num_dropped: u64, num_samples: u64 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WatchdogConfig {
+    /// The memory is considered to grow monotonically if it grew in this
+    /// many consecutive checks.
+    pub memory_growth_checks: usize,
+
+    /// Growth over these checks below this many bytes is ignored. Allocators
+    /// and caches grow a little for a while.
+    pub memory_growth_tolerance: u64,
+
+    /// Largest share of samples that may be dropped, from 0 to 1.
+    pub max_drop_ratio: f64,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            memory_growth_checks: 10,
+            memory_growth_tolerance: 1 << 20,
+            max_drop_ratio: 1e-3,
+        }
+    }
+}
+
+/// Counters a pipeline reports to the [`Watchdog`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PipelineCounters {
+    /// How full the pipeline's buffer is, from 0 to 1.
+    pub buffer_fill: f32,
+
+    /// Samples processed since the start.
+    pub num_processed: u64,
+
+    /// Samples dropped since the start, e.g. because the buffer was full.
+    pub num_dropped: u64,
+}
+
+/// The state of the process and pipeline at a check.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchdogReport {
+    /// Time since the watchdog was created.
+    pub elapsed: Duration,
+
+    /// Resident set size in bytes.
+    pub rss: Option<u64>,
+
+    /// CPU time used since the last check, relative to the time passed. This
+    /// is above 1 if the process uses more than one core.
+    pub cpu_load: Option<f64>,
+
+    pub counters: PipelineCounters,
+}
+
+impl Display for WatchdogReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>8.0} s", self.elapsed.as_secs_f64())?;
+        if let Some(rss) = self.rss {
+            write!(f, "  RSS {:>7.1} MiB", rss as f64 / f64::from(1 << 20))?;
+        }
+        if let Some(cpu_load) = self.cpu_load {
+            write!(f, "  CPU {:>5.1}%", cpu_load * 100.0)?;
+        }
+        write!(
+            f,
+            "  buffer {:>3.0}%  processed {}  dropped {}",
+            self.counters.buffer_fill * 100.0,
+            self.counters.num_processed,
+            self.counters.num_dropped
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Watchdog {
+    config: WatchdogConfig,
+    start: Instant,
+    last_check: Instant,
+    last_cpu_time: Option<Duration>,
+    rss_history: VecDeque<u64>,
+}
+
+impl Watchdog {
+    pub fn new(config: WatchdogConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            start: now,
+            last_check: now,
+            last_cpu_time: cpu_time(),
+            rss_history: VecDeque::with_capacity(config.memory_growth_checks + 1),
+        }
+    }
+
+    /// Measures the process and checks it and the pipeline's counters.
+    ///
+    /// Call this periodically, e.g. every few seconds. The report is returned
+    /// even if a check failed, so it can be logged.
+    pub fn check(&mut self, counters: PipelineCounters) -> (WatchdogReport, Result<(), Error>) {
+        let now = Instant::now();
+        let cpu_time = cpu_time();
+        let cpu_load = self.last_cpu_time.zip(cpu_time).map(|(last, current)| {
+            current.saturating_sub(last).as_secs_f64()
+                / (now - self.last_check).as_secs_f64().max(1e-9)
+        });
+        self.last_check = now;
+        self.last_cpu_time = cpu_time;
+
+        let report = WatchdogReport {
+            elapsed: now - self.start,
+            rss: rss(),
+            cpu_load,
+            counters,
+        };
+        let result = self.check_report(&report);
+        (report, result)
+    }
+
+    fn check_report(&mut self, report: &WatchdogReport) -> Result<(), Error> {
+        if let Some(rss) = report.rss {
+            self.rss_history.push_back(rss);
+            if self.rss_history.len() > self.config.memory_growth_checks + 1 {
+                self.rss_history.pop_front();
+            }
+            self.check_memory()?;
+        }
+
+        let num_samples = report.counters.num_processed + report.counters.num_dropped;
+        if num_samples > 0
+            && report.counters.num_dropped as f64 > self.config.max_drop_ratio * num_samples as f64
+        {
+            return Err(Error::TooManyDrops {
+                num_dropped: report.counters.num_dropped,
+                num_samples,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn check_memory(&self) -> Result<(), Error> {
+        if self.config.memory_growth_checks == 0
+            || self.rss_history.len() <= self.config.memory_growth_checks
+        {
+            return Ok(());
+        }
+
+        let always_grew = self
+            .rss_history
+            .iter()
+            .zip(self.rss_history.iter().skip(1))
+            .all(|(before, after)| after > before);
+        if !always_grew {
+            return Ok(());
+        }
+
+        // the history grew in every step, so the last entry is the largest
+        let growth = self.rss_history.back().unwrap() - self.rss_history.front().unwrap();
+        if growth > self.config.memory_growth_tolerance {
+            Err(Error::MemoryGrowth {
+                num_checks: self.config.memory_growth_checks,
+                growth,
+            })
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+/// Resident set size of this process in bytes.
+fn rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// User and system CPU time of this process.
+fn cpu_time() -> Option<Duration> {
+    // the kernel reports these in USER_HZ, which is 100 on all architectures
+    const TICKS_PER_SECOND: u64 = 100;
+
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // the process name can contain spaces, so the fields are counted from the
+    // parenthesis that closes it. utime and stime are fields 14 and 15.
+    let mut fields = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .skip(11);
+    let user = fields.next()?.parse::<u64>().ok()?;
+    let system = fields.next()?.parse::<u64>().ok()?;
+    Some(Duration::from_millis(
+        (user + system) * 1000 / TICKS_PER_SECOND,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Error,
+        PipelineCounters,
+        Watchdog,
+        WatchdogConfig,
+        WatchdogReport,
+    };
+
+    fn report(rss: u64, num_processed: u64, num_dropped: u64) -> WatchdogReport {
+        WatchdogReport {
+            elapsed: Default::default(),
+            rss: Some(rss),
+            cpu_load: None,
+            counters: PipelineCounters {
+                buffer_fill: 0.5,
+                num_processed,
+                num_dropped,
+            },
+        }
+    }
+
+    #[test]
+    fn it_fails_on_monotonic_memory_growth() {
+        let mut watchdog = Watchdog::new(WatchdogConfig {
+            memory_growth_checks: 3,
+            memory_growth_tolerance: 100,
+            max_drop_ratio: 0.1,
+        });
+
+        // growing, but then shrinking again
+        for rss in [1000, 1100, 1200, 1100, 1200, 1300] {
+            watchdog.check_report(&report(rss, 0, 0)).unwrap();
+        }
+        assert!(matches!(
+            watchdog.check_report(&report(1400, 0, 0)),
+            Err(Error::MemoryGrowth { growth: 300, .. })
+        ));
+    }
+
+    #[test]
+    fn it_accepts_shrinking_memory() {
+        let mut watchdog = Watchdog::new(WatchdogConfig {
+            memory_growth_checks: 3,
+            memory_growth_tolerance: 100,
+            max_drop_ratio: 0.1,
+        });
+
+        for rss in [1300, 1200, 1100, 1000, 900] {
+            watchdog.check_report(&report(rss, 0, 0)).unwrap();
+        }
+    }
+
+    #[test]
+    fn it_fails_on_drops() {
+        let mut watchdog = Watchdog::new(WatchdogConfig {
+            max_drop_ratio: 0.1,
+            ..Default::default()
+        });
+        watchdog.check_report(&report(1000, 900, 100)).unwrap();
+        assert!(matches!(
+            watchdog.check_report(&report(1000, 900, 101)),
+            Err(Error::TooManyDrops {
+                num_dropped: 101,
+                num_samples: 1001
+            })
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn it_measures_the_process() {
+        let mut watchdog = Watchdog::default();
+        let (report, result) = watchdog.check(PipelineCounters::default());
+        result.unwrap();
+        assert!(report.rss.unwrap() > 0);
+        assert!(report.cpu_load.is_some());
+    }
+}
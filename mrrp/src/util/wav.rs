@@ -0,0 +1,502 @@
+//! Format and data chunks of WAV files.
+//!
+//! This reads and writes integer PCM with 8, 16, 24 or 32 bits, and IEEE
+//! floats with 32 or 64 bits, in plain and `WAVE_FORMAT_EXTENSIBLE` format
+//! chunks. The other chunks are handled by
+//! [`WavMetadata`][super::riff::WavMetadata].
+//!
+//! All samples of a frame are interleaved. IQ recordings have 2 channels, I
+//! and Q.
+
+use std::io::{
+    self,
+    Read,
+    Seek,
+    SeekFrom,
+    Write,
+};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Size that is written for the data chunk of files that are still being
+/// written, or that are streamed.
+const UNKNOWN_DATA_SIZE: u32 = u32::MAX;
+
+/// Offset of the data chunk's size in the header that [`write_header`]
+/// writes.
+const DATA_SIZE_OFFSET: u64 = 40;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("io error")]
+    Io(#[from] io::Error),
+
+    #[error("invalid WAV file: {0}")]
+    Invalid(&'static str),
+
+    #[error("unsupported WAV format {format_tag:#06x} with {bits_per_sample} bits per sample")]
+    Unsupported {
+        format_tag: u16,
+        bits_per_sample: u16,
+    },
+}
+
+/// Encoding of the samples in a WAV file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WavSampleFormat {
+    /// 8 bit integers, which are unsigned in WAV files.
+    U8,
+    I16,
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl WavSampleFormat {
+    fn from_format_tag(format_tag: u16, bits_per_sample: u16) -> Option<Self> {
+        match (format_tag, bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => Some(Self::U8),
+            (WAVE_FORMAT_PCM, 16) => Some(Self::I16),
+            (WAVE_FORMAT_PCM, 24) => Some(Self::I24),
+            (WAVE_FORMAT_PCM, 32) => Some(Self::I32),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Some(Self::F32),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => Some(Self::F64),
+            _ => None,
+        }
+    }
+
+    fn format_tag(&self) -> u16 {
+        if self.is_float() {
+            WAVE_FORMAT_IEEE_FLOAT
+        }
+        else {
+            WAVE_FORMAT_PCM
+        }
+    }
+
+    #[inline]
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::F32 | Self::F64)
+    }
+
+    #[inline]
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::I16 => 2,
+            Self::I24 => 3,
+            Self::I32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    #[inline]
+    pub fn bits_per_sample(&self) -> u16 {
+        8 * self.bytes_per_sample() as u16
+    }
+
+    /// Decodes an integer sample as it is stored. 8 bit samples are shifted to
+    /// be signed. Floats are truncated.
+    #[inline]
+    pub fn decode_int(&self, bytes: &[u8]) -> i32 {
+        match self {
+            Self::U8 => i32::from(bytes[0]) - 128,
+            Self::I16 => i32::from(i16::from_le_bytes([bytes[0], bytes[1]])),
+            // sign-extended by the shift
+            Self::I24 => i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8,
+            Self::I32 => i32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            Self::F32 | Self::F64 => self.decode_float(bytes) as i32,
+        }
+    }
+
+    /// Decodes a sample, with integers scaled to `[-1, 1)`.
+    #[inline]
+    pub fn decode_float(&self, bytes: &[u8]) -> f64 {
+        match self {
+            Self::F32 => f64::from(f32::from_le_bytes(bytes[..4].try_into().unwrap())),
+            Self::F64 => f64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            _ => f64::from(self.decode_int(bytes)) / self.int_scale(),
+        }
+    }
+
+    /// Encodes an integer sample as it is stored. 8 bit samples are shifted to
+    /// be unsigned. Integers that don't fit are clipped.
+    #[inline]
+    pub fn encode_int(&self, value: i32, output: &mut Vec<u8>) {
+        match self {
+            Self::U8 => output.push((value.clamp(-128, 127) + 128) as u8),
+            Self::I16 => {
+                output.extend_from_slice(&(value.clamp(-0x8000, 0x7fff) as i16).to_le_bytes())
+            }
+            Self::I24 => {
+                output.extend_from_slice(&value.clamp(-0x80_0000, 0x7f_ffff).to_le_bytes()[..3])
+            }
+            Self::I32 => output.extend_from_slice(&value.to_le_bytes()),
+            Self::F32 | Self::F64 => self.encode_float(f64::from(value), output),
+        }
+    }
+
+    /// Encodes a sample that is scaled to `[-1, 1)` for integers. Integers are
+    /// rounded and clipped.
+    #[inline]
+    pub fn encode_float(&self, value: f64, output: &mut Vec<u8>) {
+        match self {
+            Self::F32 => output.extend_from_slice(&(value as f32).to_le_bytes()),
+            Self::F64 => output.extend_from_slice(&value.to_le_bytes()),
+            _ => {
+                // `as` saturates
+                self.encode_int((value * self.int_scale()).round() as i32, output)
+            }
+        }
+    }
+
+    #[inline]
+    fn int_scale(&self) -> f64 {
+        f64::from(1u32 << (self.bits_per_sample() - 1))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavSpec {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub sample_format: WavSampleFormat,
+}
+
+impl WavSpec {
+    /// Size of a frame, i.e. a sample of each channel, in bytes.
+    #[inline]
+    pub fn block_align(&self) -> usize {
+        usize::from(self.channels) * self.sample_format.bytes_per_sample()
+    }
+}
+
+/// Format and location of the samples of a WAV file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavHeader {
+    pub spec: WavSpec,
+
+    /// Offset of the first sample from the start of the file.
+    pub data_start: u64,
+
+    /// Size of the samples in bytes, or `None` if the file was written as a
+    /// stream, and the samples go until the end of the file.
+    pub data_size: Option<u64>,
+}
+
+impl WavHeader {
+    /// Reads the header up to the start of the samples, where it leaves the
+    /// reader.
+    ///
+    /// Chunks before the samples are skipped by reading them, so this works on
+    /// readers that can't seek.
+    pub fn read_from<R>(reader: &mut R) -> Result<Self, Error>
+    where
+        R: Read,
+    {
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(Error::Invalid("not a RIFF/WAVE file"));
+        }
+
+        let mut spec = None;
+        let mut position = 12;
+
+        loop {
+            let mut chunk_header = [0; 8];
+            match reader.read_exact(&mut chunk_header) {
+                Ok(()) => {}
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(Error::Invalid("no data chunk"));
+                }
+                Err(error) => return Err(error.into()),
+            }
+            position += 8;
+            let size = u32_at(&chunk_header, 4);
+
+            match &chunk_header[0..4] {
+                b"fmt " => {
+                    if size < 16 {
+                        return Err(Error::Invalid("format chunk too short"));
+                    }
+                    let mut data = vec![0; size as usize];
+                    reader.read_exact(&mut data)?;
+                    spec = Some(parse_format(&data)?);
+                }
+                b"data" => {
+                    let spec = spec.ok_or(Error::Invalid("data chunk before format chunk"))?;
+                    return Ok(Self {
+                        spec,
+                        data_start: position,
+                        data_size: (size != UNKNOWN_DATA_SIZE).then_some(u64::from(size)),
+                    });
+                }
+                _ => {
+                    // chunks are word aligned
+                    let padded_size = u64::from(size) + u64::from(size & 1);
+                    let skipped = io::copy(&mut reader.take(padded_size), &mut io::sink())?;
+                    if skipped < padded_size {
+                        return Err(Error::Invalid("no data chunk"));
+                    }
+                    position += padded_size;
+                    continue;
+                }
+            }
+
+            position += u64::from(size);
+            if size & 1 == 1 {
+                reader.read_exact(&mut [0])?;
+                position += 1;
+            }
+        }
+    }
+
+    /// Number of frames in the file, if known.
+    #[inline]
+    pub fn num_frames(&self) -> Option<u64> {
+        self.data_size
+            .map(|data_size| data_size / self.spec.block_align() as u64)
+    }
+
+    /// Fixes the size of the samples from the size of the file.
+    ///
+    /// Files that weren't finalized, e.g. because the recording crashed, claim
+    /// to have no samples, or more than there are.
+    pub fn fix_data_size(&mut self, file_size: u64) {
+        let available = file_size.saturating_sub(self.data_start);
+        self.data_size = match self.data_size {
+            Some(data_size) if data_size > 0 && data_size <= available => Some(data_size),
+            _ => Some(available),
+        };
+    }
+}
+
+fn parse_format(data: &[u8]) -> Result<WavSpec, Error> {
+    let mut format_tag = u16_at(data, 0);
+    let channels = u16_at(data, 2);
+    let sample_rate = u32_at(data, 4);
+    let block_align = u16_at(data, 12);
+    let bits_per_sample = u16_at(data, 14);
+
+    if format_tag == WAVE_FORMAT_EXTENSIBLE {
+        if data.len() < 40 {
+            return Err(Error::Invalid("extensible format chunk too short"));
+        }
+        // the sub format is a GUID that starts with the format tag
+        format_tag = u16_at(data, 24);
+    }
+
+    if channels == 0 {
+        return Err(Error::Invalid("no channels"));
+    }
+
+    let sample_format = WavSampleFormat::from_format_tag(format_tag, bits_per_sample).ok_or(
+        Error::Unsupported {
+            format_tag,
+            bits_per_sample,
+        },
+    )?;
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        sample_format,
+    };
+    if usize::from(block_align) != spec.block_align() {
+        return Err(Error::Invalid("unexpected block alignment"));
+    }
+
+    Ok(spec)
+}
+
+/// Writes the header of a WAV file whose size isn't known yet.
+///
+/// The data chunk follows directly. Once all samples are written, the sizes
+/// are fixed with [`finish`].
+pub fn write_header<W>(writer: &mut W, spec: &WavSpec) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let block_align = u16::try_from(spec.block_align())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many channels"))?;
+
+    let mut header = Vec::with_capacity(DATA_SIZE_OFFSET as usize + 4);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&UNKNOWN_DATA_SIZE.to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&spec.sample_format.format_tag().to_le_bytes());
+    header.extend_from_slice(&spec.channels.to_le_bytes());
+    header.extend_from_slice(&spec.sample_rate.to_le_bytes());
+    header.extend_from_slice(&(spec.sample_rate * u32::from(block_align)).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&spec.sample_format.bits_per_sample().to_le_bytes());
+
+    header.extend_from_slice(b"data");
+    debug_assert_eq!(header.len() as u64, DATA_SIZE_OFFSET);
+    header.extend_from_slice(&UNKNOWN_DATA_SIZE.to_le_bytes());
+
+    writer.write_all(&header)
+}
+
+/// Pads the samples and writes the sizes into the header written by
+/// [`write_header`].
+///
+/// The writer is left at the end of the file, so that more chunks can be
+/// appended.
+pub fn finish<W>(writer: &mut W, data_size: u64) -> Result<(), io::Error>
+where
+    W: Write + Seek,
+{
+    let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "WAV file too large");
+
+    if data_size & 1 == 1 {
+        writer.write_all(&[0])?;
+    }
+    let end = writer.stream_position()?;
+
+    let data_size = u32::try_from(data_size)
+        .ok()
+        .filter(|size| *size != UNKNOWN_DATA_SIZE)
+        .ok_or_else(too_large)?;
+    let riff_size = u32::try_from(end - 8).map_err(|_| too_large())?;
+
+    writer.seek(SeekFrom::Start(4))?;
+    writer.write_all(&riff_size.to_le_bytes())?;
+    writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    writer.seek(SeekFrom::Start(end))?;
+
+    Ok(())
+}
+
+#[inline]
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..][..4].try_into().unwrap())
+}
+
+#[inline]
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..][..2].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{
+        WavHeader,
+        WavSampleFormat,
+        WavSpec,
+        finish,
+        write_header,
+    };
+
+    #[test]
+    fn it_round_trips_samples() {
+        let formats = [
+            WavSampleFormat::U8,
+            WavSampleFormat::I16,
+            WavSampleFormat::I24,
+            WavSampleFormat::I32,
+            WavSampleFormat::F32,
+            WavSampleFormat::F64,
+        ];
+        for format in formats {
+            let mut bytes = vec![];
+            for value in [-1.0, -0.5, 0.0, 0.25, 2.0] {
+                format.encode_float(value, &mut bytes);
+            }
+            assert_eq!(bytes.len(), 5 * format.bytes_per_sample());
+
+            let decoded = bytes
+                .chunks_exact(format.bytes_per_sample())
+                .map(|sample| format.decode_float(sample))
+                .collect::<Vec<_>>();
+            let max = if format.is_float() { 2.0 } else { 1.0 };
+            let tolerance = if format.is_float() {
+                0.0
+            }
+            else {
+                1.0 / format.int_scale()
+            };
+            for (decoded, expected) in decoded.iter().zip([-1.0, -0.5, 0.0, 0.25, max]) {
+                assert!(
+                    (decoded - expected).abs() <= tolerance,
+                    "{format:?}: {decoded} != {expected}"
+                );
+            }
+        }
+
+        let mut bytes = vec![];
+        WavSampleFormat::I24.encode_int(-2, &mut bytes);
+        assert_eq!(bytes, [0xfe, 0xff, 0xff]);
+        assert_eq!(WavSampleFormat::I24.decode_int(&bytes), -2);
+    }
+
+    #[test]
+    fn it_reads_files_written_by_hound() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut file = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut file, spec).unwrap();
+        for sample in [1, -1, 0x7f_ffff, -0x80_0000] {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        file.set_position(0);
+        let header = WavHeader::read_from(&mut file).unwrap();
+        assert_eq!(
+            header.spec,
+            WavSpec {
+                channels: 2,
+                sample_rate: 48000,
+                sample_format: WavSampleFormat::I24,
+            }
+        );
+        assert_eq!(header.num_frames(), Some(2));
+
+        let data = &file.get_ref()[header.data_start as usize..];
+        let samples = data
+            .chunks_exact(3)
+            .map(|sample| WavSampleFormat::I24.decode_int(sample))
+            .collect::<Vec<_>>();
+        assert_eq!(samples, [1, -1, 0x7f_ffff, -0x80_0000]);
+    }
+
+    #[test]
+    fn hound_reads_files_we_write() {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            sample_format: WavSampleFormat::I24,
+        };
+        let mut file = Cursor::new(vec![]);
+        write_header(&mut file, &spec).unwrap();
+        let mut data = vec![];
+        for sample in [3, -3, 0x12_3456] {
+            spec.sample_format.encode_int(sample, &mut data);
+        }
+        std::io::Write::write_all(&mut file, &data).unwrap();
+        finish(&mut file, data.len() as u64).unwrap();
+
+        file.set_position(0);
+        let mut reader = hound::WavReader::new(file).unwrap();
+        assert_eq!(reader.spec().bits_per_sample, 24);
+        let samples = reader
+            .samples::<i32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(samples, [3, -3, 0x12_3456]);
+    }
+}
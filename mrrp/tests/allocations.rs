@@ -0,0 +1,169 @@
+//! Checks that hot paths don't allocate once they're warmed up.
+//!
+//! A counting global allocator counts the allocations per thread, so tests
+//! running in parallel don't see each other's allocations.
+
+use std::{
+    alloc::{
+        GlobalAlloc,
+        Layout,
+        System,
+    },
+    cell::Cell,
+};
+
+use futures_util::FutureExt;
+use mrrp::{
+    analysis::{
+        peaks::PeakDetector,
+        sideband::SidebandDetector,
+    },
+    fft::{
+        FftBackend,
+        FftPool,
+    },
+    filter::design::{
+        FilterDesign,
+        Lowpass,
+        pm_remez::pm_remez,
+    },
+    io::{
+        AsyncReadSamplesExt,
+        combinators::Trigger,
+    },
+    source::white_noise,
+};
+use num_complex::Complex;
+use rand::rngs::SmallRng;
+
+/// Calls before counting, so that buffers can grow to their final size.
+const WARMUP: usize = 16;
+
+/// Calls that must not allocate.
+const ITERATIONS: usize = 1000;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    // the thread local might already be gone while the thread exits
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Warms `f` up and then asserts that calling it [`ITERATIONS`] more times
+/// doesn't allocate on this thread.
+fn assert_no_allocations(name: &str, mut f: impl FnMut()) {
+    for _ in 0..WARMUP {
+        f();
+    }
+
+    let before = ALLOCATIONS.with(Cell::get);
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let allocations = ALLOCATIONS.with(Cell::get) - before;
+
+    assert_eq!(
+        allocations, 0,
+        "{name} allocated {allocations} times in {ITERATIONS} iterations"
+    );
+}
+
+#[test]
+fn reading_through_combinators_does_not_allocate() {
+    let filter = pm_remez(Lowpass::new(0.25, 0.01, 0.05, 0.05), 17)
+        .unwrap()
+        .fir_filter();
+
+    let stream = white_noise::<SmallRng, Complex<f32>>(rand::make_rng())
+        .map(|sample: Complex<f32>| 0.5 * sample)
+        .scan_with(filter)
+        .stats();
+    let stats = stream.handle();
+    let mut stream = stream.scope(1024, 128, Trigger::FreeRun);
+    let scope = stream.handle();
+
+    let mut buffer = vec![Complex::default(); 256];
+
+    assert_no_allocations("combinators", || {
+        stream
+            .read_samples_exact(&mut buffer)
+            .now_or_never()
+            .expect("white noise returned pending")
+            .expect("white noise returned error");
+
+        // like a UI would between reads
+        stats.take();
+        drop(scope.latest());
+    });
+}
+
+#[test]
+fn fft_does_not_allocate() {
+    let mut fft = FftPool::new().plan(1024);
+    let mut data = vec![Complex::new(1.0, 0.0); 4 * 1024];
+
+    assert_no_allocations("FFT", || fft.process_batch(&mut data));
+
+    // without worker threads this transforms in place on this thread
+    assert_no_allocations("FFT in pool", || {
+        data = fft
+            .process_in_pool(std::mem::take(&mut data))
+            .now_or_never()
+            .expect("FFT pool returned pending");
+    });
+}
+
+#[test]
+fn spectrum_analysis_does_not_allocate() {
+    let spectrum = (0..1024)
+        .map(|i| {
+            let x = (i as f32 - 300.0) / 4.0;
+            -100.0 + 40.0 * (-0.5 * x * x).exp()
+        })
+        .collect::<Vec<f32>>();
+
+    let detector = PeakDetector::default();
+    let mut peaks = vec![];
+    let mut sorted = vec![];
+    assert_no_allocations("peak detection", || {
+        detector.find_peaks_into(&spectrum, &mut peaks, &mut sorted);
+    });
+    assert_eq!(peaks.len(), 1);
+
+    let power = spectrum
+        .iter()
+        .map(|level| 10f32.powf(level / 10.0))
+        .collect::<Vec<f32>>();
+    let mut detector = SidebandDetector::default();
+    assert_no_allocations("sideband detection", || {
+        detector.push(&power, 0.0, 10.0, 3000.0);
+    });
+}
@@ -0,0 +1,109 @@
+#!/usr/bin/env python3
+"""Generates tests/data/pocsag.wav, the capture the POCSAG example is tested on.
+
+Usage: pocsag.py [output file]
+
+The page is encoded and FSK-modulated like `PocsagEncoder` and
+`AfskModulator` do it, then some white noise is added and the signal is
+quantized to unsigned 8 bit IQ, like an RTL-SDR delivers it. The noise is
+seeded, so the output is reproducible.
+"""
+
+import cmath
+import math
+import os
+import random
+import sys
+import wave
+
+ADDRESS = 1234560
+MESSAGE = "Hello, pager!"
+
+SAMPLE_RATE = 48_000
+BAUD_RATE = 1200
+DEVIATION = 4500
+NOISE = 0.05
+SILENCE = 0.05
+
+SYNC = 0x7CD215D8
+IDLE = 0x7A89C197
+BCH_GENERATOR = 0b111_0110_1001
+PREAMBLE_LENGTH = 576
+
+
+def bch_remainder(value):
+    for i in reversed(range(10, 31)):
+        if value & (1 << i):
+            value ^= BCH_GENERATOR << (i - 10)
+    return value
+
+
+def encode_codeword(codeword):
+    data = codeword & 0xFFFFF800
+    codeword = data | (bch_remainder(data >> 1) << 1)
+    return codeword | (bin(codeword).count("1") & 1)
+
+
+def encode(address, message):
+    codewords = []
+
+    frame = address & 7
+    while len(codewords) % 16 != 2 * frame:
+        codewords.append(IDLE)
+    codewords.append(encode_codeword((((address >> 3) & 0x3FFFF) << 13) | (3 << 11)))
+
+    data = [bool(c & (1 << i)) for c in message.encode("ascii") for i in range(7)]
+    for i in range(0, len(data), 20):
+        chunk = (data[i : i + 20] + [False] * 20)[:20]
+        bits = 0
+        for bit in chunk:
+            bits = (bits << 1) | bit
+        codewords.append(encode_codeword((1 << 31) | (bits << 11)))
+
+    codewords.append(IDLE)
+    while len(codewords) % 16:
+        codewords.append(IDLE)
+
+    bits = [i % 2 == 0 for i in range(PREAMBLE_LENGTH)]
+    for i in range(0, len(codewords), 16):
+        for codeword in [SYNC] + codewords[i : i + 16]:
+            bits += [bool(codeword & (1 << j)) for j in reversed(range(32))]
+    return bits
+
+
+def modulate(bits):
+    samples = []
+    phase = 0.0
+    symbol_clock = 0.0
+    for bit in bits:
+        frequency = -DEVIATION if bit else DEVIATION
+        symbol_clock += SAMPLE_RATE / BAUD_RATE
+        while symbol_clock >= 1.0:
+            samples.append(cmath.rect(1.0, phase))
+            phase = (phase + 2 * math.pi * frequency / SAMPLE_RATE) % (2 * math.pi)
+            symbol_clock -= 1.0
+    return samples
+
+
+def quantize(value):
+    return max(0, min(255, round(value * 100) + 128))
+
+
+def main():
+    output = sys.argv[1] if len(sys.argv) > 1 else os.path.join(os.path.dirname(__file__), "pocsag.wav")
+
+    silence = [0j] * int(SILENCE * SAMPLE_RATE)
+    signal = silence + modulate(encode(ADDRESS, MESSAGE)) + silence
+
+    rng = random.Random(3704)
+    with wave.open(output, "wb") as file:
+        file.setnchannels(2)
+        file.setsampwidth(1)
+        file.setframerate(SAMPLE_RATE)
+        for sample in signal:
+            sample += complex(rng.gauss(0, NOISE), rng.gauss(0, NOISE))
+            file.writeframes(bytes([quantize(sample.real), quantize(sample.imag)]))
+
+
+if __name__ == "__main__":
+    main()
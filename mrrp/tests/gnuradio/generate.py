@@ -0,0 +1,108 @@
+#!/usr/bin/env python3
+"""Generates the GNU Radio reference vectors for tests/gnuradio_interop.rs.
+
+Usage: generate.py [output directory]
+
+The output directory defaults to the directory of this script, where the
+committed fixtures are.
+
+Every block runs on the same inputs that are written next to its output, so
+the Rust tests don't need to reproduce the inputs. Files are raw little-endian
+float32 (`.f32`) or interleaved complex float32 (`.c64`), like GNU Radio's file
+sink writes them.
+"""
+
+import os
+import sys
+
+import numpy as np
+from gnuradio import analog, blocks, filter, gr
+from gnuradio.fft import window
+
+SAMPLE_RATE = 48_000
+FM_DEVIATION = 2_000
+NUM_SAMPLES = 1024
+
+
+def run(source_data, block, source=blocks.vector_source_f, sink=blocks.vector_sink_f):
+    top_block = gr.top_block()
+    vector_source = source(source_data.tolist(), False)
+    vector_sink = sink()
+    top_block.connect(vector_source, block, vector_sink)
+    top_block.run()
+    return np.array(vector_sink.data())
+
+
+def write(directory, name, data):
+    dtype = np.complex64 if name.endswith(".c64") else np.float32
+    np.asarray(data, dtype=dtype).tofile(os.path.join(directory, name))
+
+
+def main():
+    directory = sys.argv[1] if len(sys.argv) > 1 else os.path.dirname(os.path.abspath(__file__))
+    os.makedirs(directory, exist_ok=True)
+    rng = np.random.default_rng(3738)
+    t = np.arange(NUM_SAMPLES) / SAMPLE_RATE
+
+    # FIR filter: a low-pass on two tones and some noise
+    fir_input = (
+        np.sin(2 * np.pi * 1_000 * t)
+        + 0.5 * np.sin(2 * np.pi * 9_000 * t)
+        + 0.1 * rng.standard_normal(NUM_SAMPLES)
+    ).astype(np.float32)
+    fir_taps = np.array(
+        filter.firdes.low_pass(1.0, SAMPLE_RATE, 4_000, 2_000, window.WIN_HAMMING),
+        dtype=np.float32,
+    )
+    write(directory, "fir_input.f32", fir_input)
+    write(directory, "fir_taps.f32", fir_taps)
+    write(
+        directory,
+        "fir_output.f32",
+        run(fir_input, filter.fir_filter_fff(1, fir_taps.tolist())),
+    )
+
+    # FM: a 700 Hz tone, modulated and demodulated by GNU Radio
+    audio = (0.8 * np.sin(2 * np.pi * 700 * t)).astype(np.float32)
+    sensitivity = 2 * np.pi * FM_DEVIATION / SAMPLE_RATE
+    fm_input = run(
+        audio,
+        analog.frequency_modulator_fc(sensitivity),
+        sink=blocks.vector_sink_c,
+    )
+    write(directory, "fm_input.c64", fm_input)
+    write(
+        directory,
+        "fm_demod.f32",
+        run(
+            fm_input,
+            analog.quadrature_demod_cf(1.0 / sensitivity),
+            source=blocks.vector_source_c,
+        ),
+    )
+
+    # resampling: plain decimation and interpolation are FIR filters with a
+    # single tap of 1
+    resample_input = (
+        rng.standard_normal(NUM_SAMPLES) + 1j * rng.standard_normal(NUM_SAMPLES)
+    ).astype(np.complex64)
+    write(directory, "resample_input.c64", resample_input)
+    for name, block in [
+        ("decimate_4.c64", filter.fir_filter_ccf(4, [1.0])),
+        ("interpolate_3.c64", filter.interp_fir_filter_ccf(3, [1.0])),
+        ("decimating_fir_5.c64", filter.fir_filter_ccf(5, fir_taps.tolist())),
+    ]:
+        write(
+            directory,
+            name,
+            run(
+                resample_input,
+                block,
+                source=blocks.vector_source_c,
+                sink=blocks.vector_sink_c,
+            ),
+        )
+
+
+if __name__ == "__main__":
+    main()
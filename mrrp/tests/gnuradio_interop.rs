@@ -0,0 +1,183 @@
+//! Compares mrrp against reference outputs of GNU Radio.
+//!
+//! This catches differences in scaling, sign and sample alignment that our own
+//! tests can't, since they're written with the same conventions as the code.
+//!
+//! The reference vectors belong in `tests/gnuradio/`. They are
+//! generated with `tests/gnuradio/generate.py`, which needs GNU Radio and
+//! numpy. The tests need the `gnuradio-interop` feature:
+//!
+//! ```sh
+//! cargo test --features gnuradio-interop --test gnuradio_interop
+//! ```
+//!
+//! To compare against another GNU Radio version, generate the vectors into
+//! another directory and point `MRRP_GNURADIO_FIXTURES` at it:
+//!
+//! ```sh
+//! python3 tests/gnuradio/generate.py target/gnuradio
+//! MRRP_GNURADIO_FIXTURES=target/gnuradio cargo test --features gnuradio-interop --test gnuradio_interop
+//! ```
+
+use std::path::PathBuf;
+
+use futures_util::FutureExt;
+use mrrp::{
+    filter::{
+        fir::FirFilter,
+        multistage::DecimatingFir,
+    },
+    io::{
+        AsyncReadSamplesExt,
+        Cursor,
+        combinators::Scanner,
+    },
+    modem::fm::FmDemodulator,
+};
+use num_complex::Complex;
+
+/// Must match `generate.py`.
+const SAMPLE_RATE: f32 = 48_000.0;
+const FM_DEVIATION: f32 = 2_000.0;
+
+struct Fixtures {
+    directory: PathBuf,
+}
+
+impl Fixtures {
+    /// The committed fixtures, or the ones in `MRRP_GNURADIO_FIXTURES`.
+    fn get() -> Self {
+        let directory = std::env::var_os("MRRP_GNURADIO_FIXTURES").map_or_else(
+            || PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/gnuradio"),
+            PathBuf::from,
+        );
+        Self { directory }
+    }
+
+    fn read(&self, name: &str) -> Vec<f32> {
+        let path = self.directory.join(name);
+        let bytes = std::fs::read(&path).unwrap_or_else(|error| {
+            panic!(
+                "failed to read {}: {error}. Generate it with tests/gnuradio/generate.py.",
+                path.display()
+            )
+        });
+        assert_eq!(bytes.len() % 4, 0, "{} is truncated", path.display());
+        bytes
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    fn read_complex(&self, name: &str) -> Vec<Complex<f32>> {
+        self.read(name)
+            .chunks_exact(2)
+            .map(|pair| Complex::new(pair[0], pair[1]))
+            .collect()
+    }
+}
+
+#[track_caller]
+fn assert_close<T>(actual: &[T], expected: &[T], tolerance: f32, norm: impl Fn(T) -> f32)
+where
+    T: Copy + std::ops::Sub<Output = T>,
+{
+    assert_eq!(actual.len(), expected.len(), "lengths differ");
+    for (i, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+        let error = norm(*actual - *expected);
+        assert!(
+            error <= tolerance,
+            "sample {i} differs by {error} (tolerance {tolerance})"
+        );
+    }
+}
+
+#[test]
+fn fir_filter_matches_fir_filter_fff() {
+    let fixtures = Fixtures::get();
+    let input = fixtures.read("fir_input.f32");
+    let taps = fixtures.read("fir_taps.f32");
+    let expected = fixtures.read("fir_output.f32");
+
+    let mut filter = FirFilter::<f32, f32>::new(taps);
+    let output = input
+        .iter()
+        .map(|sample| filter.scan(*sample))
+        .collect::<Vec<_>>();
+
+    assert_close(&output, &expected, 1e-4, f32::abs);
+}
+
+#[test]
+fn fm_demodulator_matches_quadrature_demod_cf() {
+    let fixtures = Fixtures::get();
+    let input = fixtures.read_complex("fm_input.c64");
+    let expected = fixtures.read("fm_demod.f32");
+
+    let mut demodulator = FmDemodulator::new(SAMPLE_RATE, FM_DEVIATION);
+    let output = input
+        .iter()
+        .map(|sample| demodulator.scan(*sample))
+        .collect::<Vec<_>>();
+
+    // GNU Radio takes the phase step from the previous sample, while we take the
+    // central difference around the previous sample, so our output is the mean
+    // of two of theirs. The first two outputs are missing their history.
+    let expected = expected
+        .windows(2)
+        .map(|pair| 0.5 * (pair[0] + pair[1]))
+        .collect::<Vec<_>>();
+    assert_close(&output[2..], &expected[1..], 1e-2, f32::abs);
+}
+
+#[test]
+fn decimate_matches_fir_filter_ccf() {
+    let fixtures = Fixtures::get();
+    let input = fixtures.read_complex("resample_input.c64");
+    let expected = fixtures.read_complex("decimate_4.c64");
+
+    let mut output = vec![];
+    Cursor::new(input)
+        .decimate(4)
+        .read_to_end(&mut output)
+        .now_or_never()
+        .expect("pending")
+        .unwrap();
+
+    assert_close(&output, &expected, 1e-6, |x| x.norm());
+}
+
+#[test]
+fn interpolate_matches_interp_fir_filter_ccf() {
+    let fixtures = Fixtures::get();
+    let input = fixtures.read_complex("resample_input.c64");
+    let expected = fixtures.read_complex("interpolate_3.c64");
+
+    let mut output = vec![];
+    Cursor::new(input)
+        .interpolate(3)
+        .read_to_end(&mut output)
+        .now_or_never()
+        .expect("pending")
+        .unwrap();
+
+    assert_close(&output, &expected, 1e-6, |x| x.norm());
+}
+
+#[test]
+fn decimating_fir_matches_fir_filter_ccf() {
+    let fixtures = Fixtures::get();
+    let input = fixtures.read_complex("resample_input.c64");
+    let taps = fixtures.read("fir_taps.f32");
+    let expected = fixtures.read_complex("decimating_fir_5.c64");
+
+    let mut filter = DecimatingFir::new(taps, 5);
+    let output = input
+        .iter()
+        .filter_map(|sample| filter.scan(*sample))
+        .collect::<Vec<_>>();
+
+    // GNU Radio only outputs a sample once it has consumed the full decimation
+    // for it, so it misses our last output
+    assert_close(&output[..expected.len()], &expected, 1e-4, |x| x.norm());
+}